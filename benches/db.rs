@@ -0,0 +1,27 @@
+use std::env::temp_dir;
+use std::fs;
+use criterion::{criterion_group, criterion_main, Criterion};
+use home_accounting_db::db::HomeAccountingDB;
+use home_accounting_db::generator::{generate_json, GeneratorOptions};
+use home_accounting_db::json_db_config::JsonDBConfiguration;
+
+fn load(path: &str) -> HomeAccountingDB {
+    HomeAccountingDB::load(path.to_string(), Box::new(JsonDBConfiguration::new()), 1000000).unwrap()
+}
+
+fn db_benchmark(c: &mut Criterion) {
+    let folder = temp_dir().join("home_accounting_db_bench");
+    _ = fs::remove_dir_all(&folder);
+    let path = folder.to_str().unwrap().to_string();
+    generate_json(&path, &GeneratorOptions::new(2)).unwrap();
+
+    c.bench_function("load", |b|b.iter(||load(&path)));
+    let db = load(&path);
+    c.bench_function("get_operations", |b|b.iter(||db.get_operations(0, u64::MAX).unwrap()));
+    c.bench_function("category_summary", |b|b.iter(||db.build_category_summary(0, u64::MAX).unwrap()));
+    c.bench_function("rebuild_totals", |b|b.iter(||db.rebuild_totals().unwrap()));
+    _ = fs::remove_dir_all(&folder);
+}
+
+criterion_group!(benches, db_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+#ifndef HOME_ACCOUNTING_DB_H
+#define HOME_ACCOUNTING_DB_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+typedef struct HomeAccountingDB HomeAccountingDB;
+
+typedef struct {
+    uint64_t date;
+    uint64_t account;
+    uint64_t subcategory;
+    uint64_t amount;         /* 0 when operation has no amount */
+    int64_t summa;
+    uint64_t second_account; /* 0 when absent */
+} FfiOperation;
+
+typedef struct {
+    uint64_t account;
+    int64_t start_balance;
+    int64_t income;
+    int64_t expenditure;
+    int64_t end_balance;
+} FfiBalance;
+
+/* All functions returning int return 0 on success and -1 on failure. */
+const char *had_last_error(void);
+HomeAccountingDB *had_open_json(const char *data_folder_path, size_t max_active_items);
+void had_close(HomeAccountingDB *db);
+int had_get_operations(HomeAccountingDB *db, uint64_t date, FfiOperation *out, size_t capacity, size_t *count);
+/* Increased by every change of the month, clients compare it with the version of cached operations. */
+uint64_t had_get_month_version(HomeAccountingDB *db, uint64_t date);
+int had_get_balances(HomeAccountingDB *db, uint64_t date, FfiBalance *out, size_t capacity, size_t *count);
+/* Returns 1 when the operation is added but exceeds hard spending limit of its subcategory. */
+int had_add_operation(HomeAccountingDB *db, const FfiOperation *op);
+/* Adds the operation once per idempotency key, returns 2 when it was already added with the key. */
+int had_add_operation_once(HomeAccountingDB *db, const char *key, const FfiOperation *op);
+
+#endif
@@ -0,0 +1,105 @@
+//! Operations as Arrow record batches for analytic engines. Applications embedding DuckDB or DataFusion
+//! register the batch as a table and run SQL over it without exporting files.
+
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+use arrow_array::{Date32Array, Int64Array, RecordBatch, StringArray, UInt64Array};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use crate::core::dates::date_to_unix_days;
+use crate::entities::accounts::Accounts;
+use crate::entities::finance_operations::FinanceOperation;
+use crate::entities::subcategories::{Categories, Subcategories};
+
+/// Columns of the operations batch, summa is in hundredths, amount in thousandths.
+pub fn operations_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("date", DataType::Date32, false),
+        Field::new("account_id", DataType::UInt64, false),
+        Field::new("account", DataType::Utf8, false),
+        Field::new("currency", DataType::Utf8, false),
+        Field::new("category_id", DataType::UInt64, false),
+        Field::new("category", DataType::Utf8, false),
+        Field::new("subcategory_id", DataType::UInt64, false),
+        Field::new("subcategory", DataType::Utf8, false),
+        Field::new("operation_code", DataType::Utf8, false),
+        Field::new("summa", DataType::Int64, false),
+        Field::new("amount", DataType::UInt64, true),
+        Field::new("network", DataType::Utf8, true),
+        Field::new("payee_id", DataType::UInt64, true)
+    ]))
+}
+
+/// One row per operation with dictionary names resolved.
+pub fn operations_to_record_batch(operations: &[FinanceOperation], accounts: &Accounts, categories: &Categories,
+                                  subcategories: &Subcategories) -> Result<RecordBatch, Error> {
+    let mut account_names = Vec::with_capacity(operations.len());
+    let mut currencies = Vec::with_capacity(operations.len());
+    let mut category_ids = Vec::with_capacity(operations.len());
+    let mut category_names = Vec::with_capacity(operations.len());
+    let mut subcategory_names = Vec::with_capacity(operations.len());
+    let mut operation_codes = Vec::with_capacity(operations.len());
+    for op in operations {
+        let account = accounts.get(op.get_account())?;
+        let subcategory = subcategories.get(op.get_subcategory())?;
+        let category = categories.get(subcategory.category)?;
+        account_names.push(account.name.as_str());
+        currencies.push(account.currency_at(op.date));
+        category_ids.push(category.id.0);
+        category_names.push(category.name.as_str());
+        subcategory_names.push(subcategory.name.as_str());
+        operation_codes.push(subcategory.operation_code.as_str());
+    }
+    let dates = operations.iter()
+        .map(|op|i32::try_from(date_to_unix_days(op.date))
+            .map_err(|_|Error::new(ErrorKind::InvalidData, format!("invalid date {}", op.date))))
+        .collect::<Result<Vec<_>, Error>>()?;
+    RecordBatch::try_new(operations_schema(), vec![
+        Arc::new(Date32Array::from(dates)),
+        Arc::new(UInt64Array::from_iter_values(operations.iter().map(|op|op.get_account().0))),
+        Arc::new(StringArray::from(account_names)),
+        Arc::new(StringArray::from(currencies)),
+        Arc::new(UInt64Array::from(category_ids)),
+        Arc::new(StringArray::from(category_names)),
+        Arc::new(UInt64Array::from_iter_values(operations.iter().map(|op|op.get_subcategory().0))),
+        Arc::new(StringArray::from(subcategory_names)),
+        Arc::new(StringArray::from(operation_codes)),
+        Arc::new(Int64Array::from_iter_values(operations.iter().map(|op|op.get_summa()))),
+        Arc::new(UInt64Array::from_iter(operations.iter().map(|op|op.get_amount()))),
+        Arc::new(StringArray::from_iter(operations.iter().map(|op|op.get_network()))),
+        Arc::new(UInt64Array::from_iter(operations.iter().map(|op|op.get_payee().map(|p|p.0))))
+    ]).map_err(|e|Error::new(ErrorKind::InvalidData, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use arrow_array::{Array, Date32Array, Int64Array, StringArray};
+    use crate::analytics::operations_to_record_batch;
+    use crate::entities::accounts::{Account, AccountId, Accounts};
+    use crate::entities::finance_operations::{FinOpParameter, FinanceOperation};
+    use crate::entities::subcategories::{Categories, Category, CategoryId, Subcategories, Subcategory, SubcategoryCode,
+                                         SubcategoryId, SubcategoryOperationCode};
+
+    #[test]
+    fn test_operations_to_record_batch() -> Result<(), Error> {
+        let accounts = Accounts::new(vec![Account::new(AccountId(1), "Cash".to_string(), "UAH".to_string(), true)])?;
+        let categories = Categories::new(vec![Category{id: CategoryId(1), name: "Food".to_string(), tax_relevant: false}]);
+        let subcategories = Subcategories::new(vec![Subcategory{id: SubcategoryId(1), name: "Groceries".to_string(),
+            code: SubcategoryCode::None, operation_code: SubcategoryOperationCode::Expn, category: CategoryId(1),
+            tax_relevant: false, retired: false, soft_limit: None, hard_limit: None}]);
+        let operations = vec![
+            FinanceOperation::new(19700102, AccountId(1), SubcategoryId(1), None, 250, vec![FinOpParameter::Netw("SILPO".into())]),
+            FinanceOperation::new(19700103, AccountId(1), SubcategoryId(1), None, 100, Vec::new())
+        ];
+        let batch = operations_to_record_batch(&operations, &accounts, &categories, &subcategories)?;
+        assert_eq!(batch.num_rows(), 2);
+        let column = |name: &str|batch.column_by_name(name).unwrap().clone();
+        assert_eq!(column("date").as_any().downcast_ref::<Date32Array>().unwrap().values().to_vec(), vec![1, 2]);
+        assert_eq!(column("summa").as_any().downcast_ref::<Int64Array>().unwrap().values().to_vec(), vec![250, 100]);
+        let network = column("network");
+        let network = network.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!((network.value(0), network.is_null(1)), ("SILPO", true));
+        assert_eq!(column("category").as_any().downcast_ref::<StringArray>().unwrap().value(1), "Food");
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+//! Anonymized copy of a JSON database for bug reports. Files, ids, dates and references stay the same,
+//! names and free texts are replaced with pseudonyms, amounts are scaled by random factors.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use serde_json::{Number, Value};
+use crate::core::archive::ARCHIVE_FOLDER;
+use crate::core::data_source::json_error;
+use crate::core::wal::{CHECKPOINTS_FOLDER, WAL_FILE_NAME};
+use crate::generator::Random;
+
+/// folders with operations, archived accounts and attachments, other files of the root folder except
+/// dictionaries are caches and are rebuilt by the copy
+const DATA_FOLDERS: [&str; 3] = ["dates", "accounts", "attachments"];
+const ATTACHMENTS_FOLDER: &str = "attachments";
+const TEXT_KEYS: [&str; 4] = ["name", "stringValue", "network", "ticker"];
+const AMOUNT_KEYS: [&str; 11] = ["summa", "amount", "numericValue", "minBalance", "creditLimit", "softLimit",
+    "hardLimit", "target", "principal", "monthlyPayment", "price"];
+
+struct Anonymizer {
+    random: Random,
+    /// equal texts get equal pseudonyms, so grouping by payee or network is kept
+    pseudonyms: HashMap<String, String>
+}
+
+impl Anonymizer {
+    fn pseudonym(&mut self, text: &str) -> String {
+        let next = self.pseudonyms.len() + 1;
+        self.pseudonyms.entry(text.to_string()).or_insert_with(||format!("Name {}", next)).clone()
+    }
+
+    /// Scales by 50..150%, sign, zero and integer or decimal form are kept.
+    fn scale(&mut self, key: &str, value: &Number) -> Number {
+        let factor = (50 + self.random.next(101)) as f64 / 100.0;
+        if let Some(v) = value.as_i64() {
+            Number::from((v as f64 * factor).round() as i64)
+        } else if let Some(v) = value.as_u64() {
+            Number::from((v as f64 * factor).round() as u64)
+        } else {
+            let precision = if key == "amount" {1000.0} else {100.0};
+            let v = value.as_f64().unwrap_or_default();
+            Number::from_f64((v * factor * precision).round() / precision).unwrap_or_else(||value.clone())
+        }
+    }
+
+    fn process(&mut self, key: &str, value: &mut Value) {
+        match value {
+            Value::Array(items) => items.iter_mut().for_each(|item|self.process(key, item)),
+            Value::Object(fields) => fields.iter_mut().for_each(|(k, v)|self.process(k, v)),
+            Value::String(text) if TEXT_KEYS.contains(&key) => *text = self.pseudonym(text),
+            Value::Number(n) if AMOUNT_KEYS.contains(&key) => *n = self.scale(key, n),
+            _ => {}
+        }
+    }
+
+    fn copy_file(&mut self, source: &Path, target: &Path, attachment: bool) -> Result<(), Error> {
+        if attachment {
+            // contents of receipts can't be anonymized, references to them stay valid
+            return fs::write(target, []);
+        }
+        if source.extension().is_none_or(|e|e != "json") {
+            return fs::copy(source, target).map(|_|());
+        }
+        let name = source.to_string_lossy();
+        let mut value: Value = serde_json::from_slice(&fs::read(source)?).map_err(|e|json_error(&name, e))?;
+        self.process("", &mut value);
+        fs::write(target, serde_json::to_vec(&value)?)
+    }
+
+    fn copy_folder(&mut self, source: &Path, target: &Path, attachments: bool) -> Result<usize, Error> {
+        fs::create_dir_all(target)?;
+        let mut entries = fs::read_dir(source)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|e|e.file_name());
+        let mut count = 0;
+        for entry in entries {
+            if entry.file_type()?.is_dir() {
+                count += self.copy_folder(&entry.path(), &target.join(entry.file_name()), attachments)?;
+            } else {
+                self.copy_file(&entry.path(), &target.join(entry.file_name()), attachments)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// Writes anonymized copy of the database to an empty or missing target folder, returns number of
+/// written files. Archived years, checkpoints and write-ahead log are not supported.
+pub fn anonymize(data_folder_path: &str, target_folder_path: &str, seed: u64) -> Result<usize, Error> {
+    let root = Path::new(data_folder_path);
+    for unsupported in [ARCHIVE_FOLDER, CHECKPOINTS_FOLDER, WAL_FILE_NAME] {
+        if root.join(unsupported).exists() {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{} can't be anonymized", unsupported)));
+        }
+    }
+    let target = Path::new(target_folder_path);
+    if target.exists() && fs::read_dir(target)?.next().is_some() {
+        return Err(Error::new(ErrorKind::AlreadyExists, format!("{} is not empty", target_folder_path)));
+    }
+    fs::create_dir_all(target)?;
+    let mut anonymizer = Anonymizer{random: Random::new(seed), pseudonyms: HashMap::new()};
+    let mut files = fs::read_dir(root)?.map(|e|e.map(|e|e.path())).collect::<Result<Vec<_>, _>>()?;
+    files.retain(|f|f.is_file() && f.extension().is_some_and(|e|e == "json"));
+    files.sort();
+    for file in &files {
+        anonymizer.copy_file(file, &target.join(file.file_name().unwrap_or_default()), false)?;
+    }
+    let mut count = files.len();
+    for folder in DATA_FOLDERS {
+        if root.join(folder).is_dir() {
+            count += anonymizer.copy_folder(&root.join(folder), &target.join(folder), folder == ATTACHMENTS_FOLDER)?;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::{Error, ErrorKind};
+    use serde_json::Value;
+    use crate::anonymizer::anonymize;
+
+    #[test]
+    fn test_anonymize() -> Result<(), Error> {
+        let folder = temp_dir().join("hadb_anonymize_test");
+        let _ = fs::remove_dir_all(&folder);
+        let source = folder.join("source");
+        fs::create_dir_all(source.join("dates/20240105"))?;
+        fs::write(source.join("accounts.json"), r#"[{"id":1,"name":"Cash","valutaCode":"UAH","isCash":true}]"#)?;
+        fs::write(source.join("totals.txt"), "1 100")?;
+        fs::write(source.join("dates/20240105/ops.json"),
+                  r#"[{"id":0,"accountId":1,"subcategoryId":2,"amount":null,"summa":-1234,"finOpProperies":
+                  [{"numericValue":null,"stringValue":"Cash","dateValue":null,"propertyCode":"NETW"}]}]"#)?;
+        let source = source.to_string_lossy().to_string();
+        let target = folder.join("target");
+        assert_eq!(anonymize(&source, &target.to_string_lossy(), 7)?, 2);
+        assert!(!target.join("totals.txt").exists());
+        let accounts: Value = serde_json::from_slice(&fs::read(target.join("accounts.json"))?)?;
+        assert_eq!(accounts[0]["name"], "Name 1");
+        assert_eq!(accounts[0]["valutaCode"], "UAH");
+        let ops: Value = serde_json::from_slice(&fs::read(target.join("dates/20240105/ops.json"))?)?;
+        let summa = ops[0]["summa"].as_i64().unwrap();
+        assert!((-1851..=-617).contains(&summa));
+        assert_eq!(ops[0]["subcategoryId"], 2);
+        assert_eq!(ops[0]["finOpProperies"][0]["stringValue"], "Name 1");
+        assert_eq!(anonymize(&source, &target.to_string_lossy(), 7).err().map(|e|e.kind()), Some(ErrorKind::AlreadyExists));
+        fs::remove_dir_all(&folder)
+    }
+}
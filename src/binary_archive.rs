@@ -0,0 +1,244 @@
+//! Archived (rkyv) representation of a month of operations. Archived months are validated once
+//! and then read directly from the buffer, full deserialization happens only in to_record.
+//! NETW and TYPE strings are stored once per month in the strings table and referenced by index.
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+use rkyv::rancor;
+use rkyv::util::AlignedVec;
+use rkyv::vec::ArchivedVec;
+use rkyv::{Archive, Deserialize, Serialize};
+use crate::core::interner::StringPool;
+use crate::entities::accounts::AccountId;
+use crate::entities::finance_operations::{FinOpParameter, FinanceOperation, FinanceRecord, ParameterValue};
+use crate::entities::instruments::InstrumentId;
+use crate::entities::payees::PayeeId;
+use crate::entities::subcategories::SubcategoryId;
+
+#[derive(Archive, Serialize, Deserialize)]
+pub enum ParameterImage {
+    Amou(u64),
+    Dist(u64),
+    Netw(u32),
+    Ppto(u64),
+    Seca(u64),
+    Typ(u32),
+    Inst(u64),
+    CustomNumeric(String, u64),
+    CustomString(String, String),
+    CustomDate(String, u64)
+}
+
+#[derive(Archive, Serialize, Deserialize)]
+pub struct OperationImage {
+    date: u64,
+    account: u64,
+    subcategory: u64,
+    amount: Option<u64>,
+    summa: i64,
+    parameters: Vec<ParameterImage>,
+    payee: Option<u64>,
+    attachments: Vec<String>
+}
+
+#[derive(Archive, Serialize, Deserialize)]
+pub struct RecordImage {
+    strings: Vec<String>,
+    operations: Vec<OperationImage>,
+    totals: Vec<(u64, i64)>
+}
+
+/// Strings table of the archived month.
+struct StringTable {
+    strings: Vec<String>,
+    indexes: HashMap<Arc<str>, u32>
+}
+
+impl StringTable {
+    fn index(&mut self, s: &Arc<str>) -> u32 {
+        if let Some(i) = self.indexes.get(s) {
+            return *i;
+        }
+        let i = self.strings.len() as u32;
+        self.strings.push(s.to_string());
+        self.indexes.insert(s.clone(), i);
+        i
+    }
+}
+
+impl ParameterImage {
+    fn new(p: &FinOpParameter, table: &mut StringTable) -> Self {
+        match p {
+            FinOpParameter::Amou(v) => ParameterImage::Amou(*v),
+            FinOpParameter::Dist(v) => ParameterImage::Dist(*v),
+            FinOpParameter::Netw(v) => ParameterImage::Netw(table.index(v)),
+            FinOpParameter::Ppto(v) => ParameterImage::Ppto(*v),
+            FinOpParameter::Seca(v) => ParameterImage::Seca(v.0),
+            FinOpParameter::Typ(v) => ParameterImage::Typ(table.index(v)),
+            FinOpParameter::Inst(v) => ParameterImage::Inst(v.0),
+            FinOpParameter::Custom(c) => match &c.value {
+                ParameterValue::Numeric(v) => ParameterImage::CustomNumeric(c.code.clone(), *v),
+                ParameterValue::String(v) => ParameterImage::CustomString(c.code.clone(), v.clone()),
+                ParameterValue::Date(v) => ParameterImage::CustomDate(c.code.clone(), *v)
+            }
+        }
+    }
+}
+
+impl ArchivedParameterImage {
+    fn to_parameter(&self, strings: &[Arc<str>]) -> FinOpParameter {
+        match self {
+            ArchivedParameterImage::Amou(v) => FinOpParameter::Amou(v.to_native()),
+            ArchivedParameterImage::Dist(v) => FinOpParameter::Dist(v.to_native()),
+            ArchivedParameterImage::Netw(v) => FinOpParameter::Netw(strings[v.to_native() as usize].clone()),
+            ArchivedParameterImage::Ppto(v) => FinOpParameter::Ppto(v.to_native()),
+            ArchivedParameterImage::Seca(v) => FinOpParameter::Seca(AccountId(v.to_native())),
+            ArchivedParameterImage::Typ(v) => FinOpParameter::Typ(strings[v.to_native() as usize].clone()),
+            ArchivedParameterImage::Inst(v) => FinOpParameter::Inst(InstrumentId(v.to_native())),
+            ArchivedParameterImage::CustomNumeric(code, v) =>
+                FinOpParameter::custom(code.to_string(), ParameterValue::Numeric(v.to_native())),
+            ArchivedParameterImage::CustomString(code, v) =>
+                FinOpParameter::custom(code.to_string(), ParameterValue::String(v.to_string())),
+            ArchivedParameterImage::CustomDate(code, v) =>
+                FinOpParameter::custom(code.to_string(), ParameterValue::Date(v.to_native()))
+        }
+    }
+}
+
+impl ArchivedOperationImage {
+    pub fn get_date(&self) -> u64 {
+        self.date.to_native()
+    }
+
+    pub fn get_account(&self) -> AccountId {
+        AccountId(self.account.to_native())
+    }
+
+    pub fn get_subcategory(&self) -> SubcategoryId {
+        SubcategoryId(self.subcategory.to_native())
+    }
+
+    pub fn get_summa(&self) -> i64 {
+        self.summa.to_native()
+    }
+
+    fn to_operation(&self, strings: &[Arc<str>]) -> FinanceOperation {
+        let parameters = self.parameters.iter().map(|p|p.to_parameter(strings)).collect();
+        let mut op = FinanceOperation::new(self.get_date(), self.get_account(), self.get_subcategory(),
+                                           self.amount.as_ref().map(|a|a.to_native()), self.get_summa(),
+                                           parameters);
+        op.set_payee(self.payee.as_ref().map(|p|PayeeId(p.to_native())));
+        for a in self.attachments.iter() {
+            op.add_attachment(a.to_string());
+        }
+        op
+    }
+}
+
+pub fn archive_record(record: &FinanceRecord) -> Result<Vec<u8>, Error> {
+    archive(record.operations.iter(), record.totals.iter().map(|(a, v)|(a.0, *v)).collect())
+}
+
+/// Archived operations of a date file, totals are kept by the month record only.
+pub fn archive_operations<'a>(operations: impl Iterator<Item = &'a FinanceOperation>) -> Result<Vec<u8>, Error> {
+    archive(operations, Vec::new())
+}
+
+fn archive<'a>(operations: impl Iterator<Item = &'a FinanceOperation>, totals: Vec<(u64, i64)>)
+    -> Result<Vec<u8>, Error> {
+    let mut table = StringTable{strings: Vec::new(), indexes: HashMap::new()};
+    let operations = operations.map(|op|OperationImage{
+        date: op.date,
+        account: op.get_account().0,
+        subcategory: op.get_subcategory().0,
+        amount: op.get_amount(),
+        summa: op.get_summa(),
+        parameters: op.get_parameters().iter().map(|p|ParameterImage::new(p, &mut table)).collect(),
+        payee: op.get_payee().map(|p|p.0),
+        attachments: op.get_attachments().clone()
+    }).collect();
+    let image = RecordImage{strings: table.strings, operations, totals};
+    rkyv::to_bytes::<rancor::Error>(&image)
+        .map(|b|b.into_vec())
+        .map_err(|e|Error::new(ErrorKind::InvalidData, e.to_string()))
+}
+
+pub struct ArchivedMonth {
+    bytes: AlignedVec
+}
+
+impl ArchivedMonth {
+    /// Validates the buffer, afterwards operations are accessed without copying.
+    pub fn new(data: &[u8]) -> Result<ArchivedMonth, Error> {
+        let mut bytes = AlignedVec::with_capacity(data.len());
+        bytes.extend_from_slice(data);
+        rkyv::access::<ArchivedRecordImage, rancor::Error>(&bytes)
+            .map_err(|e|Error::new(ErrorKind::InvalidData, e.to_string()))?;
+        Ok(ArchivedMonth{bytes})
+    }
+
+    fn image(&self) -> &ArchivedRecordImage {
+        // SAFETY: the buffer was validated in new and is never modified afterwards
+        unsafe { rkyv::access_unchecked::<ArchivedRecordImage>(&self.bytes) }
+    }
+
+    pub fn operations(&self) -> &ArchivedVec<ArchivedOperationImage> {
+        &self.image().operations
+    }
+
+    pub fn get_string(&self, index: u32) -> Option<&str> {
+        self.image().strings.get(index as usize).map(|s|s.as_str())
+    }
+
+    /// Deserializes all operations, strings are shared through the pool.
+    pub fn to_record(&self, pool: &mut StringPool) -> Result<FinanceRecord, Error> {
+        let image = self.image();
+        let strings: Vec<Arc<str>> = image.strings.iter().map(|s|pool.intern(s.as_str())).collect();
+        for op in image.operations.iter() {
+            for p in op.parameters.iter() {
+                if let ArchivedParameterImage::Netw(i) | ArchivedParameterImage::Typ(i) = p {
+                    if i.to_native() as usize >= strings.len() {
+                        return Err(Error::new(ErrorKind::InvalidData, "invalid string index"));
+                    }
+                }
+            }
+        }
+        let mut record = FinanceRecord::new(image.operations.iter().map(|op|op.to_operation(&strings)).collect());
+        record.totals = image.totals.iter()
+            .map(|t|(AccountId(t.0.to_native()), t.1.to_native()))
+            .collect();
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::binary_archive::{archive_record, ArchivedMonth};
+    use crate::core::interner::StringPool;
+    use crate::entities::accounts::AccountId;
+    use crate::entities::finance_operations::{FinOpParameter, FinanceOperation, FinanceRecord};
+    use crate::entities::subcategories::SubcategoryId;
+
+    #[test]
+    fn test_archive_round_trip() -> Result<(), Error> {
+        let mut record = FinanceRecord::new(vec![
+            FinanceOperation::new(20240105, AccountId(1), SubcategoryId(2), None, 1000, Vec::new()),
+            FinanceOperation::new(20240106, AccountId(1), SubcategoryId(3), Some(500), 250,
+                                  vec![FinOpParameter::Seca(AccountId(2)), FinOpParameter::Netw("shop".into())]),
+            FinanceOperation::new(20240107, AccountId(1), SubcategoryId(3), None, 100,
+                                  vec![FinOpParameter::Netw("shop".into())])
+        ]);
+        record.totals.insert(AccountId(1), 42);
+        let month = ArchivedMonth::new(&archive_record(&record)?)?;
+        assert_eq!(month.operations().len(), 3);
+        assert_eq!(month.operations()[1].get_summa(), 250);
+        assert_eq!(month.get_string(0), Some("shop"));
+        assert_eq!(month.get_string(1), None);
+        let restored = month.to_record(&mut StringPool::new())?;
+        assert_eq!(restored.totals[&AccountId(1)], 42);
+        assert!(restored.operations[1].is_duplicate_of(&record.operations[1]));
+        Ok(())
+    }
+}
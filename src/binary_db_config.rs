@@ -1,34 +1,358 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::ops::Add;
+use std::sync::Arc;
+use crate::binary_format::{decode_records, encode_records, BinaryRecord};
+use crate::core::crypto::{AesGcmProcessor, CryptoProcessor};
 use crate::core::data_source::DataSource;
-use crate::core::time_series_data::DatedSource;
+use crate::core::dates::check_date;
+use crate::core::interner::StringPool;
+use crate::core::time_series_data::{month_folders, DatedSource, FileInfo, FileWithDate, SaveBatch};
 use crate::db::DBConfiguration;
 use crate::entities::accounts::Account;
-use crate::entities::finance_operations::FinanceRecord;
+use crate::entities::currencies::{Currency, SharedMinorUnits};
+use crate::entities::payees::Payee;
+use crate::entities::parameters::ParameterDefinition;
+use crate::entities::loans::Loan;
+use crate::entities::instruments::{Instrument, InstrumentPrice};
+use crate::entities::goals::Goal;
+use crate::entities::budgets::BudgetLine;
+use crate::entities::rates::ExchangeRate;
+use crate::entities::settings::Settings;
+use crate::entities::templates::OperationTemplate;
+use crate::entities::validation_rules::ValidationRule;
+use crate::reports::custom::ReportSpec;
+use crate::entities::finance_operations::{FinanceOperation, FinanceRecord};
 use crate::entities::subcategories::{Category, Subcategory};
 
+/// Every file of a binary database, attachments and archives included, is encrypted with AES-256-GCM.
 pub struct BinaryDBConfiguration {
-    aes_key: [u8; 32]
+    crypto: Arc<AesGcmProcessor>
 }
 
 impl BinaryDBConfiguration {
     pub fn new(aes_key: [u8; 32]) -> BinaryDBConfiguration {
-        BinaryDBConfiguration{aes_key}
+        BinaryDBConfiguration{crypto: Arc::new(AesGcmProcessor::new(&aes_key))}
+    }
+
+    fn source<T: BinaryRecord>(&self) -> Box<dyn DataSource<Vec<T>>> {
+        Box::new(BinaryDataSource{crypto: self.crypto.clone()})
     }
 }
 
 impl DBConfiguration for BinaryDBConfiguration {
     fn get_accounts_source(&self) -> Box<dyn DataSource<Vec<Account>>> {
-        todo!()
+        self.source()
     }
 
     fn get_categories_source(&self) -> Box<dyn DataSource<Vec<Category>>> {
-        todo!()
+        self.source()
     }
 
     fn get_subcategories_source(&self) -> Box<dyn DataSource<Vec<Subcategory>>> {
-        todo!()
+        self.source()
+    }
+
+    fn get_currencies_source(&self) -> Box<dyn DataSource<Vec<Currency>>> {
+        self.source()
+    }
+
+    fn get_payees_source(&self) -> Box<dyn DataSource<Vec<Payee>>> {
+        self.source()
+    }
+
+    fn get_parameters_source(&self) -> Box<dyn DataSource<Vec<ParameterDefinition>>> {
+        self.source()
+    }
+
+    fn get_loans_source(&self) -> Box<dyn DataSource<Vec<Loan>>> {
+        self.source()
+    }
+
+    fn get_instruments_source(&self) -> Box<dyn DataSource<Vec<Instrument>>> {
+        self.source()
+    }
+
+    fn get_prices_source(&self) -> Box<dyn DataSource<Vec<InstrumentPrice>>> {
+        self.source()
+    }
+
+    fn get_goals_source(&self) -> Box<dyn DataSource<Vec<Goal>>> {
+        self.source()
+    }
+
+    fn get_budgets_source(&self) -> Box<dyn DataSource<Vec<BudgetLine>>> {
+        self.source()
+    }
+
+    fn get_rates_source(&self) -> Box<dyn DataSource<Vec<ExchangeRate>>> {
+        self.source()
+    }
+
+    fn get_settings_source(&self) -> Box<dyn DataSource<Settings>> {
+        Box::new(BinaryDataSource{crypto: self.crypto.clone()})
+    }
+
+    fn get_templates_source(&self) -> Box<dyn DataSource<Vec<OperationTemplate>>> {
+        self.source()
+    }
+
+    fn get_report_specs_source(&self) -> Box<dyn DataSource<Vec<ReportSpec>>> {
+        self.source()
+    }
+
+    fn get_validation_rules_source(&self) -> Box<dyn DataSource<Vec<ValidationRule>>> {
+        self.source()
+    }
+
+    /// Sums are stored as integers, so they need no conversion.
+    fn get_main_data_source(&self, _minor_units: SharedMinorUnits) -> Box<dyn DatedSource<FinanceRecord>> {
+        Box::new(BinaryDatedSource{pool: StringPool::new(), crypto: self.crypto.clone()})
+    }
+
+    fn get_attachments_crypto(&self) -> Option<Box<dyn CryptoProcessor>> {
+        Some(Box::new(self.crypto.clone()))
+    }
+
+    fn encrypts_files(&self) -> bool {
+        true
+    }
+}
+
+const BINARY_EXTENSION: &str = ".bin";
+/// Operations of a date archived with rkyv, written instead of operations.bin when the rkyv feature is enabled.
+const ARCHIVE_FILE_NAME: &str = "operations.rkyv";
+#[cfg(feature = "rkyv")]
+const OPERATIONS_FILE_NAME: &str = ARCHIVE_FILE_NAME;
+#[cfg(not(feature = "rkyv"))]
+const OPERATIONS_FILE_NAME: &str = "operations.bin";
+
+/// Reads the encrypted file and decodes its records, errors name the file.
+fn load_records<T: BinaryRecord>(file_name: &str, crypto: &AesGcmProcessor) -> Result<Vec<T>, Error> {
+    crypto.decode(&fs::read(file_name)?)
+        .and_then(|data|decode_records(&data))
+        .map_err(|e|Error::new(e.kind(), format!("{}: {}", file_name, e)))
+}
+
+struct BinaryDataSource {
+    crypto: Arc<AesGcmProcessor>
+}
+
+impl<T: BinaryRecord> DataSource<Vec<T>> for BinaryDataSource {
+    fn load(&self, file_name: String, add_extension: bool) -> Result<Vec<T>, Error> {
+        let fname = if add_extension {file_name.add(BINARY_EXTENSION)} else {file_name};
+        load_records(&fname, &self.crypto)
+    }
+
+    fn save(&self, data: &Vec<T>, file_name: String) -> Result<(), Error> {
+        fs::write(file_name.add(BINARY_EXTENSION), self.crypto.encode(&encode_records(data.iter()))?)
+    }
+}
+
+/// Settings file holds a single record.
+impl DataSource<Settings> for BinaryDataSource {
+    fn load(&self, file_name: String, add_extension: bool) -> Result<Settings, Error> {
+        let fname = if add_extension {file_name.add(BINARY_EXTENSION)} else {file_name};
+        load_records(&fname, &self.crypto)?.pop()
+            .ok_or(Error::new(ErrorKind::InvalidData, format!("{}: settings record expected", fname)))
+    }
+
+    fn save(&self, data: &Settings, file_name: String) -> Result<(), Error> {
+        fs::write(file_name.add(BINARY_EXTENSION), self.crypto.encode(&encode_records([data].into_iter()))?)
+    }
+}
+
+/// Same layout as the JSON database: operations of a date are kept in dates/yyyymmdd/operations.bin,
+/// or in dates/yyyymmdd/operations.rkyv with the rkyv feature.
+struct BinaryDatedSource {
+    pool: StringPool,
+    crypto: Arc<AesGcmProcessor>
+}
+
+impl BinaryDatedSource {
+    /// Archived files are validated once and read through ArchivedMonth, other files are decoded record by record.
+    fn load_file(&mut self, file_name: &str) -> Result<Vec<FinanceOperation>, Error> {
+        if !file_name.ends_with(ARCHIVE_FILE_NAME) {
+            return load_records(file_name, &self.crypto);
+        }
+        #[cfg(feature = "rkyv")]
+        {
+            self.crypto.decode(&fs::read(file_name)?)
+                .and_then(|data|crate::binary_archive::ArchivedMonth::new(&data))
+                .and_then(|month|month.to_record(&mut self.pool))
+                .map(|record|record.operations)
+                .map_err(|e|Error::new(e.kind(), format!("{}: {}", file_name, e)))
+        }
+        #[cfg(not(feature = "rkyv"))]
+        Err(Error::new(ErrorKind::Unsupported, format!("{}: archived operations require the rkyv feature", file_name)))
     }
 
-    fn get_main_data_source(&self) -> Box<dyn DatedSource<FinanceRecord>> {
-        todo!()
+    fn encode_operations(&self, ops: &[&FinanceOperation]) -> Result<Vec<u8>, Error> {
+        #[cfg(feature = "rkyv")]
+        let data = crate::binary_archive::archive_operations(ops.iter().copied())?;
+        #[cfg(not(feature = "rkyv"))]
+        let data = encode_records(ops.iter().copied());
+        self.crypto.encode(&data)
     }
-}
\ No newline at end of file
+}
+
+impl DatedSource<FinanceRecord> for BinaryDatedSource {
+    fn load(&mut self, files: Vec<FileWithDate>) -> Result<FinanceRecord, Error> {
+        let mut record = FinanceRecord::new(Vec::new());
+        for file in files {
+            let mut ops = self.load_file(&file.name)?;
+            for op in ops.iter_mut() {
+                op.date = file.date;
+                op.intern_strings(&mut self.pool);
+            }
+            record.add_file_operations(file.name, ops);
+        }
+        Ok(record)
+    }
+
+    fn parse_date(&self, info: &FileInfo) -> Result<u64, Error> {
+        let date = info.convert_folder_name_to_number()?;
+        check_date(date)?;
+        Ok(date)
+    }
+
+    /// Every date of the month is written to a single file, other files of the month are removed,
+    /// so a save converts the month between operations.bin and operations.rkyv.
+    fn prepare_save(&self, data: &FinanceRecord, data_folder_path: &str, key: u64) -> Result<SaveBatch, Error> {
+        let mut batch = SaveBatch::default();
+        let mut by_date: BTreeMap<u64, Vec<&FinanceOperation>> = BTreeMap::new();
+        for op in &data.operations {
+            by_date.entry(op.date).or_default().push(op);
+        }
+        for (date, ops) in &by_date {
+            let folder = format!("{}/{}", data_folder_path, date);
+            batch.files.push((format!("{}/{}", folder, OPERATIONS_FILE_NAME), self.encode_operations(ops)?));
+            batch.folders.insert(folder);
+        }
+        for (date, folder) in month_folders(data_folder_path, key)? {
+            let keep = by_date.contains_key(&date);
+            for entry in fs::read_dir(&folder)? {
+                let entry = entry?;
+                if !keep || entry.file_name() != OPERATIONS_FILE_NAME {
+                    batch.obsolete_files.push(entry.path().to_string_lossy().to_string());
+                }
+            }
+            if !keep {
+                batch.obsolete_folders.push(folder);
+            }
+        }
+        Ok(batch)
+    }
+
+    fn get_files(&self, data_folder_path: &str, key: u64) -> Result<Vec<FileWithDate>, Error> {
+        let mut result = Vec::new();
+        for (date, folder) in month_folders(data_folder_path, key)? {
+            for entry in fs::read_dir(&folder)? {
+                result.push(FileWithDate{name: entry?.path().to_string_lossy().to_string(), date});
+            }
+        }
+        result.sort_by(|a, b|(a.date, &a.name).cmp(&(b.date, &b.name)));
+        Ok(result)
+    }
+
+    fn fork(&self) -> Box<dyn DatedSource<FinanceRecord>> {
+        Box::new(BinaryDatedSource{pool: StringPool::new(), crypto: self.crypto.clone()})
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::Error;
+    use crate::binary_db_config::BinaryDBConfiguration;
+    use crate::db::HomeAccountingDB;
+    use crate::entities::accounts::AccountId;
+    use crate::entities::finance_operations::FinanceOperation;
+    use crate::entities::payees::PayeeId;
+    use crate::entities::subcategories::SubcategoryId;
+    use crate::json_db_config::JsonDBConfiguration;
+
+    #[test]
+    fn test_migrate_to_binary() -> Result<(), Error> {
+        let folder = temp_dir().join("had_test_binary_migrate").to_str().unwrap().to_string();
+        let dest = folder.clone() + "/binary";
+        let source = folder.clone() + "/json";
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&source)?;
+        fs::write(source.clone() + "/accounts.json",
+                  r#"[{"id": 1, "name": "Card", "valutaCode": "UAH", "activeTo": null, "isCash": false},
+                      {"id": 2, "name": "Cash", "valutaCode": "UAH", "activeTo": null, "isCash": true}]"#)?;
+        fs::write(source.clone() + "/categories.json", r#"[{"id": 1, "name": "Food"}]"#)?;
+        fs::write(source.clone() + "/subcategories.json",
+                  r#"[{"id": 1, "name": "Groceries", "code": null, "operationCodeId": "EXPN", "categoryId": 1}]"#)?;
+        fs::write(source.clone() + "/currencies.json",
+                  r#"[{"code": "UAH", "symbol": "₴", "minorUnits": 2, "rounding": "halfEven"}]"#)?;
+        fs::write(source.clone() + "/payees.json", r#"[{"id": 5, "name": "Market"}]"#)?;
+        fs::write(source.clone() + "/settings.json", r#"{"baseCurrency": "UAH", "warmMonths": 3}"#)?;
+        let db = HomeAccountingDB::new(source.clone(), Box::new(JsonDBConfiguration::new()), 100)?;
+        let mut op = FinanceOperation::new(20240105, AccountId(1), SubcategoryId(1), None, 2550, Vec::new());
+        op.set_payee(Some(PayeeId(5)));
+        db.add_operation(op)?;
+        db.add_operation(FinanceOperation::new(20240210, AccountId(1), SubcategoryId(1), None, 1000, Vec::new()))?;
+        db.flush(false)?;
+        let key = [7u8; 32];
+        fs::write(source.clone() + "/loans.json", "[{")?;
+        assert!(db.migrate(dest.clone(), Box::new(BinaryDBConfiguration::new(key))).is_err());
+        assert!(!fs::exists(&dest)?);
+        fs::remove_file(source.clone() + "/loans.json")?;
+        assert_eq!(db.migrate(dest.clone(), Box::new(BinaryDBConfiguration::new(key)))?, 2);
+        assert!(fs::exists(dest.clone() + "/currencies.bin")?);
+        let copy = HomeAccountingDB::load(dest.clone(), Box::new(BinaryDBConfiguration::new(key)), 100)?;
+        assert_eq!(copy.get_currencies().get("UAH")?.symbol, "₴");
+        assert_eq!(copy.get_payees().get(PayeeId(5))?.name, "Market");
+        assert_eq!(copy.get_settings().warm_months, Some(3));
+        let operations = copy.get_operations(0, u64::MAX)?;
+        assert_eq!(operations.iter().map(|o|o.get_summa()).collect::<Vec<_>>(), vec![2550, 1000]);
+        assert_eq!(operations[0].get_payee(), Some(PayeeId(5)));
+        // a wrong key is rejected instead of loading garbage
+        assert!(HomeAccountingDB::load(dest.clone(), Box::new(BinaryDBConfiguration::new([8u8; 32])), 100).is_err());
+        fs::remove_dir_all(&folder)
+    }
+
+    /// Months written before the rkyv feature was enabled are read and converted on save.
+    #[cfg(feature = "rkyv")]
+    #[test]
+    fn test_archived_months() -> Result<(), Error> {
+        use crate::binary_format::encode_records;
+        use crate::core::crypto::{AesGcmProcessor, CryptoProcessor};
+        use crate::entities::finance_operations::FinOpParameter;
+        let folder = temp_dir().join("had_test_binary_archived").to_str().unwrap().to_string();
+        let dest = folder.clone() + "/binary";
+        let source = folder.clone() + "/json";
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&source)?;
+        fs::write(source.clone() + "/accounts.json",
+                  r#"[{"id": 1, "name": "Cash", "valutaCode": "UAH", "activeTo": null, "isCash": true}]"#)?;
+        fs::write(source.clone() + "/categories.json", r#"[{"id": 1, "name": "Food"}]"#)?;
+        fs::write(source.clone() + "/subcategories.json",
+                  r#"[{"id": 1, "name": "Groceries", "code": null, "operationCodeId": "EXPN", "categoryId": 1}]"#)?;
+        let db = HomeAccountingDB::new(source.clone(), Box::new(JsonDBConfiguration::new()), 100)?;
+        db.add_operation(FinanceOperation::new(20240105, AccountId(1), SubcategoryId(1), None, 100, Vec::new()))?;
+        db.flush(false)?;
+        let key = [7u8; 32];
+        db.migrate(dest.clone(), Box::new(BinaryDBConfiguration::new(key)))?;
+        let month = dest.clone() + "/dates/20240105";
+        assert!(fs::exists(month.clone() + "/operations.rkyv")?);
+        fs::remove_file(month.clone() + "/operations.rkyv")?;
+        let op = FinanceOperation::new(20240105, AccountId(1), SubcategoryId(1), None, 1000,
+                                       vec![FinOpParameter::Netw("shop".into())]);
+        fs::write(month.clone() + "/operations.bin", AesGcmProcessor::new(&key).encode(&encode_records([&op].into_iter()))?)?;
+        let db = HomeAccountingDB::load(dest.clone(), Box::new(BinaryDBConfiguration::new(key)), 100)?;
+        db.add_operation(FinanceOperation::new(20240106, AccountId(1), SubcategoryId(1), None, 500, Vec::new()))?;
+        db.flush(false)?;
+        assert!(!fs::exists(month.clone() + "/operations.bin")?);
+        assert!(fs::exists(month.clone() + "/operations.rkyv")?);
+        let copy = HomeAccountingDB::load(dest.clone(), Box::new(BinaryDBConfiguration::new(key)), 100)?;
+        let operations = copy.get_operations(0, u64::MAX)?;
+        assert_eq!(operations.iter().map(|o|o.get_summa()).collect::<Vec<_>>(), vec![1000, 500]);
+        assert_eq!(operations[0].get_network(), Some("shop"));
+        fs::remove_dir_all(&folder)
+    }
+}
@@ -0,0 +1,681 @@
+//! Compact binary encoding of dictionaries, settings and operations. A file is a u32 record count followed by
+//! the records, every record is prefixed with its u32 length. Integers are little endian, strings are
+//! UTF-8 prefixed with u32 length, optional values are prefixed with a presence byte.
+//! Fields appended to a record by newer versions are skipped by older readers.
+
+use std::io::{Error, ErrorKind};
+use crate::core::amounts::Rounding;
+use crate::entities::accounts::{Account, AccountGroup, AccountId, CurrencyChange};
+use crate::entities::budgets::{BudgetLine, Rollover};
+use crate::entities::currencies::Currency;
+use crate::entities::finance_operations::{FinOpParameter, FinanceOperation, ParameterValue};
+use crate::entities::goals::{Goal, GoalId};
+use crate::entities::instruments::{Instrument, InstrumentId, InstrumentPrice};
+use crate::entities::loans::{Loan, LoanId};
+use crate::entities::parameters::{ParameterDefinition, ParameterValueType};
+use crate::entities::payees::{Payee, PayeeId};
+use crate::entities::rates::ExchangeRate;
+use crate::entities::settings::Settings;
+use crate::entities::subcategories::{Category, CategoryId, Subcategory, SubcategoryCode, SubcategoryId,
+                                     SubcategoryOperationCode};
+use crate::entities::templates::OperationTemplate;
+use crate::entities::validation_rules::ValidationRule;
+use crate::reports::custom::{Aggregate, GroupBy, ReportSpec};
+
+pub trait BinaryRecord: Sized {
+    fn write(&self, writer: &mut BinaryWriter);
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error>;
+}
+
+#[derive(Default)]
+pub struct BinaryWriter {
+    data: Vec<u8>
+}
+
+impl BinaryWriter {
+    pub fn new() -> BinaryWriter {
+        BinaryWriter::default()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_i64(&mut self, value: i64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_string(&mut self, value: &str) {
+        self.write_u32(value.len() as u32);
+        self.data.extend_from_slice(value.as_bytes());
+    }
+
+    pub fn write_option_u64(&mut self, value: Option<u64>) {
+        self.write_bool(value.is_some());
+        if let Some(v) = value {
+            self.write_u64(v);
+        }
+    }
+
+    pub fn write_option_i64(&mut self, value: Option<i64>) {
+        self.write_bool(value.is_some());
+        if let Some(v) = value {
+            self.write_i64(v);
+        }
+    }
+
+    pub fn write_option_string(&mut self, value: Option<&str>) {
+        self.write_bool(value.is_some());
+        if let Some(v) = value {
+            self.write_string(v);
+        }
+    }
+
+    pub fn write_strings(&mut self, values: &[String]) {
+        self.write_u32(values.len() as u32);
+        for v in values {
+            self.write_string(v);
+        }
+    }
+
+    /// Enum value is written as its index in variants.
+    pub fn write_enum<T: PartialEq>(&mut self, value: &T, variants: &[T]) {
+        self.write_u8(variants.iter().position(|v|v == value).expect("value is one of the variants") as u8);
+    }
+}
+
+pub struct BinaryReader<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl<'a> BinaryReader<'a> {
+    pub fn new(data: &'a [u8]) -> BinaryReader<'a> {
+        BinaryReader{data, position: 0}
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position == self.data.len()
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() - self.position < length {
+            return Err(Error::new(ErrorKind::InvalidData, "unexpected end of binary record"));
+        }
+        let bytes = &self.data[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, Error> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            v => Err(Error::new(ErrorKind::InvalidData, format!("invalid boolean value {}", v)))
+        }
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_i64(&mut self) -> Result<i64, Error> {
+        Ok(i64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_string(&mut self) -> Result<String, Error> {
+        let length = self.read_u32()? as usize;
+        String::from_utf8(self.read_bytes(length)?.to_vec())
+            .map_err(|_|Error::new(ErrorKind::InvalidData, "invalid UTF-8 string"))
+    }
+
+    pub fn read_option_u64(&mut self) -> Result<Option<u64>, Error> {
+        Ok(if self.read_bool()? {Some(self.read_u64()?)} else {None})
+    }
+
+    pub fn read_option_i64(&mut self) -> Result<Option<i64>, Error> {
+        Ok(if self.read_bool()? {Some(self.read_i64()?)} else {None})
+    }
+
+    pub fn read_option_string(&mut self) -> Result<Option<String>, Error> {
+        Ok(if self.read_bool()? {Some(self.read_string()?)} else {None})
+    }
+
+    pub fn read_strings(&mut self) -> Result<Vec<String>, Error> {
+        (0..self.read_u32()?).map(|_|self.read_string()).collect()
+    }
+
+    pub fn read_enum<T: Copy>(&mut self, variants: &[T], name: &str) -> Result<T, Error> {
+        let index = self.read_u8()?;
+        variants.get(index as usize).copied()
+            .ok_or(Error::new(ErrorKind::InvalidData, format!("invalid {} {}", name, index)))
+    }
+}
+
+pub fn encode_records<'a, T: BinaryRecord + 'a>(records: impl ExactSizeIterator<Item = &'a T>) -> Vec<u8> {
+    let mut writer = BinaryWriter::new();
+    writer.write_u32(records.len() as u32);
+    for record in records {
+        let mut record_writer = BinaryWriter::new();
+        record.write(&mut record_writer);
+        let bytes = record_writer.into_bytes();
+        writer.write_u32(bytes.len() as u32);
+        writer.data.extend_from_slice(&bytes);
+    }
+    writer.into_bytes()
+}
+
+pub fn decode_records<T: BinaryRecord>(data: &[u8]) -> Result<Vec<T>, Error> {
+    let mut reader = BinaryReader::new(data);
+    let count = reader.read_u32()? as usize;
+    let mut records = Vec::with_capacity(count.min(data.len()));
+    for index in 0..count {
+        let length = reader.read_u32()? as usize;
+        let mut record_reader = BinaryReader::new(reader.read_bytes(length)?);
+        records.push(T::read(&mut record_reader)
+            .map_err(|e|Error::new(e.kind(), format!("record {}: {}", index, e)))?);
+    }
+    if !reader.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, "unexpected data after the last record"));
+    }
+    Ok(records)
+}
+
+impl BinaryRecord for Account {
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_u64(self.id.0);
+        writer.write_string(&self.name);
+        writer.write_string(&self.currency);
+        writer.write_option_u64(self.active_to);
+        writer.write_bool(self.is_cash());
+        writer.write_option_string(self.get_explicit_group().map(|g|g.as_str()));
+        writer.write_u32(self.order);
+        writer.write_bool(self.retired);
+        writer.write_u32(self.currency_history.len() as u32);
+        for change in &self.currency_history {
+            writer.write_option_u64(change.date);
+            writer.write_string(&change.currency);
+            writer.write_i64(change.rate);
+        }
+        writer.write_option_i64(self.min_balance);
+        writer.write_option_i64(self.credit_limit);
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        let id = AccountId(reader.read_u64()?);
+        let name = reader.read_string()?;
+        let currency = reader.read_string()?;
+        let active_to = reader.read_option_u64()?;
+        let mut account = Account::new(id, name, currency, reader.read_bool()?);
+        account.active_to = active_to;
+        if let Some(group) = reader.read_option_string()? {
+            account.set_group(Some(AccountGroup::parse(&group)
+                .ok_or(Error::new(ErrorKind::InvalidData, format!("invalid account group {}", group)))?));
+        }
+        account.order = reader.read_u32()?;
+        account.retired = reader.read_bool()?;
+        for _ in 0..reader.read_u32()? {
+            account.currency_history.push(CurrencyChange{date: reader.read_option_u64()?,
+                currency: reader.read_string()?, rate: reader.read_i64()?});
+        }
+        account.min_balance = reader.read_option_i64()?;
+        account.credit_limit = reader.read_option_i64()?;
+        Ok(account)
+    }
+}
+
+impl BinaryRecord for Category {
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_u64(self.id.0);
+        writer.write_string(&self.name);
+        writer.write_bool(self.tax_relevant);
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        Ok(Category{id: CategoryId(reader.read_u64()?), name: reader.read_string()?,
+            tax_relevant: reader.read_bool()?})
+    }
+}
+
+impl BinaryRecord for Subcategory {
+    /// Code is written as empty string when subcategory has no code.
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_u64(self.id.0);
+        writer.write_string(&self.name);
+        writer.write_string(self.code.as_str());
+        writer.write_string(self.operation_code.as_str());
+        writer.write_u64(self.category.0);
+        writer.write_bool(self.tax_relevant);
+        writer.write_bool(self.retired);
+        writer.write_option_i64(self.soft_limit);
+        writer.write_option_i64(self.hard_limit);
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        let id = SubcategoryId(reader.read_u64()?);
+        let name = reader.read_string()?;
+        let code = match reader.read_string()? {
+            c if c.is_empty() => SubcategoryCode::None,
+            c => SubcategoryCode::parse(c)
+        };
+        let operation_code = reader.read_string()?;
+        let operation_code = SubcategoryOperationCode::parse(&operation_code)
+            .ok_or(Error::new(ErrorKind::InvalidData, format!("invalid operation code {}", operation_code)))?;
+        Ok(Subcategory{id, name, code, operation_code, category: CategoryId(reader.read_u64()?),
+            tax_relevant: reader.read_bool()?, retired: reader.read_bool()?, soft_limit: reader.read_option_i64()?,
+            hard_limit: reader.read_option_i64()?})
+    }
+}
+
+const PARAMETER_AMOU: u8 = 0;
+const PARAMETER_DIST: u8 = 1;
+const PARAMETER_NETW: u8 = 2;
+const PARAMETER_PPTO: u8 = 3;
+const PARAMETER_SECA: u8 = 4;
+const PARAMETER_TYPE: u8 = 5;
+const PARAMETER_INST: u8 = 6;
+const PARAMETER_CUSTOM_NUMERIC: u8 = 7;
+const PARAMETER_CUSTOM_STRING: u8 = 8;
+const PARAMETER_CUSTOM_DATE: u8 = 9;
+
+fn write_parameter(writer: &mut BinaryWriter, parameter: &FinOpParameter) {
+    match parameter {
+        FinOpParameter::Amou(v) => {writer.write_u8(PARAMETER_AMOU); writer.write_u64(*v)}
+        FinOpParameter::Dist(v) => {writer.write_u8(PARAMETER_DIST); writer.write_u64(*v)}
+        FinOpParameter::Netw(v) => {writer.write_u8(PARAMETER_NETW); writer.write_string(v)}
+        FinOpParameter::Ppto(v) => {writer.write_u8(PARAMETER_PPTO); writer.write_u64(*v)}
+        FinOpParameter::Seca(v) => {writer.write_u8(PARAMETER_SECA); writer.write_u64(v.0)}
+        FinOpParameter::Typ(v) => {writer.write_u8(PARAMETER_TYPE); writer.write_string(v)}
+        FinOpParameter::Inst(v) => {writer.write_u8(PARAMETER_INST); writer.write_u64(v.0)}
+        FinOpParameter::Custom(c) => match &c.value {
+            ParameterValue::Numeric(v) => {
+                writer.write_u8(PARAMETER_CUSTOM_NUMERIC);
+                writer.write_string(&c.code);
+                writer.write_u64(*v);
+            }
+            ParameterValue::String(v) => {
+                writer.write_u8(PARAMETER_CUSTOM_STRING);
+                writer.write_string(&c.code);
+                writer.write_string(v);
+            }
+            ParameterValue::Date(v) => {
+                writer.write_u8(PARAMETER_CUSTOM_DATE);
+                writer.write_string(&c.code);
+                writer.write_u64(*v);
+            }
+        }
+    }
+}
+
+fn read_parameter(reader: &mut BinaryReader) -> Result<FinOpParameter, Error> {
+    Ok(match reader.read_u8()? {
+        PARAMETER_AMOU => FinOpParameter::Amou(reader.read_u64()?),
+        PARAMETER_DIST => FinOpParameter::Dist(reader.read_u64()?),
+        PARAMETER_NETW => FinOpParameter::Netw(reader.read_string()?.into()),
+        PARAMETER_PPTO => FinOpParameter::Ppto(reader.read_u64()?),
+        PARAMETER_SECA => FinOpParameter::Seca(AccountId(reader.read_u64()?)),
+        PARAMETER_TYPE => FinOpParameter::Typ(reader.read_string()?.into()),
+        PARAMETER_INST => FinOpParameter::Inst(InstrumentId(reader.read_u64()?)),
+        PARAMETER_CUSTOM_NUMERIC =>
+            FinOpParameter::custom(reader.read_string()?, ParameterValue::Numeric(reader.read_u64()?)),
+        PARAMETER_CUSTOM_STRING =>
+            FinOpParameter::custom(reader.read_string()?, ParameterValue::String(reader.read_string()?)),
+        PARAMETER_CUSTOM_DATE =>
+            FinOpParameter::custom(reader.read_string()?, ParameterValue::Date(reader.read_u64()?)),
+        t => return Err(Error::new(ErrorKind::InvalidData, format!("invalid parameter type {}", t)))
+    })
+}
+
+impl BinaryRecord for FinanceOperation {
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_u64(self.date);
+        writer.write_u64(self.get_account().0);
+        writer.write_u64(self.get_subcategory().0);
+        writer.write_option_u64(self.get_amount());
+        writer.write_i64(self.get_summa());
+        writer.write_u32(self.get_parameters().len() as u32);
+        for p in self.get_parameters() {
+            write_parameter(writer, p);
+        }
+        writer.write_option_u64(self.get_payee().map(|p|p.0));
+        writer.write_u32(self.get_attachments().len() as u32);
+        for a in self.get_attachments() {
+            writer.write_string(a);
+        }
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        let date = reader.read_u64()?;
+        let account = AccountId(reader.read_u64()?);
+        let subcategory = SubcategoryId(reader.read_u64()?);
+        let amount = reader.read_option_u64()?;
+        let summa = reader.read_i64()?;
+        let parameters = (0..reader.read_u32()?).map(|_|read_parameter(reader)).collect::<Result<Vec<_>, Error>>()?;
+        let mut op = FinanceOperation::new(date, account, subcategory, amount, summa, parameters);
+        op.set_payee(reader.read_option_u64()?.map(PayeeId));
+        for _ in 0..reader.read_u32()? {
+            op.add_attachment(reader.read_string()?);
+        }
+        Ok(op)
+    }
+}
+
+const ROUNDINGS: [Rounding; 3] = [Rounding::HalfUp, Rounding::HalfEven, Rounding::Down];
+const VALUE_TYPES: [ParameterValueType; 3] = [ParameterValueType::Numeric, ParameterValueType::String,
+    ParameterValueType::Date];
+const ROLLOVERS: [Rollover; 4] = [Rollover::None, Rollover::Unused, Rollover::Overspend, Rollover::Both];
+const GROUPS: [GroupBy; 8] = [GroupBy::Year, GroupBy::Month, GroupBy::Day, GroupBy::Account, GroupBy::Currency,
+    GroupBy::Category, GroupBy::Subcategory, GroupBy::Network];
+const AGGREGATES: [Aggregate; 5] = [Aggregate::Sum, Aggregate::Count, Aggregate::Min, Aggregate::Max, Aggregate::Avg];
+
+impl BinaryRecord for Currency {
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_string(&self.code);
+        writer.write_string(&self.symbol);
+        writer.write_u32(self.minor_units);
+        writer.write_enum(&self.rounding, &ROUNDINGS);
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        let mut currency = Currency::new(reader.read_string()?, reader.read_string()?, reader.read_u32()?);
+        currency.rounding = reader.read_enum(&ROUNDINGS, "rounding")?;
+        Ok(currency)
+    }
+}
+
+impl BinaryRecord for Payee {
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_u64(self.id.0);
+        writer.write_string(&self.name);
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        Ok(Payee{id: PayeeId(reader.read_u64()?), name: reader.read_string()?})
+    }
+}
+
+impl BinaryRecord for ParameterDefinition {
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_string(&self.code);
+        writer.write_string(&self.name);
+        writer.write_enum(&self.value_type, &VALUE_TYPES);
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        Ok(ParameterDefinition{code: reader.read_string()?, name: reader.read_string()?,
+            value_type: reader.read_enum(&VALUE_TYPES, "parameter value type")?})
+    }
+}
+
+impl BinaryRecord for Loan {
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_u64(self.id.0);
+        writer.write_string(&self.name);
+        writer.write_u64(self.account.0);
+        writer.write_i64(self.principal);
+        writer.write_u64(self.rate);
+        writer.write_option_u64(self.start);
+        writer.write_i64(self.monthly_payment);
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        Ok(Loan{id: LoanId(reader.read_u64()?), name: reader.read_string()?, account: AccountId(reader.read_u64()?),
+            principal: reader.read_i64()?, rate: reader.read_u64()?, start: reader.read_option_u64()?,
+            monthly_payment: reader.read_i64()?})
+    }
+}
+
+impl BinaryRecord for Instrument {
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_u64(self.id.0);
+        writer.write_string(&self.name);
+        writer.write_string(&self.ticker);
+        writer.write_string(&self.currency);
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        Ok(Instrument{id: InstrumentId(reader.read_u64()?), name: reader.read_string()?,
+            ticker: reader.read_string()?, currency: reader.read_string()?})
+    }
+}
+
+impl BinaryRecord for InstrumentPrice {
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_u64(self.instrument.0);
+        writer.write_option_u64(self.date);
+        writer.write_i64(self.price);
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        Ok(InstrumentPrice{instrument: InstrumentId(reader.read_u64()?), date: reader.read_option_u64()?,
+            price: reader.read_i64()?})
+    }
+}
+
+impl BinaryRecord for Goal {
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_u64(self.id.0);
+        writer.write_string(&self.name);
+        writer.write_i64(self.target);
+        writer.write_option_u64(self.target_date);
+        writer.write_u32(self.accounts.len() as u32);
+        for a in &self.accounts {
+            writer.write_u64(a.0);
+        }
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        Ok(Goal{id: GoalId(reader.read_u64()?), name: reader.read_string()?, target: reader.read_i64()?,
+            target_date: reader.read_option_u64()?,
+            accounts: (0..reader.read_u32()?).map(|_|reader.read_u64().map(AccountId)).collect::<Result<_, Error>>()?})
+    }
+}
+
+impl BinaryRecord for BudgetLine {
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_u64(self.subcategory.0);
+        writer.write_i64(self.amount);
+        writer.write_u64(self.from_month);
+        writer.write_enum(&self.rollover, &ROLLOVERS);
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        Ok(BudgetLine{subcategory: SubcategoryId(reader.read_u64()?), amount: reader.read_i64()?,
+            from_month: reader.read_u64()?, rollover: reader.read_enum(&ROLLOVERS, "rollover")?})
+    }
+}
+
+impl BinaryRecord for ExchangeRate {
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_string(&self.base);
+        writer.write_string(&self.currency);
+        writer.write_option_u64(self.date);
+        writer.write_i64(self.rate);
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        Ok(ExchangeRate{base: reader.read_string()?, currency: reader.read_string()?, date: reader.read_option_u64()?,
+            rate: reader.read_i64()?})
+    }
+}
+
+impl BinaryRecord for Settings {
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_option_string(self.base_currency.as_deref());
+        writer.write_option_u64(self.slow_operation_ms);
+        writer.write_option_i64(self.utc_offset_minutes);
+        writer.write_option_u64(self.day_start_hour);
+        writer.write_option_u64(self.warm_months.map(|m|m as u64));
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        Ok(Settings{base_currency: reader.read_option_string()?, slow_operation_ms: reader.read_option_u64()?,
+            utc_offset_minutes: reader.read_option_i64()?, day_start_hour: reader.read_option_u64()?,
+            warm_months: reader.read_option_u64()?.map(|m|m as usize),
+            // binary files are encrypted as a whole, field encryption is used by JSON databases only
+            encrypted_fields: None, field_key_file: None})
+    }
+}
+
+impl BinaryRecord for OperationTemplate {
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_string(&self.name);
+        writer.write_u64(self.account.0);
+        writer.write_u64(self.subcategory.0);
+        writer.write_option_i64(self.summa);
+        writer.write_option_string(self.network.as_deref());
+        writer.write_option_u64(self.second_account.map(|a|a.0));
+        writer.write_option_u64(self.payee.map(|p|p.0));
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        Ok(OperationTemplate{name: reader.read_string()?, account: AccountId(reader.read_u64()?),
+            subcategory: SubcategoryId(reader.read_u64()?), summa: reader.read_option_i64()?,
+            network: reader.read_option_string()?, second_account: reader.read_option_u64()?.map(AccountId),
+            payee: reader.read_option_u64()?.map(PayeeId)})
+    }
+}
+
+impl BinaryRecord for ReportSpec {
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_string(&self.name);
+        writer.write_string(&self.filter);
+        writer.write_u32(self.group_by.len() as u32);
+        for g in &self.group_by {
+            writer.write_enum(g, &GROUPS);
+        }
+        writer.write_u32(self.aggregates.len() as u32);
+        for a in &self.aggregates {
+            writer.write_enum(a, &AGGREGATES);
+        }
+        writer.write_strings(&self.columns);
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        let name = reader.read_string()?;
+        let filter = reader.read_string()?;
+        let group_by = (0..reader.read_u32()?).map(|_|reader.read_enum(&GROUPS, "group")).collect::<Result<_, Error>>()?;
+        let aggregates = (0..reader.read_u32()?).map(|_|reader.read_enum(&AGGREGATES, "aggregate"))
+            .collect::<Result<_, Error>>()?;
+        Ok(ReportSpec{name, filter, group_by, aggregates, columns: reader.read_strings()?})
+    }
+}
+
+impl BinaryRecord for ValidationRule {
+    fn write(&self, writer: &mut BinaryWriter) {
+        writer.write_string(&self.name);
+        writer.write_option_u64(self.account.map(|a|a.0));
+        writer.write_option_u64(self.subcategory.map(|s|s.0));
+        writer.write_option_i64(self.min_summa);
+        writer.write_option_i64(self.max_summa);
+        writer.write_option_string(self.required_parameter.as_deref());
+        writer.write_strings(&self.command);
+    }
+
+    fn read(reader: &mut BinaryReader) -> Result<Self, Error> {
+        Ok(ValidationRule{name: reader.read_string()?, account: reader.read_option_u64()?.map(AccountId),
+            subcategory: reader.read_option_u64()?.map(SubcategoryId), min_summa: reader.read_option_i64()?,
+            max_summa: reader.read_option_i64()?, required_parameter: reader.read_option_string()?,
+            command: reader.read_strings()?})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Error, ErrorKind};
+    use crate::binary_format::{decode_records, encode_records, BinaryRecord, BinaryWriter};
+    use crate::entities::accounts::{Account, AccountGroup, AccountId, CurrencyChange};
+    use crate::entities::finance_operations::{FinOpParameter, FinanceOperation, ParameterValue};
+    use crate::entities::payees::PayeeId;
+    use crate::entities::subcategories::{Category, CategoryId, Subcategory, SubcategoryCode, SubcategoryId,
+                                         SubcategoryOperationCode};
+
+    #[test]
+    fn test_accounts_and_subcategories() -> Result<(), Error> {
+        let mut card = Account::new(AccountId(1), "Card".to_string(), "UAH".to_string(), false);
+        card.set_group(Some(AccountGroup::Savings));
+        card.active_to = Some(20241231);
+        card.currency_history.push(CurrencyChange{date: Some(20200101), currency: "USD".to_string(), rate: 27000000});
+        card.credit_limit = Some(500000);
+        let cash = Account::new(AccountId(2), "Cash".to_string(), "UAH".to_string(), true);
+        let accounts: Vec<Account> = decode_records(&encode_records([card, cash].iter()))?;
+        assert_eq!(accounts.len(), 2);
+        assert!(!accounts[0].is_cash() && accounts[1].is_cash());
+        assert_eq!(accounts[0].get_explicit_group(), Some(AccountGroup::Savings));
+        assert_eq!(accounts[0].active_to, Some(20241231));
+        assert_eq!(accounts[0].currency_history[0].rate, 27000000);
+        assert_eq!((accounts[0].min_balance, accounts[0].credit_limit), (None, Some(500000)));
+        let subcategories = [
+            Subcategory{id: SubcategoryId(3), name: "Fuel".to_string(), code: SubcategoryCode::Fuel,
+                operation_code: SubcategoryOperationCode::Expn, category: CategoryId(2), tax_relevant: false,
+                retired: true, soft_limit: Some(100000), hard_limit: None},
+            Subcategory{id: SubcategoryId(4), name: "Food".to_string(), code: SubcategoryCode::None,
+                operation_code: SubcategoryOperationCode::Expn, category: CategoryId(2), tax_relevant: true,
+                retired: false, soft_limit: None, hard_limit: None}
+        ];
+        let decoded: Vec<Subcategory> = decode_records(&encode_records(subcategories.iter()))?;
+        assert_eq!(decoded[0].code, SubcategoryCode::Fuel);
+        assert_eq!(decoded[1].code, SubcategoryCode::None);
+        assert!(decoded[0].retired && decoded[1].tax_relevant);
+        assert_eq!(decoded[0].soft_limit, Some(100000));
+        let categories = [Category{id: CategoryId(2), name: "Car".to_string(), tax_relevant: false}];
+        assert_eq!(decode_records::<Category>(&encode_records(categories.iter()))?[0].name, "Car");
+        Ok(())
+    }
+
+    #[test]
+    fn test_operations() -> Result<(), Error> {
+        let mut op = FinanceOperation::new(20240105, AccountId(1), SubcategoryId(3), Some(40500), 210050,
+                                           vec![FinOpParameter::Netw("OKKO".into()),
+                                                FinOpParameter::custom("ODOM".to_string(), ParameterValue::Numeric(123456))]);
+        op.set_payee(Some(PayeeId(7)));
+        op.add_attachment("receipt.jpg".to_string());
+        let bytes = encode_records([op.copy()].iter());
+        let decoded: Vec<FinanceOperation> = decode_records(&bytes)?;
+        assert_eq!((decoded[0].date, decoded[0].get_account(), decoded[0].get_subcategory()),
+                   (20240105, AccountId(1), SubcategoryId(3)));
+        assert_eq!((decoded[0].get_amount(), decoded[0].get_summa()), (Some(40500), 210050));
+        assert!(decoded[0].get_parameters() == op.get_parameters());
+        assert_eq!(decoded[0].get_payee(), Some(PayeeId(7)));
+        assert_eq!(decoded[0].get_attachments(), op.get_attachments());
+        // a damaged file is rejected instead of being read partially
+        let e = decode_records::<FinanceOperation>(&bytes[..bytes.len() - 1]).err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        // fields appended by newer versions are skipped
+        let mut writer = BinaryWriter::new();
+        Category{id: CategoryId(1), name: "Food".to_string(), tax_relevant: true}.write(&mut writer);
+        writer.write_u64(42);
+        let record = writer.into_bytes();
+        let mut file = BinaryWriter::new();
+        file.write_u32(1);
+        file.write_u32(record.len() as u32);
+        let mut bytes = file.into_bytes();
+        bytes.extend_from_slice(&record);
+        assert!(decode_records::<Category>(&bytes)?[0].tax_relevant);
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+use crate::db::HomeAccountingDB;
+
+/// Several independent databases opened by one process, each book has its own dictionaries and cache.
+/// Private books of encryption domains stay locked until their owner unlocks them.
+pub struct Books {
+    map: BTreeMap<String, HomeAccountingDB>,
+    locked: BTreeMap<String, LockedBook>
+}
+
+/// Private book that is not opened yet.
+pub struct LockedBook {
+    pub path: String,
+    pub max_active_items: usize,
+    /// owner of the encryption domain
+    pub user: String
+}
+
+impl Default for Books {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Books {
+    pub fn new() -> Books {
+        Books{map: BTreeMap::new(), locked: BTreeMap::new()}
+    }
+
+    pub fn add(&mut self, name: String, db: HomeAccountingDB) -> Result<(), Error> {
+        self.check_new(&name)?;
+        self.map.insert(name, db);
+        Ok(())
+    }
+
+    pub fn add_locked(&mut self, name: String, book: LockedBook) -> Result<(), Error> {
+        self.check_new(&name)?;
+        self.locked.insert(name, book);
+        Ok(())
+    }
+
+    fn check_new(&self, name: &str) -> Result<(), Error> {
+        if self.map.contains_key(name) || self.locked.contains_key(name) {
+            return Err(Error::new(ErrorKind::AlreadyExists, format!("book {} already exists", name)));
+        }
+        Ok(())
+    }
+
+    fn not_found(&self, name: &str) -> Error {
+        if self.locked.contains_key(name) {
+            Error::new(ErrorKind::PermissionDenied, format!("book {} is locked", name))
+        } else {
+            Error::new(ErrorKind::NotFound, format!("unknown book {}", name))
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<&HomeAccountingDB, Error> {
+        self.map.get(name).ok_or_else(||self.not_found(name))
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Result<&mut HomeAccountingDB, Error> {
+        if !self.map.contains_key(name) {
+            return Err(self.not_found(name));
+        }
+        Ok(self.map.get_mut(name).unwrap())
+    }
+
+    pub fn is_locked(&self, name: &str) -> bool {
+        self.locked.contains_key(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.map.keys()
+    }
+}
+
+#[cfg(feature = "json")]
+mod json {
+    use std::fs::File;
+    use std::io::{BufReader, Error, ErrorKind};
+    use serde::Deserialize;
+    use crate::books::{Books, LockedBook};
+    #[cfg(feature = "encryption")]
+    use crate::core::encryption_domain::open_domain;
+    use crate::db::HomeAccountingDB;
+    use crate::json_db_config::JsonDBConfiguration;
+
+    #[derive(Deserialize)]
+    struct BookConfiguration {
+        name: String,
+        path: String,
+        #[serde(rename = "maxActiveItems")]
+        max_active_items: usize,
+        /// owner of the private book
+        user: Option<String>
+    }
+
+    impl Books {
+        /// Opens JSON books listed in configuration file: [{"name": "...", "path": "...", "maxActiveItems": N}],
+        /// books with "user" are private and stay locked.
+        pub fn load_json(configuration_file: &str) -> Result<Books, Error> {
+            let reader = BufReader::new(File::open(configuration_file)?);
+            let configuration: Vec<BookConfiguration> = serde_json::from_reader(reader)
+                .map_err(|e|Error::new(ErrorKind::InvalidData, e.to_string()))?;
+            let mut books = Books::new();
+            for c in configuration {
+                if let Some(user) = c.user {
+                    books.add_locked(c.name, LockedBook{path: c.path, max_active_items: c.max_active_items, user})?;
+                    continue;
+                }
+                let db = HomeAccountingDB::load(c.path, Box::new(JsonDBConfiguration::new()), c.max_active_items)?;
+                books.add(c.name, db)?;
+            }
+            Ok(books)
+        }
+
+        /// Opens the private book with its owner's passphrase, the book stays locked on failure.
+        #[cfg(feature = "encryption")]
+        pub fn unlock(&mut self, name: &str, passphrase: &str) -> Result<(), Error> {
+            let book = self.locked.get(name).ok_or_else(||self.not_found(name))?;
+            let crypto = open_domain(&book.path, &book.user, passphrase)?;
+            let db = HomeAccountingDB::load(book.path.clone(), Box::new(JsonDBConfiguration::new_with_crypto(crypto)),
+                                            book.max_active_items)?;
+            self.locked.remove(name);
+            self.map.insert(name.to_string(), db);
+            Ok(())
+        }
+    }
+}
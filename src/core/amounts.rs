@@ -0,0 +1,157 @@
+use std::io::{Error, ErrorKind};
+use serde::{Deserialize, Serialize};
+
+/// Sums are stored in minor units of the account currency, this is the number of minor units
+/// used when the database has no currencies dictionary.
+pub const SUMMA_DECIMALS: u32 = 2;
+/// Amounts (quantities of instruments, sums of the second currency) are stored in thousandths.
+pub const AMOUNT_DECIMALS: u32 = 3;
+
+/// How values are rounded to minor units of a currency.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Rounding {
+    /// halves away from zero
+    #[default]
+    HalfUp,
+    /// halves to the even neighbour, also known as bankers rounding
+    HalfEven,
+    /// towards zero
+    Down
+}
+
+impl Rounding {
+    pub fn is_default(&self) -> bool {
+        *self == Rounding::default()
+    }
+}
+
+/// Rounds value to a multiple of divider.
+pub fn round_to(value: i64, divider: i64, rounding: Rounding) -> i64 {
+    if divider <= 1 {
+        return value;
+    }
+    let quotient = value.unsigned_abs() / divider as u64;
+    let remainder = value.unsigned_abs() % divider as u64;
+    let half = divider as u64 / 2;
+    let up = match rounding {
+        Rounding::HalfUp => remainder >= half && remainder > 0,
+        Rounding::HalfEven => remainder > half || (remainder == half && remainder > 0 && quotient % 2 == 1),
+        Rounding::Down => false
+    };
+    let result = (quotient + up as u64) as i64 * divider;
+    if value < 0 {-result} else {result}
+}
+
+/// Formats value in hundredths as 1234.56
+pub fn format_summa(value: i64) -> String {
+    format_decimal(value, SUMMA_DECIMALS)
+}
+
+/// Formats value given in minor units, 12345 with 3 minor units is 12.345
+pub fn format_decimal(value: i64, minor_units: u32) -> String {
+    let sign = if value < 0 {"-"} else {""};
+    if minor_units == 0 {
+        return format!("{}{}", sign, value.unsigned_abs());
+    }
+    let divider = 10u64.pow(minor_units);
+    format!("{}{}.{:0width$}", sign, value.unsigned_abs() / divider, value.unsigned_abs() % divider,
+            width = minor_units as usize)
+}
+
+/// Parses decimal amount like -1234.5 or 3,50 into hundredths.
+pub fn parse_summa(text: &str) -> Result<i64, Error> {
+    parse_decimal(text, SUMMA_DECIMALS, None)
+}
+
+/// Parses decimal amount into minor units, extra fraction digits are rounded when rounding is given
+/// and rejected otherwise.
+pub fn parse_decimal(text: &str, minor_units: u32, rounding: Option<Rounding>) -> Result<i64, Error> {
+    let invalid = || Error::new(ErrorKind::InvalidInput, format!("invalid amount {}", text));
+    let text = text.trim().replace(',', ".");
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(&text))
+    };
+    let (units, fraction) = match digits.split_once('.') {
+        Some((_, "")) => return Err(invalid()),
+        Some(parts) => parts,
+        None => (digits, "")
+    };
+    if units.is_empty() || fraction.len() > 9 ||
+        !(units.to_string() + fraction).bytes().all(|b|b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let decimals = fraction.len().max(minor_units as usize);
+    let extra = 10i64.pow(decimals as u32 - minor_units);
+    if extra > 1 && rounding.is_none() {
+        return Err(invalid());
+    }
+    let units: i64 = units.parse().map_err(|_|invalid())?;
+    let fraction: i64 = format!("0{:0<width$}", fraction, width = decimals).parse().map_err(|_|invalid())?;
+    let value = units.checked_mul(10i64.pow(decimals as u32)).and_then(|u|u.checked_add(fraction)).ok_or_else(invalid)?;
+    let value = round_to(value, extra, rounding.unwrap_or_default()) / extra;
+    Ok(if negative {-value} else {value})
+}
+
+/// Parses decimal rate like 1.0823 into millionths.
+pub fn parse_rate(text: &str) -> Result<i64, Error> {
+    let invalid = || Error::new(ErrorKind::InvalidData, format!("invalid rate {}", text));
+    let text = text.trim();
+    if text.starts_with('-') {
+        return Err(invalid());
+    }
+    let (int_part, fraction) = text.split_once('.').unwrap_or((text, ""));
+    if fraction.len() > 6 || !fraction.chars().all(|c|c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let int_part: i64 = int_part.parse().map_err(|_|invalid())?;
+    let fraction: i64 = format!("{:0<6}", fraction).parse().map_err(|_|invalid())?;
+    let rate = int_part.checked_mul(1000000).and_then(|r|r.checked_add(fraction)).ok_or_else(invalid)?;
+    if rate <= 0 {
+        return Err(invalid());
+    }
+    Ok(rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::amounts::{format_decimal, format_summa, parse_decimal, parse_summa, round_to, Rounding};
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(parse_summa("3.5").unwrap(), 350);
+        assert_eq!(parse_summa("-1234,56").unwrap(), -123456);
+        assert_eq!(parse_summa("+12").unwrap(), 1200);
+        assert!(parse_summa("1.234").is_err());
+        assert!(parse_summa("1e5").is_err());
+        assert!(parse_summa("-").is_err());
+        assert_eq!(format_summa(-5), "-0.05");
+        assert_eq!(format_summa(123456), "1234.56");
+    }
+
+    #[test]
+    fn test_minor_units() {
+        assert_eq!(parse_decimal("1.234", 3, None).unwrap(), 1234);
+        assert_eq!(parse_decimal("-1.5", 3, None).unwrap(), -1500);
+        assert_eq!(parse_decimal("12", 0, None).unwrap(), 12);
+        assert!(parse_decimal("12.5", 0, None).is_err());
+        assert_eq!(parse_decimal("12.5", 0, Some(Rounding::HalfEven)).unwrap(), 12);
+        assert_eq!(parse_decimal("-12.5", 0, Some(Rounding::HalfUp)).unwrap(), -13);
+        assert_eq!(parse_decimal("1.2345", 3, Some(Rounding::Down)).unwrap(), 1234);
+        assert_eq!(format_decimal(-1234, 3), "-1.234");
+        assert_eq!(format_decimal(1234, 0), "1234");
+        assert_eq!(format_decimal(5, 1), "0.5");
+    }
+
+    #[test]
+    fn test_round_to() {
+        assert_eq!(round_to(12350, 100, Rounding::HalfUp), 12400);
+        assert_eq!(round_to(-12350, 100, Rounding::HalfUp), -12400);
+        assert_eq!(round_to(12350, 100, Rounding::HalfEven), 12400);
+        assert_eq!(round_to(12250, 100, Rounding::HalfEven), 12200);
+        assert_eq!(round_to(12251, 100, Rounding::HalfEven), 12300);
+        assert_eq!(round_to(-12399, 100, Rounding::Down), -12300);
+        assert_eq!(round_to(12345, 1, Rounding::Down), 12345);
+    }
+}
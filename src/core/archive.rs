@@ -0,0 +1,208 @@
+//! Cold archive of old years. Every year is packed into one compressed and optionally encrypted bundle,
+//! archived folders are removed from the dates folder so they are not scanned on startup.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::path::Path;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use crate::core::crypto::CryptoProcessor;
+use crate::entities::accounts::AccountId;
+
+pub const ARCHIVE_FOLDER: &str = "archive";
+const BUNDLE_EXTENSION: &str = "bundle";
+const BALANCES_FILE_NAME: &str = "balances.txt";
+
+/// Packs files (relative name and contents) into a bundle.
+pub fn pack(files: &[(String, Vec<u8>)], crypto: Option<&dyn CryptoProcessor>) -> Result<Vec<u8>, Error> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    for (name, data) in files {
+        encoder.write_all(&(name.len() as u32).to_le_bytes())?;
+        encoder.write_all(name.as_bytes())?;
+        encoder.write_all(&(data.len() as u64).to_le_bytes())?;
+        encoder.write_all(data)?;
+    }
+    let compressed = encoder.finish()?;
+    match crypto {
+        Some(c) => c.encode(&compressed),
+        None => Ok(compressed)
+    }
+}
+
+pub fn unpack(bundle: &[u8], crypto: Option<&dyn CryptoProcessor>) -> Result<Vec<(String, Vec<u8>)>, Error> {
+    let decrypted;
+    let compressed = match crypto {
+        Some(c) => {
+            decrypted = c.decode(bundle)?;
+            decrypted.as_slice()
+        }
+        None => bundle
+    };
+    let mut data = Vec::new();
+    DeflateDecoder::new(compressed).read_to_end(&mut data)?;
+    let mut result = Vec::new();
+    let mut rest = data.as_slice();
+    while !rest.is_empty() {
+        let name_length = u32::from_le_bytes(take(&mut rest, 4)?.try_into().unwrap()) as usize;
+        let name = String::from_utf8(take(&mut rest, name_length)?.to_vec())
+            .map_err(|_|Error::new(ErrorKind::InvalidData, "invalid file name in archive bundle"))?;
+        let data_length = u64::from_le_bytes(take(&mut rest, 8)?.try_into().unwrap()) as usize;
+        result.push((name, take(&mut rest, data_length)?.to_vec()));
+    }
+    Ok(result)
+}
+
+fn take<'a>(data: &mut &'a [u8], length: usize) -> Result<&'a [u8], Error> {
+    if data.len() < length {
+        return Err(Error::new(ErrorKind::InvalidData, "truncated archive bundle"));
+    }
+    let (head, tail) = data.split_at(length);
+    *data = tail;
+    Ok(head)
+}
+
+pub struct ColdArchive {
+    folder: String,
+    crypto: Option<Box<dyn CryptoProcessor>>,
+    years: Vec<u64>
+}
+
+impl ColdArchive {
+    pub fn open(data_folder_path: &str, crypto: Option<Box<dyn CryptoProcessor>>) -> Result<ColdArchive, Error> {
+        let folder = data_folder_path.to_string() + "/" + ARCHIVE_FOLDER;
+        let mut years: Vec<u64> = match fs::read_dir(&folder) {
+            Ok(entries) => entries
+                .filter_map(|e|e.ok())
+                .map(|e|e.path())
+                .filter(|p|p.extension().is_some_and(|e|e == BUNDLE_EXTENSION))
+                .filter_map(|p|p.file_stem().and_then(|s|s.to_str()).and_then(|s|s.parse().ok()))
+                .collect(),
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e)
+        };
+        years.sort();
+        Ok(ColdArchive{folder, crypto, years})
+    }
+
+    pub fn get_years(&self) -> &[u64] {
+        &self.years
+    }
+
+    /// Last archived year, months of this and earlier years are not in the dates folder.
+    pub fn last_year(&self) -> Option<u64> {
+        self.years.last().copied()
+    }
+
+    fn bundle_name(&self, year: u64) -> String {
+        format!("{}/{}.{}", self.folder, year, BUNDLE_EXTENSION)
+    }
+
+    /// Packs all date folders of the year into a bundle, returns paths of packed folders.
+    /// Folders are left in place, the caller removes them once the archive is consistent.
+    pub fn archive_year(&mut self, dates_folder_path: &str, year: u64) -> Result<Vec<String>, Error> {
+        let name = self.bundle_name(year);
+        if Path::new(&name).exists() {
+            return Err(Error::new(ErrorKind::AlreadyExists, format!("year {} is already archived", year)));
+        }
+        let mut folders = Vec::new();
+        for entry in fs::read_dir(dates_folder_path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.file_type()?.is_dir() && name.parse::<u64>().is_ok_and(|d|d / 10000 == year) {
+                folders.push(name);
+            }
+        }
+        if folders.is_empty() {
+            return Ok(folders);
+        }
+        folders.sort();
+        let mut files = Vec::new();
+        for folder in &folders {
+            for entry in fs::read_dir(Path::new(dates_folder_path).join(folder))? {
+                let entry = entry?;
+                files.push((folder.clone() + "/" + &entry.file_name().to_string_lossy(), fs::read(entry.path())?));
+            }
+        }
+        let bundle = pack(&files, self.crypto.as_deref())?;
+        fs::create_dir_all(&self.folder)?;
+        let temp_name = name.clone() + ".tmp";
+        fs::write(&temp_name, bundle)?;
+        fs::rename(temp_name, name)?;
+        self.years.push(year);
+        self.years.sort();
+        Ok(folders.into_iter().map(|f|dates_folder_path.to_string() + "/" + &f).collect())
+    }
+
+    /// Unpacks the year into target dates folder.
+    pub fn extract_year(&self, year: u64, target_dates_folder_path: &str) -> Result<(), Error> {
+        let files = unpack(&fs::read(self.bundle_name(year))?, self.crypto.as_deref())?;
+        for (name, data) in files {
+            if name.split('/').any(|p|p.is_empty() || p == "..") {
+                return Err(Error::new(ErrorKind::InvalidData, format!("invalid file name {} in archive", name)));
+            }
+            let file = Path::new(target_dates_folder_path).join(name);
+            if let Some(parent) = file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(file, data)?;
+        }
+        Ok(())
+    }
+
+    /// Balances at the end of the last archived year.
+    pub fn load_balances(&self) -> Result<HashMap<AccountId, i64>, Error> {
+        let text = match fs::read_to_string(self.folder.clone() + "/" + BALANCES_FILE_NAME) {
+            Ok(t) => t,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e)
+        };
+        let mut result = HashMap::new();
+        for line in text.lines().filter(|l|!l.is_empty()) {
+            let (account, balance) = line.split_once(' ')
+                .and_then(|(a, b)|Some((a.parse().ok()?, b.parse().ok()?)))
+                .ok_or(Error::new(ErrorKind::InvalidData, format!("invalid archive balance line {}", line)))?;
+            result.insert(AccountId(account), balance);
+        }
+        Ok(result)
+    }
+
+    pub fn save_balances(&self, balances: &HashMap<AccountId, i64>) -> Result<(), Error> {
+        let mut accounts: Vec<_> = balances.iter().collect();
+        accounts.sort();
+        let text: String = accounts.iter().map(|(a, b)|format!("{} {}\n", a.0, b)).collect();
+        fs::create_dir_all(&self.folder)?;
+        fs::write(self.folder.clone() + "/" + BALANCES_FILE_NAME, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::core::archive::{pack, unpack};
+    use crate::core::crypto::CryptoProcessor;
+
+    struct XorProcessor{}
+
+    impl CryptoProcessor for XorProcessor {
+        fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+            Ok(data.iter().map(|b|b ^ 0x55).collect())
+        }
+
+        fn decode(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+            self.encode(data)
+        }
+    }
+
+    #[test]
+    fn test_pack_unpack() -> Result<(), Error> {
+        let files = vec![("20200101/operations.json".to_string(), b"[]".repeat(100)),
+                         ("20200102/operations.json".to_string(), Vec::new())];
+        let bundle = pack(&files, Some(&XorProcessor{}))?;
+        assert!(bundle.len() < 100);
+        assert_eq!(unpack(&bundle, Some(&XorProcessor{}))?, files);
+        assert!(unpack(&bundle[..bundle.len() - 2], Some(&XorProcessor{})).is_err());
+        Ok(())
+    }
+}
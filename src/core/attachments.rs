@@ -0,0 +1,118 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use crate::core::crypto::CryptoProcessor;
+
+/// Stores receipt files referenced from operations, one file per attachment.
+/// Attachment id is the file name: date_number.extension
+pub struct AttachmentStorage {
+    folder: String,
+    crypto: Option<Box<dyn CryptoProcessor>>
+}
+
+impl AttachmentStorage {
+    pub fn new(folder: String, crypto: Option<Box<dyn CryptoProcessor>>) -> AttachmentStorage {
+        AttachmentStorage{folder, crypto}
+    }
+
+    fn build_path(&self, id: &str) -> Result<String, Error> {
+        if id.is_empty() || id.contains('/') || id.contains('\\') || id.starts_with('.') {
+            return Err(Error::new(ErrorKind::InvalidInput, "invalid attachment id"));
+        }
+        Ok(format!("{}/{}", self.folder, id))
+    }
+
+    pub fn attach(&self, date: u64, extension: &str, data: &[u8]) -> Result<String, Error> {
+        if extension.is_empty() || !extension.chars().all(|c|c.is_ascii_alphanumeric()) {
+            return Err(Error::new(ErrorKind::InvalidInput, "invalid attachment extension"));
+        }
+        fs::create_dir_all(&self.folder)?;
+        let mut n = 1;
+        let id = loop {
+            let id = format!("{}_{}.{}", date, n, extension);
+            if !Path::new(&self.build_path(&id)?).exists() {
+                break id;
+            }
+            n += 1;
+        };
+        let contents = match &self.crypto {
+            Some(c) => c.encode(data)?,
+            None => data.to_vec()
+        };
+        fs::write(self.build_path(&id)?, contents)?;
+        Ok(id)
+    }
+
+    pub fn fetch(&self, id: &str) -> Result<Vec<u8>, Error> {
+        let contents = fs::read(self.build_path(id)?)?;
+        match &self.crypto {
+            Some(c) => c.decode(&contents),
+            None => Ok(contents)
+        }
+    }
+
+    /// Removes files that are not referenced, returns ids of removed files.
+    pub fn collect_garbage(&self, referenced: &HashSet<String>) -> Result<Vec<String>, Error> {
+        let mut removed = Vec::new();
+        let entries = match fs::read_dir(&self.folder) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(removed),
+            Err(e) => return Err(e)
+        };
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let id = entry.file_name().into_string()
+                .map_err(|_|Error::new(ErrorKind::InvalidData, "invalid file name"))?;
+            if !referenced.contains(&id) {
+                fs::remove_file(entry.path())?;
+                removed.push(id);
+            }
+        }
+        removed.sort();
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::Error;
+    use crate::core::attachments::AttachmentStorage;
+    use crate::core::crypto::CryptoProcessor;
+
+    struct XorProcessor{}
+
+    impl CryptoProcessor for XorProcessor {
+        fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+            Ok(data.iter().map(|b|b ^ 0x55).collect())
+        }
+
+        fn decode(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+            self.encode(data)
+        }
+    }
+
+    #[test]
+    fn test_attach_fetch_gc() -> Result<(), Error> {
+        let folder = temp_dir().join("had_test_attachments").to_str().unwrap().to_string();
+        let _ = fs::remove_dir_all(&folder);
+        let storage = AttachmentStorage::new(folder.clone(), Some(Box::new(XorProcessor{})));
+        let id1 = storage.attach(20240105, "pdf", b"receipt1")?;
+        let id2 = storage.attach(20240105, "pdf", b"receipt2")?;
+        assert_eq!(id1, "20240105_1.pdf");
+        assert_eq!(id2, "20240105_2.pdf");
+        assert_ne!(fs::read(format!("{}/{}", folder, id1))?, b"receipt1");
+        assert_eq!(storage.fetch(&id1)?, b"receipt1");
+        assert!(storage.fetch("../x").is_err());
+        let removed = storage.collect_garbage(&HashSet::from([id2.clone()]))?;
+        assert_eq!(removed, vec![id1]);
+        assert_eq!(storage.fetch(&id2)?, b"receipt2");
+        fs::remove_dir_all(&folder)
+    }
+}
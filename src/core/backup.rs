@@ -0,0 +1,115 @@
+//! Backups of the whole data folder, packed like cold archive bundles with a manifest of file checksums.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use crate::core::archive::{pack, unpack};
+use crate::core::crypto::CryptoProcessor;
+use crate::verify::checksums::checksum;
+
+pub const BACKUP_MANIFEST_FILE_NAME: &str = "backup_manifest.txt";
+
+/// Packs all files of the data folder into a bundle, top level entries listed in skip are not packed.
+pub fn create_backup(data_folder_path: &str, skip: &[&str], crypto: Option<&dyn CryptoProcessor>)
+    -> Result<Vec<u8>, Error> {
+    let mut files = Vec::new();
+    collect_files(Path::new(data_folder_path), "", skip, &mut files)?;
+    files.sort();
+    let manifest: String = files.iter().map(|(name, data)|format!("{:016x} {}\n", checksum(data), name)).collect();
+    files.push((BACKUP_MANIFEST_FILE_NAME.to_string(), manifest.into_bytes()));
+    pack(&files, crypto)
+}
+
+fn collect_files(folder: &Path, prefix: &str, skip: &[&str], result: &mut Vec<(String, Vec<u8>)>) -> Result<(), Error> {
+    for entry in fs::read_dir(folder)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if skip.contains(&name.as_str()) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &(prefix.to_string() + &name + "/"), &[], result)?;
+        } else {
+            result.push((prefix.to_string() + &name, fs::read(entry.path())?));
+        }
+    }
+    Ok(())
+}
+
+/// Decrypts and unpacks the bundle and checks every file against the manifest. Returns backed up files.
+pub fn verify_backup(bundle: &[u8], crypto: Option<&dyn CryptoProcessor>) -> Result<Vec<(String, Vec<u8>)>, Error> {
+    let mut files = unpack(bundle, crypto)
+        .map_err(|e|Error::new(ErrorKind::InvalidData, format!("backup cannot be decrypted or unpacked: {}", e)))?;
+    let idx = files.iter().position(|(name, _)|name == BACKUP_MANIFEST_FILE_NAME)
+        .ok_or(Error::new(ErrorKind::InvalidData, "backup manifest is missing"))?;
+    let (_, manifest) = files.remove(idx);
+    let manifest = String::from_utf8(manifest)
+        .map_err(|_|Error::new(ErrorKind::InvalidData, "invalid backup manifest"))?;
+    let mut expected = HashMap::new();
+    for line in manifest.lines().filter(|l|!l.is_empty()) {
+        let (sum, name) = line.split_once(' ')
+            .and_then(|(c, n)|Some((u64::from_str_radix(c, 16).ok()?, n)))
+            .ok_or(Error::new(ErrorKind::InvalidData, format!("invalid backup manifest line {}", line)))?;
+        expected.insert(name, sum);
+    }
+    for (name, data) in &files {
+        match expected.remove(name.as_str()) {
+            Some(sum) if sum == checksum(data) => {}
+            Some(_) => return Err(Error::new(ErrorKind::InvalidData, format!("checksum mismatch for {}", name))),
+            None => return Err(Error::new(ErrorKind::InvalidData, format!("{} is not in backup manifest", name)))
+        }
+    }
+    if let Some(name) = expected.keys().next() {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{} is missing in backup", name)));
+    }
+    Ok(files)
+}
+
+/// Writes backed up files into target folder.
+pub fn extract_files(files: &[(String, Vec<u8>)], target_folder_path: &str) -> Result<(), Error> {
+    for (name, data) in files {
+        if name.split('/').any(|p|p.is_empty() || p == "..") {
+            return Err(Error::new(ErrorKind::InvalidData, format!("invalid file name {} in backup", name)));
+        }
+        let file = Path::new(target_folder_path).join(name);
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(file, data)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::Error;
+    use crate::core::archive::{pack, unpack};
+    use crate::core::backup::{create_backup, verify_backup};
+
+    #[test]
+    fn test_verify_backup() -> Result<(), Error> {
+        let folder = temp_dir().join("had_test_backup").to_str().unwrap().to_string();
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.clone() + "/dates/20240105")?;
+        fs::create_dir_all(folder.clone() + "/checkpoints/1")?;
+        fs::write(folder.clone() + "/accounts.json", "[]")?;
+        fs::write(folder.clone() + "/dates/20240105/operations.json", "[]")?;
+        fs::write(folder.clone() + "/checkpoints/1/accounts.json", "[]")?;
+        let bundle = create_backup(&folder, &["checkpoints"], None)?;
+        let files = verify_backup(&bundle, None)?;
+        assert_eq!(files.iter().map(|(n, _)|n.as_str()).collect::<Vec<_>>(),
+                   vec!["accounts.json", "dates/20240105/operations.json"]);
+
+        let mut files = unpack(&bundle, None)?;
+        files[1].1 = b"[{}]".to_vec();
+        let e = verify_backup(&pack(&files, None)?, None).err().unwrap();
+        assert_eq!(e.to_string(), "checksum mismatch for dates/20240105/operations.json");
+        files.remove(0);
+        assert!(verify_backup(&pack(&files, None)?, None).is_err());
+        assert!(verify_backup(&bundle[1..], None).is_err());
+        fs::remove_dir_all(&folder)
+    }
+}
@@ -0,0 +1,32 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Hit and miss counters of a cache.
+#[derive(Default)]
+pub struct CacheStats {
+    hits: AtomicUsize,
+    misses: AtomicUsize
+}
+
+impl CacheStats {
+    pub fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Adds counters of another cache, used to sum up caches of shards.
+    pub fn add(&self, other: &CacheStats) {
+        self.hits.fetch_add(other.get_hits(), Ordering::Relaxed);
+        self.misses.fetch_add(other.get_misses(), Ordering::Relaxed);
+    }
+
+    pub fn get_hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn get_misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,46 @@
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::core::dates::unix_days_to_date;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+
+    /// Current UTC date as yyyymmdd.
+    fn today(&self) -> u64 {
+        let seconds = self.now().duration_since(UNIX_EPOCH).map(|d|d.as_secs()).unwrap_or(0);
+        unix_days_to_date((seconds / 86400) as i64)
+    }
+}
+
+pub struct SystemClock {}
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Clock for tests and replay tools, changes only when set or advanced.
+pub struct FixedClock {
+    time: Mutex<SystemTime>
+}
+
+impl FixedClock {
+    pub fn new(time: SystemTime) -> FixedClock {
+        FixedClock{time: Mutex::new(time)}
+    }
+
+    pub fn set(&self, time: SystemTime) {
+        *self.time.lock().unwrap() = time;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.time.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        *self.time.lock().unwrap()
+    }
+}
@@ -1,6 +1,81 @@
 use std::io::Error;
+#[cfg(any(feature = "binary", feature = "server", feature = "encryption"))]
+use std::io::ErrorKind;
+use std::sync::Arc;
+#[cfg(any(feature = "binary", feature = "server", feature = "encryption"))]
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+#[cfg(any(feature = "binary", feature = "server", feature = "encryption"))]
+use aes_gcm::{Aes256Gcm, Key, Nonce};
 
-pub trait CryptoProcessor {
-    fn encode(data: &Vec<u8>) -> Result<Vec<u8>, Error>;
-    fn decode(data: &Vec<u8>) -> Result<Vec<u8>, Error>;
-}
\ No newline at end of file
+#[cfg(any(feature = "binary", feature = "server", feature = "encryption"))]
+const NONCE_SIZE: usize = 12;
+
+pub trait CryptoProcessor: Send + Sync {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Error>;
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+/// Lets one processor be shared by data sources and attachment storage.
+impl<T: CryptoProcessor + ?Sized> CryptoProcessor for Arc<T> {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        (**self).encode(data)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        (**self).decode(data)
+    }
+}
+
+/// AES-256-GCM, every encoded value starts with its random nonce, decoding fails when the
+/// authentication tag doesn't match.
+#[cfg(any(feature = "binary", feature = "server", feature = "encryption"))]
+pub struct AesGcmProcessor {
+    cipher: Aes256Gcm
+}
+
+#[cfg(any(feature = "binary", feature = "server", feature = "encryption"))]
+impl AesGcmProcessor {
+    pub fn new(key: &[u8; 32]) -> AesGcmProcessor {
+        AesGcmProcessor{cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))}
+    }
+}
+
+#[cfg(any(feature = "binary", feature = "server", feature = "encryption"))]
+impl CryptoProcessor for AesGcmProcessor {
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let encrypted = self.cipher.encrypt(&nonce, data)
+            .map_err(|_|Error::new(ErrorKind::InvalidInput, "encryption failed"))?;
+        Ok([nonce.as_slice(), &encrypted].concat())
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        if data.len() < NONCE_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, "encrypted data is too short"));
+        }
+        let (nonce, encrypted) = data.split_at(NONCE_SIZE);
+        self.cipher.decrypt(Nonce::from_slice(nonce), encrypted)
+            .map_err(|_|Error::new(ErrorKind::InvalidData, "decryption failed"))
+    }
+}
+
+#[cfg(all(test, any(feature = "binary", feature = "server", feature = "encryption")))]
+mod tests {
+    use std::io::Error;
+    use crate::core::crypto::{AesGcmProcessor, CryptoProcessor};
+
+    #[test]
+    fn test_aes_gcm() -> Result<(), Error> {
+        let processor = AesGcmProcessor::new(&[7u8; 32]);
+        let encoded = processor.encode(b"operations")?;
+        // random nonce makes every encoding different
+        assert_ne!(encoded, processor.encode(b"operations")?);
+        assert_eq!(processor.decode(&encoded)?, b"operations");
+        let mut tampered = encoded.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(processor.decode(&tampered).is_err());
+        assert!(AesGcmProcessor::new(&[8u8; 32]).decode(&encoded).is_err());
+        assert!(processor.decode(&encoded[..5]).is_err());
+        Ok(())
+    }
+}
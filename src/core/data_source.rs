@@ -1,23 +1,68 @@
+use std::io::Error;
+#[cfg(all(feature = "fs", feature = "json"))]
 use std::fs::File;
-use std::io::{BufReader, Error};
+#[cfg(all(feature = "fs", feature = "json"))]
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+#[cfg(all(feature = "fs", feature = "json"))]
 use std::ops::Add;
+#[cfg(all(feature = "fs", feature = "json"))]
 use serde::de::DeserializeOwned;
+#[cfg(all(feature = "fs", feature = "json"))]
+use serde::Serialize;
+#[cfg(all(feature = "fs", feature = "json"))]
+use std::sync::Arc;
+#[cfg(all(feature = "fs", feature = "json"))]
+use crate::core::crypto::CryptoProcessor;
 
-pub trait DataSource<T> {
+pub trait DataSource<T>: Send + Sync {
     fn load(&self, file_name: String, add_extension: bool) -> Result<T, Error>;
     fn save(&self, data: &T, file_name: String) -> Result<(), Error>;
 }
 
+/// Converts serde error to io error that names the file.
+#[cfg(all(feature = "fs", feature = "json"))]
+pub fn json_error(file_name: &str, e: serde_json::Error) -> Error {
+    if e.is_io() {
+        return e.into();
+    }
+    Error::new(ErrorKind::InvalidData, format!("{}: {}", file_name, e))
+}
+
+#[cfg(all(feature = "fs", feature = "json"))]
 pub struct JsonDataSource {}
-impl<'de, T: DeserializeOwned> DataSource<T> for JsonDataSource {
+#[cfg(all(feature = "fs", feature = "json"))]
+impl<T: DeserializeOwned + Serialize> DataSource<T> for JsonDataSource {
     fn load(&self, file_name: String, add_extension: bool) -> Result<T, Error> {
         let fname = if add_extension {file_name.add(".json")} else {file_name};
-        let file = File::open(fname)?;
+        let file = File::open(&fname)?;
         let reader = BufReader::new(file);
-        Ok(serde_json::from_reader(reader)?)
+        serde_json::from_reader(reader).map_err(|e|json_error(&fname, e))
+    }
+
+    fn save(&self, data: &T, file_name: String) -> Result<(), Error> {
+        let fname = file_name.add(".json");
+        let mut writer = BufWriter::new(File::create(&fname)?);
+        serde_json::to_writer(&mut writer, data).map_err(|e|json_error(&fname, e))?;
+        writer.flush()
+    }
+}
+
+/// JSON files encrypted as a whole.
+#[cfg(all(feature = "fs", feature = "json"))]
+pub struct EncryptedJsonDataSource {
+    pub crypto: Arc<dyn CryptoProcessor>
+}
+#[cfg(all(feature = "fs", feature = "json"))]
+impl<T: DeserializeOwned + Serialize> DataSource<T> for EncryptedJsonDataSource {
+    fn load(&self, file_name: String, add_extension: bool) -> Result<T, Error> {
+        let fname = if add_extension {file_name.add(".json")} else {file_name};
+        let data = self.crypto.decode(&std::fs::read(&fname)?)?;
+        serde_json::from_slice(&data).map_err(|e|json_error(&fname, e))
     }
 
     fn save(&self, data: &T, file_name: String) -> Result<(), Error> {
-        todo!()
+        let fname = file_name.add(".json");
+        let bytes = serde_json::to_vec(data).map_err(|e|json_error(&fname, e))?;
+        std::fs::write(&fname, self.crypto.encode(&bytes)?)
     }
 }
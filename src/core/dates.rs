@@ -0,0 +1,184 @@
+use std::cell::Cell;
+use std::io::{Error, ErrorKind};
+
+thread_local! {
+    static LENIENT_DATES: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Converts number of days since 1970-01-01 to yyyymmdd date.
+pub fn unix_days_to_date(days: i64) -> u64 {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year * 10000 + month * 100 + day) as u64
+}
+
+/// Converts yyyymmdd date to number of days since 1970-01-01.
+pub fn date_to_unix_days(date: u64) -> i64 {
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let month = (date / 100 % 100) as i64;
+    let year = (date / 10000) as i64 - if month <= 2 { 1 } else { 0 };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + (date % 100) as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Local time zone and hour the accounting day starts at, used to date imported bank transactions.
+/// Transactions made before day_start_hour belong to the previous day.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct DayBoundary {
+    pub utc_offset_minutes: i64,
+    pub day_start_hour: u64
+}
+
+impl DayBoundary {
+    pub fn new(utc_offset_minutes: i64, day_start_hour: u64) -> DayBoundary {
+        DayBoundary{utc_offset_minutes, day_start_hour}
+    }
+
+    /// yyyymmdd accounting date of the unix time.
+    pub fn date_of(&self, unix_seconds: i64) -> u64 {
+        let local = unix_seconds + self.utc_offset_minutes * 60 - self.day_start_hour as i64 * 3600;
+        unix_days_to_date(local.div_euclid(86400))
+    }
+
+    /// Accounting date of the timestamp given as yyyymmdd date and seconds since its midnight,
+    /// timestamps without utc offset are in the local time zone.
+    pub fn date_of_timestamp(&self, date: u64, seconds: i64, utc_offset_minutes: Option<i64>) -> u64 {
+        let offset = utc_offset_minutes.unwrap_or(self.utc_offset_minutes);
+        self.date_of(date_to_unix_days(date) * 86400 + seconds - offset * 60)
+    }
+}
+
+pub fn is_leap_year(year: u64) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+pub fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 => if is_leap_year(year) {29} else {28},
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+/// Checks that yyyymmdd date exists in the calendar.
+pub fn is_valid_date(date: u64) -> bool {
+    let year = date / 10000;
+    let month = date / 100 % 100;
+    let day = date % 100;
+    year > 0 && (1..=12).contains(&month) && day >= 1 && day <= days_in_month(year, month)
+}
+
+/// Validates date read from the storage, invalid dates are accepted inside with_lenient_dates(true, ...).
+pub fn check_date(date: u64) -> Result<(), Error> {
+    if is_valid_date(date) || LENIENT_DATES.with(|l|l.get()) {
+        Ok(())
+    } else {
+        Err(Error::new(ErrorKind::InvalidData, format!("invalid date {}", date)))
+    }
+}
+
+/// Runs f with lenient date validation turned on or off for the current thread,
+/// used to load legacy data that contains impossible dates.
+pub fn with_lenient_dates<T>(lenient: bool, f: impl FnOnce() -> T) -> T {
+    let prev = LENIENT_DATES.with(|l|l.replace(lenient));
+    let result = f();
+    LENIENT_DATES.with(|l|l.set(prev));
+    result
+}
+
+/// Adds months to yyyymmdd date, day is clamped to the length of resulting month.
+pub fn add_months(date: u64, months: u64) -> u64 {
+    let total = (date / 10000) * 12 + (date / 100 % 100 - 1) + months;
+    let year = total / 12;
+    let month = total % 12 + 1;
+    let day = (date % 100).min(days_in_month(year, month));
+    year * 10000 + month * 100 + day
+}
+
+/// Subtracts months from yyyymmdd date, day is clamped to the length of resulting month.
+pub fn sub_months(date: u64, months: u64) -> u64 {
+    let total = ((date / 10000) * 12 + (date / 100 % 100 - 1)).saturating_sub(months);
+    let year = total / 12;
+    let month = total % 12 + 1;
+    let day = (date % 100).min(days_in_month(year, month));
+    year * 10000 + month * 100 + day
+}
+
+/// Number of whole months from one yyyymmdd date to another, 0 when to is not after from.
+pub fn months_between(from: u64, to: u64) -> u64 {
+    if to <= from {
+        return 0;
+    }
+    let months = (to / 10000 * 12 + to / 100 % 100) - (from / 10000 * 12 + from / 100 % 100);
+    if to % 100 < from % 100 {months - 1} else {months}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::dates::{add_months, check_date, date_to_unix_days, is_valid_date, months_between, sub_months,
+                             unix_days_to_date, with_lenient_dates, DayBoundary};
+
+    #[test]
+    fn test_unix_days_to_date() {
+        assert_eq!(unix_days_to_date(0), 19700101);
+        assert_eq!(unix_days_to_date(11016), 20000229);
+        assert_eq!(unix_days_to_date(19777), 20240224);
+        assert_eq!(unix_days_to_date(-1), 19691231);
+        for days in [-1, 0, 11016, 19777, -719468] {
+            assert_eq!(date_to_unix_days(unix_days_to_date(days)), days);
+        }
+    }
+
+    #[test]
+    fn test_day_boundary() {
+        // 2024-01-05 22:30 UTC
+        let time = 19727 * 86400 + 22 * 3600 + 1800;
+        assert_eq!(DayBoundary::default().date_of(time), 20240105);
+        assert_eq!(DayBoundary::new(120, 0).date_of(time), 20240106);
+        assert_eq!(DayBoundary::new(120, 4).date_of(time), 20240105);
+        assert_eq!(DayBoundary::new(-300, 0).date_of_timestamp(20240106, 3600, None), 20240106);
+        assert_eq!(DayBoundary::new(-300, 0).date_of_timestamp(20240106, 3600, Some(0)), 20240105);
+    }
+
+    #[test]
+    fn test_add_months() {
+        assert_eq!(add_months(20240131, 1), 20240229);
+        assert_eq!(add_months(20231115, 2), 20240115);
+        assert_eq!(add_months(20230131, 13), 20240229);
+        assert_eq!(add_months(20240105, 0), 20240105);
+        assert_eq!(sub_months(20240331, 1), 20240229);
+        assert_eq!(sub_months(20240115, 13), 20221215);
+    }
+
+    #[test]
+    fn test_months_between() {
+        assert_eq!(months_between(20240115, 20240215), 1);
+        assert_eq!(months_between(20240115, 20240214), 0);
+        assert_eq!(months_between(20231231, 20241231), 12);
+        assert_eq!(months_between(20240115, 20240101), 0);
+    }
+
+    #[test]
+    fn test_is_valid_date() {
+        assert!(is_valid_date(20240229));
+        assert!(!is_valid_date(20230229));
+        assert!(!is_valid_date(20241301));
+        assert!(!is_valid_date(20240145));
+        assert!(!is_valid_date(20240100));
+        assert!(check_date(20240431).is_err());
+        assert!(with_lenient_dates(true, ||check_date(20240431)).is_ok());
+        assert!(check_date(20240431).is_err());
+    }
+}
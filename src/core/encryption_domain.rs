@@ -0,0 +1,187 @@
+//! Encryption domains of private books. Every book has its own random data key, stored only wrapped
+//! with a key derived from its owner's credentials, so nobody without the passphrase, the server
+//! operator included, can read the book. Changing the passphrase rewraps the data key only.
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use pbkdf2::pbkdf2_hmac;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use crate::core::archive::ARCHIVE_FOLDER;
+use crate::core::crypto::{AesGcmProcessor, CryptoProcessor};
+use crate::core::sharded::SHARD_INDEX_FILE_NAME;
+use crate::core::totals_cache::TOTALS_FILE_NAME;
+use crate::core::wal::{CHECKPOINTS_FOLDER, WAL_FILE_NAME};
+
+pub const DOMAIN_FILE_NAME: &str = "domain.json";
+#[cfg(not(test))]
+const ITERATIONS: u32 = 600_000;
+/// stored in domain files, so tests don't need slow key derivation
+#[cfg(test)]
+const ITERATIONS: u32 = 1000;
+const SALT_SIZE: usize = 16;
+/// folders with files written by data sources and attachment storage
+const ENCRYPTED_FOLDERS: [&str; 3] = ["dates", "attachments", "accounts"];
+
+#[derive(Serialize, Deserialize)]
+struct DomainFile {
+    user: String,
+    /// hex encoded
+    salt: String,
+    iterations: u32,
+    /// data key encrypted with the user key, hex encoded
+    #[serde(rename = "dataKey")]
+    data_key: String
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b|format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Result<Vec<u8>, Error> {
+    let invalid = || Error::new(ErrorKind::InvalidData, "invalid encryption domain file");
+    if !text.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    (0..text.len()).step_by(2)
+        .map(|i|text.get(i..i + 2).and_then(|b|u8::from_str_radix(b, 16).ok()).ok_or_else(invalid))
+        .collect()
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut result = [0u8; N];
+    OsRng.fill_bytes(&mut result);
+    result
+}
+
+/// Key derived from the passphrase, salted with the user name so equal passphrases of different users
+/// give different keys.
+fn user_key(user: &str, passphrase: &str, salt: &[u8], iterations: u32) -> AesGcmProcessor {
+    let mut key = [0u8; 32];
+    pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), &[salt, user.as_bytes()].concat(), iterations, &mut key);
+    AesGcmProcessor::new(&key)
+}
+
+fn write_domain(data_folder_path: &str, user: &str, passphrase: &str, data_key: &[u8]) -> Result<(), Error> {
+    let salt: [u8; SALT_SIZE] = random_bytes();
+    let wrapped = user_key(user, passphrase, &salt, ITERATIONS).encode(data_key)?;
+    let domain = DomainFile{user: user.to_string(), salt: to_hex(&salt), iterations: ITERATIONS, data_key: to_hex(&wrapped)};
+    let file_name = Path::new(data_folder_path).join(DOMAIN_FILE_NAME);
+    let temp_name = file_name.with_extension("tmp");
+    fs::write(&temp_name, serde_json::to_vec(&domain)?)?;
+    fs::rename(temp_name, file_name)
+}
+
+/// Data key of the domain, wrong user or passphrase gives PermissionDenied.
+fn read_data_key(data_folder_path: &str, user: &str, passphrase: &str) -> Result<[u8; 32], Error> {
+    let file_name = Path::new(data_folder_path).join(DOMAIN_FILE_NAME);
+    let domain: DomainFile = serde_json::from_slice(&fs::read(file_name)?)
+        .map_err(|e|Error::new(ErrorKind::InvalidData, e.to_string()))?;
+    let denied = || Error::new(ErrorKind::PermissionDenied, "invalid user or passphrase");
+    if domain.user != user {
+        return Err(denied());
+    }
+    user_key(user, passphrase, &from_hex(&domain.salt)?, domain.iterations).decode(&from_hex(&domain.data_key)?)
+        .map_err(|_|denied())?
+        .try_into()
+        .map_err(|_|Error::new(ErrorKind::InvalidData, "invalid data key length"))
+}
+
+/// Creates the domain of the book owned by user, returns the processor of its files.
+pub fn create_domain(data_folder_path: &str, user: &str, passphrase: &str) -> Result<Arc<dyn CryptoProcessor>, Error> {
+    if Path::new(data_folder_path).join(DOMAIN_FILE_NAME).exists() {
+        return Err(Error::new(ErrorKind::AlreadyExists, "book already has an encryption domain"));
+    }
+    let data_key: [u8; 32] = random_bytes();
+    write_domain(data_folder_path, user, passphrase, &data_key)?;
+    Ok(Arc::new(AesGcmProcessor::new(&data_key)))
+}
+
+/// Processor of files of the book, available only to its owner.
+pub fn open_domain(data_folder_path: &str, user: &str, passphrase: &str) -> Result<Arc<dyn CryptoProcessor>, Error> {
+    Ok(Arc::new(AesGcmProcessor::new(&read_data_key(data_folder_path, user, passphrase)?)))
+}
+
+pub fn change_passphrase(data_folder_path: &str, user: &str, passphrase: &str, new_passphrase: &str) -> Result<(), Error> {
+    let data_key = read_data_key(data_folder_path, user, passphrase)?;
+    write_domain(data_folder_path, user, new_passphrase, &data_key)
+}
+
+fn collect_files(path: &Path, files: &mut Vec<std::path::PathBuf>) -> Result<(), Error> {
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), files)?;
+        } else if entry.file_name() != SHARD_INDEX_FILE_NAME {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// Encrypts plain files of an existing book: dictionaries, archived accounts, operations and attachments.
+/// Saved totals are removed, archived years, checkpoints and write-ahead log are not supported.
+/// Returns number of encrypted files.
+pub fn encrypt_folder(data_folder_path: &str, crypto: &dyn CryptoProcessor) -> Result<usize, Error> {
+    let root = Path::new(data_folder_path);
+    for unsupported in [ARCHIVE_FOLDER, CHECKPOINTS_FOLDER, WAL_FILE_NAME] {
+        if root.join(unsupported).exists() {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{} can't be encrypted", unsupported)));
+        }
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e|e == "json") && !path.ends_with(DOMAIN_FILE_NAME) {
+            files.push(path);
+        }
+    }
+    for folder in ENCRYPTED_FOLDERS {
+        if root.join(folder).is_dir() {
+            collect_files(&root.join(folder), &mut files)?;
+        }
+    }
+    for file in &files {
+        let temp_name = file.with_extension("tmp");
+        fs::write(&temp_name, crypto.encode(&fs::read(file)?)?)?;
+        fs::rename(temp_name, file)?;
+    }
+    match fs::remove_file(root.join(TOTALS_FILE_NAME)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    Ok(files.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::{Error, ErrorKind};
+    use crate::core::encryption_domain::{change_passphrase, create_domain, encrypt_folder, open_domain};
+
+    #[test]
+    fn test_encryption_domain() -> Result<(), Error> {
+        let folder = temp_dir().join("hadb_encryption_domain_test");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("dates/20240105"))?;
+        fs::write(folder.join("accounts.json"), "[]")?;
+        fs::write(folder.join("dates/20240105/operations.json"), "[1]")?;
+        let path = folder.to_string_lossy().to_string();
+        let crypto = create_domain(&path, "alice", "secret")?;
+        assert_eq!(create_domain(&path, "alice", "other").err().map(|e|e.kind()), Some(ErrorKind::AlreadyExists));
+        assert_eq!(encrypt_folder(&path, &crypto)?, 2);
+        let encrypted = fs::read(folder.join("dates/20240105/operations.json"))?;
+        assert_ne!(encrypted, b"[1]");
+        assert_eq!(open_domain(&path, "bob", "secret").err().map(|e|e.kind()), Some(ErrorKind::PermissionDenied));
+        assert_eq!(open_domain(&path, "alice", "wrong").err().map(|e|e.kind()), Some(ErrorKind::PermissionDenied));
+        change_passphrase(&path, "alice", "secret", "new secret")?;
+        assert!(open_domain(&path, "alice", "secret").is_err());
+        assert_eq!(open_domain(&path, "alice", "new secret")?.decode(&encrypted)?, b"[1]");
+        fs::remove_dir_all(&folder)
+    }
+}
@@ -0,0 +1,196 @@
+//! Stable error codes for server responses. Library functions keep returning io::Error, field level
+//! details and crypto failures are carried as its inner error and turned into an ApiError on answer.
+
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use crate::core::trace::current_request_id;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorCode {
+    ValidationFailed,
+    NotFound,
+    Conflict,
+    /// months are locked by another owner
+    Locked,
+    CryptoError,
+    InternalError
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::ValidationFailed => "VALIDATION_FAILED",
+            ErrorCode::NotFound => "NOT_FOUND",
+            ErrorCode::Conflict => "CONFLICT",
+            ErrorCode::Locked => "LOCKED",
+            ErrorCode::CryptoError => "CRYPTO_ERROR",
+            ErrorCode::InternalError => "INTERNAL_ERROR"
+        }
+    }
+
+    pub fn parse(code: &str) -> Option<ErrorCode> {
+        [ErrorCode::ValidationFailed, ErrorCode::NotFound, ErrorCode::Conflict, ErrorCode::Locked,
+            ErrorCode::CryptoError, ErrorCode::InternalError].into_iter().find(|c|c.as_str() == code)
+    }
+
+    /// Error kind for the client side.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ErrorCode::ValidationFailed => ErrorKind::InvalidInput,
+            ErrorCode::NotFound => ErrorKind::NotFound,
+            ErrorCode::Conflict => ErrorKind::AlreadyExists,
+            ErrorCode::Locked => ErrorKind::WouldBlock,
+            ErrorCode::CryptoError => ErrorKind::InvalidData,
+            ErrorCode::InternalError => ErrorKind::Other
+        }
+    }
+}
+
+/// Invalid value of a single field of the request.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+/// Rejections of a mutation by validation rules, one per failed check.
+#[derive(Debug)]
+pub struct Rejections(pub Vec<FieldError>);
+
+impl fmt::Display for Rejections {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.iter().map(|r|r.message.as_str()).collect::<Vec<_>>().join("; "))
+    }
+}
+
+impl std::error::Error for Rejections {}
+
+#[derive(Debug)]
+struct CryptoFailure(String);
+
+impl fmt::Display for CryptoFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for CryptoFailure {}
+
+pub fn field_error(kind: ErrorKind, field: &str, message: String) -> Error {
+    Error::new(kind, FieldError{field: field.to_string(), message})
+}
+
+pub fn rejections_error(rejections: Vec<FieldError>) -> Error {
+    Error::new(ErrorKind::InvalidInput, Rejections(rejections))
+}
+
+/// Marks errors of encryption or decryption.
+pub fn crypto_error(e: Error) -> Error {
+    Error::new(e.kind(), CryptoFailure(e.to_string()))
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    /// id of the failed request, to find its storage activity in the log
+    pub request_id: Option<String>,
+    pub message: String,
+    pub details: Vec<FieldError>
+}
+
+impl ApiError {
+    /// Request id is taken from the current request span.
+    pub fn from_error(e: &Error) -> ApiError {
+        let inner = e.get_ref();
+        let code = if inner.is_some_and(|i|i.is::<CryptoFailure>()) {
+            ErrorCode::CryptoError
+        } else {
+            match e.kind() {
+                ErrorKind::InvalidInput | ErrorKind::InvalidData => ErrorCode::ValidationFailed,
+                ErrorKind::NotFound => ErrorCode::NotFound,
+                ErrorKind::AlreadyExists => ErrorCode::Conflict,
+                ErrorKind::WouldBlock => ErrorCode::Locked,
+                _ => ErrorCode::InternalError
+            }
+        };
+        let details = match inner.and_then(|i|i.downcast_ref::<Rejections>()) {
+            Some(rejections) => rejections.0.clone(),
+            None => inner.and_then(|i|i.downcast_ref::<FieldError>()).cloned().into_iter().collect()
+        };
+        ApiError{code, request_id: current_request_id(), message: e.to_string(), details}
+    }
+
+    pub fn to_error(&self) -> Error {
+        match &self.request_id {
+            Some(id) => Error::new(self.code.kind(), format!("{} [{}]: {}", self.code.as_str(), id, self.message)),
+            None => Error::new(self.code.kind(), format!("{}: {}", self.code.as_str(), self.message))
+        }
+    }
+
+    /// First line is the code, second is the request id (empty if unknown), third is the message,
+    /// then one "field: message" line per detail.
+    pub fn encode(&self) -> String {
+        let line = |s: &str|s.replace(['\r', '\n'], " ");
+        let mut result = format!("{}\n{}\n{}\n", self.code.as_str(), self.request_id.as_deref().unwrap_or(""),
+                                 line(&self.message));
+        for detail in &self.details {
+            result += &format!("{}: {}\n", line(&detail.field), line(&detail.message));
+        }
+        result
+    }
+
+    pub fn decode(text: &str) -> Result<ApiError, Error> {
+        let invalid = || Error::new(ErrorKind::InvalidData, format!("invalid error response {}", text));
+        let mut lines = text.lines();
+        let code = lines.next().and_then(ErrorCode::parse).ok_or_else(invalid)?;
+        let request_id = lines.next().ok_or_else(invalid)?;
+        let request_id = if request_id.is_empty() {None} else {Some(request_id.to_string())};
+        let message = lines.next().ok_or_else(invalid)?.to_string();
+        let details = lines
+            .map(|l|l.split_once(": ").map(|(f, m)|FieldError{field: f.to_string(), message: m.to_string()}))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+        Ok(ApiError{code, request_id, message, details})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Error, ErrorKind};
+    use crate::core::errors::{crypto_error, field_error, rejections_error, ApiError, ErrorCode, FieldError};
+    use crate::core::trace::{current_request_id, RequestSpan};
+
+    #[test]
+    fn test_error_codes() -> Result<(), Error> {
+        let e = ApiError::from_error(&field_error(ErrorKind::InvalidInput, "accountId", "account Card is closed".to_string()));
+        assert_eq!(e.code, ErrorCode::ValidationFailed);
+        assert_eq!(e.message, "account Card is closed");
+        assert_eq!(e.details, vec![FieldError{field: "accountId".to_string(), message: "account Card is closed".to_string()}]);
+        assert_eq!(ApiError::decode(&e.encode())?, e);
+        let e = ApiError::from_error(&crypto_error(Error::new(ErrorKind::InvalidData, "bad\nkey")));
+        assert_eq!((e.code, e.details.len()), (ErrorCode::CryptoError, 0));
+        assert_eq!(ApiError::decode(&e.encode())?.message, "bad key");
+        let rejections = vec![FieldError{field: "summa".to_string(), message: "cap: too large".to_string()},
+                              FieldError{field: "finOpProperies".to_string(), message: "cap: NETW is required".to_string()}];
+        let e = ApiError::from_error(&rejections_error(rejections.clone()));
+        assert_eq!((e.code, e.message.as_str()), (ErrorCode::ValidationFailed, "cap: too large; cap: NETW is required"));
+        assert_eq!(ApiError::decode(&e.encode())?.details, rejections);
+        assert_eq!(ApiError::from_error(&Error::new(ErrorKind::AlreadyExists, "exists")).code, ErrorCode::Conflict);
+        assert_eq!(ApiError::from_error(&Error::new(ErrorKind::WouldBlock, "locked")).to_error().kind(), ErrorKind::WouldBlock);
+        assert_eq!(ApiError::from_error(&Error::other("io")).code, ErrorCode::InternalError);
+        assert!(ApiError::decode("UNKNOWN\n\nmessage\n").is_err());
+        let _span = RequestSpan::start("test");
+        let e = ApiError::from_error(&Error::new(ErrorKind::NotFound, "missing"));
+        assert_eq!(e.request_id, current_request_id());
+        assert_eq!(ApiError::decode(&e.encode())?, e);
+        Ok(())
+    }
+}
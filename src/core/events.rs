@@ -0,0 +1,125 @@
+//! Change stream of the database. Every subscriber gets the events it subscribed to published after
+//! it subscribed, in the same order, numbered by one sequence, so webhooks, push channels, audit logs,
+//! metrics and notifiers see one consistent history.
+//! Subscriptions are bounded: a subscriber that doesn't keep up is disconnected instead of holding
+//! unbounded memory, it sees the disconnect after receiving queued events and can subscribe again.
+
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Mutex;
+use crate::entities::accounts::AccountId;
+use crate::entities::subcategories::SubcategoryId;
+use crate::reports::alerts::BalanceAlert;
+
+/// Events queued for a subscriber of all events before it is disconnected.
+pub const SUBSCRIBER_CAPACITY: usize = 1024;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum EventKind {
+    OperationAdded{date: u64, account: AccountId, subcategory: SubcategoryId, summa: i64},
+    /// stored operations of the month were changed, moved or removed
+    OperationsUpdated{month: u64},
+    /// changes of the month were written to disk, version is the month version after the change
+    MonthFlushed{month: u64, version: u64},
+    /// name of the saved or reloaded dictionary, like accounts or subcategories
+    DictionaryChanged(&'static str),
+    /// balance crossed a threshold of the account in either direction
+    BalanceAlert(BalanceAlert)
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Event {
+    pub sequence: u64,
+    pub kind: EventKind
+}
+
+struct Subscriber {
+    sender: SyncSender<Event>,
+    filter: fn(&EventKind) -> bool
+}
+
+#[derive(Default)]
+struct Subscribers {
+    next_sequence: u64,
+    subscribers: Vec<Subscriber>
+}
+
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Mutex<Subscribers>
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        EventBus::default()
+    }
+
+    /// All events, up to SUBSCRIBER_CAPACITY are queued until received, dropping the receiver ends
+    /// the subscription.
+    pub fn subscribe(&self) -> Receiver<Event> {
+        self.subscribe_to(SUBSCRIBER_CAPACITY, |_|true)
+    }
+
+    /// Events accepted by the filter, the subscriber is disconnected when capacity events are queued.
+    pub fn subscribe_to(&self, capacity: usize, filter: fn(&EventKind) -> bool) -> Receiver<Event> {
+        let (sender, receiver) = sync_channel(capacity);
+        self.subscribers.lock().unwrap().subscribers.push(Subscriber{sender, filter});
+        receiver
+    }
+
+    /// Sequence numbers are assigned only when somebody listens. Publishing never blocks,
+    /// full and closed subscriptions are removed.
+    pub fn publish(&self, kind: EventKind) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if subscribers.subscribers.is_empty() {
+            return;
+        }
+        subscribers.next_sequence += 1;
+        let event = Event{sequence: subscribers.next_sequence, kind};
+        subscribers.subscribers.retain(|s|!(s.filter)(&event.kind) || s.sender.try_send(event.clone()).is_ok());
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.lock().unwrap().subscribers.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::TryRecvError;
+    use crate::core::events::{Event, EventBus, EventKind};
+
+    #[test]
+    fn test_event_bus() {
+        let bus = EventBus::new();
+        bus.publish(EventKind::OperationsUpdated{month: 202312});
+        let first = bus.subscribe();
+        bus.publish(EventKind::DictionaryChanged("accounts"));
+        let second = bus.subscribe();
+        bus.publish(EventKind::MonthFlushed{month: 202401, version: 3});
+        assert_eq!(first.try_iter().collect::<Vec<_>>(), vec![
+            Event{sequence: 1, kind: EventKind::DictionaryChanged("accounts")},
+            Event{sequence: 2, kind: EventKind::MonthFlushed{month: 202401, version: 3}}
+        ]);
+        assert_eq!(second.try_iter().map(|e|e.sequence).collect::<Vec<_>>(), vec![2]);
+        drop(first);
+        bus.publish(EventKind::OperationsUpdated{month: 202401});
+        assert_eq!(bus.subscriber_count(), 1);
+        assert_eq!(second.recv().unwrap().sequence, 3);
+    }
+
+    #[test]
+    fn test_slow_subscriber() {
+        let bus = EventBus::new();
+        let slow = bus.subscribe_to(2, |_|true);
+        let flushes = bus.subscribe_to(2, |k|matches!(k, EventKind::MonthFlushed{..}));
+        for month in 202401..202404 {
+            bus.publish(EventKind::OperationsUpdated{month});
+        }
+        bus.publish(EventKind::MonthFlushed{month: 202401, version: 1});
+        assert_eq!(bus.subscriber_count(), 1);
+        assert_eq!(slow.try_iter().map(|e|e.sequence).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(matches!(slow.try_recv(), Err(TryRecvError::Disconnected)));
+        assert_eq!(flushes.try_iter().collect::<Vec<_>>(),
+                   vec![Event{sequence: 4, kind: EventKind::MonthFlushed{month: 202401, version: 1}}]);
+    }
+}
@@ -0,0 +1,92 @@
+//! Encryption of selected free-text fields. Encrypted values are stored as "enc:" followed by
+//! hex encoded encrypted bytes, amounts and ids stay plain so they remain queryable.
+
+use std::collections::HashSet;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+use crate::core::crypto::CryptoProcessor;
+use crate::entities::finance_operations::FinanceOperation;
+
+/// Field name of payee names in the payees dictionary, other fields are operation parameter codes.
+pub const PAYEE_FIELD: &str = "PAYEE";
+const PREFIX: &str = "enc:";
+
+pub struct FieldCrypto {
+    crypto: Arc<dyn CryptoProcessor>,
+    fields: HashSet<String>
+}
+
+impl FieldCrypto {
+    /// fields are parameter codes (NETW, TYPE or custom string parameters) and PAYEE_FIELD.
+    pub fn new(crypto: Arc<dyn CryptoProcessor>, fields: Vec<String>) -> FieldCrypto {
+        FieldCrypto{crypto, fields: fields.into_iter().collect()}
+    }
+
+    pub fn is_encrypted(&self, field: &str) -> bool {
+        self.fields.contains(field)
+    }
+
+    pub fn encrypt(&self, value: &str) -> Result<String, Error> {
+        let data = self.crypto.encode(value.as_bytes())?;
+        Ok(data.iter().fold(PREFIX.to_string(), |s, b|s + &format!("{:02x}", b)))
+    }
+
+    /// Values without prefix are returned as is, so data written before encryption was enabled stays readable.
+    pub fn decrypt(&self, value: &str) -> Result<String, Error> {
+        let Some(hex) = value.strip_prefix(PREFIX) else { return Ok(value.to_string()) };
+        let invalid = || Error::new(ErrorKind::InvalidData, "invalid encrypted value");
+        if hex.len() % 2 != 0 {
+            return Err(invalid());
+        }
+        let data: Vec<u8> = (0..hex.len()).step_by(2)
+            .map(|i|hex.get(i..i + 2).and_then(|b|u8::from_str_radix(b, 16).ok()).ok_or_else(invalid))
+            .collect::<Result<_, _>>()?;
+        String::from_utf8(self.crypto.decode(&data)?).map_err(|_|invalid())
+    }
+
+    pub fn encrypt_operation(&self, op: &mut FinanceOperation) -> Result<(), Error> {
+        op.map_strings(|code, v|if self.is_encrypted(code) {self.encrypt(v).map(Some)} else {Ok(None)})
+    }
+
+    pub fn decrypt_operation(&self, op: &mut FinanceOperation) -> Result<(), Error> {
+        op.map_strings(|code, v|if self.is_encrypted(code) {self.decrypt(v).map(Some)} else {Ok(None)})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use std::sync::Arc;
+    use crate::core::crypto::CryptoProcessor;
+    use crate::core::field_crypto::FieldCrypto;
+    use crate::entities::accounts::AccountId;
+    use crate::entities::finance_operations::{FinOpParameter, FinanceOperation};
+    use crate::entities::subcategories::SubcategoryId;
+
+    struct XorProcessor{}
+
+    impl CryptoProcessor for XorProcessor {
+        fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+            Ok(data.iter().map(|b|b ^ 0x55).collect())
+        }
+
+        fn decode(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+            self.encode(data)
+        }
+    }
+
+    #[test]
+    fn test_encrypt_operation() -> Result<(), Error> {
+        let crypto = FieldCrypto::new(Arc::new(XorProcessor{}), vec!["NETW".to_string()]);
+        let mut op = FinanceOperation::new(20240105, AccountId(1), SubcategoryId(2), None, 1000,
+                                           vec![FinOpParameter::Netw("shop".into()), FinOpParameter::Typ("card".into())]);
+        crypto.encrypt_operation(&mut op)?;
+        assert_eq!(op.get_network(), Some("enc:263d3a25"));
+        assert!(matches!(&op.get_parameters()[1], FinOpParameter::Typ(t) if t.as_ref() == "card"));
+        crypto.decrypt_operation(&mut op)?;
+        assert_eq!(op.get_network(), Some("shop"));
+        assert_eq!(crypto.decrypt("plain")?, "plain");
+        assert!(crypto.decrypt("enc:2g").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+//! Idempotency keys of recent mutations. A client retrying a request after a network failure sends
+//! the same key, and the mutation is not applied twice. Keys are kept for KEY_RETENTION_MS.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+pub const IDEMPOTENCY_KEYS_FILE_NAME: &str = "idempotency_keys.txt";
+/// 7 days
+pub const KEY_RETENTION_MS: u64 = 7 * 24 * 3600 * 1000;
+
+#[derive(Default)]
+pub struct IdempotencyKeys {
+    /// key and unix time of its mutation in milliseconds
+    keys: HashMap<String, u64>,
+    /// keys of mutations in progress
+    pending: HashSet<String>,
+    modified: bool
+}
+
+impl IdempotencyKeys {
+    /// One "timestamp key" line per key, missing file means no keys.
+    pub fn load(data_folder_path: &str) -> Result<IdempotencyKeys, Error> {
+        let text = match fs::read_to_string(Path::new(data_folder_path).join(IDEMPOTENCY_KEYS_FILE_NAME)) {
+            Ok(t) => t,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(IdempotencyKeys::default()),
+            Err(e) => return Err(e)
+        };
+        let keys = text.lines()
+            .filter(|l|!l.is_empty())
+            .map(|line|line.split_once(' ').and_then(|(t, k)|Some((k.to_string(), t.parse().ok()?)))
+                .ok_or(Error::new(ErrorKind::InvalidData, format!("invalid idempotency key line {}", line))))
+            .collect::<Result<_, _>>()?;
+        Ok(IdempotencyKeys{keys, pending: HashSet::new(), modified: false})
+    }
+
+    /// Keys are printable text without spaces.
+    pub fn validate(key: &str) -> Result<(), Error> {
+        if key.is_empty() || key.len() > 200 || key.chars().any(|c|c.is_whitespace() || c.is_control()) {
+            return Err(Error::new(ErrorKind::InvalidInput, "idempotency key should be 1 to 200 characters without spaces"));
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, key: &str, now: u64) -> bool {
+        self.keys.get(key).is_some_and(|t|now.saturating_sub(*t) < KEY_RETENTION_MS)
+    }
+
+    /// Reserves the key for a mutation, false when a mutation with the key was already applied.
+    pub fn begin(&mut self, key: &str, now: u64) -> Result<bool, Error> {
+        IdempotencyKeys::validate(key)?;
+        if self.contains(key, now) {
+            return Ok(false);
+        }
+        if !self.pending.insert(key.to_string()) {
+            return Err(Error::new(ErrorKind::ResourceBusy, format!("request with idempotency key {} is in progress", key)));
+        }
+        Ok(true)
+    }
+
+    /// Releases the reserved key, the key is recorded when the mutation was applied.
+    pub fn finish(&mut self, key: &str, now: u64, applied: bool) {
+        self.pending.remove(key);
+        if applied {
+            self.insert(key.to_string(), now);
+        }
+    }
+
+    /// Records the key and forgets expired ones.
+    pub fn insert(&mut self, key: String, now: u64) {
+        self.keys.retain(|_, t|now.saturating_sub(*t) < KEY_RETENTION_MS);
+        self.keys.insert(key, now);
+        self.modified = true;
+    }
+
+    /// Writes keys inserted since the previous save.
+    pub fn save(&mut self, data_folder_path: &str) -> Result<(), Error> {
+        if !self.modified {
+            return Ok(());
+        }
+        let text: String = self.keys.iter().map(|(key, t)|format!("{} {}\n", t, key)).collect();
+        let file_name = Path::new(data_folder_path).join(IDEMPOTENCY_KEYS_FILE_NAME);
+        let temp_name = file_name.with_extension("tmp");
+        fs::write(&temp_name, text)?;
+        fs::rename(temp_name, file_name)?;
+        self.modified = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::Error;
+    use crate::core::idempotency::{IdempotencyKeys, KEY_RETENTION_MS};
+
+    #[test]
+    fn test_idempotency_keys() -> Result<(), Error> {
+        let folder = temp_dir().join("hadb_idempotency_test");
+        fs::create_dir_all(&folder)?;
+        let path = folder.to_string_lossy().to_string();
+        let mut keys = IdempotencyKeys::load(&path)?;
+        keys.insert("a1".to_string(), 1000);
+        keys.insert("b2".to_string(), 2000 + KEY_RETENTION_MS);
+        keys.save(&path)?;
+        let mut keys = IdempotencyKeys::load(&path)?;
+        assert!(keys.contains("b2", 3000 + KEY_RETENTION_MS));
+        assert!(!keys.contains("a1", 3000 + KEY_RETENTION_MS));
+        assert!(keys.begin("a1", 3000 + KEY_RETENTION_MS)?);
+        assert!(keys.begin("a1", 3000 + KEY_RETENTION_MS).is_err());
+        keys.finish("a1", 3000 + KEY_RETENTION_MS, true);
+        assert!(!keys.begin("a1", 4000 + KEY_RETENTION_MS)?);
+        assert!(IdempotencyKeys::validate("retry 1").is_err());
+        fs::remove_dir_all(&folder)
+    }
+}
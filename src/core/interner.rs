@@ -0,0 +1,53 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Pool of shared strings, repeating parameter values are stored once.
+#[derive(Default)]
+pub struct StringPool {
+    strings: HashSet<Arc<str>>
+}
+
+impl StringPool {
+    pub fn new() -> StringPool {
+        StringPool{strings: HashSet::new()}
+    }
+
+    pub fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(v) = self.strings.get(s) {
+            return v.clone();
+        }
+        let v: Arc<str> = Arc::from(s);
+        self.strings.insert(v.clone());
+        v
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// Total length of pooled strings.
+    pub fn bytes(&self) -> usize {
+        self.strings.iter().map(|s|s.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::core::interner::StringPool;
+
+    #[test]
+    fn test_intern() {
+        let mut pool = StringPool::new();
+        let a = pool.intern("SILPO");
+        let b = pool.intern("SILPO");
+        pool.intern("ATB");
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.bytes(), 8);
+    }
+}
@@ -1,3 +1,38 @@
+#[cfg(feature = "fs")]
 pub mod time_series_data;
+#[cfg(feature = "fs")]
+pub mod attachments;
+#[cfg(feature = "fs")]
+pub mod archive;
+#[cfg(feature = "fs")]
+pub mod backup;
+#[cfg(feature = "fs")]
+pub mod totals_cache;
+#[cfg(feature = "fs")]
+pub mod warm_list;
+#[cfg(feature = "fs")]
+pub mod idempotency;
+#[cfg(feature = "fs")]
+pub mod month_locks;
+#[cfg(feature = "fs")]
+pub mod month_versions;
+#[cfg(feature = "fs")]
+pub mod year_shards;
+#[cfg(feature = "server")]
+pub mod replication;
+#[cfg(all(feature = "fs", feature = "json"))]
+pub mod wal;
 pub mod data_source;
-mod crypto;
\ No newline at end of file
+pub mod crypto;
+pub mod errors;
+pub mod field_crypto;
+#[cfg(feature = "encryption")]
+pub mod encryption_domain;
+pub mod clock;
+pub mod dates;
+pub mod interner;
+pub mod amounts;
+pub mod cache_stats;
+pub mod trace;
+pub mod sharded;
+pub mod events;
@@ -0,0 +1,161 @@
+//! Advisory locks of months shared by all processes using the data folder. A batch job (mass import,
+//! repair) locks the months it rewrites, other processes refuse to change them until they are unlocked.
+//! Every lock is a file locks/yyyymm.lock created exclusively, its text is "owner unix_seconds".
+
+use std::fs::{self, OpenOptions};
+use std::io::{Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+pub const LOCKS_FOLDER: &str = "locks";
+const LOCK_EXTENSION: &str = "lock";
+
+#[derive(PartialEq, Debug)]
+pub struct MonthLock {
+    /// yyyymm
+    pub month: u64,
+    pub owner: String,
+    /// unix time the lock was taken at, in seconds
+    pub since: u64
+}
+
+impl MonthLock {
+    pub fn describe(&self) -> String {
+        format!("month {} is locked by {}", self.month, self.owner)
+    }
+}
+
+fn lock_path(data_folder_path: &str, month: u64) -> PathBuf {
+    Path::new(data_folder_path).join(LOCKS_FOLDER).join(format!("{}.{}", month, LOCK_EXTENSION))
+}
+
+fn check_owner(owner: &str) -> Result<(), Error> {
+    if owner.is_empty() || owner.contains(char::is_whitespace) {
+        return Err(Error::new(ErrorKind::InvalidInput, "lock owner should be a word without spaces"));
+    }
+    Ok(())
+}
+
+/// Comma separated months (yyyymm) and ranges of months (yyyymm-yyyymm).
+pub fn parse_months(text: &str) -> Result<Vec<u64>, Error> {
+    let invalid = |item: &str|Error::new(ErrorKind::InvalidInput, format!("invalid month {}", item));
+    let month = |item: &str|item.parse::<u64>().ok().filter(|m|(1..=12).contains(&(m % 100)) && *m > 100)
+        .ok_or_else(||invalid(item));
+    let mut result = Vec::new();
+    for item in text.split(',') {
+        let (from, to) = match item.split_once('-') {
+            Some((from, to)) => (month(from)?, month(to)?),
+            None => (month(item)?, month(item)?)
+        };
+        if from > to {
+            return Err(invalid(item));
+        }
+        let mut m = from;
+        while m <= to {
+            result.push(m);
+            m = if m % 100 == 12 {m + 89} else {m + 1};
+        }
+    }
+    Ok(result)
+}
+
+/// Lock of the month, None when it is not locked.
+pub fn get_lock(data_folder_path: &str, month: u64) -> Result<Option<MonthLock>, Error> {
+    let text = match fs::read_to_string(lock_path(data_folder_path, month)) {
+        Ok(t) => t,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e)
+    };
+    let (owner, since) = text.trim().split_once(' ')
+        .and_then(|(o, s)|Some((o.to_string(), s.parse().ok()?)))
+        .ok_or(Error::new(ErrorKind::InvalidData, format!("invalid lock of month {}", month)))?;
+    Ok(Some(MonthLock{month, owner, since}))
+}
+
+/// Locks all months or none of them, months already locked by the same owner stay locked.
+pub fn lock_months(data_folder_path: &str, months: &[u64], owner: &str, now: u64) -> Result<(), Error> {
+    check_owner(owner)?;
+    fs::create_dir_all(Path::new(data_folder_path).join(LOCKS_FOLDER))?;
+    let mut taken = Vec::new();
+    for month in months {
+        let created = OpenOptions::new().write(true).create_new(true).open(lock_path(data_folder_path, *month))
+            .and_then(|mut file|file.write_all(format!("{} {}", owner, now).as_bytes()));
+        let result = match created {
+            Ok(()) => {
+                taken.push(*month);
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => match get_lock(data_folder_path, *month)? {
+                Some(lock) if lock.owner != owner => Err(Error::new(ErrorKind::WouldBlock, lock.describe())),
+                _ => Ok(())
+            },
+            Err(e) => Err(e)
+        };
+        if let Err(e) = result {
+            for month in taken {
+                fs::remove_file(lock_path(data_folder_path, month))?;
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Removes locks of the owner, locks of other owners are removed only when forced.
+pub fn unlock_months(data_folder_path: &str, months: &[u64], owner: &str, force: bool) -> Result<(), Error> {
+    for month in months {
+        match get_lock(data_folder_path, *month)? {
+            Some(lock) if lock.owner != owner && !force => return Err(Error::new(ErrorKind::PermissionDenied, lock.describe())),
+            Some(_) => fs::remove_file(lock_path(data_folder_path, *month))?,
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+/// All locks ordered by month.
+pub fn list_locks(data_folder_path: &str) -> Result<Vec<MonthLock>, Error> {
+    let entries = match fs::read_dir(Path::new(data_folder_path).join(LOCKS_FOLDER)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e)
+    };
+    let mut result = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e|e == LOCK_EXTENSION) {
+            if let Some(month) = path.file_stem().and_then(|s|s.to_str()).and_then(|s|s.parse().ok()) {
+                result.extend(get_lock(data_folder_path, month)?);
+            }
+        }
+    }
+    result.sort_by_key(|l|l.month);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::{Error, ErrorKind};
+    use crate::core::month_locks::{get_lock, list_locks, lock_months, parse_months, unlock_months};
+
+    #[test]
+    fn test_month_locks() -> Result<(), Error> {
+        let folder = temp_dir().join("hadb_month_locks_test");
+        let _ = fs::remove_dir_all(&folder);
+        let path = folder.to_string_lossy().to_string();
+        lock_months(&path, &[202401, 202402], "import", 100)?;
+        assert_eq!(lock_months(&path, &[202403, 202402], "repair", 200).err().map(|e|e.kind()), Some(ErrorKind::WouldBlock));
+        assert_eq!(get_lock(&path, 202403)?, None);
+        lock_months(&path, &[202402, 202403], "import", 300)?;
+        assert_eq!(list_locks(&path)?.iter().map(|l|(l.month, l.since)).collect::<Vec<_>>(),
+                   vec![(202401, 100), (202402, 100), (202403, 300)]);
+        assert!(unlock_months(&path, &[202401], "repair", false).is_err());
+        unlock_months(&path, &[202401, 202402], "import", false)?;
+        unlock_months(&path, &[202403], "repair", true)?;
+        assert!(list_locks(&path)?.is_empty());
+        assert_eq!(parse_months("202311-202402,202406")?, vec![202311, 202312, 202401, 202402, 202406]);
+        assert!(parse_months("202413").is_err());
+        fs::remove_dir_all(&folder)
+    }
+}
@@ -0,0 +1,54 @@
+//! Versions of months, bumped on every change. Clients compare them with versions of months they
+//! cached to find stale ones without loading operations.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+pub const MONTH_VERSIONS_FILE_NAME: &str = "month_versions.txt";
+
+/// One "yyyymm version" line per month, missing file means no month was changed.
+pub fn load_month_versions(data_folder_path: &str) -> Result<HashMap<u64, u64>, Error> {
+    let text = match fs::read_to_string(Path::new(data_folder_path).join(MONTH_VERSIONS_FILE_NAME)) {
+        Ok(t) => t,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e)
+    };
+    text.lines()
+        .filter(|l|!l.is_empty())
+        .map(|line|line.split_once(' ').and_then(|(m, v)|Some((m.parse().ok()?, v.parse().ok()?)))
+            .ok_or(Error::new(ErrorKind::InvalidData, format!("invalid month versions line {}", line))))
+        .collect()
+}
+
+pub fn save_month_versions(data_folder_path: &str, versions: &HashMap<u64, u64>) -> Result<(), Error> {
+    let mut months: Vec<(&u64, &u64)> = versions.iter().collect();
+    months.sort();
+    let text: String = months.into_iter().map(|(month, version)|format!("{} {}\n", month, version)).collect();
+    let file_name = Path::new(data_folder_path).join(MONTH_VERSIONS_FILE_NAME);
+    let temp_name = file_name.with_extension("tmp");
+    fs::write(&temp_name, text)?;
+    fs::rename(temp_name, file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::Error;
+    use crate::core::month_versions::{load_month_versions, save_month_versions};
+
+    #[test]
+    fn test_month_versions() -> Result<(), Error> {
+        let folder = temp_dir().join("hadb_month_versions_test");
+        fs::create_dir_all(&folder)?;
+        let path = folder.to_string_lossy().to_string();
+        assert!(load_month_versions(&path)?.is_empty());
+        let versions = HashMap::from([(202401, 3), (202312, 1)]);
+        save_month_versions(&path, &versions)?;
+        assert_eq!(load_month_versions(&path)?, versions);
+        fs::remove_dir_all(&folder)
+    }
+}
@@ -0,0 +1,294 @@
+//! Shipping of flushed files to a standby instance. Every connection carries a list of
+//! length-prefixed frames terminated by an empty frame, the standby answers with a single byte,
+//! rejection is followed by a frame with the encoded ApiError. Frames are encrypted with the key
+//! shared by the primary and the standby, a connection with a frame that fails to decrypt is rejected
+//! and none of its files are applied.
+
+use std::fs;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path};
+use crate::core::crypto::CryptoProcessor;
+use crate::core::errors::{crypto_error, ApiError};
+use crate::core::trace::{trace, RequestSpan};
+use crate::core::time_series_data::SaveBatch;
+
+const FRAME_FILE: u8 = 1;
+const FRAME_REMOVE_FILE: u8 = 2;
+const FRAME_REMOVE_FOLDER: u8 = 3;
+const ACK: u8 = 0;
+const NAK: u8 = 1;
+/// Largest replicated frame, a month file or an attachment with the path.
+const MAX_REPLICATION_FRAME: usize = 256 << 20;
+
+#[derive(PartialEq, Debug)]
+pub enum ReplicationMessage {
+    File{path: String, data: Vec<u8>},
+    RemoveFile(String),
+    RemoveFolder(String)
+}
+
+impl ReplicationMessage {
+    fn encode(&self) -> Vec<u8> {
+        let (kind, path, data) = match self {
+            ReplicationMessage::File{path, data} => (FRAME_FILE, path, data.as_slice()),
+            ReplicationMessage::RemoveFile(path) => (FRAME_REMOVE_FILE, path, [].as_slice()),
+            ReplicationMessage::RemoveFolder(path) => (FRAME_REMOVE_FOLDER, path, [].as_slice())
+        };
+        let mut result = Vec::with_capacity(5 + path.len() + data.len());
+        result.push(kind);
+        result.extend_from_slice(&(path.len() as u32).to_le_bytes());
+        result.extend_from_slice(path.as_bytes());
+        result.extend_from_slice(data);
+        result
+    }
+
+    fn decode(frame: &[u8]) -> Result<ReplicationMessage, Error> {
+        if frame.len() < 5 {
+            return Err(Error::new(ErrorKind::InvalidData, "replication frame is too short"));
+        }
+        let path_length = u32::from_le_bytes(frame[1..5].try_into().unwrap()) as usize;
+        let path = frame.get(5..5 + path_length)
+            .ok_or(Error::new(ErrorKind::InvalidData, "invalid replication frame path length"))?;
+        let path = String::from_utf8(path.to_vec())
+            .map_err(|_|Error::new(ErrorKind::InvalidData, "invalid replication frame path"))?;
+        match frame[0] {
+            FRAME_FILE => Ok(ReplicationMessage::File{path, data: frame[5 + path_length..].to_vec()}),
+            FRAME_REMOVE_FILE => Ok(ReplicationMessage::RemoveFile(path)),
+            FRAME_REMOVE_FOLDER => Ok(ReplicationMessage::RemoveFolder(path)),
+            k => Err(Error::new(ErrorKind::InvalidData, format!("unknown replication frame type {}", k)))
+        }
+    }
+}
+
+pub(crate) fn write_frame(stream: &mut impl Write, frame: &[u8]) -> Result<(), Error> {
+    stream.write_all(&(frame.len() as u32).to_le_bytes())?;
+    stream.write_all(frame)
+}
+
+/// Frames longer than max_length are rejected, memory grows only as frame data arrives.
+pub(crate) fn read_frame(stream: &mut impl Read, max_length: usize) -> Result<Vec<u8>, Error> {
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length)?;
+    let length = u32::from_le_bytes(length) as usize;
+    if length > max_length {
+        return Err(Error::new(ErrorKind::InvalidData, format!("frame of {} bytes exceeds the limit of {} bytes",
+                                                              length, max_length)));
+    }
+    let mut frame = Vec::new();
+    stream.take(length as u64).read_to_end(&mut frame)?;
+    if frame.len() != length {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed in the middle of a frame"));
+    }
+    Ok(frame)
+}
+
+/// Accepts only relative paths that stay inside the data folder.
+fn check_path(path: &str) -> Result<(), Error> {
+    if path.is_empty() || !Path::new(path).components().all(|c|matches!(c, Component::Normal(_))) {
+        return Err(Error::new(ErrorKind::InvalidData, format!("invalid replicated path {}", path)));
+    }
+    Ok(())
+}
+
+pub struct ReplicationSender {
+    address: String,
+    data_folder_path: String,
+    crypto: Box<dyn CryptoProcessor>
+}
+
+impl ReplicationSender {
+    pub fn new(address: String, data_folder_path: String, crypto: Box<dyn CryptoProcessor>) -> ReplicationSender {
+        ReplicationSender{address, data_folder_path, crypto}
+    }
+
+    fn relative_path(&self, path: &str) -> Result<String, Error> {
+        Path::new(path).strip_prefix(&self.data_folder_path).ok()
+            .and_then(|p|p.to_str())
+            .map(|p|p.to_string())
+            .ok_or(Error::new(ErrorKind::InvalidInput, format!("{} is outside of the data folder", path)))
+    }
+
+    /// Sends files written by the batch and removals of obsolete files and folders.
+    pub fn send_batch(&self, batch: &SaveBatch) -> Result<(), Error> {
+        let mut messages = Vec::new();
+        for (name, data) in &batch.files {
+            messages.push(ReplicationMessage::File{path: self.relative_path(name)?, data: data.clone()});
+        }
+        for name in &batch.obsolete_files {
+            messages.push(ReplicationMessage::RemoveFile(self.relative_path(name)?));
+        }
+        for name in &batch.obsolete_folders {
+            messages.push(ReplicationMessage::RemoveFolder(self.relative_path(name)?));
+        }
+        self.send(&messages)
+    }
+
+    /// Sends given files of the data folder as they are on disk.
+    pub fn send_files(&self, names: &[String]) -> Result<(), Error> {
+        let mut messages = Vec::new();
+        for name in names {
+            messages.push(ReplicationMessage::File{path: self.relative_path(name)?, data: fs::read(name)?});
+        }
+        self.send(&messages)
+    }
+
+    pub fn send(&self, messages: &[ReplicationMessage]) -> Result<(), Error> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let mut stream = TcpStream::connect(&self.address)?;
+        for message in messages {
+            write_frame(&mut stream, &self.crypto.encode(&message.encode()).map_err(crypto_error)?)?;
+        }
+        write_frame(&mut stream, &[])?;
+        let mut answer = [0u8];
+        stream.read_exact(&mut answer)?;
+        if answer[0] != ACK {
+            // standbys without error frames just close the connection
+            let reason = read_frame(&mut stream, MAX_REPLICATION_FRAME).ok()
+                .and_then(|f|String::from_utf8(f).ok())
+                .and_then(|t|ApiError::decode(&t).ok());
+            return Err(match reason {
+                Some(e) => Error::new(e.code.kind(), format!("standby {} rejected replicated files: {}",
+                                                             self.address, e.to_error())),
+                None => Error::other(format!("standby {} rejected replicated files", self.address))
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Standby side, applies received files to its data folder.
+pub struct ReplicationReceiver {
+    data_folder_path: String,
+    crypto: Box<dyn CryptoProcessor>
+}
+
+impl ReplicationReceiver {
+    pub fn new(data_folder_path: String, crypto: Box<dyn CryptoProcessor>) -> ReplicationReceiver {
+        ReplicationReceiver{data_folder_path, crypto}
+    }
+
+    /// Serves primaries forever, errors of a single connection are reported and do not stop the loop.
+    pub fn run(&self, listener: TcpListener) -> Result<(), Error> {
+        for stream in listener.incoming() {
+            let result = stream.and_then(|mut s|self.handle(&mut s));
+            if let Err(e) = result {
+                println!("replication error: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Receives all frames of the connection first, files are applied only when every frame is valid.
+    pub fn handle(&self, stream: &mut TcpStream) -> Result<usize, Error> {
+        let _span = RequestSpan::start(&format!("replication from {}", stream.peer_addr()?));
+        let result = self.receive(stream).and_then(|messages| {
+            for message in &messages {
+                self.apply(message)?;
+            }
+            Ok(messages.len())
+        });
+        match &result {
+            Ok(_) => stream.write_all(&[ACK])?,
+            Err(e) => {
+                trace(&format!("replication failed: {}", e));
+                stream.write_all(&[NAK])?;
+                write_frame(stream, ApiError::from_error(e).encode().as_bytes())?;
+            }
+        }
+        result
+    }
+
+    fn receive(&self, stream: &mut TcpStream) -> Result<Vec<ReplicationMessage>, Error> {
+        let mut messages = Vec::new();
+        loop {
+            let frame = read_frame(stream, MAX_REPLICATION_FRAME)?;
+            if frame.is_empty() {
+                return Ok(messages);
+            }
+            messages.push(ReplicationMessage::decode(&self.crypto.decode(&frame).map_err(crypto_error)?)?);
+        }
+    }
+
+    fn apply(&self, message: &ReplicationMessage) -> Result<(), Error> {
+        match message {
+            ReplicationMessage::File{path, data} => {
+                check_path(path)?;
+                let file = Path::new(&self.data_folder_path).join(path);
+                if let Some(parent) = file.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(file, data)
+            }
+            ReplicationMessage::RemoveFile(path) => {
+                check_path(path)?;
+                ignore_not_found(fs::remove_file(Path::new(&self.data_folder_path).join(path)))
+            }
+            ReplicationMessage::RemoveFolder(path) => {
+                check_path(path)?;
+                ignore_not_found(fs::remove_dir(Path::new(&self.data_folder_path).join(path)))
+            }
+        }
+    }
+}
+
+fn ignore_not_found(result: Result<(), Error>) -> Result<(), Error> {
+    match result {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        r => r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::{Error, ErrorKind};
+    use std::net::TcpListener;
+    use std::thread;
+    use crate::core::crypto::AesGcmProcessor;
+    use crate::core::replication::{ReplicationMessage, ReplicationReceiver, ReplicationSender};
+    use crate::core::time_series_data::SaveBatch;
+
+    #[test]
+    fn test_replicate_batch() -> Result<(), Error> {
+        let folder = temp_dir().join("had_test_replica").to_str().unwrap().to_string();
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.clone() + "/dates/20240101")?;
+        fs::write(folder.clone() + "/dates/20240101/old.json", b"[]")?;
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?.to_string();
+        let standby_folder = folder.clone();
+        let handle = thread::spawn(move || {
+            let receiver = ReplicationReceiver::new(standby_folder, Box::new(AesGcmProcessor::new(&[5u8; 32])));
+            let mut counts = Vec::new();
+            for _ in 0..3 {
+                counts.push(receiver.handle(&mut listener.accept()?.0).ok());
+            }
+            Ok::<_, Error>(counts)
+        });
+        let sender = ReplicationSender::new(address.clone(), "/primary".to_string(),
+                                            Box::new(AesGcmProcessor::new(&[5u8; 32])));
+        let mut batch = SaveBatch::default();
+        batch.files.push(("/primary/dates/20240102/operations.json".to_string(), b"[1]".to_vec()));
+        batch.obsolete_files.push("/primary/dates/20240101/old.json".to_string());
+        batch.obsolete_folders.push("/primary/dates/20240101".to_string());
+        sender.send_batch(&batch)?;
+        let bad = [ReplicationMessage::File{path: "../escape.json".to_string(), data: Vec::new()}];
+        let e = sender.send(&bad).err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        assert!(e.to_string().contains("VALIDATION_FAILED ["));
+        assert!(e.to_string().ends_with("]: invalid replicated path ../escape.json"));
+        // a primary with another key is rejected and nothing it sent is written
+        let intruder = ReplicationSender::new(address, "/primary".to_string(), Box::new(AesGcmProcessor::new(&[6u8; 32])));
+        let forged = [ReplicationMessage::File{path: "dates/forged.json".to_string(), data: Vec::new()}];
+        assert!(intruder.send(&forged).is_err());
+        assert_eq!(handle.join().unwrap()?, vec![Some(3), None, None]);
+        assert!(!fs::exists(folder.clone() + "/dates/forged.json")?);
+        assert_eq!(fs::read(folder.clone() + "/dates/20240102/operations.json")?, b"[1]");
+        assert!(!fs::exists(folder.clone() + "/dates/20240101")?);
+        fs::remove_dir_all(&folder)
+    }
+}
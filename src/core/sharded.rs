@@ -0,0 +1,192 @@
+//! Dictionary split into shard files that are loaded on first access. The index file maps every id
+//! to its shard and is kept in memory, so lookups of unknown ids never touch the disk.
+
+use std::sync::OnceLock;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Error, ErrorKind};
+use crate::core::cache_stats::CacheStats;
+use crate::core::data_source::DataSource;
+
+pub const SHARD_INDEX_FILE_NAME: &str = "index.txt";
+
+pub struct ShardedDictionary<T> {
+    folder: String,
+    source: Box<dyn DataSource<Vec<T>>>,
+    key: fn(&T) -> u64,
+    index: HashMap<u64, u64>,
+    shards: BTreeMap<u64, OnceLock<HashMap<u64, T>>>,
+    stats: CacheStats
+}
+
+impl<T> ShardedDictionary<T> {
+    /// Reads the shard index from folder, missing index means an empty dictionary.
+    pub fn load(folder: String, source: Box<dyn DataSource<Vec<T>>>, key: fn(&T) -> u64)
+        -> Result<ShardedDictionary<T>, Error> {
+        let text = match fs::read_to_string(folder.clone() + "/" + SHARD_INDEX_FILE_NAME) {
+            Ok(t) => t,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e)
+        };
+        let index = parse_index(&text)?;
+        let shards = index.values().map(|s|(*s, OnceLock::new())).collect();
+        Ok(ShardedDictionary{folder, source, key, index, shards, stats: CacheStats::default()})
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        self.index.contains_key(&id)
+    }
+
+    pub fn get(&self, id: u64) -> Result<Option<&T>, Error> {
+        let shard = match self.index.get(&id) {
+            Some(s) => *s,
+            None => return Ok(None)
+        };
+        self.get_shard(shard)?.get(&id)
+            .map(Some)
+            .ok_or(Error::new(ErrorKind::InvalidData, format!("id {} is missing in shard {}", id, shard)))
+    }
+
+    /// Loads all shards.
+    pub fn all(&self) -> Result<Vec<&T>, Error> {
+        let mut result = Vec::new();
+        for shard in self.shards.keys() {
+            result.extend(self.get_shard(*shard)?.values());
+        }
+        Ok(result)
+    }
+
+    fn get_shard(&self, shard: u64) -> Result<&HashMap<u64, T>, Error> {
+        let cell = self.shards.get(&shard)
+            .ok_or(Error::new(ErrorKind::InvalidInput, format!("unknown shard {}", shard)))?;
+        if let Some(items) = cell.get() {
+            self.stats.hit();
+            return Ok(items);
+        }
+        self.stats.miss();
+        let items = self.source.load(self.shard_name(shard), true)?.into_iter()
+            .map(|t|((self.key)(&t), t))
+            .collect();
+        Ok(cell.get_or_init(||items))
+    }
+
+    fn shard_name(&self, shard: u64) -> String {
+        format!("{}/{}", self.folder, shard)
+    }
+
+    /// Adds items (shard and item) to shards, rewrites affected shard files and the index.
+    pub fn add(&mut self, items: Vec<(u64, T)>) -> Result<(), Error> {
+        let mut by_shard: BTreeMap<u64, Vec<T>> = BTreeMap::new();
+        for (shard, item) in items {
+            let id = (self.key)(&item);
+            if self.index.get(&id).is_some_and(|s|*s != shard) {
+                return Err(Error::new(ErrorKind::AlreadyExists, format!("id {} is already in another shard", id)));
+            }
+            by_shard.entry(shard).or_default().push(item);
+        }
+        fs::create_dir_all(&self.folder)?;
+        for (shard, items) in by_shard {
+            if self.shards.contains_key(&shard) {
+                self.get_shard(shard)?;
+            }
+            let mut existing = self.shards.remove(&shard).and_then(|mut c|c.take()).unwrap_or_default();
+            for item in items {
+                let id = (self.key)(&item);
+                self.index.insert(id, shard);
+                existing.insert(id, item);
+            }
+            let mut values: Vec<T> = existing.into_values().collect();
+            values.sort_by_key(|t|(self.key)(t));
+            self.source.save(&values, self.shard_name(shard))?;
+            let items: HashMap<u64, T> = values.into_iter().map(|t|((self.key)(&t), t)).collect();
+            self.shards.insert(shard, OnceLock::from(items));
+        }
+        let temp_name = self.folder.clone() + "/" + SHARD_INDEX_FILE_NAME + ".tmp";
+        fs::write(&temp_name, format_index(&self.index))?;
+        fs::rename(temp_name, self.folder.clone() + "/" + SHARD_INDEX_FILE_NAME)
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn get_shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn get_loaded_shards(&self) -> usize {
+        self.shards.values().filter(|c|c.get().is_some()).count()
+    }
+
+    pub fn get_stats(&self) -> &CacheStats {
+        &self.stats
+    }
+}
+
+/// Index lines are "id shard".
+fn parse_index(text: &str) -> Result<HashMap<u64, u64>, Error> {
+    let mut result = HashMap::new();
+    for line in text.lines().filter(|l|!l.is_empty()) {
+        let (id, shard) = line.split_once(' ')
+            .and_then(|(i, s)|Some((i.parse().ok()?, s.parse().ok()?)))
+            .ok_or(Error::new(ErrorKind::InvalidData, format!("invalid shard index line {}", line)))?;
+        result.insert(id, shard);
+    }
+    Ok(result)
+}
+
+fn format_index(index: &HashMap<u64, u64>) -> String {
+    let mut entries: Vec<_> = index.iter().collect();
+    entries.sort();
+    entries.iter().map(|(i, s)|format!("{} {}\n", i, s)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Error;
+    use std::sync::Mutex;
+    use crate::core::data_source::DataSource;
+    use crate::core::sharded::ShardedDictionary;
+
+    static FILES: Mutex<Vec<(String, Vec<u64>)>> = Mutex::new(Vec::new());
+
+    struct MemorySource {}
+
+    impl DataSource<Vec<u64>> for MemorySource {
+        fn load(&self, file_name: String, _add_extension: bool) -> Result<Vec<u64>, Error> {
+            let files = FILES.lock().unwrap();
+            Ok(files.iter().rev().find(|(n, _)|*n == file_name).map(|(_, v)|v.clone()).unwrap_or_default())
+        }
+
+        fn save(&self, data: &Vec<u64>, file_name: String) -> Result<(), Error> {
+            FILES.lock().unwrap().push((file_name, data.clone()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_lazy_shards() -> Result<(), Error> {
+        let folder = std::env::temp_dir().join(format!("hadb_sharded_{}", std::process::id()))
+            .to_string_lossy().to_string();
+        let mut dictionary = ShardedDictionary::load(folder.clone(), Box::new(MemorySource{}), |v|*v)?;
+        assert!(dictionary.is_empty());
+        dictionary.add(vec![(2020, 5), (2021, 7), (2020, 3)])?;
+        assert!(dictionary.add(vec![(2021, 5)]).is_err());
+
+        let dictionary = ShardedDictionary::load(folder.clone(), Box::new(MemorySource{}), |v|*v)?;
+        assert_eq!(dictionary.index, HashMap::from([(3, 2020), (5, 2020), (7, 2021)]));
+        assert_eq!(dictionary.get_loaded_shards(), 0);
+        assert_eq!(dictionary.get(5)?, Some(&5));
+        assert_eq!(dictionary.get(3)?, Some(&3));
+        assert_eq!(dictionary.get(4)?, None);
+        assert_eq!((dictionary.get_loaded_shards(), dictionary.get_shard_count()), (1, 2));
+        assert_eq!((dictionary.get_stats().get_hits(), dictionary.get_stats().get_misses()), (1, 1));
+        assert_eq!(dictionary.all()?.len(), 3);
+        std::fs::remove_dir_all(folder)
+    }
+}
@@ -1,89 +1,202 @@
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
-use std::io::{Error, ErrorKind};
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Write};
 use std::num::ParseIntError;
 use std::ops::{Add, Deref};
-use std::rc::Rc;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Mutex, MutexGuard};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::thread;
+use std::time::Instant;
+use crate::core::cache_stats::CacheStats;
+use crate::core::trace::{log_if_slow, trace};
 
+pub type DataRange<T> = Vec<(u64, Arc<Mutex<T>>)>;
+/// Files of every item key.
+pub type FileMap = HashMap<u64, Vec<FileWithDate>>;
+
+#[derive(Clone)]
 pub struct FileWithDate {
     pub name: String,
     pub date: u64
 }
 
-pub trait DatedSource<T> {
+/// File that could not be loaded and was excluded from the data set.
+#[derive(Clone)]
+pub struct LoadProblem {
+    pub file: String,
+    pub message: String
+}
+
+pub trait DatedSource<T>: Send {
     fn load(&mut self, files: Vec<FileWithDate>) -> Result<T, Error>;
     fn parse_date(&self, info: &FileInfo) -> Result<u64, Error>;
-    fn save(&self, data: &T, data_folder_path: &String, date: u64) -> Result<(), Error>;
-    fn get_files(&self, data_folder_path: &String, date: u64) -> Result<Vec<FileWithDate>, Error>;
+    /// Serializes item with given key, nothing is written until SaveBatch::write.
+    fn prepare_save(&self, data: &T, data_folder_path: &str, key: u64) -> Result<SaveBatch, Error>;
+    fn get_files(&self, data_folder_path: &str, key: u64) -> Result<Vec<FileWithDate>, Error>;
+    /// Independent source with the same settings, so shards load and save without sharing a lock.
+    fn fork(&self) -> Box<dyn DatedSource<T>>;
+}
+
+/// Files to write and remove when saving one or more items.
+#[derive(Default)]
+pub struct SaveBatch {
+    pub folders: BTreeSet<String>,
+    pub files: Vec<(String, Vec<u8>)>,
+    /// files and then folders to remove after all files are written
+    pub obsolete_files: Vec<String>,
+    pub obsolete_folders: Vec<String>
+}
+
+impl SaveBatch {
+    pub fn append(&mut self, mut other: SaveBatch) {
+        self.folders.append(&mut other.folders);
+        self.files.append(&mut other.files);
+        self.obsolete_files.append(&mut other.obsolete_files);
+        self.obsolete_folders.append(&mut other.obsolete_folders);
+    }
+
+    /// Creates every folder once, then writes files, in several threads when parallel is set.
+    pub fn write(&self, parallel: bool) -> Result<(), Error> {
+        for folder in &self.folders {
+            fs::create_dir_all(folder)?;
+        }
+        let threads = if parallel {
+            thread::available_parallelism().map(|n|n.get()).unwrap_or(1)
+        } else {
+            1
+        };
+        if threads > 1 && self.files.len() > 1 {
+            let chunk_size = self.files.len().div_ceil(threads);
+            thread::scope(|s| {
+                let handles: Vec<_> = self.files.chunks(chunk_size)
+                    .map(|chunk|s.spawn(move ||write_files(chunk)))
+                    .collect();
+                handles.into_iter()
+                    .map(|h|h.join().unwrap_or_else(|_|Err(Error::other("save thread panicked"))))
+                    .collect::<Result<Vec<()>, Error>>()
+            })?;
+        } else {
+            write_files(&self.files)?;
+        }
+        for file in &self.obsolete_files {
+            fs::remove_file(file)?;
+        }
+        for folder in &self.obsolete_folders {
+            fs::remove_dir(folder)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_files(files: &[(String, Vec<u8>)]) -> Result<(), Error> {
+    for (name, data) in files {
+        let mut writer = BufWriter::new(File::create(name)?);
+        writer.write_all(data)?;
+        writer.flush()?;
+    }
+    Ok(())
 }
 
 struct DataHolder<T> {
-    data: Option<Rc<Mutex<T>>>,
-    key:  u64,
+    data: Option<Arc<Mutex<T>>>,
     prev: Option<u64>,
     next: Option<u64>
 }
 
 impl<T> DataHolder<T> {
-    fn new(key: u64, value: T, next: Option<u64>) -> DataHolder<T> {
-        DataHolder{key, data: Some(Rc::new(Mutex::new(value))), next, prev: None}
+    fn new(value: T, next: Option<u64>) -> DataHolder<T> {
+        DataHolder{data: Some(Arc::new(Mutex::new(value))), next, prev: None}
     }
 
-    fn empty(key: u64) -> DataHolder<T> {
-        DataHolder{key, data: None, next: None, prev: None}
+    fn empty() -> DataHolder<T> {
+        DataHolder{data: None, next: None, prev: None}
     }
-    
+
     fn set(&mut self, value: T, next: Option<u64>) {
-        _ = self.data.insert(Rc::new(Mutex::new(value)));
+        _ = self.data.insert(Arc::new(Mutex::new(value)));
         self.prev = None;
         self.next = next;
     }
 
-    fn set_next(&mut self, next: Option<u64>) {
-        self.prev = None;
-        self.next = next;
-    }
-    
-    fn unset(&mut self) {
-        self.data.take();
+    /// Item used outside of the cache is not evicted, so changes made through it can't be lost.
+    fn is_pinned(&self) -> bool {
+        self.data.as_ref().is_some_and(|d|Arc::strong_count(d) > 1)
     }
 }
 
+/// Ends of the LRU list, its lock also guards prev and next links of loaded items.
+#[derive(Default)]
+struct LruList {
+    head: Option<u64>,
+    tail: Option<u64>
+}
+
+type ItemMap<T> = BTreeMap<u64, Mutex<DataHolder<T>>>;
+
+/// Items are added and removed under the map write lock, loaded and changed under the read lock,
+/// so reads of one item proceed while another one is loaded or changed.
+/// Lock order is map, lru list, modified set, item, source, an item being loaded is locked right after the map.
 pub struct TimeSeriesData<T> {
     source: Mutex<Box<dyn DatedSource<T>>>,
     data_folder_path: String,
     max_active_items: usize,
     active_items: AtomicUsize,
-    map: BTreeMap<u64, Mutex<DataHolder<T>>>,
+    map: RwLock<ItemMap<T>>,
     modified: Mutex<HashSet<u64>>,
-    head: Mutex<Option<u64>>,
-    tail: Mutex<Option<u64>>
+    lru: Mutex<LruList>,
+    problems: Vec<LoadProblem>,
+    stats: CacheStats,
+    /// accesses of every item since the last take_access_counts call
+    access_counts: Mutex<HashMap<u64, u64>>,
+    /// bumped on every change of the item, kept when the item is removed
+    versions: Mutex<HashMap<u64, u64>>
 }
 
-impl<'a, T> TimeSeriesData<T> {
+impl<T> TimeSeriesData<T> {
     pub fn load(data_folder_path: String, source: Box<dyn DatedSource<T>>,
                 index_calculator: fn(u64) -> u64, max_active_items: usize)
         -> Result<TimeSeriesData<T>, Error> {
-        let mut file_map = HashMap::new();
-        for file in get_file_list(data_folder_path.clone())? {
-            let date = source.parse_date(&file)?;
-            let key = index_calculator(date);
-            file_map.entry(key).or_insert(Vec::new())
-                .push(FileWithDate { name: file.name, date });
-        }
+        TimeSeriesData::load_files_from(data_folder_path, source, index_calculator, max_active_items, false)
+    }
+
+    /// Same as load, but files that cannot be parsed are quarantined instead of failing the whole load.
+    /// Quarantined files are listed by get_problems and never read again.
+    pub fn load_with_recovery(data_folder_path: String, source: Box<dyn DatedSource<T>>,
+                              index_calculator: fn(u64) -> u64, max_active_items: usize)
+        -> Result<TimeSeriesData<T>, Error> {
+        TimeSeriesData::load_files_from(data_folder_path, source, index_calculator, max_active_items, true)
+    }
+
+    fn load_files_from(data_folder_path: String, source: Box<dyn DatedSource<T>>,
+                       index_calculator: fn(u64) -> u64, max_active_items: usize, recover: bool)
+        -> Result<TimeSeriesData<T>, Error> {
+        let (file_map, problems) = list_files(&data_folder_path, source.as_ref(), index_calculator, recover)?;
+        let mut data = TimeSeriesData::from_files(data_folder_path, source, file_map, max_active_items, recover)?;
+        data.problems.splice(0..0, problems);
+        Ok(data)
+    }
+
+    /// Loads items from files grouped by key, see list_files.
+    pub fn from_files(data_folder_path: String, source: Box<dyn DatedSource<T>>,
+                      file_map: FileMap, max_active_items: usize, recover: bool)
+        -> Result<TimeSeriesData<T>, Error> {
         let mut data = TimeSeriesData::new(data_folder_path, source, max_active_items);
         for (key, files) in file_map {
-            data.load_files(key, files)?;
+            if recover {
+                data.load_files_recovering(key, files)?;
+            } else {
+                data.load_files(key, files)?;
+            }
         }
         Ok(data)
     }
-    
+
     pub fn new(data_folder_path: String, source: Box<dyn DatedSource<T>>, max_active_items: usize) -> TimeSeriesData<T> {
         TimeSeriesData{source: Mutex::new(source), data_folder_path, max_active_items,
-            active_items: AtomicUsize::new(0), map: BTreeMap::new(), modified: Mutex::new(HashSet::new()),
-            head: Mutex::new(None), tail: Mutex::new(None)}
+            active_items: AtomicUsize::new(0), map: RwLock::new(BTreeMap::new()), modified: Mutex::new(HashSet::new()),
+            lru: Mutex::new(LruList::default()), problems: Vec::new(), stats: CacheStats::default(),
+            access_counts: Mutex::new(HashMap::new()), versions: Mutex::new(HashMap::new())}
     }
 
     pub fn init(data_folder_path: String, source: Box<dyn DatedSource<T>>,
@@ -93,132 +206,387 @@ impl<'a, T> TimeSeriesData<T> {
         for file in get_file_list(data_folder_path.clone())? {
             let date = source.parse_date(&file)?;
             let key = index_calculator(date);
-            map.insert(key, Mutex::new(DataHolder::empty(key)));
+            map.insert(key, Mutex::new(DataHolder::empty()));
         }
         Ok(TimeSeriesData{source: Mutex::new(source), data_folder_path, max_active_items,
-            active_items: AtomicUsize::new(0), map, modified: Mutex::new(HashSet::new()),
-            head: Mutex::new(None), tail: Mutex::new(None)})
+            active_items: AtomicUsize::new(0), map: RwLock::new(map), modified: Mutex::new(HashSet::new()),
+            lru: Mutex::new(LruList::default()), problems: Vec::new(), stats: CacheStats::default(),
+            access_counts: Mutex::new(HashMap::new()), versions: Mutex::new(HashMap::new())})
     }
 
     fn load_files(&mut self, key: u64, files: Vec<FileWithDate>) -> Result<(), Error> {
+        let started = Instant::now();
         let v = self.source.lock().unwrap().load(files)?;
+        log_if_slow(&format!("load of month {}", key), started);
         self.add(key, v, false)
     }
-    
-    pub fn add(&mut self, key: u64, v: T, add_to_modified: bool) -> Result<(), Error> {
-        self.cleanup()?;
-        let h = self.add_to_lru(key, v);
-        self.map.insert(key, h);
+
+    fn load_files_recovering(&mut self, key: u64, files: Vec<FileWithDate>) -> Result<(), Error> {
+        let loaded = self.source.lock().unwrap().load(files.clone());
+        if let Ok(v) = loaded {
+            return self.add(key, v, false);
+        }
+        let mut good = Vec::new();
+        for file in files {
+            let loaded = self.source.lock().unwrap().load(vec![file.clone()]);
+            match loaded {
+                Ok(_) => good.push(file),
+                Err(e) => self.problems.push(LoadProblem{file: file.name, message: e.to_string()})
+            }
+        }
+        if good.is_empty() {
+            return Ok(());
+        }
+        self.load_files(key, good)
+    }
+
+    /// Quarantined files are never removed, they are left for the user to fix.
+    fn prepare_save(&self, source: &MutexGuard<Box<dyn DatedSource<T>>>, data: &T, key: u64) -> Result<SaveBatch, Error> {
+        let started = Instant::now();
+        let mut batch = source.prepare_save(data, &self.data_folder_path, key)?;
+        log_if_slow(&format!("save of month {}", key), started);
+        batch.obsolete_files.retain(|f|!self.problems.iter().any(|p|&p.file == f));
+        batch.obsolete_folders.retain(|d|!self.problems.iter().any(|p|p.file.starts_with(d.as_str())));
+        Ok(batch)
+    }
+
+    /// Saves all modified items, returns written batch.
+    pub fn flush(&self, parallel: bool) -> Result<SaveBatch, Error> {
+        let map = self.map.read().unwrap();
+        let mut modified = self.modified.lock().unwrap();
+        let items: Vec<_> = modified.iter()
+            .filter_map(|key|map.get(key).and_then(|h|h.lock().unwrap().data.clone()).map(|d|(*key, d)))
+            .collect();
+        let mut batch = SaveBatch::default();
+        let source = self.source.lock().unwrap();
+        let started = Instant::now();
+        if !modified.is_empty() {
+            trace(&format!("flush of {} months", modified.len()));
+        }
+        for (key, data) in items {
+            batch.append(self.prepare_save(&source, data.lock().unwrap().deref(), key)?);
+        }
+        batch.write(parallel)?;
+        log_if_slow(&format!("flush of {} months", modified.len()), started);
+        modified.clear();
+        Ok(batch)
+    }
+
+    pub fn get_problems(&self) -> &[LoadProblem] {
+        &self.problems
+    }
+
+    fn is_quarantined(&self, file: &FileWithDate) -> bool {
+        self.problems.iter().any(|p|p.file == file.name)
+    }
+
+    pub fn add(&self, key: u64, v: T, add_to_modified: bool) -> Result<(), Error> {
+        self.cleanup(&self.map.read().unwrap())?;
+        let mut map = self.map.write().unwrap();
+        let mut lru = self.lru.lock().unwrap();
+        if map.get(&key).is_some_and(|h|h.lock().unwrap().data.is_some()) {
+            self.detach(&map, &mut lru, key);
+            self.active_items.fetch_sub(1, Ordering::Relaxed);
+        }
+        let mut h = DataHolder::new(v, lru.head);
+        self.attach(&map, &mut lru, key, &mut h);
+        map.insert(key, Mutex::new(h));
+        drop(lru);
         if add_to_modified {
             self.modified.lock().unwrap().insert(key);
+            self.bump_version(key);
         }
         Ok(())
     }
-    
-    fn add_to_lru(&self, key: u64, v: T) -> Mutex<DataHolder<T>> {
-        let h = Mutex::new(DataHolder::new(key, v, self.head.lock().unwrap().clone()));
-        self.attach(key);
-        h
-    }
-    
-    fn attach(&self, key: u64) {
-        if let Some(hh) = self.head.lock().unwrap().as_ref() {
-            self.map.get(hh).unwrap().lock().unwrap().prev = Some(key);
+
+    /// Puts item to the head of the list, holder is passed as it is locked by the caller or not in the map yet.
+    fn attach(&self, map: &ItemMap<T>, lru: &mut LruList, key: u64, holder: &mut DataHolder<T>) {
+        holder.prev = None;
+        holder.next = lru.head;
+        if let Some(hh) = lru.head {
+            map.get(&hh).unwrap().lock().unwrap().prev = Some(key);
         } else {
-            _ = self.tail.lock().unwrap().insert(key);
+            lru.tail = Some(key);
         }
-        _ = self.head.lock().unwrap().insert(key);
+        lru.head = Some(key);
         self.active_items.fetch_add(1, Ordering::Relaxed);
     }
-    
-    fn cleanup(&self) -> Result<(), Error> {
+
+    fn cleanup(&self, map: &ItemMap<T>) -> Result<(), Error> {
         while self.active_items.load(Ordering::Relaxed) >= self.max_active_items {
-            self.remove_by_lru()?;
+            if !self.remove_by_lru(map)? {
+                break;
+            }
         }
         Ok(())
     }
-    
-    fn remove_by_lru(&self) -> Result<(), Error> {
-        let lock = self.tail.lock().unwrap();
-        if let Some(h) = lock.as_ref() {
-            let mut l = self.modified.lock().unwrap(); 
-            if l.contains(h) {
-                self.source.lock().unwrap().save(self.map.get(&h).unwrap().lock().unwrap().data.as_ref().unwrap().lock().unwrap().deref(),
-                                                 &self.data_folder_path, *h)?;
-                l.remove(h);
+
+    /// Evicts the least recently used item that is not pinned, returns false when all items are pinned.
+    fn remove_by_lru(&self, map: &ItemMap<T>) -> Result<bool, Error> {
+        let mut lru = self.lru.lock().unwrap();
+        let mut l = self.modified.lock().unwrap();
+        let mut candidate = lru.tail;
+        while let Some(h) = candidate {
+            let mut holder = map.get(&h).unwrap().lock().unwrap();
+            if holder.is_pinned() {
+                candidate = holder.prev;
+                continue;
             }
-            let mut data = self.map.get(h).unwrap().lock().unwrap();
-            data.data = None;
-            drop(data);
+            if l.contains(&h) {
+                trace(&format!("save of evicted month {}", h));
+                let started = Instant::now();
+                let source = self.source.lock().unwrap();
+                self.prepare_save(&source, holder.data.as_ref().unwrap().lock().unwrap().deref(), h)?.write(false)?;
+                log_if_slow(&format!("write of evicted month {}", h), started);
+                l.remove(&h);
+            }
+            holder.data = None;
+            drop(holder);
+            drop(l);
             self.active_items.fetch_sub(1, Ordering::Relaxed);
-            self.detach(*h, lock);
+            self.detach(map, &mut lru, h);
+            return Ok(true);
         }
-        Ok(())
+        Ok(false)
     }
 
-    fn detach(&self, idx: u64, mut l: MutexGuard<Option<u64>>) {
-        let data = self.map.get(&idx).unwrap().lock().unwrap();
-        if let Some(next) = data.next {
-            self.map.get(&next).unwrap().lock().unwrap().prev = data.prev;
+    fn detach(&self, map: &ItemMap<T>, lru: &mut LruList, idx: u64) {
+        let data = map.get(&idx).unwrap().lock().unwrap();
+        let (prev, next) = (data.prev, data.next);
+        drop(data);
+        if let Some(next) = next {
+            map.get(&next).unwrap().lock().unwrap().prev = prev;
         } else {
-            *l = data.prev;
+            lru.tail = prev;
         }
-        if let Some(prev) = data.prev {
-            self.map.get(&prev).unwrap().lock().unwrap().next = data.next;
+        if let Some(prev) = prev {
+            map.get(&prev).unwrap().lock().unwrap().next = next;
         } else {
-            *self.head.lock().unwrap() = data.next;
+            lru.head = next;
         }
     }
-    
-    pub fn get(&self, idx: u64) -> Result<Option<Rc<Mutex<T>>>, Error> {
-        if let Some((real_idx, d)) = self.map.range(..=idx).last() {
-            let v = self.get_t(*real_idx, d)?;
+
+    pub fn get(&self, idx: u64) -> Result<Option<Arc<Mutex<T>>>, Error> {
+        let map = self.map.read().unwrap();
+        if let Some((real_idx, d)) = map.range(..=idx).last() {
+            let v = self.get_t(&map, *real_idx, d)?;
             Ok(Some(v))
         } else {
             Ok(None)
         }
     }
-    
-    pub fn get_range(&self, from: u64, to: u64) -> Result<Vec<(u64, Rc<Mutex<T>>)>, Error> {
+
+    /// Returns the key of the item that get(idx) would return.
+    pub fn get_key(&self, idx: u64) -> Option<u64> {
+        self.map.read().unwrap().range(..=idx).next_back().map(|(k, _)|*k)
+    }
+
+    /// Drops the item without saving it.
+    pub fn remove(&self, key: u64) {
+        let mut map = self.map.write().unwrap();
+        let loaded = match map.get(&key) {
+            Some(h) => h.lock().unwrap().data.is_some(),
+            None => return
+        };
+        if loaded {
+            self.detach(&map, &mut self.lru.lock().unwrap(), key);
+            self.active_items.fetch_sub(1, Ordering::Relaxed);
+        }
+        map.remove(&key);
+        self.modified.lock().unwrap().remove(&key);
+        self.bump_version(key);
+    }
+
+    pub fn first_key(&self) -> Option<u64> {
+        self.map.read().unwrap().keys().next().copied()
+    }
+
+    /// Keys of items changed since the last flush.
+    pub fn get_modified(&self) -> Vec<u64> {
+        self.modified.lock().unwrap().iter().copied().collect()
+    }
+
+    /// Has to be called while the changed item is still referenced, so it is not evicted unsaved,
+    /// but after its lock is released, as flush locks items while holding the modified set.
+    pub fn mark_modified(&self, key: u64) {
+        self.modified.lock().unwrap().insert(key);
+        self.bump_version(key);
+    }
+
+    fn bump_version(&self, key: u64) {
+        *self.versions.lock().unwrap().entry(key).or_default() += 1;
+    }
+
+    /// Version of the item, 0 when it was never changed.
+    pub fn get_version(&self, key: u64) -> u64 {
+        self.versions.lock().unwrap().get(&key).copied().unwrap_or(0)
+    }
+
+    pub fn get_versions(&self) -> HashMap<u64, u64> {
+        self.versions.lock().unwrap().clone()
+    }
+
+    pub fn get_range(&self, from: u64, to: u64) -> Result<DataRange<T>, Error> {
+        let map = self.map.read().unwrap();
         let mut result = Vec::new();
-        for (pk, d) in self.map.range(from..=to) {
+        for (pk, d) in map.range(from..=to) {
             let k = *pk;
-            let t = self.get_t(k, d)?;
+            let t = self.get_t(&map, k, d)?;
             result.push((k, t));
         }
         Ok(result)
     }
 
-    fn move_to_front(&self, idx: u64) {
-        self.detach(idx, self.tail.lock().unwrap());
-        let mut head = self.head.lock().unwrap();
-        let head_idx = head.clone();
-        let mut v = self.map.get(&idx).unwrap().lock().unwrap();
-        v.next = head_idx;
-        v.prev = None;
-        let _ = head.insert(idx);
-        let _ = self.map.get(&head_idx.unwrap()).unwrap().lock().unwrap().prev.insert(idx);
+    /// Calls f for items of the range without changing the cache: loaded items keep their place in the
+    /// LRU list, others are loaded from the source and dropped after the call, so bulk scans don't evict
+    /// the working set. Cache statistics and access counts are not updated.
+    pub fn scan_range(&self, from: u64, to: u64, mut f: impl FnMut(u64, &Arc<Mutex<T>>) -> Result<(), Error>)
+        -> Result<(), Error> {
+        self.scan_until(from, to, |key, t|f(key, t).map(|_|false))
     }
-    
-    fn get_t(&self, key: u64, d: &Mutex<DataHolder<T>>) -> Result<Rc<Mutex<T>>, Error> {
+
+    /// Same as scan_range, but stops at the first item for which f returns true.
+    pub fn scan_until(&self, from: u64, to: u64, mut f: impl FnMut(u64, &Arc<Mutex<T>>) -> Result<bool, Error>)
+        -> Result<(), Error> {
+        let keys: Vec<u64> = self.map.read().unwrap().range(from..=to).map(|(k, _)|*k).collect();
+        for key in keys {
+            let t = {
+                let map = self.map.read().unwrap();
+                let Some(d) = map.get(&key) else {
+                    continue;
+                };
+                // locked while loading, so the item can't be loaded and changed in the cache meanwhile
+                let v = d.lock().unwrap();
+                match v.data.clone() {
+                    Some(t) => t,
+                    None => {
+                        let mut l = self.source.lock().unwrap();
+                        let files = l.get_files(&self.data_folder_path, key)?.into_iter()
+                            .filter(|f|!self.is_quarantined(f))
+                            .collect();
+                        trace(&format!("uncached load of month {}", key));
+                        Arc::new(Mutex::new(l.load(files)?))
+                    }
+                }
+            };
+            if f(key, &t)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of items in the range, loaded or not.
+    pub fn count_range(&self, from: u64, to: u64) -> usize {
+        self.map.read().unwrap().range(from..=to).count()
+    }
+
+    pub fn get_max_active_items(&self) -> usize {
+        self.max_active_items
+    }
+
+    fn move_to_front(&self, map: &ItemMap<T>, idx: u64) {
+        let mut lru = self.lru.lock().unwrap();
+        if lru.head == Some(idx) {
+            return;
+        }
+        self.detach(map, &mut lru, idx);
+        self.active_items.fetch_sub(1, Ordering::Relaxed);
+        let mut v = map.get(&idx).unwrap().lock().unwrap();
+        self.attach(map, &mut lru, idx, &mut v);
+    }
+
+    fn get_t(&self, map: &ItemMap<T>, key: u64, d: &Mutex<DataHolder<T>>) -> Result<Arc<Mutex<T>>, Error> {
+        *self.access_counts.lock().unwrap().entry(key).or_default() += 1;
         let mut v = d.lock().unwrap();
         if let Some(d) = v.data.clone() {
             drop(v);
-            self.move_to_front(key);
+            self.stats.hit();
+            // pinned by the clone, so the item can't be evicted before it is moved
+            self.move_to_front(map, key);
             return Ok(d);
         }
-        self.cleanup()?;
+        self.stats.miss();
+        self.cleanup(map)?;
         let mut l = self.source.lock().unwrap();
-        let files = l.get_files(&self.data_folder_path, key)?;
+        let files = l.get_files(&self.data_folder_path, key)?.into_iter()
+            .filter(|f|!self.is_quarantined(f))
+            .collect();
+        trace(&format!("load of month {}", key));
+        let started = Instant::now();
         let t = l.load(files)?;
-        v.set(t, self.head.lock().unwrap().clone());
-        self.attach(key);
+        drop(l);
+        log_if_slow(&format!("load of month {}", key), started);
+        let mut lru = self.lru.lock().unwrap();
+        v.set(t, lru.head);
+        self.attach(map, &mut lru, key, &mut v);
         Ok(v.data.as_ref().unwrap().clone())
     }
-    
+
     pub fn get_active_items(&self) -> usize {
         self.active_items.load(Ordering::Relaxed)
     }
+
+    pub fn get_stats(&self) -> &CacheStats {
+        &self.stats
+    }
+
+    /// Accesses of every item since the previous call.
+    pub fn take_access_counts(&self) -> HashMap<u64, u64> {
+        std::mem::take(&mut *self.access_counts.lock().unwrap())
+    }
+
+    /// Loads items ordered from the most important one, so it ends at the head of the LRU list.
+    /// Keys without items are skipped.
+    pub fn warm(&self, keys: &[u64]) -> Result<(), Error> {
+        for key in keys.iter().rev() {
+            self.get_range(*key, *key)?;
+        }
+        Ok(())
+    }
+}
+
+/// Files of the data folder grouped by item key. Files which date can't be parsed fail the listing,
+/// or are returned as problems when recover is set.
+pub fn list_files<T>(data_folder_path: &str, source: &dyn DatedSource<T>, index_calculator: fn(u64) -> u64,
+                     recover: bool) -> Result<(FileMap, Vec<LoadProblem>), Error> {
+    let mut file_map = HashMap::new();
+    let mut problems = Vec::new();
+    for file in get_file_list(data_folder_path.to_string())? {
+        let date = match source.parse_date(&file) {
+            Ok(date) => date,
+            Err(e) if recover => {
+                problems.push(LoadProblem{file: file.name, message: e.to_string()});
+                continue;
+            }
+            Err(e) => return Err(e)
+        };
+        file_map.entry(index_calculator(date)).or_insert(Vec::new())
+            .push(FileWithDate { name: file.name, date });
+    }
+    Ok((file_map, problems))
+}
+
+/// Date folders (dates/yyyymmdd) of the month with given key (yyyymm).
+pub fn month_folders(data_folder_path: &str, key: u64) -> Result<Vec<(u64, String)>, Error> {
+    let entries = match fs::read_dir(data_folder_path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e)
+    };
+    let mut result = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        if let Some(date) = entry.file_name().to_str().and_then(|n|n.parse::<u64>().ok()) {
+            if date / 100 == key {
+                result.push((date, entry.path().to_string_lossy().to_string()));
+            }
+        }
+    }
+    Ok(result)
 }
 
 pub struct FileInfo {
@@ -255,45 +623,52 @@ fn get_file_list(data_folder_path: String) -> Result<Vec<FileInfo>, Error> {
 #[cfg(test)]
 mod tests {
     use std::io::Error;
-    use crate::core::time_series_data::{DatedSource, FileInfo, FileWithDate, TimeSeriesData};
+    use std::sync::Arc;
+    use std::thread;
+    use crate::core::time_series_data::{DatedSource, FileInfo, FileWithDate, SaveBatch, TimeSeriesData};
 
     struct TestData{}
     struct TestDataSource{}
 
     impl DatedSource<TestData> for TestDataSource {
-        fn load(&mut self, files: Vec<FileWithDate>) -> Result<TestData, Error> {
+        fn load(&mut self, _files: Vec<FileWithDate>) -> Result<TestData, Error> {
             Ok(TestData{})
         }
 
-        fn parse_date(&self, info: &FileInfo) -> Result<u64, Error> {
+        fn parse_date(&self, _info: &FileInfo) -> Result<u64, Error> {
             todo!()
         }
 
-        fn save(&self, data: &TestData, data_folder_path: &String, date: u64) -> Result<(), Error> {
+        fn prepare_save(&self, _data: &TestData, _data_folder_path: &str, _key: u64) -> Result<SaveBatch, Error> {
             todo!()
         }
 
-        fn get_files(&self, data_folder_path: &String, date: u64) -> Result<Vec<FileWithDate>, Error> {
+        fn get_files(&self, _data_folder_path: &str, _date: u64) -> Result<Vec<FileWithDate>, Error> {
             Ok(Vec::new())
         }
+
+        fn fork(&self) -> Box<dyn DatedSource<TestData>> {
+            Box::new(TestDataSource{})
+        }
     }
 
     #[test]
     fn test_lru_list() -> Result<(), Error> {
-        let mut data = TimeSeriesData::new("".to_string(), Box::new(TestDataSource{}), 500);
+        let data = TimeSeriesData::new("".to_string(), Box::new(TestDataSource{}), 500);
         for i in 0..3 {
             data.add(i, TestData{}, false)?;
         }
-        let head = data.head.lock().unwrap().unwrap();
+        let head = data.lru.lock().unwrap().head.unwrap();
         assert_eq!(head, 2);
-        assert_eq!(data.tail.lock().unwrap().unwrap(), 0);
-        let item = data.map.get(&head).unwrap().lock().unwrap();
+        assert_eq!(data.lru.lock().unwrap().tail.unwrap(), 0);
+        let map = data.map.read().unwrap();
+        let item = map.get(&head).unwrap().lock().unwrap();
         assert_eq!(item.prev, None);
         assert_eq!(item.next, Some(1));
-        let item = data.map.get(&1).unwrap().lock().unwrap();
+        let item = map.get(&1).unwrap().lock().unwrap();
         assert_eq!(item.prev, Some(2));
         assert_eq!(item.next, Some(0));
-        let item = data.map.get(&0).unwrap().lock().unwrap();
+        let item = map.get(&0).unwrap().lock().unwrap();
         assert_eq!(item.prev, Some(1));
         assert_eq!(item.next, None);
         Ok(())
@@ -301,40 +676,43 @@ mod tests {
 
     #[test]
     fn test_lru_expire_and_move_to_front() -> Result<(), Error> {
-        let mut data = TimeSeriesData::new("".to_string(), Box::new(TestDataSource{}), 500);
+        let data = TimeSeriesData::new("".to_string(), Box::new(TestDataSource{}), 500);
         for i in 0..1000 {
             data.add(i, TestData{}, false)?;
         }
-        let head = data.head.lock().unwrap().unwrap();
+        let head = data.lru.lock().unwrap().head.unwrap();
         assert_eq!(head, 999);
-        assert_eq!(data.tail.lock().unwrap().unwrap(), 500);
+        assert_eq!(data.lru.lock().unwrap().tail.unwrap(), 500);
         assert_eq!(data.get_active_items(), 500);
 
-        let item = data.map.get(&500).unwrap().lock().unwrap();
+        let map = data.map.read().unwrap();
+        let item = map.get(&500).unwrap().lock().unwrap();
         assert_eq!(item.prev, Some(501));
         assert_eq!(item.next, None);
         drop(item);
-        let item = data.map.get(&999).unwrap().lock().unwrap();
+        let item = map.get(&999).unwrap().lock().unwrap();
         assert_eq!(item.prev, None);
         assert_eq!(item.next, Some(998));
         drop(item);
+        drop(map);
         
         let _ = data.get(501)?;
 
-        let head = data.head.lock().unwrap().unwrap();
+        let head = data.lru.lock().unwrap().head.unwrap();
         assert_eq!(head, 501);
-        assert_eq!(data.tail.lock().unwrap().unwrap(), 500);
-        let item = data.map.get(&head).unwrap().lock().unwrap();
+        assert_eq!(data.lru.lock().unwrap().tail.unwrap(), 500);
+        let map = data.map.read().unwrap();
+        let item = map.get(&head).unwrap().lock().unwrap();
         assert_eq!(item.prev, None);
         assert_eq!(item.next, Some(999));
-        let item = data.map.get(&998).unwrap().lock().unwrap();
+        let item = map.get(&998).unwrap().lock().unwrap();
         assert_eq!(item.prev, Some(999));
         assert_eq!(item.next, Some(997));
 
-        let item = data.map.get(&500).unwrap().lock().unwrap();
+        let item = map.get(&500).unwrap().lock().unwrap();
         assert_eq!(item.prev, Some(502));
         assert_eq!(item.next, None);
-        let item = data.map.get(&502).unwrap().lock().unwrap();
+        let item = map.get(&502).unwrap().lock().unwrap();
         assert_eq!(item.prev, Some(503));
         assert_eq!(item.next, Some(500));
         
@@ -343,17 +721,62 @@ mod tests {
 
     #[test]
     fn test_lru_load() -> Result<(), Error> {
-        let mut data = TimeSeriesData::new("".to_string(), Box::new(TestDataSource {}), 500);
+        let data = TimeSeriesData::new("".to_string(), Box::new(TestDataSource {}), 500);
         for i in 0..1000 {
             data.add(i, TestData {}, false)?;
         }
 
         let _ = data.get(499)?;
-        let head = data.head.lock().unwrap().unwrap();
+        let head = data.lru.lock().unwrap().head.unwrap();
         assert_eq!(head, 499);
-        assert_eq!(data.tail.lock().unwrap().unwrap(), 501);
+        assert_eq!(data.lru.lock().unwrap().tail.unwrap(), 501);
         assert_eq!(data.get_active_items(), 500);
         
         Ok(())
     }
+
+    #[test]
+    fn test_pinned_items_are_not_evicted() -> Result<(), Error> {
+        let data = TimeSeriesData::new("".to_string(), Box::new(TestDataSource {}), 2);
+        data.add(1, TestData {}, false)?;
+        data.add(2, TestData {}, false)?;
+        let pinned = data.get(1)?.unwrap();
+        data.add(3, TestData {}, false)?;
+        assert_eq!(data.lru.lock().unwrap().tail.unwrap(), 1);
+        assert!(data.map.read().unwrap().get(&2).unwrap().lock().unwrap().data.is_none());
+        assert!(Arc::ptr_eq(&pinned, &data.get(1)?.unwrap()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_range_keeps_cache() -> Result<(), Error> {
+        let data = TimeSeriesData::new("".to_string(), Box::new(TestDataSource {}), 2);
+        for i in 0..3 {
+            data.add(i, TestData {}, false)?;
+        }
+        let mut keys = Vec::new();
+        data.scan_range(0, 2, |k, _|{keys.push(k); Ok(())})?;
+        assert_eq!(keys, vec![0, 1, 2]);
+        assert_eq!(data.lru.lock().unwrap().head, Some(2));
+        assert_eq!(data.lru.lock().unwrap().tail, Some(1));
+        assert!(data.map.read().unwrap().get(&0).unwrap().lock().unwrap().data.is_none());
+        assert_eq!(data.get_stats().get_misses(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_access() -> Result<(), Error> {
+        let data = TimeSeriesData::new("".to_string(), Box::new(TestDataSource {}), 50);
+        data.add(0, TestData {}, false)?;
+        thread::scope(|s| {
+            let writer = s.spawn(||(1..200).try_for_each(|i|data.add(i, TestData {}, false)));
+            for i in 0..1000 {
+                data.get(i % 200)?;
+            }
+            writer.join().unwrap()
+        })?;
+        assert_eq!(data.get_range(0, 199)?.len(), 200);
+        assert!(data.get_active_items() <= 200);
+        Ok(())
+    }
 }
\ No newline at end of file
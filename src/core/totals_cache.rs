@@ -0,0 +1,152 @@
+//! Month totals persisted with the database. The watermark is the first month changed since totals
+//! were saved, on startup only this and later months are recalculated. Months changed outside of
+//! the database are found by fingerprints of their files.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use crate::entities::accounts::AccountId;
+use crate::verify::checksums::checksum;
+
+pub const TOTALS_FILE_NAME: &str = "totals.txt";
+pub const TOTALS_WATERMARK_FILE_NAME: &str = "totals_watermark.txt";
+
+pub struct SavedTotals {
+    /// modification time of dictionaries the totals were calculated with, nanoseconds since epoch
+    pub dictionaries_modified: u128,
+    /// fingerprint of files and opening balances of every month
+    pub months: BTreeMap<u64, (u64, HashMap<AccountId, i64>)>
+}
+
+impl SavedTotals {
+    /// First month which files were changed, added or removed since totals were saved.
+    pub fn first_changed(&self, fingerprints: &BTreeMap<u64, u64>) -> Option<u64> {
+        let changed = self.months.iter()
+            .find(|(month, (fingerprint, _))|fingerprints.get(month) != Some(fingerprint))
+            .map(|(month, _)|*month);
+        let added = fingerprints.keys().find(|month|!self.months.contains_key(month)).copied();
+        changed.into_iter().chain(added).min()
+    }
+}
+
+/// Fingerprints of names, sizes and modification times of files of every month in the dates folder.
+pub fn month_fingerprints(dates_folder_path: &str) -> Result<BTreeMap<u64, u64>, Error> {
+    let mut folders: Vec<(u64, fs::DirEntry)> = fs::read_dir(dates_folder_path)?
+        .filter_map(|e|e.ok())
+        .filter_map(|e|e.file_name().to_str().and_then(|n|n.parse().ok()).map(|d|(d, e)))
+        .collect();
+    folders.sort_by_key(|(d, _)|*d);
+    let mut data: BTreeMap<u64, Vec<u8>> = BTreeMap::new();
+    for (date, folder) in folders {
+        let mut files: Vec<_> = fs::read_dir(folder.path())?.filter_map(|e|e.ok()).collect();
+        files.sort_by_key(|e|e.file_name());
+        let month_data = data.entry(date / 100).or_default();
+        month_data.extend_from_slice(&date.to_le_bytes());
+        for file in files {
+            let metadata = file.metadata()?;
+            let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map(|d|d.as_nanos()).unwrap_or(0);
+            month_data.extend_from_slice(file.file_name().to_string_lossy().as_bytes());
+            month_data.extend_from_slice(&metadata.len().to_le_bytes());
+            month_data.extend_from_slice(&modified.to_le_bytes());
+        }
+    }
+    Ok(data.into_iter().map(|(month, d)|(month, checksum(&d))).collect())
+}
+
+/// First line is dictionaries modification time, then one line per month: "yyyymm fingerprint account:total ...".
+pub fn load_totals(data_folder_path: &str) -> Result<Option<SavedTotals>, Error> {
+    let text = match fs::read_to_string(Path::new(data_folder_path).join(TOTALS_FILE_NAME)) {
+        Ok(t) => t,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e)
+    };
+    let invalid = |line: &str|Error::new(ErrorKind::InvalidData, format!("invalid totals line {}", line));
+    let mut lines = text.lines();
+    let first = lines.next().unwrap_or_default();
+    let dictionaries_modified = first.parse().map_err(|_|invalid(first))?;
+    let mut months = BTreeMap::new();
+    for line in lines.filter(|l|!l.is_empty()) {
+        let mut parts = line.split(' ');
+        let month = parts.next().and_then(|m|m.parse().ok()).ok_or_else(||invalid(line))?;
+        let fingerprint = parts.next().and_then(|f|u64::from_str_radix(f, 16).ok()).ok_or_else(||invalid(line))?;
+        let totals = parts
+            .map(|p|p.split_once(':').and_then(|(a, t)|Some((AccountId(a.parse().ok()?), t.parse().ok()?))))
+            .collect::<Option<HashMap<AccountId, i64>>>()
+            .ok_or_else(||invalid(line))?;
+        months.insert(month, (fingerprint, totals));
+    }
+    Ok(Some(SavedTotals{dictionaries_modified, months}))
+}
+
+pub fn save_totals(data_folder_path: &str, totals: &SavedTotals) -> Result<(), Error> {
+    let mut text = format!("{}\n", totals.dictionaries_modified);
+    for (month, (fingerprint, month_totals)) in &totals.months {
+        let mut accounts: Vec<_> = month_totals.iter().collect();
+        accounts.sort();
+        text += &format!("{} {:016x}", month, fingerprint);
+        for (account, total) in accounts {
+            text += &format!(" {}:{}", account.0, total);
+        }
+        text += "\n";
+    }
+    let file_name = Path::new(data_folder_path).join(TOTALS_FILE_NAME);
+    let temp_name = file_name.with_extension("tmp");
+    fs::write(&temp_name, text)?;
+    fs::rename(temp_name, file_name)
+}
+
+pub fn load_watermark(data_folder_path: &str) -> Result<Option<u64>, Error> {
+    match fs::read_to_string(Path::new(data_folder_path).join(TOTALS_WATERMARK_FILE_NAME)) {
+        Ok(t) => t.trim().parse().map(Some)
+            .map_err(|_|Error::new(ErrorKind::InvalidData, format!("invalid totals watermark {}", t))),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e)
+    }
+}
+
+pub fn save_watermark(data_folder_path: &str, month: u64) -> Result<(), Error> {
+    fs::write(Path::new(data_folder_path).join(TOTALS_WATERMARK_FILE_NAME), month.to_string())
+}
+
+pub fn clear_watermark(data_folder_path: &str) -> Result<(), Error> {
+    match fs::remove_file(Path::new(data_folder_path).join(TOTALS_WATERMARK_FILE_NAME)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::Error;
+    use crate::core::totals_cache::{clear_watermark, load_totals, load_watermark, save_totals, save_watermark, SavedTotals};
+    use crate::entities::accounts::AccountId;
+
+    #[test]
+    fn test_save_load() -> Result<(), Error> {
+        let folder = temp_dir().join("had_test_totals").to_str().unwrap().to_string();
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder)?;
+        assert!(load_totals(&folder)?.is_none());
+        let months = BTreeMap::from([(202401, (1, HashMap::new())),
+                                     (202402, (2, HashMap::from([(AccountId(1), -5), (AccountId(2), 1000)])))]);
+        save_totals(&folder, &SavedTotals{dictionaries_modified: 123, months: months.clone()})?;
+        let saved = load_totals(&folder)?.unwrap();
+        assert_eq!(saved.dictionaries_modified, 123);
+        assert_eq!(saved.months, months);
+        assert_eq!(saved.first_changed(&BTreeMap::from([(202401, 1), (202402, 2)])), None);
+        assert_eq!(saved.first_changed(&BTreeMap::from([(202401, 1), (202402, 3)])), Some(202402));
+        assert_eq!(saved.first_changed(&BTreeMap::from([(202312, 1), (202401, 1), (202402, 2)])), Some(202312));
+        assert_eq!(saved.first_changed(&BTreeMap::from([(202402, 2)])), Some(202401));
+        save_watermark(&folder, 202402)?;
+        assert_eq!(load_watermark(&folder)?, Some(202402));
+        clear_watermark(&folder)?;
+        clear_watermark(&folder)?;
+        assert_eq!(load_watermark(&folder)?, None);
+        fs::remove_dir_all(&folder)
+    }
+}
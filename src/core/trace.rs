@@ -0,0 +1,93 @@
+//! Request ids for correlating server requests with storage activity. A span marks the current
+//! thread as serving a request, storage operations inside it are logged with the request id.
+//! Operations slower than the configured threshold are logged with or without a request.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+static NEXT_REQUEST: AtomicU64 = AtomicU64::new(0);
+/// in milliseconds, 0 disables the slow operation log
+static SLOW_THRESHOLD: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static REQUEST_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Counter makes the id unique within the process, current time across restarts.
+pub fn new_request_id() -> String {
+    let counter = NEXT_REQUEST.fetch_add(1, Ordering::Relaxed);
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d|d.as_secs()).unwrap_or(0);
+    format!("{:08x}-{:06x}", seconds as u32, counter)
+}
+
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.with(|id|id.borrow().clone())
+}
+
+/// Logs the event with the current request id, events outside of requests are not logged.
+pub fn trace(event: &str) {
+    if let Some(id) = current_request_id() {
+        println!("[{}] {}", id, event);
+    }
+}
+
+/// Threshold is process wide, None disables the slow operation log.
+pub fn set_slow_threshold(milliseconds: Option<u64>) {
+    SLOW_THRESHOLD.store(milliseconds.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Logs the operation if it took longer than the slow operation threshold.
+pub fn log_if_slow(operation: &str, started: Instant) {
+    let threshold = SLOW_THRESHOLD.load(Ordering::Relaxed);
+    let elapsed = started.elapsed().as_millis();
+    if threshold == 0 || elapsed <= threshold as u128 {
+        return;
+    }
+    match current_request_id() {
+        Some(id) => println!("[{}] slow {}: {} ms", id, operation, elapsed),
+        None => println!("slow {}: {} ms", operation, elapsed)
+    }
+}
+
+/// Active while alive, nested spans restore the outer request id on drop.
+pub struct RequestSpan {
+    name: String,
+    started: Instant,
+    previous: Option<String>
+}
+
+impl RequestSpan {
+    pub fn start(name: &str) -> RequestSpan {
+        let id = new_request_id();
+        let previous = REQUEST_ID.with(|current|current.replace(Some(id)));
+        trace(&format!("{} started", name));
+        RequestSpan{name: name.to_string(), started: Instant::now(), previous}
+    }
+}
+
+impl Drop for RequestSpan {
+    fn drop(&mut self) {
+        log_if_slow(&self.name, self.started);
+        REQUEST_ID.with(|current|*current.borrow_mut() = self.previous.take());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::trace::{current_request_id, RequestSpan};
+
+    #[test]
+    fn test_spans() {
+        assert_eq!(current_request_id(), None);
+        let outer = RequestSpan::start("outer");
+        let outer_id = current_request_id().unwrap();
+        {
+            let _inner = RequestSpan::start("inner");
+            assert_ne!(current_request_id(), Some(outer_id.clone()));
+        }
+        assert_eq!(current_request_id(), Some(outer_id));
+        drop(outer);
+        assert_eq!(current_request_id(), None);
+    }
+}
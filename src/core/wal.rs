@@ -0,0 +1,214 @@
+//! Write-ahead log of operation changes and checkpoints of the data folder. A checkpoint plus
+//! the log entries written after it restore the database to any moment.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Error, ErrorKind, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::entities::finance_operations::FinanceOperation;
+use crate::entities::subcategories::SubcategoryId;
+use crate::query::OperationQuery;
+use crate::verify::repair::Fixer;
+
+pub const WAL_FILE_NAME: &str = "wal.log";
+pub const CHECKPOINTS_FOLDER: &str = "checkpoints";
+
+#[derive(Serialize, Deserialize)]
+pub struct WalEntry {
+    /// unix time in milliseconds
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub change: WalChange
+}
+
+/// Change of stored operations, replayed by calling the same database method.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WalChange {
+    Operation(FinanceOperation),
+    Recategorize{query: OperationQuery, from: SubcategoryId, to: SubcategoryId},
+    Dedupe,
+    Repair{fixers: Vec<Fixer>},
+    MergeSubcategories{source: SubcategoryId, target: SubcategoryId},
+    SplitSubcategory{source: SubcategoryId, rules: Vec<(String, SubcategoryId)>, default: Option<SubcategoryId>}
+}
+
+pub fn to_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d|d.as_millis() as u64).unwrap_or(0)
+}
+
+/// Log of JSON lines, every line is synced to disk before the operation is applied.
+pub struct Wal {
+    file_name: String
+}
+
+impl Wal {
+    pub fn new(data_folder_path: &str) -> Wal {
+        Wal{file_name: data_folder_path.to_string() + "/" + WAL_FILE_NAME}
+    }
+
+    /// Operations are logged without copying, the line is read back as WalChange::Operation.
+    pub fn append(&self, time: SystemTime, operation: &FinanceOperation) -> Result<(), Error> {
+        self.write_line(serde_json::to_vec(&WalEntryRef{timestamp: to_millis(time), operation})?)
+    }
+
+    pub fn append_change(&self, time: SystemTime, change: WalChange) -> Result<(), Error> {
+        self.write_line(serde_json::to_vec(&WalEntry{timestamp: to_millis(time), change})?)
+    }
+
+    fn write_line(&self, mut line: Vec<u8>) -> Result<(), Error> {
+        line.push(b'\n');
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.file_name)?;
+        file.write_all(&line)?;
+        file.sync_data()
+    }
+
+    /// Reads all entries, an incomplete last line left by a crash is ignored.
+    pub fn read(&self) -> Result<Vec<WalEntry>, Error> {
+        let file = match File::open(&self.file_name) {
+            Ok(f) => f,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e)
+        };
+        let lines: Vec<String> = BufReader::new(file).lines().collect::<Result<_, _>>()?;
+        let mut result = Vec::with_capacity(lines.len());
+        for (i, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(entry) => result.push(entry),
+                Err(_) if i == lines.len() - 1 => break,
+                Err(e) => return Err(Error::new(ErrorKind::InvalidData,
+                                                format!("{} line {}: {}", self.file_name, i + 1, e)))
+            }
+        }
+        Ok(result)
+    }
+
+    /// Drops entries written at or before given unix time in milliseconds, returns number of dropped entries.
+    pub fn truncate(&self, timestamp: u64) -> Result<usize, Error> {
+        let entries = self.read()?;
+        let kept: Vec<&WalEntry> = entries.iter().filter(|e|e.timestamp > timestamp).collect();
+        if kept.len() == entries.len() {
+            return Ok(0);
+        }
+        let mut data = Vec::new();
+        for entry in &kept {
+            data.extend(serde_json::to_vec(entry)?);
+            data.push(b'\n');
+        }
+        let temp_name = self.file_name.clone() + ".tmp";
+        fs::write(&temp_name, data)?;
+        fs::rename(temp_name, &self.file_name)?;
+        Ok(entries.len() - kept.len())
+    }
+}
+
+#[derive(Serialize)]
+struct WalEntryRef<'a> {
+    timestamp: u64,
+    operation: &'a FinanceOperation
+}
+
+/// Copies folder recursively, top level entries listed in skip are not copied.
+pub fn copy_folder(source: &Path, destination: &Path, skip: &[&str]) -> Result<(), Error> {
+    fs::create_dir_all(destination)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name.to_str().is_some_and(|n|skip.contains(&n)) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            copy_folder(&entry.path(), &destination.join(&name), &[])?;
+        } else {
+            fs::copy(entry.path(), destination.join(&name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns timestamps of all checkpoints in ascending order.
+pub fn list_checkpoints(data_folder_path: &str) -> Result<Vec<u64>, Error> {
+    let folder = Path::new(data_folder_path).join(CHECKPOINTS_FOLDER);
+    let mut result: Vec<u64> = match fs::read_dir(folder) {
+        Ok(entries) => entries
+            .filter_map(|e|e.ok())
+            .filter_map(|e|e.file_name().to_str().and_then(|n|n.parse().ok()))
+            .collect(),
+        Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e)
+    };
+    result.sort();
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::{Error, Write};
+    use std::thread;
+    use std::time::{Duration, UNIX_EPOCH};
+    use crate::core::wal::{Wal, WalChange};
+    use crate::db::HomeAccountingDB;
+    use crate::entities::accounts::AccountId;
+    use crate::entities::finance_operations::FinanceOperation;
+    use crate::entities::subcategories::SubcategoryId;
+    use crate::json_db_config::JsonDBConfiguration;
+
+    #[test]
+    fn test_append_read() -> Result<(), Error> {
+        let folder = temp_dir().join("had_test_wal").to_str().unwrap().to_string();
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder)?;
+        let wal = Wal::new(&folder);
+        assert!(wal.read()?.is_empty());
+        let op = FinanceOperation::new(20240105, AccountId(1), SubcategoryId(2), Some(1500), 1234, Vec::new());
+        wal.append(UNIX_EPOCH + Duration::from_millis(5000), &op)?;
+        wal.append(UNIX_EPOCH + Duration::from_millis(6000), &op)?;
+        wal.append_change(UNIX_EPOCH + Duration::from_millis(7000),
+                          WalChange::MergeSubcategories{source: SubcategoryId(2), target: SubcategoryId(3)})?;
+        fs::OpenOptions::new().append(true).open(folder.clone() + "/wal.log")?.write_all(b"{\"timest")?;
+        let entries = wal.read()?;
+        assert_eq!(entries.len(), 3);
+        assert!(matches!(entries[2].change, WalChange::MergeSubcategories{source: SubcategoryId(2), ..}));
+        assert_eq!(entries[1].timestamp, 6000);
+        assert!(matches!(&entries[0].change, WalChange::Operation(o) if o.is_duplicate_of(&op)));
+        assert_eq!(wal.truncate(5000)?, 1);
+        assert_eq!(wal.truncate(5000)?, 0);
+        assert_eq!(wal.read()?.iter().map(|e|e.timestamp).collect::<Vec<_>>(), vec![6000, 7000]);
+        fs::remove_dir_all(&folder)
+    }
+
+    #[test]
+    fn test_restore_replays_changes() -> Result<(), Error> {
+        let folder = temp_dir().join("had_test_wal_restore").to_str().unwrap().to_string();
+        let (data, target) = (folder.clone() + "/data", folder.clone() + "/restored");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&data)?;
+        fs::write(data.clone() + "/accounts.json",
+                  r#"[{"id": 1, "name": "Cash", "valutaCode": "UAH", "activeTo": null, "isCash": true}]"#)?;
+        fs::write(data.clone() + "/categories.json", r#"[{"id": 1, "name": "Food"}]"#)?;
+        fs::write(data.clone() + "/subcategories.json",
+                  r#"[{"id": 1, "name": "Groceries", "code": null, "operationCodeId": "EXPN", "categoryId": 1},
+                      {"id": 2, "name": "Food", "code": null, "operationCodeId": "EXPN", "categoryId": 1}]"#)?;
+        let mut db = HomeAccountingDB::new(data.clone(), Box::new(JsonDBConfiguration::new()), 100)?;
+        db.enable_wal();
+        let op = ||FinanceOperation::new(20240105, AccountId(1), SubcategoryId(1), None, 1000, Vec::new());
+        db.add_operation(op())?;
+        db.create_checkpoint()?;
+        assert!(Wal::new(&data).read()?.is_empty());
+        thread::sleep(Duration::from_millis(2));
+        db.add_operation(op())?;
+        assert_eq!(db.dedupe()?, 1);
+        assert_eq!(db.merge_subcategories(SubcategoryId(1), SubcategoryId(2))?, 1);
+        db.flush(false)?;
+        assert_eq!(HomeAccountingDB::restore(&data, target.clone(), u64::MAX, Box::new(JsonDBConfiguration::new()), 100)?, 3);
+        let restored = HomeAccountingDB::load(target, Box::new(JsonDBConfiguration::new()), 100)?;
+        let operations = restored.get_operations(0, u64::MAX)?;
+        assert_eq!(operations.len(), 1);
+        assert_eq!(operations[0].get_subcategory(), SubcategoryId(2));
+        assert!(restored.get_subcategories().get(SubcategoryId(1)).is_err());
+        fs::remove_dir_all(&folder)
+    }
+}
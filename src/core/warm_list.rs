@@ -0,0 +1,63 @@
+//! Month access counts accumulated across sessions. Months accessed most are loaded into the cache
+//! on startup, so the usual working set stays resident when the cache is smaller than the database.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+pub const WARM_LIST_FILE_NAME: &str = "warm_list.txt";
+
+/// One "yyyymm count" line per month, missing file means no accesses were recorded.
+pub fn load_access_counts(data_folder_path: &str) -> Result<HashMap<u64, u64>, Error> {
+    let text = match fs::read_to_string(Path::new(data_folder_path).join(WARM_LIST_FILE_NAME)) {
+        Ok(t) => t,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e)
+    };
+    text.lines()
+        .filter(|l|!l.is_empty())
+        .map(|line|line.split_once(' ').and_then(|(m, c)|Some((m.parse().ok()?, c.parse().ok()?)))
+            .ok_or(Error::new(ErrorKind::InvalidData, format!("invalid warm list line {}", line))))
+        .collect()
+}
+
+/// Months are written from the most accessed one.
+pub fn save_access_counts(data_folder_path: &str, counts: &HashMap<u64, u64>) -> Result<(), Error> {
+    let text: String = top_months(counts, counts.len()).iter()
+        .map(|month|format!("{} {}\n", month, counts[month]))
+        .collect();
+    let file_name = Path::new(data_folder_path).join(WARM_LIST_FILE_NAME);
+    let temp_name = file_name.with_extension("tmp");
+    fs::write(&temp_name, text)?;
+    fs::rename(temp_name, file_name)
+}
+
+/// Up to count most accessed months, later months first when counts are equal.
+pub fn top_months(counts: &HashMap<u64, u64>, count: usize) -> Vec<u64> {
+    let mut months: Vec<(u64, u64)> = counts.iter().map(|(m, c)|(*m, *c)).collect();
+    months.sort_by(|a, b|b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
+    months.into_iter().take(count).map(|(m, _)|m).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::Error;
+    use crate::core::warm_list::{load_access_counts, save_access_counts, top_months};
+
+    #[test]
+    fn test_warm_list() -> Result<(), Error> {
+        let folder = temp_dir().join("hadb_warm_list_test");
+        fs::create_dir_all(&folder)?;
+        let path = folder.to_string_lossy().to_string();
+        let counts = HashMap::from([(202401, 3), (202402, 10), (202403, 3), (202312, 1)]);
+        assert_eq!(top_months(&counts, 3), vec![202402, 202403, 202401]);
+        assert!(load_access_counts(&path)?.is_empty());
+        save_access_counts(&path, &counts)?;
+        assert_eq!(load_access_counts(&path)?, counts);
+        fs::remove_dir_all(&folder)
+    }
+}
@@ -0,0 +1,310 @@
+//! Dated storage partitioned into independent shards, usually by year. Every shard has its own source,
+//! LRU list and modified set, so months of different years are loaded, evicted and saved without
+//! contending for the same locks, and load and flush process shards in parallel.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Error;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use crate::core::cache_stats::CacheStats;
+use crate::core::time_series_data::{list_files, DataRange, DatedSource, FileMap, LoadProblem, SaveBatch, TimeSeriesData};
+
+type Shard<T> = Arc<TimeSeriesData<T>>;
+
+pub struct ShardedTimeSeriesData<T> {
+    data_folder_path: String,
+    /// maps item key to its shard, keys of a later shard have to be greater
+    shard_of: fn(u64) -> u64,
+    /// limit of loaded items of every shard
+    max_active_items: usize,
+    /// sources of new shards are forked from it
+    source: Mutex<Box<dyn DatedSource<T>>>,
+    shards: RwLock<BTreeMap<u64, Shard<T>>>,
+    problems: Vec<LoadProblem>,
+    /// versions saved by the previous session, shards count changes made since the load
+    base_versions: Mutex<HashMap<u64, u64>>
+}
+
+/// Shard function keeping all items in one shard.
+pub fn single_shard(_key: u64) -> u64 {0}
+
+/// Shard function of yyyymm keys.
+pub fn year_shard(key: u64) -> u64 {key / 100}
+
+/// Runs f for every item in several threads.
+fn run_parallel<I: Send, R: Send>(items: Vec<I>, f: impl Fn(I) -> Result<R, Error> + Sync) -> Result<Vec<R>, Error> {
+    let threads = thread::available_parallelism().map(|n|n.get()).unwrap_or(1);
+    if threads == 1 || items.len() < 2 {
+        return items.into_iter().map(f).collect();
+    }
+    let mut chunks: Vec<Vec<I>> = (0..threads.min(items.len())).map(|_|Vec::new()).collect();
+    let count = chunks.len();
+    for (i, item) in items.into_iter().enumerate() {
+        chunks[i % count].push(item);
+    }
+    thread::scope(|s| {
+        let handles: Vec<_> = chunks.into_iter()
+            .map(|chunk|s.spawn(||chunk.into_iter().map(&f).collect::<Result<Vec<R>, Error>>()))
+            .collect();
+        let mut result = Vec::new();
+        for h in handles {
+            result.append(&mut h.join().unwrap_or_else(|_|Err(Error::other("shard thread panicked")))?);
+        }
+        Ok(result)
+    })
+}
+
+impl<T: Send> ShardedTimeSeriesData<T> {
+    /// Loads all shards in parallel. Files which date can't be parsed fail the load, or are listed
+    /// by get_problems when recover is set.
+    pub fn load(data_folder_path: String, source: Box<dyn DatedSource<T>>, index_calculator: fn(u64) -> u64,
+                shard_of: fn(u64) -> u64, max_active_items: usize, recover: bool)
+        -> Result<ShardedTimeSeriesData<T>, Error> {
+        let (file_map, mut problems) = list_files(&data_folder_path, source.as_ref(), index_calculator, recover)?;
+        let mut by_shard: BTreeMap<u64, FileMap> = BTreeMap::new();
+        for (key, files) in file_map {
+            by_shard.entry(shard_of(key)).or_default().insert(key, files);
+        }
+        let jobs: Vec<_> = by_shard.into_iter().map(|(shard, files)|(shard, files, source.fork())).collect();
+        let loaded = run_parallel(jobs, |(shard, files, source)|
+            TimeSeriesData::from_files(data_folder_path.clone(), source, files, max_active_items, recover)
+                .map(|data|(shard, Arc::new(data))))?;
+        for (_, data) in &loaded {
+            problems.extend(data.get_problems().iter().cloned());
+        }
+        Ok(ShardedTimeSeriesData{data_folder_path, shard_of, max_active_items, source: Mutex::new(source),
+            shards: RwLock::new(loaded.into_iter().collect()), problems, base_versions: Mutex::new(HashMap::new())})
+    }
+
+    pub fn new(data_folder_path: String, source: Box<dyn DatedSource<T>>, shard_of: fn(u64) -> u64,
+               max_active_items: usize) -> ShardedTimeSeriesData<T> {
+        ShardedTimeSeriesData{data_folder_path, shard_of, max_active_items, source: Mutex::new(source),
+            shards: RwLock::new(BTreeMap::new()), problems: Vec::new(), base_versions: Mutex::new(HashMap::new())}
+    }
+
+    fn shard(&self, key: u64) -> Option<Shard<T>> {
+        self.shards.read().unwrap().get(&(self.shard_of)(key)).cloned()
+    }
+
+    /// Shards which may have keys between from and to, ordered by key.
+    fn shards_between(&self, from: u64, to: u64) -> Vec<Shard<T>> {
+        self.shards.read().unwrap().range((self.shard_of)(from)..=(self.shard_of)(to)).map(|(_, s)|s.clone()).collect()
+    }
+
+    fn all_shards(&self) -> Vec<Shard<T>> {
+        self.shards.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn add(&self, key: u64, v: T, add_to_modified: bool) -> Result<(), Error> {
+        let shard = match self.shard(key) {
+            Some(shard) => shard,
+            None => self.shards.write().unwrap().entry((self.shard_of)(key))
+                .or_insert_with(||Arc::new(TimeSeriesData::new(self.data_folder_path.clone(),
+                                                                self.source.lock().unwrap().fork(),
+                                                                self.max_active_items)))
+                .clone()
+        };
+        shard.add(key, v, add_to_modified)
+    }
+
+    /// Item with the greatest key not greater than idx.
+    pub fn get(&self, idx: u64) -> Result<Option<Arc<Mutex<T>>>, Error> {
+        for shard in self.shards_between(0, idx).iter().rev() {
+            if let Some(v) = shard.get(idx)? {
+                return Ok(Some(v));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the key of the item that get(idx) would return.
+    pub fn get_key(&self, idx: u64) -> Option<u64> {
+        self.shards_between(0, idx).iter().rev().find_map(|s|s.get_key(idx))
+    }
+
+    /// Drops the item without saving it.
+    pub fn remove(&self, key: u64) {
+        if let Some(shard) = self.shard(key) {
+            shard.remove(key);
+        }
+    }
+
+    pub fn first_key(&self) -> Option<u64> {
+        self.all_shards().iter().find_map(|s|s.first_key())
+    }
+
+    /// Keys of items changed since the last flush.
+    pub fn get_modified(&self) -> Vec<u64> {
+        self.all_shards().iter().flat_map(|s|s.get_modified()).collect()
+    }
+
+    /// Same contract as TimeSeriesData::mark_modified.
+    pub fn mark_modified(&self, key: u64) {
+        if let Some(shard) = self.shard(key) {
+            shard.mark_modified(key);
+        }
+    }
+
+    /// Saves modified items, shards with changes are saved in parallel when requested.
+    pub fn flush(&self, parallel: bool) -> Result<SaveBatch, Error> {
+        let shards: Vec<Shard<T>> = self.all_shards().into_iter().filter(|s|!s.get_modified().is_empty()).collect();
+        let batches = if parallel && shards.len() > 1 {
+            run_parallel(shards, |s|s.flush(false))?
+        } else {
+            shards.iter().map(|s|s.flush(parallel)).collect::<Result<Vec<_>, Error>>()?
+        };
+        let mut result = SaveBatch::default();
+        for batch in batches {
+            result.append(batch);
+        }
+        Ok(result)
+    }
+
+    pub fn get_problems(&self) -> &[LoadProblem] {
+        &self.problems
+    }
+
+    /// Version of the item, 0 when it was never changed.
+    pub fn get_version(&self, key: u64) -> u64 {
+        let base = self.base_versions.lock().unwrap().get(&key).copied().unwrap_or(0);
+        base + self.shard(key).map(|s|s.get_version(key)).unwrap_or(0)
+    }
+
+    pub fn get_versions(&self) -> HashMap<u64, u64> {
+        let mut result = self.base_versions.lock().unwrap().clone();
+        for shard in self.all_shards() {
+            for (key, version) in shard.get_versions() {
+                *result.entry(key).or_default() += version;
+            }
+        }
+        result
+    }
+
+    /// Restores versions saved by the previous session, so they keep increasing after restart.
+    pub fn set_versions(&self, versions: HashMap<u64, u64>) {
+        *self.base_versions.lock().unwrap() = versions;
+    }
+
+    pub fn get_range(&self, from: u64, to: u64) -> Result<DataRange<T>, Error> {
+        let mut result = Vec::new();
+        for shard in self.shards_between(from, to) {
+            result.append(&mut shard.get_range(from, to)?);
+        }
+        Ok(result)
+    }
+
+    /// Same as TimeSeriesData::scan_range over all shards.
+    pub fn scan_range(&self, from: u64, to: u64, mut f: impl FnMut(u64, &Arc<Mutex<T>>) -> Result<(), Error>)
+        -> Result<(), Error> {
+        for shard in self.shards_between(from, to) {
+            shard.scan_range(from, to, &mut f)?;
+        }
+        Ok(())
+    }
+
+    /// Same as TimeSeriesData::scan_until over all shards.
+    pub fn scan_until(&self, from: u64, to: u64, mut f: impl FnMut(u64, &Arc<Mutex<T>>) -> Result<bool, Error>)
+        -> Result<(), Error> {
+        let mut stopped = false;
+        for shard in self.shards_between(from, to) {
+            shard.scan_until(from, to, |key, t|{
+                stopped = f(key, t)?;
+                Ok(stopped)
+            })?;
+            if stopped {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of items in the range, loaded or not.
+    pub fn count_range(&self, from: u64, to: u64) -> usize {
+        self.shards_between(from, to).iter().map(|s|s.count_range(from, to)).sum()
+    }
+
+    /// Limit of loaded items of one shard.
+    pub fn get_max_active_items(&self) -> usize {
+        self.max_active_items
+    }
+
+    pub fn get_active_items(&self) -> usize {
+        self.all_shards().iter().map(|s|s.get_active_items()).sum()
+    }
+
+    /// Hits and misses of all shards.
+    pub fn get_stats(&self) -> CacheStats {
+        let stats = CacheStats::default();
+        for shard in self.all_shards() {
+            stats.add(shard.get_stats());
+        }
+        stats
+    }
+
+    /// Accesses of every item since the previous call.
+    pub fn take_access_counts(&self) -> HashMap<u64, u64> {
+        self.all_shards().iter().flat_map(|s|s.take_access_counts()).collect()
+    }
+
+    /// Loads items ordered from the most important one, so it ends at the head of the LRU list of its shard.
+    pub fn warm(&self, keys: &[u64]) -> Result<(), Error> {
+        for key in keys.iter().rev() {
+            self.get_range(*key, *key)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::core::time_series_data::{DatedSource, FileInfo, FileWithDate, SaveBatch};
+    use crate::core::year_shards::{year_shard, ShardedTimeSeriesData};
+
+    struct TestData{}
+    struct TestDataSource{}
+
+    impl DatedSource<TestData> for TestDataSource {
+        fn load(&mut self, _files: Vec<FileWithDate>) -> Result<TestData, Error> {
+            Ok(TestData{})
+        }
+
+        fn parse_date(&self, _info: &FileInfo) -> Result<u64, Error> {
+            todo!()
+        }
+
+        fn prepare_save(&self, _data: &TestData, _data_folder_path: &str, _key: u64) -> Result<SaveBatch, Error> {
+            Ok(SaveBatch::default())
+        }
+
+        fn get_files(&self, _data_folder_path: &str, _date: u64) -> Result<Vec<FileWithDate>, Error> {
+            Ok(Vec::new())
+        }
+
+        fn fork(&self) -> Box<dyn DatedSource<TestData>> {
+            Box::new(TestDataSource{})
+        }
+    }
+
+    #[test]
+    fn test_year_shards() -> Result<(), Error> {
+        let data = ShardedTimeSeriesData::new("".to_string(), Box::new(TestDataSource{}), year_shard, 2);
+        for key in [202311, 202312, 202401, 202402, 202403] {
+            data.add(key, TestData{}, false)?;
+        }
+        // every year keeps its own working set
+        assert_eq!(data.get_active_items(), 4);
+        assert_eq!(data.get_key(202400), Some(202312));
+        assert_eq!(data.get_key(202405), Some(202403));
+        assert_eq!(data.get_range(202312, 202402)?.iter().map(|(k, _)|*k).collect::<Vec<_>>(), vec![202312, 202401, 202402]);
+        data.mark_modified(202401);
+        data.mark_modified(202312);
+        assert_eq!(data.get_version(202401), 1);
+        let mut modified = data.get_modified();
+        modified.sort();
+        assert_eq!(modified, vec![202312, 202401]);
+        data.flush(true)?;
+        assert!(data.get_modified().is_empty());
+        Ok(())
+    }
+}
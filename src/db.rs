@@ -1,79 +1,1044 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::io::{Error, ErrorKind};
 use std::ops::Add;
-use std::time::Instant;
+use std::env::temp_dir;
+use std::fs;
+use std::process;
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::mpsc::Receiver;
+use crate::core::archive::ColdArchive;
+use crate::core::attachments::AttachmentStorage;
+use crate::core::month_versions::{load_month_versions, save_month_versions};
+use crate::core::warm_list::{load_access_counts, save_access_counts, top_months};
+use crate::core::idempotency::IdempotencyKeys;
+use crate::core::month_locks::{get_lock, list_locks, lock_months, unlock_months, MonthLock};
+use crate::core::totals_cache::{clear_watermark, load_totals, load_watermark, month_fingerprints, save_totals, save_watermark,
+                                SavedTotals};
+#[cfg(feature = "json")]
+use crate::core::backup::create_backup;
+use crate::core::backup::{extract_files, verify_backup};
+use crate::core::clock::{Clock, SystemClock};
+use crate::core::crypto::CryptoProcessor;
+use crate::core::errors::{field_error, rejections_error};
+use crate::core::trace::set_slow_threshold;
+#[cfg(feature = "server")]
+use crate::core::replication::ReplicationSender;
+#[cfg(feature = "json")]
+use crate::core::wal::{copy_folder, list_checkpoints, to_millis, Wal, WalChange, CHECKPOINTS_FOLDER, WAL_FILE_NAME};
+use crate::core::dates::{check_date, days_in_month, is_valid_date, sub_months, with_lenient_dates};
 use crate::core::data_source::DataSource;
-use crate::core::time_series_data::{DatedSource, TimeSeriesData};
-use crate::entities::accounts::{Account, Accounts};
-use crate::entities::finance_operations::{FinanceChanges, FinanceOperation, FinanceRecord};
-use crate::entities::subcategories::{Categories, Category, Subcategories, Subcategory};
+use crate::core::time_series_data::{DataRange, DatedSource, LoadProblem, SaveBatch, TimeSeriesData};
+use crate::core::year_shards::{single_shard, year_shard, ShardedTimeSeriesData};
+use crate::entities::accounts::{Account, AccountId, Accounts};
+use crate::entities::currencies::{Currencies, Currency, MinorUnits, SharedMinorUnits};
+use crate::entities::payees::{Payee, PayeeId, Payees};
+use crate::entities::parameters::{ParameterDefinition, ParameterDefinitions};
+use crate::entities::loans::{Loan, Loans};
+use crate::entities::instruments::{Instrument, InstrumentPrice, Instruments};
+use crate::entities::goals::{Goal, Goals};
+use crate::entities::budgets::{BudgetLine, Budgets};
+use crate::entities::rates::{ExchangeRate, Rates};
+use crate::entities::settings::Settings;
+use crate::entities::templates::{OperationTemplate, Templates};
+use crate::entities::validation_rules::{OperationValidator, ValidationRule, ValidationRules};
+use crate::entities::finance_operations::{FinOpParameter, FinanceChanges, FinanceOperation, FinanceRecord, SpecialHandler, SpecialHandlers};
+use crate::entities::subcategories::{Categories, Category, CategoryId, Subcategories, Subcategory, SubcategoryCode, SubcategoryId};
+use crate::query::{OperationQuery, OperationView, QueryPage};
+use crate::suggester::SubcategorySuggester;
+use crate::verify::checksums::{build_checksums, changed_months, load_checksums, save_checksums};
+use crate::verify::duplicates::{check_duplicates, DuplicateOperation};
+use crate::verify::manifest::{build_digests, compare_digests, forget_months, load_manifest, save_manifest, ManifestLine,
+                              ManifestMismatch, MonthDigest};
+use crate::verify::opening::{check_opening_balances, is_opening_balance};
+use crate::verify::references::{check_references, ReferenceViolation};
+use crate::verify::repair::{find_date_fixes, find_misplaced, find_sign_fixes, Fixer, RepairChange};
+use crate::verify::totals::{check_totals, TotalsMismatch};
+use crate::reports::alerts::{build_alerts, find_crossed, Threshold};
+use crate::reports::annual::AnnualReport;
+use crate::reports::anomalies::{build_anomalies_report, SpendingAnomaly};
+use crate::scenario::Scenario;
+use crate::core::events::{Event, EventBus, EventKind};
+use crate::reports::budget::{build_budget_report, BudgetStatus};
+use crate::reports::gnucash::GnuCashExport;
+use crate::reports::html::HtmlReport;
+use crate::reports::limits::{build_limits_report, check_limits, LimitKind, LimitStatus};
+use crate::reports::goals::{build_goals_report, GoalProgress};
+use crate::reports::loans::{build_loans_report, LoanStatus};
+use crate::reports::memory::{add_shared_strings, dictionaries_bytes, MemoryReport};
+use crate::reports::monthly::MonthlyReport;
+use crate::reports::net_worth::{build_holdings, build_net_worth, NetWorth};
+use crate::reports::statement::Statement;
+use crate::reports::tax::{build_tax_report, TaxReport};
+use crate::reports::custom::{build_custom_report, load_report_specs, CustomReport, ReportSpec};
+use crate::reports::summary::{build_category_summary, build_network_summary, build_payee_summary, build_subcategory_summary, SummaryItem};
+#[cfg(feature = "json")]
+use crate::snapshot::Snapshot;
+#[cfg(feature = "arrow")]
+use crate::analytics::operations_to_record_batch;
 
-pub trait DBConfiguration {
+pub trait DBConfiguration: Send + Sync {
     fn get_accounts_source(&self) ->  Box<dyn DataSource<Vec<Account>>>;
     fn get_categories_source(&self) ->  Box<dyn DataSource<Vec<Category>>>;
     fn get_subcategories_source(&self) ->  Box<dyn DataSource<Vec<Subcategory>>>;
-    fn get_main_data_source(&self) -> Box<dyn DatedSource<FinanceRecord>>;
+    fn get_currencies_source(&self) ->  Box<dyn DataSource<Vec<Currency>>>;
+    fn get_payees_source(&self) ->  Box<dyn DataSource<Vec<Payee>>>;
+    fn get_parameters_source(&self) ->  Box<dyn DataSource<Vec<ParameterDefinition>>>;
+    fn get_loans_source(&self) ->  Box<dyn DataSource<Vec<Loan>>>;
+    fn get_instruments_source(&self) ->  Box<dyn DataSource<Vec<Instrument>>>;
+    fn get_prices_source(&self) ->  Box<dyn DataSource<Vec<InstrumentPrice>>>;
+    fn get_goals_source(&self) ->  Box<dyn DataSource<Vec<Goal>>>;
+    fn get_budgets_source(&self) ->  Box<dyn DataSource<Vec<BudgetLine>>>;
+    fn get_rates_source(&self) ->  Box<dyn DataSource<Vec<ExchangeRate>>>;
+    fn get_settings_source(&self) ->  Box<dyn DataSource<Settings>>;
+    fn get_templates_source(&self) ->  Box<dyn DataSource<Vec<OperationTemplate>>>;
+    fn get_report_specs_source(&self) ->  Box<dyn DataSource<Vec<ReportSpec>>>;
+    fn get_validation_rules_source(&self) ->  Box<dyn DataSource<Vec<ValidationRule>>>;
+    /// Operations source, sums of operations are kept in minor units of the account currencies.
+    fn get_main_data_source(&self, minor_units: SharedMinorUnits) -> Box<dyn DatedSource<FinanceRecord>>;
+    fn get_attachments_crypto(&self) -> Option<Box<dyn CryptoProcessor>>;
+    /// Whether data files are encrypted, plain caches of derived data like totals are not written then.
+    fn encrypts_files(&self) -> bool {
+        false
+    }
 }
 
 pub struct HomeAccountingDB {
-    data: TimeSeriesData<FinanceRecord>,
+    data: ShardedTimeSeriesData<FinanceRecord>,
     accounts: Accounts,
     categories: Categories,
-    subcategories: Subcategories
+    subcategories: Subcategories,
+    currencies: Currencies,
+    minor_units: SharedMinorUnits,
+    payees: Payees,
+    parameters: ParameterDefinitions,
+    loans: Loans,
+    instruments: Instruments,
+    goals: Goals,
+    budgets: Budgets,
+    rates: Rates,
+    settings: Settings,
+    templates: Templates,
+    validation_rules: ValidationRules,
+    /// checks registered by applications, run after validation rules
+    validators: Vec<Box<dyn OperationValidator>>,
+    attachments: AttachmentStorage,
+    handlers: SpecialHandlers,
+    clock: Box<dyn Clock>,
+    data_folder_path: String,
+    configuration: Box<dyn DBConfiguration>,
+    /// latest modification time of accounts, categories and subcategories files
+    dictionaries_modified: Option<SystemTime>,
+    archive: ColdArchive,
+    /// balances at the end of the last archived year
+    opening_balances: HashMap<AccountId, i64>,
+    /// first month changed since totals were saved
+    totals_watermark: Mutex<Option<u64>>,
+    /// month accesses of all sessions, saved on flush
+    access_counts: Mutex<HashMap<u64, u64>>,
+    /// keys of recent mutations, saved on flush
+    idempotency_keys: Mutex<IdempotencyKeys>,
+    /// owner of month locks this database may change months under
+    lock_owner: Option<String>,
+    /// bulk scans read months past the cache, so they don't evict months of interactive queries
+    scan_bypasses_cache: bool,
+    /// serializes changes of months and totals recalculation, reads lock only the months they use
+    mutation: Mutex<()>,
+    /// thresholds crossed by the latest balances
+    crossed_thresholds: Mutex<BTreeSet<(AccountId, Threshold)>>,
+    /// alerts not yet taken by notifiers
+    events: EventBus,
+    #[cfg(feature = "server")]
+    replica: Option<ReplicationSender>,
+    #[cfg(feature = "json")]
+    wal: Option<Wal>
+}
+
+const RELOADABLE_DICTIONARIES: [&str; 3] = ["accounts", "categories", "subcategories"];
+
+fn dictionary_files(data_folder_path: &str) -> Vec<fs::DirEntry> {
+    fs::read_dir(data_folder_path).map(|entries|entries
+        .filter_map(|e|e.ok())
+        .filter(|e|e.path().file_stem().and_then(|s|s.to_str()).is_some_and(|s|RELOADABLE_DICTIONARIES.contains(&s)))
+        .collect())
+        .unwrap_or_default()
+}
+
+#[cfg(feature = "server")]
+fn list_files(folder: &str, result: &mut Vec<String>) -> Result<(), Error> {
+    for entry in fs::read_dir(folder)? {
+        let entry = entry?;
+        let name = folder.to_string() + "/" + &entry.file_name().to_string_lossy();
+        if entry.file_type()?.is_dir() {
+            list_files(&name, result)?;
+        } else {
+            result.push(name);
+        }
+    }
+    Ok(())
+}
+
+pub const MAINTENANCE_LOG_FILE_NAME: &str = "maintenance.log";
+
+fn modified_nanos(time: Option<SystemTime>) -> u128 {
+    time.and_then(|t|t.duration_since(UNIX_EPOCH).ok()).map(|d|d.as_nanos()).unwrap_or(0)
+}
+
+fn dictionaries_modified_time(data_folder_path: &str) -> Option<SystemTime> {
+    dictionary_files(data_folder_path).into_iter()
+        .filter_map(|e|e.metadata().and_then(|m|m.modified()).ok())
+        .max()
+}
+
+pub struct LoadOptions {
+    pub max_active_items: usize,
+    /// check that operations reference existing dictionary entries before totals calculation
+    pub verify_references: bool,
+    /// accept impossible calendar dates (like 20240230) found in legacy data
+    pub lenient_dates: bool,
+    /// skip unreadable files instead of failing, see get_load_problems
+    pub recover_errors: bool,
+    /// keep every year in its own shard loaded and flushed in parallel, max_active_items limits
+    /// loaded months of every year then
+    pub shard_by_year: bool
+}
+
+impl LoadOptions {
+    pub fn new(max_active_items: usize) -> LoadOptions {
+        LoadOptions{max_active_items, verify_references: false, lenient_dates: false, recover_errors: false, shard_by_year: false}
+    }
 }
 
 fn index_calculator(date: u64) -> u64 {date / 100}
 
 impl HomeAccountingDB {
     pub fn load(data_folder_path: String, data_source: Box<dyn DBConfiguration>, max_active_items: usize)
+        -> Result<HomeAccountingDB, Error> {
+        HomeAccountingDB::load_with_options(data_folder_path, data_source, LoadOptions::new(max_active_items))
+    }
+
+    pub fn load_with_options(data_folder_path: String, data_source: Box<dyn DBConfiguration>, options: LoadOptions)
         -> Result<HomeAccountingDB, Error> {
         let start = Instant::now();
-        let data =
-            TimeSeriesData::load(data_folder_path.clone().add("/dates"), data_source.get_main_data_source(),
-                                 index_calculator, max_active_items)?;
-        let accounts = Accounts::load(data_folder_path.clone(), data_source.get_accounts_source())?;
-        let categories = Categories::load(data_folder_path.clone(), data_source.get_categories_source())?;
-        let subcategories = Subcategories::load(data_folder_path, data_source.get_subcategories_source())?;
-        let mut db = HomeAccountingDB{data, accounts, categories, subcategories};
+        // threshold is needed before months are loaded
+        set_slow_threshold(Settings::load(data_folder_path.clone(), data_source.get_settings_source())?.slow_operation_ms);
+        let mut db = with_lenient_dates(options.lenient_dates, || {
+            let path = data_folder_path.clone().add("/dates");
+            let shard_of = if options.shard_by_year {year_shard} else {single_shard};
+            HomeAccountingDB::create(data_folder_path, data_source, |source|
+                ShardedTimeSeriesData::load(path, source, index_calculator, shard_of, options.max_active_items,
+                                            options.recover_errors))
+        })?;
         println!("Database loaded in {} ms", start.elapsed().as_millis());
+        if options.verify_references {
+            let violations = db.check_references()?;
+            if !violations.is_empty() {
+                let messages: Vec<String> = violations.iter().map(|v|v.describe()).collect();
+                return Err(Error::new(ErrorKind::InvalidData, messages.join("\n")));
+            }
+        }
         let start = Instant::now();
-        db.build_totals(0)?;
+        db.init_totals()?;
         println!("Totals calculation finished in {} us", start.elapsed().as_micros());
+        db.warm_cache()?;
+        // thresholds crossed before the load are not reported
+        let crossed = find_crossed(&db.accounts, &db.latest_balances()?);
+        *db.crossed_thresholds.get_mut().unwrap() = crossed;
         Ok(db)
     }
     
     pub fn new(data_folder_path: String, data_source: Box<dyn DBConfiguration>, max_active_items: usize)
         -> Result<HomeAccountingDB, Error> {
-        let data =
-            TimeSeriesData::new(data_folder_path.clone().add("/dates"), data_source.get_main_data_source(),
-                                 max_active_items);
-        let accounts = Accounts::load(data_folder_path.clone(), data_source.get_accounts_source())?;
+        let path = data_folder_path.clone().add("/dates");
+        HomeAccountingDB::create(data_folder_path, data_source, |source|
+            Ok(ShardedTimeSeriesData::new(path, source, single_shard, max_active_items)))
+    }
+
+    /// Loads dictionaries, operations source is opened after accounts and currencies are known.
+    fn create<F>(data_folder_path: String, data_source: Box<dyn DBConfiguration>, open: F)
+        -> Result<HomeAccountingDB, Error>
+        where F: FnOnce(Box<dyn DatedSource<FinanceRecord>>) -> Result<ShardedTimeSeriesData<FinanceRecord>, Error> {
+        let accounts = Accounts::load(data_folder_path.clone(), data_source.get_accounts_source(),
+                                      data_source.get_accounts_source())?;
         let categories = Categories::load(data_folder_path.clone(), data_source.get_categories_source())?;
-        let subcategories = Subcategories::load(data_folder_path, data_source.get_subcategories_source())?;
-        Ok(HomeAccountingDB{data, accounts, categories, subcategories})
+        let subcategories = Subcategories::load(data_folder_path.clone(), data_source.get_subcategories_source())?;
+        let currencies = Currencies::load(data_folder_path.clone(), data_source.get_currencies_source())?;
+        currencies.validate(&accounts)?;
+        let minor_units = Arc::new(RwLock::new(MinorUnits::new(&accounts, &currencies)?));
+        let data = open(data_source.get_main_data_source(minor_units.clone()))?;
+        let payees = Payees::load(data_folder_path.clone(), data_source.get_payees_source())?;
+        let parameters = ParameterDefinitions::load(data_folder_path.clone(), data_source.get_parameters_source())?;
+        let loans = Loans::load(data_folder_path.clone(), data_source.get_loans_source())?;
+        loans.validate(&accounts)?;
+        let instruments = Instruments::load(data_folder_path.clone(), data_source.get_instruments_source(),
+                                            data_source.get_prices_source())?;
+        let goals = Goals::load(data_folder_path.clone(), data_source.get_goals_source())?;
+        goals.validate(&accounts)?;
+        let budgets = Budgets::load(data_folder_path.clone(), data_source.get_budgets_source())?;
+        budgets.validate(&subcategories)?;
+        let rates = Rates::load(data_folder_path.clone(), data_source.get_rates_source())?;
+        rates.validate(&currencies)?;
+        let settings = Settings::load(data_folder_path.clone(), data_source.get_settings_source())?;
+        settings.validate(&currencies)?;
+        set_slow_threshold(settings.slow_operation_ms);
+        let templates = Templates::load(data_folder_path.clone(), data_source.get_templates_source())?;
+        templates.validate(&accounts, &subcategories)?;
+        let validation_rules = ValidationRules::load(data_folder_path.clone(), data_source.get_validation_rules_source())?;
+        validation_rules.validate(&accounts, &subcategories)?;
+        let attachments = AttachmentStorage::new(data_folder_path.clone().add("/attachments"),
+                                                 data_source.get_attachments_crypto());
+        let dictionaries_modified = dictionaries_modified_time(&data_folder_path);
+        let archive = ColdArchive::open(&data_folder_path, data_source.get_attachments_crypto())?;
+        if let (Some(year), Some(key)) = (archive.last_year(), data.first_key()) {
+            if key / 100 <= year {
+                return Err(Error::new(ErrorKind::InvalidData,
+                                      format!("month {} belongs to archived year {}, archiving was interrupted", key, year)));
+            }
+        }
+        let opening_balances = archive.load_balances()?;
+        let totals_watermark = Mutex::new(load_watermark(&data_folder_path)?);
+        let access_counts = Mutex::new(load_access_counts(&data_folder_path)?);
+        let idempotency_keys = Mutex::new(IdempotencyKeys::load(&data_folder_path)?);
+        data.set_versions(load_month_versions(&data_folder_path)?);
+        Ok(HomeAccountingDB{data, accounts, categories, subcategories, currencies, minor_units, payees, parameters, loans,
+            instruments, goals, budgets, rates, settings, templates, validation_rules, validators: Vec::new(), attachments,
+            handlers: SpecialHandlers::new(), clock: Box::new(SystemClock{}), data_folder_path,
+            configuration: data_source, dictionaries_modified, archive, opening_balances,
+            totals_watermark, access_counts, idempotency_keys, lock_owner: None, scan_bypasses_cache: false, mutation: Mutex::new(()), crossed_thresholds: Mutex::new(BTreeSet::new()),
+            events: EventBus::new(),
+            #[cfg(feature = "server")]
+            replica: None,
+            #[cfg(feature = "json")]
+            wal: None})
+    }
+
+    /// Reloads accounts, categories and subcategories. New dictionaries are accepted only when
+    /// all stored operations still reference existing entries, otherwise the old ones are kept.
+    pub fn reload_dictionaries(&mut self) -> Result<(), Error> {
+        let accounts = Accounts::load(self.data_folder_path.clone(), self.configuration.get_accounts_source(),
+                                      self.configuration.get_accounts_source())?;
+        let categories = Categories::load(self.data_folder_path.clone(), self.configuration.get_categories_source())?;
+        let subcategories = Subcategories::load(self.data_folder_path.clone(),
+                                                self.configuration.get_subcategories_source())?;
+        self.set_dictionaries(accounts, categories, subcategories)?;
+        self.dictionaries_modified = dictionaries_modified_time(&self.data_folder_path);
+        for name in RELOADABLE_DICTIONARIES {
+            self.events.publish(EventKind::DictionaryChanged(name));
+        }
+        #[cfg(feature = "server")]
+        if let Some(replica) = &self.replica {
+            let files: Vec<String> = dictionary_files(&self.data_folder_path).iter()
+                .map(|e|e.path().to_string_lossy().to_string())
+                .collect();
+            replica.send_files(&files)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces accounts, categories and subcategories with edited ones and saves them. Changes are rejected
+    /// when stored operations or other dictionaries reference missing entries.
+    pub fn replace_dictionaries(&mut self, accounts: Vec<Account>, categories: Vec<Category>,
+                                subcategories: Vec<Subcategory>) -> Result<(), Error> {
+        if let Some(a) = accounts.iter().find(|a|self.accounts.get_archived().is_some_and(|d|d.contains(a.id.0))) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("account {} is archived", a.id)));
+        }
+        let categories = Categories::new(categories);
+        if let Some(s) = subcategories.iter().find(|s|categories.get(s.category).is_err()) {
+            return Err(Error::new(ErrorKind::InvalidData,
+                                  format!("subcategory {} has invalid category {}", s.name, s.category)));
+        }
+        let accounts = Accounts::with_archive(self.data_folder_path.clone(), self.configuration.get_accounts_source(),
+                                              accounts)?;
+        self.set_dictionaries(accounts, categories, Subcategories::new(subcategories))?;
+        self.categories.save(self.configuration.get_categories_source(), self.data_folder_path.clone())?;
+        self.events.publish(EventKind::DictionaryChanged("categories"));
+        self.save_subcategories()?;
+        self.save_accounts()
+    }
+
+    /// Totals are rebuilt with new dictionaries, the old ones are restored when it fails.
+    fn set_dictionaries(&mut self, accounts: Accounts, categories: Categories, subcategories: Subcategories)
+        -> Result<(), Error> {
+        self.currencies.validate(&accounts)?;
+        let minor_units = MinorUnits::new(&accounts, &self.currencies)?;
+        self.loans.validate(&accounts)?;
+        self.goals.validate(&accounts)?;
+        self.budgets.validate(&subcategories)?;
+        self.templates.validate(&accounts, &subcategories)?;
+        let violations = check_references(&self.data.get_range(0, u64::MAX)?, &accounts, &subcategories,
+                                          &self.payees)?;
+        if !violations.is_empty() {
+            let messages: Vec<String> = violations.iter().map(|v|v.describe()).collect();
+            return Err(Error::new(ErrorKind::InvalidData, messages.join("\n")));
+        }
+        let old_accounts = std::mem::replace(&mut self.accounts, accounts);
+        let old_categories = std::mem::replace(&mut self.categories, categories);
+        let old_subcategories = std::mem::replace(&mut self.subcategories, subcategories);
+        if let Err(e) = self.build_totals(0) {
+            self.accounts = old_accounts;
+            self.categories = old_categories;
+            self.subcategories = old_subcategories;
+            self.build_totals(0)?;
+            return Err(e);
+        }
+        *self.minor_units.write().unwrap() = minor_units;
+        Ok(())
+    }
+
+    /// Reloads dictionaries when their files were modified since the last load, returns true if reloaded.
+    pub fn reload_if_changed(&mut self) -> Result<bool, Error> {
+        if dictionaries_modified_time(&self.data_folder_path) == self.dictionaries_modified {
+            return Ok(false);
+        }
+        self.reload_dictionaries()?;
+        Ok(true)
+    }
+
+    pub fn get_accounts(&self) -> &Accounts {
+        &self.accounts
+    }
+
+    pub fn get_categories(&self) -> &Categories {
+        &self.categories
+    }
+
+    pub fn get_subcategories(&self) -> &Subcategories {
+        &self.subcategories
+    }
+
+    pub fn get_currencies(&self) -> &Currencies {
+        &self.currencies
+    }
+
+    /// Currency of the account at given date, sums of its operations are kept in its minor units.
+    pub fn get_account_currency(&self, account: AccountId, date: u64) -> Result<Currency, Error> {
+        self.minor_units.read().unwrap().currency_at(account, date).cloned()
+    }
+
+    pub fn get_payees(&self) -> &Payees {
+        &self.payees
+    }
+
+    pub fn get_parameter_definitions(&self) -> &ParameterDefinitions {
+        &self.parameters
+    }
+
+    pub fn get_loans(&self) -> &Loans {
+        &self.loans
+    }
+
+    pub fn get_instruments(&self) -> &Instruments {
+        &self.instruments
+    }
+
+    pub fn get_goals(&self) -> &Goals {
+        &self.goals
+    }
+
+    pub fn get_budgets(&self) -> &Budgets {
+        &self.budgets
+    }
+
+    pub fn get_rates(&self) -> &Rates {
+        &self.rates
+    }
+
+    pub fn get_settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn get_templates(&self) -> &Templates {
+        &self.templates
     }
 
-    fn build_totals(&mut self, from: u64) -> Result<(), Error> {
+    /// Adds operation made from the template, template summa is used when summa is None.
+    /// Returns exceeded hard limit like add_operation_checked.
+    pub fn add_from_template(&self, name: &str, date: u64, summa: Option<i64>) -> Result<Option<LimitStatus>, Error> {
+        let op = self.templates.get(name)?.to_operation(date, summa)?;
+        self.add_operation_checked(op)
+    }
+
+    /// Adds the operation, returns the hard limit of its subcategory when month expenditure exceeds it after the operation.
+    pub fn add_operation_checked(&self, op: FinanceOperation) -> Result<Option<LimitStatus>, Error> {
+        let (date, subcategory) = (op.date, op.get_subcategory());
+        self.add_operation(op)?;
+        let subcategory = self.subcategories.get(subcategory)?;
+        if subcategory.hard_limit.is_none() {
+            return Ok(None);
+        }
+        let spent = self.get_operations(date / 100 * 100 + 1, date / 100 * 100 + 31)?.iter()
+            .filter(|o|o.get_subcategory() == subcategory.id)
+            .map(|o|o.get_summa())
+            .sum();
+        Ok(check_limits(subcategory, spent).filter(|s|s.kind == LimitKind::Hard))
+    }
+
+    pub fn set_base_currency(&mut self, code: Option<String>) -> Result<(), Error> {
+        self.save_settings(Settings{base_currency: code, ..self.settings.clone()})
+    }
+
+    /// Loads, saves and server requests taking longer than given milliseconds are logged, None disables the log.
+    pub fn set_slow_operation_threshold(&mut self, milliseconds: Option<u64>) -> Result<(), Error> {
+        let milliseconds = milliseconds.filter(|ms|*ms > 0);
+        self.save_settings(Settings{slow_operation_ms: milliseconds, ..self.settings.clone()})?;
+        set_slow_threshold(milliseconds);
+        Ok(())
+    }
+
+    /// Number of most accessed months loaded into the cache on startup, None or 0 turns warming off.
+    pub fn set_warm_months(&mut self, count: Option<usize>) -> Result<(), Error> {
+        self.save_settings(Settings{warm_months: count.filter(|c|*c > 0), ..self.settings.clone()})
+    }
+
+    /// Months ordered from the most accessed one with their access counts saved by previous sessions.
+    pub fn get_month_ranking(&self) -> Vec<(u64, u64)> {
+        let counts = self.access_counts.lock().unwrap();
+        top_months(&counts, counts.len()).into_iter().map(|month|(month, counts[&month])).collect()
+    }
+
+    /// Loads the most accessed months after the startup scan of all months,
+    /// accesses made by the load itself are not counted.
+    fn warm_cache(&self) -> Result<(), Error> {
+        self.data.take_access_counts();
+        if let Some(count) = self.settings.warm_months {
+            let months = top_months(&self.access_counts.lock().unwrap(), count);
+            self.data.warm(&months)?;
+            self.data.take_access_counts();
+        }
+        Ok(())
+    }
+
+    fn save_access_counts(&self) -> Result<(), Error> {
+        let accesses = self.data.take_access_counts();
+        if accesses.is_empty() {
+            return Ok(());
+        }
+        let mut counts = self.access_counts.lock().unwrap();
+        for (month, count) in accesses {
+            *counts.entry(month).or_default() += count;
+        }
+        save_access_counts(&self.data_folder_path, &counts)
+    }
+
+    /// Time zone and day start hour imported bank transactions are dated by, zeros reset them.
+    pub fn set_day_boundary(&mut self, utc_offset_minutes: i64, day_start_hour: u64) -> Result<(), Error> {
+        self.save_settings(Settings{utc_offset_minutes: Some(utc_offset_minutes).filter(|m|*m != 0),
+            day_start_hour: Some(day_start_hour).filter(|h|*h != 0), ..self.settings.clone()})
+    }
+
+    fn save_settings(&mut self, settings: Settings) -> Result<(), Error> {
+        settings.validate(&self.currencies)?;
+        settings.save(self.configuration.get_settings_source(), self.data_folder_path.clone())?;
+        self.settings = settings;
+        self.events.publish(EventKind::DictionaryChanged("settings"));
+        Ok(())
+    }
+
+    /// Merges exchange rates into the rates time series and saves it, existing rates
+    /// for the same currency pair and date are replaced.
+    pub fn add_rates(&mut self, rates: Vec<ExchangeRate>) -> Result<(), Error> {
+        let mut merged = Rates::new(self.rates.iter().collect())?;
+        for rate in rates {
+            merged.add(rate)?;
+        }
+        merged.validate(&self.currencies)?;
+        merged.save(self.configuration.get_rates_source(), self.data_folder_path.clone())?;
+        self.rates = merged;
+        self.events.publish(EventKind::DictionaryChanged("rates"));
+        Ok(())
+    }
+
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn get_clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    pub fn register_special_handler(&mut self, code: SubcategoryCode, handler: SpecialHandler) {
+        self.handlers.register(code, handler);
+    }
+
+    /// Recalculates totals from the month of given date, saved totals are invalidated from this month.
+    /// Callers with shared access hold the mutation lock.
+    fn build_totals(&self, from: u64) -> Result<(), Error> {
+        self.invalidate_totals(index_calculator(from))?;
+        self.calculate_totals(from)?;
+        self.check_thresholds()
+    }
+
+    /// Balances after the last stored operation.
+    fn latest_balances(&self) -> Result<HashMap<AccountId, i64>, Error> {
+        match self.data.get(u64::MAX)? {
+            Some(v) => v.lock().unwrap().build_changes(&self.accounts, &self.subcategories, &self.handlers)?
+                .build_totals(),
+            None => Ok(self.opening_balances.clone())
+        }
+    }
+
+    /// Publishes alerts for account thresholds crossed since the previous check.
+    fn check_thresholds(&self) -> Result<(), Error> {
+        let balances = self.latest_balances()?;
+        let current = find_crossed(&self.accounts, &balances);
+        let mut crossed = self.crossed_thresholds.lock().unwrap();
+        for alert in build_alerts(&self.accounts, &balances, &crossed, &current) {
+            self.events.publish(EventKind::BalanceAlert(alert));
+        }
+        *crossed = current;
+        Ok(())
+    }
+
+    /// The watermark is written before changed months can reach the disk.
+    fn invalidate_totals(&self, month: u64) -> Result<(), Error> {
+        let mut watermark = self.totals_watermark.lock().unwrap();
+        if watermark.is_none_or(|w|month < w) {
+            save_watermark(&self.data_folder_path, month)?;
+            *watermark = Some(month);
+        }
+        Ok(())
+    }
+
+    /// Takes saved totals of months up to the watermark and calculates the rest. Months changed outside
+    /// of the database move the watermark back. Everything is calculated when totals were not saved,
+    /// dictionaries were changed or some files were not loaded.
+    fn init_totals(&mut self) -> Result<(), Error> {
+        if !self.data.get_problems().is_empty() || self.configuration.encrypts_files() {
+            return self.calculate_totals(0);
+        }
+        let saved = match load_totals(&self.data_folder_path)? {
+            Some(s) if s.dictionaries_modified == modified_nanos(self.dictionaries_modified) => s,
+            _ => {
+                self.calculate_totals(0)?;
+                return self.save_totals();
+            }
+        };
+        let fingerprints = month_fingerprints(&(self.data_folder_path.clone() + "/dates"))?;
+        let watermark = self.totals_watermark.lock().unwrap().into_iter().chain(saved.first_changed(&fingerprints)).min();
+        let mut start = None;
+        for (key, v) in self.data.get_range(0, watermark.unwrap_or(u64::MAX))? {
+            match saved.months.get(&key) {
+                Some((_, totals)) => v.lock().unwrap().totals = totals.clone(),
+                None => break
+            }
+            start = Some(key);
+        }
+        if watermark.is_some() {
+            self.calculate_totals(start.unwrap_or(0) * 100)?;
+            self.save_totals()?;
+        }
+        Ok(())
+    }
+
+    fn save_totals(&self) -> Result<(), Error> {
+        // saved totals would reveal balances of encrypted books
+        if !self.configuration.encrypts_files() {
+            let fingerprints = month_fingerprints(&(self.data_folder_path.clone() + "/dates"))?;
+            let months = self.data.get_range(0, u64::MAX)?.into_iter()
+                .map(|(key, v)|(key, (fingerprints.get(&key).copied().unwrap_or(0), v.lock().unwrap().totals.clone())))
+                .collect();
+            save_totals(&self.data_folder_path,
+                        &SavedTotals{dictionaries_modified: modified_nanos(self.dictionaries_modified), months})?;
+        }
+        clear_watermark(&self.data_folder_path)?;
+        *self.totals_watermark.lock().unwrap() = None;
+        Ok(())
+    }
+
+    fn calculate_totals(&self, from: u64) -> Result<(), Error> {
         let mut changes: Option<FinanceChanges> = None;
         let idx = index_calculator(from);
-        for (_, v) in self.data.get_range(idx, 99999999)? {
+        let first = self.data.first_key();
+        let mut previous = None;
+        for (key, v) in self.data.get_range(idx, 99999999)? {
             let mut vv = v.lock().unwrap();
             if let Some(c) = &changes {
-                vv.totals = c.build_totals();
+                vv.totals = c.build_totals()
+                    .map_err(|e|Error::new(e.kind(), format!("{} before month {}", e, key)))?;
+                self.accounts.convert_balances(&mut vv.totals, previous.unwrap_or(0) * 100 + 32, key * 100 + 1)?;
+            } else if Some(key) == first && !self.opening_balances.is_empty() {
+                vv.totals = self.opening_balances.clone();
+                self.accounts.convert_balances(&mut vv.totals, key * 100 + 1, key * 100 + 1)?;
             }
-            changes = Some(vv.build_changes(&self.accounts, &self.subcategories)?);
+            changes = Some(vv.build_changes(&self.accounts, &self.subcategories, &self.handlers)?);
+            previous = Some(key);
         }
         Ok(())
     }
 
-    fn build_ops_and_changes(&mut self, date: u64) -> Result<(Vec<FinanceOperation>, FinanceChanges), Error> {
+    /// Applies the mutation once per idempotency key, so a retried request doesn't repeat it. Returns None
+    /// without applying the mutation when a mutation with the key was already applied.
+    /// Keys are saved by flush together with the changes they protect.
+    pub fn once<T>(&self, key: &str, mutation: impl FnOnce(&HomeAccountingDB) -> Result<T, Error>)
+        -> Result<Option<T>, Error> {
+        let now = self.clock.now().duration_since(UNIX_EPOCH).map(|d|d.as_millis() as u64).unwrap_or(0);
+        if !self.idempotency_keys.lock().unwrap().begin(key, now)? {
+            return Ok(None);
+        }
+        // the keys lock is not held by the mutation, flush takes it after the mutation lock
+        let result = mutation(self);
+        self.idempotency_keys.lock().unwrap().finish(key, now, result.is_ok());
+        result.map(Some)
+    }
+
+    /// Operations of other months can be read while the operation is added.
+    pub fn add_operation(&self, op: FinanceOperation) -> Result<(), Error> {
+        // taken before validation, so checks of the opening balance can't race with another operation
+        let _mutation = self.mutation.lock().unwrap();
+        if !is_valid_date(op.date) {
+            return Err(field_error(ErrorKind::InvalidInput, "date", format!("invalid date {}", op.date)));
+        }
+        let accounts = [("accountId", Some(op.get_account())), ("finOpProperies", op.get_second_account())];
+        for (field, account) in accounts.into_iter().filter_map(|(f, a)|a.map(|a|(f, a))) {
+            let a = self.accounts.get(account).map_err(|e|field_error(e.kind(), field, e.to_string()))?;
+            if !a.is_active(op.date) {
+                return Err(field_error(ErrorKind::InvalidInput, field, format!("account {} is closed", a.name)));
+            }
+            if a.retired {
+                return Err(field_error(ErrorKind::InvalidInput, field, format!("account {} is retired", a.name)));
+            }
+        }
+        let subcategory = self.subcategories.get(op.get_subcategory())
+            .map_err(|e|field_error(e.kind(), "subcategoryId", e.to_string()))?;
+        if subcategory.retired {
+            return Err(field_error(ErrorKind::InvalidInput, "subcategoryId",
+                                   format!("subcategory {} is retired", subcategory.name)));
+        }
+        if subcategory.code == SubcategoryCode::Opbl {
+            self.check_opening_balance(&op)?;
+        }
+        if let Some(payee) = op.get_payee() {
+            self.payees.get(payee).map_err(|e|field_error(e.kind(), "payee", e.to_string()))?;
+        }
+        self.parameters.validate(op.get_parameters())
+            .map_err(|e|field_error(e.kind(), "finOpProperies", e.to_string()))?;
+        if self.archive.last_year().is_some_and(|y|op.date / 10000 <= y) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("year {} is archived", op.date / 10000)));
+        }
+        // sums are given in minor units of the account currency, it has to be known
+        self.get_account_currency(op.get_account(), op.date)?;
+        self.check_rules(&op)?;
+        self.check_unlocked(index_calculator(op.date))?;
+        #[cfg(feature = "json")]
+        if let Some(wal) = &self.wal {
+            wal.append(self.clock.now(), &op)?;
+        }
+        let event = EventKind::OperationAdded{date: op.date, account: op.get_account(), subcategory: op.get_subcategory(),
+            summa: op.get_summa()};
+        let from = self.insert_operation(op)?;
+        self.build_totals(from * 100)?;
+        self.events.publish(event);
+        Ok(())
+    }
+
+    /// Runs validation rules and registered validators, all rejections are returned together.
+    fn check_rules(&self, op: &FinanceOperation) -> Result<(), Error> {
+        let mut rejections = Vec::new();
+        for rule in self.validation_rules.iter() {
+            rejections.extend(rule.validate(op)?);
+        }
+        for validator in &self.validators {
+            rejections.extend(validator.validate(op)?);
+        }
+        if rejections.is_empty() {
+            Ok(())
+        } else {
+            Err(rejections_error(rejections))
+        }
+    }
+
+    pub fn get_validation_rules(&self) -> &ValidationRules {
+        &self.validation_rules
+    }
+
+    /// Registers a check of added operations.
+    pub fn add_validator(&mut self, validator: Box<dyn OperationValidator>) {
+        self.validators.push(validator);
+    }
+
+    /// Months locked by other owners can't be changed.
+    fn check_unlocked(&self, month: u64) -> Result<(), Error> {
+        match get_lock(&self.data_folder_path, month)? {
+            Some(lock) if self.lock_owner.as_ref() != Some(&lock.owner) => Err(Error::new(ErrorKind::WouldBlock, lock.describe())),
+            _ => Ok(())
+        }
+    }
+
+    /// Months locked by owner can be changed by this database, months locked by others can't.
+    pub fn set_lock_owner(&mut self, owner: Option<String>) {
+        self.lock_owner = owner;
+    }
+
+    /// Verify, exports and reports over more months than the cache holds read months directly from
+    /// the source when enabled, months used by interactive queries stay cached.
+    pub fn set_scan_cache_bypass(&mut self, bypass: bool) {
+        self.scan_bypasses_cache = bypass;
+    }
+
+    /// Months of a bulk scan, taken from the cache or read past it when bypass is enabled.
+    fn scan_range(&self, from: u64, to: u64) -> Result<DataRange<FinanceRecord>, Error> {
+        if !self.scan_bypasses_cache {
+            return self.data.get_range(from, to);
+        }
+        let mut result = Vec::new();
+        self.data.scan_range(from, to, |key, v|{result.push((key, v.clone())); Ok(())})?;
+        Ok(result)
+    }
+
+    /// Advisory locks of months for batch jobs, all months are locked or none.
+    pub fn lock_months(&self, months: &[u64], owner: &str) -> Result<(), Error> {
+        let now = self.clock.now().duration_since(UNIX_EPOCH).map(|d|d.as_secs()).unwrap_or(0);
+        lock_months(&self.data_folder_path, months, owner, now)
+    }
+
+    /// Locks of other owners are removed only when forced, for locks left by failed jobs.
+    pub fn unlock_months(&self, months: &[u64], owner: &str, force: bool) -> Result<(), Error> {
+        unlock_months(&self.data_folder_path, months, owner, force)
+    }
+
+    pub fn get_month_locks(&self) -> Result<Vec<MonthLock>, Error> {
+        list_locks(&self.data_folder_path)
+    }
+
+    /// Marks stored operations of the month as changed.
+    fn mark_updated(&self, month: u64) {
+        self.data.mark_modified(month);
+        self.events.publish(EventKind::OperationsUpdated{month});
+    }
+
+    /// Events of changes made after the call: added and updated operations, flushed months,
+    /// saved dictionaries and balance alerts.
+    pub fn subscribe(&self) -> Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// Events accepted by the filter, a subscriber with capacity events queued is disconnected.
+    pub fn subscribe_to(&self, capacity: usize, filter: fn(&EventKind) -> bool) -> Receiver<Event> {
+        self.events.subscribe_to(capacity, filter)
+    }
+
+    /// Adds operation to the record of its month, returns the first month which totals have to be rebuilt.
+    fn insert_operation(&self, op: FinanceOperation) -> Result<u64, Error> {
+        let idx = index_calculator(op.date);
+        match self.data.get_key(idx) {
+            Some(key) if key == idx => {
+                let record = self.data.get(idx)?.unwrap();
+                record.lock().unwrap().operations.push(op);
+                self.data.mark_modified(idx);
+                Ok(idx)
+            }
+            prev => {
+                self.data.add(idx, FinanceRecord::new(vec![op]), true)?;
+                Ok(prev.unwrap_or(idx))
+            }
+        }
+    }
+
+    /// Opening balance has to be the only one of the account and precede all its other operations.
+    /// Months are scanned up to the first one with operations of the account: an existing opening balance
+    /// precedes the rest, so later months can't contain it.
+    fn check_opening_balance(&self, op: &FinanceOperation) -> Result<(), Error> {
+        let account = op.get_account();
+        if self.opening_balances.contains_key(&account) {
+            return Err(field_error(ErrorKind::InvalidInput, "date",
+                                   format!("account {} has archived operations before {}", account, op.date)));
+        }
+        let mut result = Ok(());
+        self.data.scan_until(0, u64::MAX, |_, v|{
+            let record = v.lock().unwrap();
+            let mut found = false;
+            for existing in record.operations.iter()
+                .filter(|e|e.get_account() == account || e.get_second_account() == Some(account)) {
+                found = true;
+                if is_opening_balance(existing, &self.subcategories) && existing.get_account() == account {
+                    result = Err(field_error(ErrorKind::AlreadyExists, "accountId",
+                                             format!("account {} already has opening balance at {}", account, existing.date)));
+                    break;
+                }
+                if existing.date < op.date {
+                    result = Err(field_error(ErrorKind::InvalidInput, "date",
+                                             format!("account {} has operations before {}", account, op.date)));
+                }
+            }
+            Ok(found)
+        })?;
+        result
+    }
+
+    /// Logs every change of stored operations to the write-ahead log of the data folder.
+    #[cfg(feature = "json")]
+    pub fn enable_wal(&mut self) {
+        self.wal = Some(Wal::new(&self.data_folder_path));
+    }
+
+    /// Writes the change to the write-ahead log before it is applied, change is built only when the log is on.
+    #[cfg(feature = "json")]
+    fn log_change(&self, change: impl FnOnce() -> WalChange) -> Result<(), Error> {
+        match &self.wal {
+            Some(wal) => wal.append_change(self.clock.now(), change()),
+            None => Ok(())
+        }
+    }
+
+    /// Applies a change read from the write-ahead log.
+    #[cfg(feature = "json")]
+    fn replay(&mut self, change: WalChange) -> Result<(), Error> {
+        match change {
+            WalChange::Operation(op) => self.add_operation(op),
+            WalChange::Recategorize{query, from, to} => self.recategorize(&query, from, to).map(|_|()),
+            WalChange::Dedupe => self.dedupe().map(|_|()),
+            WalChange::Repair{fixers} => self.repair(&fixers, false).map(|_|()),
+            WalChange::MergeSubcategories{source, target} => self.merge_subcategories(source, target).map(|_|()),
+            WalChange::SplitSubcategory{source, rules, default} =>
+                self.split_subcategory(source, &rules, default).map(|_|())
+        }
+    }
+
+    /// Flushes modified months and copies the data folder to a new checkpoint, returns its timestamp.
+    /// Log entries written before the first checkpoint are dropped, nothing can replay them.
+    #[cfg(feature = "json")]
+    pub fn create_checkpoint(&self) -> Result<u64, Error> {
+        // changes made during the copy would be neither in the checkpoint nor after it in the log
+        let _mutation = self.mutation.lock().unwrap();
+        self.flush_locked(false)?;
+        let timestamp = to_millis(self.clock.now());
+        let folder = Path::new(&self.data_folder_path).join(CHECKPOINTS_FOLDER).join(timestamp.to_string());
+        if folder.exists() {
+            return Err(Error::new(ErrorKind::AlreadyExists, format!("checkpoint {} already exists", timestamp)));
+        }
+        copy_folder(Path::new(&self.data_folder_path), &folder, &[WAL_FILE_NAME, CHECKPOINTS_FOLDER])?;
+        if let Some(first) = list_checkpoints(&self.data_folder_path)?.first() {
+            Wal::new(&self.data_folder_path).truncate(*first)?;
+        }
+        Ok(timestamp)
+    }
+
+    /// Creates a checkpoint and removes checkpoints except the last keep_checkpoints ones together with
+    /// write-ahead log entries not needed to restore from the remaining ones. Returns number of removed entries.
+    #[cfg(feature = "json")]
+    pub fn compact(&self, keep_checkpoints: usize) -> Result<usize, Error> {
+        self.create_checkpoint()?;
+        // new entries can't be appended while the log is rewritten
+        let _mutation = self.mutation.lock().unwrap();
+        let checkpoints = list_checkpoints(&self.data_folder_path)?;
+        let (removed, kept) = checkpoints.split_at(checkpoints.len().saturating_sub(keep_checkpoints.max(1)));
+        for checkpoint in removed {
+            fs::remove_dir_all(Path::new(&self.data_folder_path).join(CHECKPOINTS_FOLDER).join(checkpoint.to_string()))?;
+        }
+        Wal::new(&self.data_folder_path).truncate(kept[0])
+    }
+
+    /// Restores state of the database at given unix time in milliseconds into an empty target folder:
+    /// copies the latest checkpoint made before that time and replays the write-ahead log up to it.
+    /// Returns number of replayed operations.
+    #[cfg(feature = "json")]
+    pub fn restore(data_folder_path: &str, target_folder: String, at: u64, configuration: Box<dyn DBConfiguration>,
+                   max_active_items: usize) -> Result<usize, Error> {
+        if fs::read_dir(&target_folder).is_ok_and(|mut entries|entries.next().is_some()) {
+            return Err(Error::new(ErrorKind::AlreadyExists, format!("{} is not empty", target_folder)));
+        }
+        let checkpoint = list_checkpoints(data_folder_path)?.into_iter().rev().find(|c|*c <= at)
+            .ok_or(Error::new(ErrorKind::NotFound, format!("no checkpoint made before {}", at)))?;
+        let source = Path::new(data_folder_path).join(CHECKPOINTS_FOLDER).join(checkpoint.to_string());
+        copy_folder(&source, Path::new(&target_folder), &[])?;
+        let mut db = HomeAccountingDB::load(target_folder, configuration, max_active_items)?;
+        let mut count = 0;
+        for entry in Wal::new(data_folder_path).read()? {
+            if entry.timestamp > checkpoint && entry.timestamp <= at {
+                db.replay(entry.change)?;
+                count += 1;
+            }
+        }
+        db.flush(false)?;
+        Ok(count)
+    }
+
+    /// Flushes modified months and packs the data folder without checkpoints into a backup file,
+    /// encrypted with the attachments crypto processor of the configuration.
+    #[cfg(feature = "json")]
+    pub fn backup(&self, file_name: &str) -> Result<(), Error> {
+        self.flush(false)?;
+        let crypto = self.configuration.get_attachments_crypto();
+        let bundle = create_backup(&self.data_folder_path, &[CHECKPOINTS_FOLDER], crypto.as_deref())?;
+        let temp_name = file_name.to_string() + ".tmp";
+        fs::write(&temp_name, bundle)?;
+        fs::rename(temp_name, file_name)
+    }
+
+    /// Checks checksums of all files in the backup and that it can be decrypted with the crypto processor
+    /// of the configuration, then loads the backed up database. Returns number of its operations.
+    pub fn verify_backup(file_name: &str, configuration: Box<dyn DBConfiguration>) -> Result<usize, Error> {
+        let crypto = configuration.get_attachments_crypto();
+        let files = verify_backup(&fs::read(file_name)?, crypto.as_deref())?;
+        let folder = temp_dir().join(format!("had_backup_{}", process::id())).to_string_lossy().to_string();
+        let _ = fs::remove_dir_all(&folder);
+        let count = extract_files(&files, &folder)
+            .and_then(|_|HomeAccountingDB::load(folder.clone(), configuration, usize::MAX))
+            .and_then(|db|db.get_operations(0, u64::MAX).map(|ops|ops.len()));
+        let _ = fs::remove_dir_all(&folder);
+        count
+    }
+
+    /// Attaches file to index-th operation of given date.
+    pub fn attach(&self, date: u64, index: usize, extension: &str, data: &[u8]) -> Result<String, Error> {
+        let _mutation = self.mutation.lock().unwrap();
+        let idx = index_calculator(date);
+        if self.data.get_key(idx) != Some(idx) {
+            return Err(Error::new(ErrorKind::NotFound, "operation not found"));
+        }
+        let record = self.data.get(idx)?.unwrap();
+        let mut r = record.lock().unwrap();
+        let op = r.operations.iter_mut().filter(|op|op.date == date).nth(index)
+            .ok_or(Error::new(ErrorKind::NotFound, "operation not found"))?;
+        let id = self.attachments.attach(date, extension, data)?;
+        op.add_attachment(id.clone());
+        drop(r);
+        self.mark_updated(idx);
+        Ok(id)
+    }
+
+    pub fn fetch_attachment(&self, id: &str) -> Result<Vec<u8>, Error> {
+        self.attachments.fetch(id)
+    }
+
+    /// Removes attachment files not referenced by any operation.
+    pub fn collect_attachments_garbage(&self) -> Result<Vec<String>, Error> {
+        let mut referenced = HashSet::new();
+        for (_, v) in self.scan_range(0, u64::MAX)? {
+            let record = v.lock().unwrap();
+            for op in &record.operations {
+                referenced.extend(op.get_attachments().iter().cloned());
+            }
+        }
+        self.attachments.collect_garbage(&referenced)
+    }
+
+    /// Version of the month of date, every change of the month increases it, so clients can tell
+    /// whether operations they cached are stale.
+    pub fn get_month_version(&self, date: u64) -> u64 {
+        self.data.get_version(index_calculator(date))
+    }
+
+    /// Versions of months between from and to dates that were ever changed, as yyyymm and version.
+    pub fn get_month_versions(&self, from: u64, to: u64) -> BTreeMap<u64, u64> {
+        self.data.get_versions().into_iter()
+            .filter(|(month, _)|*month >= index_calculator(from) && *month <= index_calculator(to))
+            .collect()
+    }
+
+    pub fn build_ops_and_changes(&self, date: u64) -> Result<(Vec<FinanceOperation>, FinanceChanges), Error> {
         let idx = index_calculator(date);
         if let Some(record) = self.data.get(idx)? {
             let r = record.lock().unwrap();
             let mut changes = r.create_changes();
-            r.update_changes(&mut changes, 0, date - 1, &self.accounts, &self.subcategories)?;
-            let totals = changes.build_totals();
+            r.update_changes(&mut changes, 0, date - 1, &self.accounts, &self.subcategories, &self.handlers)?;
+            let totals = changes.build_totals()?;
             let mut changes = FinanceChanges::new(&totals);
-            r.update_changes(&mut changes, date, date, &self.accounts, &self.subcategories)?;
+            r.update_changes(&mut changes, date, date, &self.accounts, &self.subcategories, &self.handlers)?;
             let ops = r.get_ops(date);
             Ok((ops, changes))
         } else {
@@ -81,17 +1046,897 @@ impl HomeAccountingDB {
         }
     }
 
-    pub fn test(&mut self, date_str: String) -> Result<(), Error> {
+    /// Pins a read-only copy of operations between from and to with balances before from and current
+    /// dictionaries. Long reports can run on the snapshot while the database keeps accepting changes.
+    #[cfg(feature = "json")]
+    pub fn snapshot(&self, from: u64, to: u64) -> Result<Snapshot, Error> {
+        let mut opening_balances = if from > 0 {
+            self.build_ops_and_changes(from - 1)?.1.build_totals()?
+        } else {
+            HashMap::new()
+        };
+        let operations = self.get_operations(from, to)?;
+        let first = operations.iter().map(|op|op.date).min().unwrap_or(from);
+        self.accounts.convert_balances(&mut opening_balances, from, first)?;
+        Snapshot::new(self.accounts.all()?.into_iter().cloned().collect(), self.categories.iter().cloned().collect(),
+                      self.subcategories.iter().cloned().collect(), self.currencies.iter().cloned().collect(),
+                      self.payees.iter().cloned().collect(), opening_balances, operations)
+    }
+
+    /// Moves months of years that ended more than given number of years ago to the cold archive.
+    /// Returns number of archived date folders.
+    pub fn archive_older_than(&mut self, years: u64) -> Result<usize, Error> {
+        let cutoff = (self.clock.today() / 10000).saturating_sub(years);
+        if cutoff == 0 {
+            return Ok(0);
+        }
+        self.flush(false)?;
+        let records = self.data.get_range(0, cutoff * 100 - 1)?;
+        let balances = match records.last() {
+            Some((_, last)) => last.lock().unwrap()
+                .build_changes(&self.accounts, &self.subcategories, &self.handlers)?
+                .build_totals()?,
+            None => return Ok(0)
+        };
+        let keys: Vec<u64> = records.into_iter().map(|(k, _)|k).collect();
+        let dates_folder_path = self.data_folder_path.clone() + "/dates";
+        let mut folders = Vec::new();
+        for year in keys.iter().map(|k|k / 100).collect::<BTreeSet<_>>() {
+            folders.append(&mut self.archive.archive_year(&dates_folder_path, year)?);
+        }
+        self.archive.save_balances(&balances)?;
+        for folder in &folders {
+            fs::remove_dir_all(folder)?;
+        }
+        for key in keys {
+            self.data.remove(key);
+        }
+        self.opening_balances = balances;
+        self.build_totals(0)?;
+        Ok(folders.len())
+    }
+
+    /// Unpacks archived years of the period to a temporary folder and reads their operations.
+    fn get_archived_operations(&self, from: u64, to: u64) -> Result<Vec<FinanceOperation>, Error> {
+        let mut result = Vec::new();
+        for year in self.archive.get_years().iter().filter(|y|**y >= from / 10000 && **y <= to / 10000) {
+            let folder = temp_dir().join(format!("had_archive_{}_{}", process::id(), year))
+                .to_string_lossy().to_string();
+            let _ = fs::remove_dir_all(&folder);
+            let data = self.archive.extract_year(*year, &folder)
+                .and_then(|_|TimeSeriesData::load(folder.clone(),
+                                                  self.configuration.get_main_data_source(self.minor_units.clone()),
+                                                  index_calculator, usize::MAX));
+            let _ = fs::remove_dir_all(&folder);
+            for (_, v) in data?.get_range(index_calculator(from), index_calculator(to))? {
+                let record = v.lock().unwrap();
+                result.extend(record.operations.iter().filter(|op|op.within(from, to)).map(|op|op.copy()));
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn get_operations(&self, from: u64, to: u64) -> Result<Vec<FinanceOperation>, Error> {
+        let mut result = self.get_archived_operations(from, to)?;
+        let (from_key, to_key) = (index_calculator(from), index_calculator(to));
+        let mut add = |_, v: &Arc<Mutex<FinanceRecord>>|{
+            let record = v.lock().unwrap();
+            result.extend(record.operations.iter().filter(|op|op.within(from, to)).map(|op|op.copy()));
+            Ok(())
+        };
+        if self.scan_bypasses_cache && self.data.count_range(from_key, to_key) > self.data.get_max_active_items() {
+            self.data.scan_range(from_key, to_key, add)?;
+        } else {
+            for (key, v) in self.data.get_range(from_key, to_key)? {
+                add(key, &v)?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Page of operations matching the query, sorted as requested, with resolved references.
+    pub fn query(&self, query: &OperationQuery) -> Result<QueryPage<OperationView>, Error> {
+        let mut result = Vec::new();
+        for op in self.get_operations(query.from, query.to)? {
+            if query.matches(&op, &self.subcategories)? {
+                result.push(op);
+            }
+        }
+        query.page(result).resolve(&self.accounts, &self.categories, &self.subcategories)
+    }
+
+    /// Operations between from and to as an Arrow record batch for SQL engines.
+    #[cfg(feature = "arrow")]
+    pub fn to_record_batch(&self, from: u64, to: u64) -> Result<arrow_array::RecordBatch, Error> {
+        operations_to_record_batch(&self.get_operations(from, to)?, &self.accounts, &self.categories, &self.subcategories)
+    }
+
+    pub fn build_subcategory_summary(&self, from: u64, to: u64) -> Result<BTreeMap<SubcategoryId, SummaryItem>, Error> {
+        build_subcategory_summary(self.get_operations(from, to)?.iter(), &self.subcategories)
+    }
+
+    pub fn build_category_summary(&self, from: u64, to: u64) -> Result<BTreeMap<CategoryId, SummaryItem>, Error> {
+        build_category_summary(self.get_operations(from, to)?.iter(), &self.subcategories)
+    }
+
+    /// Summary by category of the month given as yyyymm.
+    pub fn build_monthly_report(&self, month: u64) -> Result<MonthlyReport, Error> {
+        let summary = self.build_category_summary(month * 100 + 1, month * 100 + 31)?;
+        MonthlyReport::new(month, &summary, &self.categories)
+    }
+
+    /// Statement of the account for the month given as yyyymm.
+    pub fn build_statement(&self, account: AccountId, month: u64) -> Result<Statement, Error> {
+        if !(1..=12).contains(&(month % 100)) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("invalid month {}", month)));
+        }
+        if self.archive.last_year().is_some_and(|y|month / 100 <= y) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("month {} is archived", month)));
+        }
+        let acc = self.accounts.get(account)?;
+        let totals = match self.data.get(month)? {
+            Some(record) if self.data.get_key(month) == Some(month) => record.lock().unwrap().totals.clone(),
+            Some(record) => {
+                let mut totals = record.lock().unwrap()
+                    .build_changes(&self.accounts, &self.subcategories, &self.handlers)?
+                    .build_totals()?;
+                let from = self.data.get_key(month).unwrap_or(0) * 100 + 32;
+                self.accounts.convert_balances(&mut totals, from, month * 100 + 1)?;
+                totals
+            }
+            None => self.opening_balances.clone()
+        };
+        let currency = self.get_account_currency(account, month * 100 + 1)?;
+        let mut statement = Statement::new(acc.name.clone(), currency.code, currency.minor_units, month,
+                                           totals.get(&account).copied().unwrap_or(0));
+        for op in self.get_operations(month * 100 + 1, month * 100 + 31)? {
+            let mut changes = FinanceChanges::empty();
+            op.apply(&mut changes, &self.accounts, &self.subcategories, &self.handlers)?;
+            let Some(change) = changes.get(account) else { continue };
+            let description = match (op.get_network(), op.get_payee()) {
+                (Some(netw), _) => netw.to_string(),
+                (None, Some(payee)) => self.payees.get(payee)?.name.clone(),
+                (None, None) => String::new()
+            };
+            statement.add(op.date, self.subcategories.get(op.get_subcategory())?.name.clone(), description,
+                          change.get_income(), change.get_expenditure());
+        }
+        Ok(statement)
+    }
+
+    pub fn build_payee_summary(&self, from: u64, to: u64) -> Result<BTreeMap<PayeeId, SummaryItem>, Error> {
+        build_payee_summary(self.get_operations(from, to)?.iter(), &self.subcategories)
+    }
+
+    /// Categories with unusual expenditure in months from..=to (yyyymm), see build_anomalies_report.
+    pub fn build_anomalies_report(&self, from: u64, to: u64, trailing_months: u64, multiple: f64)
+        -> Result<Vec<SpendingAnomaly>, Error> {
+        let operations = self.get_operations(sub_months(from * 100 + 1, trailing_months), to * 100 + 31)?;
+        build_anomalies_report(operations.iter(), &self.subcategories, from, to, trailing_months, multiple)
+    }
+
+    pub fn build_tax_report(&self, year: u64) -> Result<TaxReport, Error> {
+        let operations = self.get_operations(year * 10000 + 101, year * 10000 + 1231)?;
+        build_tax_report(year, operations.iter(), &self.accounts, &self.subcategories, &self.categories)
+    }
+
+    /// Custom reports defined in the reports file, read on every call so edited specs apply immediately.
+    pub fn get_report_specs(&self) -> Result<Vec<ReportSpec>, Error> {
+        load_report_specs(self.data_folder_path.clone(), self.configuration.get_report_specs_source())
+    }
+
+    /// Runs the named custom report, from and to replace dates of its filter.
+    pub fn build_custom_report(&self, name: &str, from: Option<u64>, to: Option<u64>) -> Result<CustomReport, Error> {
+        let spec = self.get_report_specs()?.into_iter().find(|s|s.name == name)
+            .ok_or(Error::new(ErrorKind::NotFound, format!("unknown report {}", name)))?;
+        let mut query = OperationQuery::parse(&spec.filter)?;
+        query.from = from.unwrap_or(query.from);
+        query.to = to.unwrap_or(query.to);
+        let operations = self.get_operations(query.from, query.to)?;
+        build_custom_report(&spec, &query, operations.iter(), &self.accounts, &self.categories, &self.subcategories)
+    }
+
+    /// Balances at the end of the previous and given year and summary by category of the year.
+    pub fn build_annual_report(&self, year: u64) -> Result<AnnualReport, Error> {
+        let opening = self.build_ops_and_changes(year.saturating_sub(1) * 10000 + 1231)?.1.build_totals()?;
+        let closing = self.build_ops_and_changes(year * 10000 + 1231)?.1.build_totals()?;
+        let summary = self.build_category_summary(year * 10000 + 101, year * 10000 + 1231)?;
+        AnnualReport::new(year, &self.accounts, &opening, &closing, &summary, &self.categories)
+    }
+
+    /// Summary by category of the period and balances of accounts at the end of every month of it,
+    /// the last balance is taken at the end of the period.
+    pub fn build_html_report(&self, from: u64, to: u64) -> Result<HtmlReport, Error> {
+        let mut dates = Vec::new();
+        let mut month = from / 100;
+        while month <= to / 100 {
+            dates.push((month * 100 + days_in_month(month / 100, month % 100)).min(to));
+            month = if month % 100 == 12 {month + 89} else {month + 1};
+        }
+        let mut history: BTreeMap<AccountId, Vec<i64>> = BTreeMap::new();
+        for (i, date) in dates.iter().enumerate() {
+            for (id, balance) in self.build_ops_and_changes(*date)?.1.build_totals()? {
+                history.entry(id).or_insert_with(||vec![0; dates.len()])[i] = balance;
+            }
+        }
+        let mut balances = Vec::new();
+        for (id, values) in history {
+            if values.iter().any(|v|*v != 0) {
+                balances.push((self.accounts.get(id)?.name.clone(), values));
+            }
+        }
+        balances.sort_by(|a, b|a.0.cmp(&b.0));
+        let summary = self.build_category_summary(from, to)?;
+        HtmlReport::new(from, to, &summary, &self.categories, dates, balances)
+    }
+
+    /// All operations as GnuCash XML book, balances of archived years become opening balances.
+    pub fn export_gnucash(&self) -> Result<String, Error> {
+        let base_currency = self.settings.base_currency.clone()
+            .or_else(||self.accounts.iter().min_by_key(|a|a.id).map(|a|a.currency.clone()))
+            .unwrap_or_default();
+        let mut export = GnuCashExport::new(&self.accounts, &self.categories, &self.subcategories, &self.payees,
+                                            &self.handlers, base_currency);
+        if let Some(key) = self.data.first_key() {
+            let mut opening: Vec<(&AccountId, &i64)> = self.opening_balances.iter().collect();
+            opening.sort();
+            for (account, summa) in opening {
+                export.add_opening_balance(key * 100 + 1, *account, *summa)?;
+            }
+        }
+        for op in self.get_operations(0, u64::MAX)? {
+            export.add(&op)?;
+        }
+        Ok(export.to_xml())
+    }
+
+    pub fn build_network_summary(&self, from: u64, to: u64) -> Result<BTreeMap<(u64, String), SummaryItem>, Error> {
+        build_network_summary(self.get_operations(from, to)?.iter(), &self.subcategories)
+    }
+
+    /// Balances of accounts that are not closed at given date.
+    pub fn get_active_balances(&self, date: u64) -> Result<FinanceChanges, Error> {
+        let (_, mut changes) = self.build_ops_and_changes(date)?;
+        changes.retain_active(&self.accounts, date);
+        Ok(changes)
+    }
+
+    /// End of day balances of all accounts at every date. Dates are grouped by month, so every month
+    /// is loaded and replayed once from its starting totals however many dates it has.
+    pub fn get_balance_snapshots(&self, dates: &[u64]) -> Result<BTreeMap<u64, HashMap<AccountId, i64>>, Error> {
+        let mut by_month: BTreeMap<Option<u64>, Vec<u64>> = BTreeMap::new();
+        for date in dates {
+            if !is_valid_date(*date) {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("invalid date {}", date)));
+            }
+            by_month.entry(self.data.get_key(index_calculator(*date))).or_default().push(*date);
+        }
+        let mut result = BTreeMap::new();
+        for (key, mut month_dates) in by_month {
+            let Some(key) = key else {
+                // before the first stored month
+                result.extend(month_dates.into_iter().map(|date|(date, self.opening_balances.clone())));
+                continue;
+            };
+            month_dates.sort();
+            let Some(record) = self.data.get(key)? else { continue };
+            let r = record.lock().unwrap();
+            let mut ops: Vec<&FinanceOperation> = r.operations.iter().collect();
+            ops.sort_by_key(|op|op.date);
+            let mut changes = r.create_changes();
+            let mut next = ops.into_iter().peekable();
+            for date in month_dates {
+                while let Some(op) = next.next_if(|op|op.date <= date) {
+                    op.apply(&mut changes, &self.accounts, &self.subcategories, &self.handlers)?;
+                }
+                result.insert(date, changes.build_totals()?);
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn build_loans_report(&self, date: u64) -> Result<Vec<LoanStatus>, Error> {
+        // loans without start date count all operations of their accounts
+        let from = self.loans.iter().map(|l|l.start.unwrap_or(0)).min().unwrap_or(date);
+        build_loans_report(&self.loans, self.get_operations(from, date)?.iter(), &self.subcategories, date)
+    }
+
+    pub fn build_net_worth(&self, date: u64) -> Result<NetWorth, Error> {
+        let (_, balances) = self.build_ops_and_changes(date)?;
+        let holdings = build_holdings(self.get_operations(0, date)?.iter(), &self.subcategories)?;
+        let mut result = build_net_worth(&self.accounts, &balances, &holdings, &self.instruments, date)?;
+        if let Some(base) = &self.settings.base_currency {
+            result.convert_totals(base, &self.rates, date)?;
+        }
+        Ok(result)
+    }
+
+    pub fn build_goals_report(&self, date: u64) -> Result<Vec<GoalProgress>, Error> {
+        let (_, balances) = self.build_ops_and_changes(date)?;
+        Ok(build_goals_report(&self.goals, &balances, date))
+    }
+
+    /// Budget versus actual of the month given as yyyymm.
+    pub fn build_budget_report(&self, month: u64) -> Result<Vec<BudgetStatus>, Error> {
+        self.build_scenario_budget_report(&Scenario::new(), month)
+    }
+
+    /// Hypothetical operations must reference existing accounts and subcategories.
+    fn check_scenario(&self, scenario: &Scenario) -> Result<(), Error> {
+        for op in scenario.iter() {
+            check_date(op.date)?;
+            self.accounts.get(op.get_account())?;
+            if let Some(account) = op.get_second_account() {
+                self.accounts.get(account)?;
+            }
+            self.subcategories.get(op.get_subcategory())?;
+        }
+        Ok(())
+    }
+
+    /// Budget report of the month with hypothetical operations of the scenario counted as spent.
+    pub fn build_scenario_budget_report(&self, scenario: &Scenario, month: u64) -> Result<Vec<BudgetStatus>, Error> {
+        if !(1..=12).contains(&(month % 100)) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("invalid month {}", month)));
+        }
+        self.check_scenario(scenario)?;
+        let from = self.budgets.iter().map(|l|l.from_month).min().unwrap_or(month).min(month) * 100 + 1;
+        let operations = self.get_operations(from, month * 100 + 31)?;
+        Ok(build_budget_report(&self.budgets, operations.iter().chain(scenario.get_operations(from, month * 100 + 31)),
+                               month))
+    }
+
+    /// Balances at the end of date with hypothetical operations up to it applied. Start balances are
+    /// real balances at the end of date, hypothetical operations are their income and expenditure.
+    pub fn build_scenario_balances(&self, scenario: &Scenario, date: u64) -> Result<FinanceChanges, Error> {
+        self.check_scenario(scenario)?;
+        let mut changes = FinanceChanges::new(&self.build_ops_and_changes(date)?.1.build_totals()?);
+        for op in scenario.get_operations(0, date) {
+            op.apply(&mut changes, &self.accounts, &self.subcategories, &self.handlers)?;
+        }
+        Ok(changes)
+    }
+
+    /// Progress of goals at date, hypothetical operations up to it included.
+    pub fn build_scenario_goals_report(&self, scenario: &Scenario, date: u64) -> Result<Vec<GoalProgress>, Error> {
+        Ok(build_goals_report(&self.goals, &self.build_scenario_balances(scenario, date)?, date))
+    }
+
+    /// Subcategories of the month given as yyyymm which expenditure exceeds their soft or hard limit.
+    pub fn build_over_limit_report(&self, month: u64) -> Result<Vec<LimitStatus>, Error> {
+        if !(1..=12).contains(&(month % 100)) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("invalid month {}", month)));
+        }
+        Ok(build_limits_report(&self.subcategories, self.get_operations(month * 100 + 1, month * 100 + 31)?.iter()))
+    }
+
+    /// Builds subcategory suggester from operations in given period.
+    pub fn build_suggester(&self, from: u64, to: u64) -> Result<SubcategorySuggester, Error> {
+        Ok(SubcategorySuggester::new(self.get_operations(from, to)?.iter()))
+    }
+
+    /// Verifies that stored totals of each month match end balances of the previous month.
+    pub fn check_totals(&self) -> Result<Vec<TotalsMismatch>, Error> {
+        check_totals(&self.scan_range(0, u64::MAX)?, &self.accounts, &self.subcategories, &self.handlers)
+    }
+
+    /// Finds operations referencing missing accounts, subcategories or payees.
+    pub fn check_references(&self) -> Result<Vec<ReferenceViolation>, Error> {
+        check_references(&self.scan_range(0, u64::MAX)?, &self.accounts, &self.subcategories, &self.payees)
+    }
+
+    /// Finds exact duplicate operations within months.
+    pub fn check_duplicates(&self) -> Result<Vec<DuplicateOperation>, Error> {
+        check_duplicates(&self.scan_range(0, u64::MAX)?)
+    }
+
+    /// Removes exact duplicate operations, returns number of removed operations.
+    pub fn dedupe(&self) -> Result<usize, Error> {
+        let _mutation = self.mutation.lock().unwrap();
+        #[cfg(feature = "json")]
+        self.log_change(||WalChange::Dedupe)?;
+        let mut removed = 0;
+        let mut first_changed = None;
+        for (key, v) in self.data.get_range(0, u64::MAX)? {
+            let count = v.lock().unwrap().remove_duplicates();
+            if count > 0 {
+                self.mark_updated(key);
+                first_changed.get_or_insert(key);
+                removed += count;
+            }
+        }
+        if let Some(key) = first_changed {
+            self.build_totals(key * 100)?;
+        }
+        Ok(removed)
+    }
+
+    /// Moves operations of one subcategory matching the query to another subcategory,
+    /// returns number of changed operations. Archived years are not changed.
+    pub fn recategorize(&self, query: &OperationQuery, from: SubcategoryId, to: SubcategoryId)
+        -> Result<usize, Error> {
+        let _mutation = self.mutation.lock().unwrap();
+        self.subcategories.get(to)?;
+        #[cfg(feature = "json")]
+        self.log_change(||WalChange::Recategorize{query: query.clone(), from, to})?;
+        self.move_operations(query.from, query.to, from,
+                             |op, subcategories|Ok(query.matches(op, subcategories)?.then_some(to)))
+    }
+
+    /// Moves all operations of source subcategory to target and removes source from the dictionary,
+    /// source still referenced by archived operations is retired instead.
+    pub fn merge_subcategories(&mut self, source: SubcategoryId, target: SubcategoryId) -> Result<usize, Error> {
+        if source == target {
+            return Err(Error::new(ErrorKind::InvalidInput, "cannot merge subcategory into itself"));
+        }
+        self.subcategories.get(target)?;
+        self.check_removable(source)?;
+        #[cfg(feature = "json")]
+        self.log_change(||WalChange::MergeSubcategories{source, target})?;
+        let moved = self.move_operations(0, u64::MAX, source, |_, _|Ok(Some(target)))?;
+        let retired = self.remove_subcategory(source)?;
+        self.log_maintenance(&format!("merged subcategory {} into {}, {} operations moved{}", source.0, target.0, moved,
+                                      if retired {", source retired"} else {""}))?;
+        Ok(moved)
+    }
+
+    /// Moves operations of source subcategory to the subcategory of the first rule which text is part
+    /// of operation NETW parameter, the rest goes to default. Source is removed (or retired when archived
+    /// operations reference it) when default is given.
+    pub fn split_subcategory(&mut self, source: SubcategoryId, rules: &[(String, SubcategoryId)],
+                             default: Option<SubcategoryId>) -> Result<usize, Error> {
+        for target in rules.iter().map(|(_, t)|*t).chain(default) {
+            self.subcategories.get(target)?;
+        }
+        if default.is_some() {
+            self.check_removable(source)?;
+        }
+        #[cfg(feature = "json")]
+        self.log_change(||WalChange::SplitSubcategory{source, rules: rules.to_vec(), default})?;
+        let moved = self.move_operations(0, u64::MAX, source, |op, _|{
+            let network = op.get_network().unwrap_or_default();
+            Ok(rules.iter().find(|(text, _)|network.contains(text.as_str())).map(|(_, t)|*t).or(default))
+        })?;
+        let removed = match default {
+            Some(_) if self.remove_subcategory(source)? => ", retired",
+            Some(_) => ", removed",
+            None => ""
+        };
+        self.log_maintenance(&format!("split subcategory {} by {} rules{}, {} operations moved", source.0,
+                                      rules.len(), removed, moved))?;
+        Ok(moved)
+    }
+
+    /// Retired account stays in the dictionary for history, but new operations can't use it.
+    pub fn retire_account(&mut self, account: AccountId) -> Result<(), Error> {
+        self.accounts.set_retired(account, true)?;
+        self.save_accounts()?;
+        self.log_maintenance(&format!("retired account {}", account.0))
+    }
+
+    pub fn restore_account(&mut self, account: AccountId) -> Result<(), Error> {
+        self.accounts.set_retired(account, false)?;
+        self.save_accounts()?;
+        self.log_maintenance(&format!("restored account {}", account.0))
+    }
+
+    /// Retired subcategory stays in the dictionary for history, but new operations can't use it.
+    pub fn retire_subcategory(&mut self, subcategory: SubcategoryId) -> Result<(), Error> {
+        self.subcategories.set_retired(subcategory, true)?;
+        self.save_subcategories()?;
+        self.log_maintenance(&format!("retired subcategory {}", subcategory.0))
+    }
+
+    pub fn restore_subcategory(&mut self, subcategory: SubcategoryId) -> Result<(), Error> {
+        self.subcategories.set_retired(subcategory, false)?;
+        self.save_subcategories()?;
+        self.log_maintenance(&format!("restored subcategory {}", subcategory.0))
+    }
+
+    /// Monthly soft and hard spending limits of the expense subcategory, None removes the limit.
+    pub fn set_spending_limits(&mut self, subcategory: SubcategoryId, soft_limit: Option<i64>, hard_limit: Option<i64>)
+        -> Result<(), Error> {
+        self.subcategories.set_limits(subcategory, soft_limit, hard_limit)?;
+        self.save_subcategories()
+    }
+
+    pub fn get_retired_accounts(&self) -> Vec<&Account> {
+        let mut result: Vec<&Account> = self.accounts.iter().filter(|a|a.retired).collect();
+        result.sort_by_key(|a|a.id);
+        result
+    }
+
+    pub fn get_retired_subcategories(&self) -> Vec<&Subcategory> {
+        let mut result: Vec<&Subcategory> = self.subcategories.iter().filter(|s|s.retired).collect();
+        result.sort_by_key(|s|s.id);
+        result
+    }
+
+    /// Changes subcategory of operations in from..=to to the one returned by target, returns number of changed operations.
+    /// Callers with shared access hold the mutation lock.
+    fn move_operations(&self, from: u64, to: u64, subcategory: SubcategoryId,
+                       target: impl Fn(&FinanceOperation, &Subcategories) -> Result<Option<SubcategoryId>, Error>)
+        -> Result<usize, Error> {
+        let mut changed = 0;
+        let mut first_changed = None;
+        for (key, v) in self.data.get_range(index_calculator(from), index_calculator(to))? {
+            let mut record = v.lock().unwrap();
+            let mut count = 0;
+            for op in record.operations.iter_mut().filter(|op|op.get_subcategory() == subcategory && op.within(from, to)) {
+                if let Some(t) = target(op, &self.subcategories)? {
+                    op.set_subcategory(t);
+                    count += 1;
+                }
+            }
+            drop(record);
+            if count > 0 {
+                self.mark_updated(key);
+                first_changed.get_or_insert(key);
+                changed += count;
+            }
+        }
+        if let Some(key) = first_changed {
+            self.build_totals(key * 100)?;
+        }
+        Ok(changed)
+    }
+
+    /// Subcategory referenced by templates must stay in the dictionary.
+    fn check_removable(&self, subcategory: SubcategoryId) -> Result<(), Error> {
+        self.subcategories.get(subcategory)?;
+        if self.templates.iter().any(|t|t.subcategory == subcategory) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("subcategory {} is used by templates", subcategory.0)));
+        }
+        Ok(())
+    }
+
+    /// Operations are saved first, so stored data never references a removed subcategory.
+    /// Subcategory referenced by archived operations is retired instead, returns true in this case.
+    fn remove_subcategory(&mut self, subcategory: SubcategoryId) -> Result<bool, Error> {
+        self.flush(false)?;
+        let archived = match self.archive.last_year() {
+            Some(year) => self.get_archived_operations(0, year * 10000 + 1231)?.iter()
+                .any(|op|op.get_subcategory() == subcategory),
+            None => false
+        };
+        if archived {
+            self.subcategories.set_retired(subcategory, true)?;
+        } else {
+            self.subcategories.remove(subcategory)?;
+        }
+        self.save_subcategories()?;
+        Ok(archived)
+    }
+
+    fn save_subcategories(&mut self) -> Result<(), Error> {
+        self.subcategories.save(self.configuration.get_subcategories_source(), self.data_folder_path.clone())?;
+        self.dictionaries_modified = dictionaries_modified_time(&self.data_folder_path);
+        self.events.publish(EventKind::DictionaryChanged("subcategories"));
+        Ok(())
+    }
+
+    fn save_accounts(&mut self) -> Result<(), Error> {
+        self.accounts.save(self.configuration.get_accounts_source(), self.data_folder_path.clone())?;
+        self.dictionaries_modified = dictionaries_modified_time(&self.data_folder_path);
+        self.events.publish(EventKind::DictionaryChanged("accounts"));
+        Ok(())
+    }
+
+    /// Changes account currency from the first day of month (yyyymm), balances are converted by rate
+    /// in millionths (units of the new currency for one unit of the old one).
+    pub fn change_account_currency(&mut self, account: AccountId, month: u64, currency: String, rate: i64)
+        -> Result<(), Error> {
+        if !self.currencies.is_empty() {
+            self.currencies.get(&currency)?;
+        }
+        let date = month * 100 + 1;
+        if !is_valid_date(date) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("invalid month {}", month)));
+        }
+        let previous = self.accounts.get(account)?.currency.clone();
+        self.accounts.change_currency(account, date, currency.clone(), rate)?;
+        self.save_accounts()?;
+        // totals of the month itself are converted, so calculation starts from the previous month
+        let previous_month = self.data.get_key(month - 1).unwrap_or(month);
+        self.build_totals(previous_month * 100 + 1)?;
+        self.log_maintenance(&format!("changed account {} currency from {} to {} at {} with rate {}",
+                                      account.0, previous, currency, date, rate))
+    }
+
+    /// Moves non cash accounts closed before given date out of the accounts file to archive shards,
+    /// which are loaded only when an archived account is accessed. Returns number of archived accounts.
+    pub fn archive_accounts(&mut self, before: u64) -> Result<usize, Error> {
+        let archived = self.accounts.archive(before)?;
+        if archived > 0 {
+            self.save_accounts()?;
+            self.log_maintenance(&format!("archived {} accounts closed before {}", archived, before))?;
+        }
+        Ok(archived)
+    }
+
+    /// Appends a line to the maintenance log kept in the data folder.
+    fn log_maintenance(&self, message: &str) -> Result<(), Error> {
+        let seconds = self.clock.now().duration_since(UNIX_EPOCH).map(|d|d.as_secs()).unwrap_or(0);
+        let mut file = OpenOptions::new().create(true).append(true)
+            .open(self.data_folder_path.clone() + "/" + MAINTENANCE_LOG_FILE_NAME)?;
+        writeln!(file, "{} {}", seconds, message)
+    }
+
+    /// Files skipped during load with recover_errors option.
+    pub fn get_load_problems(&self) -> &[LoadProblem] {
+        self.data.get_problems()
+    }
+
+    pub fn verify(&self) -> Result<(), Error> {
+        self.verify_months(None)
+    }
+
+    /// Verifies only months which files were changed since the last successful verification,
+    /// all months are verified when dictionaries were changed.
+    pub fn verify_incremental(&self) -> Result<(), Error> {
+        let checksums = build_checksums(&self.data_folder_path)?;
+        let months = changed_months(&checksums, &load_checksums(&self.data_folder_path)?);
+        println!("{} changed months", months.len());
+        self.verify_months(Some(&months))?;
+        save_checksums(&self.data_folder_path, &checksums)
+    }
+
+    fn verify_months(&self, months: Option<&BTreeSet<u64>>) -> Result<(), Error> {
+        let all = self.scan_range(0, u64::MAX)?;
+        let selected: Vec<bool> = all.iter().map(|(k, _)|months.map(|m|m.contains(k)).unwrap_or(true)).collect();
+        let records: DataRange<FinanceRecord> = all.iter().zip(&selected)
+            .filter(|(_, s)|**s)
+            .map(|(r, _)|r.clone())
+            .collect();
+        // totals of a month depend on the previous one, so neighbours of changed months are checked too
+        let mut segments: Vec<DataRange<FinanceRecord>> = Vec::new();
+        let mut previous = false;
+        for (i, record) in all.iter().enumerate() {
+            let near = selected[i] || (i > 0 && selected[i - 1]) || selected.get(i + 1) == Some(&true);
+            if near {
+                if !previous {
+                    segments.push(Vec::new());
+                }
+                segments.last_mut().unwrap().push(record.clone());
+            }
+            previous = near;
+        }
+        let problems = self.get_load_problems();
+        for p in problems {
+            println!("{}: {}", p.file, p.message);
+        }
+        let violations = check_references(&records, &self.accounts, &self.subcategories, &self.payees)?;
+        for v in &violations {
+            println!("{}", v.describe());
+        }
+        let duplicates = check_duplicates(&records)?;
+        for d in &duplicates {
+            println!("{}", d.describe());
+        }
+        let openings = check_opening_balances(&all, &self.subcategories)?;
+        for o in &openings {
+            println!("{}", o.describe());
+        }
+        let manifest = self.check_manifest(&all)?;
+        for m in &manifest {
+            println!("{}", m.describe());
+        }
+        let mut mismatches = Vec::new();
+        for segment in &segments {
+            mismatches.append(&mut check_totals(segment, &self.accounts, &self.subcategories, &self.handlers)?);
+        }
+        for m in &mismatches {
+            println!("totals mismatch: month {} account {}: expected {} actual {}",
+                     m.month, self.accounts.get(m.account)?.name, m.expected, m.actual);
+        }
+        if problems.is_empty() && violations.is_empty() && duplicates.is_empty() && openings.is_empty() &&
+            manifest.is_empty() && mismatches.is_empty() {
+            println!("No problems found");
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::InvalidData, "verification failed"))
+        }
+    }
+
+    pub fn test(&self, date_str: String) -> Result<(), Error> {
         let d: u64 = date_str.parse()
             .map_err(|_|Error::new(ErrorKind::InvalidInput, "invalid date"))?;
-        let (_, changes) = self.build_ops_and_changes(d)?;
+        let changes = self.get_active_balances(d)?;
         println!("{}", d);
         changes.print(&self.accounts)?;
         println!("{}", self.data.get_active_items());
         Ok(())
     }
     
-    pub fn test_lru(&mut self, mut items: usize) -> Result<(), Error>{
+    /// Writes modified months to disk, independent months are written in parallel when requested.
+    pub fn flush(&self, parallel: bool) -> Result<(), Error> {
+        // saved totals and the watermark must not be written in the middle of recalculation
+        let _mutation = self.mutation.lock().unwrap();
+        self.flush_locked(parallel)
+    }
+
+    /// Callers hold the mutation lock.
+    fn flush_locked(&self, parallel: bool) -> Result<(), Error> {
+        let modified = self.data.get_modified();
+        for month in &modified {
+            self.check_unlocked(*month)?;
+        }
+        let _batch = self.data.flush(parallel)?;
+        forget_months(&self.data_folder_path, &modified)?;
+        if !modified.is_empty() {
+            save_month_versions(&self.data_folder_path, &self.data.get_versions())?;
+        }
+        for month in modified {
+            self.events.publish(EventKind::MonthFlushed{month, version: self.data.get_version(month)});
+        }
+        if self.totals_watermark.lock().unwrap().is_some() && self.data.get_problems().is_empty() {
+            self.save_totals()?;
+        }
+        self.save_access_counts()?;
+        self.idempotency_keys.lock().unwrap().save(&self.data_folder_path)?;
+        #[cfg(feature = "server")]
+        if let Some(replica) = &self.replica {
+            replica.send_batch(&_batch)?;
+        }
+        Ok(())
+    }
+
+    /// Ships every flushed month and reloaded dictionary to the standby at address,
+    /// encrypted with the key shared with the standby.
+    #[cfg(feature = "server")]
+    pub fn set_replica(&mut self, address: String, crypto: Box<dyn CryptoProcessor>) {
+        self.replica = Some(ReplicationSender::new(address, self.data_folder_path.clone(), crypto));
+    }
+
+    /// Sends the whole data folder to the standby, used to seed a new standby. Returns number of sent files.
+    #[cfg(feature = "server")]
+    pub fn replicate_all(&self) -> Result<usize, Error> {
+        let replica = self.replica.as_ref()
+            .ok_or(Error::new(ErrorKind::InvalidInput, "replica is not configured"))?;
+        let mut files = Vec::new();
+        list_files(&self.data_folder_path, &mut files)?;
+        replica.send_files(&files)?;
+        Ok(files.len())
+    }
+
+    /// Runs selected fixers over all months and returns changes they make, nothing is changed on dry run.
+    pub fn repair(&self, fixers: &[Fixer], dry_run: bool) -> Result<Vec<RepairChange>, Error> {
+        let _mutation = self.mutation.lock().unwrap();
+        #[cfg(feature = "json")]
+        if !dry_run {
+            self.log_change(||WalChange::Repair{fixers: fixers.to_vec()})?;
+        }
+        let mut changes = Vec::new();
+        let mut first_changed = None;
+        if fixers.contains(&Fixer::Totals) {
+            let records = self.data.get_range(0, u64::MAX)?;
+            for m in check_totals(&records, &self.accounts, &self.subcategories, &self.handlers)? {
+                changes.push(RepairChange{fixer: Fixer::Totals, month: m.month,
+                    description: format!("account {} total {} -> {}", m.account, m.actual, m.expected)});
+            }
+            if !dry_run {
+                first_changed = Some(0);
+            }
+        }
+        let mut misplaced = Vec::new();
+        for (key, v) in self.data.get_range(0, u64::MAX)? {
+            let mut record = v.lock().unwrap();
+            let count = changes.len();
+            if fixers.contains(&Fixer::Dates) {
+                for (index, date) in find_date_fixes(key, &record) {
+                    let description = format!("date {} -> {}", record.operations[index].date, date);
+                    changes.push(RepairChange::operation(Fixer::Dates, key, &record, index, description));
+                    if !dry_run {
+                        record.operations[index].date = date;
+                    }
+                }
+            }
+            if fixers.contains(&Fixer::Signs) {
+                for index in find_sign_fixes(&record, &self.subcategories)? {
+                    let summa = record.operations[index].get_summa();
+                    changes.push(RepairChange::operation(Fixer::Signs, key, &record, index,
+                                                         format!("summa {} -> {}", summa, -summa)));
+                    if !dry_run {
+                        record.operations[index].set_summa(-summa);
+                    }
+                }
+            }
+            if fixers.contains(&Fixer::Months) {
+                let indices = find_misplaced(key, &record);
+                for index in &indices {
+                    let description = format!("moved to month {}", record.operations[*index].date / 100);
+                    changes.push(RepairChange::operation(Fixer::Months, key, &record, *index, description));
+                }
+                if !dry_run {
+                    misplaced.append(&mut record.take_operations(&indices.into_iter().collect()));
+                }
+            }
+            drop(record);
+            if changes.len() > count && !dry_run {
+                self.mark_updated(key);
+                first_changed = Some(first_changed.map_or(key, |f: u64|f.min(key)));
+            }
+        }
+        for op in misplaced {
+            self.events.publish(EventKind::OperationsUpdated{month: index_calculator(op.date)});
+            let from = self.insert_operation(op)?;
+            first_changed = Some(first_changed.map_or(from, |f: u64|f.min(from)));
+        }
+        if let Some(key) = first_changed {
+            self.build_totals(key * 100)?;
+        }
+        Ok(changes)
+    }
+
+    /// Recalculates totals of all months.
+    pub fn rebuild_totals(&self) -> Result<(), Error> {
+        let _mutation = self.mutation.lock().unwrap();
+        self.build_totals(0)
+    }
+
+    pub fn bench(&self) -> Result<(), Error> {
+        let start = Instant::now();
+        let count = self.get_operations(0, u64::MAX)?.len();
+        println!("Full scan of {} operations finished in {} us", count, start.elapsed().as_micros());
+        let start = Instant::now();
+        self.rebuild_totals()?;
+        println!("Totals rebuild finished in {} us", start.elapsed().as_micros());
+        let start = Instant::now();
+        let summary = self.build_category_summary(0, u64::MAX)?;
+        println!("Summary of {} categories finished in {} us", summary.len(), start.elapsed().as_micros());
+        Ok(())
+    }
+
+    /// Prints number of operations and estimated memory they take.
+    pub fn stats(&self) -> Result<(), Error> {
+        let records = self.data.get_range(0, u64::MAX)?;
+        let mut parameter_counts = [0usize; 3];
+        let mut spilled_parameters = 0;
+        for (_, v) in &records {
+            for op in &v.lock().unwrap().operations {
+                let n = op.get_parameters().len();
+                parameter_counts[n.min(2)] += 1;
+                if n > 1 {
+                    spilled_parameters += n;
+                }
+            }
+        }
+        let operations: usize = parameter_counts.iter().sum();
+        println!("Months: {}", records.len());
+        println!("Operations: {}, without parameters: {}, with one parameter: {}, with more: {}", operations,
+                 parameter_counts[0], parameter_counts[1], parameter_counts[2]);
+        println!("Operation size: {} bytes, parameter size: {} bytes", size_of::<FinanceOperation>(),
+                 size_of::<FinOpParameter>());
+        println!("Estimated operations memory: {} bytes", operations * size_of::<FinanceOperation>() +
+            spilled_parameters * size_of::<FinOpParameter>());
+        let stats = self.data.get_stats();
+        println!("Month cache: {} active, {} hits, {} misses", self.data.get_active_items(), stats.get_hits(),
+                 stats.get_misses());
+        if let Some(archived) = self.accounts.get_archived() {
+            let stats = archived.get_stats();
+            println!("Archived accounts: {}, shards loaded: {} of {}, {} hits, {} misses", archived.len(),
+                     archived.get_loaded_shards(), archived.get_shard_count(), stats.get_hits(), stats.get_misses());
+        }
+        Ok(())
+    }
+
+    /// Estimates memory used by months, dictionaries and shared parameter strings. Loads all months.
+    pub fn memory_usage(&self) -> Result<MemoryReport, Error> {
+        let records = self.data.get_range(0, u64::MAX)?;
+        let locked: Vec<_> = records.iter().map(|(_, v)|v.lock().unwrap()).collect();
+        let mut report = MemoryReport{
+            dictionaries_bytes: dictionaries_bytes(&self.accounts, &self.categories, &self.subcategories,
+                                                   &self.payees),
+            ..MemoryReport::default()
+        };
+        for record in &locked {
+            report.add_record(record);
+        }
+        add_shared_strings(&mut report, locked.iter().map(|r|&**r));
+        Ok(report)
+    }
+
+    pub fn test_lru(&self, mut items: usize) -> Result<(), Error>{
         while items > 0 {
             self.data.add(items as u64, FinanceRecord::new(Vec::new()), false)?;
             items -= 1;
@@ -100,7 +1945,99 @@ impl HomeAccountingDB {
         Ok(())
     }
 
-    pub fn migrate(&self, dest_folder: String) -> Result<(), Error> {
-        todo!()
+    /// Months differing from the destination digests of the migration manifest, empty when there is no manifest.
+    fn check_manifest(&self, records: &DataRange<FinanceRecord>) -> Result<Vec<ManifestMismatch>, Error> {
+        let Some(lines) = load_manifest(&self.data_folder_path)? else {
+            return Ok(Vec::new());
+        };
+        let expected = lines.iter().map(|l|(l.month, l.destination)).collect();
+        let actual = build_digests(records).into_iter().filter(|(m, _)|lines.iter().any(|l|l.month == *m)).collect();
+        Ok(compare_digests(&expected, &actual))
+    }
+
+    /// Copies dictionaries and operations to the empty dest_folder in the storage format of configuration,
+    /// loads the copy and writes the integrity manifest to it. Fails when the copy differs from this
+    /// database. Archived accounts are written to the accounts dictionary. Nothing is left in dest_folder when
+    /// the copy fails. Returns number of copied operations.
+    pub fn migrate(&self, dest_folder: String, configuration: Box<dyn DBConfiguration>) -> Result<usize, Error> {
+        if self.archive.last_year().is_some() {
+            return Err(Error::new(ErrorKind::InvalidInput, "archived years can't be migrated"));
+        }
+        if Path::new(&dest_folder).exists() && fs::read_dir(&dest_folder)?.next().is_some() {
+            return Err(Error::new(ErrorKind::AlreadyExists, format!("{} is not empty", dest_folder)));
+        }
+        let existed = Path::new(&dest_folder).exists();
+        fs::create_dir_all(&dest_folder)?;
+        let result = self.copy_to(&dest_folder, configuration);
+        if result.is_err() {
+            // the destination was empty, nothing of a failed copy is kept
+            let _ = if existed {remove_folder_contents(&dest_folder)} else {fs::remove_dir_all(&dest_folder)};
+        }
+        result
+    }
+
+    fn copy_to(&self, dest_folder: &str, configuration: Box<dyn DBConfiguration>) -> Result<usize, Error> {
+        let dest_folder = dest_folder.to_string();
+        let accounts: Vec<Account> = self.accounts.all()?.into_iter().cloned().collect();
+        configuration.get_accounts_source().save(&accounts, dest_folder.clone().add("/accounts"))?;
+        let (from, to, source) = (&self.data_folder_path, &dest_folder, &self.configuration);
+        copy_dictionary(source.get_categories_source(), configuration.get_categories_source(), from, to, "categories")?;
+        copy_dictionary(source.get_subcategories_source(), configuration.get_subcategories_source(), from, to, "subcategories")?;
+        copy_dictionary(source.get_currencies_source(), configuration.get_currencies_source(), from, to, "currencies")?;
+        copy_dictionary(source.get_payees_source(), configuration.get_payees_source(), from, to, "payees")?;
+        copy_dictionary(source.get_parameters_source(), configuration.get_parameters_source(), from, to, "parameters")?;
+        copy_dictionary(source.get_loans_source(), configuration.get_loans_source(), from, to, "loans")?;
+        copy_dictionary(source.get_instruments_source(), configuration.get_instruments_source(), from, to, "instruments")?;
+        copy_dictionary(source.get_prices_source(), configuration.get_prices_source(), from, to, "prices")?;
+        copy_dictionary(source.get_goals_source(), configuration.get_goals_source(), from, to, "goals")?;
+        copy_dictionary(source.get_budgets_source(), configuration.get_budgets_source(), from, to, "budgets")?;
+        copy_dictionary(source.get_rates_source(), configuration.get_rates_source(), from, to, "rates")?;
+        copy_dictionary(source.get_settings_source(), configuration.get_settings_source(), from, to, "settings")?;
+        copy_dictionary(source.get_templates_source(), configuration.get_templates_source(), from, to, "templates")?;
+        copy_dictionary(source.get_report_specs_source(), configuration.get_report_specs_source(), from, to, "reports")?;
+        copy_dictionary(source.get_validation_rules_source(), configuration.get_validation_rules_source(), from, to,
+                        "validation_rules")?;
+        let records = self.data.get_range(0, u64::MAX)?;
+        let main = configuration.get_main_data_source(self.minor_units.clone());
+        let dates = dest_folder.clone().add("/dates");
+        let mut batch = SaveBatch::default();
+        for (key, record) in &records {
+            batch.append(main.prepare_save(&record.lock().unwrap(), &dates, *key)?);
+        }
+        batch.write(true)?;
+        let expected = build_digests(&records);
+        let copy = HomeAccountingDB::load(dest_folder.clone(), configuration, usize::MAX)?;
+        let actual = build_digests(&copy.data.get_range(0, u64::MAX)?);
+        let empty = MonthDigest{count: 0, hash: 0};
+        let mut months: Vec<u64> = expected.keys().chain(actual.keys()).cloned().collect();
+        months.sort();
+        months.dedup();
+        let lines: Vec<ManifestLine> = months.into_iter()
+            .map(|month|ManifestLine{month, source: *expected.get(&month).unwrap_or(&empty),
+                destination: *actual.get(&month).unwrap_or(&empty)})
+            .collect();
+        save_manifest(&dest_folder, &lines)?;
+        if let Some(m) = compare_digests(&expected, &actual).first() {
+            return Err(Error::new(ErrorKind::InvalidData, m.describe()));
+        }
+        Ok(expected.values().map(|d|d.count).sum())
+    }
+}
+
+fn remove_folder_contents(folder: &str) -> Result<(), Error> {
+    for entry in fs::read_dir(folder)? {
+        let path = entry?.path();
+        if path.is_dir() {fs::remove_dir_all(path)?} else {fs::remove_file(path)?}
+    }
+    Ok(())
+}
+
+/// Copies an optional dictionary file between storage formats.
+fn copy_dictionary<T>(from: Box<dyn DataSource<T>>, to: Box<dyn DataSource<T>>, source_folder: &str, dest_folder: &str,
+                      name: &str) -> Result<(), Error> {
+    match from.load(format!("{}/{}", source_folder, name), true) {
+        Ok(data) => to.save(&data, format!("{}/{}", dest_folder, name)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e)
     }
 }
@@ -1,65 +1,353 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::mem;
 use std::io::{Error, ErrorKind};
 use std::ops::Add;
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use crate::core::data_source::DataSource;
-use crate::entities::common::date_deserialize;
+use crate::core::sharded::ShardedDictionary;
+use crate::entities::common::{date_deserialize, date_serialize};
+use crate::entities::rates::convert_by_rate;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[serde(transparent)]
+pub struct AccountId(pub u64);
+
+impl fmt::Display for AccountId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum AccountGroup {
+    Cards,
+    Cash,
+    Savings,
+    Loans
+}
+
+impl AccountGroup {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountGroup::Cards => "CARDS",
+            AccountGroup::Cash => "CASH",
+            AccountGroup::Savings => "SAVINGS",
+            AccountGroup::Loans => "LOANS"
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<AccountGroup> {
+        [AccountGroup::Cards, AccountGroup::Cash, AccountGroup::Savings, AccountGroup::Loans].into_iter()
+            .find(|g|g.as_str() == name)
+    }
+}
 
 pub struct Accounts {
-    source: Box<dyn DataSource<Vec<Account>>>,
-    map: HashMap<u64, Account>,
+    map: HashMap<AccountId, Account>,
+    cash_accounts: HashMap<String, AccountId>,
+    /// account currency changes by date: account and rate
+    currency_changes: BTreeMap<u64, Vec<(AccountId, i64)>>,
+    /// closed accounts moved out of the accounts file, sharded by closing year
+    archived: Option<ShardedDictionary<Account>>
 }
 
 impl Accounts {
-    pub fn load(data_folder_path: String, source: Box<dyn DataSource<Vec<Account>>>)
-        -> Result<Accounts, Error> {
-        let mut accounts = source.load(data_folder_path.add("/accounts"), true)?;
-        let cash_accounts: HashMap<String, u64> = accounts.iter()
+    pub fn load(data_folder_path: String, source: Box<dyn DataSource<Vec<Account>>>,
+                archive_source: Box<dyn DataSource<Vec<Account>>>) -> Result<Accounts, Error> {
+        let accounts = source.load(data_folder_path.clone().add("/accounts"), true)?;
+        Accounts::with_archive(data_folder_path, archive_source, accounts)
+    }
+
+    /// Accounts of the accounts file with archive shards of the data folder.
+    pub fn with_archive(data_folder_path: String, archive_source: Box<dyn DataSource<Vec<Account>>>,
+                        accounts: Vec<Account>) -> Result<Accounts, Error> {
+        let mut accounts = Accounts::new(accounts)?;
+        accounts.archived = Some(ShardedDictionary::load(data_folder_path.add("/accounts"), archive_source,
+                                                         |a|a.id.0)?);
+        Ok(accounts)
+    }
+
+    pub fn new(mut accounts: Vec<Account>) -> Result<Accounts, Error> {
+        let cash_accounts: HashMap<String, AccountId> = accounts.iter()
             .filter(|a|a.cash_account.is_none())
             .map(|a|(a.currency.clone(), a.id)).collect();
+        let mut currency_changes: BTreeMap<u64, Vec<(AccountId, i64)>> = BTreeMap::new();
         for a in accounts.iter_mut() {
             if a.cash_account.is_some() {
-                let cash_account = *cash_accounts.get(&a.currency)
-                    .ok_or(Error::new(ErrorKind::InvalidData, "load - no cash account found"))?;
-                a.cash_account = Some(cash_account)
+                a.cash_account = Some(find_cash_account(&cash_accounts, &a.currency)?)
+            }
+            a.validate_currency_history()?;
+            for change in &a.currency_history {
+                currency_changes.entry(change.date.unwrap()).or_default().push((a.id, change.rate));
             }
         }
         let map = accounts.into_iter().map(|c|(c.id, c)).collect();
-        Ok(Accounts{source, map})
+        Ok(Accounts{map, cash_accounts, currency_changes, archived: None})
     }
 
-    pub fn get_cash_account(&self, account: u64) -> Result<Option<u64>, Error> {
+    /// Currency changes with dates in from..=to: account and rate.
+    pub fn get_currency_changes(&self, from: u64, to: u64) -> impl Iterator<Item = (AccountId, i64)> + '_ {
+        self.currency_changes.range(from..=to.max(from)).flat_map(|(_, c)|c.iter().copied())
+    }
+
+    /// Converts balances of accounts which currency changed between from and to dates.
+    pub fn convert_balances(&self, balances: &mut HashMap<AccountId, i64>, from: u64, to: u64) -> Result<(), Error> {
+        for (account, rate) in self.get_currency_changes(from, to) {
+            if let Some(balance) = balances.get_mut(&account) {
+                *balance = convert_by_rate(*balance, rate)
+                    .ok_or(Error::new(ErrorKind::InvalidData, format!("balance overflow on account {}", account)))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// New currency is valid from date, which has to be the first day of a month. Accounts file has
+    /// to be saved and totals from date recalculated after this call.
+    pub fn change_currency(&mut self, id: AccountId, date: u64, currency: String, rate: i64) -> Result<(), Error> {
+        let mut account = match self.map.get(&id) {
+            Some(a) => a.clone(),
+            None if self.archived.as_ref().is_some_and(|d|d.contains(id.0)) =>
+                return Err(Error::new(ErrorKind::InvalidInput, format!("account {} is archived", id))),
+            None => return Err(Error::new(ErrorKind::InvalidData, "invalid account id"))
+        };
+        if account.currency == currency {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("account {} already has currency {}", id, currency)));
+        }
+        if !account.is_active(date) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("account {} is closed", account.name)));
+        }
+        let previous = mem::replace(&mut account.currency, currency);
+        account.currency_history.push(CurrencyChange{date: Some(date), currency: previous, rate});
+        let mut accounts: Vec<Account> = self.map.values().filter(|a|a.id != id).cloned().collect();
+        accounts.push(account);
+        let mut result = Accounts::new(accounts)?;
+        result.archived = self.archived.take();
+        *self = result;
+        Ok(())
+    }
+
+    /// Cash account for the currency the account had at given date.
+    pub fn get_cash_account(&self, account: AccountId, date: u64) -> Result<Option<AccountId>, Error> {
         match self.map.get(&account) {
-            Some(a) => Ok(a.cash_account),
+            Some(a) if a.currency_history.is_empty() => Ok(a.cash_account),
+            Some(a) if a.cash_account.is_some() => {
+                let currency = a.currency_at(date);
+                self.cash_accounts.values()
+                    .filter_map(|id|self.map.get(id))
+                    .find(|c|c.currency_at(date) == currency)
+                    .map(|c|Some(c.id))
+                    .ok_or(Error::new(ErrorKind::InvalidData, format!("no cash account found for currency {}", currency)))
+            }
+            Some(_) => Ok(None),
+            None => {
+                let a = self.get(account)?;
+                match a.cash_account {
+                    Some(_) => Ok(Some(find_cash_account(&self.cash_accounts, &a.currency)?)),
+                    None => Ok(None)
+                }
+            }
+        }
+    }
+
+    /// Archived accounts are loaded with their shard on first access.
+    pub fn get(&self, id: AccountId) -> Result<&Account, Error> {
+        if let Some(a) = self.map.get(&id) {
+            return Ok(a);
+        }
+        let archived = match &self.archived {
+            Some(d) => d.get(id.0)?,
+            None => None
+        };
+        archived.ok_or(Error::new(ErrorKind::InvalidData, "invalid account id"))
+    }
+
+    /// Accounts from the accounts file, archived accounts are not included.
+    pub fn iter(&self) -> impl Iterator<Item = &Account> {
+        self.map.values()
+    }
+
+    /// All accounts including archived ones, loads all shards.
+    pub fn all(&self) -> Result<Vec<&Account>, Error> {
+        let mut result: Vec<&Account> = self.map.values().collect();
+        if let Some(d) = &self.archived {
+            result.extend(d.all()?.into_iter().filter(|a|!self.map.contains_key(&a.id)));
+        }
+        Ok(result)
+    }
+
+    /// All accounts in display order: by group, then by order and name.
+    pub fn ordered(&self) -> Result<Vec<&Account>, Error> {
+        let mut result = self.all()?;
+        result.sort_by(|a, b|(a.get_group(), a.order, &a.name, a.id).cmp(&(b.get_group(), b.order, &b.name, b.id)));
+        Ok(result)
+    }
+
+    /// Archived accounts can't be changed.
+    pub fn set_retired(&mut self, id: AccountId, retired: bool) -> Result<(), Error> {
+        match self.map.get_mut(&id) {
+            Some(a) => {
+                a.retired = retired;
+                Ok(())
+            }
+            None if self.archived.as_ref().is_some_and(|d|d.contains(id.0)) =>
+                Err(Error::new(ErrorKind::InvalidInput, format!("account {} is archived", id))),
             None => Err(Error::new(ErrorKind::InvalidData, "invalid account id"))
         }
     }
 
-    pub fn get(&self, id: u64) -> Result<&Account, Error> {
-        self.map.get(&id).ok_or(Error::new(ErrorKind::InvalidData, "invalid account id"))
+    pub fn get_archived(&self) -> Option<&ShardedDictionary<Account>> {
+        self.archived.as_ref()
+    }
+
+    /// Moves non cash accounts closed before given date to archive shards by closing year, accounts
+    /// with currency history stay in the accounts file. Accounts file has to be saved after this call.
+    /// Returns number of archived accounts.
+    pub fn archive(&mut self, before: u64) -> Result<usize, Error> {
+        let archived = self.archived.as_mut()
+            .ok_or(Error::new(ErrorKind::Unsupported, "accounts archive is not available"))?;
+        let ids: Vec<AccountId> = self.map.values()
+            .filter(|a|a.cash_account.is_some() && a.active_to.is_some_and(|d|d < before) && a.currency_history.is_empty())
+            .map(|a|a.id)
+            .collect();
+        let items = ids.iter()
+            .map(|id|self.map.get(id).unwrap().clone())
+            .map(|a|(a.active_to.unwrap() / 10000, a))
+            .collect();
+        archived.add(items)?;
+        for id in &ids {
+            self.map.remove(id);
+        }
+        Ok(ids.len())
     }
-    
+
     pub fn save(&self, dest: Box<dyn DataSource<Vec<Account>>>, data_folder_path: String) -> Result<(), Error>{
-        dest.save(&self.map.values().map(|a|a.clone()).collect(), data_folder_path.add("/accounts"))
+        let mut values: Vec<Account> = self.map.values().cloned().collect();
+        values.sort_by_key(|v|v.id);
+        dest.save(&values, data_folder_path.add("/accounts"))
     }
 }
 
-fn is_cash_deserialize<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+fn find_cash_account(cash_accounts: &HashMap<String, AccountId>, currency: &str) -> Result<AccountId, Error> {
+    cash_accounts.get(currency).copied()
+        .ok_or(Error::new(ErrorKind::InvalidData, "load - no cash account found"))
+}
+
+fn is_cash_deserialize<'de, D>(deserializer: D) -> Result<Option<AccountId>, D::Error>
     where
         D: Deserializer<'de>,
 {
     let v: bool = Deserialize::deserialize(deserializer)?;
-    return if v {Ok(None)} else {Ok(Some(0))};
+    if v {Ok(None)} else {Ok(Some(AccountId(0)))}
 }
 
-#[derive(Deserialize, Clone)]
+fn is_cash_serialize<S>(cash_account: &Option<AccountId>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+{
+    serializer.serialize_bool(cash_account.is_none())
+}
+
+/// Currency the account had before date, at date balance is converted to the next currency.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct CurrencyChange {
+    #[serde(deserialize_with = "date_deserialize", serialize_with = "date_serialize")]
+    pub date: Option<u64>,
+    #[serde(rename = "valutaCode")]
+    pub currency: String,
+    /// units of the next currency for one unit of this currency, in millionths
+    pub rate: i64
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Account {
-    id: u64,
+    pub id: AccountId,
     pub name: String,
     #[serde(rename = "valutaCode")]
-    currency: String,
-    #[serde(rename = "activeTo", deserialize_with = "date_deserialize")]
-    active_to: Option<u64>,
-    #[serde(rename = "isCash", deserialize_with = "is_cash_deserialize")]
-    cash_account: Option<u64>
+    pub currency: String,
+    #[serde(rename = "activeTo", deserialize_with = "date_deserialize", serialize_with = "date_serialize")]
+    pub active_to: Option<u64>,
+    #[serde(rename = "isCash", deserialize_with = "is_cash_deserialize", serialize_with = "is_cash_serialize")]
+    cash_account: Option<AccountId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    group: Option<AccountGroup>,
+    #[serde(default)]
+    pub order: u32,
+    /// retired account is kept for history but new operations can't use it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub retired: bool,
+    /// previous currencies of the account ordered by date, currency is the current one
+    #[serde(rename = "currencyHistory", default, skip_serializing_if = "Vec::is_empty")]
+    pub currency_history: Vec<CurrencyChange>,
+    /// alert is raised when the balance falls below it, in hundredths
+    #[serde(rename = "minBalance", default, skip_serializing_if = "Option::is_none")]
+    pub min_balance: Option<i64>,
+    /// alert is raised when the balance falls below minus credit limit, in hundredths
+    #[serde(rename = "creditLimit", default, skip_serializing_if = "Option::is_none")]
+    pub credit_limit: Option<i64>
+}
+
+impl Account {
+    /// Account without group and alerts. Every currency of non cash accounts needs a cash account.
+    pub fn new(id: AccountId, name: String, currency: String, cash: bool) -> Account {
+        Account{id, name, currency, active_to: None, cash_account: if cash {None} else {Some(AccountId(0))},
+            group: None, order: 0, retired: false, currency_history: Vec::new(), min_balance: None, credit_limit: None}
+    }
+
+    /// active_to is the last date when account can have operations.
+    pub fn is_active(&self, date: u64) -> bool {
+        self.active_to.is_none_or(|d|date <= d)
+    }
+
+    /// Currency of the account at given date.
+    pub fn currency_at(&self, date: u64) -> &str {
+        self.currency_history.iter()
+            .find(|c|c.date.is_some_and(|d|date < d))
+            .map(|c|c.currency.as_str())
+            .unwrap_or(&self.currency)
+    }
+
+    /// Changes are ordered, dated by first days of months and really change the currency.
+    fn validate_currency_history(&self) -> Result<(), Error> {
+        let invalid = |message: &str|Error::new(ErrorKind::InvalidData,
+                                                format!("account {} currency history: {}", self.name, message));
+        let mut previous = 0;
+        for (i, change) in self.currency_history.iter().enumerate() {
+            let date = change.date.ok_or_else(||invalid("date expected"))?;
+            if date % 100 != 1 {
+                return Err(invalid(&format!("{} is not the first day of a month", date)));
+            }
+            if date <= previous {
+                return Err(invalid(&format!("{} is not after {}", date, previous)));
+            }
+            if change.rate <= 0 {
+                return Err(invalid(&format!("invalid rate at {}", date)));
+            }
+            let next = self.currency_history.get(i + 1).map(|c|&c.currency).unwrap_or(&self.currency);
+            if *next == change.currency {
+                return Err(invalid(&format!("currency {} is not changed at {}", next, date)));
+            }
+            previous = date;
+        }
+        Ok(())
+    }
+
+    pub fn is_cash(&self) -> bool {
+        self.cash_account.is_none()
+    }
+
+    /// Group set in the accounts file, None when the default one is used.
+    pub fn get_explicit_group(&self) -> Option<AccountGroup> {
+        self.group
+    }
+
+    pub fn set_group(&mut self, group: Option<AccountGroup>) {
+        self.group = group;
+    }
+
+    /// Accounts without explicit group are put to Cash or Cards group.
+    pub fn get_group(&self) -> AccountGroup {
+        self.group.unwrap_or(if self.cash_account.is_none() {AccountGroup::Cash} else {AccountGroup::Cards})
+    }
 }
@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+use std::ops::Add;
+use serde::{Deserialize, Serialize};
+use crate::core::data_source::DataSource;
+use crate::entities::subcategories::{Subcategories, SubcategoryId, SubcategoryOperationCode};
+
+/// What is left of the month budget at its end and moves to the next month.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Rollover {
+    /// every month starts with the budget amount
+    #[default]
+    None,
+    /// unused budget is added to the next month
+    Unused,
+    /// overspend is taken from the next month
+    Overspend,
+    Both
+}
+
+impl Rollover {
+    /// Carry to the next month of the month balance (budget and carry minus spent).
+    pub fn carry(&self, balance: i64) -> i64 {
+        match self {
+            Rollover::None => 0,
+            Rollover::Unused => balance.max(0),
+            Rollover::Overspend => balance.min(0),
+            Rollover::Both => balance
+        }
+    }
+}
+
+/// Monthly limit of expenditure of the subcategory.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct BudgetLine {
+    #[serde(rename = "subcategoryId")]
+    pub subcategory: SubcategoryId,
+    /// month budget, in hundredths
+    pub amount: i64,
+    /// yyyymm of the first budgeted month, rollover is accumulated from it
+    #[serde(rename = "fromMonth")]
+    pub from_month: u64,
+    #[serde(default)]
+    pub rollover: Rollover
+}
+
+pub struct Budgets {
+    map: BTreeMap<SubcategoryId, BudgetLine>
+}
+
+impl Budgets {
+    /// Budgets file is optional, databases without it get an empty dictionary.
+    pub fn load(data_folder_path: String, source: Box<dyn DataSource<Vec<BudgetLine>>>) -> Result<Budgets, Error> {
+        match source.load(data_folder_path.add("/budgets"), true) {
+            Ok(lines) => Budgets::new(lines),
+            Err(e) if e.kind() == ErrorKind::NotFound => Budgets::new(Vec::new()),
+            Err(e) => Err(e)
+        }
+    }
+
+    pub fn new(lines: Vec<BudgetLine>) -> Result<Budgets, Error> {
+        let mut map = BTreeMap::new();
+        for line in lines {
+            if !(1..=12).contains(&(line.from_month % 100)) {
+                return Err(Error::new(ErrorKind::InvalidData,
+                                      format!("budget of subcategory {} has invalid month {}", line.subcategory, line.from_month)));
+            }
+            if let Some(line) = map.insert(line.subcategory, line) {
+                return Err(Error::new(ErrorKind::InvalidData,
+                                      format!("subcategory {} has more than one budget", line.subcategory)));
+            }
+        }
+        Ok(Budgets{map})
+    }
+
+    /// Ordered by subcategory.
+    pub fn iter(&self) -> impl Iterator<Item = &BudgetLine> {
+        self.map.values()
+    }
+
+    pub fn validate(&self, subcategories: &Subcategories) -> Result<(), Error> {
+        for line in self.map.values() {
+            let subcategory = subcategories.get(line.subcategory)
+                .map_err(|_|Error::new(ErrorKind::InvalidData, format!("budget has invalid subcategory {}", line.subcategory)))?;
+            if !matches!(subcategory.operation_code, SubcategoryOperationCode::Expn) {
+                return Err(Error::new(ErrorKind::InvalidData,
+                                      format!("budget subcategory {} is not an expense", subcategory.name)));
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serializer};
 use serde::de::Unexpected;
+use crate::core::dates::check_date;
 
 pub fn date_deserialize<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
     where
@@ -13,5 +14,18 @@ pub fn date_deserialize<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error
     if d.len() != 3 {
         return Err(serde::de::Error::invalid_value(Unexpected::Seq, &"subcategory operation code"));
     }
-    return Ok(Some(d[0] * 10000 + d[1] * 100 + d[2]));
+    let date = d[0] * 10000 + d[1] * 100 + d[2];
+    check_date(date).map_err(serde::de::Error::custom)?;
+    Ok(Some(date))
+}
+
+/// Writes yyyymmdd date as [year, month, day], the format date_deserialize reads.
+pub fn date_serialize<S>(date: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+{
+    match date {
+        Some(d) => serializer.collect_seq([d / 10000, d / 100 % 100, d % 100]),
+        None => serializer.serialize_none()
+    }
 }
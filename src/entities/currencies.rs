@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::ops::Add;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use crate::core::amounts::{format_decimal, parse_decimal, round_to, Rounding, SUMMA_DECIMALS};
+use crate::core::data_source::DataSource;
+use crate::entities::accounts::{AccountId, Accounts};
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Currency {
+    pub code: String,
+    pub symbol: String,
+    #[serde(rename = "minorUnits")]
+    pub minor_units: u32,
+    #[serde(default, skip_serializing_if = "Rounding::is_default")]
+    pub rounding: Rounding
+}
+
+impl Currency {
+    pub fn new(code: String, symbol: String, minor_units: u32) -> Currency {
+        Currency{code, symbol, minor_units, rounding: Rounding::default()}
+    }
+
+    /// Parses decimal amount into minor units, extra fraction digits are rounded.
+    pub fn parse(&self, text: &str) -> Result<i64, Error> {
+        parse_decimal(text, self.minor_units, Some(self.rounding))
+    }
+
+    /// Converts amount written as a JSON number in currency units to minor units.
+    pub fn from_decimal(&self, value: f64) -> i64 {
+        // three more digits are kept so the currency rounding decides, not the float one
+        let value = (value * 10f64.powi(self.minor_units as i32 + 3)).round() as i64;
+        round_to(value, 1000, self.rounding) / 1000
+    }
+
+    /// Formats summa given in minor units.
+    pub fn format(&self, summa: i64) -> String {
+        format!("{} {}", format_decimal(summa, self.minor_units), self.symbol)
+    }
+}
+
+pub struct Currencies {
+    map: HashMap<String, Currency>
+}
+
+impl Currencies {
+    /// Databases created before currencies dictionary was introduced have no currencies file,
+    /// in this case dictionary is empty and account currencies are not validated.
+    pub fn load(data_folder_path: String, source: Box<dyn DataSource<Vec<Currency>>>)
+        -> Result<Currencies, Error> {
+        match source.load(data_folder_path.add("/currencies"), true) {
+            Ok(currencies) => Ok(Currencies::new(currencies)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Currencies::new(Vec::new())),
+            Err(e) => Err(e)
+        }
+    }
+
+    pub fn new(currencies: Vec<Currency>) -> Currencies {
+        let map = currencies.into_iter().map(|c|(c.code.clone(), c)).collect();
+        Currencies{map}
+    }
+
+    pub fn get(&self, code: &str) -> Result<&Currency, Error> {
+        self.map.get(code).ok_or(Error::new(ErrorKind::InvalidData, "invalid currency code"))
+    }
+
+    /// Currency with given code, databases without currencies dictionary keep every currency
+    /// in hundredths.
+    pub fn resolve(&self, code: &str) -> Result<Currency, Error> {
+        if self.is_empty() {
+            return Ok(Currency::new(code.to_string(), code.to_string(), SUMMA_DECIMALS));
+        }
+        self.map.get(code).cloned()
+            .ok_or_else(||Error::new(ErrorKind::InvalidData, format!("unknown currency {}", code)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Currency> {
+        self.map.values()
+    }
+
+    pub fn validate(&self, accounts: &Accounts) -> Result<(), Error> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        for account in accounts.iter() {
+            let history = account.currency_history.iter().map(|c|&c.currency);
+            if let Some(currency) = history.chain([&account.currency]).find(|c|!self.map.contains_key(*c)) {
+                return Err(Error::new(ErrorKind::InvalidData,
+                                      format!("account {} has unknown currency {}", account.name, currency)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, dest: Box<dyn DataSource<Vec<Currency>>>, data_folder_path: String) -> Result<(), Error>{
+        let mut values: Vec<Currency> = self.map.values().cloned().collect();
+        values.sort_by(|a, b|a.code.cmp(&b.code));
+        dest.save(&values, data_folder_path.add("/currencies"))
+    }
+}
+
+/// Currencies of every account by date, sums of operations are stored in their minor units.
+/// Shared with the operations source that converts decimal sums of legacy files.
+pub type SharedMinorUnits = Arc<RwLock<MinorUnits>>;
+
+#[derive(Default)]
+pub struct MinorUnits {
+    /// currency changes as (valid before date, currency), the last one has no date
+    accounts: HashMap<AccountId, Vec<(Option<u64>, Currency)>>,
+    hundredths_only: bool
+}
+
+impl MinorUnits {
+    pub fn new(accounts: &Accounts, currencies: &Currencies) -> Result<MinorUnits, Error> {
+        let mut map = HashMap::new();
+        for account in accounts.iter() {
+            let mut history = Vec::with_capacity(account.currency_history.len() + 1);
+            for change in &account.currency_history {
+                history.push((change.date, currencies.resolve(&change.currency)?));
+            }
+            history.push((None, currencies.resolve(&account.currency)?));
+            map.insert(account.id, history);
+        }
+        let hundredths_only = map.values().flatten().all(|(_, c)|c.minor_units == SUMMA_DECIMALS);
+        Ok(MinorUnits{accounts: map, hundredths_only})
+    }
+
+    /// Currency of the account at given date.
+    pub fn currency_at(&self, account: AccountId, date: u64) -> Result<&Currency, Error> {
+        let history = self.accounts.get(&account)
+            .ok_or_else(||Error::new(ErrorKind::InvalidData, format!("unknown account {}", account)))?;
+        Ok(&history.iter()
+            .find(|(d, _)|d.is_some_and(|d|date < d))
+            .unwrap_or(&history[history.len() - 1]).1)
+    }
+
+    /// True when every account keeps sums in hundredths, decimal sums need no conversion then.
+    pub fn hundredths_only(&self) -> bool {
+        self.accounts.is_empty() || self.hundredths_only
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use std::sync::{Arc, Mutex};
+    use crate::core::data_source::DataSource;
+    use crate::core::amounts::Rounding;
+    use crate::entities::currencies::{Currencies, Currency};
+
+    fn currency(minor_units: u32) -> Currency {
+        Currency::new("XXX".to_string(), "X".to_string(), minor_units)
+    }
+
+    struct SavedCurrencies(Arc<Mutex<Vec<String>>>);
+
+    impl DataSource<Vec<Currency>> for SavedCurrencies {
+        fn load(&self, _file_name: String, _add_extension: bool) -> Result<Vec<Currency>, Error> {
+            todo!()
+        }
+
+        fn save(&self, data: &Vec<Currency>, _file_name: String) -> Result<(), Error> {
+            *self.0.lock().unwrap() = data.iter().map(|c|c.code.clone()).collect();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_save_order() -> Result<(), Error> {
+        let codes = ["UAH", "EUR", "USD", "GBP", "PLN"];
+        let currencies = Currencies::new(codes.iter()
+            .map(|c|Currency{code: c.to_string(), ..currency(2)})
+            .collect());
+        let saved = Arc::new(Mutex::new(Vec::new()));
+        currencies.save(Box::new(SavedCurrencies(saved.clone())), String::new())?;
+        assert_eq!(*saved.lock().unwrap(), vec!["EUR", "GBP", "PLN", "UAH", "USD"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(currency(2).format(12345), "123.45 X");
+        assert_eq!(currency(2).format(-5), "-0.05 X");
+        assert_eq!(currency(3).format(12345), "12.345 X");
+        assert_eq!(currency(0).format(12350), "12350 X");
+        assert_eq!(Currency{rounding: Rounding::Down, ..currency(1)}.format(-12399), "-1239.9 X");
+    }
+
+    #[test]
+    fn test_parse() -> Result<(), Error> {
+        assert_eq!(currency(3).parse("12.345")?, 12345);
+        assert_eq!(currency(3).from_decimal(12.345), 12345);
+        assert_eq!(currency(0).parse("123.5")?, 124);
+        assert_eq!(currency(0).from_decimal(-123.5), -124);
+        let even = Currency{rounding: Rounding::HalfEven, ..currency(0)};
+        assert_eq!(even.parse("122.5")?, 122);
+        assert_eq!(even.from_decimal(122.5), 122);
+        assert_eq!(Currency{rounding: Rounding::Down, ..currency(2)}.parse("-1.239")?, -123);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(Currencies::new(Vec::new()).resolve("JPY").unwrap().minor_units, 2);
+        let currencies = Currencies::new(vec![Currency{code: "JPY".to_string(), ..currency(0)}]);
+        assert_eq!(currencies.resolve("JPY").unwrap().minor_units, 0);
+        assert_eq!(currencies.resolve("KWD").err().unwrap().to_string(), "unknown currency KWD");
+    }
+}
@@ -1,65 +1,131 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::io::{Error, ErrorKind};
-use serde::{Deserialize, Deserializer};
+use std::sync::Arc;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use smallvec::SmallVec;
 use serde::de::{Unexpected, Visitor};
-use crate::entities::accounts::Accounts;
-use crate::entities::subcategories::{Subcategories, SubcategoryCode, SubcategoryOperationCode};
-use crate::entities::common::date_deserialize;
+use crate::core::amounts::{AMOUNT_DECIMALS, SUMMA_DECIMALS};
+use crate::core::interner::StringPool;
+use crate::entities::accounts::{AccountId, Accounts};
+use crate::entities::payees::PayeeId;
+use crate::entities::instruments::InstrumentId;
+use crate::entities::subcategories::{Subcategories, SubcategoryCode, SubcategoryId, SubcategoryOperationCode};
+use crate::entities::common::{date_deserialize, date_serialize};
+use crate::entities::rates::convert_by_rate;
 
 pub struct FinanceChange {
+    account: AccountId,
     start_balance: i64,
     income: i64,
     expenditure: i64
 }
 
 impl FinanceChange {
-    pub fn new(start_balance: i64) -> FinanceChange {
-        FinanceChange{start_balance, income: 0, expenditure: 0}
+    pub fn new(account: AccountId, start_balance: i64) -> FinanceChange {
+        FinanceChange{account, start_balance, income: 0, expenditure: 0}
     }
 
+    fn overflow(&self) -> Error {
+        Error::new(ErrorKind::InvalidData, format!("balance overflow on account {}", self.account))
+    }
+
+    pub fn get_start_balance(&self) -> i64 {
+        self.start_balance
+    }
+
+    pub fn get_income(&self) -> i64 {
+        self.income
+    }
+
+    pub fn get_expenditure(&self) -> i64 {
+        self.expenditure
+    }
+
+    /// Saturates on overflow, use checked_end_balance to detect it.
     pub fn get_end_balance(&self) -> i64 {
-        self.start_balance + self.income - self.expenditure
+        self.start_balance.saturating_add(self.income).saturating_sub(self.expenditure)
+    }
+
+    pub fn checked_end_balance(&self) -> Result<i64, Error> {
+        self.start_balance.checked_add(self.income)
+            .and_then(|v|v.checked_sub(self.expenditure))
+            .ok_or_else(||self.overflow())
     }
 
     pub fn handle_income(&mut self, summa: i64) -> Result<(), Error> {
-        self.income += summa;
+        self.income = self.income.checked_add(summa).ok_or_else(||self.overflow())?;
         Ok(())
     }
 
     pub fn handle_expenditure(&mut self, summa: i64) -> Result<(), Error> {
-        self.expenditure += summa;
+        self.expenditure = self.expenditure.checked_add(summa).ok_or_else(||self.overflow())?;
+        Ok(())
+    }
+
+    /// Converts balance, income and expenditure to another currency by rate in millionths.
+    pub fn convert(&mut self, rate: i64) -> Result<(), Error> {
+        let convert = |value: i64|convert_by_rate(value, rate).ok_or_else(||self.overflow());
+        (self.start_balance, self.income, self.expenditure) =
+            (convert(self.start_balance)?, convert(self.income)?, convert(self.expenditure)?);
         Ok(())
     }
 }
 
 pub struct FinanceChanges {
-    changes: HashMap<u64, FinanceChange>
+    changes: HashMap<AccountId, FinanceChange>
 }
 
 impl FinanceChanges {
-    pub fn new(totals: &HashMap<u64, i64>) -> FinanceChanges {
+    pub fn new(totals: &HashMap<AccountId, i64>) -> FinanceChanges {
         let changes = totals.iter()
-            .map(|(account, summa)|(*account, FinanceChange::new(*summa))).collect();
+            .map(|(account, summa)|(*account, FinanceChange::new(*account, *summa))).collect();
         FinanceChanges{changes}
     }
 
     pub fn empty() -> FinanceChanges {FinanceChanges{changes: HashMap::new()}}
 
-    pub fn build_totals(&self) -> HashMap<u64, i64> {
+    pub fn build_totals(&self) -> Result<HashMap<AccountId, i64>, Error> {
         self.changes.iter()
-            .map(|(account, changes)|(*account, changes.get_end_balance())).collect()
+            .map(|(account, changes)|changes.checked_end_balance().map(|b|(*account, b))).collect()
     }
 
-    fn get_account_changes(&mut self, account: u64) -> &mut FinanceChange {
-        self.changes.entry(account).or_insert(FinanceChange::new(0))
+    /// Converts changes of accounts which currency changed between from and to dates.
+    pub fn convert_currencies(&mut self, accounts: &Accounts, from: u64, to: u64) -> Result<(), Error> {
+        for (account, rate) in accounts.get_currency_changes(from, to) {
+            if let Some(change) = self.changes.get_mut(&account) {
+                change.convert(rate)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes accounts closed before given date.
+    pub fn retain_active(&mut self, accounts: &Accounts, date: u64) {
+        self.changes.retain(|account, _|accounts.get(*account).map(|a|a.is_active(date)).unwrap_or(true));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&AccountId, &FinanceChange)> {
+        self.changes.iter()
+    }
+
+    pub fn get(&self, account: AccountId) -> Option<&FinanceChange> {
+        self.changes.get(&account)
+    }
+
+    pub fn get_account_changes(&mut self, account: AccountId) -> &mut FinanceChange {
+        self.changes.entry(account).or_insert(FinanceChange::new(account, 0))
     }
 
     pub fn print(&self, accounts: &Accounts) -> Result<(), Error> {
-        for (account, change) in &self.changes {
-            let acc = accounts.get(*account)?;
-            println!("{}: {} {} {} {}", acc.name, change.start_balance, change.income,
-                     change.expenditure, change.get_end_balance());
+        for account in self.changes.keys() {
+            accounts.get(*account)?;
+        }
+        for acc in accounts.ordered()? {
+            if let Some(change) = self.changes.get(&acc.id) {
+                println!("{}: {} {} {} {}", acc.name, change.start_balance, change.income,
+                         change.expenditure, change.get_end_balance());
+            }
         }
         Ok(())
     }
@@ -67,37 +133,80 @@ impl FinanceChanges {
 
 pub struct FinanceRecord {
     pub operations: Vec<FinanceOperation>,
-    pub totals: HashMap<u64, i64>
+    pub totals: HashMap<AccountId, i64>,
+    /// source files: index of the first operation after the file and file name
+    files: Vec<(usize, String)>
 }
 
 impl FinanceRecord {
     pub fn new(operations: Vec<FinanceOperation>) -> FinanceRecord {
-        FinanceRecord{operations, totals: HashMap::new()}
+        FinanceRecord{operations, totals: HashMap::new(), files: Vec::new()}
+    }
+
+    pub fn add_file_operations(&mut self, file_name: String, mut operations: Vec<FinanceOperation>) {
+        self.operations.append(&mut operations);
+        self.files.push((self.operations.len(), file_name));
+    }
+
+    /// Name of the file index-th operation was loaded from.
+    pub fn get_file(&self, index: usize) -> Option<&str> {
+        self.files.iter().find(|(end, _)|index < *end).map(|(_, name)|name.as_str())
     }
 
     pub fn create_changes(&self) -> FinanceChanges {
         FinanceChanges::new(&self.totals)
     }
 
-    pub fn build_changes(&self, accounts: &Accounts,
-                         subcategories: &Subcategories) -> Result<FinanceChanges, Error> {
+    pub fn build_changes(&self, accounts: &Accounts, subcategories: &Subcategories,
+                         handlers: &SpecialHandlers) -> Result<FinanceChanges, Error> {
         let mut ch = self.create_changes();
         for op in &self.operations {
-            op.apply(&mut ch, accounts, subcategories)?;
+            op.apply(&mut ch, accounts, subcategories, handlers)?;
         }
         Ok(ch)
     }
 
-    pub fn update_changes(&self, ch: &mut FinanceChanges, from: u64, to: u64,
-                          accounts: &Accounts, subcategories: &Subcategories) -> Result<(), Error> {
+    pub fn update_changes(&self, ch: &mut FinanceChanges, from: u64, to: u64, accounts: &Accounts,
+                          subcategories: &Subcategories, handlers: &SpecialHandlers) -> Result<(), Error> {
         for op in &self.operations {
             if op.within(from, to) {
-                op.apply(ch, accounts, subcategories)?;
+                op.apply(ch, accounts, subcategories, handlers)?;
             }
         }
         Ok(())
     }
 
+    /// Pairs of (duplicate index, original index) for operations that repeat an earlier operation exactly.
+    pub fn find_duplicates(&self) -> Vec<(usize, usize)> {
+        let mut result = Vec::new();
+        for (i, op) in self.operations.iter().enumerate() {
+            if let Some(original) = self.operations[..i].iter().position(|o|o.is_duplicate_of(op)) {
+                result.push((i, original));
+            }
+        }
+        result
+    }
+
+    /// Removes exact duplicate operations, returns number of removed operations.
+    pub fn remove_duplicates(&mut self) -> usize {
+        let duplicates: HashSet<usize> = self.find_duplicates().into_iter().map(|(i, _)|i).collect();
+        self.take_operations(&duplicates).len()
+    }
+
+    /// Removes operations with given indices keeping source files of the others, returns removed operations.
+    pub fn take_operations(&mut self, indices: &HashSet<usize>) -> Vec<FinanceOperation> {
+        if indices.is_empty() {
+            return Vec::new();
+        }
+        for (end, _) in self.files.iter_mut() {
+            *end -= indices.iter().filter(|i|**i < *end).count();
+        }
+        let (taken, kept) = std::mem::take(&mut self.operations).into_iter().enumerate()
+            .partition::<Vec<_>, _>(|(i, _)|indices.contains(i));
+        self.operations = kept.into_iter().map(|(_, op)|op).collect();
+        taken.into_iter().map(|(_, op)|op).collect()
+    }
+
     pub fn get_ops(&self, date: u64) -> Vec<FinanceOperation> {
         let ops: Vec<FinanceOperation> = self.operations.iter()
             .filter(|op|op.date == date)
@@ -107,20 +216,31 @@ impl FinanceRecord {
     }
 }
 
-#[derive(Deserialize)]
+/// Integer amounts are written as stored (minor units of the account currency for summa,
+/// thousandths for amount), so serialized operations are read back without rounding.
+/// Decimal summas of legacy files are read in hundredths, the operations source converts them
+/// to minor units of the account currency.
+#[derive(Deserialize, Serialize)]
 pub struct FinanceOperation {
-    #[serde(alias = "Id", alias = "id")]
+    #[serde(alias = "Id", alias = "id", rename(serialize = "id"))]
     pub date: u64,
-    #[serde(alias = "AccountId", alias = "accountId")]
-    account: u64,
-    #[serde(alias = "SubcategoryId", alias = "subcategoryId")]
-    subcategory: u64,
-    #[serde(alias = "Amount", alias = "amount", deserialize_with = "deserialize_summa3")]
+    #[serde(alias = "AccountId", alias = "accountId", rename(serialize = "accountId"))]
+    account: AccountId,
+    #[serde(alias = "SubcategoryId", alias = "subcategoryId", rename(serialize = "subcategoryId"))]
+    subcategory: SubcategoryId,
+    #[serde(alias = "Amount", alias = "amount", rename(serialize = "amount"), deserialize_with = "deserialize_summa3")]
     amount: Option<u64>,
-    #[serde(alias = "Summa", alias = "summa", deserialize_with = "deserialize_summa2")]
+    #[serde(alias = "Summa", alias = "summa", rename(serialize = "summa"), deserialize_with = "deserialize_summa2")]
     summa: i64,
-    #[serde(alias = "FinOpProperies", alias = "finOpProperies", deserialize_with = "deserialize_parameters")]
-    parameters: Vec<FinOpParameter>
+    #[serde(alias = "FinOpProperies", alias = "finOpProperies", rename(serialize = "finOpProperies"),
+            deserialize_with = "deserialize_parameters", serialize_with = "serialize_parameters")]
+    parameters: Parameters,
+    #[serde(alias = "PayeeId", alias = "payeeId", rename(serialize = "payeeId"), default,
+            skip_serializing_if = "Option::is_none")]
+    payee: Option<PayeeId>,
+    #[serde(alias = "Attachments", alias = "attachments", rename(serialize = "attachments"), default,
+            skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<String>
 }
 
 fn deserialize_summa2<'de, D>(deserializer: D) -> Result<i64, D::Error>
@@ -137,7 +257,7 @@ fn deserialize_summa2<'de, D>(deserializer: D) -> Result<i64, D::Error>
         }
 
         fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> where E: serde::de::Error {
-            Ok((v * 100.0).round() as i64)
+            Ok((v * 10f64.powi(SUMMA_DECIMALS as i32)).round() as i64)
         }
 
         fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> where E: serde::de::Error {
@@ -167,7 +287,7 @@ fn deserialize_summa3<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
         }
 
         fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> where E: serde::de::Error {
-            Ok(Some((v * 1000.0).round() as u64))
+            Ok(Some((v * 10f64.powi(AMOUNT_DECIMALS as i32)).round() as u64))
         }
 
         fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> where E: serde::de::Error {
@@ -183,64 +303,185 @@ fn deserialize_summa3<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
     deserializer.deserialize_any(JsonStringVisitor)
 }
 
-fn deserialize_parameters<'de, D>(deserializer: D) -> Result<Vec<FinOpParameter>, D::Error>
+fn deserialize_parameters<'de, D>(deserializer: D) -> Result<Parameters, D::Error>
     where
         D: Deserializer<'de>,
 {
     let v: Option<Vec<FinOpParameterJson>> = Deserialize::deserialize(deserializer)?;
-    let mut result = Vec::new();
-    if v.is_some() {
-        for p in v.unwrap() {
+    let mut result = Parameters::new();
+    if let Some(parameters) = v {
+        for p in parameters {
             let pp = match p.code.as_str() {
                 "AMOU" => p.numeric_value.ok_or(serde::de::Error::invalid_value(Unexpected::Option, &"AMOU: numeric value expected"))
-                    .map(|v|FinOpParameter::Amou(v)),
+                    .map(FinOpParameter::Amou),
                 "DIST" => p.numeric_value.ok_or(serde::de::Error::invalid_value(Unexpected::Option,&"DIST: numeric value expected"))
-                    .map(|v|FinOpParameter::Dist(v)),
+                    .map(FinOpParameter::Dist),
                 "PPTO" => p.numeric_value.ok_or(serde::de::Error::invalid_value(Unexpected::Option, &"PPTO: numeric value expected"))
-                    .map(|v|FinOpParameter::Ppto(v)),
+                    .map(FinOpParameter::Ppto),
                 "SECA" => p.numeric_value.ok_or(serde::de::Error::invalid_value(Unexpected::Option, &"SECA: numeric value expected"))
-                    .map(|v|FinOpParameter::Seca(v)),
+                    .map(|v|FinOpParameter::Seca(AccountId(v))),
                 "NETW" => p.string_value.ok_or(serde::de::Error::invalid_value(Unexpected::Option,&"NETW: string value expected"))
-                    .map(|v|FinOpParameter::Netw(v)),
+                    .map(|v|FinOpParameter::Netw(Arc::from(v))),
                 "TYPE" => p.string_value.ok_or(serde::de::Error::invalid_value(Unexpected::Option,&"TYPE: string value expected"))
-                    .map(|v|FinOpParameter::Typ(v)),
-                _ => return Err(serde::de::Error::invalid_value(Unexpected::Str(p.code.as_str()),
-                                                                &"finOpParameter code"))
+                    .map(|v|FinOpParameter::Typ(Arc::from(v))),
+                "INST" => p.numeric_value.ok_or(serde::de::Error::invalid_value(Unexpected::Option,&"INST: numeric value expected"))
+                    .map(|v|FinOpParameter::Inst(InstrumentId(v))),
+                _ => {
+                    let value = if let Some(v) = p.numeric_value {
+                        Some(ParameterValue::Numeric(v))
+                    } else if let Some(v) = p.date_value {
+                        Some(ParameterValue::Date(v))
+                    } else {
+                        p.string_value.map(ParameterValue::String)
+                    };
+                    value.ok_or(serde::de::Error::invalid_value(Unexpected::Str(p.code.as_str()),
+                                                                &"finOpParameter with value"))
+                        .map(|v|FinOpParameter::custom(p.code, v))
+                }
             }?;
             result.push(pp);
         }
     }
-    return Ok(result);
+    Ok(result)
+}
+
+/// Handler for operations whose subcategory has the SPCL operation code.
+pub type SpecialHandler = fn(&FinanceOperation, &mut FinanceChanges, &Accounts) -> Result<(), Error>;
+
+pub struct SpecialHandlers {
+    map: HashMap<SubcategoryCode, SpecialHandler>
+}
+
+impl Default for SpecialHandlers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpecialHandlers {
+    pub fn new() -> SpecialHandlers {
+        let mut handlers = SpecialHandlers{map: HashMap::new()};
+        // Пополнение карточного счета наличными
+        handlers.register(SubcategoryCode::Incc, |op, ch, accounts|op.handle_incc(ch, accounts));
+        // Снятие наличных в банкомате
+        handlers.register(SubcategoryCode::Expc, |op, ch, accounts|op.handle_expc(ch, accounts));
+        // Обмен валюты
+        handlers.register(SubcategoryCode::Exch, |op, ch, accounts|op.handle_exch(ch, accounts));
+        // Перевод средств между платежными картами
+        handlers.register(SubcategoryCode::Trfr, |op, ch, accounts|op.handle_trfr(ch, accounts));
+        // Получение кредита
+        handlers.register(SubcategoryCode::Lnds,
+                          |op, ch, _|ch.get_account_changes(op.account).handle_income(op.summa));
+        // Погашение кредита
+        handlers.register(SubcategoryCode::Lnrp,
+                          |op, ch, _|ch.get_account_changes(op.account).handle_expenditure(op.summa));
+        // Проценты по кредиту
+        handlers.register(SubcategoryCode::Lnin,
+                          |op, ch, _|ch.get_account_changes(op.account).handle_expenditure(op.summa));
+        // Покупка ценных бумаг
+        handlers.register(SubcategoryCode::Ibuy,
+                          |op, ch, _|ch.get_account_changes(op.account).handle_expenditure(op.summa));
+        // Продажа ценных бумаг
+        handlers.register(SubcategoryCode::Isel,
+                          |op, ch, _|ch.get_account_changes(op.account).handle_income(op.summa));
+        // Дивиденды
+        handlers.register(SubcategoryCode::Idiv,
+                          |op, ch, _|ch.get_account_changes(op.account).handle_income(op.summa));
+        // Начальный остаток счета
+        handlers.register(SubcategoryCode::Opbl,
+                          |op, ch, _|ch.get_account_changes(op.account).handle_income(op.summa));
+        handlers
+    }
+
+    pub fn register(&mut self, code: SubcategoryCode, handler: SpecialHandler) {
+        self.map.insert(code, handler);
+    }
+
+    pub fn get(&self, code: &SubcategoryCode) -> Result<SpecialHandler, Error> {
+        self.map.get(code).copied()
+            .ok_or(Error::new(ErrorKind::InvalidData, "invalid subcategory code"))
+    }
 }
 
 impl FinanceOperation {
+    pub fn new(date: u64, account: AccountId, subcategory: SubcategoryId, amount: Option<u64>, summa: i64,
+               parameters: Vec<FinOpParameter>) -> FinanceOperation {
+        FinanceOperation{date, account, subcategory, amount, summa, parameters: Parameters::from_vec(parameters), payee: None,
+            attachments: Vec::new()}
+    }
+
     pub fn apply(&self, changes: &mut FinanceChanges, accounts: &Accounts,
-                 subcategories: &Subcategories) -> Result<(), Error> {
+                 subcategories: &Subcategories, handlers: &SpecialHandlers) -> Result<(), Error> {
         let subcategory = subcategories.get(self.subcategory)?;
-        match subcategory.operation_code {
+        let result = match subcategory.operation_code {
             SubcategoryOperationCode::Incm => changes.get_account_changes(self.account).handle_income(self.summa),
             SubcategoryOperationCode::Expn => changes.get_account_changes(self.account).handle_expenditure(self.summa),
-            SubcategoryOperationCode::Spcl => {
-                match subcategory.code {
-                    // Пополнение карточного счета наличными
-                    SubcategoryCode::Incc => self.handle_incc(changes, accounts),
-                    // Снятие наличных в банкомате
-                    SubcategoryCode::Expc => self.handle_expc(changes, accounts),
-                    // Обмен валюты
-                    SubcategoryCode::Exch => self.handle_exch(changes),
-                    // Перевод средств между платежными картами
-                    SubcategoryCode::Trfr => self.handle_trfr(changes),
-                    _ => Err(Error::new(ErrorKind::InvalidData, "invalid subcategory code"))
-                }
-            }
-        }
+            SubcategoryOperationCode::Spcl => handlers.get(&subcategory.code)?(self, changes, accounts)
+        };
+        result.map_err(|e|Error::new(e.kind(), format!("{} at {}", e, self.date)))
+    }
+
+    pub fn get_account(&self) -> AccountId {
+        self.account
+    }
+
+    pub fn get_subcategory(&self) -> SubcategoryId {
+        self.subcategory
+    }
+
+    pub fn set_subcategory(&mut self, subcategory: SubcategoryId) {
+        self.subcategory = subcategory;
+    }
+
+    pub fn get_amount(&self) -> Option<u64> {
+        self.amount
+    }
+
+    pub fn get_summa(&self) -> i64 {
+        self.summa
+    }
+
+    pub fn set_summa(&mut self, summa: i64) {
+        self.summa = summa;
+    }
+
+    pub fn get_parameters(&self) -> &[FinOpParameter] {
+        &self.parameters
+    }
+
+    pub fn get_second_account(&self) -> Option<AccountId> {
+        self.parameters.iter().find_map(|p|if let FinOpParameter::Seca(a) = p {Some(*a)} else {None})
+    }
+
+    pub fn get_instrument(&self) -> Option<InstrumentId> {
+        self.parameters.iter().find_map(|p|if let FinOpParameter::Inst(i) = p {Some(*i)} else {None})
+    }
+
+    pub fn get_network(&self) -> Option<&str> {
+        self.parameters.iter().find_map(|p|if let FinOpParameter::Netw(n) = p {Some(n.as_ref())} else {None})
+    }
+
+    pub fn get_payee(&self) -> Option<PayeeId> {
+        self.payee
+    }
+
+    pub fn set_payee(&mut self, payee: Option<PayeeId>) {
+        self.payee = payee;
+    }
+
+    pub fn get_attachments(&self) -> &Vec<String> {
+        &self.attachments
+    }
+
+    pub fn add_attachment(&mut self, id: String) {
+        self.attachments.push(id);
     }
 
     fn handle_incc(&self, changes: &mut FinanceChanges,
                    accounts: &Accounts) -> Result<(), Error> {
         changes.get_account_changes(self.account).handle_income(self.summa)?;
         // cash account for corresponding currency code
-        let cash_account = accounts.get_cash_account(self.account)?;
+        let cash_account = accounts.get_cash_account(self.account, self.date)?;
         if let Some(a) = cash_account {
             changes.get_account_changes(a).handle_expenditure(self.summa)
         } else {
@@ -251,7 +492,7 @@ impl FinanceOperation {
     fn handle_expc(&self, changes: &mut FinanceChanges, accounts: &Accounts) -> Result<(), Error> {
         changes.get_account_changes(self.account).handle_expenditure(self.summa)?;
         // cash account for corresponding currency code
-        let cash_account = accounts.get_cash_account(self.account)?;
+        let cash_account = accounts.get_cash_account(self.account, self.date)?;
         if let Some(a) = cash_account {
             changes.get_account_changes(a).handle_income(self.summa)
         } else {
@@ -259,14 +500,38 @@ impl FinanceOperation {
         }
     }
 
-    fn handle_exch(&self, changes: &mut FinanceChanges) -> Result<(), Error> {
+    /// Transfers must keep the currency, exchanges must change it.
+    fn check_currencies(&self, accounts: &Accounts, same_currency: bool) -> Result<(), Error> {
+        if let Some(second) = self.get_second_account() {
+            let from = accounts.get(self.account)?;
+            let to = accounts.get(second)?;
+            let (from_currency, to_currency) = (from.currency_at(self.date), to.currency_at(self.date));
+            if (from_currency == to_currency) != same_currency {
+                let message = if same_currency {
+                    format!("transfer between accounts with different currencies: {} ({}) -> {} ({})",
+                            from.name, from_currency, to.name, to_currency)
+                } else {
+                    format!("currency exchange between accounts with the same currency: {} -> {} ({})",
+                            from.name, to.name, to_currency)
+                };
+                return Err(Error::new(ErrorKind::InvalidData, message));
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_exch(&self, changes: &mut FinanceChanges, accounts: &Accounts) -> Result<(), Error> {
+        self.check_currencies(accounts, false)?;
         if let Some(a) = self.amount {
-            return self.handle_trfr_with_summa(changes, (a as i64) / 10)
+            let summa = i64::try_from(a / 10)
+                .map_err(|_|Error::new(ErrorKind::InvalidData, format!("amount {} is too large", a)))?;
+            return self.handle_trfr_with_summa(changes, summa)
         }
         Ok(())
     }
 
-    fn handle_trfr(&self, changes: &mut FinanceChanges) -> Result<(), Error> {
+    fn handle_trfr(&self, changes: &mut FinanceChanges, accounts: &Accounts) -> Result<(), Error> {
+        self.check_currencies(accounts, true)?;
         self.handle_trfr_with_summa(changes, self.summa)
     }
 
@@ -280,11 +545,43 @@ impl FinanceOperation {
         Ok(())
     }
 
+    /// Replaces parameter strings with shared copies from the pool.
+    pub fn intern_strings(&mut self, pool: &mut StringPool) {
+        for p in self.parameters.iter_mut() {
+            match p {
+                FinOpParameter::Netw(v) | FinOpParameter::Typ(v) => *v = pool.intern(v),
+                _ => {}
+            }
+        }
+    }
+
+    /// Replaces values of string parameters, f gets parameter code and value and returns the new value
+    /// or None to keep the parameter unchanged.
+    pub fn map_strings(&mut self, f: impl Fn(&str, &str) -> Result<Option<String>, Error>) -> Result<(), Error> {
+        for p in self.parameters.iter_mut() {
+            match p {
+                FinOpParameter::Netw(v) => if let Some(n) = f("NETW", v)? {*v = n.into()},
+                FinOpParameter::Typ(v) => if let Some(n) = f("TYPE", v)? {*v = n.into()},
+                FinOpParameter::Custom(c) => if let ParameterValue::String(v) = &mut c.value {
+                    if let Some(n) = f(&c.code, v)? {*v = n}
+                },
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Same date, account, subcategory, amount, summa and parameters.
+    pub fn is_duplicate_of(&self, other: &FinanceOperation) -> bool {
+        self.date == other.date && self.account == other.account && self.subcategory == other.subcategory &&
+            self.amount == other.amount && self.summa == other.summa && self.parameters == other.parameters
+    }
+
     pub fn within(&self, from: u64, to: u64) -> bool {
         self.date >= from && self.date <= to
     }
     
-    fn copy(&self) -> FinanceOperation {
+    pub fn copy(&self) -> FinanceOperation {
         FinanceOperation{
             date: self.date,
             account: self.account,
@@ -292,28 +589,132 @@ impl FinanceOperation {
             amount: self.amount,
             summa: self.summa,
             parameters: self.parameters.clone(),
+            payee: self.payee,
+            attachments: self.attachments.clone()
         }
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 struct FinOpParameterJson {
-    #[serde(alias = "NumericValue", alias = "numericValue")]
+    #[serde(alias = "NumericValue", alias = "numericValue", rename(serialize = "numericValue"))]
     numeric_value: Option<u64>,
-    #[serde(alias = "StringValue", alias = "stringValue")]
+    #[serde(alias = "StringValue", alias = "stringValue", rename(serialize = "stringValue"))]
     string_value: Option<String>,
-    #[serde(alias = "DateValue", alias = "dateValue", deserialize_with = "date_deserialize")]
+    #[serde(alias = "DateValue", alias = "dateValue", rename(serialize = "dateValue"),
+            deserialize_with = "date_deserialize", serialize_with = "date_serialize")]
     date_value: Option<u64>,
-    #[serde(alias = "PropertyCode", alias = "propertyCode")]
+    #[serde(alias = "PropertyCode", alias = "propertyCode", rename(serialize = "propertyCode"))]
     code: String
 }
 
-#[derive(Clone)]
+impl From<&FinOpParameter> for FinOpParameterJson {
+    fn from(p: &FinOpParameter) -> Self {
+        let code = p.get_code().to_string();
+        let (numeric_value, string_value, date_value) = match p {
+            FinOpParameter::Amou(v) | FinOpParameter::Dist(v) | FinOpParameter::Ppto(v) => (Some(*v), None, None),
+            FinOpParameter::Seca(a) => (Some(a.0), None, None),
+            FinOpParameter::Inst(i) => (Some(i.0), None, None),
+            FinOpParameter::Netw(v) | FinOpParameter::Typ(v) => (None, Some(v.to_string()), None),
+            FinOpParameter::Custom(c) => match &c.value {
+                ParameterValue::Numeric(v) => (Some(*v), None, None),
+                ParameterValue::String(v) => (None, Some(v.clone()), None),
+                ParameterValue::Date(v) => (None, None, Some(*v))
+            }
+        };
+        FinOpParameterJson{numeric_value, string_value, date_value, code}
+    }
+}
+
+fn serialize_parameters<S>(parameters: &Parameters, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+{
+    if parameters.is_empty() {
+        serializer.serialize_none()
+    } else {
+        serializer.collect_seq(parameters.iter().map(FinOpParameterJson::from))
+    }
+}
+
+#[derive(Clone, PartialEq)]
 pub enum FinOpParameter {
     Amou(u64),
     Dist(u64),
-    Netw(String),
+    Netw(Arc<str>),
     Ppto(u64),
-    Seca(u64),
-    Typ(String)
+    Seca(AccountId),
+    Typ(Arc<str>),
+    Inst(InstrumentId),
+    /// Parameter with code not known to this version, described by parameter definitions dictionary.
+    /// Boxed to keep the enum small, most operations carry only known parameters.
+    Custom(Box<CustomParameter>)
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct CustomParameter {
+    pub code: String,
+    pub value: ParameterValue
+}
+
+/// Operation parameters, most operations have zero or one so the first one is stored inline.
+pub type Parameters = SmallVec<[FinOpParameter; 1]>;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ParameterValue {
+    Numeric(u64),
+    String(String),
+    Date(u64)
+}
+
+impl FinOpParameter {
+    pub fn get_code(&self) -> &str {
+        match self {
+            FinOpParameter::Amou(_) => "AMOU",
+            FinOpParameter::Dist(_) => "DIST",
+            FinOpParameter::Netw(_) => "NETW",
+            FinOpParameter::Ppto(_) => "PPTO",
+            FinOpParameter::Seca(_) => "SECA",
+            FinOpParameter::Typ(_) => "TYPE",
+            FinOpParameter::Inst(_) => "INST",
+            FinOpParameter::Custom(c) => &c.code
+        }
+    }
+
+    pub fn custom(code: String, value: ParameterValue) -> FinOpParameter {
+        FinOpParameter::Custom(Box::new(CustomParameter{code, value}))
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use crate::entities::finance_operations::{FinOpParameter, FinanceOperation, ParameterValue};
+
+    #[test]
+    fn test_custom_parameter() {
+        let op: FinanceOperation = serde_json::from_str(r#"{"id": 1, "accountId": 2, "subcategoryId": 3,
+            "amount": null, "summa": 10, "finOpProperies": [
+            {"numericValue": null, "stringValue": "VISA", "dateValue": null, "propertyCode": "NETW"},
+            {"numericValue": null, "stringValue": "shop", "dateValue": null, "propertyCode": "SHOP"},
+            {"numericValue": null, "stringValue": null, "dateValue": [2024, 1, 5], "propertyCode": "WRNT"}
+        ]}"#).unwrap();
+        let parameters = op.get_parameters();
+        assert_eq!(parameters.len(), 3);
+        assert!(matches!(&parameters[0], FinOpParameter::Netw(v) if &**v == "VISA"));
+        assert!(matches!(&parameters[1], FinOpParameter::Custom(c) if c.code == "SHOP" && c.value == ParameterValue::String("shop".to_string())));
+        assert!(matches!(&parameters[2], FinOpParameter::Custom(c) if c.code == "WRNT" && c.value == ParameterValue::Date(20240105)));
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let json = r#"{"id": 1, "accountId": 2, "subcategoryId": 3, "amount": 1.5, "summa": 10.25, "finOpProperies": [
+            {"numericValue": 4, "stringValue": null, "dateValue": null, "propertyCode": "SECA"},
+            {"numericValue": null, "stringValue": null, "dateValue": [2024, 1, 5], "propertyCode": "WRNT"}
+        ]}"#;
+        let op: FinanceOperation = serde_json::from_str(json).unwrap();
+        let copy: FinanceOperation = serde_json::from_slice(&serde_json::to_vec(&op).unwrap()).unwrap();
+        assert!(copy.is_duplicate_of(&op));
+        assert_eq!(copy.get_amount(), Some(1500));
+        assert_eq!(copy.get_summa(), 1025);
+    }
 }
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::ops::Add;
+use serde::{Deserialize, Serialize};
+use crate::core::data_source::DataSource;
+use crate::entities::accounts::{AccountId, Accounts};
+use crate::entities::common::{date_deserialize, date_serialize};
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[serde(transparent)]
+pub struct GoalId(pub u64);
+
+impl fmt::Display for GoalId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Savings goal, progress is the sum of linked account balances.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Goal {
+    pub id: GoalId,
+    pub name: String,
+    pub target: i64,
+    #[serde(rename = "targetDate", deserialize_with = "date_deserialize", serialize_with = "date_serialize")]
+    pub target_date: Option<u64>,
+    #[serde(rename = "accountIds")]
+    pub accounts: Vec<AccountId>
+}
+
+pub struct Goals {
+    map: HashMap<GoalId, Goal>
+}
+
+impl Goals {
+    /// Goals file is optional, databases without it get an empty dictionary.
+    pub fn load(data_folder_path: String, source: Box<dyn DataSource<Vec<Goal>>>) -> Result<Goals, Error> {
+        match source.load(data_folder_path.add("/goals"), true) {
+            Ok(goals) => Ok(Goals::new(goals)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Goals::new(Vec::new())),
+            Err(e) => Err(e)
+        }
+    }
+
+    pub fn new(goals: Vec<Goal>) -> Goals {
+        let map = goals.into_iter().map(|g|(g.id, g)).collect();
+        Goals{map}
+    }
+
+    pub fn get(&self, id: GoalId) -> Result<&Goal, Error> {
+        self.map.get(&id).ok_or(Error::new(ErrorKind::InvalidData, "invalid goal id"))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Goal> {
+        self.map.values()
+    }
+
+    pub fn validate(&self, accounts: &Accounts) -> Result<(), Error> {
+        for goal in self.map.values() {
+            for account in &goal.accounts {
+                accounts.get(*account)
+                    .map_err(|_|Error::new(ErrorKind::InvalidData, format!("goal {} has invalid account", goal.name)))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, dest: Box<dyn DataSource<Vec<Goal>>>, data_folder_path: String) -> Result<(), Error>{
+        let mut values: Vec<Goal> = self.map.values().cloned().collect();
+        values.sort_by_key(|v|v.id);
+        dest.save(&values, data_folder_path.add("/goals"))
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::ops::Add;
+use serde::{Deserialize, Serialize};
+use crate::core::data_source::DataSource;
+use crate::entities::common::{date_deserialize, date_serialize};
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[serde(transparent)]
+pub struct InstrumentId(pub u64);
+
+impl fmt::Display for InstrumentId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Security traded on brokerage accounts. IBUY/ISEL operations have INST parameter
+/// and quantity in amount field (thousandths), IDIV operations are dividends.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Instrument {
+    pub id: InstrumentId,
+    pub name: String,
+    pub ticker: String,
+    #[serde(rename = "valutaCode")]
+    pub currency: String
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct InstrumentPrice {
+    #[serde(rename = "instrumentId")]
+    pub instrument: InstrumentId,
+    #[serde(deserialize_with = "date_deserialize", serialize_with = "date_serialize")]
+    pub date: Option<u64>,
+    /// price of one unit in hundredths
+    pub price: i64
+}
+
+pub struct Instruments {
+    map: HashMap<InstrumentId, Instrument>,
+    prices: HashMap<InstrumentId, BTreeMap<u64, i64>>
+}
+
+impl Instruments {
+    /// Instruments and prices files are optional, databases without them get empty dictionaries.
+    pub fn load(data_folder_path: String, source: Box<dyn DataSource<Vec<Instrument>>>,
+                prices_source: Box<dyn DataSource<Vec<InstrumentPrice>>>) -> Result<Instruments, Error> {
+        let instruments = match source.load(data_folder_path.clone().add("/instruments"), true) {
+            Ok(instruments) => instruments,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e)
+        };
+        let prices = match prices_source.load(data_folder_path.add("/prices"), true) {
+            Ok(prices) => prices,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e)
+        };
+        Instruments::new(instruments, prices)
+    }
+
+    pub fn new(instruments: Vec<Instrument>, prices: Vec<InstrumentPrice>) -> Result<Instruments, Error> {
+        let map: HashMap<InstrumentId, Instrument> = instruments.into_iter().map(|i|(i.id, i)).collect();
+        let mut result = Instruments{map, prices: HashMap::new()};
+        for p in prices {
+            result.add_price(p)?;
+        }
+        Ok(result)
+    }
+
+    pub fn add_price(&mut self, price: InstrumentPrice) -> Result<(), Error> {
+        self.get(price.instrument)?;
+        let date = price.date.ok_or(Error::new(ErrorKind::InvalidData, "instrument price date expected"))?;
+        self.prices.entry(price.instrument).or_default().insert(date, price.price);
+        Ok(())
+    }
+
+    pub fn get(&self, id: InstrumentId) -> Result<&Instrument, Error> {
+        self.map.get(&id).ok_or(Error::new(ErrorKind::InvalidData, "invalid instrument id"))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Instrument> {
+        self.map.values()
+    }
+
+    /// Latest known price at given date.
+    pub fn get_price(&self, id: InstrumentId, date: u64) -> Option<i64> {
+        self.prices.get(&id)
+            .and_then(|p|p.range(..=date).next_back())
+            .map(|(_, price)|*price)
+    }
+}
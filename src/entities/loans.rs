@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::ops::Add;
+use serde::{Deserialize, Serialize};
+use crate::core::data_source::DataSource;
+use crate::entities::accounts::{AccountId, Accounts};
+use crate::entities::common::{date_deserialize, date_serialize};
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[serde(transparent)]
+pub struct LoanId(pub u64);
+
+impl fmt::Display for LoanId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Loan linked to an account. Principal is the debt at start date, LNDS operations on the account
+/// add further draws, LNRP operations repay the debt and LNIN operations pay interest.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Loan {
+    pub id: LoanId,
+    pub name: String,
+    #[serde(rename = "accountId")]
+    pub account: AccountId,
+    pub principal: i64,
+    /// annual interest rate in hundredths of percent
+    pub rate: u64,
+    #[serde(rename = "startDate", deserialize_with = "date_deserialize", serialize_with = "date_serialize")]
+    pub start: Option<u64>,
+    #[serde(rename = "monthlyPayment")]
+    pub monthly_payment: i64
+}
+
+impl Loan {
+    /// Number of monthly payments needed to repay outstanding debt, None when payment doesn't cover interest.
+    pub fn project_payments(&self, outstanding: i64) -> Option<u64> {
+        let mut balance = outstanding as i128;
+        let mut months = 0;
+        while balance > 0 {
+            let interest = balance * self.rate as i128 / 120000;
+            if self.monthly_payment as i128 <= interest || months >= 1200 {
+                return None;
+            }
+            balance = balance + interest - self.monthly_payment as i128;
+            months += 1;
+        }
+        Some(months)
+    }
+}
+
+pub struct Loans {
+    map: HashMap<LoanId, Loan>
+}
+
+impl Loans {
+    /// Loans file is optional, databases without it get an empty dictionary.
+    pub fn load(data_folder_path: String, source: Box<dyn DataSource<Vec<Loan>>>) -> Result<Loans, Error> {
+        match source.load(data_folder_path.add("/loans"), true) {
+            Ok(loans) => Ok(Loans::new(loans)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Loans::new(Vec::new())),
+            Err(e) => Err(e)
+        }
+    }
+
+    pub fn new(loans: Vec<Loan>) -> Loans {
+        let map = loans.into_iter().map(|l|(l.id, l)).collect();
+        Loans{map}
+    }
+
+    pub fn get(&self, id: LoanId) -> Result<&Loan, Error> {
+        self.map.get(&id).ok_or(Error::new(ErrorKind::InvalidData, "invalid loan id"))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Loan> {
+        self.map.values()
+    }
+
+    pub fn validate(&self, accounts: &Accounts) -> Result<(), Error> {
+        for loan in self.map.values() {
+            accounts.get(loan.account)
+                .map_err(|_|Error::new(ErrorKind::InvalidData, format!("loan {} has invalid account", loan.name)))?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, dest: Box<dyn DataSource<Vec<Loan>>>, data_folder_path: String) -> Result<(), Error>{
+        let mut values: Vec<Loan> = self.map.values().cloned().collect();
+        values.sort_by_key(|v|v.id);
+        dest.save(&values, data_folder_path.add("/loans"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entities::accounts::AccountId;
+    use crate::entities::loans::{Loan, LoanId};
+
+    fn loan(rate: u64, monthly_payment: i64) -> Loan {
+        Loan{id: LoanId(1), name: "loan".to_string(), account: AccountId(1), principal: 120000, rate,
+            start: None, monthly_payment}
+    }
+
+    #[test]
+    fn test_project_payments() {
+        assert_eq!(loan(0, 10000).project_payments(120000), Some(12));
+        // 12% annual rate, 1% monthly
+        assert_eq!(loan(1200, 10000).project_payments(120000), Some(13));
+        assert_eq!(loan(1200, 1200).project_payments(120000), None);
+        assert_eq!(loan(1200, 1200).project_payments(0), Some(0));
+    }
+}
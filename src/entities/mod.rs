@@ -1,4 +1,15 @@
 pub mod finance_operations;
 pub mod accounts;
 pub mod subcategories;
-mod common;
\ No newline at end of file
+pub mod currencies;
+pub mod payees;
+pub mod parameters;
+pub mod loans;
+pub mod instruments;
+pub mod goals;
+pub mod budgets;
+mod common;
+pub mod rates;
+pub mod settings;
+pub mod templates;
+pub mod validation_rules;
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::ops::Add;
+use serde::{Deserialize, Serialize};
+use crate::core::data_source::DataSource;
+use crate::entities::finance_operations::{FinOpParameter, ParameterValue};
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ParameterValueType {
+    Numeric,
+    String,
+    Date
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ParameterDefinition {
+    pub code: String,
+    pub name: String,
+    #[serde(rename = "valueType")]
+    pub value_type: ParameterValueType
+}
+
+/// Definitions of operation parameter codes beyond the built-in ones (AMOU, DIST, INST, NETW, PPTO, SECA, TYPE).
+pub struct ParameterDefinitions {
+    map: HashMap<String, ParameterDefinition>
+}
+
+impl ParameterDefinitions {
+    /// Parameters file is optional, databases without it get an empty dictionary.
+    pub fn load(data_folder_path: String, source: Box<dyn DataSource<Vec<ParameterDefinition>>>)
+        -> Result<ParameterDefinitions, Error> {
+        match source.load(data_folder_path.add("/parameters"), true) {
+            Ok(definitions) => Ok(ParameterDefinitions::new(definitions)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(ParameterDefinitions::new(Vec::new())),
+            Err(e) => Err(e)
+        }
+    }
+
+    pub fn new(definitions: Vec<ParameterDefinition>) -> ParameterDefinitions {
+        let map = definitions.into_iter().map(|d|(d.code.clone(), d)).collect();
+        ParameterDefinitions{map}
+    }
+
+    pub fn get(&self, code: &str) -> Result<&ParameterDefinition, Error> {
+        self.map.get(code).ok_or(Error::new(ErrorKind::InvalidData, "invalid parameter code"))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ParameterDefinition> {
+        self.map.values()
+    }
+
+    /// Checks that custom parameters are defined and have values of the defined type.
+    pub fn validate(&self, parameters: &[FinOpParameter]) -> Result<(), Error> {
+        for p in parameters {
+            if let FinOpParameter::Custom(c) = p {
+                let definition = self.get(&c.code)?;
+                let value_type = match c.value {
+                    ParameterValue::Numeric(_) => ParameterValueType::Numeric,
+                    ParameterValue::String(_) => ParameterValueType::String,
+                    ParameterValue::Date(_) => ParameterValueType::Date
+                };
+                if value_type != definition.value_type {
+                    return Err(Error::new(ErrorKind::InvalidData, format!("{}: invalid parameter value type", c.code)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, dest: Box<dyn DataSource<Vec<ParameterDefinition>>>, data_folder_path: String) -> Result<(), Error>{
+        let mut values: Vec<ParameterDefinition> = self.map.values().cloned().collect();
+        values.sort_by(|a, b|a.code.cmp(&b.code));
+        dest.save(&values, data_folder_path.add("/parameters"))
+    }
+}
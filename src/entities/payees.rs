@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::ops::Add;
+use serde::{Deserialize, Serialize};
+use crate::core::data_source::DataSource;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[serde(transparent)]
+pub struct PayeeId(pub u64);
+
+impl fmt::Display for PayeeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Payee {
+    pub id: PayeeId,
+    pub name: String
+}
+
+pub struct Payees {
+    map: HashMap<PayeeId, Payee>
+}
+
+impl Payees {
+    /// Payees file is optional, databases without it get an empty dictionary.
+    pub fn load(data_folder_path: String, source: Box<dyn DataSource<Vec<Payee>>>)
+        -> Result<Payees, Error> {
+        match source.load(data_folder_path.add("/payees"), true) {
+            Ok(payees) => Ok(Payees::new(payees)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Payees::new(Vec::new())),
+            Err(e) => Err(e)
+        }
+    }
+
+    pub fn new(payees: Vec<Payee>) -> Payees {
+        let map = payees.into_iter().map(|p|(p.id, p)).collect();
+        Payees{map}
+    }
+
+    pub fn get(&self, id: PayeeId) -> Result<&Payee, Error> {
+        self.map.get(&id).ok_or(Error::new(ErrorKind::InvalidData, "invalid payee id"))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Payee> {
+        self.map.values()
+    }
+
+    pub fn save(&self, dest: Box<dyn DataSource<Vec<Payee>>>, data_folder_path: String) -> Result<(), Error>{
+        let mut values: Vec<Payee> = self.map.values().cloned().collect();
+        values.sort_by_key(|v|v.id);
+        dest.save(&values, data_folder_path.add("/payees"))
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Error, ErrorKind};
+use std::ops::Add;
+use serde::{Deserialize, Serialize};
+use crate::core::data_source::DataSource;
+use crate::entities::common::{date_deserialize, date_serialize};
+use crate::entities::currencies::Currencies;
+
+/// Number of units of currency for one unit of base currency.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ExchangeRate {
+    pub base: String,
+    pub currency: String,
+    #[serde(deserialize_with = "date_deserialize", serialize_with = "date_serialize")]
+    pub date: Option<u64>,
+    /// in millionths
+    pub rate: i64
+}
+
+/// Converts summa by rate in millionths, None on overflow.
+pub fn convert_by_rate(summa: i64, rate: i64) -> Option<i64> {
+    i64::try_from(summa as i128 * rate as i128 / 1000000).ok()
+}
+
+pub struct Rates {
+    map: HashMap<(String, String), BTreeMap<u64, i64>>
+}
+
+impl Rates {
+    /// Rates file is optional, databases without it get empty rates.
+    pub fn load(data_folder_path: String, source: Box<dyn DataSource<Vec<ExchangeRate>>>) -> Result<Rates, Error> {
+        match source.load(data_folder_path.add("/rates"), true) {
+            Ok(rates) => Rates::new(rates),
+            Err(e) if e.kind() == ErrorKind::NotFound => Rates::new(Vec::new()),
+            Err(e) => Err(e)
+        }
+    }
+
+    pub fn new(rates: Vec<ExchangeRate>) -> Result<Rates, Error> {
+        let mut result = Rates{map: HashMap::new()};
+        for r in rates {
+            result.add(r)?;
+        }
+        Ok(result)
+    }
+
+    pub fn add(&mut self, rate: ExchangeRate) -> Result<(), Error> {
+        let date = rate.date.ok_or(Error::new(ErrorKind::InvalidData, "exchange rate date expected"))?;
+        if rate.rate <= 0 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("invalid {}/{} rate at {}", rate.base,
+                                                                   rate.currency, date)));
+        }
+        self.map.entry((rate.base, rate.currency)).or_default().insert(date, rate.rate);
+        Ok(())
+    }
+
+    /// Latest known rate at given date.
+    pub fn get(&self, base: &str, currency: &str, date: u64) -> Option<i64> {
+        self.map.get(&(base.to_string(), currency.to_string()))
+            .and_then(|r|r.range(..=date).next_back())
+            .map(|(_, rate)|*rate)
+    }
+
+    /// Converts summa from one currency to another, directly or through a common base currency.
+    pub fn convert(&self, summa: i64, from: &str, to: &str, date: u64) -> Result<i64, Error> {
+        if from == to {
+            return Ok(summa);
+        }
+        let summa = summa as i128;
+        if let Some(rate) = self.get(to, from, date) {
+            return Ok((summa * 1000000 / rate as i128) as i64);
+        }
+        if let Some(rate) = self.get(from, to, date) {
+            return Ok((summa * rate as i128 / 1000000) as i64);
+        }
+        for (base, _) in self.map.keys().filter(|(_, currency)|currency == from) {
+            if let (Some(from_rate), Some(to_rate)) = (self.get(base, from, date), self.get(base, to, date)) {
+                return Ok((summa * to_rate as i128 / from_rate as i128) as i64);
+            }
+        }
+        Err(Error::new(ErrorKind::NotFound, format!("no {}/{} exchange rate at {}", from, to, date)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.values().map(|r|r.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ExchangeRate> + '_ {
+        self.map.iter().flat_map(|((base, currency), rates)|rates.iter().map(|(date, rate)|ExchangeRate{
+            base: base.clone(), currency: currency.clone(), date: Some(*date), rate: *rate}))
+    }
+
+    /// Every currency must be in the currencies dictionary, databases without it are not validated.
+    pub fn validate(&self, currencies: &Currencies) -> Result<(), Error> {
+        if currencies.is_empty() {
+            return Ok(());
+        }
+        for (base, currency) in self.map.keys() {
+            for code in [base, currency] {
+                currencies.get(code)
+                    .map_err(|_|Error::new(ErrorKind::InvalidData, format!("rate of unknown currency {}", code)))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, dest: Box<dyn DataSource<Vec<ExchangeRate>>>, data_folder_path: String) -> Result<(), Error> {
+        let mut rates: Vec<ExchangeRate> = self.iter().collect();
+        rates.sort_by(|a, b|(&a.base, &a.currency, a.date).cmp(&(&b.base, &b.currency, b.date)));
+        dest.save(&rates, data_folder_path.add("/rates"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::entities::rates::{ExchangeRate, Rates};
+
+    fn rate(currency: &str, date: u64, rate: i64) -> ExchangeRate {
+        ExchangeRate{base: "EUR".to_string(), currency: currency.to_string(), date: Some(date), rate}
+    }
+
+    #[test]
+    fn test_convert() -> Result<(), Error> {
+        let rates = Rates::new(vec![rate("USD", 20240101, 1100000), rate("UAH", 20240101, 44000000),
+                                    rate("UAH", 20240201, 40000000)])?;
+        assert_eq!(rates.convert(1100, "USD", "EUR", 20240115)?, 1000);
+        assert_eq!(rates.convert(1000, "EUR", "UAH", 20240115)?, 44000);
+        assert_eq!(rates.convert(44000, "UAH", "USD", 20240115)?, 1100);
+        assert_eq!(rates.convert(40000, "UAH", "EUR", 20240301)?, 1000);
+        assert!(rates.convert(100, "USD", "EUR", 20231231).is_err());
+        assert!(rates.convert(100, "GBP", "EUR", 20240115).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use std::io::{Error, ErrorKind};
+use std::ops::Add;
+use serde::{Deserialize, Serialize};
+use crate::core::data_source::DataSource;
+use crate::core::dates::DayBoundary;
+use crate::entities::currencies::Currencies;
+
+/// Database wide settings stored next to the dictionaries.
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct Settings {
+    /// currency reports are converted to, reports keep per currency totals when not set
+    #[serde(rename = "baseCurrency", default, skip_serializing_if = "Option::is_none")]
+    pub base_currency: Option<String>,
+    /// loads, saves and server requests taking longer are logged, in milliseconds
+    #[serde(rename = "slowOperationMs", default, skip_serializing_if = "Option::is_none")]
+    pub slow_operation_ms: Option<u64>,
+    /// local time zone imported bank transactions are dated in, UTC when not set
+    #[serde(rename = "utcOffsetMinutes", default, skip_serializing_if = "Option::is_none")]
+    pub utc_offset_minutes: Option<i64>,
+    /// hour the accounting day starts at, earlier transactions belong to the previous day
+    #[serde(rename = "dayStartHour", default, skip_serializing_if = "Option::is_none")]
+    pub day_start_hour: Option<u64>,
+    /// number of most accessed months loaded into the cache on startup
+    #[serde(rename = "warmMonths", default, skip_serializing_if = "Option::is_none")]
+    pub warm_months: Option<usize>,
+    /// operation parameter codes and PAYEE kept encrypted in the JSON files
+    #[serde(rename = "encryptedFields", default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_fields: Option<Vec<String>>,
+    /// file with 32 bytes of the AES-256 key of encrypted fields, kept outside of the data folder
+    #[serde(rename = "fieldKeyFile", default, skip_serializing_if = "Option::is_none")]
+    pub field_key_file: Option<String>
+}
+
+impl Settings {
+    /// Settings file is optional, databases without it get default settings.
+    pub fn load(data_folder_path: String, source: Box<dyn DataSource<Settings>>) -> Result<Settings, Error> {
+        match source.load(data_folder_path.add("/settings"), true) {
+            Ok(settings) => Ok(settings),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Settings::default()),
+            Err(e) => Err(e)
+        }
+    }
+
+    pub fn day_boundary(&self) -> DayBoundary {
+        DayBoundary::new(self.utc_offset_minutes.unwrap_or(0), self.day_start_hour.unwrap_or(0))
+    }
+
+    pub fn validate(&self, currencies: &Currencies) -> Result<(), Error> {
+        if self.utc_offset_minutes.is_some_and(|m|!(-14 * 60..=14 * 60).contains(&m)) {
+            return Err(Error::new(ErrorKind::InvalidData, "utc offset should be from -840 to 840 minutes"));
+        }
+        if self.day_start_hour.is_some_and(|h|h > 23) {
+            return Err(Error::new(ErrorKind::InvalidData, "day start hour should be from 0 to 23"));
+        }
+        if self.encrypted_fields.is_some() && self.field_key_file.is_none() {
+            return Err(Error::new(ErrorKind::InvalidData, "encrypted fields require a field key file"));
+        }
+        match &self.base_currency {
+            Some(code) => currencies.get(code)
+                .map(|_|())
+                .map_err(|_|Error::new(ErrorKind::InvalidData, format!("unknown base currency {}", code))),
+            None => Ok(())
+        }
+    }
+
+    pub fn save(&self, dest: Box<dyn DataSource<Settings>>, data_folder_path: String) -> Result<(), Error> {
+        dest.save(self, data_folder_path.add("/settings"))
+    }
+}
@@ -1,11 +1,32 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::io::{Error, ErrorKind};
 use std::ops::Add;
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde::de::Unexpected;
 use crate::core::data_source::DataSource;
 
-#[derive(Clone)]
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[serde(transparent)]
+pub struct CategoryId(pub u64);
+
+impl fmt::Display for CategoryId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[serde(transparent)]
+pub struct SubcategoryId(pub u64);
+
+impl fmt::Display for SubcategoryId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub enum SubcategoryCode {
     Comb,
     Comc,
@@ -15,9 +36,64 @@ pub enum SubcategoryCode {
     Expc,
     Exch,
     Trfr,
+    Lnds,
+    Lnrp,
+    Lnin,
+    Ibuy,
+    Isel,
+    Idiv,
+    Opbl,
+    Custom(String),
     None
 }
 
+impl SubcategoryCode {
+    /// Code as stored in the subcategories file, empty for None.
+    pub fn as_str(&self) -> &str {
+        match self {
+            SubcategoryCode::Comb => "COMB",
+            SubcategoryCode::Comc => "COMC",
+            SubcategoryCode::Fuel => "FUEL",
+            SubcategoryCode::Prcn => "PRCN",
+            SubcategoryCode::Incc => "INCC",
+            SubcategoryCode::Expc => "EXPC",
+            SubcategoryCode::Exch => "EXCH",
+            SubcategoryCode::Trfr => "TRFR",
+            SubcategoryCode::Lnds => "LNDS",
+            SubcategoryCode::Lnrp => "LNRP",
+            SubcategoryCode::Lnin => "LNIN",
+            SubcategoryCode::Ibuy => "IBUY",
+            SubcategoryCode::Isel => "ISEL",
+            SubcategoryCode::Idiv => "IDIV",
+            SubcategoryCode::Opbl => "OPBL",
+            SubcategoryCode::Custom(code) => code,
+            SubcategoryCode::None => ""
+        }
+    }
+
+    /// Parses stored code, unknown codes are custom.
+    pub fn parse(code: String) -> SubcategoryCode {
+        match code.as_str() {
+            "COMB" => SubcategoryCode::Comb,
+            "COMC" => SubcategoryCode::Comc,
+            "FUEL" => SubcategoryCode::Fuel,
+            "PRCN" => SubcategoryCode::Prcn,
+            "INCC" => SubcategoryCode::Incc,
+            "EXPC" => SubcategoryCode::Expc,
+            "EXCH" => SubcategoryCode::Exch,
+            "TRFR" => SubcategoryCode::Trfr,
+            "LNDS" => SubcategoryCode::Lnds,
+            "LNRP" => SubcategoryCode::Lnrp,
+            "LNIN" => SubcategoryCode::Lnin,
+            "IBUY" => SubcategoryCode::Ibuy,
+            "ISEL" => SubcategoryCode::Isel,
+            "IDIV" => SubcategoryCode::Idiv,
+            "OPBL" => SubcategoryCode::Opbl,
+            _ => SubcategoryCode::Custom(code)
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum SubcategoryOperationCode {
     Incm,
@@ -25,16 +101,47 @@ pub enum SubcategoryOperationCode {
     Spcl
 }
 
-#[derive(Deserialize, Clone)]
+impl SubcategoryOperationCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubcategoryOperationCode::Incm => "INCM",
+            SubcategoryOperationCode::Expn => "EXPN",
+            SubcategoryOperationCode::Spcl => "SPCL"
+        }
+    }
+
+    pub fn parse(code: &str) -> Option<SubcategoryOperationCode> {
+        match code {
+            "INCM" => Some(SubcategoryOperationCode::Incm),
+            "EXPN" => Some(SubcategoryOperationCode::Expn),
+            "SPCL" => Some(SubcategoryOperationCode::Spcl),
+            _ => None
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Subcategory {
-    pub id: u64,
+    pub id: SubcategoryId,
     pub name: String,
-    #[serde(deserialize_with = "code_deserialize")]
+    #[serde(deserialize_with = "code_deserialize", serialize_with = "code_serialize")]
     pub code: SubcategoryCode,
-    #[serde(rename = "operationCodeId", deserialize_with = "operation_code_deserialize")]
+    #[serde(rename = "operationCodeId", deserialize_with = "operation_code_deserialize",
+            serialize_with = "operation_code_serialize")]
     pub operation_code: SubcategoryOperationCode,
     #[serde(rename = "categoryId")]
-    pub category: u64
+    pub category: CategoryId,
+    #[serde(rename = "taxRelevant", default, skip_serializing_if = "std::ops::Not::not")]
+    pub tax_relevant: bool,
+    /// retired subcategory is kept for history but new operations can't use it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub retired: bool,
+    /// month expenditure above it is listed in the over limit report, in hundredths
+    #[serde(rename = "softLimit", default, skip_serializing_if = "Option::is_none")]
+    pub soft_limit: Option<i64>,
+    /// operations making month expenditure exceed it are added with a warning, in hundredths
+    #[serde(rename = "hardLimit", default, skip_serializing_if = "Option::is_none")]
+    pub hard_limit: Option<i64>
 }
 
 fn code_deserialize<'de, D>(deserializer: D) -> Result<SubcategoryCode, D::Error>
@@ -42,81 +149,127 @@ fn code_deserialize<'de, D>(deserializer: D) -> Result<SubcategoryCode, D::Error
         D: Deserializer<'de>,
 {
     let v: Option<String> = Deserialize::deserialize(deserializer)?;
-    if v.is_none() {
-        return Ok(SubcategoryCode::None);
-    }
-    let s = v.unwrap();
-    match s.as_str() {
-        "COMB" => Ok(SubcategoryCode::Comb),
-        "COMC" => Ok(SubcategoryCode::Comc),
-        "FUEL" => Ok(SubcategoryCode::Fuel),
-        "PRCN" => Ok(SubcategoryCode::Prcn),
-        "INCC" => Ok(SubcategoryCode::Incc),
-        "EXPC" => Ok(SubcategoryCode::Expc),
-        "EXCH" => Ok(SubcategoryCode::Exch),
-        "TRFR" => Ok(SubcategoryCode::Trfr),
-        _ => Err(serde::de::Error::invalid_value(Unexpected::Str(s.as_str()), &"subcategory code"))
+    Ok(v.map(SubcategoryCode::parse).unwrap_or(SubcategoryCode::None))
+}
+
+fn code_serialize<S>(code: &SubcategoryCode, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+{
+    match code {
+        SubcategoryCode::None => serializer.serialize_none(),
+        _ => serializer.serialize_str(code.as_str())
     }
 }
 
+fn operation_code_serialize<S>(code: &SubcategoryOperationCode, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+{
+    serializer.serialize_str(code.as_str())
+}
+
 fn operation_code_deserialize<'de, D>(deserializer: D) -> Result<SubcategoryOperationCode, D::Error>
     where
         D: Deserializer<'de>,
 {
     let v: String = Deserialize::deserialize(deserializer)?;
-    match v.as_str() {
-        "INCM" => Ok(SubcategoryOperationCode::Incm),
-        "EXPN" => Ok(SubcategoryOperationCode::Expn),
-        "SPCL" => Ok(SubcategoryOperationCode::Spcl),
-        _ => Err(serde::de::Error::invalid_value(Unexpected::Str(v.as_str()), &"subcategory operation code"))
-    }
+    SubcategoryOperationCode::parse(&v)
+        .ok_or(serde::de::Error::invalid_value(Unexpected::Str(v.as_str()), &"subcategory operation code"))
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Category {
-    pub id: u64,
-    pub name: String
+    pub id: CategoryId,
+    pub name: String,
+    /// expenses of all subcategories are tax deductible
+    #[serde(rename = "taxRelevant", default, skip_serializing_if = "std::ops::Not::not")]
+    pub tax_relevant: bool
 }
 
 
 pub struct Subcategories {
-    map: HashMap<u64, Subcategory>
+    map: HashMap<SubcategoryId, Subcategory>
 }
 
 impl Subcategories {
     pub fn load(data_folder_path: String, source: Box<dyn DataSource<Vec<Subcategory>>>)
         -> Result<Subcategories, Error> {
-        let subcategories = source.load(data_folder_path.add("/subcategories"), true)?;
+        Ok(Subcategories::new(source.load(data_folder_path.add("/subcategories"), true)?))
+    }
+
+    pub fn new(subcategories: Vec<Subcategory>) -> Subcategories {
         let map = subcategories.into_iter().map(|c|(c.id, c)).collect();
-        Ok(Subcategories{map})
+        Subcategories{map}
     }
 
-    pub fn get(&self, id: u64) -> Result<&Subcategory, Error> {
+    pub fn get(&self, id: SubcategoryId) -> Result<&Subcategory, Error> {
         self.map.get(&id).ok_or(Error::new(ErrorKind::InvalidData, "invalid subcategory id"))
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &Subcategory> {
+        self.map.values()
+    }
+
+    pub fn remove(&mut self, id: SubcategoryId) -> Result<Subcategory, Error> {
+        self.map.remove(&id).ok_or(Error::new(ErrorKind::InvalidData, "invalid subcategory id"))
+    }
+
+    pub fn set_retired(&mut self, id: SubcategoryId, retired: bool) -> Result<(), Error> {
+        self.map.get_mut(&id).ok_or(Error::new(ErrorKind::InvalidData, "invalid subcategory id"))?.retired = retired;
+        Ok(())
+    }
+
+    /// Monthly spending limits can be set on expense subcategories only.
+    pub fn set_limits(&mut self, id: SubcategoryId, soft_limit: Option<i64>, hard_limit: Option<i64>) -> Result<(), Error> {
+        let subcategory = self.map.get_mut(&id).ok_or(Error::new(ErrorKind::InvalidData, "invalid subcategory id"))?;
+        if !matches!(subcategory.operation_code, SubcategoryOperationCode::Expn) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("subcategory {} is not an expense", subcategory.name)));
+        }
+        subcategory.soft_limit = soft_limit;
+        subcategory.hard_limit = hard_limit;
+        Ok(())
+    }
+
+    /// Subcategory is tax relevant when it or its category is marked so.
+    pub fn is_tax_relevant(&self, id: SubcategoryId, categories: &Categories) -> Result<bool, Error> {
+        let subcategory = self.get(id)?;
+        Ok(subcategory.tax_relevant || categories.get(subcategory.category)?.tax_relevant)
+    }
+
     pub fn save(&self, dest: Box<dyn DataSource<Vec<Subcategory>>>, data_folder_path: String) -> Result<(), Error>{
-        dest.save(&self.map.values().map(|a|a.clone()).collect(), data_folder_path.add("/subcategories"))
+        let mut values: Vec<Subcategory> = self.map.values().cloned().collect();
+        values.sort_by_key(|v|v.id);
+        dest.save(&values, data_folder_path.add("/subcategories"))
     }
 }
 
 pub struct Categories {
-    map: HashMap<u64, Category>
+    map: HashMap<CategoryId, Category>
 }
 
 impl Categories {
     pub fn load(data_folder_path: String, source: Box<dyn DataSource<Vec<Category>>>)
                -> Result<Categories, Error> {
-        let categories = source.load(data_folder_path.add("/categories"), true)?;
+        Ok(Categories::new(source.load(data_folder_path.add("/categories"), true)?))
+    }
+
+    pub fn new(categories: Vec<Category>) -> Categories {
         let map = categories.into_iter().map(|c|(c.id, c)).collect();
-        Ok(Categories {map})
+        Categories {map}
     }
 
-    pub fn get(&self, id: u64) -> Result<&Category, Error> {
+    pub fn get(&self, id: CategoryId) -> Result<&Category, Error> {
         self.map.get(&id).ok_or(Error::new(ErrorKind::InvalidData, "invalid category id"))
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &Category> {
+        self.map.values()
+    }
+
     pub fn save(&self, dest: Box<dyn DataSource<Vec<Category>>>, data_folder_path: String) -> Result<(), Error>{
-        dest.save(&self.map.values().map(|a|a.clone()).collect(), data_folder_path.add("/categories"))
+        let mut values: Vec<Category> = self.map.values().cloned().collect();
+        values.sort_by_key(|v|v.id);
+        dest.save(&values, data_folder_path.add("/categories"))
     }
 }
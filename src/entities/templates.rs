@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::ops::Add;
+use serde::{Deserialize, Serialize};
+use crate::core::data_source::DataSource;
+use crate::entities::accounts::{AccountId, Accounts};
+use crate::entities::finance_operations::{FinOpParameter, FinanceOperation};
+use crate::entities::payees::PayeeId;
+use crate::entities::subcategories::{Subcategories, SubcategoryId};
+
+/// Named operation prototype for frequent manual entries.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct OperationTemplate {
+    pub name: String,
+    #[serde(rename = "accountId")]
+    pub account: AccountId,
+    #[serde(rename = "subcategoryId")]
+    pub subcategory: SubcategoryId,
+    /// typical summa in hundredths, used when entry has no summa
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summa: Option<i64>,
+    /// NETW parameter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    #[serde(rename = "secondAccountId", default, skip_serializing_if = "Option::is_none")]
+    pub second_account: Option<AccountId>,
+    #[serde(rename = "payeeId", default, skip_serializing_if = "Option::is_none")]
+    pub payee: Option<PayeeId>
+}
+
+impl OperationTemplate {
+    pub fn to_operation(&self, date: u64, summa: Option<i64>) -> Result<FinanceOperation, Error> {
+        let summa = summa.or(self.summa)
+            .ok_or(Error::new(ErrorKind::InvalidInput, format!("template {} has no summa", self.name)))?;
+        let mut parameters = Vec::new();
+        if let Some(network) = &self.network {
+            parameters.push(FinOpParameter::Netw(network.as_str().into()));
+        }
+        if let Some(account) = self.second_account {
+            parameters.push(FinOpParameter::Seca(account));
+        }
+        let mut op = FinanceOperation::new(date, self.account, self.subcategory, None, summa, parameters);
+        op.set_payee(self.payee);
+        Ok(op)
+    }
+}
+
+pub struct Templates {
+    map: HashMap<String, OperationTemplate>
+}
+
+impl Templates {
+    /// Templates file is optional, databases without it get an empty dictionary.
+    pub fn load(data_folder_path: String, source: Box<dyn DataSource<Vec<OperationTemplate>>>)
+        -> Result<Templates, Error> {
+        match source.load(data_folder_path.add("/templates"), true) {
+            Ok(templates) => Ok(Templates::new(templates)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Templates::new(Vec::new())),
+            Err(e) => Err(e)
+        }
+    }
+
+    pub fn new(templates: Vec<OperationTemplate>) -> Templates {
+        let map = templates.into_iter().map(|t|(t.name.clone(), t)).collect();
+        Templates{map}
+    }
+
+    pub fn get(&self, name: &str) -> Result<&OperationTemplate, Error> {
+        self.map.get(name).ok_or(Error::new(ErrorKind::NotFound, format!("unknown template {}", name)))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &OperationTemplate> {
+        self.map.values()
+    }
+
+    pub fn validate(&self, accounts: &Accounts, subcategories: &Subcategories) -> Result<(), Error> {
+        for template in self.map.values() {
+            let invalid = |what| Error::new(ErrorKind::InvalidData, format!("template {} has invalid {}", template.name, what));
+            for account in [Some(template.account), template.second_account].into_iter().flatten() {
+                accounts.get(account).map_err(|_|invalid("account"))?;
+            }
+            subcategories.get(template.subcategory).map_err(|_|invalid("subcategory"))?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, dest: Box<dyn DataSource<Vec<OperationTemplate>>>, data_folder_path: String) -> Result<(), Error>{
+        let mut values: Vec<OperationTemplate> = self.map.values().cloned().collect();
+        values.sort_by(|a, b|a.name.cmp(&b.name));
+        dest.save(&values, data_folder_path.add("/templates"))
+    }
+}
@@ -0,0 +1,151 @@
+use std::io::{Error, ErrorKind};
+use std::ops::Add;
+use serde::{Deserialize, Serialize};
+use crate::core::amounts::format_summa;
+use crate::core::data_source::DataSource;
+use crate::core::errors::FieldError;
+use crate::entities::accounts::{AccountId, Accounts};
+use crate::entities::finance_operations::FinanceOperation;
+use crate::entities::subcategories::{Subcategories, SubcategoryId};
+
+/// Check of operations run before they are added, returns rejections, empty when the operation is accepted.
+pub trait OperationValidator: Send + Sync {
+    fn validate(&self, op: &FinanceOperation) -> Result<Vec<FieldError>, Error>;
+}
+
+/// User defined rule like {"name": "large expenses", "minSumma": 100000, "requiredParameter": "NETW"}.
+/// Rule applies to operations of its account and subcategory (any when not set) with summa at least minSumma.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ValidationRule {
+    pub name: String,
+    #[serde(rename = "accountId", default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<AccountId>,
+    #[serde(rename = "subcategoryId", default, skip_serializing_if = "Option::is_none")]
+    pub subcategory: Option<SubcategoryId>,
+    /// in hundredths
+    #[serde(rename = "minSumma", default, skip_serializing_if = "Option::is_none")]
+    pub min_summa: Option<i64>,
+    /// in hundredths, larger summa is rejected
+    #[serde(rename = "maxSumma", default, skip_serializing_if = "Option::is_none")]
+    pub max_summa: Option<i64>,
+    /// code of the parameter operations must have, like NETW for the description
+    #[serde(rename = "requiredParameter", default, skip_serializing_if = "Option::is_none")]
+    pub required_parameter: Option<String>,
+    /// program and arguments, gets the operation as JSON on standard input and rejects it by a nonzero
+    /// exit code, its output is the rejection message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub command: Vec<String>
+}
+
+impl ValidationRule {
+    fn applies(&self, op: &FinanceOperation) -> bool {
+        self.account.is_none_or(|a|a == op.get_account()) &&
+            self.subcategory.is_none_or(|s|s == op.get_subcategory()) &&
+            self.min_summa.is_none_or(|m|op.get_summa() >= m)
+    }
+
+    fn rejection(&self, field: &str, message: String) -> FieldError {
+        FieldError{field: field.to_string(), message: format!("{}: {}", self.name, message)}
+    }
+
+    #[cfg(feature = "json")]
+    fn run_command(&self, op: &FinanceOperation) -> Result<Option<FieldError>, Error> {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+        let mut child = Command::new(&self.command[0]).args(&self.command[1..])
+            .stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&serde_json::to_vec(op)?)?;
+        }
+        let output = child.wait_with_output()?;
+        if output.status.success() {
+            return Ok(None);
+        }
+        let message = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok(Some(self.rejection("operation", if message.is_empty() {"rejected".to_string()} else {message})))
+    }
+
+    #[cfg(not(feature = "json"))]
+    fn run_command(&self, _op: &FinanceOperation) -> Result<Option<FieldError>, Error> {
+        Err(Error::new(ErrorKind::Unsupported, format!("rule {} needs json support", self.name)))
+    }
+}
+
+impl OperationValidator for ValidationRule {
+    fn validate(&self, op: &FinanceOperation) -> Result<Vec<FieldError>, Error> {
+        if !self.applies(op) {
+            return Ok(Vec::new());
+        }
+        let mut result = Vec::new();
+        if let Some(max) = self.max_summa.filter(|m|op.get_summa() > *m) {
+            result.push(self.rejection("summa", format!("summa {} exceeds {}", format_summa(op.get_summa()), format_summa(max))));
+        }
+        if let Some(code) = &self.required_parameter {
+            if !op.get_parameters().iter().any(|p|p.get_code() == code) {
+                result.push(self.rejection("finOpProperies", format!("{} is required", code)));
+            }
+        }
+        if !self.command.is_empty() {
+            result.extend(self.run_command(op)?);
+        }
+        Ok(result)
+    }
+}
+
+pub struct ValidationRules {
+    rules: Vec<ValidationRule>
+}
+
+impl ValidationRules {
+    /// Rules file is optional, databases without it accept all valid operations.
+    pub fn load(data_folder_path: String, source: Box<dyn DataSource<Vec<ValidationRule>>>)
+        -> Result<ValidationRules, Error> {
+        match source.load(data_folder_path.add("/validation_rules"), true) {
+            Ok(rules) => Ok(ValidationRules{rules}),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(ValidationRules{rules: Vec::new()}),
+            Err(e) => Err(e)
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ValidationRule> {
+        self.rules.iter()
+    }
+
+    pub fn validate(&self, accounts: &Accounts, subcategories: &Subcategories) -> Result<(), Error> {
+        for rule in &self.rules {
+            let invalid = |what| Error::new(ErrorKind::InvalidData, format!("validation rule {} has invalid {}", rule.name, what));
+            if let Some(account) = rule.account {
+                accounts.get(account).map_err(|_|invalid("account"))?;
+            }
+            if let Some(subcategory) = rule.subcategory {
+                subcategories.get(subcategory).map_err(|_|invalid("subcategory"))?;
+            }
+            if rule.max_summa.is_none() && rule.required_parameter.is_none() && rule.command.is_empty() {
+                return Err(invalid("check"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::entities::accounts::AccountId;
+    use crate::entities::finance_operations::{FinOpParameter, FinanceOperation};
+    use crate::entities::subcategories::SubcategoryId;
+    use crate::entities::validation_rules::{OperationValidator, ValidationRule};
+
+    #[test]
+    fn test_validation_rule() -> Result<(), Error> {
+        let rule = ValidationRule{name: "large".to_string(), account: None, subcategory: Some(SubcategoryId(2)),
+            min_summa: Some(10000), max_summa: Some(50000), required_parameter: Some("NETW".to_string()), command: Vec::new()};
+        let op = |summa, parameters|FinanceOperation::new(20240105, AccountId(1), SubcategoryId(2), None, summa, parameters);
+        assert!(rule.validate(&op(9999, Vec::new()))?.is_empty());
+        assert!(rule.validate(&op(20000, vec![FinOpParameter::Netw("SILPO".into())]))?.is_empty());
+        let rejections = rule.validate(&op(60000, Vec::new()))?;
+        assert_eq!(rejections.iter().map(|r|r.field.as_str()).collect::<Vec<_>>(), vec!["summa", "finOpProperies"]);
+        assert_eq!(rejections[1].message, "large: NETW is required");
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+//! C API. All functions return 0 on success and -1 on failure, the error message
+//! can be retrieved with had_last_error. had_add_operation returns 1 when the operation was added
+//! but made its subcategory exceed the hard spending limit, had_add_operation_once returns 2
+//! when the operation was already added with the same idempotency key.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::io::{Error, ErrorKind};
+use std::ptr::null_mut;
+use crate::db::HomeAccountingDB;
+use crate::entities::accounts::AccountId;
+use crate::entities::finance_operations::{FinOpParameter, FinanceOperation};
+use crate::entities::subcategories::SubcategoryId;
+use crate::json_db_config::JsonDBConfiguration;
+
+#[repr(C)]
+pub struct FfiOperation {
+    pub date: u64,
+    pub account: u64,
+    pub subcategory: u64,
+    /// 0 when operation has no amount
+    pub amount: u64,
+    pub summa: i64,
+    /// second account for transfers and exchanges, 0 when absent
+    pub second_account: u64
+}
+
+#[repr(C)]
+pub struct FfiBalance {
+    pub account: u64,
+    pub start_balance: i64,
+    pub income: i64,
+    pub expenditure: i64,
+    pub end_balance: i64
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error(e: Error) -> c_int {
+    let message = CString::new(e.to_string()).unwrap_or_default();
+    LAST_ERROR.with(|l|*l.borrow_mut() = message);
+    -1
+}
+
+fn to_result_code(r: Result<(), Error>) -> c_int {
+    match r {
+        Ok(()) => 0,
+        Err(e) => set_last_error(e)
+    }
+}
+
+/// Returns last error message for the calling thread. The pointer is valid until the next failed call.
+#[no_mangle]
+pub extern "C" fn had_last_error() -> *const c_char {
+    LAST_ERROR.with(|l|l.borrow().as_ptr())
+}
+
+/// Opens JSON database, returns null on failure.
+///
+/// # Safety
+/// data_folder_path must be a valid null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn had_open_json(data_folder_path: *const c_char, max_active_items: usize) -> *mut HomeAccountingDB {
+    let path = match CStr::from_ptr(data_folder_path).to_str() {
+        Ok(p) => p.to_string(),
+        Err(_) => {
+            set_last_error(Error::new(ErrorKind::InvalidInput, "invalid data folder path"));
+            return null_mut();
+        }
+    };
+    match HomeAccountingDB::load(path, Box::new(JsonDBConfiguration::new()), max_active_items) {
+        Ok(db) => Box::into_raw(Box::new(db)),
+        Err(e) => {
+            set_last_error(e);
+            null_mut()
+        }
+    }
+}
+
+/// # Safety
+/// db must be a pointer returned by had_open_json, it must not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn had_close(db: *mut HomeAccountingDB) {
+    if !db.is_null() {
+        drop(Box::from_raw(db));
+    }
+}
+
+/// Writes up to capacity operations for given date to out, total number of operations is written to count.
+///
+/// # Safety
+/// db must be a valid database pointer, out must point to capacity elements, count must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn had_get_operations(db: *mut HomeAccountingDB, date: u64, out: *mut FfiOperation,
+                                            capacity: usize, count: *mut usize) -> c_int {
+    let db = &mut *db;
+    to_result_code(db.build_ops_and_changes(date).map(|(ops, _)| {
+        *count = ops.len();
+        for (i, op) in ops.iter().take(capacity).enumerate() {
+            let second_account = op.get_second_account().map(|a|a.0).unwrap_or(0);
+            *out.add(i) = FfiOperation{
+                date: op.date,
+                account: op.get_account().0,
+                subcategory: op.get_subcategory().0,
+                amount: op.get_amount().unwrap_or(0),
+                summa: op.get_summa(),
+                second_account
+            };
+        }
+    }))
+}
+
+/// Version of the month of date, it is increased by every change of the month.
+///
+/// # Safety
+/// db must be a valid database pointer.
+#[no_mangle]
+pub unsafe extern "C" fn had_get_month_version(db: *mut HomeAccountingDB, date: u64) -> u64 {
+    (*db).get_month_version(date)
+}
+
+/// Writes up to capacity account balances for given date to out, total number of accounts is written to count.
+///
+/// # Safety
+/// db must be a valid database pointer, out must point to capacity elements, count must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn had_get_balances(db: *mut HomeAccountingDB, date: u64, out: *mut FfiBalance,
+                                          capacity: usize, count: *mut usize) -> c_int {
+    let db = &mut *db;
+    to_result_code(db.get_active_balances(date).map(|changes| {
+        let mut balances: Vec<_> = changes.iter().collect();
+        balances.sort_by_key(|(account, _)|**account);
+        *count = balances.len();
+        for (i, (account, change)) in balances.into_iter().take(capacity).enumerate() {
+            *out.add(i) = FfiBalance{
+                account: account.0,
+                start_balance: change.get_start_balance(),
+                income: change.get_income(),
+                expenditure: change.get_expenditure(),
+                end_balance: change.get_end_balance()
+            };
+        }
+    }))
+}
+
+fn to_operation(op: &FfiOperation) -> FinanceOperation {
+    let parameters = if op.second_account != 0 {
+        vec![FinOpParameter::Seca(AccountId(op.second_account))]
+    } else {
+        Vec::new()
+    };
+    let amount = if op.amount != 0 {Some(op.amount)} else {None};
+    FinanceOperation::new(op.date, AccountId(op.account), SubcategoryId(op.subcategory), amount, op.summa, parameters)
+}
+
+/// Returns 1 when the operation is added but exceeds hard spending limit of its subcategory.
+///
+/// # Safety
+/// db must be a valid database pointer, op must be a valid operation pointer.
+#[no_mangle]
+pub unsafe extern "C" fn had_add_operation(db: *mut HomeAccountingDB, op: *const FfiOperation) -> c_int {
+    let db = &mut *db;
+    match db.add_operation_checked(to_operation(&*op)) {
+        Ok(over_limit) => over_limit.is_some() as c_int,
+        Err(e) => set_last_error(e)
+    }
+}
+
+/// Same as had_add_operation, but the operation is added once per idempotency key, so a retried call
+/// doesn't add it again. Returns 2 when an operation with the key was already added.
+///
+/// # Safety
+/// db must be a valid database pointer, key must be a valid C string, op must be a valid operation pointer.
+#[no_mangle]
+pub unsafe extern "C" fn had_add_operation_once(db: *mut HomeAccountingDB, key: *const c_char,
+                                                op: *const FfiOperation) -> c_int {
+    let db = &mut *db;
+    let key = match CStr::from_ptr(key).to_str() {
+        Ok(k) => k,
+        Err(_) => return set_last_error(Error::new(ErrorKind::InvalidInput, "idempotency key is not valid UTF-8"))
+    };
+    match db.once(key, |db|db.add_operation_checked(to_operation(&*op))) {
+        Ok(Some(over_limit)) => over_limit.is_some() as c_int,
+        Ok(None) => 2,
+        Err(e) => set_last_error(e)
+    }
+}
@@ -0,0 +1,190 @@
+//! Synthetic JSON dataset for benchmarks and demos.
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use serde_json::{json, Value};
+use crate::core::dates::days_in_month;
+
+#[derive(PartialEq, Debug)]
+pub enum StorageFormat {
+    Json,
+    Binary
+}
+
+pub struct GeneratorOptions {
+    pub format: StorageFormat,
+    pub start_year: u64,
+    pub years: u64,
+    pub accounts: u64,
+    pub categories: u64,
+    /// average number of operations per day
+    pub operations_per_day: u64,
+    pub seed: u64
+}
+
+impl GeneratorOptions {
+    pub fn new(years: u64) -> GeneratorOptions {
+        GeneratorOptions{format: StorageFormat::Json, start_year: 2020, years, accounts: 5, categories: 10,
+            operations_per_day: 5, seed: 1}
+    }
+
+    /// Parses comma separated list like "years=5,accounts=8,density=3,format=json",
+    /// also accepts start, categories and seed.
+    pub fn parse(s: &str) -> Result<GeneratorOptions, Error> {
+        let mut options = GeneratorOptions::new(1);
+        for item in s.split(',').filter(|i|!i.is_empty()) {
+            let (key, value) = item.split_once('=')
+                .ok_or(Error::new(ErrorKind::InvalidInput, format!("invalid generator option {}", item)))?;
+            if key == "format" {
+                options.format = match value {
+                    "json" => StorageFormat::Json,
+                    "binary" => StorageFormat::Binary,
+                    _ => return Err(Error::new(ErrorKind::InvalidInput, format!("unknown format {}", value)))
+                };
+                continue;
+            }
+            let v: u64 = value.parse()
+                .map_err(|_|Error::new(ErrorKind::InvalidInput, format!("invalid value of {}", key)))?;
+            match key {
+                "years" => options.years = v,
+                "start" => options.start_year = v,
+                "accounts" => options.accounts = v,
+                "categories" => options.categories = v,
+                "density" => options.operations_per_day = v,
+                "seed" => options.seed = v,
+                _ => return Err(Error::new(ErrorKind::InvalidInput, format!("unknown generator option {}", key)))
+            }
+        }
+        Ok(options)
+    }
+}
+
+const ACCOUNT_NAMES: [&str; 6] = ["Salary card", "Credit card", "Savings", "Travel card", "Deposit", "Wallet"];
+const CATEGORY_NAMES: [&str; 10] = ["Food", "Transport", "Utilities", "Health", "Clothes", "Entertainment",
+    "Education", "Travel", "Gifts", "Household"];
+const SUBCATEGORY_NAMES: [&str; 8] = ["Groceries", "Fuel", "Medicine", "Cafe", "Bakery", "Online shopping",
+    "Cinema", "Repairs"];
+
+fn pick_name(names: &[&str], index: u64) -> String {
+    let name = names[index as usize % names.len()];
+    match index as usize / names.len() {
+        0 => name.to_string(),
+        n => format!("{} {}", name, n + 1)
+    }
+}
+
+/// xorshift64*, good enough for fake data and reproducible for a given seed.
+pub(crate) struct Random {
+    state: u64
+}
+
+impl Random {
+    pub(crate) fn new(seed: u64) -> Random {
+        Random{state: seed.max(1)}
+    }
+
+    pub(crate) fn next(&mut self, max: u64) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D) % max.max(1)
+    }
+}
+
+fn write_json(file_name: String, value: &Value) -> Result<(), Error> {
+    fs::write(file_name, serde_json::to_vec(value)?)
+}
+
+/// Writes generated dataset in the storage format selected by options, returns number of generated operations.
+pub fn generate(data_folder_path: &str, options: &GeneratorOptions) -> Result<usize, Error> {
+    match options.format {
+        StorageFormat::Json => generate_json(data_folder_path, options),
+        StorageFormat::Binary => Err(Error::new(ErrorKind::Unsupported, "binary storage is not implemented yet"))
+    }
+}
+
+/// Writes dictionaries and operations to data_folder_path, returns number of generated operations.
+/// Account 1 is the cash account, subcategory 1 is salary, the last one is transfer between accounts.
+/// Refuses to write into a folder that already contains a database.
+pub fn generate_json(data_folder_path: &str, options: &GeneratorOptions) -> Result<usize, Error> {
+    if Path::new(data_folder_path).join("accounts.json").exists() {
+        return Err(Error::new(ErrorKind::AlreadyExists, format!("{} already contains a database", data_folder_path)));
+    }
+    let mut random = Random::new(options.seed);
+    let accounts: Vec<Value> = (1..=options.accounts.max(2))
+        .map(|id|json!({"id": id, "name": if id == 1 {"Cash".to_string()} else {pick_name(&ACCOUNT_NAMES, id - 2)},
+            "valutaCode": "UAH", "activeTo": null, "isCash": id == 1}))
+        .collect();
+    let categories: Vec<Value> = (1..=options.categories.max(1))
+        .map(|id|json!({"id": id, "name": pick_name(&CATEGORY_NAMES, id - 1)}))
+        .collect();
+    let mut subcategories = vec![json!({"id": 1, "name": "Salary", "code": null, "operationCodeId": "INCM",
+        "categoryId": 1})];
+    for category in 1..=options.categories.max(1) {
+        for _ in 0..3 {
+            let id = subcategories.len() + 1;
+            subcategories.push(json!({"id": id, "name": pick_name(&SUBCATEGORY_NAMES, id as u64 - 2), "code": null,
+                "operationCodeId": "EXPN", "categoryId": category}));
+        }
+    }
+    let transfer = subcategories.len() as u64 + 1;
+    subcategories.push(json!({"id": transfer, "name": "Transfer", "code": "TRFR", "operationCodeId": "SPCL",
+        "categoryId": 1}));
+    fs::create_dir_all(data_folder_path.to_string() + "/dates")?;
+    write_json(data_folder_path.to_string() + "/accounts.json", &Value::Array(accounts))?;
+    write_json(data_folder_path.to_string() + "/categories.json", &Value::Array(categories))?;
+    write_json(data_folder_path.to_string() + "/subcategories.json", &Value::Array(subcategories))?;
+
+    let mut count = 0;
+    for year in options.start_year..options.start_year + options.years {
+        for month in 1..=12 {
+            for day in 1..=days_in_month(year, month) {
+                let mut ops = Vec::new();
+                if day == 1 {
+                    ops.push(json!({"id": 0, "accountId": 2, "subcategoryId": 1, "amount": null,
+                        "summa": 50000 + random.next(10000), "finOpProperies": null}));
+                }
+                for _ in 0..random.next(options.operations_per_day * 2 + 1) {
+                    let account = random.next(options.accounts.max(2)) + 1;
+                    let summa = (random.next(100000) + 1) as f64 / 100.0;
+                    if random.next(20) == 0 {
+                        let second = account % options.accounts.max(2) + 1;
+                        ops.push(json!({"id": 0, "accountId": account, "subcategoryId": transfer, "amount": null,
+                            "summa": summa, "finOpProperies": [{"numericValue": second, "stringValue": null,
+                                "dateValue": null, "propertyCode": "SECA"}]}));
+                    } else {
+                        ops.push(json!({"id": 0, "accountId": account, "subcategoryId": random.next(transfer - 2) + 2,
+                            "amount": null, "summa": summa, "finOpProperies": null}));
+                    }
+                }
+                if ops.is_empty() {
+                    continue;
+                }
+                count += ops.len();
+                let folder = format!("{}/dates/{}", data_folder_path, year * 10000 + month * 100 + day);
+                fs::create_dir_all(&folder)?;
+                write_json(folder + "/operations.json", &Value::Array(ops))?;
+            }
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::generator::{GeneratorOptions, StorageFormat};
+
+    #[test]
+    fn test_parse_options() -> Result<(), Error> {
+        let options = GeneratorOptions::parse("years=3,accounts=7,density=2,format=binary")?;
+        assert_eq!(options.years, 3);
+        assert_eq!(options.accounts, 7);
+        assert_eq!(options.operations_per_day, 2);
+        assert_eq!(options.format, StorageFormat::Binary);
+        assert!(GeneratorOptions::parse("years=x").is_err());
+        assert!(GeneratorOptions::parse("colour=1").is_err());
+        Ok(())
+    }
+}
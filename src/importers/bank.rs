@@ -0,0 +1,45 @@
+//! Direct download of transactions from bank APIs.
+
+use std::io::Error;
+use crate::db::HomeAccountingDB;
+use crate::importers::pipeline::{ImportPipeline, ImportSummary};
+use crate::importers::ImportedTransaction;
+
+pub struct BankAccount {
+    pub id: String,
+    pub name: String,
+    pub currency: String
+}
+
+pub struct TransactionPage {
+    pub transactions: Vec<ImportedTransaction>,
+    /// position after the last returned transaction, passed to the next fetch
+    pub cursor: String,
+    pub has_more: bool
+}
+
+pub trait BankConnector {
+    fn authenticate(&mut self) -> Result<(), Error>;
+    fn list_accounts(&self) -> Result<Vec<BankAccount>, Error>;
+    /// Transactions after the cursor, the first fetch of an account passes None.
+    fn fetch_transactions(&self, account_id: &str, cursor: Option<&str>) -> Result<TransactionPage, Error>;
+}
+
+/// Fetches all new transactions of the bank account and imports them, returns summary and cursor to store.
+pub fn sync_account(connector: &dyn BankConnector, account_id: &str, cursor: Option<String>,
+                    pipeline: &mut ImportPipeline, db: &mut HomeAccountingDB)
+    -> Result<(ImportSummary, Option<String>), Error> {
+    let mut summary = ImportSummary::default();
+    let mut cursor = cursor;
+    loop {
+        let page = connector.fetch_transactions(account_id, cursor.as_deref())?;
+        let s = pipeline.import(db, &page.transactions)?;
+        summary.imported += s.imported;
+        summary.duplicates += s.duplicates;
+        summary.uncategorized += s.uncategorized;
+        cursor = Some(page.cursor);
+        if !page.has_more {
+            return Ok((summary, cursor));
+        }
+    }
+}
@@ -0,0 +1,301 @@
+//! Books of other personal finance programs, converted to the dictionaries and operations of a new database.
+//! Category trees are flattened to two levels: top level categories become categories, the rest become
+//! subcategories named by their path below the top level one.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+use crate::db::{DBConfiguration, HomeAccountingDB};
+use crate::entities::accounts::{Account, AccountId, Accounts};
+use crate::entities::currencies::{Currencies, Currency};
+use crate::entities::finance_operations::{FinOpParameter, FinanceOperation};
+use crate::entities::payees::{Payee, PayeeId, Payees};
+use crate::entities::subcategories::{Categories, Category, CategoryId, Subcategories, Subcategory, SubcategoryCode,
+                                     SubcategoryId, SubcategoryOperationCode};
+
+pub struct BookAccount {
+    pub key: String,
+    pub name: String,
+    pub currency: String,
+    pub cash: bool,
+    pub closed: bool
+}
+
+pub struct BookCategory {
+    pub key: String,
+    pub name: String,
+    pub parent: Option<String>,
+    pub income: bool
+}
+
+pub enum BookEntryKind {
+    /// category key, None for uncategorized entries
+    Category(Option<String>),
+    /// summa leaves the account, received is credited to the second account in its currency
+    Transfer{account: String, received: i64},
+    Opening
+}
+
+pub struct BookEntry {
+    pub date: u64,
+    pub account: String,
+    /// in hundredths, negative when money leaves the account
+    pub summa: i64,
+    pub kind: BookEntryKind,
+    pub payee: Option<String>,
+    pub description: String
+}
+
+#[derive(Default)]
+pub struct Book {
+    pub accounts: Vec<BookAccount>,
+    pub categories: Vec<BookCategory>,
+    /// key and name
+    pub payees: Vec<(String, String)>,
+    pub entries: Vec<BookEntry>,
+    /// descriptions of transactions that can't be represented and were left out
+    pub skipped: Vec<String>
+}
+
+pub struct ConvertedBook {
+    pub accounts: Vec<Account>,
+    pub categories: Vec<Category>,
+    pub subcategories: Vec<Subcategory>,
+    pub payees: Vec<Payee>,
+    pub currencies: Vec<Currency>,
+    /// sorted by date, opening balance goes first on its date
+    pub operations: Vec<FinanceOperation>,
+    /// accounts to retire after operations are added
+    pub closed: Vec<AccountId>
+}
+
+/// Creates categories and subcategories while operations are converted.
+struct CategoryBuilder<'a> {
+    book: HashMap<&'a str, &'a BookCategory>,
+    categories: Vec<Category>,
+    subcategories: Vec<Subcategory>,
+    category_ids: HashMap<String, CategoryId>,
+    subcategory_ids: HashMap<(String, Option<&'a str>, bool), SubcategoryId>
+}
+
+impl<'a> CategoryBuilder<'a> {
+    fn category(&mut self, name: &str) -> CategoryId {
+        if let Some(id) = self.category_ids.get(name) {
+            return *id;
+        }
+        let id = CategoryId(self.categories.len() as u64 + 1);
+        self.categories.push(Category{id, name: name.to_string(), tax_relevant: false});
+        self.category_ids.insert(name.to_string(), id);
+        id
+    }
+
+    fn subcategory(&mut self, category: &str, key: Option<&'a str>, name: &str, code: SubcategoryCode,
+                   operation_code: SubcategoryOperationCode) -> SubcategoryId {
+        let income = matches!(operation_code, SubcategoryOperationCode::Incm);
+        if let Some(id) = self.subcategory_ids.get(&(category.to_string(), key, income)) {
+            return *id;
+        }
+        let id = SubcategoryId(self.subcategories.len() as u64 + 1);
+        let category_id = self.category(category);
+        self.subcategories.push(Subcategory{id, name: name.to_string(), code, operation_code, category: category_id,
+            tax_relevant: false, retired: false, soft_limit: None, hard_limit: None});
+        self.subcategory_ids.insert((category.to_string(), key, income), id);
+        id
+    }
+
+    /// Top level category name and path below it, category itself when it is the top level one.
+    fn path(&self, key: &str) -> Result<(String, String), Error> {
+        let mut names = Vec::new();
+        let mut current = Some(key);
+        while let Some(k) = current {
+            let c = self.book.get(k)
+                .ok_or(Error::new(ErrorKind::InvalidData, format!("unknown category {}", k)))?;
+            names.push(c.name.as_str());
+            if names.len() > self.book.len() {
+                return Err(Error::new(ErrorKind::InvalidData, format!("category {} is its own parent", key)));
+            }
+            current = c.parent.as_deref();
+        }
+        let top = names.pop().unwrap().to_string();
+        names.reverse();
+        let name = if names.is_empty() {top.clone()} else {names.join(":")};
+        Ok((top, name))
+    }
+
+    /// Summa sign selects income or expense for uncategorized entries.
+    fn entry_subcategory(&mut self, key: Option<&'a str>, summa: i64) -> Result<(SubcategoryId, bool), Error> {
+        let (category, name, income) = match key {
+            Some(k) => {
+                let (top, name) = self.path(k)?;
+                (top, name, self.book[k].income)
+            }
+            None if summa > 0 => ("Uncategorized".to_string(), "Uncategorized income".to_string(), true),
+            None => ("Uncategorized".to_string(), "Uncategorized expenses".to_string(), false)
+        };
+        let operation_code = if income {SubcategoryOperationCode::Incm} else {SubcategoryOperationCode::Expn};
+        Ok((self.subcategory(&category, key, &name, SubcategoryCode::None, operation_code), income))
+    }
+
+    fn special(&mut self, name: &'static str, code: SubcategoryCode) -> SubcategoryId {
+        self.subcategory("Transfers", Some(name), name, code, SubcategoryOperationCode::Spcl)
+    }
+}
+
+impl Book {
+    pub fn convert(&self) -> Result<ConvertedBook, Error> {
+        let mut accounts: Vec<Account> = Vec::new();
+        let mut account_ids = HashMap::new();
+        let mut closed = Vec::new();
+        for a in &self.accounts {
+            let id = AccountId(accounts.len() as u64 + 1);
+            accounts.push(Account::new(id, a.name.clone(), a.currency.clone(), a.cash));
+            account_ids.insert(a.key.as_str(), id);
+            if a.closed {
+                closed.push(id);
+            }
+        }
+        let currencies: BTreeSet<String> = self.accounts.iter().map(|a|a.currency.clone()).collect();
+        for currency in &currencies {
+            if !self.accounts.iter().any(|a|a.cash && &a.currency == currency) {
+                let id = AccountId(accounts.len() as u64 + 1);
+                accounts.push(Account::new(id, format!("Cash {}", currency), currency.clone(), true));
+            }
+        }
+        let account = |key: &str|account_ids.get(key).copied()
+            .ok_or(Error::new(ErrorKind::InvalidData, format!("unknown account {}", key)));
+        let payees: Vec<Payee> = self.payees.iter().enumerate()
+            .map(|(i, (_, name))|Payee{id: PayeeId(i as u64 + 1), name: name.clone()})
+            .collect();
+        let payee_ids: HashMap<&str, PayeeId> = self.payees.iter().zip(&payees).map(|((k, _), p)|(k.as_str(), p.id)).collect();
+        let mut builder = CategoryBuilder{book: self.categories.iter().map(|c|(c.key.as_str(), c)).collect(),
+            categories: Vec::new(), subcategories: Vec::new(), category_ids: HashMap::new(),
+            subcategory_ids: HashMap::new()};
+        // opening balance has to precede other operations of the account
+        let mut first_dates: HashMap<AccountId, u64> = HashMap::new();
+        let mut openings: HashMap<AccountId, (u64, i64)> = HashMap::new();
+        for e in &self.entries {
+            let id = account(&e.account)?;
+            if let BookEntryKind::Opening = e.kind {
+                let opening = openings.entry(id).or_insert((e.date, 0));
+                *opening = (opening.0.min(e.date), opening.1 + e.summa);
+                continue;
+            }
+            let second = if let BookEntryKind::Transfer{account: a, ..} = &e.kind {Some(account(a)?)} else {None};
+            for id in [Some(id), second].into_iter().flatten() {
+                let first = first_dates.entry(id).or_insert(e.date);
+                *first = (*first).min(e.date);
+            }
+        }
+        let mut operations = Vec::new();
+        for (id, (date, summa)) in openings {
+            let date = first_dates.get(&id).map(|d|date.min(*d)).unwrap_or(date);
+            let subcategory = builder.special("Opening balance", SubcategoryCode::Opbl);
+            operations.push((true, FinanceOperation::new(date, id, subcategory, None, summa, Vec::new())));
+        }
+        for e in &self.entries {
+            let id = account(&e.account)?;
+            let mut parameters = Vec::new();
+            let (subcategory, amount, summa) = match &e.kind {
+                BookEntryKind::Opening => continue,
+                BookEntryKind::Category(key) => {
+                    let (subcategory, income) = builder.entry_subcategory(key.as_deref(), e.summa)?;
+                    (subcategory, None, if income {e.summa} else {-e.summa})
+                }
+                BookEntryKind::Transfer{account: second, received} => {
+                    let second = account(second)?;
+                    parameters.push(FinOpParameter::Seca(second));
+                    if accounts[id.0 as usize - 1].currency == accounts[second.0 as usize - 1].currency {
+                        (builder.special("Transfer", SubcategoryCode::Trfr), None, -e.summa)
+                    } else {
+                        // exchange takes received summa and amount sent in thousandths
+                        (builder.special("Currency exchange", SubcategoryCode::Exch),
+                         u64::try_from(-e.summa).ok().map(|a|a * 10), *received)
+                    }
+                }
+            };
+            // transfers credit the second account only when it is their only parameter
+            if !e.description.is_empty() && parameters.is_empty() {
+                parameters.push(FinOpParameter::Netw(Arc::from(e.description.as_str())));
+            }
+            let mut op = FinanceOperation::new(e.date, id, subcategory, amount, summa, parameters);
+            op.set_payee(e.payee.as_deref().and_then(|p|payee_ids.get(p).copied()));
+            operations.push((false, op));
+        }
+        operations.sort_by_key(|(opening, op)|(op.date, !*opening, op.get_account()));
+        Ok(ConvertedBook{accounts,
+            categories: builder.categories,
+            subcategories: builder.subcategories,
+            payees,
+            currencies: currencies.into_iter().map(|code|Currency::new(code.clone(), code, 2)).collect(),
+            operations: operations.into_iter().map(|(_, op)|op).collect(),
+            closed})
+    }
+}
+
+impl ConvertedBook {
+    /// Writes dictionaries to an empty data folder and adds operations one by one, so they are validated
+    /// like operations entered by hand.
+    pub fn create_database(self, data_folder_path: String, configuration: Box<dyn DBConfiguration>,
+                           max_active_items: usize) -> Result<HomeAccountingDB, Error> {
+        if fs::read_dir(&data_folder_path).is_ok_and(|mut d|d.next().is_some()) {
+            return Err(Error::new(ErrorKind::AlreadyExists, format!("data folder {} is not empty", data_folder_path)));
+        }
+        fs::create_dir_all(&data_folder_path)?;
+        Accounts::new(self.accounts)?.save(configuration.get_accounts_source(), data_folder_path.clone())?;
+        Categories::new(self.categories).save(configuration.get_categories_source(), data_folder_path.clone())?;
+        Subcategories::new(self.subcategories).save(configuration.get_subcategories_source(), data_folder_path.clone())?;
+        Payees::new(self.payees).save(configuration.get_payees_source(), data_folder_path.clone())?;
+        Currencies::new(self.currencies).save(configuration.get_currencies_source(), data_folder_path.clone())?;
+        let mut db = HomeAccountingDB::new(data_folder_path, configuration, max_active_items)?;
+        for op in self.operations {
+            let date = op.date;
+            db.add_operation(op).map_err(|e|Error::new(e.kind(), format!("operation at {}: {}", date, e)))?;
+        }
+        for id in self.closed {
+            db.retire_account(id)?;
+        }
+        db.flush(false)?;
+        Ok(db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::entities::subcategories::SubcategoryCode;
+    use crate::importers::books::{Book, BookAccount, BookCategory, BookEntry, BookEntryKind};
+
+    #[test]
+    fn test_convert() -> Result<(), Error> {
+        let account = |key: &str, currency: &str|BookAccount{key: key.to_string(), name: key.to_string(),
+            currency: currency.to_string(), cash: false, closed: false};
+        let category = |key: &str, parent: Option<&str>|BookCategory{key: key.to_string(), name: key.to_string(),
+            parent: parent.map(|p|p.to_string()), income: false};
+        let entry = |date, account: &str, summa, kind|BookEntry{date, account: account.to_string(), summa, kind,
+            payee: None, description: String::new()};
+        let book = Book{
+            accounts: vec![account("Card", "EUR"), account("Savings", "USD")],
+            categories: vec![category("Food", None), category("Groceries", Some("Food")), category("Fruit", Some("Groceries"))],
+            payees: Vec::new(),
+            entries: vec![
+                entry(20240105, "Card", -350, BookEntryKind::Category(Some("Fruit".to_string()))),
+                entry(20240106, "Card", 120, BookEntryKind::Category(Some("Fruit".to_string()))),
+                entry(20240107, "Card", -1000, BookEntryKind::Transfer{account: "Savings".to_string(), received: 1090}),
+                entry(20240110, "Card", 5000, BookEntryKind::Opening)
+            ],
+            skipped: Vec::new()
+        };
+        let converted = book.convert()?;
+        // cash accounts are added for both currencies
+        assert_eq!(converted.accounts.len(), 4);
+        let names: Vec<(&str, u64)> = converted.subcategories.iter().map(|s|(s.name.as_str(), s.category.0)).collect();
+        assert_eq!(names, vec![("Opening balance", 1), ("Groceries:Fruit", 2), ("Currency exchange", 1)]);
+        let ops = &converted.operations;
+        assert_eq!(ops.iter().map(|o|(o.date, o.get_summa())).collect::<Vec<_>>(),
+                   vec![(20240105, 5000), (20240105, 350), (20240106, -120), (20240107, 1090)]);
+        assert_eq!(converted.subcategories[2].code, SubcategoryCode::Exch);
+        assert_eq!((ops[3].get_amount(), ops[3].get_second_account()), (Some(10000), Some(converted.accounts[1].id)));
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use std::io::{Error, ErrorKind};
+use crate::core::amounts::parse_summa;
+use crate::core::dates::{check_date, DayBoundary};
+use crate::importers::ImportedTransaction;
+
+/// Splits CSV line, separators inside double quotes are kept, "" is an escaped quote.
+pub fn split_line(line: &str, separator: char) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => result.push(std::mem::take(&mut field)),
+            c => field.push(c)
+        }
+    }
+    result.push(field);
+    result
+}
+
+/// Accepts yyyy-mm-dd, dd.mm.yyyy and yyyymmdd.
+pub fn parse_date(text: &str) -> Result<u64, Error> {
+    let invalid = || Error::new(ErrorKind::InvalidData, format!("invalid date {}", text));
+    let parts: Vec<&str> = text.trim().split(['-', '.', '/']).collect();
+    let numbers: Vec<u64> = parts.iter().map(|p|p.parse().map_err(|_|invalid())).collect::<Result<_, _>>()?;
+    let date = match (numbers.as_slice(), parts.first().map(|p|p.len())) {
+        ([d], _) => *d,
+        ([y, m, d], Some(4)) => y * 10000 + m * 100 + d,
+        ([d, m, y], _) => y * 10000 + m * 100 + d,
+        _ => return Err(invalid())
+    };
+    check_date(date).map_err(|_|invalid())?;
+    Ok(date)
+}
+
+/// Z, +hh, +hh:mm or +hhmm utc offset in minutes.
+fn parse_utc_offset(text: &str) -> Option<i64> {
+    let sign = match text.chars().next()? {
+        'Z' if text.len() == 1 => return Some(0),
+        '+' => 1,
+        '-' => -1,
+        _ => return None
+    };
+    let digits = text[1..].replace(':', "");
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i64>().ok()?, 0),
+        4 => (digits[..2].parse::<i64>().ok()?, digits[2..].parse::<i64>().ok()?),
+        _ => return None
+    };
+    Some(sign * (hours * 60 + minutes))
+}
+
+/// Date optionally followed by hh:mm[:ss] time and utc offset, separated by space or T.
+/// Timestamps are dated by the day boundary, times without offset are local.
+pub fn parse_timestamp(text: &str, boundary: &DayBoundary) -> Result<u64, Error> {
+    let text = text.trim();
+    let Some((date, time)) = text.split_once([' ', 'T']) else {
+        return parse_date(text);
+    };
+    let date = parse_date(date)?;
+    let invalid = || Error::new(ErrorKind::InvalidData, format!("invalid time {}", time));
+    let time = time.trim();
+    let (time, offset) = match time.find(['Z', '+', '-']) {
+        Some(p) => (&time[..p], Some(parse_utc_offset(&time[p..]).ok_or_else(invalid)?)),
+        None => (time, None)
+    };
+    let parts: Vec<i64> = time.split(':')
+        .map(|p|p.split('.').next().unwrap_or(p).parse().map_err(|_|invalid()))
+        .collect::<Result<_, _>>()?;
+    let seconds = match parts.as_slice() {
+        [h, m] if *h < 24 && *m < 60 => h * 3600 + m * 60,
+        [h, m, s] if *h < 24 && *m < 60 && *s < 61 => h * 3600 + m * 60 + s,
+        _ => return Err(invalid())
+    };
+    Ok(boundary.date_of_timestamp(date, seconds, offset))
+}
+
+fn find_column(header: &[String], names: &[&str]) -> Option<usize> {
+    header.iter().position(|h|names.contains(&h.trim().to_lowercase().as_str()))
+}
+
+/// CSV with header row containing date, amount and description columns, separated by comma or semicolon.
+/// Date column may contain time, such transactions are dated by the day boundary.
+pub fn parse_csv(contents: &str, boundary: &DayBoundary) -> Result<Vec<ImportedTransaction>, Error> {
+    let mut lines = contents.lines().filter(|l|!l.trim().is_empty());
+    let header_line = lines.next().ok_or(Error::new(ErrorKind::InvalidData, "empty CSV file"))?;
+    let separator = if header_line.contains(';') {';'} else {','};
+    let header = split_line(header_line, separator);
+    let missing = |name| Error::new(ErrorKind::InvalidData, format!("CSV file has no {} column", name));
+    let date_column = find_column(&header, &["date", "posted"]).ok_or_else(||missing("date"))?;
+    let amount_column = find_column(&header, &["amount", "summa", "sum"]).ok_or_else(||missing("amount"))?;
+    let description_column = find_column(&header, &["description", "name", "memo", "payee"])
+        .ok_or_else(||missing("description"))?;
+    let mut result = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let fields = split_line(line, separator);
+        let field = |column: usize| fields.get(column).map(|f|f.as_str())
+            .ok_or(Error::new(ErrorKind::InvalidData, format!("line {}: too few columns", i + 2)));
+        let with_line = |e: Error| Error::new(e.kind(), format!("line {}: {}", i + 2, e));
+        result.push(ImportedTransaction{
+            date: parse_timestamp(field(date_column)?, boundary).map_err(with_line)?,
+            summa: parse_summa(&field(amount_column)?.replace(' ', "")).map_err(with_line)?,
+            description: field(description_column)?.trim().to_string()
+        });
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::dates::DayBoundary;
+    use crate::importers::csv::{parse_csv, parse_timestamp};
+    use crate::importers::ImportedTransaction;
+
+    #[test]
+    fn test_parse_csv() {
+        let text = "Date;Description;Amount\n05.01.2024;\"Cafe; Central\";-3,50\n2024-01-06;Salary;1 000\n";
+        assert_eq!(parse_csv(text, &DayBoundary::default()).unwrap(), vec![
+            ImportedTransaction{date: 20240105, summa: -350, description: "Cafe; Central".to_string()},
+            ImportedTransaction{date: 20240106, summa: 100000, description: "Salary".to_string()}
+        ]);
+        assert!(parse_csv("date,amount\n", &DayBoundary::default()).is_err());
+        assert!(parse_csv("date,amount,name\n2024-02-30,1,x\n", &DayBoundary::default()).is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        let kyiv = DayBoundary::new(120, 0);
+        assert_eq!(parse_timestamp("2024-01-05 23:30", &kyiv).unwrap(), 20240105);
+        assert_eq!(parse_timestamp("2024-01-05T22:30:00Z", &kyiv).unwrap(), 20240106);
+        assert_eq!(parse_timestamp("05.01.2024 01:15:00", &DayBoundary::new(120, 4)).unwrap(), 20240104);
+        assert_eq!(parse_timestamp("2024-01-05T20:30:00.5-05:00", &kyiv).unwrap(), 20240106);
+        assert!(parse_timestamp("2024-01-05 25:00", &kyiv).is_err());
+        assert!(parse_timestamp("2024-01-05 10:00+5", &kyiv).is_err());
+    }
+}
@@ -0,0 +1,157 @@
+//! Applies dictionaries edited as CSV, files have the columns written by reports::dictionaries in any order.
+//! Rows replace entries with the same id and add new ones, entries absent from the file are kept.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{Error, ErrorKind};
+use crate::core::amounts::parse_summa;
+use crate::core::dates::check_date;
+use crate::entities::accounts::{Account, AccountGroup, AccountId};
+use crate::entities::subcategories::{Category, CategoryId, Subcategory, SubcategoryCode, SubcategoryId,
+                                     SubcategoryOperationCode};
+use crate::importers::csv::split_line;
+use crate::reports::dictionaries::{ACCOUNT_COLUMNS, CATEGORY_COLUMNS, SUBCATEGORY_COLUMNS};
+
+struct Row {
+    line: usize,
+    values: HashMap<String, String>
+}
+
+impl Row {
+    fn error(&self, message: String) -> Error {
+        Error::new(ErrorKind::InvalidData, format!("line {}: {}", self.line, message))
+    }
+
+    fn get(&self, column: &str) -> &str {
+        self.values.get(column).map(|v|v.trim()).unwrap_or("")
+    }
+
+    fn number<T: std::str::FromStr>(&self, column: &str) -> Result<T, Error> {
+        self.get(column).parse().map_err(|_|self.error(format!("invalid {} {}", column, self.get(column))))
+    }
+
+    fn text(&self, column: &str) -> Result<String, Error> {
+        Some(self.get(column).to_string()).filter(|v|!v.is_empty())
+            .ok_or_else(||self.error(format!("empty {}", column)))
+    }
+
+    fn flag(&self, column: &str) -> Result<bool, Error> {
+        match self.get(column).to_lowercase().as_str() {
+            "true" | "1" | "yes" => Ok(true),
+            "false" | "0" | "no" | "" => Ok(false),
+            v => Err(self.error(format!("invalid {} {}", column, v)))
+        }
+    }
+
+    fn amount(&self, column: &str) -> Result<Option<i64>, Error> {
+        match self.get(column) {
+            "" => Ok(None),
+            v => parse_summa(v).map(Some).map_err(|e|self.error(e.to_string()))
+        }
+    }
+}
+
+/// Rows of the file which header has to contain all columns, every id can be used once.
+fn parse_rows(contents: &str, columns: &[&str]) -> Result<Vec<(u64, Row)>, Error> {
+    let mut lines = contents.lines().enumerate().filter(|(_, l)|!l.trim().is_empty());
+    let (_, header_line) = lines.next().ok_or(Error::new(ErrorKind::InvalidData, "empty CSV file"))?;
+    let separator = if header_line.contains(';') {';'} else {','};
+    let header: Vec<String> = split_line(header_line, separator).iter().map(|h|h.trim().to_lowercase()).collect();
+    if let Some(missing) = columns.iter().find(|c|!header.iter().any(|h|h == *c)) {
+        return Err(Error::new(ErrorKind::InvalidData, format!("CSV file has no {} column", missing)));
+    }
+    let mut ids = HashSet::new();
+    let mut result = Vec::new();
+    for (i, line) in lines {
+        let row = Row{line: i + 1, values: header.iter().cloned().zip(split_line(line, separator)).collect()};
+        let id = row.number("id")?;
+        if !ids.insert(id) {
+            return Err(row.error(format!("id {} is repeated", id)));
+        }
+        result.push((id, row));
+    }
+    Ok(result)
+}
+
+/// Currency history of changed accounts is kept.
+pub fn merge_accounts(accounts: Vec<Account>, contents: &str) -> Result<Vec<Account>, Error> {
+    let mut map: BTreeMap<u64, Account> = accounts.into_iter().map(|a|(a.id.0, a)).collect();
+    for (id, row) in parse_rows(contents, &ACCOUNT_COLUMNS)? {
+        let mut account = Account::new(AccountId(id), row.text("name")?, row.text("currency")?, row.flag("cash")?);
+        account.active_to = match row.get("active_to") {
+            "" => None,
+            _ => Some(row.number("active_to")?)
+        };
+        if let Some(date) = account.active_to {
+            check_date(date).map_err(|e|row.error(e.to_string()))?;
+        }
+        account.set_group(match row.get("group") {
+            "" => None,
+            g => Some(AccountGroup::parse(&g.to_uppercase()).ok_or_else(||row.error(format!("invalid group {}", g)))?)
+        });
+        account.order = if row.get("order").is_empty() {0} else {row.number("order")?};
+        account.retired = row.flag("retired")?;
+        account.min_balance = row.amount("min_balance")?;
+        account.credit_limit = row.amount("credit_limit")?;
+        if let Some(existing) = map.get(&id) {
+            account.currency_history = existing.currency_history.clone();
+        }
+        map.insert(id, account);
+    }
+    Ok(map.into_values().collect())
+}
+
+pub fn merge_categories(categories: Vec<Category>, contents: &str) -> Result<Vec<Category>, Error> {
+    let mut map: BTreeMap<u64, Category> = categories.into_iter().map(|c|(c.id.0, c)).collect();
+    for (id, row) in parse_rows(contents, &CATEGORY_COLUMNS)? {
+        map.insert(id, Category{id: CategoryId(id), name: row.text("name")?, tax_relevant: row.flag("tax_relevant")?});
+    }
+    Ok(map.into_values().collect())
+}
+
+pub fn merge_subcategories(subcategories: Vec<Subcategory>, contents: &str) -> Result<Vec<Subcategory>, Error> {
+    let mut map: BTreeMap<u64, Subcategory> = subcategories.into_iter().map(|s|(s.id.0, s)).collect();
+    for (id, row) in parse_rows(contents, &SUBCATEGORY_COLUMNS)? {
+        let operation_code = SubcategoryOperationCode::parse(&row.get("operation_code").to_uppercase())
+            .ok_or_else(||row.error(format!("invalid operation code {}", row.get("operation_code"))))?;
+        let code = match row.get("code") {
+            "" => SubcategoryCode::None,
+            c => SubcategoryCode::parse(c.to_string())
+        };
+        map.insert(id, Subcategory{id: SubcategoryId(id), name: row.text("name")?, code, operation_code,
+            category: CategoryId(row.number("category_id")?), tax_relevant: row.flag("tax_relevant")?,
+            retired: row.flag("retired")?, soft_limit: row.amount("soft_limit")?, hard_limit: row.amount("hard_limit")?});
+    }
+    Ok(map.into_values().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::entities::accounts::{Account, AccountGroup, AccountId, CurrencyChange};
+    use crate::entities::subcategories::{CategoryId, SubcategoryCode};
+    use crate::importers::dictionaries::{merge_accounts, merge_subcategories};
+    use crate::reports::dictionaries::accounts_to_csv;
+
+    #[test]
+    fn test_merge_accounts() -> Result<(), Error> {
+        let mut card = Account::new(AccountId(2), "Card".to_string(), "USD".to_string(), false);
+        card.currency_history.push(CurrencyChange{date: Some(20240101), currency: "EUR".to_string(), rate: 1100000});
+        let accounts = vec![Account::new(AccountId(1), "Cash".to_string(), "USD".to_string(), true), card];
+        let csv = accounts_to_csv(accounts.iter());
+        assert!(csv.starts_with("id,name,currency,active_to,cash,group,order,retired,min_balance,credit_limit\n1,Cash,USD,,true,"));
+        let edited = csv.replace("2,Card,USD,,false,,0,false,,", "2,\"Card, main\",USD,20241231,false,savings,3,false,,100")
+            + "3,Deposit,USD,,false,,,,10.50,\n";
+        let merged = merge_accounts(accounts, &edited)?;
+        assert_eq!(merged.iter().map(|a|a.name.as_str()).collect::<Vec<_>>(), vec!["Cash", "Card, main", "Deposit"]);
+        assert_eq!((merged[1].active_to, merged[1].get_group(), merged[1].credit_limit), (Some(20241231), AccountGroup::Savings, Some(10000)));
+        assert_eq!(merged[1].currency_history.len(), 1);
+        assert_eq!(merged[2].min_balance, Some(1050));
+        assert!(merge_accounts(Vec::new(), "id,name\n1,Cash\n").is_err());
+        assert!(merge_accounts(Vec::new(), &(csv.clone() + "1,Cash,USD,,true,,0,false,,\n")).is_err());
+        let subcategories = merge_subcategories(Vec::new(),
+            "id;name;code;operation_code;category_id;tax_relevant;retired;soft_limit;hard_limit\n5;Fuel;FUEL;EXPN;2;;;;100\n")?;
+        assert!(matches!(subcategories[0].code, SubcategoryCode::Fuel));
+        assert_eq!((subcategories[0].category, subcategories[0].hard_limit), (CategoryId(2), Some(10000)));
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+//! HomeBank (.xhb) files. Dates are GLib julian days, amounts are decimal numbers in account currency.
+//! Transfers are stored as two linked operations, the one debiting its account is taken.
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use crate::core::dates::unix_days_to_date;
+use crate::importers::books::{Book, BookAccount, BookCategory, BookEntry, BookEntryKind};
+use crate::importers::xml::{parse_tags, XmlTag};
+
+/// Julian day of 1970-01-01.
+const UNIX_EPOCH_JULIAN_DAY: i64 = 719163;
+const ACCOUNT_TYPE_CASH: &str = "2";
+const ACCOUNT_FLAG_CLOSED: u32 = 2;
+const CATEGORY_FLAG_INCOME: u32 = 2;
+const PAYMODE_INTERNAL_TRANSFER: &str = "5";
+
+fn parse_amount(text: &str) -> Result<i64, Error> {
+    text.trim().parse::<f64>().ok().filter(|v|v.is_finite())
+        .map(|v|(v * 100.0).round() as i64)
+        .ok_or(Error::new(ErrorKind::InvalidData, format!("invalid amount {}", text)))
+}
+
+fn parse_julian_date(text: &str) -> Result<u64, Error> {
+    let days: i64 = text.parse().map_err(|_|Error::new(ErrorKind::InvalidData, format!("invalid date {}", text)))?;
+    Ok(unix_days_to_date(days - UNIX_EPOCH_JULIAN_DAY))
+}
+
+pub fn parse_homebank(contents: &str) -> Result<Book, Error> {
+    let tags = parse_tags(contents)?;
+    if !matches!(tags.first(), Some(XmlTag::Start(name, _, _)) if name == "homebank") {
+        return Err(Error::new(ErrorKind::InvalidData, "not a HomeBank file"));
+    }
+    let elements: Vec<(&str, &HashMap<String, String>)> = tags.iter()
+        .filter_map(|t|if let XmlTag::Start(name, attributes, _) = t {Some((name.as_str(), attributes))} else {None})
+        .collect();
+    let value = |attributes: &HashMap<String, String>, name: &str|attributes.get(name).cloned().unwrap_or_default();
+    let flags = |attributes: &HashMap<String, String>|attributes.get("flags").and_then(|f|f.parse::<u32>().ok()).unwrap_or(0);
+    let currencies: HashMap<String, String> = elements.iter()
+        .filter(|(name, _)|*name == "cur")
+        .map(|(_, a)|(value(a, "key"), value(a, "iso")))
+        .collect();
+    let mut book = Book::default();
+    let mut initial = Vec::new();
+    for (name, a) in &elements {
+        match *name {
+            "account" => {
+                let key = value(a, "key");
+                let currency = currencies.get(&value(a, "curr")).cloned()
+                    .ok_or(Error::new(ErrorKind::InvalidData, format!("account {}: unknown currency", key)))?;
+                let opening = parse_amount(a.get("initial").map(|i|i.as_str()).unwrap_or("0"))?;
+                if opening != 0 {
+                    initial.push((key.clone(), opening));
+                }
+                book.accounts.push(BookAccount{key, name: value(a, "name"), currency,
+                    cash: value(a, "type") == ACCOUNT_TYPE_CASH, closed: flags(a) & ACCOUNT_FLAG_CLOSED != 0});
+            }
+            "cat" => book.categories.push(BookCategory{key: value(a, "key"), name: value(a, "name"),
+                parent: a.get("parent").filter(|p|p.as_str() != "0").cloned(),
+                income: flags(a) & CATEGORY_FLAG_INCOME != 0}),
+            "pay" => book.payees.push((value(a, "key"), value(a, "name"))),
+            _ => {}
+        }
+    }
+    let operations: Vec<&HashMap<String, String>> = elements.iter().filter(|(name, _)|*name == "ope").map(|(_, a)|*a).collect();
+    // transfer key to the amount of every side
+    let mut transfers: HashMap<String, Vec<i64>> = HashMap::new();
+    for a in &operations {
+        if let Some(key) = a.get("kxfer") {
+            transfers.entry(key.clone()).or_default().push(parse_amount(&value(a, "amount"))?);
+        }
+    }
+    let category = |key: &str|Some(key.to_string()).filter(|k|!k.is_empty() && k != "0");
+    for (i, a) in operations.iter().enumerate() {
+        let at = |e: Error|Error::new(e.kind(), format!("operation {}: {}", i + 1, e));
+        let date = parse_julian_date(&value(a, "date")).map_err(at)?;
+        let summa = parse_amount(&value(a, "amount")).map_err(at)?;
+        let description = a.get("wording").or(a.get("memo")).cloned().unwrap_or_default();
+        let payee = a.get("payee").filter(|p|p.as_str() != "0").cloned();
+        let entry = |summa, kind|BookEntry{date, account: value(a, "account"), summa, kind, payee: payee.clone(),
+            description: description.clone()};
+        if value(a, "paymode") == PAYMODE_INTERNAL_TRANSFER && a.contains_key("dst_account") {
+            if summa < 0 {
+                let received = a.get("kxfer").and_then(|k|transfers.get(k))
+                    .and_then(|sides|sides.iter().find(|s|**s > 0)).copied()
+                    .unwrap_or(-summa);
+                book.entries.push(entry(summa, BookEntryKind::Transfer{account: value(a, "dst_account"), received}));
+            }
+            continue;
+        }
+        // split operations list categories, amounts and memos separated by ||
+        if let Some(categories) = a.get("scat") {
+            let amounts: Vec<&str> = a.get("samt").map(|s|s.split("||").collect()).unwrap_or_default();
+            if amounts.len() != categories.split("||").count() {
+                return Err(at(Error::new(ErrorKind::InvalidData, "split categories and amounts do not match")));
+            }
+            for (c, amount) in categories.split("||").zip(amounts) {
+                book.entries.push(entry(parse_amount(amount).map_err(at)?, BookEntryKind::Category(category(c))));
+            }
+            continue;
+        }
+        book.entries.push(entry(summa, BookEntryKind::Category(category(&value(a, "category")))));
+    }
+    let first_date = book.entries.iter().map(|e|e.date).min().unwrap_or(19700101);
+    for (account, summa) in initial {
+        book.entries.push(BookEntry{date: first_date, account, summa, kind: BookEntryKind::Opening, payee: None,
+            description: String::new()});
+    }
+    Ok(book)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::importers::books::BookEntryKind;
+    use crate::importers::homebank::parse_homebank;
+
+    #[test]
+    fn test_parse_homebank() -> Result<(), Error> {
+        let text = "<?xml version=\"1.0\"?>\n<homebank v=\"1.4\">\n\
+            <cur key=\"1\" iso=\"EUR\" name=\"Euro\"/>\n\
+            <account key=\"1\" flags=\"0\" type=\"1\" curr=\"1\" name=\"Bank\" initial=\"100.5\"/>\n\
+            <account key=\"2\" flags=\"2\" type=\"2\" curr=\"1\" name=\"Wallet\" initial=\"0\"/>\n\
+            <pay key=\"1\" name=\"Shop &amp; Co\"/>\n\
+            <cat key=\"1\" flags=\"0\" name=\"Food\"/>\n<cat key=\"2\" parent=\"1\" flags=\"1\" name=\"Fruit\"/>\n\
+            <ope date=\"738895\" amount=\"-12.499999\" account=\"1\" paymode=\"0\" payee=\"1\" category=\"2\" wording=\"Apples\"/>\n\
+            <ope date=\"738896\" amount=\"-20\" account=\"1\" dst_account=\"2\" paymode=\"5\" kxfer=\"1\"/>\n\
+            <ope date=\"738896\" amount=\"20\" account=\"2\" dst_account=\"1\" paymode=\"5\" kxfer=\"1\"/>\n\
+            <ope date=\"738897\" amount=\"-5\" account=\"2\" category=\"0\" scat=\"1||2\" samt=\"-3||-2\"/>\n\
+            </homebank>";
+        let book = parse_homebank(text)?;
+        assert_eq!(book.accounts.iter().map(|a|(a.cash, a.closed)).collect::<Vec<_>>(), vec![(false, false), (true, true)]);
+        assert_eq!(book.payees, vec![("1".to_string(), "Shop & Co".to_string())]);
+        assert_eq!(book.categories[1].parent.as_deref(), Some("1"));
+        let entries: Vec<(u64, &str, i64)> = book.entries.iter().map(|e|(e.date, e.account.as_str(), e.summa)).collect();
+        assert_eq!(entries, vec![(20240110, "1", -1250), (20240111, "1", -2000), (20240112, "2", -300),
+                                 (20240112, "2", -200), (20240110, "1", 10050)]);
+        assert!(matches!(&book.entries[1].kind, BookEntryKind::Transfer{account, received: 2000} if account == "2"));
+        assert!(matches!(book.entries[4].kind, BookEntryKind::Opening));
+        assert!(parse_homebank("<KMYMONEY-FILE/>").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+//! KMyMoney (.kmy) files, gzip compressed or plain XML. Amounts are fractions like "-350/100", shares of
+//! a split are in the currency of its account. Scheduled transactions and transactions involving investment
+//! accounts or more than two asset accounts are skipped.
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Read};
+use flate2::read::GzDecoder;
+use crate::importers::books::{Book, BookAccount, BookCategory, BookEntry, BookEntryKind};
+use crate::importers::csv::parse_date;
+use crate::importers::xml::{parse_tags, XmlTag};
+
+const ACCOUNT_TYPE_CASH: &str = "3";
+const ACCOUNT_TYPE_INCOME: &str = "12";
+const ACCOUNT_TYPE_EXPENSE: &str = "13";
+const ACCOUNT_TYPE_EQUITY: &str = "16";
+const INVESTMENT_ACCOUNT_TYPES: [&str; 2] = ["7", "15"];
+const STANDARD_ACCOUNT_PREFIX: &str = "AStd::";
+
+#[derive(Clone, Copy, PartialEq)]
+enum AccountKind {
+    Asset,
+    Category,
+    Equity,
+    Investment
+}
+
+struct Split {
+    account: String,
+    shares: i64,
+    payee: Option<String>,
+    memo: String
+}
+
+/// Fraction in hundredths, rounded half away from zero.
+fn parse_fraction(text: &str) -> Result<i64, Error> {
+    let invalid = || Error::new(ErrorKind::InvalidData, format!("invalid amount {}", text));
+    let (numerator, denominator) = text.trim().split_once('/').unwrap_or((text.trim(), "1"));
+    let numerator: i128 = numerator.parse().map_err(|_|invalid())?;
+    let denominator: i128 = denominator.parse().ok().filter(|d|*d > 0).ok_or_else(invalid)?;
+    let value = (numerator * 200 / denominator + numerator.signum()) / 2;
+    i64::try_from(value).map_err(|_|invalid())
+}
+
+/// Adds entries of one transaction, returns the reason when it can't be represented.
+fn add_transaction(book: &mut Book, kinds: &HashMap<String, AccountKind>, date: u64, memo: &str, splits: &[Split])
+    -> Result<(), String> {
+    let kind = |s: &&Split|kinds.get(&s.account).copied().unwrap_or(AccountKind::Investment);
+    let assets: Vec<&Split> = splits.iter().filter(|s|kind(s) == AccountKind::Asset).collect();
+    let categories: Vec<&Split> = splits.iter().filter(|s|kind(s) == AccountKind::Category).collect();
+    if splits.iter().any(|s|kind(&s) == AccountKind::Investment) {
+        return Err("investment account".to_string());
+    }
+    let payee = assets.iter().find_map(|s|s.payee.clone());
+    let description = |s: &Split|[s.memo.as_str(), memo].into_iter().find(|m|!m.is_empty()).unwrap_or("").to_string();
+    let mut entry = |account: &Split, summa, kind, description|book.entries.push(BookEntry{date,
+        account: account.account.clone(), summa, kind, payee: payee.clone(), description});
+    match assets[..] {
+        [asset] if splits.iter().any(|s|kind(&s) == AccountKind::Equity) =>
+            entry(asset, asset.shares, BookEntryKind::Opening, String::new()),
+        [asset] if categories.is_empty() => entry(asset, asset.shares, BookEntryKind::Category(None), description(asset)),
+        [asset] => for c in categories {
+            entry(asset, -c.shares, BookEntryKind::Category(Some(c.account.clone())), description(c));
+        },
+        [first, second] if categories.len() + 2 == splits.len() => {
+            // fees are charged to the account money leaves
+            let (from, to) = if first.shares <= second.shares {(first, second)} else {(second, first)};
+            let fees: i64 = categories.iter().map(|c|c.shares).sum();
+            for c in categories {
+                entry(from, -c.shares, BookEntryKind::Category(Some(c.account.clone())), description(c));
+            }
+            entry(from, from.shares + fees, BookEntryKind::Transfer{account: to.account.clone(), received: to.shares},
+                  description(from));
+        }
+        [] => return Err("no asset accounts".to_string()),
+        _ => return Err(format!("{} asset and equity accounts", splits.len() - categories.len()))
+    }
+    Ok(())
+}
+
+pub fn parse_kmymoney(contents: &[u8]) -> Result<Book, Error> {
+    let text = if contents.starts_with(&[0x1f, 0x8b]) {
+        let mut text = String::new();
+        GzDecoder::new(contents).read_to_string(&mut text)?;
+        text
+    } else {
+        String::from_utf8(contents.to_vec()).map_err(|e|Error::new(ErrorKind::InvalidData, e))?
+    };
+    let tags = parse_tags(&text)?;
+    if !matches!(tags.first(), Some(XmlTag::Start(name, _, _)) if name == "KMYMONEY-FILE") {
+        return Err(Error::new(ErrorKind::InvalidData, "not a KMyMoney file"));
+    }
+    let value = |attributes: &HashMap<String, String>, name: &str|attributes.get(name).cloned().unwrap_or_default();
+    let mut book = Book::default();
+    let mut kinds = HashMap::new();
+    let mut in_schedules = false;
+    let mut account: Option<usize> = None;
+    // date, memo, id and splits of the transaction being read
+    let mut transaction: Option<(u64, String, String, Vec<Split>)> = None;
+    for tag in &tags {
+        match tag {
+            XmlTag::Start(name, _, false) if name == "SCHEDULES" => in_schedules = true,
+            XmlTag::End(name) if name == "SCHEDULES" => in_schedules = false,
+            _ if in_schedules => {}
+            XmlTag::Start(name, a, empty) if name == "ACCOUNT" => {
+                let (id, account_type, parent) = (value(a, "id"), value(a, "type"), value(a, "parentaccount"));
+                let kind = match account_type.as_str() {
+                    ACCOUNT_TYPE_INCOME | ACCOUNT_TYPE_EXPENSE => AccountKind::Category,
+                    ACCOUNT_TYPE_EQUITY => AccountKind::Equity,
+                    t if INVESTMENT_ACCOUNT_TYPES.contains(&t) => AccountKind::Investment,
+                    _ => AccountKind::Asset
+                };
+                kinds.insert(id.clone(), kind);
+                account = None;
+                if id.starts_with(STANDARD_ACCOUNT_PREFIX) {
+                    continue;
+                }
+                match kind {
+                    AccountKind::Category => book.categories.push(BookCategory{key: id, name: value(a, "name"),
+                        parent: Some(parent).filter(|p|!p.starts_with(STANDARD_ACCOUNT_PREFIX)),
+                        income: account_type == ACCOUNT_TYPE_INCOME}),
+                    AccountKind::Asset => {
+                        book.accounts.push(BookAccount{key: id, name: value(a, "name"), currency: value(a, "currency"),
+                            cash: account_type == ACCOUNT_TYPE_CASH, closed: false});
+                        account = if *empty {None} else {Some(book.accounts.len() - 1)};
+                    }
+                    _ => {}
+                }
+            }
+            XmlTag::End(name) if name == "ACCOUNT" => account = None,
+            XmlTag::Start(name, a, _) if name == "PAIR" && value(a, "key") == "mm-closed" => {
+                if let Some(i) = account {
+                    book.accounts[i].closed = value(a, "value") == "yes";
+                }
+            }
+            XmlTag::Start(name, a, _) if name == "PAYEE" => book.payees.push((value(a, "id"), value(a, "name"))),
+            XmlTag::Start(name, a, _) if name == "TRANSACTION" =>
+                transaction = Some((parse_date(&value(a, "postdate"))?, value(a, "memo"), value(a, "id"), Vec::new())),
+            XmlTag::Start(name, a, _) if name == "SPLIT" => {
+                if let Some((_, _, _, splits)) = &mut transaction {
+                    splits.push(Split{account: value(a, "account"), shares: parse_fraction(&value(a, "shares"))?,
+                        payee: a.get("payee").filter(|p|!p.is_empty()).cloned(), memo: value(a, "memo")});
+                }
+            }
+            XmlTag::End(name) if name == "TRANSACTION" => {
+                if let Some((date, memo, id, splits)) = transaction.take() {
+                    if let Err(reason) = add_transaction(&mut book, &kinds, date, &memo, &splits) {
+                        book.skipped.push(format!("transaction {} at {}: {}", id, date, reason));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(book)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Error, Write};
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use crate::importers::books::BookEntryKind;
+    use crate::importers::kmymoney::{parse_fraction, parse_kmymoney};
+
+    #[test]
+    fn test_parse_kmymoney() -> Result<(), Error> {
+        assert_eq!((parse_fraction("-350/100")?, parse_fraction("1/3")?, parse_fraction("-5/1000")?), (-350, 33, -1));
+        let text = "<?xml version=\"1.0\"?>\n<!DOCTYPE KMYMONEY-FILE>\n<KMYMONEY-FILE>\n<ACCOUNTS>\n\
+            <ACCOUNT id=\"AStd::Expense\" type=\"13\" parentaccount=\"\" name=\"Expense\"/>\n\
+            <ACCOUNT id=\"A000001\" type=\"1\" parentaccount=\"AStd::Asset\" currency=\"EUR\" name=\"Checking\"/>\n\
+            <ACCOUNT id=\"A000002\" type=\"3\" parentaccount=\"AStd::Asset\" currency=\"EUR\" name=\"Wallet\">\n\
+            <KEYVALUEPAIRS><PAIR key=\"mm-closed\" value=\"yes\"/></KEYVALUEPAIRS></ACCOUNT>\n\
+            <ACCOUNT id=\"A000003\" type=\"13\" parentaccount=\"AStd::Expense\" name=\"Bank fees\"/>\n\
+            <ACCOUNT id=\"A000004\" type=\"16\" parentaccount=\"AStd::Equity\" name=\"Opening Balances\"/>\n\
+            </ACCOUNTS>\n<PAYEES><PAYEE id=\"P000001\" name=\"ATM\"/></PAYEES>\n<TRANSACTIONS>\n\
+            <TRANSACTION id=\"T1\" postdate=\"2024-01-01\" memo=\"\"><SPLITS>\n\
+            <SPLIT account=\"A000001\" shares=\"1000/1\" memo=\"\"/><SPLIT account=\"A000004\" shares=\"-1000/1\"/>\n\
+            </SPLITS></TRANSACTION>\n\
+            <TRANSACTION id=\"T2\" postdate=\"2024-01-05\" memo=\"Withdrawal\"><SPLITS>\n\
+            <SPLIT account=\"A000001\" payee=\"P000001\" shares=\"-10150/100\"/>\n\
+            <SPLIT account=\"A000002\" payee=\"P000001\" shares=\"100/1\"/>\n\
+            <SPLIT account=\"A000003\" shares=\"150/100\" memo=\"Fee\"/>\n\
+            </SPLITS></TRANSACTION>\n</TRANSACTIONS>\n<SCHEDULES><SCHEDULED_TX id=\"SCH1\">\n\
+            <TRANSACTION id=\"T3\" postdate=\"2024-02-01\"><SPLITS><SPLIT account=\"A000001\" shares=\"-1/1\"/></SPLITS>\n\
+            </TRANSACTION></SCHEDULED_TX></SCHEDULES>\n</KMYMONEY-FILE>\n";
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(text.as_bytes())?;
+        let book = parse_kmymoney(&encoder.finish()?)?;
+        assert_eq!(book.accounts.iter().map(|a|(a.name.as_str(), a.cash, a.closed)).collect::<Vec<_>>(),
+                   vec![("Checking", false, false), ("Wallet", true, true)]);
+        assert_eq!(book.categories.len(), 1);
+        assert!(book.categories[0].parent.is_none() && !book.categories[0].income);
+        let entries: Vec<(u64, &str, i64, &str)> = book.entries.iter()
+            .map(|e|(e.date, e.account.as_str(), e.summa, e.description.as_str()))
+            .collect();
+        assert_eq!(entries, vec![(20240101, "A000001", 100000, ""), (20240105, "A000001", -150, "Fee"),
+                                 (20240105, "A000001", -10000, "Withdrawal")]);
+        assert!(matches!(book.entries[0].kind, BookEntryKind::Opening));
+        assert!(matches!(&book.entries[2].kind, BookEntryKind::Transfer{account, received: 10000} if account == "A000002"));
+        assert_eq!(book.entries[2].payee.as_deref(), Some("P000001"));
+        assert!(book.skipped.is_empty());
+        assert!(parse_kmymoney(b"<homebank/>").is_err());
+        Ok(())
+    }
+}
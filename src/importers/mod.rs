@@ -0,0 +1,49 @@
+//! Importers of bank statements. Parsers produce ImportedTransaction list,
+//! ImportPipeline turns them into operations of one account. Books of other programs are converted
+//! to a new database by the books module.
+
+pub mod bank;
+pub mod books;
+pub mod csv;
+pub mod dictionaries;
+pub mod homebank;
+pub mod kmymoney;
+#[cfg(feature = "monobank")]
+pub mod monobank;
+pub mod ofx;
+pub mod pipeline;
+pub mod rates;
+pub mod xml;
+
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use crate::core::dates::DayBoundary;
+use crate::importers::books::Book;
+
+#[derive(PartialEq, Debug)]
+pub struct ImportedTransaction {
+    pub date: u64,
+    /// in hundredths, negative for expenditures
+    pub summa: i64,
+    pub description: String
+}
+
+/// Parses file contents by extension, csv and ofx are supported. Transaction times are dated by the day boundary.
+pub fn parse_file(file_name: &str, contents: &str, boundary: &DayBoundary) -> Result<Vec<ImportedTransaction>, Error> {
+    let extension = Path::new(file_name).extension().and_then(|e|e.to_str()).map(|e|e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("csv") => csv::parse_csv(contents, boundary),
+        Some("ofx") => ofx::parse_ofx(contents, boundary),
+        _ => Err(Error::new(ErrorKind::Unsupported, format!("unsupported statement file {}", file_name)))
+    }
+}
+
+/// Parses book of another program by extension, HomeBank xhb and KMyMoney kmy are supported.
+pub fn parse_book(file_name: &str, contents: &[u8]) -> Result<Book, Error> {
+    let extension = Path::new(file_name).extension().and_then(|e|e.to_str()).map(|e|e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("xhb") => homebank::parse_homebank(&String::from_utf8_lossy(contents)),
+        Some("kmy") => kmymoney::parse_kmymoney(contents),
+        _ => Err(Error::new(ErrorKind::Unsupported, format!("unsupported book file {}", file_name)))
+    }
+}
@@ -0,0 +1,135 @@
+//! Reference BankConnector for the Monobank personal API (https://api.monobank.ua/docs/).
+//! Cursor is unix time of the last fetched period end, statements are requested in 31 day periods.
+
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::io::{Error, ErrorKind};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde_json::Value;
+use crate::core::dates::DayBoundary;
+use crate::importers::bank::{BankAccount, BankConnector, TransactionPage};
+use crate::importers::ImportedTransaction;
+
+const API_URL: &str = "https://api.monobank.ua";
+/// API allows one statement request per minute
+const REQUEST_INTERVAL: Duration = Duration::from_secs(61);
+const MAX_PERIOD: u64 = 31 * 86400;
+const PAGE_SIZE: usize = 500;
+
+pub struct MonobankConnector {
+    token: String,
+    url: String,
+    boundary: DayBoundary,
+    last_request: Cell<Option<Instant>>
+}
+
+fn currency_name(code: u64) -> String {
+    match code {
+        980 => "UAH".to_string(),
+        840 => "USD".to_string(),
+        978 => "EUR".to_string(),
+        c => c.to_string()
+    }
+}
+
+/// Converts statement items, amounts are already in minor units, unix times are dated by the day boundary.
+pub fn parse_statement(items: &Value, boundary: &DayBoundary) -> Result<Vec<ImportedTransaction>, Error> {
+    let items = items.as_array().ok_or(Error::new(ErrorKind::InvalidData, "statement is not an array"))?;
+    let mut result = Vec::with_capacity(items.len());
+    for item in items {
+        let (Some(time), Some(amount)) = (item["time"].as_i64(), item["amount"].as_i64()) else {
+            return Err(Error::new(ErrorKind::InvalidData, format!("invalid statement item {}", item)));
+        };
+        result.push(ImportedTransaction{date: boundary.date_of(time), summa: amount,
+            description: item["description"].as_str().unwrap_or("").to_string()});
+    }
+    result.reverse();
+    Ok(result)
+}
+
+impl MonobankConnector {
+    pub fn new(token: String, boundary: DayBoundary) -> MonobankConnector {
+        MonobankConnector{token, url: API_URL.to_string(), boundary, last_request: Cell::new(None)}
+    }
+
+    fn get(&self, path: &str) -> Result<Value, Error> {
+        if let Some(last) = self.last_request.get() {
+            if let Some(wait) = REQUEST_INTERVAL.checked_sub(last.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+        self.last_request.set(Some(Instant::now()));
+        let response = ureq::get(format!("{}{}", self.url, path))
+            .header("X-Token", &self.token)
+            .call()
+            .map_err(|e|Error::other(format!("monobank {}: {}", path, e)))?
+            .body_mut()
+            .read_to_string()
+            .map_err(|e|Error::other(format!("monobank {}: {}", path, e)))?;
+        Ok(serde_json::from_str(&response)?)
+    }
+}
+
+impl BankConnector for MonobankConnector {
+    /// Token is personal, checks that it is accepted.
+    fn authenticate(&mut self) -> Result<(), Error> {
+        self.get("/personal/client-info").map(|_|())
+    }
+
+    fn list_accounts(&self) -> Result<Vec<BankAccount>, Error> {
+        let info = self.get("/personal/client-info")?;
+        let accounts = info["accounts"].as_array().map(|a|a.as_slice()).unwrap_or_default();
+        Ok(accounts.iter().map(|a|BankAccount{
+            id: a["id"].as_str().unwrap_or("").to_string(),
+            name: a["maskedPan"][0].as_str().or(a["type"].as_str()).unwrap_or("").to_string(),
+            currency: currency_name(a["currencyCode"].as_u64().unwrap_or(0))
+        }).collect())
+    }
+
+    fn fetch_transactions(&self, account_id: &str, cursor: Option<&str>) -> Result<TransactionPage, Error> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d|d.as_secs()).unwrap_or(0);
+        let from = match cursor {
+            Some(c) => c.parse::<u64>().map_err(|_|Error::new(ErrorKind::InvalidInput, "invalid cursor"))? + 1,
+            None => now - MAX_PERIOD
+        };
+        let to = (from + MAX_PERIOD).min(now);
+        let mut items = Vec::new();
+        let mut ids = HashSet::new();
+        let mut end = to;
+        loop {
+            let page = self.get(&format!("/personal/statement/{}/{}/{}", account_id, from, end))?;
+            let page = page.as_array().ok_or(Error::new(ErrorKind::InvalidData, "statement is not an array"))?;
+            for item in page {
+                if ids.insert(item["id"].as_str().unwrap_or("").to_string()) {
+                    items.push(item.clone());
+                }
+            }
+            // items are sorted from newest, a full page means older items of the period were cut off
+            match page.last().and_then(|i|i["time"].as_u64()) {
+                Some(oldest) if page.len() >= PAGE_SIZE && oldest > from && oldest < end => end = oldest,
+                _ => break
+            }
+        }
+        Ok(TransactionPage{transactions: parse_statement(&Value::Array(items), &self.boundary)?, cursor: to.to_string(),
+            has_more: to < now})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::core::dates::DayBoundary;
+    use crate::importers::monobank::parse_statement;
+
+    #[test]
+    fn test_parse_statement() {
+        let items = json!([{"id": "b", "time": 1704499200, "description": "Cafe", "amount": -350},
+                           {"id": "a", "time": 1704412800, "description": "Salary", "amount": 100000}]);
+        let transactions = parse_statement(&items, &DayBoundary::default()).unwrap();
+        assert_eq!((transactions[0].date, transactions[0].summa), (20240105, 100000));
+        assert_eq!(transactions[1].description, "Cafe");
+        assert_eq!(parse_statement(&items, &DayBoundary::new(-60, 0)).unwrap()[0].date, 20240104);
+        assert!(parse_statement(&json!([{"time": 1}]), &DayBoundary::default()).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use std::io::{Error, ErrorKind};
+use crate::core::amounts::parse_summa;
+use crate::core::dates::DayBoundary;
+use crate::importers::csv::parse_date;
+use crate::importers::ImportedTransaction;
+
+/// Value of the tag, works for both SGML (no closing tags) and XML flavours of OFX.
+fn tag_value<'a>(block: &'a str, tag: &str) -> Option<&'a str> {
+    let start = block.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let rest = &block[start..];
+    Some(rest[..rest.find('<').unwrap_or(rest.len())].trim())
+}
+
+/// yyyymmdd[hhmmss[.xxx]][[offset:TZ]] date, time without offset is GMT as the specification says.
+fn parse_datetime(text: &str, boundary: &DayBoundary) -> Result<u64, Error> {
+    let invalid = || Error::new(ErrorKind::InvalidData, format!("invalid date {}", text));
+    let date = parse_date(text.get(..8).unwrap_or(text))?;
+    let Some(time) = text.get(8..14).filter(|t|t.bytes().all(|b|b.is_ascii_digit())) else {
+        return Ok(date);
+    };
+    let seconds = time[..2].parse::<i64>().map_err(|_|invalid())? * 3600 + time[2..4].parse::<i64>().map_err(|_|invalid())? * 60
+        + time[4..].parse::<i64>().map_err(|_|invalid())?;
+    let offset = match (text.find('['), text.find(']')) {
+        (Some(start), Some(end)) if start < end => {
+            let hours = text[start + 1..end].split(':').next().unwrap_or("");
+            hours.parse::<f64>().map_err(|_|invalid())?
+        }
+        _ => 0.0
+    };
+    Ok(boundary.date_of_timestamp(date, seconds, Some((offset * 60.0).round() as i64)))
+}
+
+/// Posting times are dated by the day boundary.
+pub fn parse_ofx(contents: &str, boundary: &DayBoundary) -> Result<Vec<ImportedTransaction>, Error> {
+    if !contents.contains("<OFX>") {
+        return Err(Error::new(ErrorKind::InvalidData, "not an OFX file"));
+    }
+    let mut result = Vec::new();
+    for (i, block) in contents.split("<STMTTRN>").skip(1).enumerate() {
+        let block = &block[..block.find("</STMTTRN>").unwrap_or(block.len())];
+        let missing = |tag| Error::new(ErrorKind::InvalidData, format!("transaction {}: no {}", i + 1, tag));
+        let date = tag_value(block, "DTPOSTED").ok_or_else(||missing("DTPOSTED"))?;
+        let amount = tag_value(block, "TRNAMT").ok_or_else(||missing("TRNAMT"))?;
+        let description = tag_value(block, "NAME").or_else(||tag_value(block, "MEMO")).unwrap_or("");
+        result.push(ImportedTransaction{
+            date: parse_datetime(date, boundary)?,
+            summa: parse_summa(amount)?,
+            description: description.to_string()
+        });
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::dates::DayBoundary;
+    use crate::importers::ofx::parse_ofx;
+
+    #[test]
+    fn test_parse_ofx() {
+        let text = "<OFX><BANKTRANLIST><STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20240105120000<TRNAMT>-3.50<NAME>Cafe\n\
+                    </STMTTRN><STMTTRN><DTPOSTED>20240106</DTPOSTED><TRNAMT>10</TRNAMT><MEMO>Salary</MEMO></STMTTRN>\
+                    <STMTTRN><DTPOSTED>20240106203000.000[-5:EST]<TRNAMT>-1<NAME>Late</STMTTRN>";
+        let transactions = parse_ofx(text, &DayBoundary::new(120, 0)).unwrap();
+        assert_eq!(transactions.len(), 3);
+        assert_eq!((transactions[0].date, transactions[0].summa), (20240105, -350));
+        assert_eq!(transactions[0].description, "Cafe");
+        assert_eq!(transactions[1].description, "Salary");
+        assert_eq!(transactions[2].date, 20240107);
+        assert!(parse_ofx("bad", &DayBoundary::default()).is_err());
+    }
+}
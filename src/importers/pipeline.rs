@@ -0,0 +1,169 @@
+use std::io::{Error, ErrorKind};
+use crate::core::amounts::format_summa;
+use crate::db::HomeAccountingDB;
+use crate::entities::accounts::AccountId;
+use crate::entities::finance_operations::{FinOpParameter, FinanceOperation};
+use crate::entities::subcategories::{Subcategories, SubcategoryId};
+use crate::importers::ImportedTransaction;
+use crate::suggester::SubcategorySuggester;
+
+#[derive(Default, Debug, PartialEq)]
+pub struct ImportSummary {
+    pub imported: usize,
+    /// already present operations that were skipped
+    pub duplicates: usize,
+    /// operations that got default subcategory
+    pub uncategorized: usize
+}
+
+impl ImportSummary {
+    pub fn describe(&self) -> String {
+        format!("{} operations imported ({} uncategorized), {} duplicates skipped", self.imported,
+                self.uncategorized, self.duplicates)
+    }
+}
+
+/// Imported transaction converted to an operation that is not written yet.
+pub struct PreviewItem {
+    pub operation: FinanceOperation,
+    /// number of earlier operations with the same description the subcategory was suggested by,
+    /// 0 when the default subcategory was used
+    pub matches: u64,
+    /// equal to an existing operation or to an earlier item of the batch
+    pub duplicate: bool
+}
+
+impl PreviewItem {
+    pub fn describe(&self, subcategories: &Subcategories) -> Result<String, Error> {
+        let rule = if self.matches > 0 {format!("matched {} times", self.matches)} else {"default".to_string()};
+        Ok(format!("{} {} {} -> {} ({}){}", self.operation.date, self.operation.get_network().unwrap_or_default(),
+                   format_summa(self.operation.get_summa()),
+                   subcategories.get(self.operation.get_subcategory())?.name, rule,
+                   if self.duplicate {", duplicate"} else {""}))
+    }
+}
+
+pub struct ImportPreview {
+    pub items: Vec<PreviewItem>
+}
+
+impl ImportPreview {
+    /// Parses selection of items: "all" selects everything except duplicates, "none" or empty text
+    /// selects nothing, otherwise comma separated 1-based numbers and ranges like "1,3-5".
+    pub fn select(&self, text: &str) -> Result<Vec<usize>, Error> {
+        let text = text.trim();
+        match text {
+            "all" => return Ok((0..self.items.len()).filter(|i|!self.items[*i].duplicate).collect()),
+            "none" | "" => return Ok(Vec::new()),
+            _ => {}
+        }
+        let invalid = |part: &str|Error::new(ErrorKind::InvalidInput, format!("invalid selection {}", part));
+        let mut result = Vec::new();
+        for part in text.split(',').map(|p|p.trim()) {
+            let (first, last) = part.split_once('-').unwrap_or((part, part));
+            let (first, last): (usize, usize) = first.trim().parse().ok()
+                .zip(last.trim().parse().ok())
+                .filter(|(f, l)|*f >= 1 && f <= l && *l <= self.items.len())
+                .ok_or_else(||invalid(part))?;
+            result.extend(first - 1..last);
+        }
+        result.sort();
+        result.dedup();
+        Ok(result)
+    }
+}
+
+/// Adds imported transactions to one account. Subcategory is suggested by description (stored as NETW),
+/// transactions without suggestion get default income or expense subcategory.
+pub struct ImportPipeline {
+    account: AccountId,
+    income_subcategory: SubcategoryId,
+    expense_subcategory: SubcategoryId,
+    suggester: SubcategorySuggester
+}
+
+impl ImportPipeline {
+    pub fn new(account: AccountId, income_subcategory: SubcategoryId, expense_subcategory: SubcategoryId,
+               suggester: SubcategorySuggester) -> ImportPipeline {
+        ImportPipeline{account, income_subcategory, expense_subcategory, suggester}
+    }
+
+    pub fn to_operation(&self, transaction: &ImportedTransaction) -> (FinanceOperation, bool) {
+        let suggested = self.suggester.suggest_for_text(&transaction.description);
+        let default = if transaction.summa < 0 {self.expense_subcategory} else {self.income_subcategory};
+        let parameters = if transaction.description.is_empty() {
+            Vec::new()
+        } else {
+            vec![FinOpParameter::Netw(transaction.description.as_str().into())]
+        };
+        let op = FinanceOperation::new(transaction.date, self.account, suggested.unwrap_or(default), None,
+                                       transaction.summa.abs(), parameters);
+        (op, suggested.is_none())
+    }
+
+    /// Converts transactions to operations without writing anything, see accept.
+    pub fn preview(&self, db: &HomeAccountingDB, transactions: &[ImportedTransaction]) -> Result<ImportPreview, Error> {
+        let mut items: Vec<PreviewItem> = Vec::new();
+        for transaction in transactions {
+            let (operation, _) = self.to_operation(transaction);
+            let matches = self.suggester.rank_for_text(&transaction.description).first().map(|(_, n)|*n).unwrap_or(0);
+            let duplicate = items.iter().any(|i|i.operation.is_duplicate_of(&operation)) ||
+                db.get_operations(operation.date, operation.date)?.iter().any(|o|o.is_duplicate_of(&operation));
+            items.push(PreviewItem{operation, matches, duplicate});
+        }
+        Ok(ImportPreview{items})
+    }
+
+    /// Adds selected items of the preview, duplicates are added only when selected explicitly.
+    pub fn accept(&mut self, db: &HomeAccountingDB, preview: ImportPreview, selected: &[usize])
+        -> Result<ImportSummary, Error> {
+        let mut summary = ImportSummary::default();
+        for (i, item) in preview.items.into_iter().enumerate() {
+            if !selected.contains(&i) {
+                if item.duplicate {
+                    summary.duplicates += 1;
+                }
+                continue;
+            }
+            self.suggester.learn(&item.operation);
+            db.add_operation(item.operation)?;
+            summary.imported += 1;
+            if item.matches == 0 {
+                summary.uncategorized += 1;
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Adds transactions, ones equal to existing operations are skipped so repeated imports are harmless.
+    pub fn import(&mut self, db: &HomeAccountingDB, transactions: &[ImportedTransaction])
+        -> Result<ImportSummary, Error> {
+        let preview = self.preview(db, transactions)?;
+        let selected = preview.select("all")?;
+        self.accept(db, preview, &selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entities::accounts::AccountId;
+    use crate::entities::finance_operations::FinanceOperation;
+    use crate::entities::subcategories::SubcategoryId;
+    use crate::importers::pipeline::{ImportPreview, PreviewItem};
+
+    #[test]
+    fn test_select() {
+        let items = [false, true, false, false].into_iter()
+            .map(|duplicate|PreviewItem{
+                operation: FinanceOperation::new(20240101, AccountId(1), SubcategoryId(1), None, 100, Vec::new()),
+                matches: 0, duplicate})
+            .collect();
+        let preview = ImportPreview{items};
+        assert_eq!(preview.select("all").unwrap(), vec![0, 2, 3]);
+        assert_eq!(preview.select(" none ").unwrap(), Vec::<usize>::new());
+        assert_eq!(preview.select("4, 1-2,2").unwrap(), vec![0, 1, 3]);
+        assert!(preview.select("0").is_err());
+        assert!(preview.select("3-5").is_err());
+        assert!(preview.select("2-1").is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::BTreeSet;
+use std::io::{Error, ErrorKind};
+use crate::core::amounts::parse_rate;
+use crate::entities::currencies::Currencies;
+use crate::entities::rates::ExchangeRate;
+use crate::importers::csv::{parse_date, split_line};
+
+fn is_missing(value: &str) -> bool {
+    let value = value.trim();
+    value.is_empty() || value == "N/A" || value == "-"
+}
+
+/// Parses rates CSV. Two layouts are supported:
+/// ECB style with date column followed by one column per currency ("N/A" means no rate),
+/// and one rate per line with date, currency and rate columns (optional base column).
+/// base is used when file has no base column, ECB files have EUR base.
+pub fn parse_rates_csv(contents: &str, base: &str) -> Result<Vec<ExchangeRate>, Error> {
+    let mut lines = contents.lines().filter(|l|!l.trim().is_empty());
+    let header_line = lines.next().ok_or(Error::new(ErrorKind::InvalidData, "empty CSV file"))?;
+    let separator = if header_line.contains(';') {';'} else {','};
+    let header: Vec<String> = split_line(header_line, separator).into_iter()
+        .map(|h|h.trim().to_string())
+        .collect();
+    let column = |name: &str| header.iter().position(|h|h.eq_ignore_ascii_case(name));
+    let date_column = column("date").or(column("time_period"))
+        .ok_or(Error::new(ErrorKind::InvalidData, "CSV file has no date column"))?;
+    let long_layout = column("currency").zip(column("rate"));
+    let base_column = column("base");
+    let mut result = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let fields = split_line(line, separator);
+        let field = |column: usize| fields.get(column).map(|f|f.as_str())
+            .ok_or(Error::new(ErrorKind::InvalidData, format!("line {}: too few fields", i + 2)));
+        let at_line = |e: Error| Error::new(e.kind(), format!("line {}: {}", i + 2, e));
+        let date = parse_date(field(date_column)?).map_err(at_line)?;
+        if let Some((currency_column, rate_column)) = long_layout {
+            let base = match base_column {
+                Some(c) => field(c)?.trim(),
+                None => base
+            };
+            result.push(ExchangeRate{base: base.to_string(), currency: field(currency_column)?.trim().to_string(),
+                date: Some(date), rate: parse_rate(field(rate_column)?).map_err(at_line)?});
+            continue;
+        }
+        for (column, currency) in header.iter().enumerate() {
+            if column == date_column || currency.is_empty() || currency == base {
+                continue;
+            }
+            match fields.get(column) {
+                Some(value) if !is_missing(value) => result.push(ExchangeRate{base: base.to_string(),
+                    currency: currency.clone(), date: Some(date), rate: parse_rate(value).map_err(at_line)?}),
+                _ => {}
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Drops rates of currencies that are not in the dictionary, returns the skipped currency codes.
+/// Fails when the dictionary is empty or base currency of some rate is unknown.
+pub fn filter_known(rates: Vec<ExchangeRate>, currencies: &Currencies)
+    -> Result<(Vec<ExchangeRate>, BTreeSet<String>), Error> {
+    if currencies.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "currencies dictionary is empty"));
+    }
+    let mut known = Vec::new();
+    let mut skipped = BTreeSet::new();
+    for rate in rates {
+        if currencies.get(&rate.base).is_err() {
+            return Err(Error::new(ErrorKind::InvalidData, format!("unknown base currency {}", rate.base)));
+        }
+        if currencies.get(&rate.currency).is_ok() {
+            known.push(rate);
+        } else {
+            skipped.insert(rate.currency);
+        }
+    }
+    Ok((known, skipped))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::entities::currencies::{Currencies, Currency};
+    use crate::core::amounts::parse_rate;
+    use crate::importers::rates::{filter_known, parse_rates_csv};
+
+    fn currency(code: &str) -> Currency {
+        Currency::new(code.to_string(), code.to_string(), 2)
+    }
+
+    #[test]
+    fn test_parse_rates() -> Result<(), Error> {
+        assert_eq!(parse_rate("1.0823")?, 1082300);
+        assert_eq!(parse_rate("44")?, 44000000);
+        assert!(parse_rate("1.12345678").is_err());
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("10000000000000").is_err());
+        assert!(parse_rate("-0.5").is_err());
+        let ecb = "Date, USD, JPY, UAH, \n2024-01-03, 1.0919, N/A, 41.5, \n2024-01-02, 1.0956, 155.2, 41.7, \n";
+        let rates = parse_rates_csv(ecb, "EUR")?;
+        assert_eq!(rates.len(), 5);
+        assert_eq!((rates[0].currency.as_str(), rates[0].date, rates[0].rate), ("USD", Some(20240103), 1091900));
+        let currencies = Currencies::new(vec![currency("EUR"), currency("USD"), currency("UAH")]);
+        let (known, skipped) = filter_known(rates, &currencies)?;
+        assert_eq!(known.len(), 4);
+        assert_eq!(skipped.into_iter().collect::<Vec<_>>(), vec!["JPY".to_string()]);
+        let long = "date;currency;rate\n02.01.2024;USD;0.0263\n";
+        let rates = parse_rates_csv(long, "UAH")?;
+        assert_eq!((rates[0].base.as_str(), rates[0].rate), ("UAH", 26300));
+        assert!(filter_known(parse_rates_csv(long, "GBP")?, &currencies).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+
+/// Element tag of an XML document, text content is not kept.
+#[derive(PartialEq, Debug)]
+pub enum XmlTag {
+    /// name, attributes and true for self-closing tags
+    Start(String, HashMap<String, String>, bool),
+    End(String)
+}
+
+/// Replaces predefined and numeric character references.
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result += &rest[..start];
+        rest = &rest[start..];
+        let reference = rest.find(';').map(|end|&rest[1..end]);
+        let c = match reference {
+            Some("amp") => Some('&'),
+            Some("lt") => Some('<'),
+            Some("gt") => Some('>'),
+            Some("quot") => Some('"'),
+            Some("apos") => Some('\''),
+            Some(r) if r.starts_with("#x") => u32::from_str_radix(&r[2..], 16).ok().and_then(char::from_u32),
+            Some(r) if r.starts_with('#') => r[1..].parse().ok().and_then(char::from_u32),
+            _ => None
+        };
+        match (c, reference) {
+            (Some(c), Some(r)) => {
+                result.push(c);
+                rest = &rest[r.len() + 2..];
+            }
+            _ => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result + rest
+}
+
+fn parse_attributes(text: &str) -> Result<HashMap<String, String>, Error> {
+    let invalid = || Error::new(ErrorKind::InvalidData, format!("invalid XML attributes {}", text));
+    let mut result = HashMap::new();
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let (name, value) = rest.split_once('=').ok_or_else(invalid)?;
+        let value = value.trim_start();
+        let quote = value.chars().next().filter(|q|*q == '"' || *q == '\'').ok_or_else(invalid)?;
+        let end = value[1..].find(quote).ok_or_else(invalid)? + 1;
+        result.insert(name.trim().to_string(), unescape(&value[1..end]));
+        rest = value[end + 1..].trim_start();
+    }
+    Ok(result)
+}
+
+/// Tags of the document in order. Declarations, comments and processing instructions are skipped.
+pub fn parse_tags(contents: &str) -> Result<Vec<XmlTag>, Error> {
+    let mut result = Vec::new();
+    let mut rest = contents;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        let skip_to = |end: &str|rest.find(end).map(|i|i + end.len())
+            .ok_or(Error::new(ErrorKind::InvalidData, "unterminated XML tag"));
+        if rest.starts_with("<!--") {
+            rest = &rest[skip_to("-->")?..];
+            continue;
+        }
+        let end = skip_to(">")?;
+        let tag = &rest[1..end - 1];
+        rest = &rest[end..];
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+        if let Some(name) = tag.strip_prefix('/') {
+            result.push(XmlTag::End(name.trim().to_string()));
+            continue;
+        }
+        let (tag, empty) = match tag.strip_suffix('/') {
+            Some(t) => (t, true),
+            None => (tag, false)
+        };
+        let (name, attributes) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        result.push(XmlTag::Start(name.to_string(), parse_attributes(attributes)?, empty));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::importers::xml::{parse_tags, XmlTag};
+
+    #[test]
+    fn test_parse_tags() {
+        let tags = parse_tags("<?xml version=\"1.0\"?>\n<!-- comment <a> -->\n<a x = 'Tom &amp; Jerry &#233;'>text<b/></a>")
+            .unwrap();
+        assert_eq!(tags, vec![
+            XmlTag::Start("a".to_string(), HashMap::from([("x".to_string(), "Tom & Jerry é".to_string())]), false),
+            XmlTag::Start("b".to_string(), HashMap::new(), true),
+            XmlTag::End("a".to_string())
+        ]);
+        assert!(parse_tags("<a x=\"1>").is_err());
+    }
+}
@@ -1,60 +1,418 @@
-use std::io::Error;
-use crate::core::data_source::{DataSource, JsonDataSource};
-use crate::core::time_series_data::{DatedSource, FileInfo, FileWithDate};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use crate::core::crypto::CryptoProcessor;
+use crate::core::field_crypto::{FieldCrypto, PAYEE_FIELD};
+use crate::core::dates::check_date;
+use crate::core::interner::StringPool;
+use crate::core::data_source::{json_error, DataSource, EncryptedJsonDataSource, JsonDataSource};
+use crate::core::time_series_data::{month_folders, DatedSource, FileInfo, FileWithDate, SaveBatch};
 use crate::db::DBConfiguration;
 use crate::entities::accounts::Account;
+use crate::entities::currencies::{Currency, SharedMinorUnits};
+use crate::entities::payees::Payee;
+use crate::entities::parameters::ParameterDefinition;
+use crate::entities::loans::Loan;
+use crate::entities::instruments::{Instrument, InstrumentPrice};
+use crate::entities::goals::Goal;
+use crate::entities::budgets::BudgetLine;
+use crate::entities::rates::ExchangeRate;
+use crate::entities::settings::Settings;
+use crate::entities::templates::OperationTemplate;
+use crate::entities::validation_rules::ValidationRule;
+use crate::reports::custom::ReportSpec;
 use crate::entities::finance_operations::{FinanceOperation, FinanceRecord};
 use crate::entities::subcategories::{Category, Subcategory};
 
 pub struct JsonDBConfiguration {
+    field_crypto: Option<Arc<FieldCrypto>>,
+    /// encrypts whole files, attachments and backups
+    file_crypto: Option<Arc<dyn CryptoProcessor>>
+}
+
+impl Default for JsonDBConfiguration {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl JsonDBConfiguration {
     pub fn new() -> JsonDBConfiguration {
-        JsonDBConfiguration{}
+        JsonDBConfiguration{field_crypto: None, file_crypto: None}
+    }
+
+    /// Configuration that keeps selected free-text fields encrypted in the JSON files.
+    pub fn new_with_field_crypto(field_crypto: FieldCrypto) -> JsonDBConfiguration {
+        JsonDBConfiguration{field_crypto: Some(Arc::new(field_crypto)), file_crypto: None}
+    }
+
+    /// Configuration of the database in the folder, fields listed in its settings are encrypted
+    /// with the key from the field key file.
+    pub fn for_data_folder(data_folder_path: &str) -> Result<JsonDBConfiguration, Error> {
+        let settings = Settings::load(data_folder_path.to_string(), Box::new(JsonDataSource{}))?;
+        let (Some(fields), Some(key_file)) = (settings.encrypted_fields, settings.field_key_file) else {
+            return Ok(JsonDBConfiguration::new());
+        };
+        #[cfg(any(feature = "binary", feature = "server", feature = "encryption"))]
+        {
+            let key: [u8; 32] = fs::read(&key_file)?.try_into()
+                .map_err(|_|Error::new(ErrorKind::InvalidData, format!("{}: key file must contain 32 bytes", key_file)))?;
+            let crypto = Arc::new(crate::core::crypto::AesGcmProcessor::new(&key));
+            Ok(JsonDBConfiguration::new_with_field_crypto(FieldCrypto::new(crypto, fields)))
+        }
+        #[cfg(not(any(feature = "binary", feature = "server", feature = "encryption")))]
+        {
+            let _ = (fields, key_file);
+            Err(Error::new(ErrorKind::Unsupported, "field encryption is not supported by this build"))
+        }
+    }
+
+    /// Configuration that keeps all dictionaries, operations and attachments encrypted,
+    /// used for private books of encryption domains.
+    pub fn new_with_crypto(file_crypto: Arc<dyn CryptoProcessor>) -> JsonDBConfiguration {
+        JsonDBConfiguration{field_crypto: None, file_crypto: Some(file_crypto)}
+    }
+
+    fn source<T: DeserializeOwned + Serialize>(&self) -> Box<dyn DataSource<T>> {
+        match &self.file_crypto {
+            Some(c) => Box::new(EncryptedJsonDataSource{crypto: c.clone()}),
+            None => Box::new(JsonDataSource{})
+        }
     }
 }
+
 impl DBConfiguration for JsonDBConfiguration {
     fn get_accounts_source(&self) -> Box<dyn DataSource<Vec<Account>>> {
-        Box::new(JsonDataSource{})
+        self.source()
     }
 
     fn get_categories_source(&self) -> Box<dyn DataSource<Vec<Category>>> {
-        Box::new(JsonDataSource{})
+        self.source()
     }
 
     fn get_subcategories_source(&self) -> Box<dyn DataSource<Vec<Subcategory>>> {
-        Box::new(JsonDataSource{})
+        self.source()
+    }
+
+    fn get_currencies_source(&self) -> Box<dyn DataSource<Vec<Currency>>> {
+        self.source()
+    }
+
+    fn get_payees_source(&self) -> Box<dyn DataSource<Vec<Payee>>> {
+        match &self.field_crypto {
+            Some(c) if c.is_encrypted(PAYEE_FIELD) => Box::new(EncryptedPayeesSource{crypto: c.clone(),
+                                                                                     inner: self.source()}),
+            _ => self.source()
+        }
+    }
+
+    fn get_parameters_source(&self) -> Box<dyn DataSource<Vec<ParameterDefinition>>> {
+        self.source()
+    }
+
+    fn get_loans_source(&self) -> Box<dyn DataSource<Vec<Loan>>> {
+        self.source()
+    }
+
+    fn get_instruments_source(&self) -> Box<dyn DataSource<Vec<Instrument>>> {
+        self.source()
+    }
+
+    fn get_prices_source(&self) -> Box<dyn DataSource<Vec<InstrumentPrice>>> {
+        self.source()
+    }
+
+    fn get_goals_source(&self) -> Box<dyn DataSource<Vec<Goal>>> {
+        self.source()
+    }
+
+    fn get_budgets_source(&self) -> Box<dyn DataSource<Vec<BudgetLine>>> {
+        self.source()
+    }
+
+    fn get_rates_source(&self) -> Box<dyn DataSource<Vec<ExchangeRate>>> {
+        self.source()
+    }
+
+    fn get_settings_source(&self) -> Box<dyn DataSource<Settings>> {
+        self.source()
+    }
+
+    fn get_templates_source(&self) -> Box<dyn DataSource<Vec<OperationTemplate>>> {
+        self.source()
+    }
+
+    fn get_report_specs_source(&self) -> Box<dyn DataSource<Vec<ReportSpec>>> {
+        self.source()
+    }
+
+    fn get_validation_rules_source(&self) -> Box<dyn DataSource<Vec<ValidationRule>>> {
+        self.source()
+    }
+
+    fn get_main_data_source(&self, minor_units: SharedMinorUnits) -> Box<dyn DatedSource<FinanceRecord>> {
+        Box::new(JsonDatedSource{pool: StringPool::new(), field_crypto: self.field_crypto.clone(),
+            file_crypto: self.file_crypto.clone(), minor_units})
     }
 
-    fn get_main_data_source(&self) -> Box<dyn DatedSource<FinanceRecord>> {
-        Box::new(JsonDatedSource{})
+    fn get_attachments_crypto(&self) -> Option<Box<dyn CryptoProcessor>> {
+        self.file_crypto.clone().map(|c|Box::new(c) as Box<dyn CryptoProcessor>)
+    }
+
+    fn encrypts_files(&self) -> bool {
+        self.file_crypto.is_some()
+    }
+}
+
+const OPERATIONS_FILE_NAME: &str = "operations.json";
+
+/// Payees dictionary with encrypted names.
+struct EncryptedPayeesSource {
+    crypto: Arc<FieldCrypto>,
+    inner: Box<dyn DataSource<Vec<Payee>>>
+}
+
+impl DataSource<Vec<Payee>> for EncryptedPayeesSource {
+    fn load(&self, file_name: String, add_extension: bool) -> Result<Vec<Payee>, Error> {
+        let mut payees = self.inner.load(file_name, add_extension)?;
+        for payee in payees.iter_mut() {
+            payee.name = self.crypto.decrypt(&payee.name)?;
+        }
+        Ok(payees)
+    }
+
+    fn save(&self, data: &Vec<Payee>, file_name: String) -> Result<(), Error> {
+        let payees = data.iter()
+            .map(|p|Ok(Payee{id: p.id, name: self.crypto.encrypt(&p.name)?}))
+            .collect::<Result<Vec<Payee>, Error>>()?;
+        self.inner.save(&payees, file_name)
     }
 }
 
 struct JsonDatedSource {
+    pool: StringPool,
+    field_crypto: Option<Arc<FieldCrypto>>,
+    file_crypto: Option<Arc<dyn CryptoProcessor>>,
+    minor_units: SharedMinorUnits
+}
+
+/// Summa as written in the file, decimal summas are in currency units.
+#[derive(Deserialize, Serialize)]
+struct SummaRecord {
+    #[serde(alias = "Summa", alias = "summa")]
+    summa: serde_json::Number
+}
+
+/// Index of the first operation in the file that cannot be deserialized, called only after a failed load.
+fn find_bad_record(file_name: &str) -> Option<usize> {
+    let records: Vec<serde_json::Value> = JsonDataSource{}.load(file_name.to_string(), false).ok()?;
+    records.into_iter().position(|r|serde_json::from_value::<FinanceOperation>(r).is_err())
+}
+
+#[cfg(not(feature = "simd-json"))]
+fn load_operations<T: DeserializeOwned + Serialize>(file_name: &str, crypto: Option<&Arc<dyn CryptoProcessor>>)
+    -> Result<Vec<T>, Error> {
+    match crypto {
+        Some(c) => EncryptedJsonDataSource{crypto: c.clone()}.load(file_name.to_string(), false),
+        None => JsonDataSource{}.load(file_name.to_string(), false)
+    }
+}
+
+#[cfg(feature = "simd-json")]
+fn load_operations<T: DeserializeOwned>(file_name: &str, crypto: Option<&Arc<dyn CryptoProcessor>>)
+    -> Result<Vec<T>, Error> {
+    let mut data = std::fs::read(file_name)?;
+    if let Some(c) = crypto {
+        data = c.decode(&data)?;
+    }
+    simd_json::serde::from_slice(&mut data)
+        .map_err(|e|Error::new(ErrorKind::InvalidData, format!("{}: {}", file_name, e)))
+}
+
+impl JsonDatedSource {
+    /// Decimal summas are read in hundredths, they are reread in minor units of the account
+    /// currency when some account keeps sums in other units.
+    fn convert_decimal_sums(&self, file_name: &str, ops: &mut [FinanceOperation]) -> Result<(), Error> {
+        let minor_units = self.minor_units.read().unwrap();
+        if minor_units.hundredths_only() {
+            return Ok(());
+        }
+        let sums: Vec<SummaRecord> = load_operations(file_name, self.file_crypto.as_ref())?;
+        for (op, record) in ops.iter_mut().zip(sums) {
+            if let (Some(value), false) = (record.summa.as_f64(), record.summa.is_i64() || record.summa.is_u64()) {
+                op.set_summa(minor_units.currency_at(op.get_account(), op.date)?.from_decimal(value));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl DatedSource<FinanceRecord> for JsonDatedSource {
     fn load(&mut self, files: Vec<FileWithDate>) -> Result<FinanceRecord, Error> {
-        let mut operations = Vec::new();
+        let mut record = FinanceRecord::new(Vec::new());
         for file in files {
-            let mut ops: Vec<FinanceOperation> = JsonDataSource{}.load(file.name, false)?;
-            ops.iter_mut().for_each(|op|op.date = file.date);
-            operations.append(&mut ops);
+            let mut ops: Vec<FinanceOperation> = load_operations(&file.name, self.file_crypto.as_ref())
+                .map_err(|e|match find_bad_record(&file.name) {
+                    Some(index) => Error::new(e.kind(), format!("{} (month {}, record {})", e, file.date / 100, index)),
+                    None => e
+                })?;
+            for op in ops.iter_mut() {
+                op.date = file.date;
+            }
+            self.convert_decimal_sums(&file.name, &mut ops)?;
+            for op in ops.iter_mut() {
+                if let Some(c) = &self.field_crypto {
+                    c.decrypt_operation(op)?;
+                }
+                op.intern_strings(&mut self.pool);
+            }
+            record.add_file_operations(file.name, ops);
         }
-        Ok(FinanceRecord::new(operations))
+        Ok(record)
     }
 
     fn parse_date(&self, info: &FileInfo) -> Result<u64, Error> {
-        info.convert_folder_name_to_number()
+        let date = info.convert_folder_name_to_number()?;
+        check_date(date)?;
+        Ok(date)
+    }
+
+    /// Every date of the month is written to a single file, other files of the month are removed.
+    /// Operations keep their order within a date, attachments address them by index.
+    fn prepare_save(&self, data: &FinanceRecord, data_folder_path: &str, key: u64) -> Result<SaveBatch, Error> {
+        let mut batch = SaveBatch::default();
+        let mut by_date: BTreeMap<u64, Vec<&FinanceOperation>> = BTreeMap::new();
+        for op in &data.operations {
+            by_date.entry(op.date).or_default().push(op);
+        }
+        for (date, ops) in &by_date {
+            let folder = format!("{}/{}", data_folder_path, date);
+            let file_name = format!("{}/{}", folder, OPERATIONS_FILE_NAME);
+            let bytes = match &self.field_crypto {
+                Some(c) => {
+                    let mut encrypted = Vec::with_capacity(ops.len());
+                    for op in ops {
+                        let mut op = op.copy();
+                        c.encrypt_operation(&mut op)?;
+                        encrypted.push(op);
+                    }
+                    serde_json::to_vec(&encrypted)
+                }
+                None => serde_json::to_vec(ops)
+            }.map_err(|e|json_error(&file_name, e))?;
+            let bytes = match &self.file_crypto {
+                Some(c) => c.encode(&bytes)?,
+                None => bytes
+            };
+            batch.folders.insert(folder);
+            batch.files.push((file_name, bytes));
+        }
+        for (date, folder) in month_folders(data_folder_path, key)? {
+            let keep = by_date.contains_key(&date);
+            for entry in fs::read_dir(&folder)? {
+                let entry = entry?;
+                if !keep || entry.file_name() != OPERATIONS_FILE_NAME {
+                    batch.obsolete_files.push(entry.path().to_string_lossy().to_string());
+                }
+            }
+            if !keep {
+                batch.obsolete_folders.push(folder);
+            }
+        }
+        Ok(batch)
+    }
+
+    fn get_files(&self, data_folder_path: &str, key: u64) -> Result<Vec<FileWithDate>, Error> {
+        let mut result = Vec::new();
+        for (date, folder) in month_folders(data_folder_path, key)? {
+            for entry in fs::read_dir(&folder)? {
+                result.push(FileWithDate{name: entry?.path().to_string_lossy().to_string(), date});
+            }
+        }
+        result.sort_by(|a, b|(a.date, &a.name).cmp(&(b.date, &b.name)));
+        Ok(result)
     }
 
-    fn save(&self, data: &FinanceRecord, data_folder_path: &String, date: u64) -> Result<(), Error> {
-        todo!()
+    fn fork(&self) -> Box<dyn DatedSource<FinanceRecord>> {
+        Box::new(JsonDatedSource{pool: StringPool::new(), field_crypto: self.field_crypto.clone(),
+            file_crypto: self.file_crypto.clone(), minor_units: self.minor_units.clone()})
     }
+}
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::Error;
+    use crate::db::HomeAccountingDB;
+    use crate::entities::accounts::AccountId;
+    use crate::json_db_config::JsonDBConfiguration;
 
-    fn get_files(&self, data_folder_path: &String, date: u64) -> Result<Vec<FileWithDate>, Error> {
-        todo!()
+    #[test]
+    fn test_decimal_sums() -> Result<(), Error> {
+        let folder = temp_dir().join("had_test_decimal_sums").to_str().unwrap().to_string();
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.clone() + "/dates/20240105")?;
+        fs::write(folder.clone() + "/accounts.json",
+                  r#"[{"id": 1, "name": "Card", "valutaCode": "UAH", "activeTo": null, "isCash": true},
+                      {"id": 2, "name": "Dinar", "valutaCode": "KWD", "activeTo": null, "isCash": true},
+                      {"id": 3, "name": "Yen", "valutaCode": "JPY", "activeTo": null, "isCash": true}]"#)?;
+        fs::write(folder.clone() + "/categories.json", r#"[{"id": 1, "name": "Food"}]"#)?;
+        fs::write(folder.clone() + "/subcategories.json",
+                  r#"[{"id": 1, "name": "Groceries", "code": null, "operationCodeId": "EXPN", "categoryId": 1}]"#)?;
+        fs::write(folder.clone() + "/currencies.json",
+                  r#"[{"code": "UAH", "symbol": "₴", "minorUnits": 2},
+                      {"code": "KWD", "symbol": "KD", "minorUnits": 3},
+                      {"code": "JPY", "symbol": "¥", "minorUnits": 0, "rounding": "halfEven"}]"#)?;
+        fs::write(folder.clone() + "/dates/20240105/operations.json",
+                  r#"[{"Id": 1, "AccountId": 1, "SubcategoryId": 1, "Amount": null, "Summa": 12.5, "FinOpProperies": []},
+                      {"Id": 2, "AccountId": 2, "SubcategoryId": 1, "Amount": null, "Summa": 12.345, "FinOpProperies": []},
+                      {"Id": 3, "AccountId": 3, "SubcategoryId": 1, "Amount": null, "Summa": 122.5, "FinOpProperies": []},
+                      {"Id": 4, "AccountId": 2, "SubcategoryId": 1, "Amount": null, "Summa": 1500, "FinOpProperies": []}]"#)?;
+        let db = HomeAccountingDB::load(folder.clone(), Box::new(JsonDBConfiguration::new()), 100)?;
+        let sums: Vec<i64> = db.get_operations(0, u64::MAX)?.iter().map(|o|o.get_summa()).collect();
+        assert_eq!(sums, vec![1250, 12345, 122, 1500]);
+        assert_eq!(db.get_account_currency(AccountId(2), 20240105)?.format(12345), "12.345 KD");
+        assert_eq!(db.build_statement(AccountId(3), 202401)?.to_text().lines().last(), Some("Closing balance: -122"));
+        fs::write(folder.clone() + "/currencies.json", r#"[{"code": "UAH", "symbol": "₴", "minorUnits": 2}]"#)?;
+        assert!(HomeAccountingDB::load(folder.clone(), Box::new(JsonDBConfiguration::new()), 100).is_err());
+        fs::remove_dir_all(&folder)
     }
-}
\ No newline at end of file
+
+    #[cfg(any(feature = "binary", feature = "server", feature = "encryption"))]
+    #[test]
+    fn test_field_encryption() -> Result<(), Error> {
+        use crate::entities::finance_operations::{FinOpParameter, FinanceOperation};
+        use crate::entities::subcategories::SubcategoryId;
+        let folder = temp_dir().join("had_test_field_encryption").to_str().unwrap().to_string();
+        let key_file = temp_dir().join("had_test_field_encryption.key").to_str().unwrap().to_string();
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.clone() + "/dates")?;
+        fs::write(&key_file, [7u8; 32])?;
+        fs::write(folder.clone() + "/accounts.json",
+                  r#"[{"id": 1, "name": "Card", "valutaCode": "UAH", "activeTo": null, "isCash": true}]"#)?;
+        fs::write(folder.clone() + "/categories.json", r#"[{"id": 1, "name": "Food"}]"#)?;
+        fs::write(folder.clone() + "/subcategories.json",
+                  r#"[{"id": 1, "name": "Groceries", "code": null, "operationCodeId": "EXPN", "categoryId": 1}]"#)?;
+        fs::write(folder.clone() + "/settings.json",
+                  format!(r#"{{"encryptedFields": ["NETW"], "fieldKeyFile": {:?}}}"#, key_file))?;
+        let db = HomeAccountingDB::load(folder.clone(), Box::new(JsonDBConfiguration::for_data_folder(&folder)?), 100)?;
+        db.add_operation(FinanceOperation::new(20240105, AccountId(1), SubcategoryId(1), None, 1000,
+                                               vec![FinOpParameter::Netw("Corner shop".into()),
+                                                    FinOpParameter::Typ("card".into())]))?;
+        db.flush(false)?;
+        let text = fs::read_to_string(folder.clone() + "/dates/20240105/operations.json")?;
+        assert!(!text.contains("Corner shop") && text.contains("enc:") && text.contains("card"));
+        let db = HomeAccountingDB::load(folder.clone(), Box::new(JsonDBConfiguration::for_data_folder(&folder)?), 100)?;
+        let ops = db.get_operations(0, u64::MAX)?;
+        assert_eq!(ops.len(), 1);
+        assert_eq!(ops[0].get_network(), Some("Corner shop"));
+        let db = HomeAccountingDB::load(folder.clone(), Box::new(JsonDBConfiguration::new()), 100)?;
+        assert!(db.get_operations(0, u64::MAX)?[0].get_network().is_some_and(|n|n.starts_with("enc:")));
+        fs::remove_file(&key_file)?;
+        assert!(JsonDBConfiguration::for_data_folder(&folder).is_err());
+        fs::remove_dir_all(&folder)
+    }
+}
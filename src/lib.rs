@@ -0,0 +1,36 @@
+#[cfg(feature = "fs")]
+pub mod db;
+#[cfg(feature = "fs")]
+pub mod books;
+#[cfg(feature = "fs")]
+pub mod verify;
+pub mod entities;
+pub mod core;
+pub mod reports;
+pub mod suggester;
+pub mod query;
+pub mod scenario;
+#[cfg(feature = "json")]
+pub mod snapshot;
+#[cfg(all(feature = "fs", feature = "json"))]
+pub mod json_db_config;
+#[cfg(all(feature = "fs", feature = "json"))]
+pub mod generator;
+#[cfg(all(feature = "fs", feature = "json"))]
+pub mod anonymizer;
+#[cfg(feature = "binary")]
+pub mod binary_db_config;
+#[cfg(feature = "binary")]
+pub mod binary_format;
+#[cfg(feature = "rkyv")]
+pub mod binary_archive;
+#[cfg(feature = "arrow")]
+pub mod analytics;
+#[cfg(feature = "importers")]
+pub mod importers;
+#[cfg(all(feature = "server", feature = "json"))]
+pub mod server;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "python")]
+pub mod python;
@@ -1,67 +1,1313 @@
-mod db;
-mod entities;
-mod core;
-mod json_db_config;
-mod binary_db_config;
-
 use std::env::args;
 use std::io::Error;
-use crate::binary_db_config::BinaryDBConfiguration;
-use crate::db::HomeAccountingDB;
-use crate::json_db_config::JsonDBConfiguration;
+#[cfg(all(feature = "fs", feature = "json"))]
+use home_accounting_db::books::Books;
+#[cfg(all(feature = "fs", feature = "json"))]
+use home_accounting_db::db::LoadOptions;
+#[cfg(all(feature = "fs", feature = "json"))]
+use home_accounting_db::verify::repair::Fixer;
+#[cfg(all(feature = "fs", feature = "json"))]
+use home_accounting_db::core::month_locks::parse_months;
+#[cfg(all(feature = "fs", feature = "json"))]
+use home_accounting_db::generator::{generate, generate_json, GeneratorOptions};
+#[cfg(all(feature = "fs", feature = "json"))]
+use home_accounting_db::anonymizer::anonymize;
+#[cfg(all(feature = "fs", feature = "json"))]
+use std::time::Instant;
+#[cfg(all(feature = "server", feature = "json"))]
+use std::net::TcpListener;
+#[cfg(all(feature = "server", feature = "json"))]
+use home_accounting_db::core::replication::ReplicationReceiver;
+#[cfg(all(feature = "fs", feature = "json"))]
+use home_accounting_db::db::DBConfiguration;
+#[cfg(all(feature = "fs", feature = "json"))]
+use home_accounting_db::verify::manifest::MANIFEST_FILE_NAME;
+#[cfg(all(feature = "fs", feature = "json"))]
+use home_accounting_db::verify::startup::{check_startup, STARTUP_CHECK_MONTHS};
+#[cfg(all(feature = "server", feature = "json"))]
+use home_accounting_db::server::configuration::ServerConfiguration;
+#[cfg(all(feature = "server", feature = "json"))]
+use home_accounting_db::server::tcp::RequestServer;
+#[cfg(all(feature = "server", feature = "json"))]
+use home_accounting_db::server::maintenance::{load_status, MaintenanceScheduler};
+#[cfg(all(feature = "server", feature = "json"))]
+use home_accounting_db::server::scheduler::ReportScheduler;
+#[cfg(all(feature = "fs", feature = "json"))]
+use std::io::ErrorKind;
+#[cfg(all(feature = "server", feature = "json"))]
+use std::{thread, time::Duration};
+#[cfg(all(feature = "server", feature = "monobank"))]
+use home_accounting_db::importers::{bank::{sync_account, BankConnector}, monobank::MonobankConnector};
+#[cfg(any(all(feature = "server", feature = "monobank"), all(feature = "fs", feature = "json")))]
+use std::fs;
+#[cfg(all(feature = "fs", feature = "json", feature = "pdf"))]
+use home_accounting_db::reports::pdf::{annual_report_to_pdf, statement_to_pdf};
+#[cfg(all(feature = "fs", feature = "json", feature = "importers"))]
+use home_accounting_db::importers::rates::{filter_known, parse_rates_csv};
+#[cfg(all(feature = "server", feature = "json", feature = "importers"))]
+use home_accounting_db::server::watcher::FolderWatcher;
+#[cfg(all(feature = "fs", feature = "json", feature = "importers"))]
+use home_accounting_db::importers::{parse_book, parse_file, pipeline::ImportPipeline};
+#[cfg(all(feature = "fs", feature = "json", feature = "importers"))]
+use home_accounting_db::importers::dictionaries::{merge_accounts, merge_categories, merge_subcategories};
+#[cfg(all(feature = "fs", feature = "json", feature = "importers"))]
+use home_accounting_db::entities::{accounts::Account, subcategories::{Category, Subcategory}};
+#[cfg(feature = "encryption")]
+use home_accounting_db::core::encryption_domain::{change_passphrase, create_domain, encrypt_folder};
+#[cfg(all(feature = "server", feature = "json"))]
+use home_accounting_db::core::crypto::AesGcmProcessor;
+#[cfg(all(feature = "fs", feature = "json"))]
+use home_accounting_db::reports::dictionaries::{accounts_to_csv, categories_to_csv, subcategories_to_csv};
+#[cfg(all(feature = "fs", feature = "json", feature = "importers"))]
+use std::io::{stdin, stdout, Write};
+#[cfg(all(feature = "fs", feature = "json"))]
+use home_accounting_db::entities::accounts::AccountId;
+#[cfg(all(feature = "fs", feature = "json"))]
+use home_accounting_db::{core::amounts::{format_summa, parse_rate, parse_summa}, query::OperationQuery, scenario::Scenario};
+#[cfg(all(feature = "fs", feature = "json"))]
+use home_accounting_db::entities::{finance_operations::FinanceOperation, subcategories::SubcategoryId};
+#[cfg(feature = "telegram")]
+use home_accounting_db::server::telegram::TelegramBot;
+#[cfg(any(feature = "telegram", all(feature = "server", feature = "json", feature = "importers")))]
+use home_accounting_db::server::notifier::Notifier;
+#[cfg(feature = "binary")]
+use home_accounting_db::binary_db_config::BinaryDBConfiguration;
+#[cfg(any(all(feature = "fs", feature = "json"), feature = "binary"))]
+use home_accounting_db::db::HomeAccountingDB;
+#[cfg(all(feature = "fs", feature = "json"))]
+use home_accounting_db::json_db_config::JsonDBConfiguration;
+#[cfg(all(feature = "fs", feature = "json"))]
+use home_accounting_db::core::events::{Event, EventKind};
+#[cfg(all(feature = "fs", feature = "json"))]
+use std::sync::mpsc::Receiver;
+#[cfg(any(feature = "telegram", all(feature = "server", feature = "json", feature = "importers")))]
+use std::sync::mpsc::TryRecvError;
 
 fn usage() -> Result<(), Error> {
     println!("Usage: home_accounting_db data_folder_path\n  test_json date\n  test date aes_key_file");
-    println!("  migrate source_folder_path aes_key\n  server port rsa_key_file");
-    return Ok(());
+    println!("  migrate source_folder_path [aes_key_file]\n  server port aes_key_file");
+    println!("  encrypt user\n  change_passphrase user");
+    println!("  replicate standby_address aes_key_file\n  standby port aes_key_file");
+    println!("  send_report server_configuration_file yyyymm\n  telegram server_configuration_file");
+    println!("  watch server_configuration_file\n  bank_sync server_configuration_file");
+    println!("  maintenance server_configuration_file (maintenance tasks and monthly reports)");
+    println!("  import statement_file account_id income_subcategory_id expense_subcategory_id");
+    println!("  import_book homebank_or_kmymoney_file\n  export_gnucash output_file");
+    println!("  export_dictionary accounts|categories|subcategories output_file");
+    println!("  import_dictionary accounts|categories|subcategories csv_file");
+    println!("  import_rates rates_csv_file [base_currency]\n  statement account_id yyyymm [text|csv|json|pdf output_file]");
+    println!("  annual_report year [text|pdf output_file]\n  html_report from_date to_date output_file");
+    println!("  networks from_date to_date\n  anomalies from_yyyymm to_yyyymm [multiple]");
+    println!("  report name [from_date to_date] [text|csv]\n  reports");
+    println!("  tax_report year [text|csv]\n  base_currency [code]\n  slow_log [milliseconds|off]\n  net_worth date");
+    println!("  balance_snapshots date1,date2,...");
+    println!("  day_boundary [utc_offset_minutes day_start_hour]\n  warm_months [count|off]");
+    println!("  budget yyyymm\n  what_if date account_id subcategory_id summa [months]\n  over_limit yyyymm\n  spending_limits subcategory_id soft_limit|off hard_limit|off");
+    println!("  add_op account_id subcategory_id summa\n  add_op --template name [summa]");
+    println!("  validation_rules");
+    println!("  recategorize from_subcategory_id to_subcategory_id [query_filters]");
+    println!("  merge_subcategories source_id target_id\n  split_subcategory source_id netw_text=id,... [default_id]");
+    println!("  archive_accounts closed_before_date\n  change_currency account_id yyyymm currency rate");
+    println!("  retire account|subcategory id\n  restore_retired account|subcategory id\n  retired");
+    println!("  backup backup_file [--verify]\n  verify_backup backup_file");
+    println!("  query from=yyyymmdd,to=yyyymmdd,account=N,min=N,max=N,direction=income|expense,");
+    println!("        sort=date|amount|account,order=asc|desc,offset=N,limit=N");
+    println!("  lock_months months owner\n  unlock_months months owner [--force]\n  month_locks\n  month_versions [from_date to_date]");
+    println!("  archive years_to_keep\n  checkpoint\n  restore --at unix_timestamp target_folder_path");
+    println!("  verify [lenient] [recover] [incremental]\n  quick_check [months [aes_key_file]]\n  repair dates,months,signs,totals|all [--dry-run]\n  dedupe\n  stats\n  memory [max_active_items]\n  bench years\n  generate years=N[,start=YYYY,accounts=N,categories=N,density=N,seed=N,format=json|binary]");
+    println!("  anonymize target_folder_path");
+    println!("Usage: home_accounting_db books_configuration_file test_book book_name date");
+    Ok(())
+}
+
+/// Line of the standard input without the line end.
+#[cfg(feature = "encryption")]
+fn read_passphrase(prompt: &str) -> Result<String, Error> {
+    print!("{}: ", prompt);
+    std::io::Write::flush(&mut std::io::stdout())?;
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Key file contains 32 bytes of the AES-256 key.
+#[cfg(any(feature = "binary", all(feature = "server", feature = "json")))]
+fn load_aes_key(file_name: &str) -> Result<[u8; 32], Error> {
+    std::fs::read(file_name)?.try_into()
+        .map_err(|_|Error::new(std::io::ErrorKind::InvalidData, "key file must contain 32 bytes"))
+}
+
+/// Loads the JSON database, its changes are written to the write-ahead log, so a checkpoint and
+/// the log restore any moment after it.
+#[cfg(all(feature = "fs", feature = "json"))]
+fn load_db(data_folder_path: &str) -> Result<HomeAccountingDB, Error> {
+    load_db_with_options(data_folder_path, LoadOptions::new(1000000))
+}
+
+#[cfg(all(feature = "fs", feature = "json"))]
+fn load_db_with_options(data_folder_path: &str, options: LoadOptions) -> Result<HomeAccountingDB, Error> {
+    let mut db = HomeAccountingDB::load_with_options(data_folder_path.to_string(),
+                                                     Box::new(JsonDBConfiguration::for_data_folder(data_folder_path)?),
+                                                     options)?;
+    db.enable_wal();
+    Ok(db)
+}
+
+/// Compares operations count of the backup with the live database.
+#[cfg(all(feature = "fs", feature = "json"))]
+fn check_backup(db: &HomeAccountingDB, data_folder_path: &str, file_name: &str) -> Result<(), Error> {
+    let backed_up = HomeAccountingDB::verify_backup(file_name,
+                                                    Box::new(JsonDBConfiguration::for_data_folder(data_folder_path)?))?;
+    let live = db.get_operations(0, u64::MAX)?.len();
+    if backed_up != live {
+        return Err(Error::new(ErrorKind::InvalidData,
+                              format!("backup has {} operations, database has {}", backed_up, live)));
+    }
+    println!("Backup is valid, {} operations", backed_up);
+    Ok(())
+}
+
+/// Balance alerts published by the database, a subscriber with ALERTS_CAPACITY unread alerts is disconnected.
+#[cfg(all(feature = "fs", feature = "json"))]
+fn subscribe_alerts(db: &HomeAccountingDB) -> Receiver<Event> {
+    db.subscribe_to(ALERTS_CAPACITY, |k|matches!(k, EventKind::BalanceAlert(_)))
+}
+
+#[cfg(all(feature = "fs", feature = "json"))]
+const ALERTS_CAPACITY: usize = 256;
+
+/// Sends balance alerts published since the previous call, failed notifications are only logged.
+/// A subscription disconnected for falling behind is renewed.
+#[cfg(any(feature = "telegram", all(feature = "server", feature = "json", feature = "importers")))]
+fn send_alerts(db: &HomeAccountingDB, alerts: &mut Receiver<Event>, notifiers: &[Box<dyn Notifier>])
+    -> Result<(), Error> {
+    loop {
+        let alert = match alerts.try_recv() {
+            Ok(Event{kind: EventKind::BalanceAlert(alert), ..}) => alert,
+            Ok(_) => continue,
+            Err(TryRecvError::Empty) => return Ok(()),
+            Err(TryRecvError::Disconnected) => {
+                println!("alert queue overflowed, some balance alerts were dropped");
+                *alerts = subscribe_alerts(db);
+                return Ok(());
+            }
+        };
+        let text = alert.describe(db.get_accounts())?;
+        println!("{}", text);
+        for notifier in notifiers {
+            if let Err(e) = notifier.notify("Balance alert", &text) {
+                println!("notification error: {}", e);
+            }
+        }
+    }
 }
 
 fn main() -> Result<(), Error> {
     let arguments: Vec<String> = args().skip(1).collect();
     let l = arguments.len();
-    if l < 2 || l > 4 {
+    if !(2..=7).contains(&l) {
         return usage();
     }
-    let aes_key = [0u8; 32];
     match arguments[1].as_str() {
+        #[cfg(all(feature = "fs", feature = "json"))]
         "test_json" => {
             if l != 3 {
                 usage()
             } else {
-                let mut db = HomeAccountingDB::load(arguments[0].clone(), Box::new(JsonDBConfiguration::new()), 1000000)?;
+                let db = load_db(&arguments[0])?;
                 db.test(arguments[2].clone())
             }
         }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "verify" => {
+            let flags = &arguments[2..];
+            if flags.iter().any(|f|f != "lenient" && f != "recover" && f != "incremental") {
+                usage()
+            } else {
+                let incremental = flags.iter().any(|f|f == "incremental");
+                let mut options = LoadOptions::new(1000000);
+                options.verify_references = !incremental;
+                options.lenient_dates = flags.iter().any(|f|f == "lenient");
+                options.recover_errors = flags.iter().any(|f|f == "recover");
+                let db = load_db_with_options(&arguments[0], options)?;
+                if incremental {db.verify_incremental()} else {db.verify()}
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "quick_check" => {
+            let months = match l {
+                2 => STARTUP_CHECK_MONTHS,
+                3 | 4 => arguments[2].parse().map_err(|_|Error::new(ErrorKind::InvalidInput, "invalid months count"))?,
+                _ => return usage()
+            };
+            let configuration: Box<dyn DBConfiguration> = match l {
+                #[cfg(feature = "binary")]
+                4 => Box::new(BinaryDBConfiguration::new(load_aes_key(&arguments[3])?)),
+                #[cfg(not(feature = "binary"))]
+                4 => return usage(),
+                _ => Box::new(JsonDBConfiguration::for_data_folder(&arguments[0])?)
+            };
+            let problems = check_startup(&arguments[0], configuration.as_ref(), months)?;
+            for problem in &problems {
+                println!("{}", problem.describe());
+            }
+            if problems.is_empty() {
+                println!("No problems found");
+                Ok(())
+            } else {
+                Err(Error::new(ErrorKind::InvalidData, format!("{} problems found", problems.len())))
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "repair" => {
+            let dry_run = l == 4 && arguments[3] == "--dry-run";
+            if l != 3 && !dry_run {
+                usage()
+            } else {
+                let fixers = Fixer::parse_list(&arguments[2])?;
+                // dates fixer has to see operations stored in folders with impossible dates
+                let mut options = LoadOptions::new(1000000);
+                options.lenient_dates = true;
+                let db = load_db_with_options(&arguments[0], options)?;
+                let changes = db.repair(&fixers, dry_run)?;
+                for change in &changes {
+                    println!("{}", change.describe());
+                }
+                if dry_run {
+                    println!("{} changes found, nothing saved", changes.len());
+                    Ok(())
+                } else {
+                    println!("{} changes made", changes.len());
+                    db.flush(true)
+                }
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "dedupe" => {
+            if l != 2 {
+                usage()
+            } else {
+                let db = load_db(&arguments[0])?;
+                for d in db.check_duplicates()? {
+                    println!("{}", d.describe());
+                }
+                println!("{} duplicate operations removed", db.dedupe()?);
+                db.flush(true)
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "stats" => {
+            if l != 2 {
+                usage()
+            } else {
+                let db = load_db(&arguments[0])?;
+                db.stats()?;
+                #[cfg(feature = "server")]
+                for status in load_status(&arguments[0])? {
+                    println!("Maintenance {}", status.describe());
+                }
+                Ok(())
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "statement" => {
+            let account = if l >= 4 {arguments[2].parse().ok()} else {None};
+            let month = if l >= 4 {arguments[3].parse().ok()} else {None};
+            match (account, month) {
+                (Some(account), Some(month)) => {
+                    let db = load_db(&arguments[0])?;
+                    let statement = db.build_statement(AccountId(account), month)?;
+                    match (arguments.get(4).map(|f|f.as_str()).unwrap_or("text"), arguments.get(5)) {
+                        ("text", None) => print!("{}", statement.to_text()),
+                        ("csv", None) => print!("{}", statement.to_csv()),
+                        ("json", None) => println!("{}", statement.to_json()?),
+                        #[cfg(feature = "pdf")]
+                        ("pdf", Some(file_name)) => fs::write(file_name, statement_to_pdf(&statement))?,
+                        _ => return usage()
+                    }
+                    Ok(())
+                }
+                _ => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "query" => {
+            if l != 3 {
+                usage()
+            } else {
+                let query = OperationQuery::parse(&arguments[2])?;
+                let db = load_db(&arguments[0])?;
+                let page = db.query(&query)?;
+                for op in &page.operations {
+                    println!("{} {} {} {} {}", op.date, op.account_name, op.subcategory_name, op.formatted_summa, op.currency);
+                }
+                if page.operations.len() < page.total {
+                    println!("{} of {} operations shown from offset {}", page.operations.len(), page.total, query.offset);
+                } else {
+                    println!("{} operations found", page.total);
+                }
+                Ok(())
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "networks" => {
+            let from = if l == 4 {arguments[2].parse().ok()} else {None};
+            let to = if l == 4 {arguments[3].parse().ok()} else {None};
+            match (from, to) {
+                (Some(from), Some(to)) => {
+                    let db = load_db(&arguments[0])?;
+                    for ((month, network), item) in db.build_network_summary(from, to)? {
+                        if item.expenditure != 0 {
+                            println!("{}-{:02} {}: {}", month / 100, month % 100, network,
+                                     format_summa(item.expenditure));
+                        }
+                    }
+                    Ok(())
+                }
+                _ => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "anomalies" => {
+            let from = if l >= 4 {arguments[2].parse().ok()} else {None};
+            let to = if l >= 4 {arguments[3].parse().ok()} else {None};
+            let multiple = arguments.get(4).map(|m|m.parse().ok()).unwrap_or(Some(2.0));
+            match (from, to, multiple) {
+                (Some(from), Some(to), Some(multiple)) => {
+                    let db = load_db(&arguments[0])?;
+                    for a in db.build_anomalies_report(from, to, 3, multiple)? {
+                        println!("{}-{:02} {}: {} (trailing average {})", a.month / 100, a.month % 100,
+                                 db.get_categories().get(a.category)?.name, format_summa(a.spent),
+                                 format_summa(a.trailing_average));
+                    }
+                    Ok(())
+                }
+                _ => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "report" => {
+            if l < 3 {
+                return usage();
+            }
+            let period = if l >= 5 {
+                match (arguments[3].parse().ok(), arguments[4].parse().ok()) {
+                    (Some(from), Some(to)) => (Some(from), Some(to)),
+                    _ => return usage()
+                }
+            } else {
+                (None, None)
+            };
+            let format = match l {
+                4 => arguments[3].as_str(),
+                6 => arguments[5].as_str(),
+                _ => "text"
+            };
+            let db = load_db(&arguments[0])?;
+            let report = db.build_custom_report(&arguments[2], period.0, period.1)?;
+            match format {
+                "text" => print!("{}", report.to_text()),
+                "csv" => print!("{}", report.to_csv()),
+                _ => return usage()
+            }
+            Ok(())
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "reports" => {
+            let db = load_db(&arguments[0])?;
+            for spec in db.get_report_specs()? {
+                println!("{} {}", spec.name, spec.filter);
+            }
+            Ok(())
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "tax_report" => {
+            let year = if l == 3 || l == 4 {arguments[2].parse().ok()} else {None};
+            match year {
+                Some(year) => {
+                    let db = load_db(&arguments[0])?;
+                    let report = db.build_tax_report(year)?;
+                    match arguments.get(3).map(|f|f.as_str()).unwrap_or("text") {
+                        "text" => print!("{}", report.to_text()),
+                        "csv" => print!("{}", report.to_csv()),
+                        _ => return usage()
+                    }
+                    Ok(())
+                }
+                None => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "annual_report" => {
+            let year = if (3..=5).contains(&l) {arguments[2].parse().ok()} else {None};
+            match year {
+                Some(year) => {
+                    let db = load_db(&arguments[0])?;
+                    let report = db.build_annual_report(year)?;
+                    match (arguments.get(3).map(|f|f.as_str()).unwrap_or("text"), arguments.get(4)) {
+                        ("text", None) => print!("{}", report.to_text()),
+                        #[cfg(feature = "pdf")]
+                        ("pdf", Some(file_name)) => fs::write(file_name, annual_report_to_pdf(&report))?,
+                        _ => return usage()
+                    }
+                    Ok(())
+                }
+                None => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "html_report" => {
+            let from = if l == 5 {arguments[2].parse().ok()} else {None};
+            let to = if l == 5 {arguments[3].parse().ok()} else {None};
+            match (from, to) {
+                (Some(from), Some(to)) if from <= to => {
+                    let db = load_db(&arguments[0])?;
+                    fs::write(&arguments[4], db.build_html_report(from, to)?.to_html())
+                }
+                _ => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "export_gnucash" => {
+            if l != 3 {
+                usage()
+            } else {
+                let db = load_db(&arguments[0])?;
+                fs::write(&arguments[2], db.export_gnucash()?)
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "export_dictionary" => {
+            if l != 4 {
+                return usage();
+            }
+            let db = load_db(&arguments[0])?;
+            let csv = match arguments[2].as_str() {
+                "accounts" => accounts_to_csv(db.get_accounts().iter()),
+                "categories" => categories_to_csv(db.get_categories().iter()),
+                "subcategories" => subcategories_to_csv(db.get_subcategories().iter()),
+                _ => return usage()
+            };
+            fs::write(&arguments[3], csv)
+        }
+        #[cfg(all(feature = "fs", feature = "json", feature = "importers"))]
+        "import_dictionary" => {
+            if l != 4 || !["accounts", "categories", "subcategories"].contains(&arguments[2].as_str()) {
+                return usage();
+            }
+            let contents = fs::read_to_string(&arguments[3])?;
+            let mut db = load_db(&arguments[0])?;
+            let mut accounts: Vec<Account> = db.get_accounts().iter().cloned().collect();
+            let mut categories: Vec<Category> = db.get_categories().iter().cloned().collect();
+            let mut subcategories: Vec<Subcategory> = db.get_subcategories().iter().cloned().collect();
+            match arguments[2].as_str() {
+                "accounts" => accounts = merge_accounts(accounts, &contents)?,
+                "categories" => categories = merge_categories(categories, &contents)?,
+                _ => subcategories = merge_subcategories(subcategories, &contents)?
+            }
+            db.replace_dictionaries(accounts, categories, subcategories)?;
+            println!("{} imported", arguments[2]);
+            Ok(())
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "base_currency" => {
+            if l > 3 {
+                usage()
+            } else {
+                let mut db = load_db(&arguments[0])?;
+                if l == 3 {
+                    db.set_base_currency(Some(arguments[2].clone()))?;
+                }
+                println!("Base currency: {}", db.get_settings().base_currency.as_deref().unwrap_or("not set"));
+                Ok(())
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "warm_months" => {
+            let count = match arguments.get(2).map(|a|a.as_str()) {
+                None => None,
+                Some("off") => Some(None),
+                Some(count) => match count.parse() {
+                    Ok(count) => Some(Some(count)),
+                    Err(_) => return usage()
+                }
+            };
+            if l > 3 {
+                return usage();
+            }
+            let mut db = load_db(&arguments[0])?;
+            if let Some(count) = count {
+                db.set_warm_months(count)?;
+            }
+            match db.get_settings().warm_months {
+                Some(count) => println!("Most accessed months loaded on startup: {}", count),
+                None => println!("Cache warming is off")
+            }
+            for (month, count) in db.get_month_ranking().iter().take(12) {
+                println!("{} {}", month, count);
+            }
+            Ok(())
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "lock_months" => {
+            if l != 4 {
+                usage()
+            } else {
+                let months = parse_months(&arguments[2])?;
+                let db = load_db(&arguments[0])?;
+                db.lock_months(&months, &arguments[3])?;
+                println!("{} months locked", months.len());
+                Ok(())
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "unlock_months" => {
+            let force = arguments.get(4).map(|a|a.as_str());
+            if !(4..=5).contains(&l) || force.is_some_and(|f|f != "--force") {
+                usage()
+            } else {
+                let months = parse_months(&arguments[2])?;
+                let db = load_db(&arguments[0])?;
+                db.unlock_months(&months, &arguments[3], force.is_some())
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "month_locks" => {
+            let db = load_db(&arguments[0])?;
+            for lock in db.get_month_locks()? {
+                println!("{} {} since {}", lock.month, lock.owner, lock.since);
+            }
+            Ok(())
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "month_versions" => {
+            let period = match l {
+                2 => Some((0, u64::MAX)),
+                4 => arguments[2].parse().ok().zip(arguments[3].parse().ok()),
+                _ => None
+            };
+            if let Some((from, to)) = period {
+                let db = load_db(&arguments[0])?;
+                for (month, version) in db.get_month_versions(from, to) {
+                    println!("{} {}", month, version);
+                }
+                Ok(())
+            } else {
+                usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "slow_log" => {
+            let threshold = match arguments.get(2).map(|a|a.as_str()) {
+                None => None,
+                Some("off") => Some(None),
+                Some(ms) => match ms.parse() {
+                    Ok(ms) => Some(Some(ms)),
+                    Err(_) => return usage()
+                }
+            };
+            if l > 3 {
+                return usage();
+            }
+            let mut db = load_db(&arguments[0])?;
+            if let Some(threshold) = threshold {
+                db.set_slow_operation_threshold(threshold)?;
+            }
+            match db.get_settings().slow_operation_ms {
+                Some(ms) => println!("Slow operation threshold: {} ms", ms),
+                None => println!("Slow operation log is off")
+            }
+            Ok(())
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "day_boundary" => {
+            let boundary: Option<Option<(i64, u64)>> = match l {
+                2 => Some(None),
+                4 => arguments[2].parse().ok().zip(arguments[3].parse().ok()).map(Some),
+                _ => None
+            };
+            match boundary {
+                None => usage(),
+                Some(boundary) => {
+                    let mut db = load_db(&arguments[0])?;
+                    if let Some((utc_offset_minutes, day_start_hour)) = boundary {
+                        db.set_day_boundary(utc_offset_minutes, day_start_hour)?;
+                    }
+                    let boundary = db.get_settings().day_boundary();
+                    println!("UTC offset: {} minutes, day starts at {}:00", boundary.utc_offset_minutes, boundary.day_start_hour);
+                    Ok(())
+                }
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "net_worth" => {
+            let date = if l == 3 {arguments[2].parse().ok()} else {None};
+            match date {
+                Some(date) => {
+                    let db = load_db(&arguments[0])?;
+                    let net_worth = db.build_net_worth(date)?;
+                    for (currency, total) in &net_worth.totals {
+                        println!("{}: {}", currency, format_summa(*total));
+                    }
+                    if let (Some(total), Some(base)) = (net_worth.base_total, &db.get_settings().base_currency) {
+                        println!("Total in {}: {}", base, format_summa(total));
+                    }
+                    Ok(())
+                }
+                None => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "balance_snapshots" => {
+            let dates: Option<Vec<u64>> = if l == 3 {arguments[2].split(',').map(|d|d.parse().ok()).collect()} else {None};
+            match dates {
+                Some(dates) => {
+                    let db = load_db(&arguments[0])?;
+                    let snapshots = db.get_balance_snapshots(&dates)?;
+                    for account in db.get_accounts().ordered()? {
+                        let balances: Vec<String> = snapshots.values()
+                            .map(|b|format_summa(b.get(&account.id).copied().unwrap_or(0)))
+                            .collect();
+                        println!("{}: {}", account.name, balances.join(" "));
+                    }
+                    Ok(())
+                }
+                None => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "budget" => {
+            let month = if l == 3 {arguments[2].parse().ok()} else {None};
+            match month {
+                Some(month) => {
+                    let db = load_db(&arguments[0])?;
+                    for status in db.build_budget_report(month)? {
+                        println!("{}: budget {}, carried {}, spent {}, remaining {}",
+                                 db.get_subcategories().get(status.subcategory)?.name, format_summa(status.amount),
+                                 format_summa(status.carried), format_summa(status.spent), format_summa(status.remaining));
+                    }
+                    Ok(())
+                }
+                None => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "what_if" => {
+            if !(6..=7).contains(&l) {
+                return usage();
+            }
+            let values = (arguments[2].parse().ok(), arguments[3].parse().ok(), arguments[4].parse().ok(),
+                          arguments.get(6).map(|m|m.parse().ok()).unwrap_or(Some(1)));
+            let (Some(date), Some(account), Some(subcategory), Some(months)) = values else { return usage() };
+            let db = load_db(&arguments[0])?;
+            let currency = db.get_account_currency(AccountId(account), date)?;
+            let mut scenario = Scenario::new();
+            scenario.add_monthly(FinanceOperation::new(date, AccountId(account), SubcategoryId(subcategory), None,
+                                                       currency.parse(&arguments[5])?, Vec::new()), months);
+            let last = scenario.iter().map(|op|op.date).max().unwrap_or(date);
+            for status in db.build_scenario_budget_report(&scenario, last / 100)? {
+                println!("{}: budget {}, carried {}, spent {}, remaining {}",
+                         db.get_subcategories().get(status.subcategory)?.name, format_summa(status.amount),
+                         format_summa(status.carried), format_summa(status.spent), format_summa(status.remaining));
+            }
+            let balances = db.build_scenario_balances(&scenario, last)?;
+            let balance = balances.get(AccountId(account)).map(|c|c.get_end_balance()).unwrap_or(0);
+            println!("{} balance at {}: {}", db.get_accounts().get(AccountId(account))?.name, last,
+                     db.get_account_currency(AccountId(account), last)?.format(balance));
+            Ok(())
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "over_limit" => {
+            let month = if l == 3 {arguments[2].parse().ok()} else {None};
+            match month {
+                Some(month) => {
+                    let db = load_db(&arguments[0])?;
+                    for status in db.build_over_limit_report(month)? {
+                        println!("{}", status.describe(db.get_subcategories())?);
+                    }
+                    Ok(())
+                }
+                None => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "spending_limits" => {
+            let limit = |a: &String|if a == "off" {Ok(None)} else {parse_summa(a).map(Some)};
+            let id = if l == 5 {arguments[2].parse().ok()} else {None};
+            match id {
+                Some(id) => {
+                    let (soft_limit, hard_limit) = (limit(&arguments[3])?, limit(&arguments[4])?);
+                    let mut db = load_db(&arguments[0])?;
+                    db.set_spending_limits(SubcategoryId(id), soft_limit, hard_limit)
+                }
+                None => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "add_op" => {
+            if l < 4 {
+                return usage();
+            }
+            let db = load_db(&arguments[0])?;
+            let alerts = subscribe_alerts(&db);
+            let today = db.get_clock().today();
+            let over_limit = if arguments[2] == "--template" {
+                let currency = db.get_account_currency(db.get_templates().get(&arguments[3])?.account, today)?;
+                let summa = arguments.get(4).map(|s|currency.parse(s)).transpose()?;
+                db.add_from_template(&arguments[3], today, summa)?
+            } else {
+                let ids = (arguments[2].parse().ok(), arguments[3].parse().ok(), arguments.get(4));
+                let (Some(account), Some(subcategory), Some(summa)) = ids else { return usage() };
+                let summa = db.get_account_currency(AccountId(account), today)?.parse(summa)?;
+                db.add_operation_checked(FinanceOperation::new(today, AccountId(account), SubcategoryId(subcategory), None,
+                                                               summa, Vec::new()))?
+            };
+            if let Some(status) = over_limit {
+                println!("Warning: {}", status.describe(db.get_subcategories())?);
+            }
+            for event in alerts.try_iter() {
+                if let EventKind::BalanceAlert(alert) = event.kind {
+                    println!("{}", alert.describe(db.get_accounts())?);
+                }
+            }
+            db.flush(false)
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "validation_rules" => {
+            let db = load_db(&arguments[0])?;
+            for rule in db.get_validation_rules().iter() {
+                println!("{}", rule.name);
+            }
+            Ok(())
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "recategorize" => {
+            let from = if l >= 4 {arguments[2].parse().ok()} else {None};
+            let to = if l >= 4 {arguments[3].parse().ok()} else {None};
+            match (from, to) {
+                (Some(from), Some(to)) => {
+                    let query = OperationQuery::parse(arguments.get(4).map(|q|q.as_str()).unwrap_or(""))?;
+                    let db = load_db(&arguments[0])?;
+                    let changed = db.recategorize(&query, SubcategoryId(from), SubcategoryId(to))?;
+                    println!("{} operations moved", changed);
+                    db.flush(true)
+                }
+                _ => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "merge_subcategories" => {
+            let source = if l == 4 {arguments[2].parse().ok()} else {None};
+            let target = if l == 4 {arguments[3].parse().ok()} else {None};
+            match (source, target) {
+                (Some(source), Some(target)) => {
+                    let mut db = load_db(&arguments[0])?;
+                    let moved = db.merge_subcategories(SubcategoryId(source), SubcategoryId(target))?;
+                    println!("{} operations moved", moved);
+                    Ok(())
+                }
+                _ => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "archive_accounts" => {
+            match if l == 3 {arguments[2].parse().ok()} else {None} {
+                Some(before) => {
+                    let mut db = load_db(&arguments[0])?;
+                    let archived = db.archive_accounts(before)?;
+                    println!("{} accounts archived", archived);
+                    Ok(())
+                }
+                None => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "change_currency" => {
+            if l != 6 {
+                return usage();
+            }
+            let (Ok(account), Ok(month)) = (arguments[2].parse(), arguments[3].parse()) else { return usage() };
+            let rate = parse_rate(&arguments[5])?;
+            let mut db = load_db(&arguments[0])?;
+            db.change_account_currency(AccountId(account), month, arguments[4].clone(), rate)?;
+            db.flush(false)
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "retire" | "restore_retired" => {
+            let id = if l == 4 {arguments[3].parse().ok()} else {None};
+            let retire = arguments[1] == "retire";
+            match (arguments.get(2).map(|a|a.as_str()), id) {
+                (Some("account"), Some(id)) => {
+                    let mut db = load_db(&arguments[0])?;
+                    if retire {db.retire_account(AccountId(id))} else {db.restore_account(AccountId(id))}
+                }
+                (Some("subcategory"), Some(id)) => {
+                    let mut db = load_db(&arguments[0])?;
+                    if retire {db.retire_subcategory(SubcategoryId(id))} else {db.restore_subcategory(SubcategoryId(id))}
+                }
+                _ => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "retired" => {
+            if l != 2 {
+                usage()
+            } else {
+                let db = load_db(&arguments[0])?;
+                for account in db.get_retired_accounts() {
+                    println!("account {} {}", account.id, account.name);
+                }
+                for subcategory in db.get_retired_subcategories() {
+                    println!("subcategory {} {}", subcategory.id, subcategory.name);
+                }
+                Ok(())
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "split_subcategory" => {
+            let source = if l >= 4 {arguments[2].parse().ok()} else {None};
+            let rules: Option<Vec<(String, SubcategoryId)>> = if l >= 4 {
+                arguments[3].split(',')
+                    .map(|r|r.rsplit_once('=').and_then(|(t, id)|Some((t.to_string(), SubcategoryId(id.parse().ok()?)))))
+                    .collect()
+            } else {
+                None
+            };
+            // None when default is given but invalid
+            let default = match arguments.get(4) {
+                Some(d) => d.parse().ok().map(|d|Some(SubcategoryId(d))),
+                None => Some(None)
+            };
+            match (source, rules, default) {
+                (Some(source), Some(rules), Some(default)) => {
+                    let mut db = load_db(&arguments[0])?;
+                    let moved = db.split_subcategory(SubcategoryId(source), &rules, default)?;
+                    println!("{} operations moved", moved);
+                    db.flush(false)
+                }
+                _ => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "memory" => {
+            let max_active_items = if l == 3 {arguments[2].parse().ok()} else {Some(usize::MAX)};
+            match max_active_items {
+                Some(max_active_items) if l <= 3 => {
+                    let db = load_db(&arguments[0])?;
+                    let report = db.memory_usage()?;
+                    println!("Months: {}, operations: {}", report.months, report.operations);
+                    println!("Average month: {} bytes, largest month: {} bytes", report.average_month_bytes(),
+                             report.max_month_bytes);
+                    println!("Dictionaries: {} bytes, parameter strings: {} bytes", report.dictionaries_bytes,
+                             report.strings_bytes);
+                    println!("Estimated memory for {} cached months: {} bytes", max_active_items.min(report.months),
+                             report.estimate(max_active_items));
+                    Ok(())
+                }
+                _ => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "bench" => {
+            let years = if l == 3 {arguments[2].parse().ok()} else {None};
+            match years {
+                None => usage(),
+                Some(years) => {
+                    let start = Instant::now();
+                    let count = generate_json(&arguments[0], &GeneratorOptions::new(years))?;
+                    println!("{} operations generated in {} ms", count, start.elapsed().as_millis());
+                    let db = load_db(&arguments[0])?;
+                    db.bench()
+                }
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "generate" => {
+            if l != 3 {
+                usage()
+            } else {
+                let count = generate(&arguments[0], &GeneratorOptions::parse(&arguments[2])?)?;
+                println!("{} operations generated", count);
+                Ok(())
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "anonymize" => {
+            if l != 3 {
+                usage()
+            } else {
+                // random factors of amounts must not be reproducible
+                let seed = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+                    .map(|d|d.as_nanos() as u64).unwrap_or_default();
+                let count = anonymize(&arguments[0], &arguments[2], seed)?;
+                println!("{} files written", count);
+                Ok(())
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "test_book" => {
+            if l != 4 {
+                usage()
+            } else {
+                let mut books = Books::load_json(&arguments[0])?;
+                #[cfg(feature = "encryption")]
+                if books.is_locked(&arguments[2]) {
+                    books.unlock(&arguments[2], &read_passphrase("Passphrase")?)?;
+                }
+                books.get_mut(&arguments[2])?.test(arguments[3].clone())
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
         "test_lru" => {
             if l != 2 {
                 usage()
             } else {
-                let mut db = HomeAccountingDB::new(arguments[0].clone(), Box::new(JsonDBConfiguration::new()), 500)?;
+                let db = HomeAccountingDB::new(arguments[0].clone(), Box::new(JsonDBConfiguration::for_data_folder(&arguments[0])?), 500)?;
                 db.test_lru(1000)
             }
         }
+        #[cfg(feature = "binary")]
         "test" => {
             if l != 4 {
                 usage()
             } else {
-                let mut db = HomeAccountingDB::load(arguments[0].clone(), Box::new(BinaryDBConfiguration::new(aes_key)), 1000000)?;
+                let db = HomeAccountingDB::load(arguments[0].clone(), Box::new(BinaryDBConfiguration::new(load_aes_key(&arguments[3])?)), 1000000)?;
                 db.test(arguments[2].clone())
             }
         }
+        #[cfg(all(feature = "fs", feature = "json"))]
         "migrate" => {
+            let configuration: Box<dyn DBConfiguration> = match l {
+                3 => Box::new(JsonDBConfiguration::for_data_folder(&arguments[0])?),
+                #[cfg(feature = "binary")]
+                4 => Box::new(BinaryDBConfiguration::new(load_aes_key(&arguments[3])?)),
+                _ => return usage()
+            };
+            let db = HomeAccountingDB::load(arguments[2].clone(), Box::new(JsonDBConfiguration::for_data_folder(&arguments[2])?), 1000000)?;
+            let count = db.migrate(arguments[0].clone(), configuration)?;
+            println!("{} operations migrated, manifest written to {}", count, MANIFEST_FILE_NAME);
+            Ok(())
+        }
+        #[cfg(feature = "encryption")]
+        "encrypt" => {
+            if l != 3 {
+                usage()
+            } else {
+                let passphrase = read_passphrase("Passphrase")?;
+                if read_passphrase("Repeat passphrase")? != passphrase {
+                    return Err(Error::new(ErrorKind::InvalidInput, "passphrases don't match"));
+                }
+                // every file has to be readable before it is encrypted
+                load_db(&arguments[0])?;
+                let crypto = create_domain(&arguments[0], &arguments[2], &passphrase)?;
+                println!("{} files encrypted", encrypt_folder(&arguments[0], &crypto)?);
+                Ok(())
+            }
+        }
+        #[cfg(feature = "encryption")]
+        "change_passphrase" => {
+            if l != 3 {
+                usage()
+            } else {
+                let passphrase = read_passphrase("Passphrase")?;
+                let new_passphrase = read_passphrase("New passphrase")?;
+                change_passphrase(&arguments[0], &arguments[2], &passphrase, &new_passphrase)
+            }
+        }
+        #[cfg(all(feature = "server", feature = "json"))]
+        "send_report" => {
+            let month = if l == 4 {arguments[3].parse().ok()} else {None};
+            match month {
+                None => usage(),
+                Some(month) => {
+                    let configuration = ServerConfiguration::load(&arguments[2])?;
+                    let reports = configuration.reports
+                        .ok_or(Error::new(ErrorKind::InvalidInput, "reports are not configured"))?;
+                    let db = load_db(&arguments[0])?;
+                    let report = db.build_monthly_report(month)?;
+                    for notifier in &reports.notifiers {
+                        notifier.create()?.notify(&report.subject(), &report.to_text())?;
+                    }
+                    Ok(())
+                }
+            }
+        }
+        #[cfg(feature = "telegram")]
+        "telegram" => {
+            if l != 3 {
+                usage()
+            } else {
+                let server_configuration = ServerConfiguration::load(&arguments[2])?;
+                let alert_notifiers = server_configuration.create_alert_notifiers()?;
+                let configuration = server_configuration.telegram
+                    .ok_or(Error::new(ErrorKind::InvalidInput, "telegram bot is not configured"))?;
+                let mut db = load_db(&arguments[0])?;
+                let mut alerts = subscribe_alerts(&db);
+                let mut bot = TelegramBot::new(&configuration, &db)?;
+                loop {
+                    if let Err(e) = bot.poll(&mut db) {
+                        println!("telegram error: {}", e);
+                        thread::sleep(Duration::from_secs(10));
+                    }
+                    send_alerts(&db, &mut alerts, &alert_notifiers)?;
+                }
+            }
+        }
+        #[cfg(all(feature = "server", feature = "json", feature = "importers"))]
+        "watch" => {
+            if l != 3 {
+                usage()
+            } else {
+                let server_configuration = ServerConfiguration::load(&arguments[2])?;
+                let alert_notifiers = server_configuration.create_alert_notifiers()?;
+                let configuration = server_configuration.watch
+                    .ok_or(Error::new(ErrorKind::InvalidInput, "watch folder is not configured"))?;
+                let notifiers = configuration.notifiers.iter().map(|n|n.create()).collect::<Result<Vec<_>, _>>()?;
+                let mut db = load_db(&arguments[0])?;
+                let mut alerts = subscribe_alerts(&db);
+                let today = db.get_clock().today();
+                let pipeline = ImportPipeline::new(AccountId(configuration.account),
+                                                   SubcategoryId(configuration.income_subcategory),
+                                                   SubcategoryId(configuration.expense_subcategory),
+                                                   db.build_suggester(today.saturating_sub(10000), today)?);
+                let mut watcher = FolderWatcher::new(configuration.folder, pipeline);
+                loop {
+                    for (file, result) in watcher.scan(&mut db)? {
+                        let text = match result {
+                            Ok(summary) => format!("{}: {}", file, summary.describe()),
+                            Err(e) => format!("{}: import failed: {}", file, e)
+                        };
+                        println!("{}", text);
+                        for notifier in &notifiers {
+                            if let Err(e) = notifier.notify("Statement import", &text) {
+                                println!("notification error: {}", e);
+                            }
+                        }
+                    }
+                    send_alerts(&db, &mut alerts, &alert_notifiers)?;
+                    thread::sleep(Duration::from_secs(configuration.interval));
+                }
+            }
+        }
+        #[cfg(all(feature = "server", feature = "json"))]
+        "maintenance" => {
+            if l != 3 {
+                usage()
+            } else {
+                let configuration = ServerConfiguration::load(&arguments[2])?;
+                if configuration.maintenance.is_none() && configuration.reports.is_none() {
+                    return Err(Error::new(ErrorKind::InvalidInput, "neither maintenance nor reports are configured"));
+                }
+                let mut maintenance = configuration.maintenance.as_ref()
+                    .map(|c|MaintenanceScheduler::from_configuration(c, &arguments[0])).transpose()?;
+                let mut reports = configuration.reports.as_ref()
+                    .map(|c|ReportScheduler::from_configuration(c, &arguments[0])).transpose()?;
+                let mut db = load_db(&arguments[0])?;
+                loop {
+                    if let Some(scheduler) = &mut maintenance {
+                        for status in scheduler.run(&mut db, &arguments[0])? {
+                            println!("{}", status.describe());
+                        }
+                    }
+                    // delivery is retried on the next round when every notifier failed
+                    match reports.as_mut().map(|s|s.run(&db, &arguments[0])) {
+                        Some(Ok(Some(month))) => println!("report for {} sent", month),
+                        Some(Err(e)) => println!("report error: {}", e),
+                        _ => {}
+                    }
+                    thread::sleep(Duration::from_secs(60));
+                }
+            }
+        }
+        #[cfg(all(feature = "server", feature = "monobank"))]
+        "bank_sync" => {
+            if l != 3 {
+                usage()
+            } else {
+                let server_configuration = ServerConfiguration::load(&arguments[2])?;
+                let alert_notifiers = server_configuration.create_alert_notifiers()?;
+                let configuration = server_configuration.bank
+                    .ok_or(Error::new(ErrorKind::InvalidInput, "bank is not configured"))?;
+                let mut db = load_db(&arguments[0])?;
+                let mut alerts = subscribe_alerts(&db);
+                let today = db.get_clock().today();
+                let mut pipeline = ImportPipeline::new(AccountId(configuration.account),
+                                                       SubcategoryId(configuration.income_subcategory),
+                                                       SubcategoryId(configuration.expense_subcategory),
+                                                       db.build_suggester(today.saturating_sub(10000), today)?);
+                let mut connector = MonobankConnector::new(configuration.token, db.get_settings().day_boundary());
+                connector.authenticate()?;
+                let cursor = fs::read_to_string(&configuration.cursor_file).ok().map(|c|c.trim().to_string());
+                let (summary, cursor) = sync_account(&connector, &configuration.bank_account, cursor,
+                                                     &mut pipeline, &mut db)?;
+                db.flush(false)?;
+                send_alerts(&db, &mut alerts, &alert_notifiers)?;
+                if let Some(cursor) = cursor {
+                    fs::write(&configuration.cursor_file, cursor)?;
+                }
+                println!("{}", summary.describe());
+                Ok(())
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "archive" => {
+            let years = if l == 3 {arguments[2].parse().ok()} else {None};
+            match years {
+                None => usage(),
+                Some(years) => {
+                    let mut db = load_db(&arguments[0])?;
+                    println!("{} date folders archived", db.archive_older_than(years)?);
+                    Ok(())
+                }
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json", feature = "importers"))]
+        "import" => {
+            let ids: Option<Vec<u64>> = if l == 6 {arguments[3..].iter().map(|a|a.parse().ok()).collect()} else {None};
+            match ids {
+                Some(ids) => {
+                    let db = load_db(&arguments[0])?;
+                    let transactions = parse_file(&arguments[2], &fs::read_to_string(&arguments[2])?,
+                                                  &db.get_settings().day_boundary())?;
+                    let today = db.get_clock().today();
+                    let mut pipeline = ImportPipeline::new(AccountId(ids[0]), SubcategoryId(ids[1]), SubcategoryId(ids[2]),
+                                                           db.build_suggester(today.saturating_sub(10000), today)?);
+                    let preview = pipeline.preview(&db, &transactions)?;
+                    for (i, item) in preview.items.iter().enumerate() {
+                        println!("{}. {}", i + 1, item.describe(db.get_subcategories())?);
+                    }
+                    print!("Operations to import (all, none or numbers like 1,3-5): ");
+                    stdout().flush()?;
+                    let mut answer = String::new();
+                    stdin().read_line(&mut answer)?;
+                    let selected = preview.select(&answer)?;
+                    let summary = pipeline.accept(&db, preview, &selected)?;
+                    db.flush(false)?;
+                    println!("{}", summary.describe());
+                    Ok(())
+                }
+                None => usage()
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json", feature = "importers"))]
+        "import_book" => {
+            if l != 3 {
+                usage()
+            } else {
+                let book = parse_book(&arguments[2], &fs::read(&arguments[2])?)?;
+                for message in &book.skipped {
+                    println!("Skipped {}", message);
+                }
+                let converted = book.convert()?;
+                let operations = converted.operations.len();
+                converted.create_database(arguments[0].clone(), Box::new(JsonDBConfiguration::new()), 1000000)?;
+                println!("{} operations imported", operations);
+                Ok(())
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json", feature = "importers"))]
+        "import_rates" => {
+            if l != 3 && l != 4 {
+                usage()
+            } else {
+                let base = arguments.get(3).map(|b|b.as_str()).unwrap_or("EUR");
+                let rates = parse_rates_csv(&fs::read_to_string(&arguments[2])?, base)?;
+                let mut db = load_db(&arguments[0])?;
+                let (rates, skipped) = filter_known(rates, db.get_currencies())?;
+                let count = rates.len();
+                db.add_rates(rates)?;
+                println!("{} rates imported", count);
+                if !skipped.is_empty() {
+                    println!("Skipped unknown currencies: {}", skipped.into_iter().collect::<Vec<_>>().join(", "));
+                }
+                Ok(())
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "backup" => {
+            if l != 3 && !(l == 4 && arguments[3] == "--verify") {
+                usage()
+            } else {
+                let db = load_db(&arguments[0])?;
+                db.backup(&arguments[2])?;
+                println!("Backup {} created", arguments[2]);
+                if l == 4 {check_backup(&db, &arguments[0], &arguments[2])} else {Ok(())}
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "verify_backup" => {
+            if l != 3 {
+                usage()
+            } else {
+                let db = load_db(&arguments[0])?;
+                check_backup(&db, &arguments[0], &arguments[2])
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "checkpoint" => {
+            if l != 2 {
+                usage()
+            } else {
+                let db = load_db(&arguments[0])?;
+                println!("Checkpoint {} created", db.create_checkpoint()?);
+                Ok(())
+            }
+        }
+        #[cfg(all(feature = "fs", feature = "json"))]
+        "restore" => {
+            let at: Option<u64> = if l == 5 && arguments[2] == "--at" {arguments[3].parse().ok()} else {None};
+            match at {
+                None => usage(),
+                Some(at) => {
+                    let count = HomeAccountingDB::restore(&arguments[0], arguments[4].clone(), at * 1000 + 999,
+                                                          Box::new(JsonDBConfiguration::for_data_folder(&arguments[0])?),
+                                                          1000000)?;
+                    println!("{} operations replayed", count);
+                    Ok(())
+                }
+            }
+        }
+        #[cfg(all(feature = "server", feature = "json"))]
+        "replicate" => {
             if l != 4 {
                 usage()
             } else {
-                let db = HomeAccountingDB::load(arguments[2].clone(), Box::new(JsonDBConfiguration::new()), 1000000)?;
-                db.migrate(arguments[0].clone())
+                let crypto = Box::new(AesGcmProcessor::new(&load_aes_key(&arguments[3])?));
+                let mut db = load_db(&arguments[0])?;
+                db.set_replica(arguments[2].clone(), crypto);
+                println!("{} files sent", db.replicate_all()?);
+                Ok(())
             }
         }
-        "server" => {
+        #[cfg(all(feature = "server", feature = "json"))]
+        "standby" => {
             if l != 4 {
                 usage()
             } else {
-                todo!()
+                let crypto = Box::new(AesGcmProcessor::new(&load_aes_key(&arguments[3])?));
+                let listener = TcpListener::bind(format!("0.0.0.0:{}", arguments[2]))?;
+                let receiver = ReplicationReceiver::new(arguments[0].clone(), crypto);
+                receiver.run(listener)
+            }
+        }
+        #[cfg(all(feature = "server", feature = "json"))]
+        "server" => {
+            if l != 4 {
+                return usage();
+            }
+            let crypto = Box::new(AesGcmProcessor::new(&load_aes_key(&arguments[3])?));
+            let configuration = JsonDBConfiguration::for_data_folder(&arguments[0])?;
+            let problems = check_startup(&arguments[0], &configuration, STARTUP_CHECK_MONTHS)?;
+            if !problems.is_empty() {
+                for problem in &problems {
+                    println!("{}", problem.describe());
+                }
+                return Err(Error::new(ErrorKind::InvalidData, format!("startup check found {} problems", problems.len())));
             }
+            let mut db = HomeAccountingDB::load(arguments[0].clone(), Box::new(configuration), 1000000)?;
+            db.enable_wal();
+            let listener = TcpListener::bind(format!("0.0.0.0:{}", arguments[2]))?;
+            RequestServer::new(db, crypto).run(listener)
         }
         _ => usage()
     }
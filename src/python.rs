@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+use std::io::Error;
+use pyo3::exceptions::PyIOError;
+use pyo3::prelude::*;
+use crate::db::HomeAccountingDB;
+use crate::entities::finance_operations::FinanceOperation;
+use crate::json_db_config::JsonDBConfiguration;
+use crate::query::{OperationQuery, OperationView};
+use crate::reports::summary::SummaryItem;
+
+fn to_py_err(e: Error) -> PyErr {
+    PyIOError::new_err(e.to_string())
+}
+
+#[pyclass(get_all)]
+pub struct Operation {
+    date: u64,
+    account: u64,
+    subcategory: u64,
+    amount: Option<u64>,
+    summa: i64
+}
+
+impl From<&FinanceOperation> for Operation {
+    fn from(op: &FinanceOperation) -> Self {
+        Operation{date: op.date, account: op.get_account().0, subcategory: op.get_subcategory().0,
+            amount: op.get_amount(), summa: op.get_summa()}
+    }
+}
+
+/// Query result row with names of referenced dictionary entries.
+#[pyclass(get_all)]
+pub struct QueryRow {
+    date: u64,
+    account: u64,
+    account_name: String,
+    currency: String,
+    category: u64,
+    category_name: String,
+    subcategory: u64,
+    subcategory_name: String,
+    summa: i64,
+    formatted_summa: String
+}
+
+impl From<&OperationView> for QueryRow {
+    fn from(v: &OperationView) -> Self {
+        QueryRow{date: v.date, account: v.account.0, account_name: v.account_name.clone(), currency: v.currency.clone(),
+            category: v.category.0, category_name: v.category_name.clone(), subcategory: v.subcategory.0,
+            subcategory_name: v.subcategory_name.clone(), summa: v.summa, formatted_summa: v.formatted_summa.clone()}
+    }
+}
+
+#[pyclass(get_all)]
+pub struct Balance {
+    start_balance: i64,
+    income: i64,
+    expenditure: i64,
+    end_balance: i64
+}
+
+#[pyclass(get_all)]
+pub struct Summary {
+    income: i64,
+    expenditure: i64
+}
+
+impl From<&SummaryItem> for Summary {
+    fn from(s: &SummaryItem) -> Self {
+        Summary{income: s.income, expenditure: s.expenditure}
+    }
+}
+
+#[pyclass(name = "HomeAccountingDB")]
+pub struct PyHomeAccountingDB {
+    db: HomeAccountingDB
+}
+
+#[pymethods]
+impl PyHomeAccountingDB {
+    #[staticmethod]
+    #[pyo3(signature = (data_folder_path, max_active_items = 1000000))]
+    fn load_json(data_folder_path: String, max_active_items: usize) -> PyResult<PyHomeAccountingDB> {
+        let db = HomeAccountingDB::load(data_folder_path, Box::new(JsonDBConfiguration::new()), max_active_items)
+            .map_err(to_py_err)?;
+        Ok(PyHomeAccountingDB{db})
+    }
+
+    /// Reloads accounts, categories and subcategories if their files were changed, returns true if reloaded.
+    fn reload_if_changed(&mut self) -> PyResult<bool> {
+        self.db.reload_if_changed().map_err(to_py_err)
+    }
+
+    /// Reads of more months than the cache holds bypass it, so notebooks scanning all years don't evict
+    /// months of interactive queries.
+    fn set_scan_cache_bypass(&mut self, bypass: bool) {
+        self.db.set_scan_cache_bypass(bypass);
+    }
+
+    fn accounts(&self) -> BTreeMap<u64, String> {
+        self.db.get_accounts().iter().map(|a|(a.id.0, a.name.clone())).collect()
+    }
+
+    fn categories(&self) -> BTreeMap<u64, String> {
+        self.db.get_categories().iter().map(|c|(c.id.0, c.name.clone())).collect()
+    }
+
+    fn subcategories(&self) -> BTreeMap<u64, String> {
+        self.db.get_subcategories().iter().map(|s|(s.id.0, s.name.clone())).collect()
+    }
+
+    fn operations(&self, from: u64, to: u64) -> PyResult<Vec<Operation>> {
+        let ops = self.db.get_operations(from, to).map_err(to_py_err)?;
+        Ok(ops.iter().map(Operation::from).collect())
+    }
+
+    /// Filters are the same as of the query command, returns requested page and number of all matching operations.
+    fn query(&self, filters: &str) -> PyResult<(Vec<QueryRow>, usize)> {
+        let query = OperationQuery::parse(filters).map_err(to_py_err)?;
+        let page = self.db.query(&query).map_err(to_py_err)?;
+        Ok((page.operations.iter().map(QueryRow::from).collect(), page.total))
+    }
+
+    /// Versions of changed months between from and to dates by yyyymm, cached operations of a month are
+    /// stale when its version differs.
+    fn month_versions(&self, from: u64, to: u64) -> BTreeMap<u64, u64> {
+        self.db.get_month_versions(from, to)
+    }
+
+    fn balances(&mut self, date: u64) -> PyResult<BTreeMap<u64, Balance>> {
+        let changes = self.db.get_active_balances(date).map_err(to_py_err)?;
+        Ok(changes.iter().map(|(account, change)|(account.0, Balance{
+            start_balance: change.get_start_balance(),
+            income: change.get_income(),
+            expenditure: change.get_expenditure(),
+            end_balance: change.get_end_balance()
+        })).collect())
+    }
+
+    /// End of day balances of all accounts by date and account, every month is loaded once.
+    fn balance_snapshots(&self, dates: Vec<u64>) -> PyResult<BTreeMap<u64, BTreeMap<u64, i64>>> {
+        let snapshots = self.db.get_balance_snapshots(&dates).map_err(to_py_err)?;
+        Ok(snapshots.into_iter()
+            .map(|(date, balances)|(date, balances.into_iter().map(|(account, summa)|(account.0, summa)).collect()))
+            .collect())
+    }
+
+    fn category_summary(&self, from: u64, to: u64) -> PyResult<BTreeMap<u64, Summary>> {
+        let summary = self.db.build_category_summary(from, to).map_err(to_py_err)?;
+        Ok(summary.iter().map(|(id, s)|(id.0, Summary::from(s))).collect())
+    }
+
+    fn subcategory_summary(&self, from: u64, to: u64) -> PyResult<BTreeMap<u64, Summary>> {
+        let summary = self.db.build_subcategory_summary(from, to).map_err(to_py_err)?;
+        Ok(summary.iter().map(|(id, s)|(id.0, Summary::from(s))).collect())
+    }
+
+    /// Custom report from the reports file, returns column names and rows of formatted values.
+    #[pyo3(signature = (name, from_date = None, to_date = None))]
+    fn custom_report(&self, name: &str, from_date: Option<u64>, to_date: Option<u64>)
+        -> PyResult<(Vec<String>, Vec<Vec<String>>)> {
+        let report = self.db.build_custom_report(name, from_date, to_date).map_err(to_py_err)?;
+        Ok((report.columns, report.rows))
+    }
+
+    /// Summary by (yyyymm, NETW) pairs.
+    fn network_summary(&self, from: u64, to: u64) -> PyResult<BTreeMap<(u64, String), Summary>> {
+        let summary = self.db.build_network_summary(from, to).map_err(to_py_err)?;
+        Ok(summary.iter().map(|(key, s)|(key.clone(), Summary::from(s))).collect())
+    }
+}
+
+#[pymodule]
+fn homeaccounting(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyHomeAccountingDB>()?;
+    m.add_class::<Operation>()?;
+    m.add_class::<QueryRow>()?;
+    m.add_class::<Balance>()?;
+    m.add_class::<Summary>()?;
+    Ok(())
+}
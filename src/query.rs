@@ -0,0 +1,227 @@
+//! Operation search filters, sorting and paging of results.
+
+use std::io::{Error, ErrorKind};
+use serde::{Deserialize, Serialize};
+use crate::core::amounts::{format_summa, parse_summa};
+use crate::entities::accounts::{AccountId, Accounts};
+use crate::entities::finance_operations::FinanceOperation;
+use crate::entities::subcategories::{Categories, CategoryId, Subcategories, SubcategoryId, SubcategoryOperationCode};
+
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Direction {
+    Income,
+    Expense
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum SortBy {
+    Date,
+    Amount,
+    Account
+}
+
+/// Filters select operations, sort and page options apply only to query results.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OperationQuery {
+    pub from: u64,
+    pub to: u64,
+    pub account: Option<AccountId>,
+    /// in hundredths, inclusive
+    pub min_summa: Option<i64>,
+    pub max_summa: Option<i64>,
+    /// operations of special subcategories (transfers, exchanges) have no direction
+    pub direction: Option<Direction>,
+    pub sort_by: SortBy,
+    pub descending: bool,
+    /// number of sorted operations to skip
+    pub offset: usize,
+    pub limit: Option<usize>
+}
+
+/// Requested part of sorted query results.
+pub struct QueryPage<T> {
+    pub operations: Vec<T>,
+    /// number of all matching operations
+    pub total: usize
+}
+
+/// Operation with names of the account, category and subcategory it references, resolved once
+/// so consumers don't look up dictionaries for every printed or serialized row.
+#[derive(Serialize)]
+pub struct OperationView {
+    pub date: u64,
+    pub account: AccountId,
+    #[serde(rename = "accountName")]
+    pub account_name: String,
+    /// currency of the account at the operation date
+    pub currency: String,
+    pub category: CategoryId,
+    #[serde(rename = "categoryName")]
+    pub category_name: String,
+    pub subcategory: SubcategoryId,
+    #[serde(rename = "subcategoryName")]
+    pub subcategory_name: String,
+    pub summa: i64,
+    #[serde(rename = "formattedSumma")]
+    pub formatted_summa: String,
+    #[serde(skip)]
+    pub operation: FinanceOperation
+}
+
+impl OperationView {
+    pub fn new(operation: FinanceOperation, accounts: &Accounts, categories: &Categories,
+               subcategories: &Subcategories) -> Result<OperationView, Error> {
+        let account = accounts.get(operation.get_account())?;
+        let subcategory = subcategories.get(operation.get_subcategory())?;
+        let category = categories.get(subcategory.category)?;
+        Ok(OperationView{date: operation.date, account: account.id, account_name: account.name.clone(),
+            currency: account.currency_at(operation.date).to_string(), category: category.id,
+            category_name: category.name.clone(), subcategory: subcategory.id, subcategory_name: subcategory.name.clone(),
+            summa: operation.get_summa(), formatted_summa: format_summa(operation.get_summa()), operation})
+    }
+}
+
+impl QueryPage<FinanceOperation> {
+    /// Resolves references of operations of the page, other matching operations are not touched.
+    pub fn resolve(self, accounts: &Accounts, categories: &Categories, subcategories: &Subcategories)
+        -> Result<QueryPage<OperationView>, Error> {
+        let operations = self.operations.into_iter()
+            .map(|op|OperationView::new(op, accounts, categories, subcategories))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(QueryPage{operations, total: self.total})
+    }
+}
+
+impl OperationQuery {
+    pub fn new(from: u64, to: u64) -> OperationQuery {
+        OperationQuery{from, to, account: None, min_summa: None, max_summa: None, direction: None,
+            sort_by: SortBy::Date, descending: false, offset: 0, limit: None}
+    }
+
+    /// Parses comma separated list like "from=20240101,to=20241231,min=500,direction=expense",
+    /// also accepts max, account, sort (date, amount or account), order (asc or desc), offset and limit.
+    pub fn parse(s: &str) -> Result<OperationQuery, Error> {
+        let mut query = OperationQuery::new(0, 99999999);
+        for item in s.split(',').filter(|i|!i.is_empty()) {
+            let (key, value) = item.split_once('=')
+                .ok_or(Error::new(ErrorKind::InvalidInput, format!("invalid query filter {}", item)))?;
+            let number = || value.parse::<u64>()
+                .map_err(|_|Error::new(ErrorKind::InvalidInput, format!("invalid value of {}", key)));
+            match key {
+                "from" => query.from = number()?,
+                "to" => query.to = number()?,
+                "account" => query.account = Some(AccountId(number()?)),
+                "min" => query.min_summa = Some(parse_summa(value)?),
+                "max" => query.max_summa = Some(parse_summa(value)?),
+                "direction" => query.direction = match value {
+                    "income" => Some(Direction::Income),
+                    "expense" => Some(Direction::Expense),
+                    _ => return Err(Error::new(ErrorKind::InvalidInput, format!("unknown direction {}", value)))
+                },
+                "sort" => query.sort_by = match value {
+                    "date" => SortBy::Date,
+                    "amount" => SortBy::Amount,
+                    "account" => SortBy::Account,
+                    _ => return Err(Error::new(ErrorKind::InvalidInput, format!("unknown sort {}", value)))
+                },
+                "order" => query.descending = match value {
+                    "asc" => false,
+                    "desc" => true,
+                    _ => return Err(Error::new(ErrorKind::InvalidInput, format!("unknown order {}", value)))
+                },
+                "offset" => query.offset = number()? as usize,
+                "limit" => query.limit = Some(number()? as usize),
+                _ => return Err(Error::new(ErrorKind::InvalidInput, format!("unknown query filter {}", key)))
+            }
+        }
+        Ok(query)
+    }
+
+    pub fn matches(&self, op: &FinanceOperation, subcategories: &Subcategories) -> Result<bool, Error> {
+        if !op.within(self.from, self.to) || self.account.is_some_and(|a|a != op.get_account()) {
+            return Ok(false);
+        }
+        let summa = op.get_summa();
+        if self.min_summa.is_some_and(|m|summa < m) || self.max_summa.is_some_and(|m|summa > m) {
+            return Ok(false);
+        }
+        match self.direction {
+            None => Ok(true),
+            Some(direction) => {
+                let code = &subcategories.get(op.get_subcategory())?.operation_code;
+                Ok(match direction {
+                    Direction::Income => matches!(code, SubcategoryOperationCode::Incm),
+                    Direction::Expense => matches!(code, SubcategoryOperationCode::Expn)
+                })
+            }
+        }
+    }
+
+    /// Sorts matching operations and cuts the requested page, equal keys are ordered by date.
+    pub fn page(&self, mut operations: Vec<FinanceOperation>) -> QueryPage<FinanceOperation> {
+        match self.sort_by {
+            SortBy::Date => operations.sort_by_key(|op|op.date),
+            SortBy::Amount => operations.sort_by_key(|op|(op.get_summa(), op.date)),
+            SortBy::Account => operations.sort_by_key(|op|(op.get_account(), op.date))
+        }
+        if self.descending {
+            operations.reverse();
+        }
+        let total = operations.len();
+        let operations = operations.into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect();
+        QueryPage{operations, total}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::entities::accounts::{Account, AccountId, Accounts};
+    use crate::entities::finance_operations::FinanceOperation;
+    use crate::entities::subcategories::{Categories, Category, CategoryId, Subcategories, Subcategory, SubcategoryCode,
+                                         SubcategoryId, SubcategoryOperationCode};
+    use crate::query::{Direction, OperationQuery, SortBy};
+
+    #[test]
+    fn test_parse_query() -> Result<(), Error> {
+        let query = OperationQuery::parse("from=20240101,to=20241231,min=500,direction=expense,account=2")?;
+        assert_eq!((query.from, query.to), (20240101, 20241231));
+        assert_eq!(query.min_summa, Some(50000));
+        assert_eq!(query.max_summa, None);
+        assert_eq!(query.direction, Some(Direction::Expense));
+        assert_eq!(query.account, Some(AccountId(2)));
+        assert!(OperationQuery::parse("direction=up").is_err());
+        assert!(OperationQuery::parse("min=abc").is_err());
+        assert!(OperationQuery::parse("sort=payee").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_page() -> Result<(), Error> {
+        let query = OperationQuery::parse("sort=amount,order=desc,offset=1,limit=2")?;
+        assert_eq!((query.sort_by, query.descending, query.offset, query.limit), (SortBy::Amount, true, 1, Some(2)));
+        let operations = [(20240105, 1, 500), (20240110, 2, 100), (20240102, 2, 900), (20240120, 1, 500)]
+            .into_iter()
+            .map(|(date, account, summa)|FinanceOperation::new(date, AccountId(account), SubcategoryId(1), None,
+                                                               summa, Vec::new()))
+            .collect::<Vec<_>>();
+        let page = query.page(operations.iter().map(|op|op.copy()).collect());
+        assert_eq!(page.total, 4);
+        assert_eq!(page.operations.iter().map(|op|op.date).collect::<Vec<_>>(), vec![20240120, 20240105]);
+        let page = OperationQuery::parse("sort=account,offset=3")?.page(operations);
+        assert_eq!(page.operations.iter().map(|op|op.date).collect::<Vec<_>>(), vec![20240110]);
+        let accounts = Accounts::new(vec![Account::new(AccountId(2), "Card".to_string(), "USD".to_string(), true)])?;
+        let categories = Categories::new(vec![Category{id: CategoryId(3), name: "Food".to_string(), tax_relevant: false}]);
+        let subcategories = Subcategories::new(vec![Subcategory{id: SubcategoryId(1), name: "Groceries".to_string(),
+            code: SubcategoryCode::None, operation_code: SubcategoryOperationCode::Expn, category: CategoryId(3),
+            tax_relevant: false, retired: false, soft_limit: None, hard_limit: None}]);
+        let page = page.resolve(&accounts, &categories, &subcategories)?;
+        let view = &page.operations[0];
+        assert_eq!((view.account_name.as_str(), view.currency.as_str(), view.category_name.as_str(),
+                    view.subcategory_name.as_str(), view.formatted_summa.as_str()), ("Card", "USD", "Food", "Groceries", "1.00"));
+        Ok(())
+    }
+}
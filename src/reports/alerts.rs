@@ -0,0 +1,103 @@
+//! Balance thresholds of accounts. Alerts are raised when a balance crosses a threshold in either
+//! direction, an account staying below its threshold raises nothing.
+
+use std::collections::{BTreeSet, HashMap};
+use std::io::Error;
+use crate::core::amounts::format_summa;
+use crate::entities::accounts::{AccountId, Accounts};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub enum Threshold {
+    MinBalance,
+    CreditLimit
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct BalanceAlert {
+    pub account: AccountId,
+    pub threshold: Threshold,
+    /// minimum balance or credit limit
+    pub limit: i64,
+    pub balance: i64,
+    /// balance returned above the threshold
+    pub recovered: bool
+}
+
+impl BalanceAlert {
+    pub fn describe(&self, accounts: &Accounts) -> Result<String, Error> {
+        let name = &accounts.get(self.account)?.name;
+        let (limit, balance) = (format_summa(self.limit), format_summa(self.balance));
+        Ok(match (self.threshold, self.recovered) {
+            (Threshold::MinBalance, false) => format!("{}: balance {} is below minimum balance {}", name, balance, limit),
+            (Threshold::MinBalance, true) => format!("{}: balance {} is back above minimum balance {}", name, balance, limit),
+            (Threshold::CreditLimit, false) => format!("{}: credit limit {} exceeded, balance {}", name, limit, balance),
+            (Threshold::CreditLimit, true) => format!("{}: balance {} is back within credit limit {}", name, balance, limit)
+        })
+    }
+}
+
+/// Thresholds crossed by balances, accounts without balance are treated as having zero.
+pub fn find_crossed(accounts: &Accounts, balances: &HashMap<AccountId, i64>) -> BTreeSet<(AccountId, Threshold)> {
+    let mut result = BTreeSet::new();
+    for account in accounts.iter() {
+        let balance = balances.get(&account.id).copied().unwrap_or(0);
+        if account.min_balance.is_some_and(|m|balance < m) {
+            result.insert((account.id, Threshold::MinBalance));
+        }
+        if account.credit_limit.is_some_and(|l|balance < -l) {
+            result.insert((account.id, Threshold::CreditLimit));
+        }
+    }
+    result
+}
+
+/// Alerts for thresholds crossed since the previous check.
+pub fn build_alerts(accounts: &Accounts, balances: &HashMap<AccountId, i64>,
+                    previous: &BTreeSet<(AccountId, Threshold)>, current: &BTreeSet<(AccountId, Threshold)>)
+    -> Vec<BalanceAlert> {
+    let mut result = Vec::new();
+    for (account, threshold) in current.symmetric_difference(previous) {
+        // account removed on dictionaries reload
+        let Ok(a) = accounts.get(*account) else { continue };
+        let limit = match threshold {
+            Threshold::MinBalance => a.min_balance,
+            Threshold::CreditLimit => a.credit_limit
+        };
+        // recovery of an account which threshold was removed is not reported
+        let Some(limit) = limit else { continue };
+        result.push(BalanceAlert{account: *account, threshold: *threshold, limit,
+            balance: balances.get(account).copied().unwrap_or(0), recovered: !current.contains(&(*account, *threshold))});
+    }
+    result
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use std::collections::{BTreeSet, HashMap};
+    use std::io::Error;
+    use crate::entities::accounts::{Account, AccountId, Accounts};
+    use crate::reports::alerts::{build_alerts, find_crossed, Threshold};
+
+    #[test]
+    fn test_alerts() -> Result<(), Error> {
+        let accounts: Vec<Account> = serde_json::from_str(r#"[
+            {"id": 1, "name": "Cash", "valutaCode": "UAH", "activeTo": null, "isCash": true, "minBalance": 1000},
+            {"id": 2, "name": "Card", "valutaCode": "UAH", "activeTo": null, "isCash": false, "creditLimit": 5000}
+        ]"#)?;
+        let accounts = Accounts::new(accounts)?;
+        let balances = HashMap::from([(AccountId(1), 500), (AccountId(2), -4000)]);
+        let crossed = find_crossed(&accounts, &balances);
+        assert_eq!(crossed, BTreeSet::from([(AccountId(1), Threshold::MinBalance)]));
+        let alerts = build_alerts(&accounts, &balances, &BTreeSet::new(), &crossed);
+        assert_eq!(alerts[0].describe(&accounts)?, "Cash: balance 5.00 is below minimum balance 10.00");
+        assert!(build_alerts(&accounts, &balances, &crossed, &crossed).is_empty());
+
+        let balances = HashMap::from([(AccountId(1), 1000), (AccountId(2), -6000)]);
+        let current = find_crossed(&accounts, &balances);
+        let alerts = build_alerts(&accounts, &balances, &crossed, &current);
+        assert_eq!(alerts.iter().map(|a|(a.account, a.recovered)).collect::<Vec<_>>(),
+                   vec![(AccountId(1), true), (AccountId(2), false)]);
+        assert_eq!(alerts[1].describe(&accounts)?, "Card: credit limit 50.00 exceeded, balance -60.00");
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+//! Yearly overview: balances of accounts at the start and at the end of the year and summary by category.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Error;
+use crate::core::amounts::format_summa;
+use crate::entities::accounts::{AccountId, Accounts};
+use crate::entities::subcategories::{Categories, CategoryId};
+use crate::reports::summary::{name_categories, SummaryItem};
+
+pub struct AccountBalance {
+    pub account: String,
+    pub currency: String,
+    pub opening: i64,
+    pub closing: i64
+}
+
+pub struct AnnualReport {
+    pub year: u64,
+    /// accounts with non zero opening or closing balance, sorted by name
+    pub balances: Vec<AccountBalance>,
+    /// category names with their summaries, sorted by name
+    pub categories: Vec<(String, SummaryItem)>,
+    pub total: SummaryItem
+}
+
+impl AnnualReport {
+    pub fn new(year: u64, accounts: &Accounts, opening: &HashMap<AccountId, i64>, closing: &HashMap<AccountId, i64>,
+               summary: &BTreeMap<CategoryId, SummaryItem>, categories: &Categories) -> Result<AnnualReport, Error> {
+        let mut balances = Vec::new();
+        for id in opening.keys().chain(closing.keys()).collect::<BTreeSet<_>>() {
+            let (opening, closing) = (opening.get(id).copied().unwrap_or(0), closing.get(id).copied().unwrap_or(0));
+            if opening != 0 || closing != 0 {
+                let account = accounts.get(*id)?;
+                balances.push(AccountBalance{account: account.name.clone(), currency: account.currency.clone(),
+                    opening, closing});
+            }
+        }
+        balances.sort_by(|a, b|a.account.cmp(&b.account));
+        let (items, total) = name_categories(summary, categories)?;
+        Ok(AnnualReport{year, balances, categories: items, total})
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut result = format!("Annual report for {}\n\nAccount balances:\n", self.year);
+        for b in &self.balances {
+            result += &format!("{} ({}): {} -> {}\n", b.account, b.currency, format_summa(b.opening),
+                               format_summa(b.closing));
+        }
+        result += &format!("\nIncome: {}\nExpenditure: {}\n", format_summa(self.total.income),
+                           format_summa(self.total.expenditure));
+        for (name, item) in &self.categories {
+            result += &format!("{}: +{} -{}\n", name, format_summa(item.income), format_summa(item.expenditure));
+        }
+        result
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+use std::io::Error;
+use crate::entities::finance_operations::FinanceOperation;
+use crate::entities::subcategories::{CategoryId, Subcategories, SubcategoryOperationCode};
+
+#[derive(PartialEq, Debug)]
+pub struct SpendingAnomaly {
+    /// yyyymm
+    pub month: u64,
+    pub category: CategoryId,
+    pub spent: i64,
+    pub trailing_average: i64
+}
+
+fn month_index(date: u64) -> u64 {
+    date / 10000 * 12 + date / 100 % 100 - 1
+}
+
+/// Flags months of from..=to (yyyymm) where category expenditure is more than multiple times higher
+/// or lower than its average over the preceding trailing_months months. Months without expenditure
+/// count as zero, categories with zero average are not flagged.
+pub fn build_anomalies_report<'a>(operations: impl Iterator<Item = &'a FinanceOperation>,
+                                  subcategories: &Subcategories, from: u64, to: u64, trailing_months: u64,
+                                  multiple: f64) -> Result<Vec<SpendingAnomaly>, Error> {
+    let mut spent: BTreeMap<CategoryId, BTreeMap<u64, i64>> = BTreeMap::new();
+    for op in operations {
+        let subcategory = subcategories.get(op.get_subcategory())?;
+        if let SubcategoryOperationCode::Expn = subcategory.operation_code {
+            *spent.entry(subcategory.category).or_default().entry(month_index(op.date)).or_default() += op.get_summa();
+        }
+    }
+    let mut result = Vec::new();
+    let trailing_months = trailing_months.max(1);
+    for (category, months) in spent {
+        for index in month_index(from * 100 + 1)..=month_index(to * 100 + 1) {
+            let start = index.saturating_sub(trailing_months);
+            let average = months.range(start..index).map(|(_, s)|*s).sum::<i64>() / trailing_months as i64;
+            let value = months.get(&index).copied().unwrap_or(0);
+            if average > 0 && (value as f64 > average as f64 * multiple || (value as f64) * multiple < average as f64) {
+                result.push(SpendingAnomaly{month: index / 12 * 100 + index % 12 + 1, category, spent: value,
+                    trailing_average: average});
+            }
+        }
+    }
+    result.sort_by_key(|a|(a.month, a.category));
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::entities::accounts::AccountId;
+    use crate::entities::finance_operations::FinanceOperation;
+    use crate::entities::subcategories::{CategoryId, Subcategories, Subcategory, SubcategoryCode,
+                                         SubcategoryId, SubcategoryOperationCode};
+    use crate::reports::anomalies::{build_anomalies_report, SpendingAnomaly};
+
+    #[test]
+    fn test_anomalies() -> Result<(), Error> {
+        let subcategories = Subcategories::new(vec![Subcategory{id: SubcategoryId(1), name: "Food".to_string(),
+            code: SubcategoryCode::None, operation_code: SubcategoryOperationCode::Expn, category: CategoryId(2),
+            tax_relevant: false, retired: false, soft_limit: None, hard_limit: None}]);
+        let operations: Vec<FinanceOperation> = [(20240105, 1000), (20240210, 1200), (20240301, 1100),
+            (20240415, 5000), (20240520, 1000)].iter()
+            .map(|(date, summa)|FinanceOperation::new(*date, AccountId(1), SubcategoryId(1), None, *summa, Vec::new()))
+            .collect();
+        let report = build_anomalies_report(operations.iter(), &subcategories, 202404, 202406, 3, 2.0)?;
+        assert_eq!(report, vec![
+            SpendingAnomaly{month: 202404, category: CategoryId(2), spent: 5000, trailing_average: 1100},
+            SpendingAnomaly{month: 202405, category: CategoryId(2), spent: 1000, trailing_average: 2433},
+            SpendingAnomaly{month: 202406, category: CategoryId(2), spent: 0, trailing_average: 2366}
+        ]);
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+//! Budget versus actual expenditure of a month. Rollover of every line is accumulated month by month
+//! from its first budgeted month.
+
+use std::collections::HashMap;
+use crate::core::dates::add_months;
+use crate::entities::budgets::Budgets;
+use crate::entities::finance_operations::FinanceOperation;
+use crate::entities::subcategories::SubcategoryId;
+
+#[derive(PartialEq, Debug)]
+pub struct BudgetStatus {
+    pub subcategory: SubcategoryId,
+    pub amount: i64,
+    /// rollover from previous months, negative for overspend
+    pub carried: i64,
+    pub spent: i64,
+    /// amount plus carried minus spent
+    pub remaining: i64
+}
+
+/// Operations have to cover months from the first budgeted month of the lines to the report month,
+/// lines starting after the report month are not included.
+pub fn build_budget_report<'a>(budgets: &Budgets, operations: impl Iterator<Item = &'a FinanceOperation>,
+                               month: u64) -> Vec<BudgetStatus> {
+    let mut spent: HashMap<(u64, SubcategoryId), i64> = HashMap::new();
+    for op in operations {
+        *spent.entry((op.date / 100, op.get_subcategory())).or_default() += op.get_summa();
+    }
+    budgets.iter()
+        .filter(|line|line.from_month <= month)
+        .map(|line|{
+            let mut carried = 0;
+            let mut m = line.from_month;
+            while m < month {
+                let balance = line.amount + carried - spent.get(&(m, line.subcategory)).copied().unwrap_or(0);
+                carried = line.rollover.carry(balance);
+                m = add_months(m * 100 + 1, 1) / 100;
+            }
+            let spent = spent.get(&(month, line.subcategory)).copied().unwrap_or(0);
+            BudgetStatus{subcategory: line.subcategory, amount: line.amount, carried, spent,
+                remaining: line.amount + carried - spent}
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::entities::accounts::AccountId;
+    use crate::entities::budgets::{BudgetLine, Budgets, Rollover};
+    use crate::entities::finance_operations::FinanceOperation;
+    use crate::entities::subcategories::SubcategoryId;
+    use crate::reports::budget::{build_budget_report, BudgetStatus};
+
+    #[test]
+    fn test_rollover() -> Result<(), Error> {
+        let line = |subcategory, rollover|BudgetLine{subcategory: SubcategoryId(subcategory), amount: 1000,
+            from_month: 202411, rollover};
+        let budgets = Budgets::new(vec![line(1, Rollover::None), line(2, Rollover::Unused),
+                                        line(3, Rollover::Overspend), line(4, Rollover::Both)])?;
+        // 300 spent in November, 1800 in December and 100 in January on every subcategory
+        let operations: Vec<FinanceOperation> = (1..=4)
+            .flat_map(|s|[(20241105, 300), (20241210, 1800), (20250103, 100)]
+                .map(|(date, summa)|FinanceOperation::new(date, AccountId(1), SubcategoryId(s), None, summa, Vec::new())))
+            .collect();
+        let report = build_budget_report(&budgets, operations.iter(), 202501);
+        let carried: Vec<i64> = report.iter().map(|s|s.carried).collect();
+        // 700 unused in November, December overspend is 100 with the carry and 800 without it
+        assert_eq!(carried, vec![0, 0, -800, -100]);
+        assert_eq!(report[3], BudgetStatus{subcategory: SubcategoryId(4), amount: 1000, carried: -100, spent: 100,
+            remaining: 800});
+        assert!(build_budget_report(&budgets, operations.iter(), 202410).is_empty());
+        Ok(())
+    }
+}
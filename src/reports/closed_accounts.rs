@@ -0,0 +1,11 @@
+use std::io::Error;
+use crate::entities::accounts::{Account, Accounts};
+
+/// Accounts closed during given period, ordered by closing date.
+pub fn build_closed_accounts_report(accounts: &Accounts, from: u64, to: u64) -> Result<Vec<&Account>, Error> {
+    let mut result: Vec<&Account> = accounts.all()?.into_iter()
+        .filter(|a|a.active_to.is_some_and(|d|d >= from && d <= to))
+        .collect();
+    result.sort_by_key(|a|(a.active_to, a.id));
+    Ok(result)
+}
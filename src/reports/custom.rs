@@ -0,0 +1,249 @@
+//! User defined reports. Specs are stored in the reports file of the data folder and select operations
+//! with query filters, group them and compute aggregates, so new summaries need no code changes.
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+use std::ops::Add;
+use serde::{Deserialize, Serialize};
+use crate::core::amounts::format_summa;
+use crate::core::data_source::DataSource;
+use crate::entities::accounts::Accounts;
+use crate::entities::finance_operations::FinanceOperation;
+use crate::entities::subcategories::{Categories, Subcategories};
+use crate::query::OperationQuery;
+use crate::reports::csv_field;
+use crate::reports::statement::format_date;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    Year,
+    Month,
+    Day,
+    Account,
+    Currency,
+    Category,
+    Subcategory,
+    /// NETW parameter
+    Network
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregate {
+    Sum,
+    Count,
+    Min,
+    Max,
+    Avg
+}
+
+impl GroupBy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GroupBy::Year => "year",
+            GroupBy::Month => "month",
+            GroupBy::Day => "day",
+            GroupBy::Account => "account",
+            GroupBy::Currency => "currency",
+            GroupBy::Category => "category",
+            GroupBy::Subcategory => "subcategory",
+            GroupBy::Network => "network"
+        }
+    }
+}
+
+impl Aggregate {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aggregate::Sum => "sum",
+            Aggregate::Count => "count",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+            Aggregate::Avg => "avg"
+        }
+    }
+}
+
+/// Report definition like {"name": "food", "filter": "direction=expense", "groupBy": ["month", "category"],
+/// "aggregates": ["sum", "count"]}.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ReportSpec {
+    pub name: String,
+    /// filters of the query command, sorting and paging are ignored
+    #[serde(default)]
+    pub filter: String,
+    #[serde(rename = "groupBy", default)]
+    pub group_by: Vec<GroupBy>,
+    pub aggregates: Vec<Aggregate>,
+    /// group and aggregate names in output order, all of them when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub columns: Vec<String>
+}
+
+impl ReportSpec {
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |message: String|Error::new(ErrorKind::InvalidData, format!("report {}: {}", self.name, message));
+        if self.aggregates.is_empty() {
+            return Err(invalid("no aggregates".to_string()));
+        }
+        OperationQuery::parse(&self.filter).map_err(|e|invalid(e.to_string()))?;
+        let available = self.available_columns();
+        if let Some(column) = self.columns.iter().find(|c|!available.contains(c)) {
+            return Err(invalid(format!("unknown column {}", column)));
+        }
+        Ok(())
+    }
+
+    fn available_columns(&self) -> Vec<String> {
+        self.group_by.iter().map(|g|g.name().to_string())
+            .chain(self.aggregates.iter().map(|a|a.name().to_string()))
+            .collect()
+    }
+}
+
+/// Reports file is optional, databases without it have no custom reports.
+pub fn load_report_specs(data_folder_path: String, source: Box<dyn DataSource<Vec<ReportSpec>>>)
+    -> Result<Vec<ReportSpec>, Error> {
+    let specs = match source.load(data_folder_path.add("/reports"), true) {
+        Ok(specs) => specs,
+        Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e)
+    };
+    for spec in &specs {
+        spec.validate()?;
+    }
+    Ok(specs)
+}
+
+pub struct CustomReport {
+    pub name: String,
+    pub columns: Vec<String>,
+    /// formatted values, ordered by group values
+    pub rows: Vec<Vec<String>>
+}
+
+#[derive(Default)]
+struct Totals {
+    sum: i64,
+    count: i64,
+    min: Option<i64>,
+    max: Option<i64>
+}
+
+impl Totals {
+    fn format(&self, aggregate: Aggregate) -> String {
+        match aggregate {
+            Aggregate::Sum => format_summa(self.sum),
+            Aggregate::Count => self.count.to_string(),
+            Aggregate::Min => format_summa(self.min.unwrap_or_default()),
+            Aggregate::Max => format_summa(self.max.unwrap_or_default()),
+            Aggregate::Avg => format_summa(self.sum / self.count.max(1))
+        }
+    }
+}
+
+/// Group value, ordered by id or date first and shown by name.
+fn group_value(group: GroupBy, op: &FinanceOperation, accounts: &Accounts, categories: &Categories,
+               subcategories: &Subcategories) -> Result<(u64, String), Error> {
+    Ok(match group {
+        GroupBy::Year => (op.date / 10000, (op.date / 10000).to_string()),
+        GroupBy::Month => (op.date / 100, format!("{}-{:02}", op.date / 10000, op.date / 100 % 100)),
+        GroupBy::Day => (op.date, format_date(op.date)),
+        GroupBy::Account => (op.get_account().0, accounts.get(op.get_account())?.name.clone()),
+        GroupBy::Currency => (0, accounts.get(op.get_account())?.currency_at(op.date).to_string()),
+        GroupBy::Category => {
+            let category = categories.get(subcategories.get(op.get_subcategory())?.category)?;
+            (category.id.0, category.name.clone())
+        }
+        GroupBy::Subcategory => (op.get_subcategory().0, subcategories.get(op.get_subcategory())?.name.clone()),
+        GroupBy::Network => (0, op.get_network().unwrap_or_default().to_string())
+    })
+}
+
+/// Runs the spec on operations of the query period, query replaces dates of the spec filter.
+pub fn build_custom_report<'a>(spec: &ReportSpec, query: &OperationQuery, operations: impl Iterator<Item = &'a FinanceOperation>,
+                               accounts: &Accounts, categories: &Categories, subcategories: &Subcategories)
+    -> Result<CustomReport, Error> {
+    let mut groups: BTreeMap<Vec<(u64, String)>, Totals> = BTreeMap::new();
+    for op in operations {
+        if !query.matches(op, subcategories)? {
+            continue;
+        }
+        let key = spec.group_by.iter()
+            .map(|g|group_value(*g, op, accounts, categories, subcategories))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let totals = groups.entry(key).or_default();
+        let summa = op.get_summa();
+        totals.sum += summa;
+        totals.count += 1;
+        totals.min = Some(totals.min.map_or(summa, |m|m.min(summa)));
+        totals.max = Some(totals.max.map_or(summa, |m|m.max(summa)));
+    }
+    let columns = if spec.columns.is_empty() { spec.available_columns() } else { spec.columns.clone() };
+    let rows = groups.iter()
+        .map(|(key, totals)|columns.iter().map(|column|{
+            match spec.group_by.iter().position(|g|g.name() == column) {
+                Some(i) => key[i].1.clone(),
+                None => spec.aggregates.iter().find(|a|a.name() == column)
+                    .map(|a|totals.format(*a))
+                    .unwrap_or_default()
+            }
+        }).collect())
+        .collect();
+    Ok(CustomReport{name: spec.name.clone(), columns, rows})
+}
+
+impl CustomReport {
+    /// Columns separated by tabs, the first line has column names.
+    pub fn to_text(&self) -> String {
+        let mut result = self.columns.join("\t") + "\n";
+        for row in &self.rows {
+            result += &(row.join("\t") + "\n");
+        }
+        result
+    }
+
+    pub fn to_csv(&self) -> String {
+        let line = |values: &[String]|values.iter().map(|v|csv_field(v)).collect::<Vec<_>>().join(",") + "\n";
+        let mut result = line(&self.columns);
+        for row in &self.rows {
+            result += &line(row);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::entities::accounts::{Account, AccountId, Accounts};
+    use crate::entities::finance_operations::FinanceOperation;
+    use crate::entities::subcategories::{Categories, Category, CategoryId, Subcategories, Subcategory, SubcategoryCode,
+                                         SubcategoryId, SubcategoryOperationCode};
+    use crate::query::OperationQuery;
+    use crate::reports::custom::{build_custom_report, Aggregate, GroupBy, ReportSpec};
+
+    #[test]
+    fn test_custom_report() -> Result<(), Error> {
+        let spec = ReportSpec{name: "food".to_string(), filter: "min=2".to_string(),
+            group_by: vec![GroupBy::Month, GroupBy::Account], aggregates: vec![Aggregate::Sum, Aggregate::Count, Aggregate::Max],
+            columns: vec!["month".to_string(), "sum".to_string(), "count".to_string()]};
+        spec.validate()?;
+        let accounts = Accounts::new(vec![Account::new(AccountId(1), "Cash".to_string(), "UAH".to_string(), true)])?;
+        let categories = Categories::new(vec![Category{id: CategoryId(1), name: "Food".to_string(), tax_relevant: false}]);
+        let subcategories = Subcategories::new(vec![Subcategory{id: SubcategoryId(1), name: "Groceries".to_string(),
+            code: SubcategoryCode::None, operation_code: SubcategoryOperationCode::Expn, category: CategoryId(1),
+            tax_relevant: false, retired: false, soft_limit: None, hard_limit: None}]);
+        let operations = [(20240105, 500), (20240110, 100), (20240120, 250), (20240202, 1000)]
+            .into_iter()
+            .map(|(date, summa)|FinanceOperation::new(date, AccountId(1), SubcategoryId(1), None, summa, Vec::new()))
+            .collect::<Vec<_>>();
+        let query = OperationQuery::parse(&spec.filter)?;
+        let report = build_custom_report(&spec, &query, operations.iter(), &accounts, &categories, &subcategories)?;
+        assert_eq!(report.to_csv(), "month,sum,count\n2024-01,7.50,2\n2024-02,10.00,1\n");
+        let invalid = ReportSpec{group_by: Vec::new(), ..spec};
+        assert!(invalid.validate().is_err());
+        Ok(())
+    }
+}
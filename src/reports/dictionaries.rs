@@ -0,0 +1,49 @@
+//! Accounts, categories and subcategories as CSV for bulk edits in a spreadsheet,
+//! edited files are applied by importers::dictionaries.
+
+use crate::core::amounts::format_summa;
+use crate::entities::accounts::Account;
+use crate::entities::subcategories::{Category, Subcategory};
+use crate::reports::csv_field;
+
+pub const ACCOUNT_COLUMNS: [&str; 10] = ["id", "name", "currency", "active_to", "cash", "group", "order", "retired",
+                                         "min_balance", "credit_limit"];
+pub const CATEGORY_COLUMNS: [&str; 3] = ["id", "name", "tax_relevant"];
+pub const SUBCATEGORY_COLUMNS: [&str; 9] = ["id", "name", "code", "operation_code", "category_id", "tax_relevant",
+                                            "retired", "soft_limit", "hard_limit"];
+
+fn to_csv(columns: &[&str], mut rows: Vec<(u64, Vec<String>)>) -> String {
+    rows.sort_by_key(|(id, _)|*id);
+    let mut result = columns.join(",") + "\n";
+    for (_, row) in rows {
+        result += &row.iter().map(|v|csv_field(v)).collect::<Vec<_>>().join(",");
+        result += "\n";
+    }
+    result
+}
+
+fn optional<T>(value: Option<T>, f: impl Fn(T) -> String) -> String {
+    value.map(f).unwrap_or_default()
+}
+
+/// Rows are ordered by id, empty cells are unset values.
+pub fn accounts_to_csv<'a>(accounts: impl Iterator<Item = &'a Account>) -> String {
+    to_csv(&ACCOUNT_COLUMNS, accounts.map(|a|(a.id.0, vec![
+        a.id.to_string(), a.name.clone(), a.currency.clone(), optional(a.active_to, |d|d.to_string()),
+        a.is_cash().to_string(), optional(a.get_explicit_group(), |g|g.as_str().to_string()), a.order.to_string(),
+        a.retired.to_string(), optional(a.min_balance, format_summa), optional(a.credit_limit, format_summa)
+    ])).collect())
+}
+
+pub fn categories_to_csv<'a>(categories: impl Iterator<Item = &'a Category>) -> String {
+    to_csv(&CATEGORY_COLUMNS, categories.map(|c|(c.id.0, vec![c.id.to_string(), c.name.clone(),
+                                                              c.tax_relevant.to_string()])).collect())
+}
+
+pub fn subcategories_to_csv<'a>(subcategories: impl Iterator<Item = &'a Subcategory>) -> String {
+    to_csv(&SUBCATEGORY_COLUMNS, subcategories.map(|s|(s.id.0, vec![
+        s.id.to_string(), s.name.clone(), s.code.as_str().to_string(), s.operation_code.as_str().to_string(),
+        s.category.to_string(), s.tax_relevant.to_string(), s.retired.to_string(), optional(s.soft_limit, format_summa),
+        optional(s.hard_limit, format_summa)
+    ])).collect())
+}
@@ -0,0 +1,281 @@
+//! Export to an uncompressed GnuCash XML book. Accounts go under Assets or Liabilities, subcategories
+//! become income, expense, equity or loan accounts under accounts of their categories. Subcategories used
+//! with several currencies get a child account per additional currency. Accounts which currency changed
+//! are exported in their current currency.
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use crate::entities::accounts::{AccountGroup, AccountId, Accounts};
+use crate::entities::finance_operations::{FinanceChanges, FinanceOperation, SpecialHandlers};
+use crate::entities::payees::Payees;
+use crate::entities::subcategories::{Categories, Subcategories, SubcategoryCode, SubcategoryId, SubcategoryOperationCode};
+use crate::reports::escape_xml;
+use crate::reports::statement::format_date;
+
+const GUID_BOOK: u64 = 1;
+const GUID_ACCOUNT: u64 = 2;
+const GUID_TRANSACTION: u64 = 3;
+const GUID_SPLIT: u64 = 4;
+
+struct GncAccount {
+    guid: String,
+    name: String,
+    account_type: &'static str,
+    currency: String,
+    parent: Option<String>
+}
+
+pub struct GnuCashExport<'a> {
+    accounts: &'a Accounts,
+    categories: &'a Categories,
+    subcategories: &'a Subcategories,
+    payees: &'a Payees,
+    handlers: &'a SpecialHandlers,
+    base_currency: String,
+    gnc_accounts: Vec<GncAccount>,
+    /// account key to index in gnc_accounts
+    index: HashMap<String, usize>,
+    transactions: Vec<String>,
+    splits: u64
+}
+
+fn guid(kind: u64, number: u64) -> String {
+    format!("{:08x}{:024x}", kind, number)
+}
+
+fn amount(summa: i64) -> String {
+    format!("{}/100", summa)
+}
+
+fn commodity(tag: &str, currency: &str) -> String {
+    format!("<{0}><cmdty:space>CURRENCY</cmdty:space><cmdty:id>{1}</cmdty:id></{0}>", tag, escape_xml(currency))
+}
+
+/// Name of the top level account for GnuCash account type.
+fn top_level(account_type: &str) -> &'static str {
+    match account_type {
+        "INCOME" => "Income",
+        "EXPENSE" => "Expenses",
+        "EQUITY" => "Equity",
+        "LIABILITY" => "Liabilities",
+        _ => "Assets"
+    }
+}
+
+impl<'a> GnuCashExport<'a> {
+    pub fn new(accounts: &'a Accounts, categories: &'a Categories, subcategories: &'a Subcategories,
+               payees: &'a Payees, handlers: &'a SpecialHandlers, base_currency: String) -> GnuCashExport<'a> {
+        let root = GncAccount{guid: guid(GUID_ACCOUNT, 0), name: "Root Account".to_string(), account_type: "ROOT",
+            currency: base_currency.clone(), parent: None};
+        GnuCashExport{accounts, categories, subcategories, payees, handlers, base_currency, gnc_accounts: vec![root],
+            index: HashMap::new(), transactions: Vec::new(), splits: 0}
+    }
+
+    /// Account with given key, created under parent when it does not exist yet.
+    fn account(&mut self, key: String, name: &str, account_type: &'static str, currency: &str, parent: usize) -> usize {
+        if let Some(i) = self.index.get(&key) {
+            return *i;
+        }
+        let i = self.gnc_accounts.len();
+        // colon separates account names in GnuCash
+        self.gnc_accounts.push(GncAccount{guid: guid(GUID_ACCOUNT, i as u64), name: name.replace(':', "/"), account_type,
+            currency: currency.to_string(), parent: Some(self.gnc_accounts[parent].guid.clone())});
+        self.index.insert(key, i);
+        i
+    }
+
+    fn top(&mut self, account_type: &'static str) -> usize {
+        let base = self.base_currency.clone();
+        self.account(format!("top:{}", account_type), top_level(account_type), account_type, &base, 0)
+    }
+
+    fn real_account(&mut self, id: AccountId) -> Result<(usize, String), Error> {
+        let account = self.accounts.get(id)?;
+        let (name, currency) = (account.name.clone(), account.currency.clone());
+        let account_type = match account.get_group() {
+            AccountGroup::Cash => "CASH",
+            AccountGroup::Loans => "LIABILITY",
+            _ => "BANK"
+        };
+        let parent = self.top(if account_type == "LIABILITY" {"LIABILITY"} else {"ASSET"});
+        Ok((self.account(format!("account:{}", id), &name, account_type, &currency, parent), currency))
+    }
+
+    fn subcategory_account(&mut self, id: SubcategoryId, currency: &str) -> Result<usize, Error> {
+        let subcategory = self.subcategories.get(id)?;
+        let account_type = match (&subcategory.code, &subcategory.operation_code) {
+            (SubcategoryCode::Opbl, _) => "EQUITY",
+            (SubcategoryCode::Lnds | SubcategoryCode::Lnrp, _) => "LIABILITY",
+            (SubcategoryCode::Ibuy | SubcategoryCode::Isel, _) => "ASSET",
+            (SubcategoryCode::Idiv, _) | (_, SubcategoryOperationCode::Incm) => "INCOME",
+            _ => "EXPENSE"
+        };
+        let name = subcategory.name.clone();
+        let category = self.categories.get(subcategory.category)?;
+        let (category_key, category_name) = (format!("category:{}:{}", account_type, category.id), category.name.clone());
+        let base = self.base_currency.clone();
+        let top = self.top(account_type);
+        let parent = self.account(category_key, &category_name, account_type, &base, top);
+        let account = self.account(format!("subcategory:{}:{}", id, base), &name, account_type, &base, parent);
+        if currency == base {
+            return Ok(account);
+        }
+        Ok(self.account(format!("subcategory:{}:{}", id, currency), currency, account_type, currency, account))
+    }
+
+    fn add_transaction(&mut self, date: u64, currency: &str, description: &str, splits: &[(usize, i64, i64)]) {
+        let mut text = format!("<gnc:transaction version=\"2.0.0\">\n<trn:id type=\"guid\">{}</trn:id>\n{}\n\
+                                <trn:date-posted><ts:date>{} 10:59:00 +0000</ts:date></trn:date-posted>\n\
+                                <trn:date-entered><ts:date>{0} 10:59:00 +0000</ts:date></trn:date-entered>\n\
+                                <trn:description>{}</trn:description>\n<trn:splits>\n",
+                               guid(GUID_TRANSACTION, self.transactions.len() as u64 + 1), commodity("trn:currency", currency),
+                               format_date(date), escape_xml(description));
+        for (account, value, quantity) in splits {
+            self.splits += 1;
+            text += &format!("<trn:split>\n<split:id type=\"guid\">{}</split:id>\n\
+                              <split:reconciled-state>n</split:reconciled-state>\n<split:value>{}</split:value>\n\
+                              <split:quantity>{}</split:quantity>\n<split:account type=\"guid\">{}</split:account>\n\
+                              </trn:split>\n", guid(GUID_SPLIT, self.splits), amount(*value), amount(*quantity),
+                             self.gnc_accounts[*account].guid);
+        }
+        self.transactions.push(text + "</trn:splits>\n</gnc:transaction>\n");
+    }
+
+    /// Balance of an account before its first exported operation, e.g. of archived years.
+    pub fn add_opening_balance(&mut self, date: u64, account: AccountId, summa: i64) -> Result<(), Error> {
+        let (gnc_account, currency) = self.real_account(account)?;
+        let top = self.top("EQUITY");
+        let base = self.base_currency.clone();
+        let mut opening = self.account(format!("opening:{}", base), "Opening Balances", "EQUITY", &base, top);
+        if currency != base {
+            opening = self.account(format!("opening:{}", currency), &currency, "EQUITY", &currency, opening);
+        }
+        self.add_transaction(date, &currency, "Opening balance", &[(gnc_account, summa, summa), (opening, -summa, -summa)]);
+        Ok(())
+    }
+
+    /// Splits are the balance changes the operation makes, the rest goes to the subcategory account.
+    /// Change of an account in another currency, like in exchanges, balances the transaction instead.
+    pub fn add(&mut self, op: &FinanceOperation) -> Result<(), Error> {
+        let mut changes = FinanceChanges::empty();
+        op.apply(&mut changes, self.accounts, self.subcategories, self.handlers)?;
+        let (_, currency) = self.real_account(op.get_account())?;
+        let mut changes: Vec<(AccountId, i64)> = changes.iter()
+            .map(|(id, c)|(*id, c.get_income() - c.get_expenditure()))
+            .filter(|(_, delta)|*delta != 0)
+            .collect();
+        changes.sort();
+        let mut splits = Vec::new();
+        let mut foreign = Vec::new();
+        for (id, delta) in changes {
+            let (account, account_currency) = self.real_account(id)?;
+            if account_currency == currency {
+                splits.push((account, delta, delta));
+            } else {
+                foreign.push((account, delta));
+            }
+        }
+        let imbalance: i64 = -splits.iter().map(|(_, value, _)|value).sum::<i64>();
+        match foreign[..] {
+            [] if imbalance != 0 => {
+                let account = self.subcategory_account(op.get_subcategory(), &currency)?;
+                splits.push((account, imbalance, imbalance));
+            }
+            [] => {}
+            [(account, quantity)] => splits.push((account, imbalance, quantity)),
+            _ => return Err(Error::new(ErrorKind::Unsupported,
+                                       format!("operation at {} changes several accounts in other currencies", op.date)))
+        }
+        let payee = match op.get_payee() {
+            Some(p) => Some(self.payees.get(p)?.name.as_str()),
+            None => None
+        };
+        let description = match op.get_network().or(payee) {
+            Some(d) => d.to_string(),
+            None => self.subcategories.get(op.get_subcategory())?.name.clone()
+        };
+        self.add_transaction(op.date, &currency, &description, &splits);
+        Ok(())
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut currencies: Vec<&str> = self.gnc_accounts.iter().map(|a|a.currency.as_str()).collect();
+        currencies.sort();
+        currencies.dedup();
+        let mut result = "<?xml version=\"1.0\" encoding=\"utf-8\" ?>\n<gnc-v2\n\
+            xmlns:gnc=\"http://www.gnucash.org/XML/gnc\"\nxmlns:act=\"http://www.gnucash.org/XML/act\"\n\
+            xmlns:book=\"http://www.gnucash.org/XML/book\"\nxmlns:cd=\"http://www.gnucash.org/XML/cd\"\n\
+            xmlns:cmdty=\"http://www.gnucash.org/XML/cmdty\"\nxmlns:slot=\"http://www.gnucash.org/XML/slot\"\n\
+            xmlns:split=\"http://www.gnucash.org/XML/split\"\nxmlns:trn=\"http://www.gnucash.org/XML/trn\"\n\
+            xmlns:ts=\"http://www.gnucash.org/XML/ts\">\n<gnc:count-data cd:type=\"book\">1</gnc:count-data>\n"
+            .to_string();
+        result += &format!("<gnc:book version=\"2.0.0\">\n<book:id type=\"guid\">{}</book:id>\n", guid(GUID_BOOK, 1));
+        result += &format!("<gnc:count-data cd:type=\"commodity\">{}</gnc:count-data>\n\
+                            <gnc:count-data cd:type=\"account\">{}</gnc:count-data>\n\
+                            <gnc:count-data cd:type=\"transaction\">{}</gnc:count-data>\n",
+                           currencies.len(), self.gnc_accounts.len(), self.transactions.len());
+        for currency in currencies {
+            result += &format!("<gnc:commodity version=\"2.0.0\">\n<cmdty:space>CURRENCY</cmdty:space>\n\
+                                <cmdty:id>{}</cmdty:id>\n</gnc:commodity>\n", escape_xml(currency));
+        }
+        for a in &self.gnc_accounts {
+            result += &format!("<gnc:account version=\"2.0.0\">\n<act:name>{}</act:name>\n<act:id type=\"guid\">{}</act:id>\n\
+                                <act:type>{}</act:type>\n{}\n<act:commodity-scu>100</act:commodity-scu>\n",
+                               escape_xml(&a.name), a.guid, a.account_type, commodity("act:commodity", &a.currency));
+            if let Some(parent) = &a.parent {
+                result += &format!("<act:parent type=\"guid\">{}</act:parent>\n", parent);
+            }
+            result += "</gnc:account>\n";
+        }
+        for t in &self.transactions {
+            result += t;
+        }
+        result + "</gnc:book>\n</gnc-v2>\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::entities::accounts::{Account, AccountId, Accounts};
+    use crate::entities::finance_operations::{FinOpParameter, FinanceOperation, SpecialHandlers};
+    use crate::entities::payees::Payees;
+    use crate::entities::subcategories::{Categories, Category, CategoryId, Subcategories, Subcategory, SubcategoryCode,
+                                         SubcategoryId, SubcategoryOperationCode};
+    use crate::reports::gnucash::GnuCashExport;
+
+    #[test]
+    fn test_export() -> Result<(), Error> {
+        let accounts = Accounts::new(vec![Account::new(AccountId(1), "Card".to_string(), "EUR".to_string(), false),
+                                          Account::new(AccountId(2), "Cash".to_string(), "EUR".to_string(), true),
+                                          Account::new(AccountId(3), "Dollars".to_string(), "USD".to_string(), true)])?;
+        let categories = Categories::new(vec![Category{id: CategoryId(1), name: "Food".to_string(), tax_relevant: false},
+                                              Category{id: CategoryId(2), name: "Other".to_string(), tax_relevant: false}]);
+        let subcategory = |id, name: &str, category, code, operation_code|Subcategory{id: SubcategoryId(id),
+            name: name.to_string(), code, operation_code, category: CategoryId(category), tax_relevant: false, retired: false,
+            soft_limit: None, hard_limit: None};
+        let subcategories = Subcategories::new(vec![
+            subcategory(1, "Groceries", 1, SubcategoryCode::None, SubcategoryOperationCode::Expn),
+            subcategory(2, "Withdrawal", 2, SubcategoryCode::Expc, SubcategoryOperationCode::Spcl),
+            subcategory(3, "Exchange", 2, SubcategoryCode::Exch, SubcategoryOperationCode::Spcl)]);
+        let (payees, handlers) = (Payees::new(Vec::new()), SpecialHandlers::new());
+        let mut export = GnuCashExport::new(&accounts, &categories, &subcategories, &payees, &handlers, "EUR".to_string());
+        export.add_opening_balance(20240101, AccountId(1), 50000)?;
+        export.add(&FinanceOperation::new(20240105, AccountId(1), SubcategoryId(1), None, 1250, Vec::new()))?;
+        export.add(&FinanceOperation::new(20240106, AccountId(1), SubcategoryId(2), None, 2000, Vec::new()))?;
+        export.add(&FinanceOperation::new(20240107, AccountId(2), SubcategoryId(3), Some(100000), 10900,
+                                          vec![FinOpParameter::Seca(AccountId(3))]))?;
+        export.add(&FinanceOperation::new(20240108, AccountId(3), SubcategoryId(1), None, 500, Vec::new()))?;
+        let xml = export.to_xml();
+        // root, assets, card, equity, opening balances, expenses, food, groceries, cash, dollars, groceries in USD
+        assert!(xml.contains("<gnc:count-data cd:type=\"account\">11</gnc:count-data>"));
+        assert!(xml.contains("<gnc:count-data cd:type=\"transaction\">5</gnc:count-data>"));
+        assert!(xml.contains("<act:name>USD</act:name>"));
+        // exchange: 100.00 EUR leave the cash account, 109.00 USD arrive
+        assert!(xml.contains("<split:value>10000/100</split:value>\n<split:quantity>10900/100</split:quantity>"));
+        // withdrawal moves money between accounts only
+        let withdrawal = xml.split("<gnc:transaction").nth(3).unwrap();
+        assert_eq!(withdrawal.matches("<trn:split>").count(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use crate::core::dates::months_between;
+use crate::entities::finance_operations::FinanceChanges;
+use crate::entities::goals::{GoalId, Goals};
+
+pub struct GoalProgress {
+    pub goal: GoalId,
+    pub saved: i64,
+    pub remaining: i64,
+    /// saved amount in percents of target
+    pub percent: u64,
+    pub months_left: Option<u64>,
+    /// None when goal has no target date, equals remaining when target date is in the past
+    pub monthly_contribution: Option<i64>
+}
+
+pub fn build_goals_report(goals: &Goals, balances: &FinanceChanges, date: u64) -> Vec<GoalProgress> {
+    let mut result: Vec<GoalProgress> = goals.iter()
+        .map(|g|{
+            let saved: i64 = g.accounts.iter()
+                .map(|a|balances.get(*a).map(|c|c.get_end_balance()).unwrap_or(0))
+                .sum();
+            let remaining = (g.target - saved).max(0);
+            let percent = if g.target > 0 {(saved.max(0) as i128 * 100 / g.target as i128) as u64} else {100};
+            let months_left = g.target_date.map(|d|months_between(date, d));
+            let monthly_contribution = months_left.map(|m|
+                if m == 0 {remaining} else {(remaining + m as i64 - 1) / m as i64});
+            GoalProgress{goal: g.id, saved, remaining, percent, months_left, monthly_contribution}
+        })
+        .collect();
+    result.sort_by_key(|p|p.goal);
+    result
+}
@@ -0,0 +1,141 @@
+//! Self-contained HTML page of a period: summary by category and balance history as tables and
+//! inline SVG charts, without scripts or external files.
+
+use std::collections::BTreeMap;
+use std::io::Error;
+use crate::core::amounts::format_summa;
+use crate::entities::subcategories::{Categories, CategoryId};
+use crate::reports::escape_xml;
+use crate::reports::statement::format_date;
+use crate::reports::summary::{name_categories, SummaryItem};
+
+const CHART_WIDTH: i64 = 640;
+const BAR_HEIGHT: i64 = 18;
+const LABEL_WIDTH: i64 = 160;
+const LINE_CHART_HEIGHT: i64 = 240;
+const COLORS: [&str; 8] = ["#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#9c755f"];
+
+pub struct HtmlReport {
+    pub from: u64,
+    pub to: u64,
+    /// category names with their summaries, sorted by name
+    pub categories: Vec<(String, SummaryItem)>,
+    pub total: SummaryItem,
+    /// dates of the balance history, yyyymmdd
+    pub dates: Vec<u64>,
+    /// account names with their balances at every date
+    pub balances: Vec<(String, Vec<i64>)>
+}
+
+/// Horizontal bars scaled to the largest value, values are not negative.
+fn bar_chart(items: &[(&str, i64)]) -> String {
+    let max = items.iter().map(|(_, v)|*v).max().unwrap_or(0).max(1);
+    let bar_width = CHART_WIDTH - LABEL_WIDTH - 80;
+    let mut result = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-size=\"12\">\n",
+                             CHART_WIDTH, items.len() as i64 * BAR_HEIGHT);
+    for (i, (name, value)) in items.iter().enumerate() {
+        let y = i as i64 * BAR_HEIGHT;
+        let width = (bar_width * value / max).max(1);
+        result += &format!("<text x=\"0\" y=\"{}\">{}</text>\n", y + 13, escape_xml(name));
+        result += &format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n", LABEL_WIDTH, y + 2, width,
+                           BAR_HEIGHT - 4, COLORS[0]);
+        result += &format!("<text x=\"{}\" y=\"{}\">{}</text>\n", LABEL_WIDTH + width + 5, y + 13, format_summa(*value));
+    }
+    result + "</svg>\n"
+}
+
+/// One line per series over the dates, with zero line and legend.
+fn line_chart(dates: &[u64], series: &[(String, Vec<i64>)]) -> String {
+    let values = || series.iter().flat_map(|(_, v)|v.iter().copied());
+    let (min, max) = (values().min().unwrap_or(0).min(0), values().max().unwrap_or(0).max(0));
+    let range = (max - min).max(1);
+    let step = if dates.len() > 1 {(CHART_WIDTH - 20) / (dates.len() as i64 - 1)} else {0};
+    let y = |value: i64|10 + (max - value) * (LINE_CHART_HEIGHT - 20) / range;
+    let legend_height = series.len() as i64 * BAR_HEIGHT;
+    let mut result = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-size=\"12\">\n",
+                             CHART_WIDTH, LINE_CHART_HEIGHT + legend_height);
+    result += &format!("<line x1=\"10\" y1=\"{0}\" x2=\"{1}\" y2=\"{0}\" stroke=\"#999\"/>\n", y(0), CHART_WIDTH - 10);
+    for (i, (name, balances)) in series.iter().enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        let points: Vec<String> = balances.iter().enumerate()
+            .map(|(j, b)|format!("{},{}", 10 + j as i64 * step, y(*b)))
+            .collect();
+        result += &format!("<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>\n", points.join(" "), color);
+        let legend_y = LINE_CHART_HEIGHT + i as i64 * BAR_HEIGHT;
+        result += &format!("<rect x=\"10\" y=\"{}\" width=\"12\" height=\"12\" fill=\"{}\"/>\n", legend_y + 2, color);
+        result += &format!("<text x=\"28\" y=\"{}\">{}</text>\n", legend_y + 13, escape_xml(name));
+    }
+    result + "</svg>\n"
+}
+
+impl HtmlReport {
+    pub fn new(from: u64, to: u64, summary: &BTreeMap<CategoryId, SummaryItem>, categories: &Categories,
+               dates: Vec<u64>, balances: Vec<(String, Vec<i64>)>) -> Result<HtmlReport, Error> {
+        let (categories, total) = name_categories(summary, categories)?;
+        Ok(HtmlReport{from, to, categories, total, dates, balances})
+    }
+
+    pub fn to_html(&self) -> String {
+        let title = format!("Home accounting report {} - {}", format_date(self.from), format_date(self.to));
+        let mut result = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n", title);
+        result += "<style>body{font-family:sans-serif} table{border-collapse:collapse} \
+                   td,th{padding:2px 8px} td.n{text-align:right}</style>\n</head>\n<body>\n";
+        result += &format!("<h1>{}</h1>\n<h2>Categories</h2>\n<table>\n", title);
+        result += "<tr><th>Category</th><th>Income</th><th>Expenditure</th></tr>\n";
+        for (name, item) in &self.categories {
+            result += &format!("<tr><td>{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td></tr>\n", escape_xml(name),
+                               format_summa(item.income), format_summa(item.expenditure));
+        }
+        result += &format!("<tr><th>Total</th><th>{}</th><th>{}</th></tr>\n</table>\n",
+                           format_summa(self.total.income), format_summa(self.total.expenditure));
+        let expenses: Vec<(&str, i64)> = self.categories.iter()
+            .filter(|(_, i)|i.expenditure > 0)
+            .map(|(n, i)|(n.as_str(), i.expenditure))
+            .collect();
+        if !expenses.is_empty() {
+            result += &format!("<h2>Expenditure by category</h2>\n{}", bar_chart(&expenses));
+        }
+        if !self.dates.is_empty() && !self.balances.is_empty() {
+            result += &format!("<h2>Balance history</h2>\n{}<table>\n<tr><th>Account</th>", line_chart(&self.dates, &self.balances));
+            for date in &self.dates {
+                result += &format!("<th>{}</th>", format_date(*date));
+            }
+            result += "</tr>\n";
+            for (name, balances) in &self.balances {
+                result += &format!("<tr><td>{}</td>", escape_xml(name));
+                for b in balances {
+                    result += &format!("<td class=\"n\">{}</td>", format_summa(*b));
+                }
+                result += "</tr>\n";
+            }
+            result += "</table>\n";
+        }
+        result + "</body>\n</html>\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::Error;
+    use crate::entities::subcategories::{Categories, Category, CategoryId};
+    use crate::reports::html::HtmlReport;
+    use crate::reports::summary::SummaryItem;
+
+    #[test]
+    fn test_html() -> Result<(), Error> {
+        let categories = Categories::new(vec![Category{id: CategoryId(1), name: "Salary".to_string(), tax_relevant: false},
+                                              Category{id: CategoryId(2), name: "Food & <drinks>".to_string(), tax_relevant: false}]);
+        let summary = BTreeMap::from([(CategoryId(1), SummaryItem{income: 100000, expenditure: 0}),
+                                      (CategoryId(2), SummaryItem{income: 0, expenditure: 12345})]);
+        let report = HtmlReport::new(20240101, 20240229, &summary, &categories, vec![20240131, 20240229],
+                                     vec![("Card".to_string(), vec![-500, 87655])])?;
+        let html = report.to_html();
+        assert!(html.contains("<td>Food &amp; &lt;drinks&gt;</td><td class=\"n\">0.00</td><td class=\"n\">123.45</td>"));
+        assert!(html.contains("<rect x=\"160\" y=\"2\" width=\"400\" height=\"14\" fill=\"#4e79a7\"/>"));
+        // zero line is near the bottom, as only the first balance is negative
+        assert!(html.contains("<polyline points=\"10,230 630,10\""));
+        assert!(html.contains("<th>2024-02-29</th>"));
+        Ok(())
+    }
+}
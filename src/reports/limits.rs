@@ -0,0 +1,80 @@
+//! Monthly spending limits of subcategories. Soft limits are only reported, operations making
+//! expenditure exceed a hard limit are added with a warning.
+
+use std::collections::HashMap;
+use std::io::Error;
+use crate::core::amounts::format_summa;
+use crate::entities::finance_operations::FinanceOperation;
+use crate::entities::subcategories::{Subcategories, Subcategory, SubcategoryId};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LimitKind {
+    Soft,
+    Hard
+}
+
+#[derive(PartialEq, Debug)]
+pub struct LimitStatus {
+    pub subcategory: SubcategoryId,
+    pub kind: LimitKind,
+    pub limit: i64,
+    pub spent: i64
+}
+
+impl LimitStatus {
+    pub fn describe(&self, subcategories: &Subcategories) -> Result<String, Error> {
+        let kind = match self.kind {
+            LimitKind::Soft => "soft",
+            LimitKind::Hard => "hard"
+        };
+        Ok(format!("{}: spent {}, {} limit {} exceeded by {}", subcategories.get(self.subcategory)?.name,
+                   format_summa(self.spent), kind, format_summa(self.limit), format_summa(self.spent - self.limit)))
+    }
+}
+
+/// Exceeded limit of the subcategory, the hard one when both are exceeded.
+pub fn check_limits(subcategory: &Subcategory, spent: i64) -> Option<LimitStatus> {
+    [(LimitKind::Hard, subcategory.hard_limit), (LimitKind::Soft, subcategory.soft_limit)].into_iter()
+        .find_map(|(kind, limit)|limit.filter(|l|spent > *l)
+            .map(|limit|LimitStatus{subcategory: subcategory.id, kind, limit, spent}))
+}
+
+/// Subcategories with exceeded limits ordered by subcategory, operations have to belong to one month.
+pub fn build_limits_report<'a>(subcategories: &Subcategories, operations: impl Iterator<Item = &'a FinanceOperation>)
+    -> Vec<LimitStatus> {
+    let mut spent: HashMap<SubcategoryId, i64> = HashMap::new();
+    for op in operations {
+        *spent.entry(op.get_subcategory()).or_default() += op.get_summa();
+    }
+    let mut result: Vec<LimitStatus> = subcategories.iter()
+        .filter_map(|s|check_limits(s, spent.get(&s.id).copied().unwrap_or(0)))
+        .collect();
+    result.sort_by_key(|s|s.subcategory);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entities::accounts::AccountId;
+    use crate::entities::finance_operations::FinanceOperation;
+    use crate::entities::subcategories::{CategoryId, Subcategories, Subcategory, SubcategoryCode, SubcategoryId,
+                                         SubcategoryOperationCode};
+    use crate::reports::limits::{build_limits_report, LimitKind, LimitStatus};
+
+    #[test]
+    fn test_limits_report() {
+        let subcategory = |id, soft_limit, hard_limit|Subcategory{id: SubcategoryId(id), name: id.to_string(),
+            code: SubcategoryCode::None, operation_code: SubcategoryOperationCode::Expn, category: CategoryId(1),
+            tax_relevant: false, retired: false, soft_limit, hard_limit};
+        let subcategories = Subcategories::new(vec![subcategory(1, Some(1000), Some(2000)),
+                                                    subcategory(2, Some(1000), Some(2000)),
+                                                    subcategory(3, None, Some(500)), subcategory(4, None, None)]);
+        let operations: Vec<FinanceOperation> = [(1, 1500), (2, 1500), (2, 1000), (3, 500), (4, 9000)].iter()
+            .map(|(s, summa)|FinanceOperation::new(20240105, AccountId(1), SubcategoryId(*s), None, *summa, Vec::new()))
+            .collect();
+        assert_eq!(build_limits_report(&subcategories, operations.iter()), vec![
+            LimitStatus{subcategory: SubcategoryId(1), kind: LimitKind::Soft, limit: 1000, spent: 1500},
+            LimitStatus{subcategory: SubcategoryId(2), kind: LimitKind::Hard, limit: 2000, spent: 2500}
+        ]);
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::io::Error;
+use crate::core::dates::add_months;
+use crate::entities::accounts::AccountId;
+use crate::entities::finance_operations::FinanceOperation;
+use crate::entities::loans::{LoanId, Loans};
+use crate::entities::subcategories::{Subcategories, SubcategoryCode};
+
+pub struct LoanStatus {
+    pub loan: LoanId,
+    pub outstanding: i64,
+    pub interest_paid: i64,
+    /// None when loan can't be repaid with current monthly payment
+    pub payoff_date: Option<u64>
+}
+
+/// Outstanding balances at given date, operations must include all operations up to this date.
+/// Every loan counts operations on its account from its own start date up to the start of the next
+/// loan on the same account, so loans sharing an account don't inherit each other's payments.
+pub fn build_loans_report<'a>(loans: &Loans, operations: impl Iterator<Item = &'a FinanceOperation>,
+                              subcategories: &Subcategories, date: u64) -> Result<Vec<LoanStatus>, Error> {
+    let mut by_account: HashMap<AccountId, Vec<&FinanceOperation>> = HashMap::new();
+    for op in operations.filter(|op|op.date <= date) {
+        by_account.entry(op.get_account()).or_default().push(op);
+    }
+    let mut result = Vec::new();
+    for l in loans.iter().filter(|l|l.start.is_none_or(|s|s <= date)) {
+        let (mut debt, mut interest_paid) = (0, 0);
+        let from = l.start.unwrap_or(0);
+        let to = loans.iter().filter(|n|n.account == l.account).filter_map(|n|n.start).filter(|s|*s > from).min();
+        for op in by_account.get(&l.account).into_iter().flatten()
+            .filter(|op|op.date >= from && to.is_none_or(|to|op.date < to)) {
+            match subcategories.get(op.get_subcategory())?.code {
+                SubcategoryCode::Lnds => debt += op.get_summa(),
+                SubcategoryCode::Lnrp => debt -= op.get_summa(),
+                SubcategoryCode::Lnin => interest_paid += op.get_summa(),
+                _ => {}
+            }
+        }
+        let outstanding = l.principal + debt;
+        let payoff_date = l.project_payments(outstanding).map(|months|add_months(date, months));
+        result.push(LoanStatus{loan: l.id, outstanding, interest_paid, payoff_date});
+    }
+    result.sort_by_key(|s|s.loan);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::entities::accounts::AccountId;
+    use crate::entities::finance_operations::FinanceOperation;
+    use crate::entities::loans::{Loan, LoanId, Loans};
+    use crate::entities::subcategories::{CategoryId, Subcategories, Subcategory, SubcategoryCode, SubcategoryId,
+                                         SubcategoryOperationCode};
+    use crate::reports::loans::build_loans_report;
+
+    #[test]
+    fn test_loans_report() -> Result<(), Error> {
+        let subcategory = |id, code|Subcategory{id: SubcategoryId(id), name: id.to_string(), code,
+            operation_code: SubcategoryOperationCode::Expn, category: CategoryId(1), tax_relevant: false,
+            retired: false, soft_limit: None, hard_limit: None};
+        let subcategories = Subcategories::new(vec![subcategory(1, SubcategoryCode::Lnds),
+                                                    subcategory(2, SubcategoryCode::Lnrp),
+                                                    subcategory(3, SubcategoryCode::Lnin)]);
+        let loan = |id, account, start|Loan{id: LoanId(id), name: id.to_string(), account: AccountId(account),
+            principal: 100000, rate: 0, start, monthly_payment: 10000};
+        // loan 2 replaced loan 1 on account 1, loan 3 has no start date
+        let loans = Loans::new(vec![loan(1, 1, Some(20230101)), loan(2, 1, Some(20240101)), loan(3, 2, None),
+                                    loan(4, 2, Some(20250101))]);
+        let operations: Vec<FinanceOperation> = [(20230201, 1, 2, 30000), (20230301, 1, 3, 500), (20240201, 1, 1, 5000),
+                                                 (20240301, 1, 2, 10000), (20220101, 2, 2, 20000)].iter()
+            .map(|(date, account, s, summa)|FinanceOperation::new(*date, AccountId(*account), SubcategoryId(*s), None,
+                                                                  *summa, Vec::new()))
+            .collect();
+        let report = build_loans_report(&loans, operations.iter(), &subcategories, 20240331)?;
+        let statuses: Vec<(LoanId, i64, i64, Option<u64>)> = report.iter()
+            .map(|s|(s.loan, s.outstanding, s.interest_paid, s.payoff_date))
+            .collect();
+        assert_eq!(statuses, vec![(LoanId(1), 70000, 500, Some(20241031)), (LoanId(2), 95000, 0, Some(20250131)),
+                                  (LoanId(3), 80000, 0, Some(20241130))]);
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+//! Rough estimation of resident memory, helps to choose max_active_items.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use crate::entities::accounts::{AccountId, Accounts};
+use crate::entities::finance_operations::{FinOpParameter, FinanceOperation, FinanceRecord, ParameterValue};
+use crate::entities::payees::Payees;
+use crate::entities::subcategories::{Categories, Subcategories};
+
+#[derive(Default, Debug)]
+pub struct MemoryReport {
+    pub months: usize,
+    pub operations: usize,
+    pub months_bytes: usize,
+    pub max_month_bytes: usize,
+    pub dictionaries_bytes: usize,
+    /// shared NETW and TYPE strings, counted once
+    pub strings_bytes: usize
+}
+
+impl MemoryReport {
+    pub fn average_month_bytes(&self) -> usize {
+        self.months_bytes.checked_div(self.months).unwrap_or(0)
+    }
+
+    /// Expected memory usage when at most max_active_items months are cached.
+    pub fn estimate(&self, max_active_items: usize) -> usize {
+        self.dictionaries_bytes + self.strings_bytes + self.average_month_bytes() * max_active_items.min(self.months)
+    }
+
+    pub fn add_record(&mut self, record: &FinanceRecord) {
+        let bytes = record_bytes(record);
+        self.months += 1;
+        self.operations += record.operations.len();
+        self.months_bytes += bytes;
+        self.max_month_bytes = self.max_month_bytes.max(bytes);
+    }
+}
+
+fn operation_bytes(op: &FinanceOperation) -> usize {
+    let parameters = op.get_parameters();
+    let mut bytes = size_of::<FinanceOperation>();
+    if parameters.len() > 1 {
+        bytes += size_of_val(parameters);
+    }
+    for p in parameters {
+        if let FinOpParameter::Custom(c) = p {
+            bytes += size_of_val(c.as_ref()) + c.code.len();
+            if let ParameterValue::String(s) = &c.value {
+                bytes += s.len();
+            }
+        }
+    }
+    bytes + op.get_attachments().iter().map(|a|size_of::<String>() + a.len()).sum::<usize>()
+}
+
+fn record_bytes(record: &FinanceRecord) -> usize {
+    size_of::<FinanceRecord>() + record.operations.iter().map(operation_bytes).sum::<usize>() +
+        record.totals.capacity() * (size_of::<(AccountId, i64)>() + 1)
+}
+
+/// Adds sizes of interned strings used by records, every string is counted once.
+pub fn add_shared_strings<'a>(report: &mut MemoryReport, records: impl Iterator<Item = &'a FinanceRecord>) {
+    let mut seen = HashSet::new();
+    for record in records {
+        for op in &record.operations {
+            for p in op.get_parameters() {
+                if let FinOpParameter::Netw(s) | FinOpParameter::Typ(s) = p {
+                    if seen.insert(Arc::as_ptr(s) as *const u8) {
+                        report.strings_bytes += s.len() + 2 * size_of::<usize>();
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn dictionaries_bytes(accounts: &Accounts, categories: &Categories, subcategories: &Subcategories,
+                          payees: &Payees) -> usize {
+    accounts.iter().map(|a|size_of_val(a) + a.name.len()).sum::<usize>() +
+        categories.iter().map(|c|size_of_val(c) + c.name.len()).sum::<usize>() +
+        subcategories.iter().map(|s|size_of_val(s) + s.name.len()).sum::<usize>() +
+        payees.iter().map(|p|size_of_val(p) + p.name.len()).sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reports::memory::MemoryReport;
+
+    #[test]
+    fn test_estimate() {
+        let report = MemoryReport{months: 10, operations: 100, months_bytes: 10000, max_month_bytes: 2000,
+            dictionaries_bytes: 500, strings_bytes: 50};
+        assert_eq!(report.average_month_bytes(), 1000);
+        assert_eq!(report.estimate(3), 3550);
+        assert_eq!(report.estimate(100), 10550);
+        assert_eq!(MemoryReport::default().estimate(5), 0);
+    }
+}
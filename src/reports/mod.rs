@@ -0,0 +1,34 @@
+pub mod summary;
+pub mod closed_accounts;
+pub mod loans;
+pub mod net_worth;
+pub mod goals;
+pub mod memory;
+pub mod monthly;
+pub mod statement;
+pub mod anomalies;
+pub mod tax;
+pub mod alerts;
+pub mod budget;
+pub mod limits;
+pub mod annual;
+pub mod html;
+pub mod dictionaries;
+pub mod gnucash;
+pub mod custom;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+
+/// Quotes CSV field when it contains separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Escapes text for XML and HTML content and attribute values.
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,62 @@
+//! Plain text monthly summary used by scheduled report delivery.
+
+use std::collections::BTreeMap;
+use std::io::Error;
+use crate::core::amounts::format_summa;
+use crate::entities::subcategories::{Categories, CategoryId};
+use crate::reports::summary::{name_categories, SummaryItem};
+
+pub struct MonthlyReport {
+    /// yyyymm
+    pub month: u64,
+    /// category names with their summaries, sorted by name
+    pub categories: Vec<(String, SummaryItem)>,
+    pub total: SummaryItem
+}
+
+impl MonthlyReport {
+    pub fn new(month: u64, summary: &BTreeMap<CategoryId, SummaryItem>, categories: &Categories)
+        -> Result<MonthlyReport, Error> {
+        let (items, total) = name_categories(summary, categories)?;
+        Ok(MonthlyReport{month, categories: items, total})
+    }
+
+    pub fn subject(&self) -> String {
+        format!("Home accounting report for {}-{:02}", self.month / 100, self.month % 100)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut result = format!("Income: {}\nExpenditure: {}\n\n", format_summa(self.total.income),
+                                 format_summa(self.total.expenditure));
+        for (name, item) in &self.categories {
+            if item.income != 0 {
+                result += &format!("{}: +{}\n", name, format_summa(item.income));
+            }
+            if item.expenditure != 0 {
+                result += &format!("{}: -{}\n", name, format_summa(item.expenditure));
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::Error;
+    use crate::entities::subcategories::{Categories, Category, CategoryId};
+    use crate::reports::monthly::MonthlyReport;
+    use crate::reports::summary::SummaryItem;
+
+    #[test]
+    fn test_report_text() -> Result<(), Error> {
+        let categories = Categories::new(vec![Category{id: CategoryId(1), name: "Salary".to_string(), tax_relevant: false},
+                                              Category{id: CategoryId(2), name: "Food".to_string(), tax_relevant: false}]);
+        let summary = BTreeMap::from([(CategoryId(1), SummaryItem{income: 100000, expenditure: 0}),
+                                      (CategoryId(2), SummaryItem{income: 0, expenditure: 12345})]);
+        let report = MonthlyReport::new(202403, &summary, &categories)?;
+        assert_eq!(report.subject(), "Home accounting report for 2024-03");
+        assert_eq!(report.to_text(), "Income: 1000.00\nExpenditure: 123.45\n\nFood: -123.45\nSalary: +1000.00\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Error;
+use crate::entities::accounts::{AccountId, Accounts};
+use crate::entities::finance_operations::{FinanceChanges, FinanceOperation};
+use crate::entities::instruments::{InstrumentId, Instruments};
+use crate::entities::rates::Rates;
+use crate::entities::subcategories::{Subcategories, SubcategoryCode};
+
+pub struct AccountWorth {
+    pub account: AccountId,
+    pub balance: i64,
+    /// market value of securities held on the account
+    pub securities: i64
+}
+
+pub struct NetWorth {
+    pub accounts: Vec<AccountWorth>,
+    /// totals by currency code
+    pub totals: BTreeMap<String, i64>,
+    /// sum of totals in base currency, set by convert_totals
+    pub base_total: Option<i64>
+}
+
+impl NetWorth {
+    /// Converts currency totals to base currency using rates at given date.
+    pub fn convert_totals(&mut self, base: &str, rates: &Rates, date: u64) -> Result<(), Error> {
+        let mut total = 0;
+        for (currency, summa) in &self.totals {
+            total += rates.convert(*summa, currency, base, date)?;
+        }
+        self.base_total = Some(total);
+        Ok(())
+    }
+}
+
+/// Quantities of instruments held on accounts, quantity is in thousandths.
+pub fn build_holdings<'a>(operations: impl Iterator<Item = &'a FinanceOperation>, subcategories: &Subcategories)
+    -> Result<HashMap<(AccountId, InstrumentId), i64>, Error> {
+    let mut result: HashMap<(AccountId, InstrumentId), i64> = HashMap::new();
+    for op in operations {
+        let Some(instrument) = op.get_instrument() else { continue };
+        let quantity = op.get_amount().unwrap_or(0) as i64;
+        match subcategories.get(op.get_subcategory())?.code {
+            SubcategoryCode::Ibuy => *result.entry((op.get_account(), instrument)).or_default() += quantity,
+            SubcategoryCode::Isel => *result.entry((op.get_account(), instrument)).or_default() -= quantity,
+            _ => {}
+        }
+    }
+    Ok(result)
+}
+
+/// Account balances plus securities valued at latest known market prices.
+/// Securities without known price are valued at zero.
+pub fn build_net_worth(accounts: &Accounts, balances: &FinanceChanges, holdings: &HashMap<(AccountId, InstrumentId), i64>,
+                       instruments: &Instruments, date: u64) -> Result<NetWorth, Error> {
+    let mut securities: HashMap<AccountId, i64> = HashMap::new();
+    for ((account, instrument), quantity) in holdings {
+        let price = instruments.get_price(*instrument, date).unwrap_or(0);
+        *securities.entry(*account).or_default() += ((*quantity as i128 * price as i128) / 1000) as i64;
+    }
+    let mut result = NetWorth{accounts: Vec::new(), totals: BTreeMap::new(), base_total: None};
+    for account in accounts.ordered()? {
+        let balance = balances.get(account.id).map(|c|c.get_end_balance()).unwrap_or(0);
+        let s = securities.get(&account.id).copied().unwrap_or(0);
+        if balance == 0 && s == 0 {
+            continue;
+        }
+        *result.totals.entry(account.currency_at(date).to_string()).or_default() += balance + s;
+        result.accounts.push(AccountWorth{account: account.id, balance, securities: s});
+    }
+    Ok(result)
+}
@@ -0,0 +1,241 @@
+//! PDF rendering of statements and annual reports. Documents use the standard Helvetica font, which
+//! covers Latin characters only, other characters are replaced by question marks.
+
+use crate::core::amounts::format_summa;
+use crate::reports::annual::AnnualReport;
+use crate::reports::statement::{format_date, Statement};
+
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 40.0;
+const ROW_HEIGHT: f32 = 14.0;
+const FONT_SIZE: f32 = 9.0;
+
+/// Minimal PDF writer: A4 pages with text, lines and filled rectangles, y grows downwards from the top.
+pub struct PdfDocument {
+    pages: Vec<String>,
+    /// position of the next line on the current page
+    y: f32
+}
+
+/// Approximate width of Helvetica text in font size units.
+fn text_width(text: &str, size: f32) -> f32 {
+    text.chars().map(|c|match c {
+        '.' | ',' | ' ' => 0.278,
+        '-' => 0.333,
+        '+' => 0.584,
+        '0'..='9' => 0.556,
+        _ => 0.6
+    }).sum::<f32>() * size
+}
+
+/// Latin-1 characters are written as octal escapes of WinAnsiEncoding, the rest are replaced.
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                result.push('\\');
+                result.push(c);
+            }
+            ' '..='~' => result.push(c),
+            '\u{a0}'..='\u{ff}' => result += &format!("\\{:03o}", c as u32),
+            _ => result.push('?')
+        }
+    }
+    result
+}
+
+impl Default for PdfDocument {
+    fn default() -> Self {
+        PdfDocument::new()
+    }
+}
+
+impl PdfDocument {
+    pub fn new() -> PdfDocument {
+        PdfDocument{pages: vec![String::new()], y: MARGIN}
+    }
+
+    pub fn new_page(&mut self) {
+        self.pages.push(String::new());
+        self.y = MARGIN;
+    }
+
+    /// Moves to the next line, starting a new page when the current one is full.
+    pub fn next_line(&mut self, height: f32) -> f32 {
+        if self.y + height > PAGE_HEIGHT - MARGIN {
+            self.new_page();
+        }
+        let y = self.y;
+        self.y += height;
+        y
+    }
+
+    fn content(&mut self) -> &mut String {
+        self.pages.last_mut().unwrap()
+    }
+
+    pub fn text(&mut self, x: f32, y: f32, size: f32, bold: bool, text: &str) {
+        let font = if bold {"F2"} else {"F1"};
+        let line = format!("BT /{} {} Tf {:.1} {:.1} Td ({}) Tj ET\n", font, size, x, PAGE_HEIGHT - y - size, escape(text));
+        self.content().push_str(&line);
+    }
+
+    /// Text ending at x.
+    pub fn text_right(&mut self, x: f32, y: f32, size: f32, text: &str) {
+        self.text(x - text_width(text, size), y, size, false, text);
+    }
+
+    pub fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) {
+        let line = format!("{:.1} {:.1} m {:.1} {:.1} l S\n", x1, PAGE_HEIGHT - y1, x2, PAGE_HEIGHT - y2);
+        self.content().push_str(&line);
+    }
+
+    /// Rectangle filled with gray level from 0 (black) to 1 (white).
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, gray: f32) {
+        let line = format!("{:.2} g {:.1} {:.1} {:.1} {:.1} re f 0 g\n", gray, x, PAGE_HEIGHT - y - height, width, height);
+        self.content().push_str(&line);
+    }
+
+    /// Row of cells, numbers are right aligned to the end of their columns.
+    fn row(&mut self, columns: &[(f32, f32)], cells: &[String], numeric: &[bool], bold: bool) {
+        let y = self.next_line(ROW_HEIGHT);
+        for (((x, width), cell), numeric) in columns.iter().zip(cells).zip(numeric) {
+            if *numeric {
+                self.text_right(x + width, y, FONT_SIZE, cell);
+            } else {
+                self.text(*x, y, FONT_SIZE, bold, cell);
+            }
+        }
+    }
+
+    fn title(&mut self, text: &str) {
+        let y = self.next_line(24.0);
+        self.text(MARGIN, y, 14.0, true, text);
+    }
+
+    fn rule(&mut self) {
+        let y = self.next_line(4.0);
+        self.line(MARGIN, y, PAGE_WIDTH - MARGIN, y);
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            String::new(),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_string(),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_string()
+        ];
+        let mut kids = Vec::new();
+        for content in &self.pages {
+            let page = objects.len() + 1;
+            kids.push(format!("{} 0 R", page));
+            objects.push(format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                                 PAGE_WIDTH, PAGE_HEIGHT, page + 1));
+            objects.push(format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content));
+        }
+        objects[1] = format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), kids.len());
+        let mut result = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(result.len());
+            result.extend(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+        }
+        let xref = result.len();
+        result.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            result.extend(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        result.extend(format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref)
+            .as_bytes());
+        result
+    }
+}
+
+pub fn statement_to_pdf(statement: &Statement) -> Vec<u8> {
+    let mut pdf = PdfDocument::new();
+    pdf.title(&format!("Statement of {} ({}) for {}-{:02}", statement.account, statement.currency,
+                       statement.month / 100, statement.month % 100));
+    let columns = [(MARGIN, 60.0), (110.0, 120.0), (235.0, 120.0), (360.0, 60.0), (425.0, 60.0), (490.0, 65.0)];
+    let numeric = [false, false, false, true, true, true];
+    let header = ["Date", "Subcategory", "Description", "Income", "Expenditure", "Balance"].map(|h|h.to_string());
+    pdf.row(&columns, &header, &[false; 6], true);
+    pdf.rule();
+    pdf.row(&columns, &[String::new(), "Opening balance".to_string(), String::new(), String::new(), String::new(),
+        statement.format(statement.opening_balance)], &numeric, false);
+    let amount = |summa: i64|if summa == 0 {String::new()} else {statement.format(summa)};
+    for line in &statement.lines {
+        pdf.row(&columns, &[format_date(line.date), line.subcategory.clone(), line.description.clone(),
+            amount(line.income), amount(line.expenditure), statement.format(line.balance)], &numeric, false);
+    }
+    pdf.rule();
+    pdf.row(&columns, &[String::new(), "Closing balance".to_string(), String::new(), String::new(), String::new(),
+        statement.format(statement.closing_balance)], &numeric, true);
+    pdf.to_bytes()
+}
+
+/// Balances table, category table and bar chart of expenditure by category.
+pub fn annual_report_to_pdf(report: &AnnualReport) -> Vec<u8> {
+    let mut pdf = PdfDocument::new();
+    pdf.title(&format!("Annual report for {}", report.year));
+    let columns = [(MARGIN, 250.0), (295.0, 40.0), (340.0, 100.0), (445.0, 110.0)];
+    pdf.row(&columns, &["Account", "", "Opening balance", "Closing balance"].map(|h|h.to_string()), &[false; 4], true);
+    pdf.rule();
+    for b in &report.balances {
+        pdf.row(&columns, &[b.account.clone(), b.currency.clone(), format_summa(b.opening), format_summa(b.closing)],
+                &[false, false, true, true], false);
+    }
+    pdf.next_line(ROW_HEIGHT);
+    let columns = [(MARGIN, 300.0), (345.0, 100.0), (450.0, 105.0)];
+    let numeric = [false, true, true];
+    pdf.row(&columns, &["Category", "Income", "Expenditure"].map(|h|h.to_string()), &[false; 3], true);
+    pdf.rule();
+    for (name, item) in &report.categories {
+        pdf.row(&columns, &[name.clone(), format_summa(item.income), format_summa(item.expenditure)], &numeric, false);
+    }
+    pdf.rule();
+    pdf.row(&columns, &["Total".to_string(), format_summa(report.total.income), format_summa(report.total.expenditure)],
+            &numeric, true);
+    let expenses: Vec<&(String, _)> = report.categories.iter().filter(|(_, i)|i.expenditure > 0).collect();
+    let max = expenses.iter().map(|(_, i)|i.expenditure).max().unwrap_or(0);
+    if max > 0 {
+        pdf.next_line(ROW_HEIGHT);
+        let y = pdf.next_line(24.0);
+        pdf.text(MARGIN, y, 12.0, true, "Expenditure by category");
+        let bar_x = MARGIN + 160.0;
+        let bar_width = PAGE_WIDTH - MARGIN - bar_x - 70.0;
+        for (name, item) in expenses {
+            let y = pdf.next_line(ROW_HEIGHT);
+            pdf.text(MARGIN, y, FONT_SIZE, false, name);
+            let width = (bar_width * item.expenditure as f32 / max as f32).max(1.0);
+            pdf.rect(bar_x, y, width, FONT_SIZE, 0.6);
+            pdf.text(bar_x + width + 5.0, y, FONT_SIZE, false, &format_summa(item.expenditure));
+        }
+    }
+    pdf.to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reports::pdf::{escape, statement_to_pdf};
+    use crate::reports::statement::Statement;
+
+    #[test]
+    fn test_document() {
+        assert_eq!(escape("a(b)\\ é ї"), "a\\(b\\)\\\\ \\351 ?");
+        let mut statement = Statement::new("Card".to_string(), "UAH".to_string(), 2, 202403, 10000);
+        for day in 1..=100 {
+            statement.add(20240300 + day % 28 + 1, "Groceries".to_string(), "SILPO".to_string(), 0, 150);
+        }
+        let data = statement_to_pdf(&statement);
+        let text = String::from_utf8(data).unwrap();
+        assert!(text.starts_with("%PDF-1.4\n") && text.ends_with("%%EOF\n"));
+        assert!(text.contains("/Count 2 "));
+        assert!(text.contains("(Closing balance) Tj"));
+        // xref offsets point to the objects
+        let xref: usize = text.lines().rev().nth(1).unwrap().parse().unwrap();
+        let offset: usize = text[xref..].lines().nth(4).unwrap()[..10].parse().unwrap();
+        assert!(text[offset..].starts_with("2 0 obj"));
+    }
+}
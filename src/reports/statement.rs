@@ -0,0 +1,95 @@
+//! Statement of one account for one month with running balance.
+
+use serde::Serialize;
+use crate::core::amounts::format_decimal;
+use crate::reports::csv_field;
+
+#[derive(Serialize)]
+pub struct StatementLine {
+    pub date: u64,
+    pub subcategory: String,
+    /// NETW parameter or payee name
+    pub description: String,
+    pub income: i64,
+    pub expenditure: i64,
+    pub balance: i64
+}
+
+#[derive(Serialize)]
+pub struct Statement {
+    pub account: String,
+    pub currency: String,
+    /// sums are in minor units of the currency
+    pub minor_units: u32,
+    /// yyyymm
+    pub month: u64,
+    pub opening_balance: i64,
+    pub lines: Vec<StatementLine>,
+    pub closing_balance: i64
+}
+
+pub(crate) fn format_date(date: u64) -> String {
+    format!("{}-{:02}-{:02}", date / 10000, date / 100 % 100, date % 100)
+}
+
+impl Statement {
+    pub fn new(account: String, currency: String, minor_units: u32, month: u64, opening_balance: i64) -> Statement {
+        Statement{account, currency, minor_units, month, opening_balance, lines: Vec::new(),
+            closing_balance: opening_balance}
+    }
+
+    pub fn format(&self, summa: i64) -> String {
+        format_decimal(summa, self.minor_units)
+    }
+
+    pub fn add(&mut self, date: u64, subcategory: String, description: String, income: i64, expenditure: i64) {
+        self.closing_balance += income - expenditure;
+        self.lines.push(StatementLine{date, subcategory, description, income, expenditure,
+            balance: self.closing_balance});
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut result = format!("Statement of {} ({}) for {}-{:02}\nOpening balance: {}\n", self.account,
+                                 self.currency, self.month / 100, self.month % 100, self.format(self.opening_balance));
+        for line in &self.lines {
+            let change = line.income - line.expenditure;
+            result += &format!("{} {} {} {}{} {}\n", format_date(line.date), line.subcategory, line.description,
+                               if change >= 0 {"+"} else {""}, self.format(change), self.format(line.balance));
+        }
+        result + &format!("Closing balance: {}\n", self.format(self.closing_balance))
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut result = "date,subcategory,description,income,expenditure,balance\n".to_string();
+        result += &format!("{},,Opening balance,,,{}\n", format_date(self.month * 100 + 1),
+                           self.format(self.opening_balance));
+        for line in &self.lines {
+            result += &format!("{},{},{},{},{},{}\n", format_date(line.date), csv_field(&line.subcategory),
+                               csv_field(&line.description), self.format(line.income),
+                               self.format(line.expenditure), self.format(line.balance));
+        }
+        result
+    }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> Result<String, std::io::Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reports::statement::Statement;
+
+    #[test]
+    fn test_statement() {
+        let mut statement = Statement::new("Card".to_string(), "UAH".to_string(), 2, 202403, 10000);
+        statement.add(20240301, "Salary".to_string(), String::new(), 50000, 0);
+        statement.add(20240305, "Groceries".to_string(), "Shop, \"Corner\"".to_string(), 0, 1250);
+        assert_eq!(statement.closing_balance, 58750);
+        assert_eq!(statement.to_text(), "Statement of Card (UAH) for 2024-03\nOpening balance: 100.00\n\
+            2024-03-01 Salary  +500.00 600.00\n2024-03-05 Groceries Shop, \"Corner\" -12.50 587.50\n\
+            Closing balance: 587.50\n");
+        assert_eq!(statement.to_csv().lines().nth(3), Some("2024-03-05,Groceries,\"Shop, \"\"Corner\"\"\",0.00,12.50,587.50"));
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+use std::io::Error;
+use crate::entities::finance_operations::FinanceOperation;
+use crate::entities::payees::PayeeId;
+use crate::entities::subcategories::{Categories, CategoryId, Subcategories, SubcategoryId, SubcategoryOperationCode};
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub struct SummaryItem {
+    pub income: i64,
+    pub expenditure: i64
+}
+
+impl SummaryItem {
+    fn add(&mut self, operation_code: &SubcategoryOperationCode, summa: i64) {
+        match operation_code {
+            SubcategoryOperationCode::Incm => self.income += summa,
+            SubcategoryOperationCode::Expn => self.expenditure += summa,
+            // transfers and exchanges do not change income or expenditure
+            SubcategoryOperationCode::Spcl => {}
+        }
+    }
+}
+
+pub fn build_subcategory_summary<'a>(operations: impl Iterator<Item = &'a FinanceOperation>,
+                                     subcategories: &Subcategories)
+    -> Result<BTreeMap<SubcategoryId, SummaryItem>, Error> {
+    let mut result: BTreeMap<SubcategoryId, SummaryItem> = BTreeMap::new();
+    for op in operations {
+        let subcategory = subcategories.get(op.get_subcategory())?;
+        result.entry(subcategory.id).or_default().add(&subcategory.operation_code, op.get_summa());
+    }
+    Ok(result)
+}
+
+pub fn build_category_summary<'a>(operations: impl Iterator<Item = &'a FinanceOperation>,
+                                  subcategories: &Subcategories)
+    -> Result<BTreeMap<CategoryId, SummaryItem>, Error> {
+    let mut result: BTreeMap<CategoryId, SummaryItem> = BTreeMap::new();
+    for op in operations {
+        let subcategory = subcategories.get(op.get_subcategory())?;
+        result.entry(subcategory.category).or_default().add(&subcategory.operation_code, op.get_summa());
+    }
+    Ok(result)
+}
+
+/// Category names with their summaries sorted by name, and the total of all categories.
+pub fn name_categories(summary: &BTreeMap<CategoryId, SummaryItem>, categories: &Categories)
+    -> Result<(Vec<(String, SummaryItem)>, SummaryItem), Error> {
+    let mut total = SummaryItem::default();
+    let mut items = Vec::new();
+    for (id, item) in summary {
+        total.income += item.income;
+        total.expenditure += item.expenditure;
+        items.push((categories.get(*id)?.name.clone(), *item));
+    }
+    items.sort_by(|a, b|a.0.cmp(&b.0));
+    Ok((items, total))
+}
+
+/// Operations without payee are not included.
+pub fn build_payee_summary<'a>(operations: impl Iterator<Item = &'a FinanceOperation>,
+                               subcategories: &Subcategories)
+    -> Result<BTreeMap<PayeeId, SummaryItem>, Error> {
+    let mut result: BTreeMap<PayeeId, SummaryItem> = BTreeMap::new();
+    for op in operations {
+        if let Some(payee) = op.get_payee() {
+            let subcategory = subcategories.get(op.get_subcategory())?;
+            result.entry(payee).or_default().add(&subcategory.operation_code, op.get_summa());
+        }
+    }
+    Ok(result)
+}
+
+/// Summary by month (yyyymm) and NETW parameter, operations without NETW are not included.
+pub fn build_network_summary<'a>(operations: impl Iterator<Item = &'a FinanceOperation>,
+                                 subcategories: &Subcategories)
+    -> Result<BTreeMap<(u64, String), SummaryItem>, Error> {
+    let mut result: BTreeMap<(u64, String), SummaryItem> = BTreeMap::new();
+    for op in operations {
+        if let Some(network) = op.get_network() {
+            let subcategory = subcategories.get(op.get_subcategory())?;
+            result.entry((op.date / 100, network.to_string())).or_default()
+                .add(&subcategory.operation_code, op.get_summa());
+        }
+    }
+    Ok(result)
+}
@@ -0,0 +1,81 @@
+//! Yearly report of tax deductible expenses for the accountant.
+
+use std::collections::BTreeMap;
+use std::io::Error;
+use crate::core::amounts::format_summa;
+use crate::entities::accounts::Accounts;
+use crate::entities::finance_operations::FinanceOperation;
+use crate::entities::subcategories::{Categories, Subcategories, SubcategoryId, SubcategoryOperationCode};
+use crate::reports::csv_field;
+use crate::reports::statement::format_date;
+
+pub struct TaxOperation {
+    pub date: u64,
+    pub account: String,
+    /// NETW parameter
+    pub description: String,
+    pub summa: i64
+}
+
+pub struct TaxItem {
+    pub subcategory: String,
+    pub total: i64,
+    pub operations: Vec<TaxOperation>
+}
+
+pub struct TaxReport {
+    pub year: u64,
+    /// sorted by subcategory name
+    pub items: Vec<TaxItem>,
+    pub total: i64
+}
+
+/// Expenses of tax relevant subcategories with supporting operations.
+pub fn build_tax_report<'a>(year: u64, operations: impl Iterator<Item = &'a FinanceOperation>, accounts: &Accounts,
+                            subcategories: &Subcategories, categories: &Categories) -> Result<TaxReport, Error> {
+    let mut items: BTreeMap<SubcategoryId, TaxItem> = BTreeMap::new();
+    for op in operations.filter(|op|op.date / 10000 == year) {
+        let subcategory = subcategories.get(op.get_subcategory())?;
+        if !matches!(subcategory.operation_code, SubcategoryOperationCode::Expn) ||
+            !subcategories.is_tax_relevant(subcategory.id, categories)? {
+            continue;
+        }
+        let item = items.entry(subcategory.id)
+            .or_insert_with(||TaxItem{subcategory: subcategory.name.clone(), total: 0, operations: Vec::new()});
+        item.total += op.get_summa();
+        item.operations.push(TaxOperation{date: op.date, account: accounts.get(op.get_account())?.name.clone(),
+            description: op.get_network().unwrap_or_default().to_string(), summa: op.get_summa()});
+    }
+    let mut items: Vec<TaxItem> = items.into_values().collect();
+    items.sort_by(|a, b|a.subcategory.cmp(&b.subcategory));
+    for item in items.iter_mut() {
+        item.operations.sort_by_key(|op|op.date);
+    }
+    let total = items.iter().map(|i|i.total).sum();
+    Ok(TaxReport{year, items, total})
+}
+
+impl TaxReport {
+    pub fn to_text(&self) -> String {
+        let mut result = format!("Tax deductible expenses for {}: {}\n", self.year, format_summa(self.total));
+        for item in &self.items {
+            result += &format!("\n{}: {}\n", item.subcategory, format_summa(item.total));
+            for op in &item.operations {
+                result += &format!("  {} {} {} {}\n", format_date(op.date), op.account, op.description,
+                                   format_summa(op.summa));
+            }
+        }
+        result
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut result = "date,subcategory,account,description,summa\n".to_string();
+        for item in &self.items {
+            for op in &item.operations {
+                result += &format!("{},{},{},{},{}\n", format_date(op.date), csv_field(&item.subcategory),
+                                   csv_field(&op.account), csv_field(&op.description), format_summa(op.summa));
+            }
+        }
+        result
+    }
+}
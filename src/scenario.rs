@@ -0,0 +1,65 @@
+//! What-if scenarios. Hypothetical operations like a planned purchase or a changed salary are kept
+//! in memory and applied on top of real operations by scenario reports, nothing is written to disk.
+
+use crate::core::dates::add_months;
+use crate::entities::finance_operations::FinanceOperation;
+
+#[derive(Default)]
+pub struct Scenario {
+    /// ordered by date
+    operations: Vec<FinanceOperation>
+}
+
+impl Scenario {
+    pub fn new() -> Scenario {
+        Scenario::default()
+    }
+
+    pub fn add(&mut self, op: FinanceOperation) {
+        let index = self.operations.partition_point(|o|o.date <= op.date);
+        self.operations.insert(index, op);
+    }
+
+    /// Adds copies of the operation on the same day of count consecutive months, starting from its date.
+    pub fn add_monthly(&mut self, op: FinanceOperation, count: u64) {
+        for i in 1..count {
+            let mut copy = op.copy();
+            copy.date = add_months(op.date, i);
+            self.add(copy);
+        }
+        if count > 0 {
+            self.add(op);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &FinanceOperation> {
+        self.operations.iter()
+    }
+
+    pub fn get_operations(&self, from: u64, to: u64) -> impl Iterator<Item = &FinanceOperation> {
+        self.operations.iter().filter(move |op|op.within(from, to))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entities::accounts::AccountId;
+    use crate::entities::finance_operations::FinanceOperation;
+    use crate::entities::subcategories::SubcategoryId;
+    use crate::scenario::Scenario;
+
+    #[test]
+    fn test_scenario() {
+        let op = |date, summa|FinanceOperation::new(date, AccountId(1), SubcategoryId(1), None, summa, Vec::new());
+        let mut scenario = Scenario::new();
+        scenario.add(op(20240315, 5000));
+        scenario.add_monthly(op(20240131, 100), 3);
+        let dates: Vec<u64> = scenario.iter().map(|op|op.date).collect();
+        assert_eq!(dates, vec![20240131, 20240229, 20240315, 20240331]);
+        assert_eq!(scenario.get_operations(20240201, 20240331).map(|op|op.get_summa()).sum::<i64>(), 5200);
+    }
+}
@@ -0,0 +1,126 @@
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind};
+use serde::Deserialize;
+use crate::server::notifier::{Notifier, SmtpNotifier, WebhookNotifier};
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotifierConfiguration {
+    Webhook{url: String},
+    Smtp{server: String, from: String, to: Vec<String>}
+}
+
+impl NotifierConfiguration {
+    pub fn create(&self) -> Result<Box<dyn Notifier>, Error> {
+        match self {
+            NotifierConfiguration::Webhook{url} => Ok(Box::new(WebhookNotifier::new(url)?)),
+            NotifierConfiguration::Smtp{server, from, to} =>
+                Ok(Box::new(SmtpNotifier::new(server.clone(), from.clone(), to.clone())))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ReportsConfiguration {
+    /// day of month when report for the previous month is sent
+    pub day: u64,
+    pub notifiers: Vec<NotifierConfiguration>
+}
+
+#[derive(Deserialize)]
+pub struct TelegramConfiguration {
+    pub token: String,
+    /// telegram ids of users allowed to use the bot
+    pub users: Vec<i64>,
+    /// account of operations added by the bot
+    pub account: u64
+}
+
+#[derive(Deserialize)]
+pub struct WatchConfiguration {
+    pub folder: String,
+    /// seconds between folder scans
+    pub interval: u64,
+    pub account: u64,
+    /// subcategories of transactions the suggester knows nothing about
+    pub income_subcategory: u64,
+    pub expense_subcategory: u64,
+    #[serde(default)]
+    pub notifiers: Vec<NotifierConfiguration>
+}
+
+/// Monobank account synchronized into one database account.
+#[derive(Deserialize)]
+pub struct BankConfiguration {
+    pub token: String,
+    pub bank_account: String,
+    pub account: u64,
+    pub income_subcategory: u64,
+    pub expense_subcategory: u64,
+    /// file keeping position of the last synchronization
+    pub cursor_file: String
+}
+
+/// Receivers of balance alerts of accounts with a minimum balance or credit limit.
+#[derive(Deserialize)]
+pub struct AlertsConfiguration {
+    pub notifiers: Vec<NotifierConfiguration>
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "task", rename_all = "lowercase")]
+pub enum MaintenanceTask {
+    /// checkpoint and removal of older checkpoints and log entries
+    Compact{keep_checkpoints: usize},
+    Verify{#[serde(default)] incremental: bool},
+    /// backup file named by the date is created in the folder
+    Backup{folder: String},
+    Archive{years: u64}
+}
+
+/// Task run once a day at the UTC time.
+#[derive(Deserialize, Clone)]
+pub struct ScheduledTask {
+    #[serde(flatten)]
+    pub task: MaintenanceTask,
+    pub hour: u64,
+    #[serde(default)]
+    pub minute: u64
+}
+
+#[derive(Deserialize)]
+pub struct MaintenanceConfiguration {
+    pub tasks: Vec<ScheduledTask>
+}
+
+/// Server configuration file:
+/// {"reports": {"day": 1, "notifiers": [{"type": "webhook", "url": "http://host:port/path"}]},
+///  "telegram": {"token": "...", "users": [12345], "account": 1},
+///  "watch": {"folder": "...", "interval": 60, "account": 2, "income_subcategory": 1, "expense_subcategory": 5},
+///  "bank": {"token": "...", "bank_account": "...", "account": 2, "income_subcategory": 1,
+///           "expense_subcategory": 5, "cursor_file": "..."},
+///  "alerts": {"notifiers": [{"type": "webhook", "url": "http://host:port/path"}]},
+///  "maintenance": {"tasks": [{"task": "compact", "keep_checkpoints": 7, "hour": 3},
+///                            {"task": "backup", "folder": "...", "hour": 3, "minute": 30}]}}
+#[derive(Deserialize)]
+pub struct ServerConfiguration {
+    pub reports: Option<ReportsConfiguration>,
+    pub telegram: Option<TelegramConfiguration>,
+    pub watch: Option<WatchConfiguration>,
+    pub bank: Option<BankConfiguration>,
+    pub alerts: Option<AlertsConfiguration>,
+    pub maintenance: Option<MaintenanceConfiguration>
+}
+
+impl ServerConfiguration {
+    pub fn load(file_name: &str) -> Result<ServerConfiguration, Error> {
+        let reader = BufReader::new(File::open(file_name)?);
+        serde_json::from_reader(reader)
+            .map_err(|e|Error::new(ErrorKind::InvalidData, format!("{}: {}", file_name, e)))
+    }
+
+    /// Empty when alerts are not configured.
+    pub fn create_alert_notifiers(&self) -> Result<Vec<Box<dyn Notifier>>, Error> {
+        self.alerts.iter().flat_map(|a|a.notifiers.iter()).map(|n|n.create()).collect()
+    }
+}
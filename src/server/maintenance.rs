@@ -0,0 +1,199 @@
+//! Maintenance tasks run by the server once a day at configured UTC times. Status of the last run of
+//! every task is saved to the data folder, so the stats command shows it.
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::core::dates::unix_days_to_date;
+use crate::core::wal::to_millis;
+use crate::db::HomeAccountingDB;
+use crate::server::configuration::{MaintenanceConfiguration, MaintenanceTask, ScheduledTask};
+
+pub const MAINTENANCE_STATUS_FILE_NAME: &str = "maintenance.txt";
+
+impl MaintenanceTask {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaintenanceTask::Compact{..} => "compact",
+            MaintenanceTask::Verify{..} => "verify",
+            MaintenanceTask::Backup{..} => "backup",
+            MaintenanceTask::Archive{..} => "archive"
+        }
+    }
+
+    /// Returns the outcome message.
+    fn run(&self, db: &mut HomeAccountingDB, today: u64) -> Result<String, Error> {
+        match self {
+            MaintenanceTask::Compact{keep_checkpoints} =>
+                Ok(format!("{} log entries removed", db.compact(*keep_checkpoints)?)),
+            MaintenanceTask::Verify{incremental} => {
+                if *incremental {db.verify_incremental()?} else {db.verify()?}
+                Ok("no problems found".to_string())
+            }
+            MaintenanceTask::Backup{folder} => {
+                let file_name = Path::new(folder).join(format!("backup_{}.bin", today)).to_string_lossy().to_string();
+                db.backup(&file_name)?;
+                Ok(format!("{} created", file_name))
+            }
+            MaintenanceTask::Archive{years} => Ok(format!("{} date folders archived", db.archive_older_than(*years)?))
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TaskStatus {
+    pub name: String,
+    /// unix time in milliseconds
+    pub finished: u64,
+    pub duration_ms: u64,
+    /// outcome or error message
+    pub result: Result<String, String>
+}
+
+impl TaskStatus {
+    pub fn describe(&self) -> String {
+        match &self.result {
+            Ok(message) => format!("{}: finished at {} in {} ms, {}", self.name, self.finished, self.duration_ms, message),
+            Err(message) => format!("{}: failed at {} in {} ms: {}", self.name, self.finished, self.duration_ms, message)
+        }
+    }
+
+    /// Date of the run as yyyymmdd.
+    fn date(&self) -> u64 {
+        unix_days_to_date((self.finished / 86400000) as i64)
+    }
+}
+
+/// One line per task: "name finished duration ok|error message", tab separated.
+pub fn load_status(data_folder_path: &str) -> Result<Vec<TaskStatus>, Error> {
+    let text = match fs::read_to_string(Path::new(data_folder_path).join(MAINTENANCE_STATUS_FILE_NAME)) {
+        Ok(t) => t,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e)
+    };
+    let invalid = |line: &str|Error::new(ErrorKind::InvalidData, format!("invalid maintenance status line {}", line));
+    let mut result = Vec::new();
+    for line in text.lines().filter(|l|!l.is_empty()) {
+        let parts: Vec<&str> = line.splitn(5, '\t').collect();
+        let [name, finished, duration_ms, outcome, message] = parts[..] else { return Err(invalid(line)) };
+        let message = message.to_string();
+        result.push(TaskStatus{
+            name: name.to_string(),
+            finished: finished.parse().map_err(|_|invalid(line))?,
+            duration_ms: duration_ms.parse().map_err(|_|invalid(line))?,
+            result: match outcome {
+                "ok" => Ok(message),
+                "error" => Err(message),
+                _ => return Err(invalid(line))
+            }
+        });
+    }
+    Ok(result)
+}
+
+pub fn save_status(data_folder_path: &str, status: &[TaskStatus]) -> Result<(), Error> {
+    let mut text = String::new();
+    for s in status {
+        let (outcome, message) = match &s.result {
+            Ok(m) => ("ok", m),
+            Err(m) => ("error", m)
+        };
+        text += &format!("{}\t{}\t{}\t{}\t{}\n", s.name, s.finished, s.duration_ms, outcome,
+                         message.replace(['\t', '\r', '\n'], " "));
+    }
+    fs::write(Path::new(data_folder_path).join(MAINTENANCE_STATUS_FILE_NAME), text)
+}
+
+pub struct MaintenanceScheduler {
+    tasks: Vec<ScheduledTask>,
+    /// last run of every task by name, tasks that ran today are not due until tomorrow
+    status: Vec<TaskStatus>
+}
+
+impl MaintenanceScheduler {
+    pub fn new(tasks: Vec<ScheduledTask>, status: Vec<TaskStatus>) -> Result<MaintenanceScheduler, Error> {
+        if let Some(t) = tasks.iter().find(|t|t.hour > 23 || t.minute > 59) {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("invalid time {}:{:02} of maintenance task {}", t.hour, t.minute, t.task.name())));
+        }
+        Ok(MaintenanceScheduler{tasks, status})
+    }
+
+    /// Status of the previous runs is loaded from the data folder.
+    pub fn from_configuration(configuration: &MaintenanceConfiguration, data_folder_path: &str)
+        -> Result<MaintenanceScheduler, Error> {
+        MaintenanceScheduler::new(configuration.tasks.clone(), load_status(data_folder_path)?)
+    }
+
+    /// Tasks which time has come today and which did not run today yet.
+    pub fn due_tasks(&self, now: SystemTime) -> Vec<&MaintenanceTask> {
+        let seconds = now.duration_since(UNIX_EPOCH).map(|d|d.as_secs()).unwrap_or(0);
+        let today = unix_days_to_date((seconds / 86400) as i64);
+        let minute = seconds % 86400 / 60;
+        self.tasks.iter()
+            .filter(|t|t.hour * 60 + t.minute <= minute)
+            .filter(|t|!self.status.iter().any(|s|s.name == t.task.name() && s.date() >= today))
+            .map(|t|&t.task)
+            .collect()
+    }
+
+    /// Called periodically by the server, runs due tasks one by one. A failed task does not stop
+    /// the others, its error is recorded in the status. Returns status of tasks that ran.
+    pub fn run(&mut self, db: &mut HomeAccountingDB, data_folder_path: &str) -> Result<Vec<TaskStatus>, Error> {
+        let tasks: Vec<MaintenanceTask> = self.due_tasks(db.get_clock().now()).into_iter().cloned().collect();
+        let mut result = Vec::new();
+        for task in tasks {
+            let (started, today) = (db.get_clock().now(), db.get_clock().today());
+            let outcome = task.run(db, today).map_err(|e|e.to_string());
+            let finished = db.get_clock().now();
+            let status = TaskStatus{name: task.name().to_string(), finished: to_millis(finished),
+                duration_ms: to_millis(finished).saturating_sub(to_millis(started)), result: outcome};
+            self.status.retain(|s|s.name != status.name);
+            self.status.push(status.clone());
+            result.push(status);
+        }
+        if !result.is_empty() {
+            save_status(data_folder_path, &self.status)?;
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::Error;
+    use std::time::{Duration, UNIX_EPOCH};
+    use crate::server::configuration::{MaintenanceTask, ScheduledTask};
+    use crate::server::maintenance::{load_status, save_status, MaintenanceScheduler, TaskStatus};
+
+    #[test]
+    fn test_due_tasks() -> Result<(), Error> {
+        let tasks = vec![ScheduledTask{task: MaintenanceTask::Verify{incremental: true}, hour: 3, minute: 30},
+                         ScheduledTask{task: MaintenanceTask::Archive{years: 5}, hour: 12, minute: 0}];
+        // 2024-01-05 was the 19727th day since epoch
+        let day = UNIX_EPOCH + Duration::from_secs(19727 * 86400);
+        let verified = TaskStatus{name: "verify".to_string(), finished: (19727 * 86400 + 4 * 3600) * 1000,
+            duration_ms: 10, result: Err("verification failed".to_string())};
+        let scheduler = MaintenanceScheduler::new(tasks.clone(), Vec::new())?;
+        assert!(scheduler.due_tasks(day + Duration::from_secs(3 * 3600)).is_empty());
+        assert_eq!(scheduler.due_tasks(day + Duration::from_secs(13 * 3600)).len(), 2);
+        let scheduler = MaintenanceScheduler::new(tasks.clone(), vec![verified.clone()])?;
+        assert_eq!(scheduler.due_tasks(day + Duration::from_secs(13 * 3600)), vec![&MaintenanceTask::Archive{years: 5}]);
+        assert_eq!(scheduler.due_tasks(day + Duration::from_secs(86400 + 4 * 3600)),
+                   vec![&MaintenanceTask::Verify{incremental: true}]);
+        assert!(MaintenanceScheduler::new(vec![ScheduledTask{hour: 24, ..tasks[0].clone()}], Vec::new()).is_err());
+
+        let folder = temp_dir().join("had_test_maintenance").to_str().unwrap().to_string();
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder)?;
+        assert!(load_status(&folder)?.is_empty());
+        let status = vec![verified, TaskStatus{name: "compact".to_string(), finished: 5, duration_ms: 1,
+            result: Ok("3 log entries removed".to_string())}];
+        save_status(&folder, &status)?;
+        assert_eq!(load_status(&folder)?, status);
+        fs::remove_dir_all(&folder)
+    }
+}
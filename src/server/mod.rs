@@ -0,0 +1,11 @@
+//! Server mode: configuration, background jobs and the request protocol.
+
+pub mod configuration;
+pub mod maintenance;
+pub mod notifier;
+pub mod scheduler;
+pub mod tcp;
+#[cfg(feature = "telegram")]
+pub mod telegram;
+#[cfg(feature = "importers")]
+pub mod watcher;
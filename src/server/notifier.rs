@@ -0,0 +1,166 @@
+//! Report delivery. Both notifiers talk plain text protocols directly over TCP,
+//! TLS terminating proxy or local mail relay is expected for external services.
+
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use serde_json::json;
+
+pub trait Notifier {
+    fn notify(&self, subject: &str, text: &str) -> Result<(), Error>;
+}
+
+/// Posts {"subject": ..., "text": ...} to http url.
+pub struct WebhookNotifier {
+    address: String,
+    host: String,
+    path: String
+}
+
+impl WebhookNotifier {
+    pub fn new(url: &str) -> Result<WebhookNotifier, Error> {
+        let rest = url.strip_prefix("http://")
+            .ok_or(Error::new(ErrorKind::InvalidInput, format!("unsupported webhook url {}", url)))?;
+        let (host, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/")
+        };
+        if host.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("invalid webhook url {}", url)));
+        }
+        let address = if host.contains(':') {host.to_string()} else {host.to_string() + ":80"};
+        Ok(WebhookNotifier{address, host: host.to_string(), path: path.to_string()})
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify(&self, subject: &str, text: &str) -> Result<(), Error> {
+        let body = json!({"subject": subject, "text": text}).to_string();
+        let mut stream = TcpStream::connect(&self.address)?;
+        write!(stream, "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+               self.path, self.host, body.len(), body)?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        let status = response.split(' ').nth(1).unwrap_or("");
+        if !status.starts_with('2') {
+            return Err(Error::other(format!("webhook {} returned {}", self.address,
+                                            response.lines().next().unwrap_or(""))));
+        }
+        Ok(())
+    }
+}
+
+/// Sends mail through SMTP relay without authentication.
+pub struct SmtpNotifier {
+    server: String,
+    from: String,
+    to: Vec<String>
+}
+
+impl SmtpNotifier {
+    pub fn new(server: String, from: String, to: Vec<String>) -> SmtpNotifier {
+        SmtpNotifier{server, from, to}
+    }
+}
+
+fn smtp_expect(reader: &mut impl BufRead, code: &str) -> Result<(), Error> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "SMTP connection closed"));
+        }
+        if !line.starts_with(code) {
+            return Err(Error::other(format!("unexpected SMTP reply {}", line.trim_end())));
+        }
+        // multiline replies have '-' after the code
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok(());
+        }
+    }
+}
+
+impl Notifier for SmtpNotifier {
+    fn notify(&self, subject: &str, text: &str) -> Result<(), Error> {
+        let mut stream = TcpStream::connect(&self.server)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        smtp_expect(&mut reader, "220")?;
+        let mut command = |s: String, code: &str| -> Result<(), Error> {
+            stream.write_all(s.as_bytes())?;
+            smtp_expect(&mut reader, code)
+        };
+        command("HELO localhost\r\n".to_string(), "250")?;
+        command(format!("MAIL FROM:<{}>\r\n", self.from), "250")?;
+        for to in &self.to {
+            command(format!("RCPT TO:<{}>\r\n", to), "250")?;
+        }
+        command("DATA\r\n".to_string(), "354")?;
+        // lines starting with a dot are escaped as required by RFC 5321
+        let body: String = text.lines()
+            .map(|l|if l.starts_with('.') {format!(".{}\r\n", l)} else {format!("{}\r\n", l)})
+            .collect();
+        command(format!("From: {}\r\nTo: {}\r\nSubject: {}\r\n\r\n{}.\r\n", self.from, self.to.join(", "),
+                        subject, body), "250")?;
+        command("QUIT\r\n".to_string(), "221")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Error, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use crate::server::notifier::{Notifier, SmtpNotifier, WebhookNotifier};
+
+    #[test]
+    fn test_webhook() -> Result<(), Error> {
+        assert!(WebhookNotifier::new("https://example.com").is_err());
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/hook", listener.local_addr()?);
+        let handle = thread::spawn(move || {
+            let mut stream = listener.accept()?.0;
+            let mut request = vec![0u8; 4096];
+            let n = stream.read(&mut request)?;
+            stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n")?;
+            Ok::<_, Error>(String::from_utf8_lossy(&request[..n]).to_string())
+        });
+        WebhookNotifier::new(&url)?.notify("subject", "text")?;
+        let request = handle.join().unwrap()?;
+        assert!(request.starts_with("POST /hook HTTP/1.1"));
+        assert!(request.ends_with(r#"{"subject":"subject","text":"text"}"#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_smtp() -> Result<(), Error> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let server = listener.local_addr()?.to_string();
+        let handle = thread::spawn(move || {
+            let mut stream = listener.accept()?.0;
+            let mut reader = BufReader::new(stream.try_clone()?);
+            stream.write_all(b"220 test\r\n")?;
+            let mut lines = Vec::new();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line)? == 0 {
+                    break;
+                }
+                lines.push(line.clone());
+                let reply: &[u8] = match line.as_str() {
+                    ".\r\n" => {in_data = false; b"250 queued\r\n"}
+                    _ if in_data => continue,
+                    "DATA\r\n" => {in_data = true; b"354 go\r\n"}
+                    "QUIT\r\n" => b"221 bye\r\n",
+                    _ => b"250-ok\r\n250 ok\r\n"
+                };
+                stream.write_all(reply)?;
+            }
+            Ok::<_, Error>(lines)
+        });
+        SmtpNotifier::new(server, "db@home".to_string(), vec!["me@home".to_string()])
+            .notify("report", "line\n.dot")?;
+        let lines = handle.join().unwrap()?;
+        assert!(lines.contains(&"RCPT TO:<me@home>\r\n".to_string()));
+        assert!(lines.contains(&"..dot\r\n".to_string()));
+        Ok(())
+    }
+}
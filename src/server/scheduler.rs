@@ -0,0 +1,113 @@
+//! Monthly summary reports run by the server. The last delivered month is saved to the data folder,
+//! so a restarted server doesn't send it again.
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use crate::db::HomeAccountingDB;
+use crate::server::configuration::ReportsConfiguration;
+use crate::server::notifier::Notifier;
+
+pub const LAST_REPORT_FILE_NAME: &str = "last_report.txt";
+
+/// Last delivered month as yyyymm, None when nothing was delivered yet.
+pub fn load_last_month(data_folder_path: &str) -> Result<Option<u64>, Error> {
+    match fs::read_to_string(Path::new(data_folder_path).join(LAST_REPORT_FILE_NAME)) {
+        Ok(text) => text.trim().parse().map(Some)
+            .map_err(|_|Error::new(ErrorKind::InvalidData, format!("invalid last report month {}", text.trim()))),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e)
+    }
+}
+
+fn save_last_month(data_folder_path: &str, month: u64) -> Result<(), Error> {
+    fs::write(Path::new(data_folder_path).join(LAST_REPORT_FILE_NAME), month.to_string())
+}
+
+/// Sends summary of the previous month once a month, starting from the configured day.
+pub struct ReportScheduler {
+    day: u64,
+    notifiers: Vec<Box<dyn Notifier>>,
+    /// last delivered month as yyyymm
+    last_month: Option<u64>
+}
+
+impl ReportScheduler {
+    pub fn new(day: u64, notifiers: Vec<Box<dyn Notifier>>, last_month: Option<u64>) -> ReportScheduler {
+        ReportScheduler{day, notifiers, last_month}
+    }
+
+    /// Last delivered month is loaded from the data folder.
+    pub fn from_configuration(configuration: &ReportsConfiguration, data_folder_path: &str)
+        -> Result<ReportScheduler, Error> {
+        let notifiers = configuration.notifiers.iter().map(|n|n.create()).collect::<Result<_, _>>()?;
+        Ok(ReportScheduler::new(configuration.day, notifiers, load_last_month(data_folder_path)?))
+    }
+
+    /// Month (yyyymm) whose report has to be sent today, if any.
+    pub fn due_month(&self, today: u64) -> Option<u64> {
+        if today % 100 < self.day {
+            return None;
+        }
+        let current = today / 100;
+        let month = if current % 100 == 1 {current - 100 + 11} else {current - 1};
+        if self.last_month.is_some_and(|m|m >= month) {
+            return None;
+        }
+        Some(month)
+    }
+
+    /// Called periodically by the server, sends the report when it is due. Returns sent month.
+    /// The month counts as delivered when at least one notifier succeeded.
+    pub fn run(&mut self, db: &HomeAccountingDB, data_folder_path: &str) -> Result<Option<u64>, Error> {
+        let month = match self.due_month(db.get_clock().today()) {
+            Some(m) => m,
+            None => return Ok(None)
+        };
+        let report = db.build_monthly_report(month)?;
+        let (subject, text) = (report.subject(), report.to_text());
+        let errors: Vec<String> = self.notifiers.iter()
+            .filter_map(|n|n.notify(&subject, &text).err())
+            .map(|e|e.to_string())
+            .collect();
+        if errors.len() < self.notifiers.len() {
+            save_last_month(data_folder_path, month)?;
+            self.last_month = Some(month);
+        }
+        if !errors.is_empty() {
+            return Err(Error::other(format!("report delivery failed: {}", errors.join(", "))));
+        }
+        Ok(Some(month))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::Error;
+    use crate::server::scheduler::{load_last_month, save_last_month, ReportScheduler};
+
+    #[test]
+    fn test_due_month() {
+        let mut scheduler = ReportScheduler::new(3, Vec::new(), None);
+        assert_eq!(scheduler.due_month(20240102), None);
+        assert_eq!(scheduler.due_month(20240103), Some(202312));
+        scheduler.last_month = Some(202312);
+        assert_eq!(scheduler.due_month(20240131), None);
+        assert_eq!(scheduler.due_month(20240203), Some(202401));
+    }
+
+    #[test]
+    fn test_last_month() -> Result<(), Error> {
+        let folder = temp_dir().join("had_test_last_report").to_str().unwrap().to_string();
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder)?;
+        assert_eq!(load_last_month(&folder)?, None);
+        save_last_month(&folder, 202312)?;
+        let scheduler = ReportScheduler::new(3, Vec::new(), load_last_month(&folder)?);
+        assert_eq!(scheduler.due_month(20240105), None);
+        assert_eq!(scheduler.due_month(20240203), Some(202401));
+        fs::remove_dir_all(&folder)
+    }
+}
@@ -0,0 +1,336 @@
+//! Request/response protocol of the server command. A connection carries any number of requests,
+//! each one is a length-prefixed frame (u32 little endian length) with a text command:
+//!
+//! - `operations from_date to_date` - operations of the period and versions of its changed months by yyyymm
+//! - `changes date` - operations of the day, balance changes of accounts on that day and version of the month
+//! - `totals date` - end of day balances by account id and version of the month
+//! - `accounts`, `categories`, `subcategories` - dictionaries ordered by id
+//! - `lock months owner`, `unlock months owner` - advisory locks of months given as yyyymm[-yyyymm],...,
+//!   months locked by another owner are answered with the LOCKED error
+//! - `add key date account_id subcategory_id summa` - adds the operation once per idempotency key, summa is
+//!   given in units of the account currency, `added` of the result is false when a request with the key
+//!   was already applied, so a client can retry a request that timed out
+//!
+//! The server answers every request with a single byte, 0 is followed by a frame with the JSON result,
+//! 1 is followed by a frame with the encoded ApiError. Frames, but not answer bytes, are always encrypted,
+//! so only clients knowing the key are served, a frame that fails to decrypt closes the connection.
+//! The client closes the connection when it has no more requests.
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+use serde_json::{json, Value};
+use crate::core::crypto::CryptoProcessor;
+use crate::core::errors::{crypto_error, ApiError};
+use crate::core::month_locks::parse_months;
+use crate::core::replication::{read_frame, write_frame};
+use crate::core::trace::{trace, RequestSpan};
+use crate::db::HomeAccountingDB;
+use crate::entities::accounts::AccountId;
+use crate::entities::finance_operations::FinanceOperation;
+use crate::entities::subcategories::SubcategoryId;
+
+const OK: u8 = 0;
+const ERROR: u8 = 1;
+/// Longest encrypted request, requests are short commands.
+const MAX_REQUEST_FRAME: usize = 64 << 10;
+const MAX_RESPONSE_FRAME: usize = 256 << 20;
+
+#[derive(PartialEq, Debug)]
+pub enum Request {
+    Operations{from: u64, to: u64},
+    Changes(u64),
+    Totals(u64),
+    Accounts,
+    Categories,
+    Subcategories,
+    Lock{months: Vec<u64>, owner: String},
+    Unlock{months: Vec<u64>, owner: String},
+    Add{key: String, date: u64, account: AccountId, subcategory: SubcategoryId, summa: String}
+}
+
+impl Request {
+    pub fn parse(text: &str) -> Result<Request, Error> {
+        let parts: Vec<&str> = text.split_whitespace().collect();
+        let date = |i: usize|parts[i].parse::<u64>()
+            .map_err(|_|Error::new(ErrorKind::InvalidInput, format!("invalid date {}", parts[i])));
+        let id = |i: usize|parts[i].parse::<u64>()
+            .map_err(|_|Error::new(ErrorKind::InvalidInput, format!("invalid id {}", parts[i])));
+        match (parts.first().copied(), parts.len()) {
+            (Some("operations"), 3) => Ok(Request::Operations{from: date(1)?, to: date(2)?}),
+            (Some("changes"), 2) => Ok(Request::Changes(date(1)?)),
+            (Some("totals"), 2) => Ok(Request::Totals(date(1)?)),
+            (Some("accounts"), 1) => Ok(Request::Accounts),
+            (Some("categories"), 1) => Ok(Request::Categories),
+            (Some("subcategories"), 1) => Ok(Request::Subcategories),
+            (Some("lock"), 3) => Ok(Request::Lock{months: parse_months(parts[1])?, owner: parts[2].to_string()}),
+            (Some("unlock"), 3) => Ok(Request::Unlock{months: parse_months(parts[1])?, owner: parts[2].to_string()}),
+            (Some("add"), 6) => Ok(Request::Add{key: parts[1].to_string(), date: date(2)?, account: AccountId(id(3)?),
+                                                subcategory: SubcategoryId(id(4)?), summa: parts[5].to_string()}),
+            _ => Err(Error::new(ErrorKind::InvalidInput, format!("invalid request {}", text)))
+        }
+    }
+
+    fn execute(&self, db: &HomeAccountingDB) -> Result<Value, Error> {
+        let result = match self {
+            // versions are read first, so a change made during the request makes them stale, not the data
+            Request::Operations{from, to} => {
+                let versions = db.get_month_versions(*from, *to);
+                Ok(json!({"operations": db.get_operations(*from, *to)?, "versions": versions}))
+            }
+            Request::Changes(date) => {
+                let version = db.get_month_version(*date);
+                let (operations, changes) = db.build_ops_and_changes(*date)?;
+                let changes: BTreeMap<u64, Value> = changes.iter()
+                    .map(|(account, change)|(account.0, json!({
+                        "startBalance": change.get_start_balance(),
+                        "income": change.get_income(),
+                        "expenditure": change.get_expenditure(),
+                        "endBalance": change.get_end_balance()
+                    })))
+                    .collect();
+                Ok(json!({"operations": operations, "changes": changes, "version": version}))
+            }
+            Request::Totals(date) => {
+                let version = db.get_month_version(*date);
+                let totals: BTreeMap<u64, i64> = db.get_active_balances(*date)?.iter()
+                    .map(|(account, change)|(account.0, change.get_end_balance()))
+                    .collect();
+                Ok(json!({"totals": totals, "version": version}))
+            }
+            Request::Accounts => {
+                let mut accounts: Vec<_> = db.get_accounts().iter().collect();
+                accounts.sort_by_key(|a|a.id);
+                serde_json::to_value(accounts)
+            }
+            Request::Categories => {
+                let mut categories: Vec<_> = db.get_categories().iter().collect();
+                categories.sort_by_key(|c|c.id);
+                serde_json::to_value(categories)
+            }
+            Request::Subcategories => {
+                let mut subcategories: Vec<_> = db.get_subcategories().iter().collect();
+                subcategories.sort_by_key(|s|s.id);
+                serde_json::to_value(subcategories)
+            }
+            Request::Lock{months, owner} => {
+                db.lock_months(months, owner)?;
+                serde_json::to_value(months)
+            }
+            // locks of other owners are never forced remotely
+            Request::Unlock{months, owner} => {
+                db.unlock_months(months, owner, false)?;
+                serde_json::to_value(months)
+            }
+            Request::Add{key, date, account, subcategory, summa} => {
+                let summa = db.get_account_currency(*account, *date)?.parse(summa)?;
+                let op = FinanceOperation::new(*date, *account, *subcategory, None, summa, Vec::new());
+                let added = db.once(&format!("tcp:{}", key), |db|db.add_operation(op))?.is_some();
+                if added {
+                    db.flush(false)?;
+                }
+                Ok(json!({"added": added}))
+            }
+        };
+        result.map_err(|e|Error::other(e.to_string()))
+    }
+}
+
+fn send(stream: &mut impl Write, crypto: &dyn CryptoProcessor, data: &[u8]) -> Result<(), Error> {
+    write_frame(stream, &crypto.encode(data).map_err(crypto_error)?)
+}
+
+fn receive(stream: &mut impl Read, crypto: &dyn CryptoProcessor, max_length: usize) -> Result<Vec<u8>, Error> {
+    crypto.decode(&read_frame(stream, max_length)?).map_err(crypto_error)
+}
+
+pub struct RequestServer {
+    db: HomeAccountingDB,
+    crypto: Box<dyn CryptoProcessor>
+}
+
+impl RequestServer {
+    pub fn new(db: HomeAccountingDB, crypto: Box<dyn CryptoProcessor>) -> RequestServer {
+        RequestServer{db, crypto}
+    }
+
+    /// Serves clients forever, every connection in its own thread. Failed accepts are reported and skipped.
+    pub fn run(&self, listener: TcpListener) -> Result<(), Error> {
+        thread::scope(|s| {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        println!("accept error: {}", e);
+                        continue;
+                    }
+                };
+                s.spawn(move || {
+                    if let Err(e) = self.handle(&mut stream) {
+                        println!("connection error: {}", e);
+                    }
+                });
+            }
+            Ok(())
+        })
+    }
+
+    /// Answers requests until the client closes the connection, returns number of answered requests.
+    /// Failed requests are answered with an error, the connection is dropped only on transport errors.
+    pub fn handle(&self, stream: &mut TcpStream) -> Result<usize, Error> {
+        let peer = stream.peer_addr()?;
+        let mut count = 0;
+        loop {
+            let request = match receive(stream, self.crypto.as_ref(), MAX_REQUEST_FRAME) {
+                Ok(r) => r,
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(count),
+                Err(e) => return Err(e)
+            };
+            let text = String::from_utf8_lossy(&request).to_string();
+            let _span = RequestSpan::start(&format!("{} from {}", text, peer));
+            let result = Request::parse(&text)
+                .and_then(|r|r.execute(&self.db))
+                .and_then(|v|serde_json::to_vec(&v).map_err(|e|Error::other(e.to_string())));
+            match result {
+                Ok(data) => {
+                    stream.write_all(&[OK])?;
+                    send(stream, self.crypto.as_ref(), &data)?;
+                }
+                Err(e) => {
+                    trace(&format!("request failed: {}", e));
+                    stream.write_all(&[ERROR])?;
+                    send(stream, self.crypto.as_ref(), ApiError::from_error(&e).encode().as_bytes())?;
+                }
+            }
+            count += 1;
+        }
+    }
+}
+
+pub struct RequestClient {
+    stream: TcpStream,
+    crypto: Box<dyn CryptoProcessor>
+}
+
+impl RequestClient {
+    pub fn connect(address: impl ToSocketAddrs, crypto: Box<dyn CryptoProcessor>) -> Result<RequestClient, Error> {
+        Ok(RequestClient{stream: TcpStream::connect(address)?, crypto})
+    }
+
+    /// Sends the request text, server side errors are returned with their ApiError code.
+    pub fn request(&mut self, request: &str) -> Result<Value, Error> {
+        send(&mut self.stream, self.crypto.as_ref(), request.as_bytes())?;
+        let mut answer = [0u8];
+        self.stream.read_exact(&mut answer)?;
+        let data = receive(&mut self.stream, self.crypto.as_ref(), MAX_RESPONSE_FRAME)?;
+        if answer[0] != OK {
+            let text = String::from_utf8(data).map_err(|_|Error::new(ErrorKind::InvalidData, "invalid error response"))?;
+            return Err(ApiError::decode(&text)?.to_error());
+        }
+        serde_json::from_slice(&data).map_err(|e|Error::new(ErrorKind::InvalidData, e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::{Error, ErrorKind, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use serde_json::json;
+    use crate::core::crypto::AesGcmProcessor;
+    use crate::db::HomeAccountingDB;
+    use crate::entities::accounts::AccountId;
+    use crate::entities::finance_operations::FinanceOperation;
+    use crate::entities::subcategories::SubcategoryId;
+    use crate::json_db_config::JsonDBConfiguration;
+    use crate::server::tcp::{Request, RequestClient, RequestServer};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Request::parse("operations 20240101 20240131").unwrap(),
+                   Request::Operations{from: 20240101, to: 20240131});
+        assert_eq!(Request::parse(" totals  20240105").unwrap(), Request::Totals(20240105));
+        assert!(Request::parse("changes").is_err());
+        assert!(Request::parse("totals today").is_err());
+        assert_eq!(Request::parse("lock 202401-202402 import").unwrap(),
+                   Request::Lock{months: vec![202401, 202402], owner: "import".to_string()});
+        assert!(Request::parse("unlock 202413 import").is_err());
+        assert!(Request::parse("add k1 20240105 card 2 10.50").is_err());
+    }
+
+    #[test]
+    fn test_loopback() -> Result<(), Error> {
+        let folder = temp_dir().join("had_test_tcp_server").to_str().unwrap().to_string();
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder)?;
+        fs::write(folder.clone() + "/accounts.json",
+                  r#"[{"id": 1, "name": "Card", "valutaCode": "UAH", "activeTo": null, "isCash": false},
+                      {"id": 2, "name": "Cash", "valutaCode": "UAH", "activeTo": null, "isCash": true}]"#)?;
+        fs::write(folder.clone() + "/categories.json", r#"[{"id": 1, "name": "Salary"}, {"id": 2, "name": "Food"}]"#)?;
+        fs::write(folder.clone() + "/subcategories.json",
+                  r#"[{"id": 1, "name": "Salary", "code": null, "operationCodeId": "INCM", "categoryId": 1},
+                      {"id": 2, "name": "Groceries", "code": null, "operationCodeId": "EXPN", "categoryId": 2}]"#)?;
+        let db = HomeAccountingDB::new(folder.clone(), Box::new(JsonDBConfiguration::new()), 100)?;
+        db.add_operation(FinanceOperation::new(20240105, AccountId(1), SubcategoryId(1), None, 100000, Vec::new()))?;
+        db.add_operation(FinanceOperation::new(20240110, AccountId(1), SubcategoryId(2), None, 2550, Vec::new()))?;
+        let version = db.get_month_version(20240110);
+        assert!(version > 0);
+        let server = RequestServer::new(db, Box::new(AesGcmProcessor::new(&[3u8; 32])));
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        thread::scope(|s| {
+            let handle = s.spawn(|| server.handle(&mut listener.accept()?.0));
+            let mut client = RequestClient::connect(address, Box::new(AesGcmProcessor::new(&[3u8; 32])))?;
+            assert_eq!(client.request("accounts")?.as_array().map(|a|a.len()), Some(2));
+            assert_eq!(client.request("subcategories")?[1]["name"], "Groceries");
+            let operations = client.request("operations 20231201 20240131")?;
+            assert_eq!(operations["operations"].as_array().map(|a|a.len()), Some(2));
+            assert_eq!(operations["versions"], json!({"202401": version}));
+            let totals = client.request("totals 20240131")?;
+            assert_eq!(totals["totals"]["1"], 97450);
+            assert_eq!(totals["version"], version);
+            let changes = client.request("changes 20240110")?;
+            assert_eq!(changes["changes"]["1"]["expenditure"], 2550);
+            assert_eq!(changes["operations"][0]["summa"], 2550);
+            assert_eq!(changes["version"], version);
+            assert_eq!(client.request("changes 20231210")?["version"], 0);
+            let e = client.request("balances").unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidInput);
+            // the connection is still usable after a failed request
+            assert_eq!(client.request("categories")?.as_array().map(|a|a.len()), Some(2));
+            assert_eq!(client.request("lock 202401,202402 import")?, json!([202401, 202402]));
+            drop(client);
+            assert_eq!(handle.join().unwrap()?, 9);
+            // months locked by one owner can't be locked or unlocked by another
+            let handle = s.spawn(|| server.handle(&mut listener.accept()?.0));
+            let mut client = RequestClient::connect(address, Box::new(AesGcmProcessor::new(&[3u8; 32])))?;
+            assert_eq!(client.request("lock 202402 repair").unwrap_err().kind(), ErrorKind::WouldBlock);
+            assert!(client.request("unlock 202401 repair").is_err());
+            client.request("unlock 202401-202402 import")?;
+            client.request("lock 202402 repair")?;
+            // a retried add with the same key doesn't add the operation again
+            assert_eq!(client.request("add a1 20240112 2 2 10.50")?["added"], true);
+            assert_eq!(client.request("add a1 20240112 2 2 10.50")?["added"], false);
+            assert_eq!(client.request("add a2 20240112 2 2 10.50")?["added"], true);
+            let operations = client.request("operations 20240112 20240112")?;
+            assert_eq!(operations["operations"].as_array().map(|a|a.len()), Some(2));
+            assert_eq!(operations["operations"][0]["summa"], 1050);
+            drop(client);
+            assert_eq!(handle.join().unwrap()?, 8);
+            // clients without the key are disconnected without an answer
+            let handle = s.spawn(|| server.handle(&mut listener.accept()?.0));
+            let mut client = RequestClient::connect(address, Box::new(AesGcmProcessor::new(&[4u8; 32])))?;
+            assert!(client.request("accounts").is_err());
+            assert!(handle.join().unwrap().is_err());
+            // the announced length is checked before anything is allocated
+            let handle = s.spawn(|| server.handle(&mut listener.accept()?.0));
+            TcpStream::connect(address)?.write_all(&u32::MAX.to_le_bytes())?;
+            assert_eq!(handle.join().unwrap().unwrap_err().kind(), ErrorKind::InvalidData);
+            Ok::<(), Error>(())
+        })?;
+        fs::remove_dir_all(&folder)
+    }
+}
@@ -0,0 +1,160 @@
+//! Telegram bot for quick entry. Messages like "coffee 3.50" become expenditures of the configured
+//! account, subcategory is taken from the suggester or matched by name. /balance lists active balances.
+
+use std::collections::HashSet;
+use std::io::{Error, ErrorKind};
+use serde_json::{json, Value};
+use crate::core::amounts::parse_decimal;
+use crate::core::trace::{current_request_id, trace, RequestSpan};
+use crate::db::HomeAccountingDB;
+use crate::entities::accounts::AccountId;
+use crate::entities::finance_operations::{FinOpParameter, FinanceOperation};
+use crate::entities::subcategories::{Subcategories, SubcategoryId, SubcategoryOperationCode};
+use crate::server::configuration::TelegramConfiguration;
+use crate::suggester::SubcategorySuggester;
+
+const API_URL: &str = "https://api.telegram.org/bot";
+const POLL_TIMEOUT: u64 = 30;
+
+#[derive(PartialEq, Debug)]
+pub struct QuickEntry {
+    pub text: String,
+    /// in minor units of the account currency
+    pub summa: i64
+}
+
+/// Parses "text amount", amount uses dot or comma as decimal separator and has at most minor_units
+/// fraction digits.
+pub fn parse_entry(message: &str, minor_units: u32) -> Result<QuickEntry, Error> {
+    let invalid = || Error::new(ErrorKind::InvalidInput, "expected message like: coffee 3.50");
+    let (text, amount) = message.trim().rsplit_once(char::is_whitespace).ok_or_else(invalid)?;
+    let summa = parse_decimal(amount, minor_units, None).map_err(|_|invalid())?;
+    if text.trim().is_empty() || summa <= 0 {
+        return Err(invalid());
+    }
+    Ok(QuickEntry{text: text.trim().to_string(), summa})
+}
+
+/// Expenditure subcategory for the text: suggested by history first, otherwise the one with the same name.
+pub fn find_subcategory(text: &str, suggester: &SubcategorySuggester, subcategories: &Subcategories)
+    -> Option<SubcategoryId> {
+    suggester.suggest_for_text(text).or_else(||subcategories.iter()
+        .find(|s|matches!(s.operation_code, SubcategoryOperationCode::Expn) && s.name.eq_ignore_ascii_case(text))
+        .map(|s|s.id))
+}
+
+pub struct TelegramBot {
+    url: String,
+    users: HashSet<i64>,
+    account: AccountId,
+    suggester: SubcategorySuggester,
+    offset: i64
+}
+
+impl TelegramBot {
+    /// Suggester learns from operations of the last year.
+    pub fn new(configuration: &TelegramConfiguration, db: &HomeAccountingDB) -> Result<TelegramBot, Error> {
+        let today = db.get_clock().today();
+        let suggester = db.build_suggester(today.saturating_sub(10000), today)?;
+        Ok(TelegramBot{url: API_URL.to_string() + &configuration.token, users: configuration.users.iter().copied().collect(),
+            account: AccountId(configuration.account), suggester, offset: 0})
+    }
+
+    fn call(&self, method: &str, body: Value) -> Result<Value, Error> {
+        let response = ureq::post(format!("{}/{}", self.url, method))
+            .header("Content-Type", "application/json")
+            .send(body.to_string())
+            .map_err(|e|Error::other(format!("telegram {}: {}", method, e)))?
+            .body_mut()
+            .read_to_string()
+            .map_err(|e|Error::other(format!("telegram {}: {}", method, e)))?;
+        let value: Value = serde_json::from_str(&response)?;
+        if value["ok"] != Value::Bool(true) {
+            return Err(Error::other(format!("telegram {}: {}", method, value["description"])));
+        }
+        Ok(value["result"].clone())
+    }
+
+    /// Waits for new messages and answers them, messages from unknown users are ignored.
+    pub fn poll(&mut self, db: &mut HomeAccountingDB) -> Result<(), Error> {
+        let updates = self.call("getUpdates", json!({"offset": self.offset, "timeout": POLL_TIMEOUT}))?;
+        for update in updates.as_array().map(|u|u.as_slice()).unwrap_or_default() {
+            let update_id = update["update_id"].as_i64().unwrap_or(0);
+            self.offset = self.offset.max(update_id + 1);
+            let message = &update["message"];
+            let (Some(user), Some(chat), Some(text)) =
+                (message["from"]["id"].as_i64(), message["chat"]["id"].as_i64(), message["text"].as_str()) else {
+                continue;
+            };
+            if !self.users.contains(&user) {
+                continue;
+            }
+            let _span = RequestSpan::start(&format!("telegram message from {}", user));
+            let answer = self.handle(db, update_id, text).unwrap_or_else(|e| {
+                trace(&format!("telegram message failed: {}", e));
+                format!("{} (request {})", e, current_request_id().unwrap_or_default())
+            });
+            self.call("sendMessage", json!({"chat_id": chat, "text": answer}))?;
+        }
+        Ok(())
+    }
+
+    /// Updates not confirmed before a restart are delivered again, their ids keep operations from being added twice.
+    fn handle(&mut self, db: &mut HomeAccountingDB, update_id: i64, text: &str) -> Result<String, Error> {
+        match text.trim() {
+            "/start" | "/help" => Ok("Send \"text amount\" to add an expenditure, /balance to see balances, /report name to run a custom report".to_string()),
+            "/balance" => {
+                let today = db.get_clock().today();
+                let changes = db.get_active_balances(today)?;
+                let mut lines = Vec::new();
+                for account in db.get_accounts().ordered()? {
+                    if let Some(change) = changes.get(account.id) {
+                        let currency = db.get_account_currency(account.id, today)?;
+                        lines.push(format!("{}: {}", account.name, currency.format(change.get_end_balance())));
+                    }
+                }
+                Ok(lines.join("\n"))
+            }
+            message if message.starts_with("/report ") => {
+                let report = db.build_custom_report(message["/report ".len()..].trim(), None, None)?;
+                Ok(report.to_text())
+            }
+            message => {
+                let currency = db.get_account_currency(self.account, db.get_clock().today())?;
+                let entry = parse_entry(message, currency.minor_units)?;
+                let subcategory = find_subcategory(&entry.text, &self.suggester, db.get_subcategories())
+                    .ok_or(Error::new(ErrorKind::NotFound, format!("unknown category for {}", entry.text)))?;
+                let op = FinanceOperation::new(db.get_clock().today(), self.account, subcategory, None, entry.summa,
+                                               vec![FinOpParameter::Netw(entry.text.as_str().into())]);
+                self.suggester.learn(&op);
+                let Some(over_limit) = db.once(&format!("telegram:{}", update_id), |db|db.add_operation_checked(op))? else {
+                    return Ok(format!("{} {} was already added", entry.text, currency.format(entry.summa)));
+                };
+                db.flush(false)?;
+                let mut reply = format!("{} {} added to {}", entry.text, currency.format(entry.summa),
+                                        db.get_subcategories().get(subcategory)?.name);
+                if let Some(status) = over_limit {
+                    reply = format!("{}\nWarning: {}", reply, status.describe(db.get_subcategories())?);
+                }
+                Ok(reply)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::telegram::{parse_entry, QuickEntry};
+
+    #[test]
+    fn test_parse_entry() {
+        assert_eq!(parse_entry("coffee 3.50", 2).unwrap(), QuickEntry{text: "coffee".to_string(), summa: 350});
+        assert_eq!(parse_entry(" fresh bread 2,5 ", 2).unwrap(), QuickEntry{text: "fresh bread".to_string(), summa: 250});
+        assert_eq!(parse_entry("taxi 12", 2).unwrap().summa, 1200);
+        assert!(parse_entry("coffee", 2).is_err());
+        assert!(parse_entry("coffee 3.505", 2).is_err());
+        assert!(parse_entry("coffee -3", 2).is_err());
+        assert_eq!(parse_entry("coffee 3.505", 3).unwrap().summa, 3505);
+        assert!(parse_entry("coffee 3.5", 0).is_err());
+    }
+}
@@ -0,0 +1,66 @@
+//! Drop folder auto-import. Statements placed into the folder are imported and moved to its
+//! archive subfolder, files that cannot be imported are moved to the failed subfolder.
+
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use crate::db::HomeAccountingDB;
+use crate::importers::parse_file;
+use crate::importers::pipeline::{ImportPipeline, ImportSummary};
+
+const ARCHIVE_SUBFOLDER: &str = "archive";
+const FAILED_SUBFOLDER: &str = "failed";
+
+/// File name with result of its import.
+pub type ImportedFile = (String, Result<ImportSummary, Error>);
+
+pub struct FolderWatcher {
+    folder: PathBuf,
+    pipeline: ImportPipeline
+}
+
+/// Moves file to subfolder, existing file with the same name is not overwritten.
+fn move_to(file: &Path, subfolder: &Path) -> Result<(), Error> {
+    fs::create_dir_all(subfolder)?;
+    let name = file.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let mut target = subfolder.join(&name);
+    let mut n = 1;
+    while target.exists() {
+        target = subfolder.join(format!("{}.{}", name, n));
+        n += 1;
+    }
+    fs::rename(file, target)
+}
+
+impl FolderWatcher {
+    pub fn new(folder: String, pipeline: ImportPipeline) -> FolderWatcher {
+        FolderWatcher{folder: PathBuf::from(folder), pipeline}
+    }
+
+    fn import_file(&mut self, db: &mut HomeAccountingDB, file: &Path) -> Result<ImportSummary, Error> {
+        let transactions = parse_file(&file.to_string_lossy(), &fs::read_to_string(file)?,
+                                      &db.get_settings().day_boundary())?;
+        let summary = self.pipeline.import(db, &transactions)?;
+        db.flush(false)?;
+        Ok(summary)
+    }
+
+    /// Imports all csv and ofx files of the folder, returns file names with import results.
+    pub fn scan(&mut self, db: &mut HomeAccountingDB) -> Result<Vec<ImportedFile>, Error> {
+        let mut files: Vec<PathBuf> = fs::read_dir(&self.folder)?
+            .filter_map(|e|e.ok())
+            .map(|e|e.path())
+            .filter(|p|p.is_file() && p.extension().and_then(|e|e.to_str())
+                .is_some_and(|e|e.eq_ignore_ascii_case("csv") || e.eq_ignore_ascii_case("ofx")))
+            .collect();
+        files.sort();
+        let mut result = Vec::new();
+        for file in files {
+            let summary = self.import_file(db, &file);
+            let subfolder = if summary.is_ok() {ARCHIVE_SUBFOLDER} else {FAILED_SUBFOLDER};
+            move_to(&file, &self.folder.join(subfolder))?;
+            result.push((file.file_name().unwrap_or_default().to_string_lossy().to_string(), summary));
+        }
+        Ok(result)
+    }
+}
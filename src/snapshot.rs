@@ -0,0 +1,270 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Error, ErrorKind};
+use serde::Deserialize;
+use crate::core::interner::StringPool;
+use crate::entities::accounts::{Account, AccountId, Accounts};
+use crate::entities::currencies::{Currencies, Currency};
+use crate::entities::payees::{Payee, PayeeId, Payees};
+use crate::entities::finance_operations::{FinanceChanges, FinanceOperation, SpecialHandlers};
+use crate::entities::subcategories::{Categories, Category, CategoryId, Subcategories, Subcategory, SubcategoryId};
+use crate::reports::summary::{build_category_summary, build_payee_summary, build_subcategory_summary, SummaryItem};
+
+#[derive(Deserialize)]
+struct SnapshotJson {
+    accounts: Vec<Account>,
+    categories: Vec<Category>,
+    subcategories: Vec<Subcategory>,
+    #[serde(default)]
+    currencies: Vec<Currency>,
+    #[serde(default)]
+    payees: Vec<Payee>,
+    operations: BTreeMap<u64, Vec<FinanceOperation>>
+}
+
+/// Read-only in-memory copy of a database, loaded from bytes without any filesystem access.
+pub struct Snapshot {
+    accounts: Accounts,
+    categories: Categories,
+    subcategories: Subcategories,
+    currencies: Currencies,
+    payees: Payees,
+    handlers: SpecialHandlers,
+    /// balances before the first operation, converted by currency changes up to its date
+    opening_balances: HashMap<AccountId, i64>,
+    operations: BTreeMap<u64, Vec<FinanceOperation>>
+}
+
+impl Snapshot {
+    /// Operations are grouped by date, like in the dates folder of a JSON database.
+    pub fn from_json(data: &[u8]) -> Result<Snapshot, Error> {
+        let mut s: SnapshotJson = serde_json::from_slice(data)
+            .map_err(|e|Error::new(ErrorKind::InvalidData, e.to_string()))?;
+        let mut pool = StringPool::new();
+        for (date, ops) in s.operations.iter_mut() {
+            for op in ops.iter_mut() {
+                op.date = *date;
+                op.intern_strings(&mut pool);
+            }
+        }
+        let accounts = Accounts::new(s.accounts)?;
+        let currencies = Currencies::new(s.currencies);
+        currencies.validate(&accounts)?;
+        Ok(Snapshot{
+            accounts,
+            categories: Categories::new(s.categories),
+            subcategories: Subcategories::new(s.subcategories),
+            currencies,
+            payees: Payees::new(s.payees),
+            handlers: SpecialHandlers::new(),
+            opening_balances: HashMap::new(),
+            operations: s.operations
+        })
+    }
+
+    /// Snapshot of copied dictionaries and operations, later changes of the source are not visible.
+    pub fn new(accounts: Vec<Account>, categories: Vec<Category>, subcategories: Vec<Subcategory>,
+               currencies: Vec<Currency>, payees: Vec<Payee>, opening_balances: HashMap<AccountId, i64>,
+               operations: Vec<FinanceOperation>) -> Result<Snapshot, Error> {
+        let mut by_date: BTreeMap<u64, Vec<FinanceOperation>> = BTreeMap::new();
+        for op in operations {
+            by_date.entry(op.date).or_default().push(op);
+        }
+        Ok(Snapshot{
+            accounts: Accounts::new(accounts)?,
+            categories: Categories::new(categories),
+            subcategories: Subcategories::new(subcategories),
+            currencies: Currencies::new(currencies),
+            payees: Payees::new(payees),
+            handlers: SpecialHandlers::new(),
+            opening_balances,
+            operations: by_date
+        })
+    }
+
+    pub fn get_accounts(&self) -> &Accounts {
+        &self.accounts
+    }
+
+    pub fn get_categories(&self) -> &Categories {
+        &self.categories
+    }
+
+    pub fn get_subcategories(&self) -> &Subcategories {
+        &self.subcategories
+    }
+
+    pub fn get_currencies(&self) -> &Currencies {
+        &self.currencies
+    }
+
+    pub fn get_payees(&self) -> &Payees {
+        &self.payees
+    }
+
+    pub fn get_operations(&self, from: u64, to: u64) -> impl Iterator<Item = &FinanceOperation> {
+        self.operations.range(from..=to).flat_map(|(_, ops)|ops.iter())
+    }
+
+    /// Balances of accounts which currency changed are converted before operations of the change date.
+    pub fn build_changes(&self, from: u64, to: u64) -> Result<FinanceChanges, Error> {
+        let mut date = self.operations.keys().next().copied().unwrap_or(0);
+        let mut changes = FinanceChanges::new(&self.opening_balances);
+        for op in self.operations.range(..from).flat_map(|(_, ops)|ops.iter()) {
+            changes.convert_currencies(&self.accounts, date + 1, op.date)?;
+            date = date.max(op.date);
+            op.apply(&mut changes, &self.accounts, &self.subcategories, &self.handlers)?;
+        }
+        let mut changes = FinanceChanges::new(&changes.build_totals()?);
+        changes.convert_currencies(&self.accounts, date + 1, from)?;
+        date = date.max(from);
+        for op in self.get_operations(from, to) {
+            changes.convert_currencies(&self.accounts, date + 1, op.date)?;
+            date = date.max(op.date);
+            op.apply(&mut changes, &self.accounts, &self.subcategories, &self.handlers)?;
+        }
+        changes.convert_currencies(&self.accounts, date + 1, to)?;
+        Ok(changes)
+    }
+
+    pub fn build_subcategory_summary(&self, from: u64, to: u64) -> Result<BTreeMap<SubcategoryId, SummaryItem>, Error> {
+        build_subcategory_summary(self.get_operations(from, to), &self.subcategories)
+    }
+
+    pub fn build_category_summary(&self, from: u64, to: u64) -> Result<BTreeMap<CategoryId, SummaryItem>, Error> {
+        build_category_summary(self.get_operations(from, to), &self.subcategories)
+    }
+
+    pub fn build_payee_summary(&self, from: u64, to: u64) -> Result<BTreeMap<PayeeId, SummaryItem>, Error> {
+        build_payee_summary(self.get_operations(from, to), &self.subcategories)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Error;
+    use crate::entities::accounts::AccountId;
+    use crate::entities::subcategories::CategoryId;
+    use crate::reports::summary::SummaryItem;
+    use crate::snapshot::Snapshot;
+
+    const SNAPSHOT: &str = r#"{
+        "accounts": [
+            {"id": 1, "name": "Cash", "valutaCode": "UAH", "activeTo": null, "isCash": true},
+            {"id": 2, "name": "Card", "valutaCode": "UAH", "activeTo": null, "isCash": false}
+        ],
+        "categories": [{"id": 1, "name": "Salary"}, {"id": 2, "name": "Food"}],
+        "subcategories": [
+            {"id": 1, "name": "Salary", "code": null, "operationCodeId": "INCM", "categoryId": 1},
+            {"id": 2, "name": "Groceries", "code": null, "operationCodeId": "EXPN", "categoryId": 2},
+            {"id": 3, "name": "Cash withdrawal", "code": "EXPC", "operationCodeId": "SPCL", "categoryId": 2}
+        ],
+        "operations": {
+            "20240105": [
+                {"id": 0, "accountId": 2, "subcategoryId": 1, "amount": null, "summa": 1000, "finOpProperies": null}
+            ],
+            "20240210": [
+                {"id": 0, "accountId": 2, "subcategoryId": 2, "amount": null, "summa": 2.5, "finOpProperies": null},
+                {"id": 0, "accountId": 2, "subcategoryId": 3, "amount": null, "summa": 100, "finOpProperies": null}
+            ]
+        }
+    }"#;
+
+    #[test]
+    fn test_snapshot_changes() -> Result<(), Error> {
+        let snapshot = Snapshot::from_json(SNAPSHOT.as_bytes())?;
+        let changes = snapshot.build_changes(20240201, 20240229)?;
+        let card = changes.get(AccountId(2)).unwrap();
+        assert_eq!(card.get_start_balance(), 1000);
+        assert_eq!(card.get_expenditure(), 350);
+        assert_eq!(card.get_end_balance(), 650);
+        let cash = changes.get(AccountId(1)).unwrap();
+        assert_eq!(cash.get_start_balance(), 0);
+        assert_eq!(cash.get_income(), 100);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_opening_balances() -> Result<(), Error> {
+        let json = Snapshot::from_json(SNAPSHOT.as_bytes())?;
+        let operations = json.get_operations(20240201, 20240229).map(|op|op.copy()).collect();
+        let snapshot = Snapshot::new(json.get_accounts().iter().cloned().collect(),
+                                     json.get_categories().iter().cloned().collect(),
+                                     json.get_subcategories().iter().cloned().collect(), Vec::new(), Vec::new(),
+                                     HashMap::from([(AccountId(2), 1000)]), operations)?;
+        let changes = snapshot.build_changes(20240201, 20240229)?;
+        assert_eq!(changes.get(AccountId(2)).unwrap().get_end_balance(), 650);
+        assert_eq!(changes.get(AccountId(1)).unwrap().get_end_balance(), 100);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_category_summary() -> Result<(), Error> {
+        let snapshot = Snapshot::from_json(SNAPSHOT.as_bytes())?;
+        let summary = snapshot.build_category_summary(20240101, 20241231)?;
+        assert_eq!(summary.len(), 2);
+        assert_eq!(summary[&CategoryId(1)], SummaryItem{income: 1000, expenditure: 0});
+        assert_eq!(summary[&CategoryId(2)], SummaryItem{income: 0, expenditure: 250});
+        Ok(())
+    }
+
+    #[test]
+    fn test_transfer_currency_mismatch() -> Result<(), Error> {
+        let snapshot = Snapshot::from_json(r#"{
+            "accounts": [
+                {"id": 1, "name": "Card UAH", "valutaCode": "UAH", "activeTo": null, "isCash": false},
+                {"id": 2, "name": "Card USD", "valutaCode": "USD", "activeTo": null, "isCash": false},
+                {"id": 3, "name": "Cash UAH", "valutaCode": "UAH", "activeTo": null, "isCash": true},
+                {"id": 4, "name": "Cash USD", "valutaCode": "USD", "activeTo": null, "isCash": true}
+            ],
+            "categories": [{"id": 1, "name": "Transfers"}],
+            "subcategories": [
+                {"id": 1, "name": "Transfer", "code": "TRFR", "operationCodeId": "SPCL", "categoryId": 1}
+            ],
+            "operations": {
+                "20240105": [
+                    {"id": 0, "accountId": 1, "subcategoryId": 1, "amount": null, "summa": 10,
+                     "finOpProperies": [{"numericValue": 2, "stringValue": null, "dateValue": null, "propertyCode": "SECA"}]}
+                ]
+            }
+        }"#.as_bytes())?;
+        let e = snapshot.build_changes(20240101, 20240131).err().unwrap();
+        assert!(e.to_string().starts_with("transfer between accounts with different currencies"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_currency_change() -> Result<(), Error> {
+        let snapshot = Snapshot::from_json(r#"{
+            "accounts": [
+                {"id": 1, "name": "Cash BYN", "valutaCode": "BYN", "activeTo": null, "isCash": true,
+                 "currencyHistory": [{"date": [2016, 7, 1], "valutaCode": "BYR", "rate": 100}]},
+                {"id": 2, "name": "Card", "valutaCode": "BYN", "activeTo": null, "isCash": false,
+                 "currencyHistory": [{"date": [2016, 7, 1], "valutaCode": "BYR", "rate": 100}]}
+            ],
+            "categories": [{"id": 1, "name": "Salary"}, {"id": 2, "name": "Cash"}],
+            "subcategories": [
+                {"id": 1, "name": "Salary", "code": null, "operationCodeId": "INCM", "categoryId": 1},
+                {"id": 2, "name": "Cash withdrawal", "code": "EXPC", "operationCodeId": "SPCL", "categoryId": 2}
+            ],
+            "operations": {
+                "20160610": [
+                    {"id": 0, "accountId": 2, "subcategoryId": 1, "amount": null, "summa": 5000000, "finOpProperies": null},
+                    {"id": 0, "accountId": 2, "subcategoryId": 2, "amount": null, "summa": 1000000, "finOpProperies": null}
+                ],
+                "20160705": [
+                    {"id": 0, "accountId": 2, "subcategoryId": 1, "amount": null, "summa": 100, "finOpProperies": null}
+                ]
+            }
+        }"#.as_bytes())?;
+        let card = snapshot.get_accounts().get(AccountId(2))?;
+        assert_eq!((card.currency_at(20160630), card.currency_at(20160701)), ("BYR", "BYN"));
+        let changes = snapshot.build_changes(20160601, 20160630)?;
+        assert_eq!(changes.get(AccountId(2)).unwrap().get_end_balance(), 4000000);
+        let changes = snapshot.build_changes(20160701, 20160731)?;
+        let card = changes.get(AccountId(2)).unwrap();
+        assert_eq!((card.get_start_balance(), card.get_end_balance()), (400, 500));
+        assert_eq!(changes.get(AccountId(1)).unwrap().get_end_balance(), 100);
+        Ok(())
+    }
+}
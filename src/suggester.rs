@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use crate::entities::finance_operations::{FinOpParameter, FinanceOperation};
+use crate::entities::payees::PayeeId;
+use crate::entities::subcategories::SubcategoryId;
+
+type Counts = HashMap<SubcategoryId, u64>;
+
+/// Suggests subcategory for a payee or NETW string by frequency in historical operations.
+#[derive(Default)]
+pub struct SubcategorySuggester {
+    by_payee: HashMap<PayeeId, Counts>,
+    by_text: HashMap<String, Counts>
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+fn ranked(counts: Option<&Counts>) -> Vec<(SubcategoryId, u64)> {
+    let mut result: Vec<(SubcategoryId, u64)> = counts
+        .map(|c|c.iter().map(|(s, n)|(*s, *n)).collect())
+        .unwrap_or_default();
+    result.sort_by(|a, b|b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    result
+}
+
+impl SubcategorySuggester {
+    pub fn new<'a>(operations: impl Iterator<Item = &'a FinanceOperation>) -> SubcategorySuggester {
+        let mut suggester = SubcategorySuggester::default();
+        for op in operations {
+            suggester.learn(op);
+        }
+        suggester
+    }
+
+    pub fn learn(&mut self, op: &FinanceOperation) {
+        let subcategory = op.get_subcategory();
+        if let Some(payee) = op.get_payee() {
+            *self.by_payee.entry(payee).or_default().entry(subcategory).or_default() += 1;
+        }
+        for p in op.get_parameters() {
+            if let FinOpParameter::Netw(text) = p {
+                *self.by_text.entry(normalize(text)).or_default().entry(subcategory).or_default() += 1;
+            }
+        }
+    }
+
+    /// Subcategories used with the payee with number of operations, most frequent first.
+    pub fn rank_for_payee(&self, payee: PayeeId) -> Vec<(SubcategoryId, u64)> {
+        ranked(self.by_payee.get(&payee))
+    }
+
+    /// Subcategories used with the NETW string with number of operations, most frequent first.
+    pub fn rank_for_text(&self, text: &str) -> Vec<(SubcategoryId, u64)> {
+        ranked(self.by_text.get(&normalize(text)))
+    }
+
+    pub fn suggest_for_payee(&self, payee: PayeeId) -> Option<SubcategoryId> {
+        self.rank_for_payee(payee).first().map(|(s, _)|*s)
+    }
+
+    pub fn suggest_for_text(&self, text: &str) -> Option<SubcategoryId> {
+        self.rank_for_text(text).first().map(|(s, _)|*s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entities::accounts::AccountId;
+    use crate::entities::finance_operations::{FinOpParameter, FinanceOperation};
+    use crate::entities::subcategories::SubcategoryId;
+    use crate::suggester::SubcategorySuggester;
+
+    fn op(subcategory: u64, netw: &str) -> FinanceOperation {
+        FinanceOperation::new(20240101, AccountId(1), SubcategoryId(subcategory), None, 100,
+                              vec![FinOpParameter::Netw(netw.into())])
+    }
+
+    #[test]
+    fn test_suggest_for_text() {
+        let ops = [op(1, "SILPO"), op(2, "silpo "), op(2, "Silpo"), op(3, "WOG")];
+        let suggester = SubcategorySuggester::new(ops.iter());
+        assert_eq!(suggester.suggest_for_text("SILPO"), Some(SubcategoryId(2)));
+        assert_eq!(suggester.rank_for_text("silpo"), vec![(SubcategoryId(2), 2), (SubcategoryId(1), 1)]);
+        assert_eq!(suggester.suggest_for_text("ATB"), None);
+    }
+}
@@ -0,0 +1,114 @@
+//! Per-month file checksums of the last successful verification, used by incremental verify.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use crate::core::totals_cache::{TOTALS_FILE_NAME, TOTALS_WATERMARK_FILE_NAME};
+
+pub const CHECKSUMS_FILE_NAME: &str = "verify_checksums.txt";
+/// key of the dictionaries checksum, months are keyed by yyyymm
+pub const DICTIONARIES_KEY: u64 = 0;
+
+/// FNV-1a, stable between runs and platforms.
+fn update_hash(mut hash: u64, data: &[u8]) -> u64 {
+    for b in data {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// FNV-1a checksum of data.
+pub fn checksum(data: &[u8]) -> u64 {
+    update_hash(0xcbf29ce484222325, data)
+}
+
+fn hash_files(hash: u64, folder: &Path) -> Result<u64, Error> {
+    let mut files: Vec<_> = fs::read_dir(folder)?
+        .filter_map(|e|e.ok())
+        .filter(|e|e.file_type().is_ok_and(|t|t.is_file()))
+        .filter(|e|![CHECKSUMS_FILE_NAME, TOTALS_FILE_NAME, TOTALS_WATERMARK_FILE_NAME].iter().any(|n|e.file_name() == *n))
+        .collect();
+    files.sort_by_key(|e|e.file_name());
+    let mut hash = hash;
+    for file in files {
+        hash = update_hash(hash, file.file_name().to_string_lossy().as_bytes());
+        hash = update_hash(hash, &fs::read(file.path())?);
+    }
+    Ok(hash)
+}
+
+/// Checksums of the dictionary files and of every month in the dates folder.
+pub fn build_checksums(data_folder_path: &str) -> Result<BTreeMap<u64, u64>, Error> {
+    build_selected_checksums(data_folder_path, |_|true)
+}
+
+/// Checksums of the dictionary files and of the months (yyyymm) accepted by the filter,
+/// other months are not read.
+pub fn build_selected_checksums(data_folder_path: &str, selected: impl Fn(u64) -> bool)
+    -> Result<BTreeMap<u64, u64>, Error> {
+    let mut result = BTreeMap::new();
+    result.insert(DICTIONARIES_KEY, hash_files(0xcbf29ce484222325, Path::new(data_folder_path))?);
+    let dates = Path::new(data_folder_path).join("dates");
+    let mut folders: Vec<(u64, String)> = fs::read_dir(&dates)?
+        .filter_map(|e|e.ok())
+        .filter_map(|e|{
+            let name = e.file_name().to_string_lossy().to_string();
+            name.parse().ok().map(|date|(date, name))
+        })
+        .filter(|(date, _)|selected(date / 100))
+        .collect();
+    folders.sort();
+    for (date, name) in folders {
+        let month = date / 100;
+        let hash = *result.get(&month).unwrap_or(&0xcbf29ce484222325);
+        result.insert(month, hash_files(update_hash(hash, name.as_bytes()), &dates.join(name))?);
+    }
+    Ok(result)
+}
+
+/// Months which checksums differ from the saved ones, all months when dictionaries were changed.
+pub fn changed_months(current: &BTreeMap<u64, u64>, saved: &BTreeMap<u64, u64>) -> BTreeSet<u64> {
+    let all = current.get(&DICTIONARIES_KEY) != saved.get(&DICTIONARIES_KEY);
+    current.iter()
+        .filter(|(k, v)|**k != DICTIONARIES_KEY && (all || saved.get(k) != Some(v)))
+        .map(|(k, _)|*k)
+        .collect()
+}
+
+pub fn load_checksums(data_folder_path: &str) -> Result<BTreeMap<u64, u64>, Error> {
+    let text = match fs::read_to_string(Path::new(data_folder_path).join(CHECKSUMS_FILE_NAME)) {
+        Ok(t) => t,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e)
+    };
+    let mut result = BTreeMap::new();
+    for line in text.lines().filter(|l|!l.is_empty()) {
+        let (key, checksum) = line.split_once(' ')
+            .and_then(|(k, c)|Some((k.parse().ok()?, u64::from_str_radix(c, 16).ok()?)))
+            .ok_or(Error::new(ErrorKind::InvalidData, format!("invalid checksum line {}", line)))?;
+        result.insert(key, checksum);
+    }
+    Ok(result)
+}
+
+pub fn save_checksums(data_folder_path: &str, checksums: &BTreeMap<u64, u64>) -> Result<(), Error> {
+    let text: String = checksums.iter().map(|(k, c)|format!("{} {:016x}\n", k, c)).collect();
+    fs::write(Path::new(data_folder_path).join(CHECKSUMS_FILE_NAME), text)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+    use crate::verify::checksums::changed_months;
+
+    #[test]
+    fn test_changed_months() {
+        let saved = BTreeMap::from([(0, 1), (202401, 2), (202402, 3)]);
+        let current = BTreeMap::from([(0, 1), (202401, 2), (202402, 4), (202403, 5)]);
+        assert_eq!(changed_months(&current, &saved), BTreeSet::from([202402, 202403]));
+        let current = BTreeMap::from([(0, 9), (202401, 2)]);
+        assert_eq!(changed_months(&current, &saved), BTreeSet::from([202401]));
+    }
+}
@@ -0,0 +1,32 @@
+use std::io::Error;
+use crate::core::time_series_data::DataRange;
+use crate::entities::finance_operations::FinanceRecord;
+
+pub struct DuplicateOperation {
+    pub date: u64,
+    pub file: Option<String>,
+    /// index of the duplicate within the month
+    pub index: usize,
+    /// index of the operation it repeats
+    pub original: usize
+}
+
+impl DuplicateOperation {
+    pub fn describe(&self) -> String {
+        format!("{} {}: operation {} duplicates operation {}", self.date, self.file.as_deref().unwrap_or("-"),
+                self.index, self.original)
+    }
+}
+
+/// Finds operations repeating another operation of the same month exactly, usually left by a repeated import.
+pub fn check_duplicates(records: &DataRange<FinanceRecord>) -> Result<Vec<DuplicateOperation>, Error> {
+    let mut result = Vec::new();
+    for (_, record) in records {
+        let r = record.lock().unwrap();
+        for (index, original) in r.find_duplicates() {
+            result.push(DuplicateOperation{date: r.operations[index].date, file: r.get_file(index).map(|f|f.to_string()),
+                index, original});
+        }
+    }
+    Ok(result)
+}
@@ -0,0 +1,171 @@
+//! Integrity manifest written by migrate: per-month operation counts and hashes of the source and of
+//! the migrated database. Hashes are computed from operations, not files, so they don't depend on
+//! the storage format, and verify checks the live database against the destination hashes.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use crate::core::time_series_data::DataRange;
+use crate::entities::finance_operations::{FinOpParameter, FinanceOperation, FinanceRecord, ParameterValue};
+use crate::verify::checksums::checksum;
+
+pub const MANIFEST_FILE_NAME: &str = "migration_manifest.txt";
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct MonthDigest {
+    pub count: usize,
+    pub hash: u64
+}
+
+pub struct ManifestLine {
+    pub month: u64,
+    pub source: MonthDigest,
+    pub destination: MonthDigest
+}
+
+pub struct ManifestMismatch {
+    pub month: u64,
+    pub expected: Option<MonthDigest>,
+    pub actual: Option<MonthDigest>
+}
+
+impl ManifestMismatch {
+    pub fn describe(&self) -> String {
+        let count = |d: Option<MonthDigest>|d.map(|d|d.count).unwrap_or(0);
+        format!("manifest mismatch: month {} expected {} operations, actual {}{}", self.month, count(self.expected),
+                count(self.actual), if count(self.expected) == count(self.actual) {" with different contents"} else {""})
+    }
+}
+
+fn parameter_text(p: &FinOpParameter) -> String {
+    let value = match p {
+        FinOpParameter::Amou(v) | FinOpParameter::Dist(v) | FinOpParameter::Ppto(v) => v.to_string(),
+        FinOpParameter::Seca(a) => a.0.to_string(),
+        FinOpParameter::Inst(i) => i.0.to_string(),
+        FinOpParameter::Netw(v) | FinOpParameter::Typ(v) => v.to_string(),
+        FinOpParameter::Custom(c) => match &c.value {
+            ParameterValue::Numeric(v) | ParameterValue::Date(v) => v.to_string(),
+            ParameterValue::String(v) => v.clone()
+        }
+    };
+    format!("{}={}", p.get_code(), value)
+}
+
+/// All stored fields of the operation, parameters and attachments in their order.
+fn operation_text(op: &FinanceOperation) -> String {
+    let parameters: Vec<String> = op.get_parameters().iter().map(parameter_text).collect();
+    format!("{}|{}|{}|{:?}|{}|{}|{:?}|{}", op.date, op.get_account().0, op.get_subcategory().0, op.get_amount(),
+            op.get_summa(), parameters.join(","), op.get_payee().map(|p|p.0), op.get_attachments().join(","))
+}
+
+/// Digest of every month, independent of the order of operations within a date.
+pub fn build_digests(records: &DataRange<FinanceRecord>) -> BTreeMap<u64, MonthDigest> {
+    let mut result = BTreeMap::new();
+    for (key, record) in records {
+        let r = record.lock().unwrap();
+        if r.operations.is_empty() {
+            continue;
+        }
+        let mut lines: Vec<String> = r.operations.iter().map(operation_text).collect();
+        lines.sort();
+        result.insert(*key, MonthDigest{count: lines.len(), hash: checksum(lines.join("\n").as_bytes())});
+    }
+    result
+}
+
+/// Months missing in one of the digests or with different counts or hashes.
+pub fn compare_digests(expected: &BTreeMap<u64, MonthDigest>, actual: &BTreeMap<u64, MonthDigest>) -> Vec<ManifestMismatch> {
+    let mut months: Vec<u64> = expected.keys().chain(actual.keys()).cloned().collect();
+    months.sort();
+    months.dedup();
+    months.into_iter()
+        .filter(|m|expected.get(m) != actual.get(m))
+        .map(|month|ManifestMismatch{month, expected: expected.get(&month).cloned(), actual: actual.get(&month).cloned()})
+        .collect()
+}
+
+/// One "yyyymm count source_hash count destination_hash" line per month.
+pub fn save_manifest(data_folder_path: &str, lines: &[ManifestLine]) -> Result<(), Error> {
+    let text: String = lines.iter()
+        .map(|l|format!("{} {} {:016x} {} {:016x}\n", l.month, l.source.count, l.source.hash, l.destination.count,
+                        l.destination.hash))
+        .collect();
+    fs::write(Path::new(data_folder_path).join(MANIFEST_FILE_NAME), text)
+}
+
+/// Manifest of the data folder, None when it was not migrated.
+pub fn load_manifest(data_folder_path: &str) -> Result<Option<Vec<ManifestLine>>, Error> {
+    let text = match fs::read_to_string(Path::new(data_folder_path).join(MANIFEST_FILE_NAME)) {
+        Ok(t) => t,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e)
+    };
+    let parse_line = |line: &str| -> Option<ManifestLine> {
+        let parts: Vec<&str> = line.split(' ').collect();
+        if parts.len() != 5 {
+            return None;
+        }
+        let digest = |count: &str, hash: &str|Some(MonthDigest{count: count.parse().ok()?,
+            hash: u64::from_str_radix(hash, 16).ok()?});
+        Some(ManifestLine{month: parts[0].parse().ok()?, source: digest(parts[1], parts[2])?,
+            destination: digest(parts[3], parts[4])?})
+    };
+    text.lines()
+        .filter(|l|!l.is_empty())
+        .map(|line|parse_line(line).ok_or(Error::new(ErrorKind::InvalidData, format!("invalid manifest line {}", line))))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+/// Removes lines of months changed after migration, their digests no longer describe the database.
+pub fn forget_months(data_folder_path: &str, months: &[u64]) -> Result<(), Error> {
+    let Some(lines) = load_manifest(data_folder_path)? else {
+        return Ok(());
+    };
+    if !lines.iter().any(|l|months.contains(&l.month)) {
+        return Ok(());
+    }
+    let lines: Vec<ManifestLine> = lines.into_iter().filter(|l|!months.contains(&l.month)).collect();
+    save_manifest(data_folder_path, &lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::Error;
+    use std::sync::{Arc, Mutex};
+    use crate::entities::accounts::AccountId;
+    use crate::entities::finance_operations::{FinOpParameter, FinanceOperation, FinanceRecord};
+    use crate::entities::subcategories::SubcategoryId;
+    use crate::verify::manifest::{build_digests, compare_digests, forget_months, load_manifest, save_manifest, ManifestLine};
+
+    fn record(summas: &[i64]) -> Arc<Mutex<FinanceRecord>> {
+        Arc::new(Mutex::new(FinanceRecord::new(summas.iter()
+            .map(|s|FinanceOperation::new(20240105, AccountId(1), SubcategoryId(2), None, *s,
+                                          vec![FinOpParameter::Netw("SILPO".into())]))
+            .collect())))
+    }
+
+    #[test]
+    fn test_manifest() -> Result<(), Error> {
+        let source = build_digests(&vec![(202401, record(&[100, 200])), (202402, record(&[300]))]);
+        assert_eq!(compare_digests(&source, &build_digests(&vec![(202401, record(&[200, 100])), (202402, record(&[300]))])).len(), 0);
+        let changed = build_digests(&vec![(202401, record(&[100, 201]))]);
+        let mismatches = compare_digests(&source, &changed);
+        assert_eq!(mismatches.iter().map(|m|m.month).collect::<Vec<_>>(), vec![202401, 202402]);
+        assert_eq!(mismatches[1].describe(), "manifest mismatch: month 202402 expected 1 operations, actual 0");
+        let folder = temp_dir().join("hadb_manifest_test");
+        fs::create_dir_all(&folder)?;
+        let path = folder.to_string_lossy().to_string();
+        save_manifest(&path, &[ManifestLine{month: 202401, source: source[&202401], destination: source[&202401]}])?;
+        let lines = load_manifest(&path)?.unwrap();
+        let loaded: BTreeMap<_, _> = lines.iter().map(|l|(l.month, l.destination)).collect();
+        assert_eq!(loaded.get(&202401), source.get(&202401));
+        forget_months(&path, &[202401])?;
+        assert!(load_manifest(&path)?.unwrap().is_empty());
+        fs::remove_dir_all(&folder)
+    }
+}
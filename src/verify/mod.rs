@@ -0,0 +1,8 @@
+pub mod totals;
+pub mod references;
+pub mod duplicates;
+pub mod checksums;
+pub mod opening;
+pub mod repair;
+pub mod manifest;
+pub mod startup;
@@ -0,0 +1,53 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Error;
+use crate::core::time_series_data::DataRange;
+use crate::entities::accounts::AccountId;
+use crate::entities::finance_operations::{FinanceOperation, FinanceRecord};
+use crate::entities::subcategories::{Subcategories, SubcategoryCode};
+
+pub struct OpeningBalanceProblem {
+    pub account: AccountId,
+    pub message: String
+}
+
+impl OpeningBalanceProblem {
+    pub fn describe(&self) -> String {
+        format!("account {}: {}", self.account, self.message)
+    }
+}
+
+pub fn is_opening_balance(op: &FinanceOperation, subcategories: &Subcategories) -> bool {
+    subcategories.get(op.get_subcategory()).is_ok_and(|s|s.code == SubcategoryCode::Opbl)
+}
+
+/// Checks that every account has at most one opening balance operation and no operations before it.
+pub fn check_opening_balances(records: &DataRange<FinanceRecord>, subcategories: &Subcategories)
+    -> Result<Vec<OpeningBalanceProblem>, Error> {
+    let mut openings: BTreeMap<AccountId, Vec<u64>> = BTreeMap::new();
+    let mut first_operations: HashMap<AccountId, u64> = HashMap::new();
+    for (_, record) in records {
+        let r = record.lock().unwrap();
+        for op in &r.operations {
+            if is_opening_balance(op, subcategories) {
+                openings.entry(op.get_account()).or_default().push(op.date);
+                continue;
+            }
+            for account in [Some(op.get_account()), op.get_second_account()].into_iter().flatten() {
+                let first = first_operations.entry(account).or_insert(op.date);
+                *first = (*first).min(op.date);
+            }
+        }
+    }
+    let mut result = Vec::new();
+    for (account, dates) in openings {
+        if dates.len() > 1 {
+            result.push(OpeningBalanceProblem{account, message: format!("{} opening balance operations", dates.len())});
+        }
+        let opening = dates.into_iter().min().unwrap();
+        if let Some(first) = first_operations.get(&account).filter(|d|**d < opening) {
+            result.push(OpeningBalanceProblem{account,
+                message: format!("operation at {} before opening balance at {}", first, opening)});
+        }
+    }
+    Ok(result)
+}
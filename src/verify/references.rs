@@ -0,0 +1,50 @@
+use std::io::Error;
+use crate::core::time_series_data::DataRange;
+use crate::entities::accounts::Accounts;
+use crate::entities::finance_operations::FinanceRecord;
+use crate::entities::payees::Payees;
+use crate::entities::subcategories::Subcategories;
+
+pub struct ReferenceViolation {
+    pub date: u64,
+    pub file: Option<String>,
+    pub message: String
+}
+
+impl ReferenceViolation {
+    pub fn describe(&self) -> String {
+        format!("{} {}: {}", self.date, self.file.as_deref().unwrap_or("-"), self.message)
+    }
+}
+
+/// Checks that accounts, subcategories and payees referenced by operations exist.
+pub fn check_references(records: &DataRange<FinanceRecord>, accounts: &Accounts, subcategories: &Subcategories,
+                        payees: &Payees) -> Result<Vec<ReferenceViolation>, Error> {
+    let mut result = Vec::new();
+    for (_, record) in records {
+        let r = record.lock().unwrap();
+        for (i, op) in r.operations.iter().enumerate() {
+            let mut messages = Vec::new();
+            if accounts.get(op.get_account()).is_err() {
+                messages.push(format!("invalid account id {}", op.get_account()));
+            }
+            if let Some(a) = op.get_second_account() {
+                if accounts.get(a).is_err() {
+                    messages.push(format!("invalid second account id {}", a));
+                }
+            }
+            if subcategories.get(op.get_subcategory()).is_err() {
+                messages.push(format!("invalid subcategory id {}", op.get_subcategory()));
+            }
+            if let Some(p) = op.get_payee() {
+                if payees.get(p).is_err() {
+                    messages.push(format!("invalid payee id {}", p));
+                }
+            }
+            for message in messages {
+                result.push(ReferenceViolation{date: op.date, file: r.get_file(i).map(|f|f.to_string()), message});
+            }
+        }
+    }
+    Ok(result)
+}
@@ -0,0 +1,131 @@
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::core::dates::days_in_month;
+use crate::entities::finance_operations::FinanceRecord;
+use crate::entities::subcategories::{Subcategories, SubcategoryOperationCode};
+
+/// Fixer of historical data, every fixer can be run alone and in dry run mode.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fixer {
+    /// operation dates are taken from names of their folders
+    Dates,
+    /// operations stored in a month their date doesn't belong to are moved to the right month
+    Months,
+    /// negative income and expenditure left by imports using bank sign convention are made positive
+    Signs,
+    /// totals of all months are recalculated
+    Totals
+}
+
+impl Fixer {
+    pub const ALL: [Fixer; 4] = [Fixer::Dates, Fixer::Months, Fixer::Signs, Fixer::Totals];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Fixer::Dates => "dates",
+            Fixer::Months => "months",
+            Fixer::Signs => "signs",
+            Fixer::Totals => "totals"
+        }
+    }
+
+    /// Comma separated fixer names or all.
+    pub fn parse_list(text: &str) -> Result<Vec<Fixer>, Error> {
+        if text == "all" {
+            return Ok(Fixer::ALL.to_vec());
+        }
+        text.split(',')
+            .map(|name|Fixer::ALL.into_iter().find(|f|f.name() == name)
+                .ok_or(Error::new(ErrorKind::InvalidInput, format!("unknown fixer {}", name))))
+            .collect()
+    }
+}
+
+pub struct RepairChange {
+    pub fixer: Fixer,
+    /// yyyymm
+    pub month: u64,
+    pub description: String
+}
+
+impl RepairChange {
+    /// Change of the index-th operation of the month record.
+    pub fn operation(fixer: Fixer, month: u64, record: &FinanceRecord, index: usize, description: String) -> RepairChange {
+        RepairChange{fixer, month, description: format!("{} operation {}: {}", record.get_file(index).unwrap_or("-"),
+                                                       index, description)}
+    }
+
+    pub fn describe(&self) -> String {
+        format!("{} {}: {}", self.fixer.name(), self.month, self.description)
+    }
+}
+
+/// Date of the folder the file is stored in, days past the month end are moved to its last day.
+fn folder_date(file: &str) -> Option<u64> {
+    let date: u64 = Path::new(file).parent()?.file_name()?.to_str()?.parse().ok()?;
+    let (year, month) = (date / 10000, date / 100 % 100);
+    if year == 0 || !(1..=12).contains(&month) {
+        return None;
+    }
+    Some(year * 10000 + month * 100 + (date % 100).clamp(1, days_in_month(year, month)))
+}
+
+/// Indices and folder dates of operations of the month which dates differ from their folder names.
+pub fn find_date_fixes(month: u64, record: &FinanceRecord) -> Vec<(usize, u64)> {
+    record.operations.iter().enumerate()
+        .filter_map(|(i, op)|record.get_file(i).and_then(folder_date)
+            .filter(|date|*date != op.date && *date / 100 == month)
+            .map(|date|(i, date)))
+        .collect()
+}
+
+/// Indices of operations which dates are not in the month.
+pub fn find_misplaced(month: u64, record: &FinanceRecord) -> Vec<usize> {
+    record.operations.iter().enumerate()
+        .filter(|(_, op)|op.date / 100 != month)
+        .map(|(i, _)|i)
+        .collect()
+}
+
+/// Indices of income and expenditure operations with negative summa.
+pub fn find_sign_fixes(record: &FinanceRecord, subcategories: &Subcategories) -> Result<Vec<usize>, Error> {
+    let mut result = Vec::new();
+    for (i, op) in record.operations.iter().enumerate() {
+        let operation_code = &subcategories.get(op.get_subcategory())?.operation_code;
+        if op.get_summa() < 0 && !matches!(operation_code, SubcategoryOperationCode::Spcl) {
+            result.push(i);
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+    use crate::entities::accounts::AccountId;
+    use crate::entities::finance_operations::{FinanceOperation, FinanceRecord};
+    use crate::entities::subcategories::{CategoryId, Subcategories, Subcategory, SubcategoryCode, SubcategoryId,
+                                         SubcategoryOperationCode};
+    use crate::verify::repair::{find_date_fixes, find_misplaced, find_sign_fixes, Fixer};
+
+    #[test]
+    fn test_fixers() -> Result<(), Error> {
+        let subcategory = |id, code, operation_code|Subcategory{id: SubcategoryId(id), name: id.to_string(), code,
+            operation_code, category: CategoryId(1), tax_relevant: false, retired: false, soft_limit: None, hard_limit: None};
+        let subcategories = Subcategories::new(vec![subcategory(1, SubcategoryCode::None, SubcategoryOperationCode::Expn),
+                                                    subcategory(2, SubcategoryCode::Trfr, SubcategoryOperationCode::Spcl)]);
+        let op = |date, subcategory, summa|FinanceOperation::new(date, AccountId(1), SubcategoryId(subcategory), None,
+                                                                 summa, Vec::new());
+        let mut record = FinanceRecord::new(Vec::new());
+        record.add_file_operations("dates/20240431/ops.json".to_string(), vec![op(20240431, 1, -100), op(20240431, 2, -5)]);
+        record.add_file_operations("dates/20240502/ops.json".to_string(), vec![op(20240502, 1, 200)]);
+        assert_eq!(find_date_fixes(202404, &record), vec![(0, 20240430), (1, 20240430)]);
+        assert_eq!(find_misplaced(202404, &record), vec![2]);
+        assert_eq!(find_sign_fixes(&record, &subcategories)?, vec![0]);
+        assert_eq!(Fixer::parse_list("signs,totals")?, vec![Fixer::Signs, Fixer::Totals]);
+        assert!(Fixer::parse_list("sign").is_err());
+        Ok(())
+    }
+}
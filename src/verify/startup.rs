@@ -0,0 +1,197 @@
+//! Quick integrity check run before the server starts accepting writes. Dictionaries and every file of
+//! the most recent months are decoded one by one, so a damaged file is reported by name while older
+//! months are not read at all. Dictionaries and months whose checksums match the ones saved by the
+//! last successful incremental verify are known to be good and are not decoded either.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Error, ErrorKind};
+use std::ops::Add;
+use crate::core::data_source::DataSource;
+use crate::core::time_series_data::{list_files, FileWithDate};
+use crate::db::DBConfiguration;
+use crate::entities::currencies::SharedMinorUnits;
+use crate::verify::checksums::{build_selected_checksums, load_checksums, DICTIONARIES_KEY};
+
+/// Number of months checked by the server on start.
+pub const STARTUP_CHECK_MONTHS: usize = 12;
+
+pub struct StartupProblem {
+    pub file: String,
+    pub message: String
+}
+
+impl StartupProblem {
+    pub fn describe(&self) -> String {
+        format!("{}: {}", self.file, self.message)
+    }
+}
+
+/// Missing optional dictionaries are fine, the database loads them as empty.
+fn check_dictionary<T>(problems: &mut Vec<StartupProblem>, data_folder_path: &str, name: &str,
+                       source: Box<dyn DataSource<T>>, required: bool) {
+    let file = data_folder_path.to_string().add("/").add(name);
+    match source.load(file.clone(), true) {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound && !required => {}
+        Err(e) => problems.push(StartupProblem{file, message: e.to_string()})
+    }
+}
+
+/// Every dictionary the database loads, the report specs included.
+fn check_dictionaries(problems: &mut Vec<StartupProblem>, data_folder_path: &str, configuration: &dyn DBConfiguration) {
+    check_dictionary(problems, data_folder_path, "accounts", configuration.get_accounts_source(), true);
+    check_dictionary(problems, data_folder_path, "categories", configuration.get_categories_source(), true);
+    check_dictionary(problems, data_folder_path, "subcategories", configuration.get_subcategories_source(), true);
+    check_dictionary(problems, data_folder_path, "currencies", configuration.get_currencies_source(), false);
+    check_dictionary(problems, data_folder_path, "payees", configuration.get_payees_source(), false);
+    check_dictionary(problems, data_folder_path, "parameters", configuration.get_parameters_source(), false);
+    check_dictionary(problems, data_folder_path, "loans", configuration.get_loans_source(), false);
+    check_dictionary(problems, data_folder_path, "instruments", configuration.get_instruments_source(), false);
+    check_dictionary(problems, data_folder_path, "prices", configuration.get_prices_source(), false);
+    check_dictionary(problems, data_folder_path, "goals", configuration.get_goals_source(), false);
+    check_dictionary(problems, data_folder_path, "budgets", configuration.get_budgets_source(), false);
+    check_dictionary(problems, data_folder_path, "rates", configuration.get_rates_source(), false);
+    check_dictionary(problems, data_folder_path, "settings", configuration.get_settings_source(), false);
+    check_dictionary(problems, data_folder_path, "templates", configuration.get_templates_source(), false);
+    check_dictionary(problems, data_folder_path, "reports", configuration.get_report_specs_source(), false);
+    check_dictionary(problems, data_folder_path, "validation_rules", configuration.get_validation_rules_source(), false);
+}
+
+/// Checks dictionaries and files of the last months of the dates folder, works with any backend.
+pub fn check_startup(data_folder_path: &str, configuration: &dyn DBConfiguration, months: usize)
+    -> Result<Vec<StartupProblem>, Error> {
+    let mut problems = Vec::new();
+    // decimal sums are read in hundredths, files are only checked to be readable here
+    let mut source = configuration.get_main_data_source(SharedMinorUnits::default());
+    let (file_map, load_problems) = match list_files(&data_folder_path.to_string().add("/dates"), source.as_ref(),
+                                                     |date|date / 100, true) {
+        Ok(r) => r,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            check_dictionaries(&mut problems, data_folder_path, configuration);
+            return Ok(problems);
+        }
+        Err(e) => return Err(e)
+    };
+    let mut keys: Vec<u64> = file_map.keys().copied().collect();
+    keys.sort();
+    let checked: BTreeSet<u64> = keys.iter().rev().take(months).copied().collect();
+    let saved = load_checksums(data_folder_path)?;
+    let current = if saved.is_empty() {
+        BTreeMap::new()
+    } else {
+        build_selected_checksums(data_folder_path, |month|checked.contains(&month))?
+    };
+    let verified = |key: u64|saved.get(&key).is_some_and(|c|current.get(&key) == Some(c));
+    if !verified(DICTIONARIES_KEY) {
+        check_dictionaries(&mut problems, data_folder_path, configuration);
+    }
+    problems.extend(load_problems.into_iter().map(|p|StartupProblem{file: p.file, message: p.message}));
+    for key in checked.iter().rev().filter(|k|!verified(**k)) {
+        for file in &file_map[key] {
+            if let Err(e) = source.load(vec![FileWithDate{name: file.name.clone(), date: file.date}]) {
+                problems.push(StartupProblem{file: file.name.clone(), message: e.to_string()});
+            }
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::Error;
+    use crate::json_db_config::JsonDBConfiguration;
+    use crate::verify::checksums::{build_checksums, save_checksums};
+    use crate::verify::startup::check_startup;
+
+    #[test]
+    fn test_check_startup() -> Result<(), Error> {
+        let folder = temp_dir().join("had_test_startup_check").to_str().unwrap().to_string();
+        let _ = fs::remove_dir_all(&folder);
+        for date in ["20230105", "20240110", "20240215"] {
+            fs::create_dir_all(format!("{}/dates/{}", folder, date))?;
+        }
+        fs::write(folder.clone() + "/accounts.json", "[]")?;
+        fs::write(folder.clone() + "/categories.json", "[]")?;
+        fs::write(folder.clone() + "/subcategories.json", "[]")?;
+        fs::write(folder.clone() + "/dates/20230105/ops.json", "[{")?;
+        fs::write(folder.clone() + "/dates/20240110/ops.json", "[]")?;
+        fs::write(folder.clone() + "/dates/20240215/ops.json", "[]")?;
+        let configuration = JsonDBConfiguration::new();
+        // the damaged month is older than the checked ones
+        assert!(check_startup(&folder, &configuration, 2)?.is_empty());
+        let problems = check_startup(&folder, &configuration, 3)?;
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].file, folder.clone() + "/dates/20230105/ops.json");
+        fs::remove_file(folder.clone() + "/categories.json")?;
+        fs::write(folder.clone() + "/payees.json", "{")?;
+        let files: Vec<String> = check_startup(&folder, &configuration, 2)?.into_iter().map(|p|p.file).collect();
+        assert_eq!(files, vec![folder.clone() + "/categories", folder.clone() + "/payees"]);
+        fs::write(folder.clone() + "/categories.json", "[]")?;
+        fs::write(folder.clone() + "/payees.json", "[]")?;
+        fs::write(folder.clone() + "/loans.json", "[{")?;
+        let files: Vec<String> = check_startup(&folder, &configuration, 2)?.into_iter().map(|p|p.file).collect();
+        assert_eq!(files, vec![folder.clone() + "/loans"]);
+        fs::remove_dir_all(&folder)
+    }
+
+    #[test]
+    fn test_verified_checksums() -> Result<(), Error> {
+        let folder = temp_dir().join("had_test_startup_checksums").to_str().unwrap().to_string();
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.clone() + "/dates/20240110")?;
+        fs::write(folder.clone() + "/accounts.json", "[]")?;
+        fs::write(folder.clone() + "/categories.json", "[]")?;
+        fs::write(folder.clone() + "/subcategories.json", "[]")?;
+        fs::write(folder.clone() + "/goals.json", "{")?;
+        fs::write(folder.clone() + "/dates/20240110/ops.json", "[{")?;
+        let configuration = JsonDBConfiguration::new();
+        assert_eq!(check_startup(&folder, &configuration, 1)?.len(), 2);
+        // files matching the checksums of the last verification are not decoded
+        save_checksums(&folder, &build_checksums(&folder)?)?;
+        assert!(check_startup(&folder, &configuration, 1)?.is_empty());
+        fs::write(folder.clone() + "/dates/20240110/ops.json", "[{}")?;
+        let files: Vec<String> = check_startup(&folder, &configuration, 1)?.into_iter().map(|p|p.file).collect();
+        assert_eq!(files, vec![folder.clone() + "/dates/20240110/ops.json"]);
+        fs::write(folder.clone() + "/goals.json", "[{")?;
+        assert_eq!(check_startup(&folder, &configuration, 1)?.len(), 2);
+        fs::remove_dir_all(&folder)
+    }
+
+    #[cfg(feature = "binary")]
+    #[test]
+    fn test_binary_backend() -> Result<(), Error> {
+        use crate::binary_db_config::BinaryDBConfiguration;
+        use crate::db::HomeAccountingDB;
+        use crate::entities::accounts::AccountId;
+        use crate::entities::finance_operations::FinanceOperation;
+        use crate::entities::subcategories::SubcategoryId;
+        let folder = temp_dir().join("had_test_startup_binary").to_str().unwrap().to_string();
+        let source = folder.clone() + "/json";
+        let dest = folder.clone() + "/binary";
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&source)?;
+        fs::write(source.clone() + "/accounts.json",
+                  r#"[{"id": 1, "name": "Cash", "valutaCode": "UAH", "activeTo": null, "isCash": true}]"#)?;
+        fs::write(source.clone() + "/categories.json", r#"[{"id": 1, "name": "Food"}]"#)?;
+        fs::write(source.clone() + "/subcategories.json",
+                  r#"[{"id": 1, "name": "Groceries", "code": null, "operationCodeId": "EXPN", "categoryId": 1}]"#)?;
+        let db = HomeAccountingDB::new(source.clone(), Box::new(JsonDBConfiguration::new()), 100)?;
+        db.add_operation(FinanceOperation::new(20240105, AccountId(1), SubcategoryId(1), None, 100, Vec::new()))?;
+        db.add_operation(FinanceOperation::new(20240210, AccountId(1), SubcategoryId(1), None, 200, Vec::new()))?;
+        db.flush(false)?;
+        let key = [7u8; 32];
+        db.migrate(dest.clone(), Box::new(BinaryDBConfiguration::new(key)))?;
+        assert!(check_startup(&dest, &BinaryDBConfiguration::new(key), 2)?.is_empty());
+        let damaged = fs::read_dir(dest.clone() + "/dates/20240210")?.next().unwrap()?.path();
+        fs::write(&damaged, "garbage")?;
+        let files: Vec<String> = check_startup(&dest, &BinaryDBConfiguration::new(key), 2)?.into_iter()
+            .map(|p|p.file)
+            .collect();
+        assert_eq!(files, vec![damaged.to_string_lossy().to_string()]);
+        // a wrong key fails every dictionary and file
+        assert!(check_startup(&dest, &BinaryDBConfiguration::new([8u8; 32]), 2)?.len() > 3);
+        fs::remove_dir_all(&folder)
+    }
+}
@@ -0,0 +1,41 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Error;
+use crate::core::time_series_data::DataRange;
+use crate::entities::accounts::{AccountId, Accounts};
+use crate::entities::finance_operations::{FinanceRecord, SpecialHandlers};
+use crate::entities::subcategories::Subcategories;
+
+pub struct TotalsMismatch {
+    pub account: AccountId,
+    /// yyyymm
+    pub month: u64,
+    pub expected: i64,
+    pub actual: i64
+}
+
+/// Compares stored totals of each month with end balances calculated from the previous month
+/// converted by account currency changes, reports only the first divergent month for each account.
+pub fn check_totals(records: &DataRange<FinanceRecord>, accounts: &Accounts, subcategories: &Subcategories,
+                    handlers: &SpecialHandlers) -> Result<Vec<TotalsMismatch>, Error> {
+    let mut result = Vec::new();
+    let mut reported = HashSet::new();
+    let mut expected: Option<(u64, HashMap<AccountId, i64>)> = None;
+    for (month, record) in records {
+        let r = record.lock().unwrap();
+        if let Some((previous, e)) = &mut expected {
+            accounts.convert_balances(e, *previous * 100 + 32, month * 100 + 1)?;
+            let mut all: Vec<AccountId> = r.totals.keys().chain(e.keys()).copied().collect();
+            all.sort();
+            all.dedup();
+            for account in all {
+                let ev = e.get(&account).copied().unwrap_or(0);
+                let av = r.totals.get(&account).copied().unwrap_or(0);
+                if ev != av && reported.insert(account) {
+                    result.push(TotalsMismatch{account, month: *month, expected: ev, actual: av});
+                }
+            }
+        }
+        expected = Some((*month, r.build_changes(accounts, subcategories, handlers)?.build_totals()?));
+    }
+    Ok(result)
+}